@@ -65,6 +65,12 @@ pub enum Error {
     /** Method Not Allowed */
     #[error("Method Not Allowed: {internal_message}")]
     MethodNotAllowed { internal_message: String },
+    /**
+     * A precondition of a conditional request (e.g., from an `If-Match`
+     * header) was not met.
+     */
+    #[error("Precondition Failed: {message}")]
+    PreconditionFailed { message: String },
 }
 
 /** Indicates how an object was looked up (for an `ObjectNotFound` error) */
@@ -112,6 +118,7 @@ impl Error {
             | Error::InvalidValue { .. }
             | Error::Forbidden
             | Error::MethodNotAllowed { .. }
+            | Error::PreconditionFailed { .. }
             | Error::InternalError { .. } => false,
         }
     }
@@ -235,6 +242,14 @@ impl From<Error> for HttpError {
                 )
             }
 
+            Error::PreconditionFailed { message } => {
+                HttpError::for_client_error(
+                    Some(String::from("PreconditionFailed")),
+                    http::StatusCode::PRECONDITION_FAILED,
+                    message,
+                )
+            }
+
             Error::Forbidden => HttpError::for_client_error(
                 Some(String::from("Forbidden")),
                 http::StatusCode::FORBIDDEN,
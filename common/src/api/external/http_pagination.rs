@@ -43,6 +43,7 @@
  */
 
 use crate::api::external::DataPageParams;
+use crate::api::external::LabelSelector;
 use crate::api::external::Name;
 use crate::api::external::ObjectIdentity;
 use crate::api::external::PaginationOrder;
@@ -247,6 +248,53 @@ impl ScanParams for ScanByName {
     }
 }
 
+/*
+ * Pagination by name in ascending order only, for resources that have labels
+ */
+
+/** Query parameters for pagination by name only, filtered by labels */
+pub type PaginatedByNameWithLabels =
+    PaginationParams<ScanByNameWithLabels, PageSelectorByNameWithLabels>;
+/** Page selector for pagination by name only, filtered by labels */
+pub type PageSelectorByNameWithLabels =
+    PageSelector<ScanByNameWithLabels, Name>;
+/**
+ * Scan parameters for resources that support scanning by name only and
+ * filtering by their labels
+ */
+#[derive(Clone, Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
+pub struct ScanByNameWithLabels {
+    #[serde(default = "default_name_sort_mode")]
+    sort_by: NameSortMode,
+    /** only list resources whose labels match this selector */
+    #[serde(default, skip_serializing_if = "LabelSelector::is_empty")]
+    label_selector: LabelSelector,
+}
+
+impl ScanParams for ScanByNameWithLabels {
+    type MarkerValue = Name;
+    fn direction(&self) -> PaginationOrder {
+        PaginationOrder::Ascending
+    }
+    fn marker_for_item<T: ObjectIdentity>(&self, item: &T) -> Name {
+        item.identity().name.clone()
+    }
+    fn from_query(
+        p: &PaginationParams<Self, PageSelector<Self, Self::MarkerValue>>,
+    ) -> Result<&Self, HttpError> {
+        Ok(match p.page {
+            WhichPage::First(ref scan_params) => scan_params,
+            WhichPage::Next(PageSelector { ref scan, .. }) => scan,
+        })
+    }
+}
+
+impl ScanByNameWithLabels {
+    pub fn label_selector(&self) -> &LabelSelector {
+        &self.label_selector
+    }
+}
+
 /*
  * Pagination by id in ascending order only (for some anonymous resources today)
  */
@@ -294,6 +342,29 @@ impl ScanParams for ScanById {
     }
 }
 
+impl ScanById {
+    /**
+     * Like [`ScanParams::results_page`], but for items that have an id without
+     * the rest of the `ObjectIdentity` metadata
+     *
+     * `item_id` returns the id of an item in `list`.
+     */
+    pub fn results_page_by_id<T, F>(
+        query: &PaginatedById,
+        list: Vec<T>,
+        item_id: F,
+    ) -> Result<ResultsPage<T>, HttpError>
+    where
+        T: Serialize,
+        F: Fn(&T) -> Uuid,
+    {
+        let scan_params = Self::from_query(query)?;
+        ResultsPage::new(list, scan_params, |item: &T, scan: &ScanById| {
+            PageSelector { scan: scan.clone(), last_seen: item_id(item) }
+        })
+    }
+}
+
 /*
  * Pagination by any of: name ascending, name descending, or id ascending.
  * We include this now primarily to exercise the interface for doing so.
@@ -309,6 +380,9 @@ pub type PageSelectorByNameOrId = PageSelector<ScanByNameOrId, NameOrIdMarker>;
 pub struct ScanByNameOrId {
     #[serde(default = "default_nameid_sort_mode")]
     sort_by: NameOrIdSortMode,
+    /** only list resources whose labels match this selector */
+    #[serde(default, skip_serializing_if = "LabelSelector::is_empty")]
+    label_selector: LabelSelector,
 }
 /** Supported set of sort modes for scanning by name or id */
 #[derive(Copy, Clone, Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
@@ -354,6 +428,12 @@ pub enum PagField {
     Name,
 }
 
+impl ScanByNameOrId {
+    pub fn label_selector(&self) -> &LabelSelector {
+        &self.label_selector
+    }
+}
+
 pub fn pagination_field_for_scan_params(p: &ScanByNameOrId) -> PagField {
     match p.sort_by {
         NameOrIdSortMode::NameAscending => PagField::Name,
@@ -505,11 +585,14 @@ mod test {
     use super::PaginatedById;
     use super::PaginatedByName;
     use super::PaginatedByNameOrId;
+    use super::PaginatedByNameWithLabels;
     use super::ScanById;
     use super::ScanByName;
     use super::ScanByNameOrId;
+    use super::ScanByNameWithLabels;
     use super::ScanParams;
     use crate::api::external::IdentityMetadata;
+    use crate::api::external::LabelSelector;
     use crate::api::external::ObjectIdentity;
     use chrono::Utc;
     use dropshot::PaginationOrder;
@@ -574,10 +657,14 @@ mod test {
     fn test_pagination_examples() {
         let scan_by_id = ScanById { sort_by: IdSortMode::IdAscending };
         let scan_by_name = ScanByName { sort_by: NameSortMode::NameAscending };
-        let scan_by_nameid_name =
-            ScanByNameOrId { sort_by: NameOrIdSortMode::NameAscending };
-        let scan_by_nameid_id =
-            ScanByNameOrId { sort_by: NameOrIdSortMode::IdAscending };
+        let scan_by_nameid_name = ScanByNameOrId {
+            sort_by: NameOrIdSortMode::NameAscending,
+            label_selector: LabelSelector::default(),
+        };
+        let scan_by_nameid_id = ScanByNameOrId {
+            sort_by: NameOrIdSortMode::IdAscending,
+            label_selector: LabelSelector::default(),
+        };
         let id: Uuid = "61a78113-d3c6-4b35-a410-23e9eae64328".parse().unwrap();
         let name: Name = "bort".parse().unwrap();
         let examples = vec![
@@ -778,6 +865,38 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_scan_by_name_with_labels() {
+        /* Start with the common battery of tests. */
+        let scan = ScanByNameWithLabels {
+            sort_by: NameSortMode::NameAscending,
+            label_selector: "env=prod,!deprecated".parse().unwrap(),
+        };
+
+        let list = list_of_things();
+        let (_, p1) = test_scan_param_common(
+            &list,
+            &scan,
+            "sort_by=name-ascending&label_selector=env%3Dprod%2C%21deprecated",
+            &"thing0".parse().unwrap(),
+            &"thing19".parse().unwrap(),
+            &ScanByNameWithLabels {
+                sort_by: NameSortMode::NameAscending,
+                label_selector: LabelSelector::default(),
+            },
+        );
+
+        /* The selector is carried along in the page token. */
+        let scan1 = ScanByNameWithLabels::from_query(&p1).unwrap();
+        assert_eq!(scan1.label_selector().to_string(), "env=prod,!deprecated");
+
+        /* An invalid selector is rejected. */
+        serde_urlencoded::from_str::<PaginatedByNameWithLabels>(
+            "label_selector=Env%3Dprod",
+        )
+        .unwrap_err();
+    }
+
     #[test]
     fn test_scan_by_id() {
         /* Start with the common battery of tests. */
@@ -843,7 +962,10 @@ mod test {
     #[test]
     fn test_scan_by_nameid_name() {
         /* Start with the common battery of tests. */
-        let scan = ScanByNameOrId { sort_by: NameOrIdSortMode::NameDescending };
+        let scan = ScanByNameOrId {
+            sort_by: NameOrIdSortMode::NameDescending,
+            label_selector: LabelSelector::default(),
+        };
         assert_eq!(pagination_field_for_scan_params(&scan), PagField::Name);
         assert_eq!(scan.direction(), PaginationOrder::Descending);
 
@@ -857,7 +979,10 @@ mod test {
             "sort_by=name-descending",
             &thing0_marker,
             &thinglast_marker,
-            &ScanByNameOrId { sort_by: NameOrIdSortMode::NameAscending },
+            &ScanByNameOrId {
+                sort_by: NameOrIdSortMode::NameAscending,
+                label_selector: LabelSelector::default(),
+            },
         );
 
         /* Verify data pages based on the query params. */
@@ -880,7 +1005,10 @@ mod test {
     #[test]
     fn test_scan_by_nameid_id() {
         /* Start with the common battery of tests. */
-        let scan = ScanByNameOrId { sort_by: NameOrIdSortMode::IdAscending };
+        let scan = ScanByNameOrId {
+            sort_by: NameOrIdSortMode::IdAscending,
+            label_selector: LabelSelector::default(),
+        };
         assert_eq!(pagination_field_for_scan_params(&scan), PagField::Id);
         assert_eq!(scan.direction(), PaginationOrder::Ascending);
 
@@ -895,7 +1023,10 @@ mod test {
             "sort_by=id-ascending",
             &thing0_marker,
             &thinglast_marker,
-            &ScanByNameOrId { sort_by: NameOrIdSortMode::NameAscending },
+            &ScanByNameOrId {
+                sort_by: NameOrIdSortMode::NameAscending,
+                label_selector: LabelSelector::default(),
+            },
         );
 
         /* Verify data pages based on the query params. */
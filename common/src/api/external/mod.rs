@@ -566,13 +566,20 @@ pub enum ResourceType {
     Fleet,
     Organization,
     Project,
+    ProjectWebhook,
     Dataset,
     Disk,
+    Snapshot,
     Instance,
+    AffinityGroup,
     NetworkInterface,
+    IpPool,
+    IpPoolRange,
+    FloatingIp,
     Rack,
     Sled,
     SagaDbg,
+    Operation,
     Vpc,
     VpcFirewallRule,
     VpcSubnet,
@@ -582,6 +589,8 @@ pub enum ResourceType {
     MetricProducer,
     Role,
     User,
+    ApiToken,
+    SshKey,
     Zpool,
 }
 
@@ -624,6 +633,31 @@ pub struct IdentityMetadata {
 pub struct IdentityMetadataCreateParams {
     pub name: Name,
     pub description: String,
+    /**
+     * labels for the new resource
+     *
+     * Only Organizations, Projects, Instances, Disks, and VPCs have labels
+     * today.  Other resources can't be created with any.
+     */
+    #[serde(default)]
+    pub labels: Labels,
+}
+
+impl IdentityMetadataCreateParams {
+    /**
+     * Fails if these parameters specify labels for a resource of type
+     * `resource_type`, which doesn't have any
+     */
+    pub fn check_no_labels(
+        &self,
+        resource_type: ResourceType,
+    ) -> Result<(), Error> {
+        if self.labels.is_empty() {
+            Ok(())
+        } else {
+            Err(labels_unsupported(resource_type))
+        }
+    }
 }
 
 /**
@@ -633,6 +667,264 @@ pub struct IdentityMetadataCreateParams {
 pub struct IdentityMetadataUpdateParams {
     pub name: Option<Name>,
     pub description: Option<String>,
+    /**
+     * if present, these replace all of the resource's labels (only
+     * Organizations, Projects, Instances, Disks, and VPCs have any)
+     */
+    pub labels: Option<Labels>,
+}
+
+impl IdentityMetadataUpdateParams {
+    /**
+     * Fails if these parameters specify labels for a resource of type
+     * `resource_type`, which doesn't have any
+     */
+    pub fn check_no_labels(
+        &self,
+        resource_type: ResourceType,
+    ) -> Result<(), Error> {
+        match &self.labels {
+            Some(labels) if !labels.is_empty() => {
+                Err(labels_unsupported(resource_type))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+fn labels_unsupported(resource_type: ResourceType) -> Error {
+    Error::InvalidValue {
+        label: String::from("labels"),
+        message: format!("resources of type {} have no labels", resource_type),
+    }
+}
+
+/*
+ * LABELS
+ */
+
+/** The most labels that a resource may have */
+pub const LABELS_MAX: usize = 64;
+
+/**
+ * Key/value labels used to organize resources
+ *
+ * Keys consist of up to 63 lowercase ASCII letters, digits, '-', '_', '.',
+ * and '/', beginning with a letter or digit.  Values consist of up to 63 ASCII
+ * letters, digits, '-', '_', and '.', and may be empty.  A resource may have
+ * at most 64 labels.
+ */
+/*
+ * Keys and values are restricted so that any label can be written in a
+ * `LabelSelector`.
+ */
+#[derive(
+    Clone, Debug, Default, Deserialize, Eq, JsonSchema, PartialEq, Serialize,
+)]
+#[serde(try_from = "BTreeMap<String, String>")]
+pub struct Labels(BTreeMap<String, String>);
+
+impl Labels {
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).map(|v| v.as_str())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl TryFrom<BTreeMap<String, String>> for Labels {
+    type Error = String;
+    fn try_from(labels: BTreeMap<String, String>) -> Result<Self, Self::Error> {
+        if labels.len() > LABELS_MAX {
+            return Err(format!(
+                "a resource may have at most {} labels",
+                LABELS_MAX
+            ));
+        }
+        for (key, value) in &labels {
+            label_key_check(key)?;
+            label_value_check(value)?;
+        }
+        Ok(Labels(labels))
+    }
+}
+
+fn label_key_check(key: &str) -> Result<(), String> {
+    if key.is_empty() || key.len() > 63 {
+        return Err(format!(
+            "label key \"{}\" must be between 1 and 63 characters long",
+            key
+        ));
+    }
+    let valid = key.chars().enumerate().all(|(i, c)| {
+        c.is_ascii_lowercase()
+            || c.is_ascii_digit()
+            || (i > 0 && (c == '-' || c == '_' || c == '.' || c == '/'))
+    });
+    if !valid {
+        return Err(format!(
+            "label key \"{}\" must consist of lowercase ASCII letters, \
+             digits, '-', '_', '.', and '/', beginning with a letter or digit",
+            key
+        ));
+    }
+    Ok(())
+}
+
+fn label_value_check(value: &str) -> Result<(), String> {
+    if value.len() > 63 {
+        return Err(format!(
+            "label value \"{}\" may contain at most 63 characters",
+            value
+        ));
+    }
+    let valid = value
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.');
+    if !valid {
+        return Err(format!(
+            "label value \"{}\" must consist of ASCII letters, digits, \
+             '-', '_', and '.'",
+            value
+        ));
+    }
+    Ok(())
+}
+
+/**
+ * Selects resources by their labels
+ *
+ * A selector is a comma-separated list of requirements, all of which must be
+ * met by a resource's labels:
+ *
+ * * `key=value`: the resource has label `key` with value `value`
+ * * `key!=value`: the resource does not have label `key` with value `value`
+ * * `key`: the resource has label `key`
+ * * `!key`: the resource does not have label `key`
+ *
+ * An empty selector selects every resource.
+ */
+#[derive(
+    Clone, Debug, Default, DeserializeFromStr, Eq, PartialEq, SerializeDisplay,
+)]
+pub struct LabelSelector(Vec<LabelRequirement>);
+
+/** One of the requirements that make up a [`LabelSelector`] */
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum LabelRequirement {
+    Equals { key: String, value: String },
+    NotEquals { key: String, value: String },
+    Exists { key: String },
+    NotExists { key: String },
+}
+
+impl LabelSelector {
+    pub fn requirements(&self) -> &[LabelRequirement] {
+        &self.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl FromStr for LabelSelector {
+    type Err = String;
+    fn from_str(selector: &str) -> Result<Self, Self::Err> {
+        if selector.trim().is_empty() {
+            return Ok(LabelSelector::default());
+        }
+
+        selector
+            .split(',')
+            .map(|requirement| {
+                let requirement = requirement.trim();
+                let parsed = if let Some(key) = requirement.strip_prefix('!') {
+                    LabelRequirement::NotExists { key: key.to_string() }
+                } else if let Some((key, value)) = requirement.split_once("!=")
+                {
+                    LabelRequirement::NotEquals {
+                        key: key.to_string(),
+                        value: value.to_string(),
+                    }
+                } else if let Some((key, value)) = requirement.split_once('=') {
+                    LabelRequirement::Equals {
+                        key: key.to_string(),
+                        value: value.to_string(),
+                    }
+                } else {
+                    LabelRequirement::Exists { key: requirement.to_string() }
+                };
+                match &parsed {
+                    LabelRequirement::Equals { key, value }
+                    | LabelRequirement::NotEquals { key, value } => {
+                        label_key_check(key)?;
+                        label_value_check(value)?;
+                    }
+                    LabelRequirement::Exists { key }
+                    | LabelRequirement::NotExists { key } => {
+                        label_key_check(key)?;
+                    }
+                }
+                Ok(parsed)
+            })
+            .collect::<Result<Vec<_>, String>>()
+            .map(LabelSelector)
+    }
+}
+
+impl Display for LabelSelector {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FormatResult {
+        for (i, requirement) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, ",")?;
+            }
+            match requirement {
+                LabelRequirement::Equals { key, value } => {
+                    write!(f, "{}={}", key, value)?
+                }
+                LabelRequirement::NotEquals { key, value } => {
+                    write!(f, "{}!={}", key, value)?
+                }
+                LabelRequirement::Exists { key } => write!(f, "{}", key)?,
+                LabelRequirement::NotExists { key } => write!(f, "!{}", key)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl JsonSchema for LabelSelector {
+    fn schema_name() -> String {
+        "LabelSelector".to_string()
+    }
+
+    fn json_schema(
+        _: &mut schemars::gen::SchemaGenerator,
+    ) -> schemars::schema::Schema {
+        schemars::schema::Schema::Object(schemars::schema::SchemaObject {
+            metadata: Some(Box::new(schemars::schema::Metadata {
+                title: Some("A selector for resources' labels".to_string()),
+                description: Some(
+                    "A comma-separated list of requirements, each one of \
+                     \"key=value\", \"key!=value\", \"key\" (the label is \
+                     present), or \"!key\" (the label is absent)"
+                        .to_string(),
+                ),
+                ..Default::default()
+            })),
+            instance_type: Some(schemars::schema::SingleOrVec::Single(
+                Box::new(schemars::schema::InstanceType::String),
+            )),
+            ..Default::default()
+        })
+    }
 }
 
 /*
@@ -799,6 +1091,7 @@ pub struct Instance {
     /* TODO is flattening here the intent in RFD 4? */
     #[serde(flatten)]
     pub identity: IdentityMetadata,
+    pub labels: Labels,
 
     /** id for the project containing this Instance */
     pub project_id: Uuid,
@@ -809,11 +1102,26 @@ pub struct Instance {
     pub memory: ByteCount,
     /** RFC1035-compliant hostname for the Instance. */
     pub hostname: String, /* TODO-cleanup different type? */
+    /** id for the affinity group containing this Instance, if any */
+    pub affinity_group_id: Option<Uuid>,
 
     #[serde(flatten)]
     pub runtime: InstanceRuntimeState,
 }
 
+/**
+ * Describes how the members of an affinity group are placed relative to one
+ * another
+ */
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AffinityPolicy {
+    /** All members of the group must run on the same sled. */
+    Affinity,
+    /** No two members of the group may run on the same sled. */
+    AntiAffinity,
+}
+
 /*
  * DISKS
  */
@@ -825,6 +1133,7 @@ pub struct Instance {
 pub struct Disk {
     #[serde(flatten)]
     pub identity: IdentityMetadata,
+    pub labels: Labels,
     pub project_id: Uuid,
     pub snapshot_id: Option<Uuid>,
     pub size: ByteCount,
@@ -934,110 +1243,6 @@ impl DiskState {
     }
 }
 
-/*
- * Sagas
- *
- * These are currently only intended for observability by developers.  We will
- * eventually want to flesh this out into something more observable for end
- * users.
- */
-#[derive(ObjectIdentity, Clone, Debug, Serialize, JsonSchema)]
-pub struct Saga {
-    pub id: Uuid,
-    pub state: SagaState,
-    /*
-     * TODO-cleanup This object contains a fake `IdentityMetadata`.  Why?  We
-     * want to paginate these objects.  http_pagination.rs provides a bunch of
-     * useful facilities -- notably `PaginatedById`.  `PaginatedById`
-     * requires being able to take an arbitrary object in the result set and get
-     * its id.  To do that, it uses the `ObjectIdentity` trait, which expects
-     * to be able to return an `IdentityMetadata` reference from an object.
-     * Finally, the pagination facilities just pull the `id` out of that.
-     *
-     * In this case (as well as others, like sleds and racks), we have ids, and
-     * we want to be able to paginate by id, but we don't have full identity
-     * metadata.  (Or we do, but it's similarly faked up.)  What we should
-     * probably do is create a new trait, say `ObjectId`, that returns _just_
-     * an id.  We can provide a blanket impl for anything that impls
-     * IdentityMetadata.  We can define one-off impls for structs like this
-     * one.  Then the id-only pagination interfaces can require just
-     * `ObjectId`.
-     */
-    #[serde(skip)]
-    pub identity: IdentityMetadata,
-}
-
-impl From<steno::SagaView> for Saga {
-    fn from(s: steno::SagaView) -> Self {
-        Saga {
-            id: Uuid::from(s.id),
-            state: SagaState::from(s.state),
-            identity: IdentityMetadata {
-                /* TODO-cleanup See the note in Saga above. */
-                id: Uuid::from(s.id),
-                name: format!("saga-{}", s.id).parse().unwrap(),
-                description: format!("saga {}", s.id),
-                time_created: Utc::now(),
-                time_modified: Utc::now(),
-            },
-        }
-    }
-}
-
-#[derive(Clone, Debug, Serialize, JsonSchema)]
-#[serde(tag = "state", rename_all = "snake_case")]
-pub enum SagaState {
-    Running,
-    Succeeded,
-    Failed { error_node_name: String, error_info: SagaErrorInfo },
-}
-
-#[derive(Clone, Debug, Serialize, JsonSchema)]
-#[serde(tag = "error", rename_all = "snake_case")]
-pub enum SagaErrorInfo {
-    ActionFailed { source_error: serde_json::Value },
-    DeserializeFailed { message: String },
-    InjectedError,
-    SerializeFailed { message: String },
-    SubsagaCreateFailed { message: String },
-}
-
-impl From<steno::SagaStateView> for SagaState {
-    fn from(st: steno::SagaStateView) -> Self {
-        match st {
-            steno::SagaStateView::Ready { .. } => SagaState::Running,
-            steno::SagaStateView::Running { .. } => SagaState::Running,
-            steno::SagaStateView::Done {
-                result: steno::SagaResult { kind: Ok(_), .. },
-                ..
-            } => SagaState::Succeeded,
-            steno::SagaStateView::Done {
-                result: steno::SagaResult { kind: Err(e), .. },
-                ..
-            } => SagaState::Failed {
-                error_node_name: e.error_node_name,
-                error_info: match e.error_source {
-                    steno::ActionError::ActionFailed { source_error } => {
-                        SagaErrorInfo::ActionFailed { source_error }
-                    }
-                    steno::ActionError::DeserializeFailed { message } => {
-                        SagaErrorInfo::DeserializeFailed { message }
-                    }
-                    steno::ActionError::InjectedError => {
-                        SagaErrorInfo::InjectedError
-                    }
-                    steno::ActionError::SerializeFailed { message } => {
-                        SagaErrorInfo::SerializeFailed { message }
-                    }
-                    steno::ActionError::SubsagaCreateFailed { message } => {
-                        SagaErrorInfo::SubsagaCreateFailed { message }
-                    }
-                },
-            },
-        }
-    }
-}
-
 /// An `Ipv4Net` represents a IPv4 subnetwork, including the address and network mask.
 #[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
 pub struct Ipv4Net(pub ipnetwork::Ipv4Network);
@@ -1432,6 +1637,34 @@ pub struct RouterRouteUpdateParams {
     pub destination: RouteDestination,
 }
 
+/// A route in the effective route table of a VPC Subnet, which says where
+/// traffic to a block of destination addresses is sent.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, JsonSchema)]
+pub struct EffectiveRoute {
+    /// The first address in the block of destination addresses.
+    pub destination: IpAddr,
+    /// The length of the destination block's prefix.
+    pub prefix_len: u8,
+    /// Where traffic to the destination block is sent.
+    pub target: RouteTarget,
+    /// The kind of route from which this one was derived.
+    pub kind: RouterRouteKind,
+    /// The VPC Router whose route this is, unless it's the route to one of
+    /// the VPC's own subnets.
+    pub router_id: Option<Uuid>,
+    /// The route from which this one was derived, unless it's the route to
+    /// one of the VPC's own subnets.
+    pub route_id: Option<Uuid>,
+}
+
+/// The routes that apply to traffic leaving a VPC Subnet, ordered so that the
+/// first one whose destination block contains an address is the one with the
+/// longest matching prefix.
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct EffectiveRouteTable {
+    pub routes: Vec<EffectiveRoute>,
+}
+
 /// A single rule in a VPC firewall
 #[derive(ObjectIdentity, Clone, Debug, Deserialize, Serialize, JsonSchema)]
 pub struct VpcFirewallRule {
@@ -1922,13 +2155,17 @@ pub struct NetworkInterface {
 
     /** The IP address assigned to this interface. */
     pub ip: IpAddr,
+
+    /** The floating IP attached to this interface, if any. */
+    pub external_ip: Option<IpAddr>,
 }
 
 #[cfg(test)]
 mod test {
     use super::{
-        ByteCount, L4Port, L4PortRange, Name, NetworkTarget, RoleName,
-        VpcFirewallRuleAction, VpcFirewallRuleDirection, VpcFirewallRuleFilter,
+        ByteCount, L4Port, L4PortRange, LabelRequirement, LabelSelector,
+        Labels, Name, NetworkTarget, RoleName, VpcFirewallRuleAction,
+        VpcFirewallRuleDirection, VpcFirewallRuleFilter,
         VpcFirewallRuleHostFilter, VpcFirewallRulePriority,
         VpcFirewallRuleProtocol, VpcFirewallRuleStatus, VpcFirewallRuleTarget,
         VpcFirewallRuleUpdate, VpcFirewallRuleUpdateParams,
@@ -2142,6 +2379,93 @@ mod test {
         assert_eq!(3, tib3.to_whole_tebibytes());
     }
 
+    #[test]
+    fn test_labels_deserialization() {
+        let labels = serde_json::from_str::<Labels>(
+            r#"{ "cost-center": "1234", "example.com/owner": "", "env": "prod" }"#,
+        )
+        .unwrap();
+        assert_eq!(labels.get("cost-center"), Some("1234"));
+        assert_eq!(labels.get("example.com/owner"), Some(""));
+        assert_eq!(labels.get("owner"), None);
+        assert_eq!(
+            labels.iter().map(|(k, _)| k).collect::<Vec<_>>(),
+            vec!["cost-center", "env", "example.com/owner"]
+        );
+
+        let error_cases: Vec<(&str, &str)> = vec![
+            (r#"{ "": "x" }"#, "must be between 1 and 63 characters long"),
+            (r#"{ "Env": "x" }"#, "must consist of lowercase ASCII letters"),
+            (r#"{ "-env": "x" }"#, "beginning with a letter or digit"),
+            (r#"{ "env": "a b" }"#, "must consist of ASCII letters"),
+            (r#"{ "env": 3 }"#, "invalid type"),
+        ];
+        for (input, expected_message) in error_cases {
+            let error =
+                serde_json::from_str::<Labels>(input).unwrap_err().to_string();
+            assert!(
+                error.contains(expected_message),
+                "input: {}, error: {}",
+                input,
+                error
+            );
+        }
+
+        let too_many = (0..=super::LABELS_MAX)
+            .map(|i| format!("\"key{}\": \"\"", i))
+            .collect::<Vec<_>>()
+            .join(",");
+        let error =
+            serde_json::from_str::<Labels>(&format!("{{{}}}", too_many))
+                .unwrap_err()
+                .to_string();
+        assert!(error.contains("at most 64 labels"), "error: {}", error);
+    }
+
+    #[test]
+    fn test_label_selector_parse() {
+        let selector: LabelSelector = "".parse().unwrap();
+        assert!(selector.is_empty());
+
+        let selector: LabelSelector =
+            "env=prod, tier!=web,example.com/owner,!deprecated,empty="
+                .parse()
+                .unwrap();
+        assert_eq!(
+            selector.requirements(),
+            &[
+                LabelRequirement::Equals {
+                    key: String::from("env"),
+                    value: String::from("prod"),
+                },
+                LabelRequirement::NotEquals {
+                    key: String::from("tier"),
+                    value: String::from("web"),
+                },
+                LabelRequirement::Exists {
+                    key: String::from("example.com/owner"),
+                },
+                LabelRequirement::NotExists { key: String::from("deprecated") },
+                LabelRequirement::Equals {
+                    key: String::from("empty"),
+                    value: String::from(""),
+                },
+            ]
+        );
+        assert_eq!(
+            selector.to_string(),
+            "env=prod,tier!=web,example.com/owner,!deprecated,empty="
+        );
+
+        for input in &["env=prod,", "Env=prod", "env=a b", "!", "env==prod"] {
+            assert!(
+                input.parse::<LabelSelector>().is_err(),
+                "input: {}",
+                input
+            );
+        }
+    }
+
     #[test]
     fn test_ip_port_range_from_str() {
         assert_eq!(
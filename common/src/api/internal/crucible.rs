@@ -0,0 +1,48 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! APIs exposed by the Crucible Agent that are not (yet) covered by
+//! `crucible-agent-client`.
+//!
+//! These cover snapshots of individual regions, populating a region from a
+//! snapshot of another region, and growing a region in place.
+
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use uuid::Uuid;
+
+/// Sent to a Crucible Agent to take a snapshot of one of its regions
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct CreateRegionSnapshot {
+    /// id of the snapshot (shared by the snapshots of every region of a disk)
+    pub id: Uuid,
+}
+
+/// A point-in-time snapshot of a single region
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema, PartialEq)]
+pub struct RegionSnapshot {
+    pub id: Uuid,
+    pub time_created: DateTime<Utc>,
+}
+
+/// Sent to a Crucible Agent to populate one of its regions from a snapshot of
+/// a region (possibly) managed by another agent
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema, PartialEq)]
+pub struct ImportRegionSnapshot {
+    /// address of the Crucible Agent holding the source region
+    pub source_address: SocketAddr,
+    /// id of the region from which the snapshot was taken
+    pub source_region_id: Uuid,
+    /// id of the snapshot to copy
+    pub snapshot_id: Uuid,
+}
+
+/// Sent to a Crucible Agent to change the number of extents in one of its
+/// regions
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema, PartialEq)]
+pub struct ResizeRegion {
+    pub extent_count: u64,
+}
@@ -0,0 +1,54 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! APIs exposed by the internal DNS server.
+
+use crate::api::external::Generation;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+/// The domain of the zone served by the internal DNS server.
+pub const DNS_ZONE: &str = "internal";
+
+/// The contents of the internal DNS zone, as of one generation.
+///
+/// Nexus replaces the whole zone at once.  Each update carries a newer
+/// generation than the one before, so that the server can tell a stale update
+/// (for example, one that was delayed, or that came from another Nexus) from a
+/// fresh one.
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema, PartialEq)]
+pub struct DnsZoneConfig {
+    /// the generation of the zone's contents
+    pub generation: Generation,
+    /// the records of the zone, indexed by name relative to the zone (e.g.,
+    /// `_nexus._tcp` for `_nexus._tcp.internal`)
+    pub records: BTreeMap<String, Vec<DnsRecord>>,
+}
+
+/// A record in the internal DNS zone.
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema, PartialEq)]
+#[serde(tag = "type", content = "data", rename_all = "UPPERCASE")]
+pub enum DnsRecord {
+    /// an IPv4 address
+    A(Ipv4Addr),
+    /// an IPv6 address
+    Aaaa(Ipv6Addr),
+    /// the location of a service
+    Srv(Srv),
+}
+
+/// The location of one instance of a service, as in an SRV record (RFC 2782).
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema, PartialEq)]
+pub struct Srv {
+    /// priority of this target; lower values are preferred
+    pub prio: u16,
+    /// relative weight among targets of the same priority
+    pub weight: u16,
+    /// port on which the service listens
+    pub port: u16,
+    /// name, relative to the zone, of the host running the service
+    pub target: String,
+}
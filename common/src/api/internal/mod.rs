@@ -4,5 +4,7 @@
 
 //! Internally facing APIs.
 
+pub mod crucible;
+pub mod dns;
 pub mod nexus;
 pub mod sled_agent;
@@ -8,7 +8,7 @@ use crate::api::{external, internal};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::fmt::{Debug, Display, Formatter, Result as FormatResult};
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
 use uuid::Uuid;
 
 /// Describes the instance hardware.
@@ -16,6 +16,30 @@ use uuid::Uuid;
 pub struct InstanceHardware {
     pub runtime: internal::nexus::InstanceRuntimeState,
     pub nics: Vec<external::NetworkInterface>,
+    pub guest_metadata: GuestMetadata,
+}
+
+/// Data presented to the guest's instance initialization system (such as
+/// cloud-init)
+#[derive(Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct GuestMetadata {
+    /// user data, passed to the guest as-is
+    pub user_data: Vec<u8>,
+    /// SSH public keys to authorize for logging in to the guest
+    pub ssh_public_keys: Vec<String>,
+}
+
+// The user data may well contain secrets, so keep it out of the logs.
+impl Debug for GuestMetadata {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FormatResult {
+        f.debug_struct("GuestMetadata")
+            .field(
+                "user_data",
+                &format_args!("<{} bytes>", self.user_data.len()),
+            )
+            .field("ssh_public_keys", &self.ssh_public_keys)
+            .finish()
+    }
 }
 
 /// Sent to a sled agent to establish the runtime state of an Instance
@@ -36,6 +60,24 @@ pub struct InstanceMigrateParams {
     pub src_propolis_addr: SocketAddr,
 }
 
+/// Query parameters for fetching the recent output of an Instance's serial
+/// console
+#[derive(Deserialize, JsonSchema)]
+pub struct InstanceSerialConsoleHistoryParams {
+    /// most bytes of output to return (defaults to the whole scrollback)
+    pub max_bytes: Option<u64>,
+}
+
+/// Recent output from an Instance's serial console
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct InstanceSerialConsoleData {
+    /// the most recent bytes written to the console, oldest first
+    pub data: Vec<u8>,
+    /// total number of bytes the Instance has written to the console, of
+    /// which `data` are the last
+    pub last_byte_offset: u64,
+}
+
 /// Requestable running state of an Instance.
 ///
 /// A subset of [`external::InstanceState`].
@@ -108,3 +150,65 @@ pub struct InstanceRuntimeStateRequested {
     pub run_state: InstanceStateRequested,
     pub migration_params: Option<InstanceRuntimeStateMigrateParams>,
 }
+
+/// Sent to a sled agent to establish the firewall rules of some of the network
+/// interfaces it hosts
+///
+/// The rules of each interface listed replace any it had before; interfaces
+/// that aren't listed keep theirs.  An interface whose list of rules is empty
+/// has none left, which is how a sled agent learns that one has gone away.
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct FirewallRulesEnsureBody {
+    pub interfaces: Vec<NetworkInterfaceFirewallRules>,
+}
+
+/// The firewall rules that apply to one network interface
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize, JsonSchema)]
+pub struct NetworkInterfaceFirewallRules {
+    pub interface_id: Uuid,
+    pub rules: Vec<FirewallRule>,
+}
+
+/// A VPC firewall rule, with its targets and host filters resolved into
+/// addresses
+///
+/// Only enabled rules are sent to sled agents.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize, JsonSchema)]
+pub struct FirewallRule {
+    /// the VPC firewall rule that this was resolved from
+    pub rule_id: Uuid,
+    /// whether this rule is for incoming or outgoing traffic
+    pub direction: external::VpcFirewallRuleDirection,
+    /// If present, the sources (if incoming) or destinations (if outgoing)
+    /// this rule applies to.  An empty list matches no traffic at all.
+    pub hosts: Option<Vec<FirewallRuleHost>>,
+    /// If present, the networking protocols this rule applies to.
+    pub protocols: Option<Vec<external::VpcFirewallRuleProtocol>>,
+    /// If present, the destination ports this rule applies to.
+    pub ports: Option<Vec<external::L4PortRange>>,
+    /// whether traffic matching the rule should be allowed or dropped
+    pub action: external::VpcFirewallRuleAction,
+    /// the relative priority of this rule
+    pub priority: external::VpcFirewallRulePriority,
+}
+
+/// A block of addresses matched by a [`FirewallRule`]
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Deserialize,
+    Eq,
+    Ord,
+    PartialEq,
+    PartialOrd,
+    Serialize,
+    JsonSchema,
+)]
+pub struct FirewallRuleHost {
+    /// first address of the block
+    pub address: IpAddr,
+    /// number of leading bits that an address must share with `address` to be
+    /// in the block
+    pub prefix_len: u8,
+}
@@ -29,6 +29,7 @@ pub mod backoff;
 pub mod cmd;
 pub mod config;
 pub mod packaging;
+pub mod websocket;
 
 #[macro_export]
 macro_rules! generate_logging_api {
@@ -62,7 +62,11 @@ CREATE TABLE omicron.public.sled (
     rcgen INT NOT NULL,
 
     ip INET NOT NULL,
-    port INT4 NOT NULL
+    port INT4 NOT NULL,
+
+    /* Capacity available to Instances, as reported by the sled agent */
+    usable_hardware_threads INT NOT NULL,
+    usable_physical_ram INT NOT NULL
 );
 
 /*
@@ -163,7 +167,10 @@ CREATE TABLE omicron.public.organization (
     time_deleted TIMESTAMPTZ,
 
     /* child resource generation number, per RFD 192 */
-    rcgen INT NOT NULL
+    rcgen INT NOT NULL,
+
+    /* Key/value labels, as a JSON object of strings */
+    labels JSONB NOT NULL DEFAULT '{}'
 );
 
 CREATE UNIQUE INDEX ON omicron.public.organization (
@@ -186,7 +193,10 @@ CREATE TABLE omicron.public.project (
     time_deleted TIMESTAMPTZ,
 
     /* Which organization this project belongs to */
-    organization_id UUID NOT NULL /* foreign key into "Organization" table */
+    organization_id UUID NOT NULL, /* foreign key into "Organization" table */
+
+    /* Key/value labels, as a JSON object of strings */
+    labels JSONB NOT NULL DEFAULT '{}'
 );
 
 CREATE UNIQUE INDEX ON omicron.public.project (
@@ -195,6 +205,31 @@ CREATE UNIQUE INDEX ON omicron.public.project (
 ) WHERE
     time_deleted IS NULL;
 
+/*
+ * Resource quotas
+ *
+ * Each row limits the resources that may be provisioned within one
+ * Organization or Project, identified by "collection_id".  A NULL limit is
+ * unlimited, as is an Organization or Project without a row here.
+ */
+
+CREATE TABLE omicron.public.quota (
+    /* id of the Organization or Project to which this quota applies */
+    collection_id UUID PRIMARY KEY,
+    time_modified TIMESTAMPTZ NOT NULL,
+
+    /* number of Instances */
+    instances INT,
+    /* total vCPUs of all Instances */
+    vcpus INT,
+    /* total bytes of memory of all Instances */
+    memory INT,
+    /* total size in bytes of all Disks */
+    disk_bytes INT,
+    /* number of VPCs */
+    vpcs INT
+);
+
 /*
  * Instances
  */
@@ -262,7 +297,20 @@ CREATE TABLE omicron.public.instance (
     /* Instance configuration */
     ncpus INT NOT NULL,
     memory INT NOT NULL,
-    hostname STRING(63) NOT NULL
+    hostname STRING(63) NOT NULL,
+
+    /* Affinity group to which this Instance belongs, if any */
+    affinity_group_id UUID,
+
+    /* Key/value labels, as a JSON object of strings */
+    labels JSONB NOT NULL DEFAULT '{}',
+
+    /*
+     * Data the guest initializes itself from.  These are passed to the sled
+     * agent with the Instance, but are never shown in the external API.
+     */
+    user_data BYTES NOT NULL DEFAULT b'',
+    ssh_public_keys STRING[] NOT NULL DEFAULT ARRAY[]
 );
 
 CREATE UNIQUE INDEX ON omicron.public.instance (
@@ -271,6 +319,67 @@ CREATE UNIQUE INDEX ON omicron.public.instance (
 ) WHERE
     time_deleted IS NULL;
 
+/*
+ * Used to tally the resources reserved on each sled when placing new
+ * Instances.
+ */
+CREATE INDEX ON omicron.public.instance (
+    active_server_id
+) WHERE
+    time_deleted IS NULL;
+
+/* Used to find the members of an affinity group. */
+CREATE INDEX ON omicron.public.instance (
+    affinity_group_id
+) WHERE
+    time_deleted IS NULL AND affinity_group_id IS NOT NULL;
+
+CREATE TYPE omicron.public.affinity_policy AS ENUM (
+    'affinity',
+    'anti_affinity'
+);
+
+/*
+ * Affinity groups constrain where the Instances in them may be placed
+ * relative to one another.
+ */
+CREATE TABLE omicron.public.affinity_group (
+    /* Identity metadata (resource) */
+    id UUID PRIMARY KEY,
+    name STRING(63) NOT NULL,
+    description STRING(512) NOT NULL,
+    time_created TIMESTAMPTZ NOT NULL,
+    time_modified TIMESTAMPTZ NOT NULL,
+    /* Indicates that the object has been deleted */
+    time_deleted TIMESTAMPTZ,
+
+    /* Every affinity group is in exactly one Project at a time. */
+    project_id UUID NOT NULL,
+    policy omicron.public.affinity_policy NOT NULL
+);
+
+CREATE UNIQUE INDEX ON omicron.public.affinity_group (
+    project_id,
+    name
+) WHERE
+    time_deleted IS NULL;
+
+/*
+ * A sled chosen for an Instance in an affinity group while the Instance is
+ * being created, before its Instance record makes that membership visible.
+ * Claims for a group are made while holding its row, so that concurrent
+ * placements see one another.
+ */
+CREATE TABLE omicron.public.affinity_group_claim (
+    instance_id UUID PRIMARY KEY,
+    affinity_group_id UUID NOT NULL,
+    sled_id UUID NOT NULL
+);
+
+CREATE INDEX ON omicron.public.affinity_group_claim (
+    affinity_group_id
+);
+
 
 /*
  * Guest-Visible, Virtual Disks
@@ -329,7 +438,16 @@ CREATE TABLE omicron.public.disk (
 
     /* Disk configuration */
     size_bytes INT NOT NULL,
-    origin_snapshot UUID
+    origin_snapshot UUID,
+
+    /*
+     * The resize in progress, if any.  At most one resize of a Disk runs at a
+     * time.
+     */
+    resize_id UUID,
+
+    /* Key/value labels, as a JSON object of strings */
+    labels JSONB NOT NULL DEFAULT '{}'
 );
 
 CREATE UNIQUE INDEX ON omicron.public.disk (
@@ -343,6 +461,38 @@ CREATE INDEX ON omicron.public.disk (
 ) WHERE
     time_deleted IS NULL AND attach_instance_id IS NOT NULL;
 
+CREATE INDEX ON omicron.public.disk (
+    origin_snapshot
+) WHERE
+    time_deleted IS NULL AND origin_snapshot IS NOT NULL;
+
+/*
+ * Point-in-time snapshots of Disks.  The data itself lives alongside the
+ * Disk's regions, which are managed by the Crucible Agents.
+ */
+CREATE TABLE omicron.public.snapshot (
+    /* Identity metadata (resource) */
+    id UUID PRIMARY KEY,
+    name STRING(63) NOT NULL,
+    description STRING(512) NOT NULL,
+    time_created TIMESTAMPTZ NOT NULL,
+    time_modified TIMESTAMPTZ NOT NULL,
+    /* Indicates that the object has been deleted */
+    time_deleted TIMESTAMPTZ,
+
+    /* Every Snapshot is in the same Project as the Disk it was taken of. */
+    project_id UUID NOT NULL,
+    disk_id UUID NOT NULL,
+
+    /* Size of the Disk when the Snapshot was taken */
+    size_bytes INT NOT NULL
+);
+
+CREATE UNIQUE INDEX ON omicron.public.snapshot (
+    disk_id,
+    name
+) WHERE
+    time_deleted IS NULL;
 
 /*
  * Oximeter collector servers.
@@ -355,6 +505,28 @@ CREATE TABLE omicron.public.oximeter (
     port INT4 NOT NULL
 );
 
+/*
+ * Nexus servers.
+ */
+CREATE TABLE omicron.public.nexus (
+    id UUID PRIMARY KEY,
+    time_created TIMESTAMPTZ NOT NULL,
+    time_modified TIMESTAMPTZ NOT NULL,
+    /* The address of the server's internal API */
+    ip INET NOT NULL,
+    port INT4 NOT NULL
+);
+
+/*
+ * Internal DNS zones, with the generation of each zone's contents most
+ * recently sent to the DNS servers.
+ */
+CREATE TABLE omicron.public.dns_zone (
+    name STRING(63) PRIMARY KEY,
+    time_modified TIMESTAMPTZ NOT NULL,
+    generation INT NOT NULL
+);
+
 /*
  * Information about registered metric producers.
  */
@@ -399,7 +571,10 @@ CREATE TABLE omicron.public.vpc (
 
     /* Used to ensure that two requests do not concurrently modify the
        VPC's firewall */
-    firewall_gen INT NOT NULL
+    firewall_gen INT NOT NULL,
+
+    /* Key/value labels, as a JSON object of strings */
+    labels JSONB NOT NULL DEFAULT '{}'
 );
 
 CREATE UNIQUE INDEX ON omicron.public.vpc (
@@ -419,7 +594,9 @@ CREATE TABLE omicron.public.vpc_subnet (
     time_deleted TIMESTAMPTZ,
     vpc_id UUID NOT NULL,
     ipv4_block INET NOT NULL,
-    ipv6_block INET NOT NULL
+    ipv6_block INET NOT NULL,
+    /* The custom router whose routes apply to the subnet, if any */
+    custom_router_id UUID
 );
 
 /* Subnet and network interface names are unique per VPC, not project */
@@ -456,8 +633,12 @@ CREATE TABLE omicron.public.network_interface (
  * as moving IPs between NICs on different instances, etc.
  */
 
+/*
+ * Interfaces are named and looked up within their Instance, so two Instances
+ * may each have an interface of the same name in the same VPC.
+ */
 CREATE UNIQUE INDEX ON omicron.public.network_interface (
-    vpc_id,
+    instance_id,
     name
 ) WHERE
     time_deleted IS NULL;
@@ -478,6 +659,94 @@ CREATE UNIQUE INDEX ON omicron.public.network_interface (
 ) WHERE
     time_deleted IS NULL;
 
+/*
+ * IP pools and floating IPs
+ *
+ * An IP pool is a fleet-wide set of ranges of external addresses.  Floating
+ * IPs are allocated from a pool into a Project and may be attached to the
+ * primary network interface of one of its Instances.
+ */
+
+CREATE TABLE omicron.public.ip_pool (
+    /* Identity metadata (resource) */
+    id UUID PRIMARY KEY,
+    name STRING(63) NOT NULL,
+    description STRING(512) NOT NULL,
+    time_created TIMESTAMPTZ NOT NULL,
+    time_modified TIMESTAMPTZ NOT NULL,
+    /* Indicates that the object has been deleted */
+    time_deleted TIMESTAMPTZ
+);
+
+CREATE UNIQUE INDEX ON omicron.public.ip_pool (
+    name
+) WHERE
+    time_deleted IS NULL;
+
+/*
+ * An inclusive range of addresses in an IP pool.  Both ends are in the same
+ * address family, and live ranges never overlap, even across pools.
+ */
+CREATE TABLE omicron.public.ip_pool_range (
+    id UUID PRIMARY KEY,
+    time_created TIMESTAMPTZ NOT NULL,
+    /* Indicates that the object has been deleted */
+    time_deleted TIMESTAMPTZ,
+    /* FK into the IP pool table */
+    ip_pool_id UUID NOT NULL,
+    first_address INET NOT NULL,
+    last_address INET NOT NULL
+);
+
+CREATE INDEX ON omicron.public.ip_pool_range (
+    ip_pool_id,
+    first_address
+) WHERE
+    time_deleted IS NULL;
+
+CREATE TABLE omicron.public.floating_ip (
+    /* Identity metadata (resource) */
+    id UUID PRIMARY KEY,
+    name STRING(63) NOT NULL,
+    description STRING(512) NOT NULL,
+    time_created TIMESTAMPTZ NOT NULL,
+    time_modified TIMESTAMPTZ NOT NULL,
+    /* Indicates that the object has been deleted */
+    time_deleted TIMESTAMPTZ,
+    project_id UUID NOT NULL,
+    /* FK into the IP pool table */
+    ip_pool_id UUID NOT NULL,
+    /* FK into the IP pool range table */
+    ip_pool_range_id UUID NOT NULL,
+    ip INET NOT NULL,
+    /* The Instance and network interface this address is attached to, if any */
+    instance_id UUID,
+    network_interface_id UUID
+);
+
+CREATE UNIQUE INDEX ON omicron.public.floating_ip (
+    project_id,
+    name
+) WHERE
+    time_deleted IS NULL;
+
+/* Ensure we do not hand out the same external address twice */
+CREATE UNIQUE INDEX ON omicron.public.floating_ip (
+    ip
+) WHERE
+    time_deleted IS NULL;
+
+/* A network interface has at most one floating IP */
+CREATE UNIQUE INDEX ON omicron.public.floating_ip (
+    network_interface_id
+) WHERE
+    time_deleted IS NULL AND network_interface_id IS NOT NULL;
+
+CREATE INDEX ON omicron.public.floating_ip (
+    instance_id
+) WHERE
+    time_deleted IS NULL AND instance_id IS NOT NULL;
+
 CREATE TYPE omicron.public.vpc_router_kind AS ENUM (
     'system',
     'custom'
@@ -671,6 +940,33 @@ CREATE TABLE omicron.public.saga_node_event (
     PRIMARY KEY (saga_id, node_id, event_type)
 );
 
+/*
+ * Long-running operations carried out by sagas on behalf of the external API
+ *
+ * The state of an operation comes from its saga (see the "saga" and
+ * "saga_node_event" tables).  This table records what the operation acts on so
+ * that operations can be listed and authorized by Project.
+ */
+CREATE TABLE omicron.public.operation (
+    /* the id of the saga carrying out the operation */
+    id UUID PRIMARY KEY,
+    time_created TIMESTAMPTZ NOT NULL,
+    project_id UUID NOT NULL,
+    /* the type of resource the operation acts on, like "instance" */
+    target_type STRING(63) NOT NULL,
+    /*
+     * the resource the operation acts on -- for operations that create it, this
+     * is filled in once it has been created
+     */
+    target_id UUID
+);
+
+/* to be used for listing operations in a Project */
+CREATE UNIQUE INDEX ON omicron.public.operation (
+    project_id,
+    id
+);
+
 /*******************************************************************/
 
 /*
@@ -692,6 +988,204 @@ CREATE INDEX ON omicron.public.console_session (
 
 /*******************************************************************/
 
+/*
+ * Users, who log in with a password that's checked against "password_hash"
+ */
+CREATE TABLE omicron.public.user (
+    /* Identity metadata (resource) */
+    id UUID PRIMARY KEY,
+    name STRING(63) NOT NULL,
+    description STRING(512) NOT NULL,
+    time_created TIMESTAMPTZ NOT NULL,
+    time_modified TIMESTAMPTZ NOT NULL,
+    time_deleted TIMESTAMPTZ,
+
+    /* PHC-format string produced by a slow password hash (argon2) */
+    password_hash STRING(512) NOT NULL
+);
+
+CREATE UNIQUE INDEX ON omicron.public.user (
+    name
+) WHERE
+    time_deleted IS NULL;
+
+/* Used to delete a user's sessions when the user is deleted */
+CREATE INDEX ON omicron.public.console_session (
+    user_id
+);
+
+/*******************************************************************/
+
+/*
+ * API tokens, which users create for automation (e.g., CI pipelines)
+ */
+CREATE TYPE omicron.public.api_token_scope AS ENUM (
+    'read_write',
+    'read_only'
+);
+
+CREATE TABLE omicron.public.api_token (
+    id UUID PRIMARY KEY,
+    description STRING(512) NOT NULL,
+    time_created TIMESTAMPTZ NOT NULL,
+    /* if NULL, the token does not expire */
+    time_expires TIMESTAMPTZ,
+    /* the user on whose behalf requests using this token are made */
+    user_id UUID NOT NULL,
+    /*
+     * hex-encoded SHA-256 hash of the token itself, which is never stored.
+     * (The token is long and random, so a slow hash is not needed.)
+     */
+    token_hash STRING(64) NOT NULL,
+    scope omicron.public.api_token_scope NOT NULL
+);
+
+CREATE UNIQUE INDEX ON omicron.public.api_token (
+    token_hash
+);
+
+/* Used to list a user's tokens (and delete them when the user is deleted) */
+CREATE UNIQUE INDEX ON omicron.public.api_token (
+    user_id,
+    id
+);
+
+/*******************************************************************/
+
+/*
+ * SSH public keys, which users upload to be authorized for logging in to the
+ * instances that they create
+ */
+CREATE TABLE omicron.public.ssh_key (
+    id UUID PRIMARY KEY,
+    description STRING(512) NOT NULL,
+    time_created TIMESTAMPTZ NOT NULL,
+    /* the user who uploaded the key */
+    user_id UUID NOT NULL,
+    /* the key, in OpenSSH's one-line format */
+    public_key STRING(4096) NOT NULL,
+    /* the key's SHA-256 fingerprint, as printed by "ssh-keygen -l" */
+    fingerprint STRING(63) NOT NULL
+);
+
+/* Used to list a user's keys (and delete them when the user is deleted) */
+CREATE UNIQUE INDEX ON omicron.public.ssh_key (
+    user_id,
+    id
+);
+
+/* A user can upload each key only once */
+CREATE UNIQUE INDEX ON omicron.public.ssh_key (
+    user_id,
+    fingerprint
+);
+
+/*******************************************************************/
+
+/*
+ * Audit log of mutating external API calls
+ */
+CREATE TABLE omicron.public.audit_log (
+    id UUID PRIMARY KEY,
+    time_created TIMESTAMPTZ NOT NULL,
+    /* authenticated actor, if the request was authenticated */
+    actor_id UUID,
+    request_id STRING(63) NOT NULL,
+    http_method STRING(15) NOT NULL,
+    http_path STRING(2048) NOT NULL,
+    /* resource that the request acted on, if it got that far */
+    resource_id UUID,
+    http_status INT4 NOT NULL
+);
+
+/* The audit log is listed in chronological order. */
+CREATE INDEX ON omicron.public.audit_log (
+    time_created,
+    id
+);
+
+/*******************************************************************/
+
+/*
+ * Idempotency keys for external API create requests
+ *
+ * When a client retries a create request with the same "Idempotency-Key"
+ * header, Nexus returns the result of the original request instead of doing it
+ * again.  See nexus/src/idempotency.rs.
+ */
+CREATE TABLE omicron.public.idempotency_key (
+    /* the actor that made the request (keys are only unique per actor) */
+    actor_id UUID NOT NULL,
+    client_key STRING(255) NOT NULL,
+    time_created TIMESTAMPTZ NOT NULL,
+    /* the request, which a retry must match */
+    http_method STRING(15) NOT NULL,
+    http_path STRING(2048) NOT NULL,
+    /* hex-encoded SHA-256 hash of the request body */
+    request_hash STRING(64) NOT NULL,
+    /* the saga carrying out the request, if it's saga-backed */
+    saga_id UUID,
+    /* body of the response, once the request has succeeded */
+    response_body JSONB,
+    PRIMARY KEY (actor_id, client_key)
+);
+
+/* to be used for cleaning up expired keys */
+CREATE INDEX ON omicron.public.idempotency_key (
+    time_created
+);
+
+/*******************************************************************/
+
+/*
+ * Log of changes to the resources in each Project
+ *
+ * Clients read a Project's log in order, using "seq" as a cursor, and it's
+ * also delivered to the Project's webhooks.  See nexus/src/events.rs.
+ */
+CREATE TABLE omicron.public.project_event (
+    id UUID PRIMARY KEY,
+    project_id UUID NOT NULL,
+    /* position in the Project's log, counting from 1 */
+    seq INT8 NOT NULL,
+    time_created TIMESTAMPTZ NOT NULL,
+    resource_type STRING(63) NOT NULL,
+    resource_id UUID NOT NULL,
+    /* "created", "state_changed", or "deleted" */
+    event_type STRING(63) NOT NULL,
+    /* the resource's new state, for "state_changed" events */
+    state STRING(63)
+);
+
+CREATE UNIQUE INDEX ON omicron.public.project_event (
+    project_id,
+    seq
+);
+
+/*
+ * URLs to which a Project's events are delivered
+ */
+CREATE TABLE omicron.public.project_webhook (
+    id UUID PRIMARY KEY,
+    project_id UUID NOT NULL,
+    time_created TIMESTAMPTZ NOT NULL,
+    time_deleted TIMESTAMPTZ,
+    url STRING(2048) NOT NULL,
+    /* key with which deliveries are signed */
+    secret STRING(255) NOT NULL,
+    /* "seq" of the last event delivered to the webhook */
+    delivered_seq INT8 NOT NULL,
+    /* "seq" of the last event that couldn't be delivered, if any */
+    last_failed_seq INT8
+);
+
+CREATE INDEX ON omicron.public.project_webhook (
+    project_id
+) WHERE
+    time_deleted IS NULL;
+
+/*******************************************************************/
+
 /*
  * Identity and Access Management (IAM)
  *
@@ -820,6 +1314,37 @@ CREATE TABLE omicron.public.role_assignment_builtin (
     PRIMARY KEY(user_builtin_id, resource_type, resource_id, role_name)
 );
 
+/*
+ * Assignments of built-in roles to users on particular resources, made through
+ * the external API
+ *
+ * This works like "role_assignment_builtin", except that these records come
+ * from updating a resource's policy rather than being fixed when Nexus starts.
+ */
+CREATE TABLE omicron.public.role_assignment (
+    /* Composite foreign key into "role_builtin" table */
+    resource_type STRING(63) NOT NULL,
+    role_name STRING(63) NOT NULL,
+
+    /*
+     * Foreign key into some other resource table.  Which table?  This is
+     * identified implicitly by "resource_type" above.
+     */
+    resource_id UUID NOT NULL,
+
+    /* The user to whom the role is assigned */
+    user_id UUID NOT NULL,
+
+    /* The entire row is the primary key. */
+    PRIMARY KEY(user_id, resource_type, resource_id, role_name)
+);
+
+/* Used to fetch the policy for a resource */
+CREATE INDEX ON omicron.public.role_assignment (
+    resource_type,
+    resource_id
+);
+
 /*******************************************************************/
 
 /*
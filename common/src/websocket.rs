@@ -0,0 +1,201 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Serving and relaying WebSocket connections from dropshot endpoints
+//!
+//! Dropshot has no notion of a WebSocket endpoint.  An endpoint which wants to
+//! speak WebSocket instead returns the "101 Switching Protocols" response
+//! built by [`upgrade`], and hands the accompanying [`OnUpgrade`] to a task
+//! which [`accept`]s the connection once hyper has sent that response.
+
+use dropshot::HttpError;
+use futures::{Sink, SinkExt, StreamExt};
+use http::header;
+use http::HeaderMap;
+use http::StatusCode;
+use hyper::upgrade::OnUpgrade;
+use hyper::upgrade::Upgraded;
+use hyper::{Body, Request, Response};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_tungstenite::tungstenite::handshake::derive_accept_key;
+use tokio_tungstenite::tungstenite::protocol::Role;
+use tokio_tungstenite::tungstenite::Error as WebSocketError;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
+
+/// A WebSocket accepted from a client of a dropshot server
+pub type ServerWebSocket = WebSocketStream<Upgraded>;
+
+/// Returns whether the comma-separated header `name` includes `token`
+fn header_has_token(
+    headers: &HeaderMap,
+    name: header::HeaderName,
+    token: &str,
+) -> bool {
+    headers.get_all(name).iter().any(|value| {
+        value
+            .to_str()
+            .map(|v| v.split(',').any(|t| t.trim().eq_ignore_ascii_case(token)))
+            .unwrap_or(false)
+    })
+}
+
+/// Validates that `request` asks to switch to the WebSocket protocol
+///
+/// On success, returns the response which completes the handshake, along with
+/// a future which resolves to the upgraded connection once that response has
+/// been sent.
+pub fn upgrade(
+    request: &mut Request<Body>,
+) -> Result<(Response<Body>, OnUpgrade), HttpError> {
+    let bad_request = |message: &str| {
+        HttpError::for_bad_request(None, format!("WebSocket: {}", message))
+    };
+
+    let headers = request.headers();
+    if !header_has_token(headers, header::CONNECTION, "upgrade")
+        || !header_has_token(headers, header::UPGRADE, "websocket")
+    {
+        return Err(bad_request("expected a request to upgrade"));
+    }
+    if headers.get(header::SEC_WEBSOCKET_VERSION).map(|v| v.as_bytes())
+        != Some(&b"13"[..])
+    {
+        return Err(bad_request("unsupported protocol version"));
+    }
+    let accept_key = match headers.get(header::SEC_WEBSOCKET_KEY) {
+        Some(key) => derive_accept_key(key.as_bytes()),
+        None => return Err(bad_request("missing key")),
+    };
+
+    let response = Response::builder()
+        .status(StatusCode::SWITCHING_PROTOCOLS)
+        .header(header::CONNECTION, "Upgrade")
+        .header(header::UPGRADE, "websocket")
+        .header(header::SEC_WEBSOCKET_ACCEPT, accept_key)
+        .body(Body::empty())?;
+    Ok((response, hyper::upgrade::on(request)))
+}
+
+/// Waits for the connection handed back by [`upgrade`], then begins speaking
+/// WebSocket on it as the server
+pub async fn accept(
+    on_upgrade: OnUpgrade,
+) -> Result<ServerWebSocket, hyper::Error> {
+    let upgraded = on_upgrade.await?;
+    Ok(WebSocketStream::from_raw_socket(upgraded, Role::Server, None).await)
+}
+
+/// Passes one message read from one end of a relay on to the other end
+///
+/// Returns whether the relay should keep going.
+async fn forward<S>(
+    message: Option<Result<Message, WebSocketError>>,
+    sink: &mut S,
+) -> Result<bool, WebSocketError>
+where
+    S: Sink<Message, Error = WebSocketError> + Unpin,
+{
+    match message {
+        None => Ok(false),
+        Some(Err(error)) => Err(error),
+        // Each end of the relay answers its own peer's pings.
+        Some(Ok(Message::Ping(_))) | Some(Ok(Message::Pong(_))) => Ok(true),
+        Some(Ok(message @ Message::Close(_))) => {
+            // The other end may well have gone away already.
+            let _ = sink.send(message).await;
+            Ok(false)
+        }
+        Some(Ok(message)) => {
+            sink.send(message).await?;
+            Ok(true)
+        }
+    }
+}
+
+/// Copies messages between two WebSockets in both directions until either one
+/// of them closes
+pub async fn relay<A, B>(
+    a: WebSocketStream<A>,
+    b: WebSocketStream<B>,
+) -> Result<(), WebSocketError>
+where
+    A: AsyncRead + AsyncWrite + Unpin,
+    B: AsyncRead + AsyncWrite + Unpin,
+{
+    let (mut a_sink, mut a_stream) = a.split();
+    let (mut b_sink, mut b_stream) = b.split();
+    loop {
+        let keep_going = tokio::select! {
+            message = a_stream.next() => forward(message, &mut b_sink).await?,
+            message = b_stream.next() => forward(message, &mut a_sink).await?,
+        };
+        if !keep_going {
+            return Ok(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::upgrade;
+    use http::header;
+    use http::StatusCode;
+    use hyper::{Body, Request};
+
+    fn request(headers: &[(header::HeaderName, &str)]) -> Request<Body> {
+        let mut builder = Request::builder().uri("/serial-console");
+        for (name, value) in headers {
+            builder = builder.header(name, *value);
+        }
+        builder.body(Body::empty()).unwrap()
+    }
+
+    #[test]
+    fn test_upgrade() {
+        // This is the example handshake from RFC 6455.
+        let mut good = request(&[
+            (header::CONNECTION, "keep-alive, Upgrade"),
+            (header::UPGRADE, "websocket"),
+            (header::SEC_WEBSOCKET_VERSION, "13"),
+            (header::SEC_WEBSOCKET_KEY, "dGhlIHNhbXBsZSBub25jZQ=="),
+        ]);
+        let (response, _) = upgrade(&mut good).unwrap();
+        assert_eq!(response.status(), StatusCode::SWITCHING_PROTOCOLS);
+        assert_eq!(
+            response.headers()[header::SEC_WEBSOCKET_ACCEPT],
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+
+        let bad_requests = vec![
+            request(&[]),
+            request(&[
+                (header::UPGRADE, "websocket"),
+                (header::SEC_WEBSOCKET_VERSION, "13"),
+                (header::SEC_WEBSOCKET_KEY, "dGhlIHNhbXBsZSBub25jZQ=="),
+            ]),
+            request(&[
+                (header::CONNECTION, "Upgrade"),
+                (header::UPGRADE, "h2c"),
+                (header::SEC_WEBSOCKET_VERSION, "13"),
+                (header::SEC_WEBSOCKET_KEY, "dGhlIHNhbXBsZSBub25jZQ=="),
+            ]),
+            request(&[
+                (header::CONNECTION, "Upgrade"),
+                (header::UPGRADE, "websocket"),
+                (header::SEC_WEBSOCKET_VERSION, "8"),
+                (header::SEC_WEBSOCKET_KEY, "dGhlIHNhbXBsZSBub25jZQ=="),
+            ]),
+            request(&[
+                (header::CONNECTION, "Upgrade"),
+                (header::UPGRADE, "websocket"),
+                (header::SEC_WEBSOCKET_VERSION, "13"),
+            ]),
+        ];
+        for mut bad in bad_requests {
+            let error = upgrade(&mut bad).unwrap_err();
+            assert_eq!(error.status_code, StatusCode::BAD_REQUEST);
+        }
+    }
+}
@@ -0,0 +1,48 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Copyright 2022 Oxide Computer Company
+
+//! Interface for API requests to an internal DNS server
+
+omicron_common::generate_logging_api!("../openapi/internal-dns.json");
+
+impl From<omicron_common::api::external::Generation> for types::Generation {
+    fn from(s: omicron_common::api::external::Generation) -> Self {
+        Self(i64::from(&s) as u64)
+    }
+}
+
+impl From<omicron_common::api::internal::dns::DnsZoneConfig>
+    for types::DnsZoneConfig
+{
+    fn from(s: omicron_common::api::internal::dns::DnsZoneConfig) -> Self {
+        Self {
+            generation: s.generation.into(),
+            records: s
+                .records
+                .into_iter()
+                .map(|(name, records)| {
+                    (name, records.into_iter().map(|r| r.into()).collect())
+                })
+                .collect(),
+        }
+    }
+}
+
+impl From<omicron_common::api::internal::dns::DnsRecord> for types::DnsRecord {
+    fn from(s: omicron_common::api::internal::dns::DnsRecord) -> Self {
+        use omicron_common::api::internal::dns::DnsRecord;
+        match s {
+            DnsRecord::A(address) => Self::A(address.to_string()),
+            DnsRecord::Aaaa(address) => Self::Aaaa(address.to_string()),
+            DnsRecord::Srv(srv) => Self::Srv(types::Srv {
+                port: srv.port,
+                prio: srv.prio,
+                target: srv.target,
+                weight: srv.weight,
+            }),
+        }
+    }
+}
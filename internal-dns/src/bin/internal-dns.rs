@@ -0,0 +1,61 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Main entry point to run an internal DNS server in the control plane.
+// Copyright 2022 Oxide Computer Company
+
+use internal_dns::{dns_api, run_server, Config};
+use omicron_common::cmd::fatal;
+use omicron_common::cmd::CmdError;
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+pub fn run_openapi() -> Result<(), String> {
+    dns_api()
+        .openapi("Oxide Internal DNS API", "0.0.1")
+        .description("API for updating the control plane's internal DNS zone")
+        .contact_url("https://oxide.computer")
+        .contact_email("api@oxide.computer")
+        .write(&mut std::io::stdout())
+        .map_err(|e| e.to_string())
+}
+
+/// Run an internal DNS server in the Oxide Control Plane.
+#[derive(StructOpt)]
+#[structopt(
+    name = "internal-dns",
+    about = "See README.adoc for more information"
+)]
+struct Args {
+    #[structopt(
+        short = "O",
+        long = "openapi",
+        help = "Print the external OpenAPI Spec document and exit"
+    )]
+    openapi: bool,
+
+    /// Path to TOML file with configuration for the server
+    #[structopt(name = "CONFIG_FILE", parse(from_os_str))]
+    config_file: PathBuf,
+}
+
+#[tokio::main]
+async fn main() {
+    if let Err(cmd_error) = do_run().await {
+        fatal(cmd_error);
+    }
+}
+
+async fn do_run() -> Result<(), CmdError> {
+    let args = Args::from_args_safe().map_err(|err| {
+        CmdError::Usage(format!("parsing arguments: {}", err.message))
+    })?;
+    let config = Config::from_file(args.config_file)
+        .map_err(|e| CmdError::Failure(e.to_string()))?;
+    if args.openapi {
+        run_openapi().map_err(CmdError::Failure)
+    } else {
+        run_server(&config).await.map_err(CmdError::Failure)
+    }
+}
@@ -0,0 +1,292 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Answers DNS queries over UDP from the contents of the zone
+
+use crate::wire::{
+    message_id, Message, Rcode, Record, RecordData, CLASS_IN, TYPE_A,
+    TYPE_AAAA, TYPE_ANY, TYPE_SRV,
+};
+use crate::zone::{Lookup, Zone};
+use omicron_common::api::internal::dns::DnsRecord;
+use slog::{debug, error, trace, Logger};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::net::UdpSocket;
+
+/// Largest response that fits in a UDP datagram without EDNS (RFC 1035
+/// section 4.2.1)
+const MAX_UDP_RESPONSE: usize = 512;
+
+/// Answers queries arriving on `socket` until the socket fails
+///
+/// The zone changes whenever Nexus updates it, so records are served with a
+/// TTL of zero and clients don't cache them.
+// TODO-completeness There's no TCP listener, so clients can't retrieve
+// responses that had to be truncated.  The names in this zone are short and
+// there are few records per name, so this shouldn't happen in practice.
+pub async fn serve(log: Logger, zone: Arc<Zone>, socket: UdpSocket) {
+    let mut buf = [0u8; 65536];
+    loop {
+        let (len, client) = match socket.recv_from(&mut buf).await {
+            Ok(received) => received,
+            Err(error) => {
+                error!(log, "failed to receive DNS query"; "error" => ?error);
+                return;
+            }
+        };
+        let response = match respond(&log, &zone, &buf[..len], client) {
+            Some(response) => response,
+            None => continue,
+        };
+        if let Err(error) = socket.send_to(&response, client).await {
+            debug!(
+                log,
+                "failed to send DNS response";
+                "client" => client.to_string(),
+                "error" => ?error,
+            );
+        }
+    }
+}
+
+/// Returns the encoded response to the datagram `query` from `client`, or
+/// `None` if it doesn't warrant one
+fn respond(
+    log: &Logger,
+    zone: &Zone,
+    query: &[u8],
+    client: SocketAddr,
+) -> Option<Vec<u8>> {
+    let query = match Message::parse(query) {
+        Ok(query) => query,
+        Err(error) => {
+            debug!(
+                log,
+                "failed to parse DNS query";
+                "client" => client.to_string(),
+                "error" => %error,
+            );
+            // Answer if there's enough of a header to address the response.
+            let response = Message {
+                id: message_id(query)?,
+                response: true,
+                opcode: 0,
+                authoritative: false,
+                truncated: false,
+                recursion_desired: false,
+                rcode: Rcode::FormErr,
+                questions: vec![],
+                answers: vec![],
+            };
+            return response.to_bytes().ok();
+        }
+    };
+    if query.response {
+        // Responses are never solicited, so don't answer them.
+        return None;
+    }
+
+    let response = answer(zone, &query);
+    trace!(
+        log,
+        "answering DNS query";
+        "client" => client.to_string(),
+        "questions" => ?query.questions,
+        "rcode" => ?response.rcode,
+        "answers" => response.answers.len(),
+    );
+    match encode(response) {
+        Ok(bytes) => Some(bytes),
+        Err(error) => {
+            error!(log, "failed to encode DNS response"; "error" => %error);
+            let response = Message::response_to(&query, Rcode::ServFail);
+            response.to_bytes().ok()
+        }
+    }
+}
+
+/// Returns the response to `query`
+fn answer(zone: &Zone, query: &Message) -> Message {
+    if query.opcode != 0 {
+        return Message::response_to(query, Rcode::NotImp);
+    }
+    let question = match query.questions.as_slice() {
+        [question] => question,
+        _ => return Message::response_to(query, Rcode::FormErr),
+    };
+    if question.qclass != CLASS_IN {
+        return Message::response_to(query, Rcode::Refused);
+    }
+    let records = match zone.lookup(&question.name) {
+        Lookup::NotInZone => {
+            return Message::response_to(query, Rcode::Refused)
+        }
+        Lookup::NoSuchName => {
+            let mut response = Message::response_to(query, Rcode::NxDomain);
+            response.authoritative = true;
+            return response;
+        }
+        Lookup::Records(records) => records,
+    };
+
+    let mut response = Message::response_to(query, Rcode::NoError);
+    response.authoritative = true;
+    response.answers = records
+        .into_iter()
+        .filter_map(|record| {
+            let data = match record {
+                DnsRecord::A(address) => RecordData::A(address),
+                DnsRecord::Aaaa(address) => RecordData::Aaaa(address),
+                DnsRecord::Srv(srv) => RecordData::Srv {
+                    prio: srv.prio,
+                    weight: srv.weight,
+                    port: srv.port,
+                    target: format!("{}.{}", srv.target, zone.name()),
+                },
+            };
+            let wanted = match &data {
+                RecordData::A(_) => TYPE_A,
+                RecordData::Aaaa(_) => TYPE_AAAA,
+                RecordData::Srv { .. } => TYPE_SRV,
+            };
+            if question.qtype == wanted || question.qtype == TYPE_ANY {
+                Some(Record { name: question.name.clone(), ttl: 0, data })
+            } else {
+                None
+            }
+        })
+        .collect();
+    response
+}
+
+/// Encodes `response`, dropping answers that don't fit in a datagram
+fn encode(mut response: Message) -> Result<Vec<u8>, crate::wire::WireError> {
+    loop {
+        let bytes = response.to_bytes()?;
+        if bytes.len() <= MAX_UDP_RESPONSE || response.answers.is_empty() {
+            return Ok(bytes);
+        }
+        response.answers.pop();
+        response.truncated = true;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{answer, encode};
+    use crate::wire::{
+        Message, Rcode, Record, RecordData, TYPE_A, TYPE_AAAA, TYPE_ANY,
+        TYPE_SRV,
+    };
+    use crate::zone::Zone;
+    use omicron_common::api::internal::dns::{DnsRecord, DnsZoneConfig, Srv};
+    use std::collections::BTreeMap;
+
+    fn test_zone() -> Zone {
+        let zone = Zone::new("internal");
+        let mut records = BTreeMap::new();
+        records.insert(
+            String::from("_nexus._tcp"),
+            vec![DnsRecord::Srv(Srv {
+                prio: 0,
+                weight: 0,
+                port: 12221,
+                target: String::from("nexus1.host"),
+            })],
+        );
+        records.insert(
+            String::from("nexus1.host"),
+            vec![
+                DnsRecord::Aaaa("fd00::1".parse().unwrap()),
+                DnsRecord::A("10.1.0.1".parse().unwrap()),
+            ],
+        );
+        zone.update(DnsZoneConfig {
+            generation: zone.config().generation.next(),
+            records,
+        })
+        .unwrap();
+        zone
+    }
+
+    #[test]
+    fn test_answer() {
+        let zone = test_zone();
+        let a = RecordData::A("10.1.0.1".parse().unwrap());
+        let aaaa = RecordData::Aaaa("fd00::1".parse().unwrap());
+        let srv = RecordData::Srv {
+            prio: 0,
+            weight: 0,
+            port: 12221,
+            target: String::from("nexus1.host.internal"),
+        };
+        let cases = [
+            ("nexus1.host.internal", TYPE_A, Rcode::NoError, vec![a.clone()]),
+            (
+                "nexus1.host.internal",
+                TYPE_AAAA,
+                Rcode::NoError,
+                vec![aaaa.clone()],
+            ),
+            ("nexus1.host.internal", TYPE_ANY, Rcode::NoError, vec![aaaa, a]),
+            ("nexus1.host.internal", TYPE_SRV, Rcode::NoError, vec![]),
+            ("_nexus._tcp.internal", TYPE_SRV, Rcode::NoError, vec![srv]),
+            ("internal", TYPE_A, Rcode::NoError, vec![]),
+            ("nexus2.host.internal", TYPE_A, Rcode::NxDomain, vec![]),
+            ("oxide.computer", TYPE_A, Rcode::Refused, vec![]),
+        ];
+        for (name, qtype, rcode, data) in cases {
+            let query = Message::query(7, name, qtype);
+            let response = answer(&zone, &query);
+            let expected_answers: Vec<_> = data
+                .into_iter()
+                .map(|data| Record { name: name.to_string(), ttl: 0, data })
+                .collect();
+            assert_eq!(response.id, 7);
+            assert!(response.response);
+            assert_eq!(response.rcode, rcode, "name {}", name);
+            assert_eq!(response.answers, expected_answers, "name {}", name);
+            assert_eq!(response.authoritative, rcode != Rcode::Refused);
+        }
+    }
+
+    #[test]
+    fn test_answer_bad_queries() {
+        let zone = test_zone();
+
+        let mut query = Message::query(1, "nexus1.host.internal", TYPE_A);
+        query.opcode = 2;
+        assert_eq!(answer(&zone, &query).rcode, Rcode::NotImp);
+
+        let mut query = Message::query(1, "nexus1.host.internal", TYPE_A);
+        query.questions.push(query.questions[0].clone());
+        assert_eq!(answer(&zone, &query).rcode, Rcode::FormErr);
+        query.questions.clear();
+        assert_eq!(answer(&zone, &query).rcode, Rcode::FormErr);
+
+        let mut query = Message::query(1, "nexus1.host.internal", TYPE_A);
+        query.questions[0].qclass = 3;
+        assert_eq!(answer(&zone, &query).rcode, Rcode::Refused);
+    }
+
+    #[test]
+    fn test_encode_truncates() {
+        let query = Message::query(1, "many.internal", TYPE_A);
+        let mut response = Message::response_to(&query, Rcode::NoError);
+        response.answers = (0..100)
+            .map(|i| Record {
+                name: String::from("many.internal"),
+                ttl: 0,
+                data: RecordData::A([10, 0, 0, i].into()),
+            })
+            .collect();
+        let bytes = encode(response).unwrap();
+        assert!(bytes.len() <= 512);
+        let decoded = Message::parse(&bytes).unwrap();
+        assert!(decoded.truncated);
+        assert!(!decoded.answers.is_empty());
+        assert!(decoded.answers.len() < 100);
+    }
+}
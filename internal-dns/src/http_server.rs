@@ -0,0 +1,61 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! HTTP API used by Nexus to update the zone
+
+use crate::zone::Zone;
+use dropshot::{
+    endpoint, ApiDescription, HttpError, HttpResponseOk,
+    HttpResponseUpdatedNoContent, RequestContext, TypedBody,
+};
+use http::StatusCode;
+use omicron_common::api::internal::dns::DnsZoneConfig;
+use std::sync::Arc;
+
+/// Returns a description of the internal DNS server's HTTP API
+pub fn dns_api() -> ApiDescription<Arc<Zone>> {
+    fn register_endpoints(
+        api: &mut ApiDescription<Arc<Zone>>,
+    ) -> Result<(), String> {
+        api.register(zone_get)?;
+        api.register(zone_put)?;
+        Ok(())
+    }
+
+    let mut api = ApiDescription::new();
+    if let Err(err) = register_endpoints(&mut api) {
+        panic!("failed to register entrypoints: {}", err);
+    }
+    api
+}
+
+/// Fetch the records currently served
+#[endpoint {
+    method = GET,
+    path = "/zone",
+}]
+async fn zone_get(
+    rqctx: Arc<RequestContext<Arc<Zone>>>,
+) -> Result<HttpResponseOk<DnsZoneConfig>, HttpError> {
+    Ok(HttpResponseOk(rqctx.context().config()))
+}
+
+/// Replace the records served with those of a newer generation
+#[endpoint {
+    method = PUT,
+    path = "/zone",
+}]
+async fn zone_put(
+    rqctx: Arc<RequestContext<Arc<Zone>>>,
+    body: TypedBody<DnsZoneConfig>,
+) -> Result<HttpResponseUpdatedNoContent, HttpError> {
+    rqctx.context().update(body.into_inner()).map_err(|error| {
+        HttpError::for_client_error(
+            None,
+            StatusCode::CONFLICT,
+            error.to_string(),
+        )
+    })?;
+    Ok(HttpResponseUpdatedNoContent())
+}
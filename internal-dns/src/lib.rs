@@ -0,0 +1,151 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Authoritative DNS server for the control plane's internal zone
+//!
+//! The server answers queries for names under `.internal`: instances, as
+//! `<hostname>.<vpc>.<project>.internal`, and control plane services, through
+//! SRV records like `_nexus._tcp.internal`.  It has no records of its own.
+//! Nexus builds the zone from the database and replaces it over the HTTP API
+//! whenever it changes.
+
+// Copyright 2022 Oxide Computer Company
+
+use dropshot::{ConfigDropshot, ConfigLogging, HttpServer, HttpServerStarter};
+use omicron_common::api::internal::dns::DNS_ZONE;
+use serde::{Deserialize, Serialize};
+use slog::{debug, error, info, o, Logger};
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
+use thiserror::Error;
+use tokio::net::UdpSocket;
+use tokio::task::JoinHandle;
+
+mod dns_server;
+mod http_server;
+pub mod wire;
+mod zone;
+
+pub use http_server::dns_api;
+pub use zone::Zone;
+
+/// Errors running the internal DNS server
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Error running internal DNS server: {0}")]
+    Server(String),
+}
+
+/// Configuration for the DNS listener
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct DnsConfig {
+    /// The address on which to answer DNS queries, over UDP
+    pub bind_address: SocketAddr,
+}
+
+/// Configuration used to initialize an internal DNS server
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Config {
+    /// Logging configuration
+    pub log: ConfigLogging,
+
+    /// The HTTP server used by Nexus to update the zone
+    pub dropshot: ConfigDropshot,
+
+    /// The DNS server
+    pub dns: DnsConfig,
+}
+
+impl Config {
+    /// Load configuration for an internal DNS server from a file.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Config, Error> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| Error::Server(e.to_string()))?;
+        toml::from_str(&contents).map_err(|e| Error::Server(e.to_string()))
+    }
+}
+
+/// A running internal DNS server
+pub struct Server {
+    /// the HTTP server used to update the zone
+    pub http_server: HttpServer<Arc<Zone>>,
+    /// the address on which DNS queries are answered
+    pub dns_address: SocketAddr,
+    dns_task: JoinHandle<()>,
+}
+
+impl Server {
+    /// Start an internal DNS server with an empty zone
+    pub async fn start(config: &Config, log: &Logger) -> Result<Server, Error> {
+        let zone = Arc::new(Zone::new(DNS_ZONE));
+
+        let socket = UdpSocket::bind(config.dns.bind_address)
+            .await
+            .map_err(|e| Error::Server(e.to_string()))?;
+        let dns_address =
+            socket.local_addr().map_err(|e| Error::Server(e.to_string()))?;
+        let dns_log = log.new(o!("component" => "dns"));
+        let dns_task =
+            tokio::spawn(dns_server::serve(dns_log, Arc::clone(&zone), socket));
+
+        let dropshot_log = log.new(o!("component" => "dropshot"));
+        let http_server = HttpServerStarter::new(
+            &config.dropshot,
+            dns_api(),
+            zone,
+            &dropshot_log,
+        )
+        .map_err(|e| Error::Server(e.to_string()))?
+        .start();
+
+        info!(
+            log,
+            "internal DNS server started";
+            "dns_address" => dns_address.to_string(),
+            "http_address" => http_server.local_addr().to_string(),
+        );
+        Ok(Server { http_server, dns_address, dns_task })
+    }
+
+    /// Wait for the server to shut down
+    ///
+    /// Note that this doesn't initiate a graceful shutdown, so if you call
+    /// this immediately after calling `start()`, the program will block
+    /// indefinitely or until something else initiates a graceful shutdown.
+    pub async fn wait_for_finish(self) -> Result<(), Error> {
+        let result = self.http_server.await.map_err(Error::Server);
+        self.dns_task.abort();
+        result
+    }
+
+    /// Shut down the server
+    pub async fn close(self) -> Result<(), Error> {
+        self.dns_task.abort();
+        self.http_server.close().await.map_err(Error::Server)
+    }
+}
+
+/// Run an instance of the [Server].
+pub async fn run_server(config: &Config) -> Result<(), String> {
+    use slog::Drain;
+    let (drain, registration) = slog_dtrace::with_drain(
+        config
+            .log
+            .to_logger("internal-dns")
+            .map_err(|message| format!("initializing logger: {}", message))?,
+    );
+    let log = slog::Logger::root(drain.fuse(), slog::o!());
+    if let slog_dtrace::ProbeRegistration::Failed(e) = registration {
+        let msg = format!("failed to register DTrace probes: {}", e);
+        error!(log, "{}", msg);
+        return Err(msg);
+    } else {
+        debug!(log, "registered DTrace probes");
+    }
+    let server =
+        Server::start(config, &log).await.map_err(|e| e.to_string())?;
+    server.wait_for_finish().await.map_err(|e| e.to_string())
+}
@@ -0,0 +1,542 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Encoding and decoding DNS messages (RFC 1035)
+//!
+//! This supports only what the internal DNS server needs: queries with their
+//! questions, and responses with A, AAAA, and SRV answers.  Names are never
+//! compressed when encoding, but compressed names are understood when
+//! decoding.  The authority and additional sections of a message are ignored
+//! when decoding, and always empty when encoding.
+
+use std::convert::TryFrom;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use thiserror::Error;
+
+/// Record type of an IPv4 address
+pub const TYPE_A: u16 = 1;
+/// Record type of an IPv6 address
+pub const TYPE_AAAA: u16 = 28;
+/// Record type of a service location
+pub const TYPE_SRV: u16 = 33;
+/// Query type matching records of any type
+pub const TYPE_ANY: u16 = 255;
+/// The Internet class, the only one we serve
+pub const CLASS_IN: u16 = 1;
+
+/// Size of the fixed header at the start of every message
+const HEADER_LEN: usize = 12;
+/// Longest name that can be encoded
+const MAX_NAME_LEN: usize = 255;
+/// Longest label that can be encoded
+const MAX_LABEL_LEN: usize = 63;
+/// Most compression pointers followed while decoding one name, to stop loops
+const MAX_POINTERS: usize = 16;
+
+/// Errors encoding or decoding a DNS message
+#[derive(Debug, Error, PartialEq)]
+pub enum WireError {
+    #[error("message ends unexpectedly")]
+    Truncated,
+    #[error("invalid name: {0}")]
+    InvalidName(String),
+    #[error("unsupported response code {0}")]
+    UnsupportedRcode(u8),
+    #[error("too many entries to encode")]
+    TooLong,
+}
+
+/// The response code of a message
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Rcode {
+    NoError,
+    FormErr,
+    ServFail,
+    NxDomain,
+    NotImp,
+    Refused,
+}
+
+impl Rcode {
+    fn to_u8(self) -> u8 {
+        match self {
+            Rcode::NoError => 0,
+            Rcode::FormErr => 1,
+            Rcode::ServFail => 2,
+            Rcode::NxDomain => 3,
+            Rcode::NotImp => 4,
+            Rcode::Refused => 5,
+        }
+    }
+
+    fn from_u8(value: u8) -> Result<Rcode, WireError> {
+        match value {
+            0 => Ok(Rcode::NoError),
+            1 => Ok(Rcode::FormErr),
+            2 => Ok(Rcode::ServFail),
+            3 => Ok(Rcode::NxDomain),
+            4 => Ok(Rcode::NotImp),
+            5 => Ok(Rcode::Refused),
+            _ => Err(WireError::UnsupportedRcode(value)),
+        }
+    }
+}
+
+/// A DNS message: a query, or the response to one
+#[derive(Clone, Debug, PartialEq)]
+pub struct Message {
+    /// chosen by the client to match responses to queries
+    pub id: u16,
+    /// whether this is a response, rather than a query
+    pub response: bool,
+    /// kind of query; only standard queries (0) are supported
+    pub opcode: u8,
+    /// whether the response comes from the server authoritative for the name
+    pub authoritative: bool,
+    /// whether the response was cut short to fit in a datagram
+    pub truncated: bool,
+    /// whether the client asked the server to resolve the name recursively
+    pub recursion_desired: bool,
+    pub rcode: Rcode,
+    pub questions: Vec<Question>,
+    pub answers: Vec<Record>,
+}
+
+impl Message {
+    /// Returns a standard query for records of type `qtype` named `name`
+    pub fn query(id: u16, name: &str, qtype: u16) -> Message {
+        Message {
+            id,
+            response: false,
+            opcode: 0,
+            authoritative: false,
+            truncated: false,
+            recursion_desired: false,
+            rcode: Rcode::NoError,
+            questions: vec![Question {
+                name: name.to_string(),
+                qtype,
+                qclass: CLASS_IN,
+            }],
+            answers: vec![],
+        }
+    }
+
+    /// Returns an empty response to `query` with the given response code
+    pub fn response_to(query: &Message, rcode: Rcode) -> Message {
+        Message {
+            id: query.id,
+            response: true,
+            opcode: query.opcode,
+            authoritative: false,
+            truncated: false,
+            recursion_desired: query.recursion_desired,
+            rcode,
+            questions: query.questions.clone(),
+            answers: vec![],
+        }
+    }
+
+    /// Decodes a message
+    pub fn parse(buf: &[u8]) -> Result<Message, WireError> {
+        let mut reader = Reader { buf, pos: 0 };
+        let id = reader.u16()?;
+        let flags = reader.u16()?;
+        let qdcount = reader.u16()?;
+        let ancount = reader.u16()?;
+        let _nscount = reader.u16()?;
+        let _arcount = reader.u16()?;
+
+        let mut questions = Vec::with_capacity(usize::from(qdcount));
+        for _ in 0..qdcount {
+            questions.push(Question {
+                name: reader.name()?,
+                qtype: reader.u16()?,
+                qclass: reader.u16()?,
+            });
+        }
+        let mut answers = Vec::with_capacity(usize::from(ancount));
+        for _ in 0..ancount {
+            if let Some(record) = reader.record()? {
+                answers.push(record);
+            }
+        }
+
+        Ok(Message {
+            id,
+            response: flags & 0x8000 != 0,
+            opcode: ((flags >> 11) & 0xf) as u8,
+            authoritative: flags & 0x0400 != 0,
+            truncated: flags & 0x0200 != 0,
+            recursion_desired: flags & 0x0100 != 0,
+            rcode: Rcode::from_u8((flags & 0xf) as u8)?,
+            questions,
+            answers,
+        })
+    }
+
+    /// Encodes the message
+    pub fn to_bytes(&self) -> Result<Vec<u8>, WireError> {
+        let mut flags =
+            u16::from(self.opcode & 0xf) << 11 | u16::from(self.rcode.to_u8());
+        if self.response {
+            flags |= 0x8000;
+        }
+        if self.authoritative {
+            flags |= 0x0400;
+        }
+        if self.truncated {
+            flags |= 0x0200;
+        }
+        if self.recursion_desired {
+            flags |= 0x0100;
+        }
+
+        let mut buf = Vec::with_capacity(512);
+        put_u16(&mut buf, self.id);
+        put_u16(&mut buf, flags);
+        put_u16(&mut buf, count(self.questions.len())?);
+        put_u16(&mut buf, count(self.answers.len())?);
+        put_u16(&mut buf, 0);
+        put_u16(&mut buf, 0);
+        for question in &self.questions {
+            put_name(&mut buf, &question.name)?;
+            put_u16(&mut buf, question.qtype);
+            put_u16(&mut buf, question.qclass);
+        }
+        for answer in &self.answers {
+            put_name(&mut buf, &answer.name)?;
+            put_u16(&mut buf, answer.data.rtype());
+            put_u16(&mut buf, CLASS_IN);
+            buf.extend_from_slice(&answer.ttl.to_be_bytes());
+            let mut rdata = Vec::new();
+            match &answer.data {
+                RecordData::A(address) => {
+                    rdata.extend_from_slice(&address.octets())
+                }
+                RecordData::Aaaa(address) => {
+                    rdata.extend_from_slice(&address.octets())
+                }
+                RecordData::Srv { prio, weight, port, target } => {
+                    put_u16(&mut rdata, *prio);
+                    put_u16(&mut rdata, *weight);
+                    put_u16(&mut rdata, *port);
+                    put_name(&mut rdata, target)?;
+                }
+            }
+            put_u16(&mut buf, count(rdata.len())?);
+            buf.extend_from_slice(&rdata);
+        }
+        Ok(buf)
+    }
+}
+
+/// A question in a message: the name and type of the records wanted
+#[derive(Clone, Debug, PartialEq)]
+pub struct Question {
+    /// fully-qualified name, without the trailing dot
+    pub name: String,
+    pub qtype: u16,
+    pub qclass: u16,
+}
+
+/// A resource record in the answer section of a response
+#[derive(Clone, Debug, PartialEq)]
+pub struct Record {
+    /// fully-qualified name, without the trailing dot
+    pub name: String,
+    /// how many seconds the record may be cached
+    pub ttl: u32,
+    pub data: RecordData,
+}
+
+/// The type-specific data of a resource record
+#[derive(Clone, Debug, PartialEq)]
+pub enum RecordData {
+    A(Ipv4Addr),
+    Aaaa(Ipv6Addr),
+    Srv { prio: u16, weight: u16, port: u16, target: String },
+}
+
+impl RecordData {
+    fn rtype(&self) -> u16 {
+        match self {
+            RecordData::A(_) => TYPE_A,
+            RecordData::Aaaa(_) => TYPE_AAAA,
+            RecordData::Srv { .. } => TYPE_SRV,
+        }
+    }
+}
+
+/// Reads the fields of a message in order
+struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], WireError> {
+        let bytes = self
+            .buf
+            .get(self.pos..self.pos + len)
+            .ok_or(WireError::Truncated)?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, WireError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, WireError> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, WireError> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    /// Reads a name, following compression pointers
+    fn name(&mut self) -> Result<String, WireError> {
+        let mut labels = Vec::new();
+        let mut pos = self.pos;
+        let mut end = None;
+        let mut pointers = 0;
+        loop {
+            let len = *self.buf.get(pos).ok_or(WireError::Truncated)?;
+            match len & 0xc0 {
+                0x00 if len == 0 => {
+                    end.get_or_insert(pos + 1);
+                    break;
+                }
+                0x00 => {
+                    let start = pos + 1;
+                    let label = self
+                        .buf
+                        .get(start..start + usize::from(len))
+                        .ok_or(WireError::Truncated)?;
+                    let label = std::str::from_utf8(label).map_err(|_| {
+                        WireError::InvalidName(String::from(
+                            "label is not valid UTF-8",
+                        ))
+                    })?;
+                    labels.push(label.to_string());
+                    pos = start + usize::from(len);
+                }
+                0xc0 => {
+                    let low =
+                        *self.buf.get(pos + 1).ok_or(WireError::Truncated)?;
+                    end.get_or_insert(pos + 2);
+                    pointers += 1;
+                    if pointers > MAX_POINTERS {
+                        return Err(WireError::InvalidName(String::from(
+                            "too many compression pointers",
+                        )));
+                    }
+                    pos = usize::from(len & 0x3f) << 8 | usize::from(low);
+                }
+                _ => {
+                    return Err(WireError::InvalidName(format!(
+                        "unsupported label type {:#x}",
+                        len
+                    )));
+                }
+            }
+        }
+        self.pos = end.unwrap();
+        Ok(labels.join("."))
+    }
+
+    /// Reads a resource record, returning `None` if it's of a type we don't
+    /// decode
+    fn record(&mut self) -> Result<Option<Record>, WireError> {
+        let name = self.name()?;
+        let rtype = self.u16()?;
+        let _class = self.u16()?;
+        let ttl = self.u32()?;
+        let rdlength = usize::from(self.u16()?);
+        let end = self.pos + rdlength;
+        let data = match rtype {
+            TYPE_A if rdlength == 4 => {
+                let b = self.bytes(4)?;
+                Some(RecordData::A(Ipv4Addr::new(b[0], b[1], b[2], b[3])))
+            }
+            TYPE_AAAA if rdlength == 16 => {
+                let mut octets = [0; 16];
+                octets.copy_from_slice(self.bytes(16)?);
+                Some(RecordData::Aaaa(Ipv6Addr::from(octets)))
+            }
+            TYPE_SRV => Some(RecordData::Srv {
+                prio: self.u16()?,
+                weight: self.u16()?,
+                port: self.u16()?,
+                target: self.name()?,
+            }),
+            _ => None,
+        };
+        if self.pos > end || end > self.buf.len() {
+            return Err(WireError::Truncated);
+        }
+        self.pos = end;
+        Ok(data.map(|data| Record { name, ttl, data }))
+    }
+}
+
+fn put_u16(buf: &mut Vec<u8>, value: u16) {
+    buf.extend_from_slice(&value.to_be_bytes());
+}
+
+/// Converts the number of entries in a section, or bytes in a record's data,
+/// to its encoded form
+fn count(len: usize) -> Result<u16, WireError> {
+    u16::try_from(len).map_err(|_| WireError::TooLong)
+}
+
+fn put_name(buf: &mut Vec<u8>, name: &str) -> Result<(), WireError> {
+    let name = name.trim_end_matches('.');
+    // Each label takes a byte for its length, and the empty root label one
+    // more.
+    if name.len() + 2 > MAX_NAME_LEN {
+        return Err(WireError::InvalidName(format!("{:?} is too long", name)));
+    }
+    if !name.is_empty() {
+        for label in name.split('.') {
+            if label.is_empty() || label.len() > MAX_LABEL_LEN {
+                return Err(WireError::InvalidName(format!(
+                    "{:?} has an empty or overlong label",
+                    name
+                )));
+            }
+            buf.push(label.len() as u8);
+            buf.extend_from_slice(label.as_bytes());
+        }
+    }
+    buf.push(0);
+    Ok(())
+}
+
+/// Returns the message ID of a message that couldn't be decoded, if it's long
+/// enough to have one, so that the error can be reported to its sender
+pub fn message_id(buf: &[u8]) -> Option<u16> {
+    if buf.len() < HEADER_LEN {
+        return None;
+    }
+    Some(u16::from_be_bytes([buf[0], buf[1]]))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_query_round_trip() {
+        let query = Message::query(0x1234, "web.default.project.internal", 1);
+        let bytes = query.to_bytes().unwrap();
+        assert_eq!(
+            &bytes[..HEADER_LEN],
+            &[0x12, 0x34, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0]
+        );
+        assert_eq!(Message::parse(&bytes).unwrap(), query);
+    }
+
+    #[test]
+    fn test_response_round_trip() {
+        let query = Message::query(7, "_nexus._tcp.internal", TYPE_SRV);
+        let mut response = Message::response_to(&query, Rcode::NoError);
+        response.authoritative = true;
+        response.answers = vec![
+            Record {
+                name: String::from("_nexus._tcp.internal"),
+                ttl: 0,
+                data: RecordData::Srv {
+                    prio: 0,
+                    weight: 0,
+                    port: 12221,
+                    target: String::from("host1.host.internal"),
+                },
+            },
+            Record {
+                name: String::from("host1.host.internal"),
+                ttl: 30,
+                data: RecordData::A(Ipv4Addr::new(192, 0, 2, 1)),
+            },
+            Record {
+                name: String::from("host1.host.internal"),
+                ttl: 30,
+                data: RecordData::Aaaa("fd00:1122:3344::1".parse().unwrap()),
+            },
+        ];
+        let bytes = response.to_bytes().unwrap();
+        assert_eq!(bytes[2], 0x84);
+        assert_eq!(Message::parse(&bytes).unwrap(), response);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let bytes = Message::query(7, "a.internal", TYPE_A).to_bytes().unwrap();
+
+        // Every prefix of a message is incomplete.
+        for len in 0..bytes.len() {
+            assert_eq!(
+                Message::parse(&bytes[..len]),
+                Err(WireError::Truncated),
+                "length {}",
+                len
+            );
+        }
+
+        // A compression pointer that points at itself is rejected rather than
+        // followed forever.
+        let mut looping = bytes[..HEADER_LEN].to_vec();
+        looping.extend_from_slice(&[0xc0, 12, 0, 1, 0, 1]);
+        assert_eq!(
+            Message::parse(&looping),
+            Err(WireError::InvalidName(String::from(
+                "too many compression pointers"
+            )))
+        );
+
+        let mut bad_rcode = bytes.clone();
+        bad_rcode[3] = 0x0b;
+        assert_eq!(
+            Message::parse(&bad_rcode),
+            Err(WireError::UnsupportedRcode(11))
+        );
+    }
+
+    #[test]
+    fn test_compressed_names() {
+        // A response whose answer refers back to the name in its question
+        let mut bytes =
+            vec![0, 7, 0x84, 0, 0, 1, 0, 1, 0, 0, 0, 0, 1, b'a', 0, 0, 1, 0, 1];
+        bytes.extend_from_slice(&[0xc0, 12, 0, 1, 0, 1, 0, 0, 0, 5, 0, 4]);
+        bytes.extend_from_slice(&[192, 0, 2, 1]);
+        let message = Message::parse(&bytes).unwrap();
+        assert_eq!(message.questions[0].name, "a");
+        assert_eq!(
+            message.answers,
+            vec![Record {
+                name: String::from("a"),
+                ttl: 5,
+                data: RecordData::A(Ipv4Addr::new(192, 0, 2, 1)),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_invalid_names() {
+        let long_label = "a".repeat(64);
+        let long_name = vec!["a".repeat(63); 4].join(".");
+        for name in &["a..internal", long_label.as_str(), long_name.as_str()] {
+            let query = Message::query(7, name, TYPE_A);
+            assert!(
+                matches!(query.to_bytes(), Err(WireError::InvalidName(_))),
+                "name {:?}",
+                name
+            );
+        }
+    }
+}
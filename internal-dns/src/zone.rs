@@ -0,0 +1,243 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! The contents of the zone served by the internal DNS server
+
+use omicron_common::api::external::Generation;
+use omicron_common::api::internal::dns::{DnsRecord, DnsZoneConfig};
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use thiserror::Error;
+
+/// Errors updating the zone
+#[derive(Debug, Error, PartialEq)]
+pub enum UpdateError {
+    #[error(
+        "zone generation {requested} is older than the current generation \
+         {current}"
+    )]
+    StaleGeneration { requested: Generation, current: Generation },
+    #[error(
+        "zone generation {generation} is current, but its records are \
+         different"
+    )]
+    ConflictingGeneration { generation: Generation },
+}
+
+/// What the zone says about a name
+#[derive(Debug, PartialEq)]
+pub enum Lookup {
+    /// The name isn't in the zone, so some other server is authoritative for
+    /// it
+    NotInZone,
+    /// The name is in the zone, but doesn't exist
+    NoSuchName,
+    /// The name exists and has these records, which may be none (as for the
+    /// zone's own name)
+    Records(Vec<DnsRecord>),
+}
+
+/// The zone served by the internal DNS server
+///
+/// The server starts with no records, as of generation 1, and serves whatever
+/// the latest update contains.  Nothing is persisted: a server that restarts
+/// is empty until Nexus next updates it.
+// TODO-robustness Nexus only updates the zone when its contents change, so a
+// restarted server can be empty for a long time.  Either the server should
+// persist the zone, or Nexus should periodically make sure each server has
+// the current generation.
+pub struct Zone {
+    /// the zone's domain, without the trailing dot
+    name: String,
+    config: Mutex<DnsZoneConfig>,
+}
+
+impl Zone {
+    /// Returns an empty zone for the domain `name`
+    pub fn new(name: &str) -> Zone {
+        Zone {
+            name: name.trim_end_matches('.').to_lowercase(),
+            config: Mutex::new(DnsZoneConfig {
+                generation: Generation::new(),
+                records: BTreeMap::new(),
+            }),
+        }
+    }
+
+    /// Returns the zone's domain
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the zone's current contents
+    pub fn config(&self) -> DnsZoneConfig {
+        self.config.lock().unwrap().clone()
+    }
+
+    /// Replaces the zone's contents with `config`, if it's newer than what the
+    /// zone has now
+    ///
+    /// Applying the current generation again changes nothing, so that updates
+    /// can be retried.
+    pub fn update(&self, config: DnsZoneConfig) -> Result<(), UpdateError> {
+        // Names are case-insensitive, so store them in the form lookups use.
+        let mut records = BTreeMap::new();
+        for (name, name_records) in config.records {
+            records
+                .entry(name.trim_end_matches('.').to_lowercase())
+                .or_insert_with(Vec::new)
+                .extend(name_records);
+        }
+
+        let mut current = self.config.lock().unwrap();
+        if config.generation < current.generation {
+            return Err(UpdateError::StaleGeneration {
+                requested: config.generation,
+                current: current.generation,
+            });
+        }
+        if config.generation == current.generation {
+            if records != current.records {
+                return Err(UpdateError::ConflictingGeneration {
+                    generation: config.generation,
+                });
+            }
+            return Ok(());
+        }
+        *current = DnsZoneConfig { generation: config.generation, records };
+        Ok(())
+    }
+
+    /// Looks up the fully-qualified name `name`
+    pub fn lookup(&self, name: &str) -> Lookup {
+        let name = name.trim_end_matches('.').to_lowercase();
+        let relative = if name == self.name {
+            None
+        } else {
+            match name.strip_suffix(&self.name) {
+                Some(prefix) if prefix.ends_with('.') => {
+                    Some(prefix.trim_end_matches('.'))
+                }
+                _ => return Lookup::NotInZone,
+            }
+        };
+        let config = self.config.lock().unwrap();
+        match relative {
+            None => Lookup::Records(vec![]),
+            Some(relative) => match config.records.get(relative) {
+                Some(records) => Lookup::Records(records.clone()),
+                None => Lookup::NoSuchName,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Lookup, UpdateError, Zone};
+    use omicron_common::api::internal::dns::{DnsRecord, DnsZoneConfig, Srv};
+    use std::collections::BTreeMap;
+
+    fn config(generation: u64, records: &[(&str, DnsRecord)]) -> DnsZoneConfig {
+        let mut config = Zone::new("internal").config();
+        for _ in 1..generation {
+            config.generation = config.generation.next();
+        }
+        let mut by_name = BTreeMap::new();
+        for (name, record) in records {
+            by_name
+                .entry(name.to_string())
+                .or_insert_with(Vec::new)
+                .push(record.clone());
+        }
+        config.records = by_name;
+        config
+    }
+
+    #[test]
+    fn test_lookup() {
+        let zone = Zone::new("internal");
+        let host = DnsRecord::Aaaa("fd00:1122:3344::1".parse().unwrap());
+        let srv = DnsRecord::Srv(Srv {
+            prio: 0,
+            weight: 0,
+            port: 12221,
+            target: String::from("host1.host"),
+        });
+        zone.update(config(
+            2,
+            &[
+                ("host1.host", host.clone()),
+                ("_nexus._tcp", srv.clone()),
+                (
+                    "Web.Default.Project",
+                    DnsRecord::A("10.0.0.5".parse().unwrap()),
+                ),
+            ],
+        ))
+        .unwrap();
+
+        let cases = [
+            ("host1.host.internal", Lookup::Records(vec![host.clone()])),
+            ("host1.host.internal.", Lookup::Records(vec![host.clone()])),
+            ("HOST1.host.Internal", Lookup::Records(vec![host])),
+            ("_nexus._tcp.internal", Lookup::Records(vec![srv])),
+            (
+                "web.default.project.internal",
+                Lookup::Records(vec![DnsRecord::A(
+                    "10.0.0.5".parse().unwrap(),
+                )]),
+            ),
+            ("internal", Lookup::Records(vec![])),
+            ("host2.host.internal", Lookup::NoSuchName),
+            ("host.internal", Lookup::NoSuchName),
+            ("host1.host", Lookup::NotInZone),
+            ("host1.host.notinternal", Lookup::NotInZone),
+            ("oxide.computer", Lookup::NotInZone),
+        ];
+        for (name, expected) in cases {
+            assert_eq!(zone.lookup(name), expected, "name {}", name);
+        }
+    }
+
+    #[test]
+    fn test_update_generations() {
+        let zone = Zone::new("internal");
+        let a = ("a", DnsRecord::A("10.0.0.1".parse().unwrap()));
+        let b = ("b", DnsRecord::A("10.0.0.2".parse().unwrap()));
+
+        // The zone starts empty at generation 1, and an empty generation 1 can
+        // be applied again.
+        assert_eq!(zone.config(), config(1, &[]));
+        zone.update(config(1, &[])).unwrap();
+
+        // Newer generations replace the contents.
+        zone.update(config(3, &[a.clone()])).unwrap();
+        assert_eq!(zone.config(), config(3, &[a.clone()]));
+        zone.update(config(4, &[b.clone()])).unwrap();
+        assert_eq!(zone.config(), config(4, &[b.clone()]));
+
+        // The current generation can be retried, but not changed.
+        zone.update(config(4, &[b.clone()])).unwrap();
+        let error = zone.update(config(4, &[a.clone()])).unwrap_err();
+        assert_eq!(
+            error,
+            UpdateError::ConflictingGeneration {
+                generation: config(4, &[]).generation
+            }
+        );
+        assert_eq!(
+            error.to_string(),
+            "zone generation 4 is current, but its records are different"
+        );
+
+        // Older generations are rejected.
+        let error = zone.update(config(3, &[a])).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "zone generation 3 is older than the current generation 4"
+        );
+        assert_eq!(zone.config(), config(4, &[b]));
+    }
+}
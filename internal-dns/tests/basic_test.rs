@@ -0,0 +1,138 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Copyright 2022 Oxide Computer Company
+
+use dropshot::{ConfigDropshot, ConfigLogging, ConfigLoggingLevel};
+use internal_dns::wire::{Message, Rcode, RecordData, TYPE_A, TYPE_SRV};
+use internal_dns::{Config, DnsConfig, Server};
+use internal_dns_client::Client;
+use omicron_common::api::external::Generation;
+use omicron_common::api::internal::dns::{DnsRecord, DnsZoneConfig, Srv};
+use omicron_test_utils::dev::test_setup_log;
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::net::UdpSocket;
+
+/**
+ * Sends a query for `name` to the DNS server at `server` and returns the
+ * response.
+ */
+async fn query(server: SocketAddr, id: u16, name: &str, qtype: u16) -> Message {
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let query = Message::query(id, name, qtype);
+    socket.send_to(&query.to_bytes().unwrap(), server).await.unwrap();
+    let mut buf = [0u8; 512];
+    let (len, _) = tokio::time::timeout(
+        Duration::from_secs(10),
+        socket.recv_from(&mut buf),
+    )
+    .await
+    .expect("timed out waiting for DNS response")
+    .unwrap();
+    let response = Message::parse(&buf[..len]).unwrap();
+    assert_eq!(response.id, id);
+    assert!(response.response);
+    response
+}
+
+#[tokio::test]
+async fn test_zone_updates_and_queries() {
+    let logctx = test_setup_log("test_zone_updates_and_queries");
+    let config = Config {
+        log: ConfigLogging::StderrTerminal { level: ConfigLoggingLevel::Info },
+        dropshot: ConfigDropshot {
+            bind_address: "127.0.0.1:0".parse().unwrap(),
+            ..Default::default()
+        },
+        dns: DnsConfig { bind_address: "127.0.0.1:0".parse().unwrap() },
+    };
+    let server = Server::start(&config, &logctx.log).await.unwrap();
+    let dns_address = server.dns_address;
+    let client = Client::new(
+        &format!("http://{}", server.http_server.local_addr()),
+        logctx.log.clone(),
+    );
+
+    /* The server starts out empty. */
+    let initial = client.zone_get().await.unwrap().into_inner();
+    assert_eq!(initial.generation.0, 1);
+    assert!(initial.records.is_empty());
+    let response =
+        query(dns_address, 1, "web.default.proj.internal", TYPE_A).await;
+    assert_eq!(response.rcode, Rcode::NxDomain);
+
+    /* Populate it with an instance and a service. */
+    let mut records = BTreeMap::new();
+    records.insert(
+        String::from("web.default.proj"),
+        vec![DnsRecord::A("172.30.0.5".parse().unwrap())],
+    );
+    records.insert(
+        String::from("_nexus._tcp"),
+        vec![DnsRecord::Srv(Srv {
+            prio: 0,
+            weight: 0,
+            port: 12221,
+            target: String::from("nexus1.host"),
+        })],
+    );
+    records.insert(
+        String::from("nexus1.host"),
+        vec![DnsRecord::Aaaa("fd00:1122:3344:101::3".parse().unwrap())],
+    );
+    let zone = DnsZoneConfig { generation: Generation::new().next(), records };
+    client.zone_put(&zone.clone().into()).await.unwrap();
+    let current = client.zone_get().await.unwrap().into_inner();
+    assert_eq!(current.generation.0, 2);
+    assert_eq!(current.records.len(), 3);
+
+    let response =
+        query(dns_address, 2, "web.default.proj.internal", TYPE_A).await;
+    assert_eq!(response.rcode, Rcode::NoError);
+    assert!(response.authoritative);
+    assert_eq!(response.answers.len(), 1);
+    assert_eq!(
+        response.answers[0].data,
+        RecordData::A("172.30.0.5".parse().unwrap())
+    );
+
+    let response =
+        query(dns_address, 3, "_nexus._tcp.internal", TYPE_SRV).await;
+    assert_eq!(response.rcode, Rcode::NoError);
+    assert_eq!(
+        response.answers.iter().map(|a| a.data.clone()).collect::<Vec<_>>(),
+        vec![RecordData::Srv {
+            prio: 0,
+            weight: 0,
+            port: 12221,
+            target: String::from("nexus1.host.internal"),
+        }]
+    );
+
+    let response = query(dns_address, 4, "oxide.computer", TYPE_A).await;
+    assert_eq!(response.rcode, Rcode::Refused);
+
+    /*
+     * Retrying the current generation succeeds, but an older generation is
+     * rejected without changing anything.
+     */
+    client.zone_put(&zone.clone().into()).await.unwrap();
+    let stale = DnsZoneConfig {
+        generation: Generation::new(),
+        records: BTreeMap::new(),
+    };
+    let error = client.zone_put(&stale.into()).await.unwrap_err();
+    assert!(matches!(
+        omicron_common::api::external::Error::from(error),
+        omicron_common::api::external::Error::InvalidRequest { .. }
+    ));
+    let response =
+        query(dns_address, 5, "web.default.proj.internal", TYPE_A).await;
+    assert_eq!(response.answers.len(), 1);
+
+    server.close().await.unwrap();
+    logctx.cleanup_successful();
+}
@@ -0,0 +1,98 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+ * Audit log of mutating external API calls
+ *
+ * Every external API request that may change something (i.e., anything other
+ * than a GET or HEAD) is recorded in the `audit_log` table once its handler
+ * has finished.  While the request is being handled, the entry is kept in a
+ * [`PendingAuditEntry`] that's stashed in the request's extensions.  The
+ * [`crate::context::OpContext`] for the request picks it up so that the
+ * authenticated actor and the resource that the request acted on (which may
+ * only be known once a saga has finished) can be filled in as they're found.
+ */
+
+use crate::db::model::AuditLogEntry;
+use dropshot::RequestContext;
+use http::Method;
+use http::StatusCode;
+use std::sync::Arc;
+use std::sync::Mutex;
+use uuid::Uuid;
+
+/**
+ * An audit log entry for a request that's still being handled
+ */
+#[derive(Debug)]
+pub struct PendingAuditEntry {
+    request_id: String,
+    http_method: String,
+    http_path: String,
+    details: Mutex<PendingDetails>,
+}
+
+#[derive(Debug, Default)]
+struct PendingDetails {
+    actor_id: Option<Uuid>,
+    resource_id: Option<Uuid>,
+}
+
+impl PendingAuditEntry {
+    /**
+     * Begins an audit log entry for the request `rqctx`, if it's one that
+     * should be audited
+     *
+     * The entry is also attached to the request so that the request's
+     * `OpContext` can find it.
+     */
+    pub async fn begin<T: Send + Sync + 'static>(
+        rqctx: &RequestContext<T>,
+    ) -> Option<Arc<PendingAuditEntry>> {
+        let mut request = rqctx.request.lock().await;
+        if *request.method() == Method::GET || *request.method() == Method::HEAD
+        {
+            return None;
+        }
+        let pending = Arc::new(PendingAuditEntry {
+            request_id: rqctx.request_id.clone(),
+            http_method: request.method().to_string(),
+            http_path: request.uri().path().to_string(),
+            details: Mutex::new(PendingDetails::default()),
+        });
+        request.extensions_mut().insert(Arc::clone(&pending));
+        Some(pending)
+    }
+
+    /** Returns the pending entry for the request `rqctx`, if there is one */
+    pub async fn for_request<T: Send + Sync + 'static>(
+        rqctx: &RequestContext<T>,
+    ) -> Option<Arc<PendingAuditEntry>> {
+        let request = rqctx.request.lock().await;
+        request.extensions().get::<Arc<PendingAuditEntry>>().cloned()
+    }
+
+    /** Records the authenticated actor making the request */
+    pub fn set_actor(&self, actor_id: Uuid) {
+        self.details.lock().unwrap().actor_id = Some(actor_id);
+    }
+
+    /** Records the resource that the request acted on */
+    pub fn set_resource(&self, resource_id: Uuid) {
+        self.details.lock().unwrap().resource_id = Some(resource_id);
+    }
+
+    /** Completes the entry for a request that finished with `status` */
+    pub fn finish(&self, status: StatusCode) -> AuditLogEntry {
+        let details = self.details.lock().unwrap();
+        AuditLogEntry::new(
+            details.actor_id,
+            self.request_id.clone(),
+            self.http_method.clone(),
+            self.http_path.clone(),
+            details.resource_id,
+            status,
+        )
+    }
+}
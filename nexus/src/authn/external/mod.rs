@@ -9,8 +9,10 @@ use async_trait::async_trait;
 use authn::Reason;
 
 pub mod cookies;
+pub mod password;
 pub mod session_cookie;
 pub mod spoof;
+pub mod token;
 
 /// Authenticates incoming HTTP requests using schemes intended for use by the
 /// external API
@@ -159,6 +161,7 @@ mod test {
                 SKIP => SchemeResult::NotRequested,
                 OK => SchemeResult::Authenticated(authn::Details {
                     actor: self.actor,
+                    read_only: false,
                 }),
                 FAIL => SchemeResult::Failed(Reason::BadCredentials {
                     actor: self.actor,
@@ -0,0 +1,266 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! authn scheme for users who log in with a password stored (hashed) in the
+//! user table
+
+use super::{HttpAuthnScheme, Reason, SchemeResult};
+use crate::authn;
+use crate::authn::{Actor, Details};
+use anyhow::anyhow;
+use argon2::password_hash::{
+    PasswordHash, PasswordHasher, PasswordVerifier, SaltString,
+};
+use argon2::Argon2;
+use async_trait::async_trait;
+use headers::authorization::{Authorization, Basic};
+use headers::HeaderMapExt;
+use rand::RngCore;
+use uuid::Uuid;
+
+// Clients provide the user's name and password using HTTP "Basic"
+// authentication (RFC 7617), which looks like this:
+//
+//     Authorization: Basic base64(username ":" password)
+//
+// Since the password itself is sent with every request, this is mostly useful
+// for scripts and for getting started.  Interactive clients should log in once
+// (using the console's "/login" endpoint, which checks the password the same
+// way) and use the resulting session instead.
+//
+// Passwords are stored using argon2, which is deliberately slow to compute, so
+// hashing and verification happen on a blocking thread.
+
+pub const PASSWORD_SCHEME_NAME: authn::SchemeName =
+    authn::SchemeName("password");
+
+/// Number of random bytes used to salt each password hash
+const SALT_NBYTES: usize = 16;
+
+pub trait PasswordUser {
+    fn user_id(&self) -> Uuid;
+    fn password_hash(&self) -> &str;
+}
+
+#[async_trait]
+pub trait PasswordStore {
+    type UserModel;
+
+    /// Retrieve the user with the given name (along with their password hash)
+    async fn user_fetch_for_password(
+        &self,
+        username: String,
+    ) -> Option<Self::UserModel>;
+}
+
+/// Implements an authentication scheme where the client provides a user name
+/// and password, which we check against the password hash stored for that user
+#[derive(Debug)]
+pub struct HttpAuthnPassword;
+
+#[async_trait]
+impl<T> HttpAuthnScheme<T> for HttpAuthnPassword
+where
+    T: Send + Sync + 'static + PasswordStore,
+    T::UserModel: Send + Sync + 'static + PasswordUser,
+{
+    fn name(&self) -> authn::SchemeName {
+        PASSWORD_SCHEME_NAME
+    }
+
+    async fn authn(
+        &self,
+        ctx: &T,
+        _log: &slog::Logger,
+        request: &http::Request<hyper::Body>,
+    ) -> SchemeResult {
+        let credentials: Authorization<Basic> =
+            match request.headers().typed_get() {
+                Some(credentials) => credentials,
+                None => return SchemeResult::NotRequested,
+            };
+
+        // TODO-security This returns much more quickly for users that don't
+        // exist than for users that do, which tells an attacker which user
+        // names are valid.
+        let username = credentials.username().to_owned();
+        let user = match ctx.user_fetch_for_password(username.clone()).await {
+            Some(user) => user,
+            None => {
+                return SchemeResult::Failed(Reason::UnknownActor {
+                    actor: username,
+                })
+            }
+        };
+
+        let actor = Actor(user.user_id());
+        match password_verify(
+            credentials.password().to_owned(),
+            user.password_hash().to_owned(),
+        )
+        .await
+        {
+            Ok(true) => {
+                SchemeResult::Authenticated(Details { actor, read_only: false })
+            }
+            Ok(false) => SchemeResult::Failed(Reason::BadCredentials {
+                actor,
+                source: anyhow!("password did not match"),
+            }),
+            Err(source) => {
+                SchemeResult::Failed(Reason::BadCredentials { actor, source })
+            }
+        }
+    }
+}
+
+/// Hashes `password` for storage, using a new random salt
+pub async fn password_hash(password: String) -> Result<String, anyhow::Error> {
+    let mut salt_bytes = [0u8; SALT_NBYTES];
+    rand::thread_rng().fill_bytes(&mut salt_bytes);
+    tokio::task::spawn_blocking(move || {
+        let salt = SaltString::b64_encode(&salt_bytes)
+            .map_err(|e| anyhow!("generating salt: {}", e))?;
+        Argon2::default()
+            .hash_password(password.as_bytes(), &salt)
+            .map(|hash| hash.to_string())
+            .map_err(|e| anyhow!("hashing password: {}", e))
+    })
+    .await?
+}
+
+/// Returns whether `password` matches `hash`, a hash produced by
+/// [`password_hash()`]
+pub async fn password_verify(
+    password: String,
+    hash: String,
+) -> Result<bool, anyhow::Error> {
+    tokio::task::spawn_blocking(move || {
+        let hash = PasswordHash::new(&hash)
+            .map_err(|e| anyhow!("parsing password hash: {}", e))?;
+        Ok(Argon2::default()
+            .verify_password(password.as_bytes(), &hash)
+            .is_ok())
+    })
+    .await?
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        password_hash, password_verify, HttpAuthnPassword, HttpAuthnScheme,
+        PasswordStore, PasswordUser, Reason, SchemeResult,
+    };
+    use async_trait::async_trait;
+    use headers::authorization::Authorization;
+    use headers::HeaderMapExt;
+    use std::collections::HashMap;
+    use uuid::Uuid;
+
+    struct TestUser {
+        id: Uuid,
+        password_hash: String,
+    }
+
+    impl PasswordUser for TestUser {
+        fn user_id(&self) -> Uuid {
+            self.id
+        }
+        fn password_hash(&self) -> &str {
+            &self.password_hash
+        }
+    }
+
+    struct TestServerContext {
+        users: HashMap<String, (Uuid, String)>,
+    }
+
+    #[async_trait]
+    impl PasswordStore for TestServerContext {
+        type UserModel = TestUser;
+
+        async fn user_fetch_for_password(
+            &self,
+            username: String,
+        ) -> Option<Self::UserModel> {
+            self.users.get(&username).map(|(id, password_hash)| TestUser {
+                id: *id,
+                password_hash: password_hash.clone(),
+            })
+        }
+    }
+
+    async fn authn_with_basic(
+        context: &TestServerContext,
+        credentials: Option<(&str, &str)>,
+    ) -> SchemeResult {
+        let mut request = http::Request::new(hyper::Body::from("hi"));
+        if let Some((username, password)) = credentials {
+            request
+                .headers_mut()
+                .typed_insert(Authorization::basic(username, password));
+        }
+        let log = slog::Logger::root(slog::Discard, o!());
+        HttpAuthnPassword.authn(context, &log, &request).await
+    }
+
+    #[tokio::test]
+    async fn test_password_hash() {
+        let hash = password_hash(String::from("hunter2")).await.unwrap();
+        assert!(hash.starts_with("$argon2"));
+        assert!(!hash.contains("hunter2"));
+        assert!(password_verify(String::from("hunter2"), hash.clone())
+            .await
+            .unwrap());
+        assert!(!password_verify(String::from("hunter3"), hash.clone())
+            .await
+            .unwrap());
+
+        // Salts are random, so hashing the same password twice produces
+        // different hashes.
+        let hash2 = password_hash(String::from("hunter2")).await.unwrap();
+        assert_ne!(hash, hash2);
+
+        // A bogus hash is an error, not a mismatch.
+        password_verify(String::from("hunter2"), String::from("hunter2"))
+            .await
+            .unwrap_err();
+    }
+
+    #[tokio::test]
+    async fn test_password_authn() {
+        let user_id = Uuid::new_v4();
+        let hash = password_hash(String::from("hunter2")).await.unwrap();
+        let context = TestServerContext {
+            users: vec![(String::from("alice"), (user_id, hash))]
+                .into_iter()
+                .collect(),
+        };
+
+        let result = authn_with_basic(&context, None).await;
+        assert!(matches!(result, SchemeResult::NotRequested));
+
+        let result =
+            authn_with_basic(&context, Some(("alice", "hunter2"))).await;
+        match result {
+            SchemeResult::Authenticated(details) => {
+                assert_eq!(details.actor.0, user_id)
+            }
+            _ => panic!("expected authentication to succeed"),
+        }
+
+        let result =
+            authn_with_basic(&context, Some(("alice", "hunter3"))).await;
+        assert!(matches!(
+            result,
+            SchemeResult::Failed(Reason::BadCredentials { .. })
+        ));
+
+        let result = authn_with_basic(&context, Some(("bob", "hunter2"))).await;
+        assert!(matches!(
+            result,
+            SchemeResult::Failed(Reason::UnknownActor { .. })
+        ));
+    }
+}
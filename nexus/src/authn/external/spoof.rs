@@ -119,7 +119,10 @@ fn authn_spoof(raw_value: Option<&Authorization<Bearer>>) -> SchemeResult {
     }
 
     match Uuid::parse_str(str_value).context("parsing header value as UUID") {
-        Ok(id) => SchemeResult::Authenticated(Details { actor: Actor(id) }),
+        Ok(id) => SchemeResult::Authenticated(Details {
+            actor: Actor(id),
+            read_only: false,
+        }),
         Err(source) => SchemeResult::Failed(Reason::BadFormat { source }),
     }
 }
@@ -231,7 +234,7 @@ mod test {
         assert!(matches!(
             success_case,
             SchemeResult::Authenticated(
-                Details { actor: Actor(i) }
+                Details { actor: Actor(i), .. }
             ) if i == test_uuid
         ));
     }
@@ -65,7 +65,7 @@ impl Context {
     /// Returns the authenticated actor if present, Unauthenticated error otherwise
     pub fn actor_required(&self) -> Result<&Actor, dropshot::HttpError> {
         match &self.kind {
-            Kind::Authenticated(Details { actor }) => Ok(actor),
+            Kind::Authenticated(Details { actor, .. }) => Ok(actor),
             Kind::Unauthenticated => Err(dropshot::HttpError::from(
                 omicron_common::api::external::Error::Unauthenticated {
                     internal_message: "Actor required".to_string(),
@@ -74,6 +74,17 @@ impl Context {
         }
     }
 
+    /// Returns whether the actor is restricted to operations that don't modify
+    /// anything (regardless of what roles they have)
+    ///
+    /// This is the case for requests authenticated with a read-only API token.
+    pub fn read_only(&self) -> bool {
+        match &self.kind {
+            Kind::Authenticated(Details { read_only, .. }) => *read_only,
+            Kind::Unauthenticated => false,
+        }
+    }
+
     /// Returns the list of schemes tried, in order
     ///
     /// This should generally *not* be exposed to clients.
@@ -104,7 +115,10 @@ impl Context {
 
     fn context_for_actor(actor_id: Uuid) -> Context {
         Context {
-            kind: Kind::Authenticated(Details { actor: Actor(actor_id) }),
+            kind: Kind::Authenticated(Details {
+                actor: Actor(actor_id),
+                read_only: false,
+            }),
             schemes_tried: Vec::new(),
         }
     }
@@ -175,6 +189,10 @@ enum Kind {
 pub struct Details {
     /// the actor performing the request
     actor: Actor,
+    /// whether the actor may only perform operations that don't modify
+    /// anything
+    #[serde(default)]
+    read_only: bool,
 }
 
 /// Who is performing an operation
@@ -477,5 +477,8 @@ impl ApiResourceError for ProjectChild {
     }
 }
 
+pub type AffinityGroup = ProjectChild;
 pub type Disk = ProjectChild;
 pub type Instance = ProjectChild;
+pub type FloatingIp = ProjectChild;
+pub type Snapshot = ProjectChild;
@@ -87,6 +87,18 @@ impl Context {
         let actor = AnyActor::new(&self.authn, roles);
         let is_authn = self.authn.actor().is_some();
         match self.authz.is_allowed(&actor, action, &resource) {
+            // Some credentials (like read-only API tokens) only permit
+            // operations that don't change anything, no matter what roles the
+            // actor has.
+            Ok(true)
+                if self.authn.read_only()
+                    && !matches!(
+                        action,
+                        Action::Query | Action::Read | Action::ListChildren
+                    ) =>
+            {
+                Err(Error::Forbidden)
+            }
             Ok(true) => Ok(()),
             Err(error) => Err(Error::internal_error(&format!(
                 "failed to compute authorization: {:#}",
@@ -119,6 +119,10 @@
 //! tables have other columns.)  See the [`roles`] module for more details on
 //! how we find these records and make them available for the authz check.
 //!
+//! Roles granted to users through the external API (by updating the policy on
+//! an Organization or Project) work the same way, except that they're stored
+//! in the "role_assignment" table, which refers to users by "user_id".
+//!
 //! ## Authorization control flow
 //!
 //! Suppose we receive a request from Abby to modify Project "monster-foodies".
@@ -163,13 +167,17 @@
 mod actor;
 
 mod api_resources;
+pub use api_resources::AffinityGroup;
+pub use api_resources::ApiResource;
 pub use api_resources::ApiResourceError;
 pub use api_resources::Disk;
 pub use api_resources::Fleet;
 pub use api_resources::FleetChild;
+pub use api_resources::FloatingIp;
 pub use api_resources::Instance;
 pub use api_resources::Organization;
 pub use api_resources::Project;
+pub use api_resources::Snapshot;
 pub use api_resources::FLEET;
 
 mod context;
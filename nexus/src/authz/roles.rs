@@ -8,7 +8,7 @@
 //! said there that in evaluating the authorization decision, Oso winds up
 //! checking whether the actor has one of many different roles on many different
 //! resources.  It's essentially looking for specific rows in the
-//! "role_assignment_builtin" table.
+//! "role_assignment_builtin" and "role_assignment" tables.
 //!
 //! To achieve this, before calling into Oso, we load _all_ of the roles that
 //! the actor has on this resource _or any related resource_ that might affect
@@ -155,6 +155,15 @@ pub async fn load_roles_for_resource(
             assert_eq!(resource_type.to_string(), role_asgn.resource_type);
             roleset.insert(resource_type, resource_id, &role_asgn.role_name);
         }
+
+        // Do the same for roles assigned through the resource's policy.
+        let roles = datastore
+            .role_asgn_list_for(opctx, actor_id.0, resource_type, resource_id)
+            .await?;
+        for role_asgn in roles {
+            assert_eq!(resource_type.to_string(), role_asgn.resource_type);
+            roleset.insert(resource_type, resource_id, &role_asgn.role_name);
+        }
     }
 
     Ok(())
@@ -8,9 +8,12 @@
  */
 
 use crate::db;
+use crate::placement::PlacementConfig;
+use crate::throttle::ThrottleConfig;
 use anyhow::anyhow;
 use dropshot::ConfigDropshot;
 use dropshot::ConfigLogging;
+use ipnetwork::IpNetwork;
 use serde::Deserialize;
 use serde::Serialize;
 use serde_with::DeserializeFromStr;
@@ -49,6 +52,32 @@ pub struct TimeseriesDbConfig {
     pub address: SocketAddr,
 }
 
+/**
+ * Configuration for the internal DNS server.
+ */
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct InternalDnsConfig {
+    /** address of the server's HTTP API, used to update its zone */
+    pub address: SocketAddr,
+}
+
+/**
+ * Configuration for delivering Project events to webhooks.
+ */
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct WebhooksConfig {
+    /**
+     * networks to which webhooks may be delivered even though they're
+     * internal (see [`crate::events::is_internal`])
+     */
+    pub allowed_internal_networks: Vec<IpNetwork>,
+    /**
+     * how long, in seconds, delivery of an event is retried before it's
+     * recorded as failed and the next event is delivered
+     */
+    pub delivery_retry_secs: u64,
+}
+
 /**
  * Configuration for a nexus server
  */
@@ -70,6 +99,14 @@ pub struct Config {
     pub authn: AuthnConfig,
     /** Timeseries database configuration. */
     pub timeseries_db: TimeseriesDbConfig,
+    /** Internal DNS configuration */
+    pub internal_dns: InternalDnsConfig,
+    /** Instance placement configuration */
+    pub placement: PlacementConfig,
+    /** External API rate limiting and load shedding configuration */
+    pub throttle: ThrottleConfig,
+    /** Webhook delivery configuration */
+    pub webhooks: WebhooksConfig,
 }
 
 #[derive(Debug)]
@@ -131,6 +168,8 @@ impl std::cmp::PartialEq<std::io::Error> for LoadError {
 pub enum SchemeName {
     Spoof,
     SessionCookie,
+    Password,
+    Token,
 }
 
 impl std::str::FromStr for SchemeName {
@@ -140,6 +179,8 @@ impl std::str::FromStr for SchemeName {
         match s {
             "spoof" => Ok(SchemeName::Spoof),
             "session_cookie" => Ok(SchemeName::SessionCookie),
+            "password" => Ok(SchemeName::Password),
+            "token" => Ok(SchemeName::Token),
             _ => Err(anyhow!("unsupported authn scheme: {:?}", s)),
         }
     }
@@ -150,6 +191,8 @@ impl std::fmt::Display for SchemeName {
         f.write_str(match self {
             SchemeName::Spoof => "spoof",
             SchemeName::SessionCookie => "session_cookie",
+            SchemeName::Password => "password",
+            SchemeName::Token => "token",
         })
     }
 }
@@ -174,10 +217,12 @@ impl Config {
 #[cfg(test)]
 mod test {
     use super::{
-        AuthnConfig, Config, ConsoleConfig, LoadError, LoadErrorKind,
-        SchemeName, TimeseriesDbConfig,
+        AuthnConfig, Config, ConsoleConfig, InternalDnsConfig, LoadError,
+        LoadErrorKind, SchemeName, TimeseriesDbConfig, WebhooksConfig,
     };
     use crate::db;
+    use crate::placement::{PlacementConfig, PlacementPolicy};
+    use crate::throttle::{RateLimitConfig, ThrottleConfig};
     use dropshot::ConfigDropshot;
     use dropshot::ConfigLogging;
     use dropshot::ConfigLoggingIfExists;
@@ -185,6 +230,7 @@ mod test {
     use libc;
     use std::fs;
     use std::net::SocketAddr;
+    use std::num::NonZeroU32;
     use std::path::Path;
     use std::path::PathBuf;
 
@@ -306,6 +352,19 @@ mod test {
             if_exists = "fail"
             [timeseries_db]
             address = "[::1]:8123"
+            [internal_dns]
+            address = "[::1]:5380"
+            [placement]
+            policy = "spread"
+            [throttle]
+            per_actor = { requests_per_second = 50, burst = 100 }
+            per_source_ip = { requests_per_second = 100, burst = 200 }
+            trusted_proxies = [ "10.0.0.0/24" ]
+            max_concurrent_requests = 256
+            db_wait_threshold_ms = 1000
+            [webhooks]
+            allowed_internal_networks = []
+            delivery_retry_secs = 3600
             "##,
         )
         .unwrap();
@@ -346,6 +405,27 @@ mod test {
                 timeseries_db: TimeseriesDbConfig {
                     address: "[::1]:8123".parse().unwrap()
                 },
+                internal_dns: InternalDnsConfig {
+                    address: "[::1]:5380".parse().unwrap()
+                },
+                placement: PlacementConfig { policy: PlacementPolicy::Spread },
+                throttle: ThrottleConfig {
+                    per_actor: RateLimitConfig {
+                        requests_per_second: NonZeroU32::new(50).unwrap(),
+                        burst: NonZeroU32::new(100).unwrap(),
+                    },
+                    per_source_ip: RateLimitConfig {
+                        requests_per_second: NonZeroU32::new(100).unwrap(),
+                        burst: NonZeroU32::new(200).unwrap(),
+                    },
+                    trusted_proxies: vec!["10.0.0.0/24".parse().unwrap()],
+                    max_concurrent_requests: NonZeroU32::new(256).unwrap(),
+                    db_wait_threshold_ms: 1000,
+                },
+                webhooks: WebhooksConfig {
+                    allowed_internal_networks: vec![],
+                    delivery_retry_secs: 3600,
+                },
             }
         );
 
@@ -359,7 +439,7 @@ mod test {
             session_idle_timeout_minutes = 60
             session_absolute_timeout_minutes = 480
             [authn]
-            schemes_external = [ "spoof", "session_cookie" ]
+            schemes_external = [ "spoof", "session_cookie", "password", "token" ]
             [dropshot_external]
             bind_address = "10.1.2.3:4567"
             request_body_max_bytes = 1024
@@ -375,13 +455,31 @@ mod test {
             if_exists = "fail"
             [timeseries_db]
             address = "[::1]:8123"
+            [internal_dns]
+            address = "[::1]:5380"
+            [placement]
+            policy = "spread"
+            [throttle]
+            per_actor = { requests_per_second = 50, burst = 100 }
+            per_source_ip = { requests_per_second = 100, burst = 200 }
+            trusted_proxies = [ "10.0.0.0/24" ]
+            max_concurrent_requests = 256
+            db_wait_threshold_ms = 1000
+            [webhooks]
+            allowed_internal_networks = []
+            delivery_retry_secs = 3600
             "##,
         )
         .unwrap();
 
         assert_eq!(
             config.authn.schemes_external,
-            vec![SchemeName::Spoof, SchemeName::SessionCookie],
+            vec![
+                SchemeName::Spoof,
+                SchemeName::SessionCookie,
+                SchemeName::Password,
+                SchemeName::Token,
+            ],
         );
     }
 
@@ -413,6 +511,19 @@ mod test {
             if_exists = "fail"
             [timeseries_db]
             address = "[::1]:8123"
+            [internal_dns]
+            address = "[::1]:5380"
+            [placement]
+            policy = "spread"
+            [throttle]
+            per_actor = { requests_per_second = 50, burst = 100 }
+            per_source_ip = { requests_per_second = 100, burst = 200 }
+            trusted_proxies = [ "10.0.0.0/24" ]
+            max_concurrent_requests = 256
+            db_wait_threshold_ms = 1000
+            [webhooks]
+            allowed_internal_networks = []
+            delivery_retry_secs = 3600
             "##,
         )
         .expect_err("expected failure");
@@ -428,4 +539,114 @@ mod test {
             );
         }
     }
+
+    #[test]
+    fn test_bad_placement_policy() {
+        let error = read_config(
+            "bad placement.policy",
+            r##"
+            id = "28b90dc4-c22a-65ba-f49a-f051fe01208f"
+            [console]
+            static_dir = "tests/static"
+            cache_control_max_age_minutes = 10
+            session_idle_timeout_minutes = 60
+            session_absolute_timeout_minutes = 480
+            [authn]
+            schemes_external = []
+            [dropshot_external]
+            bind_address = "10.1.2.3:4567"
+            request_body_max_bytes = 1024
+            [dropshot_internal]
+            bind_address = "10.1.2.3:4568"
+            request_body_max_bytes = 1024
+            [database]
+            url = "postgresql://127.0.0.1?sslmode=disable"
+            [log]
+            mode = "file"
+            level = "debug"
+            path = "/nonexistent/path"
+            if_exists = "fail"
+            [timeseries_db]
+            address = "[::1]:8123"
+            [internal_dns]
+            address = "[::1]:5380"
+            [placement]
+            policy = "random"
+            [throttle]
+            per_actor = { requests_per_second = 50, burst = 100 }
+            per_source_ip = { requests_per_second = 100, burst = 200 }
+            trusted_proxies = [ "10.0.0.0/24" ]
+            max_concurrent_requests = 256
+            db_wait_threshold_ms = 1000
+            [webhooks]
+            allowed_internal_networks = []
+            delivery_retry_secs = 3600
+            "##,
+        )
+        .expect_err("expected failure");
+        if let LoadErrorKind::Parse(error) = &error.kind {
+            assert!(error
+                .to_string()
+                .starts_with("unknown variant `random`, expected one of"));
+        } else {
+            panic!(
+                "Got an unexpected error, expected Parse but got {:?}",
+                error
+            );
+        }
+    }
+
+    #[test]
+    fn test_bad_throttle_limit() {
+        let error = read_config(
+            "bad throttle.per_actor",
+            r##"
+            id = "28b90dc4-c22a-65ba-f49a-f051fe01208f"
+            [console]
+            static_dir = "tests/static"
+            cache_control_max_age_minutes = 10
+            session_idle_timeout_minutes = 60
+            session_absolute_timeout_minutes = 480
+            [authn]
+            schemes_external = []
+            [dropshot_external]
+            bind_address = "10.1.2.3:4567"
+            request_body_max_bytes = 1024
+            [dropshot_internal]
+            bind_address = "10.1.2.3:4568"
+            request_body_max_bytes = 1024
+            [database]
+            url = "postgresql://127.0.0.1?sslmode=disable"
+            [log]
+            mode = "file"
+            level = "debug"
+            path = "/nonexistent/path"
+            if_exists = "fail"
+            [timeseries_db]
+            address = "[::1]:8123"
+            [internal_dns]
+            address = "[::1]:5380"
+            [placement]
+            policy = "spread"
+            [throttle]
+            per_actor = { requests_per_second = 50, burst = 0 }
+            per_source_ip = { requests_per_second = 100, burst = 200 }
+            trusted_proxies = [ "10.0.0.0/24" ]
+            max_concurrent_requests = 256
+            db_wait_threshold_ms = 1000
+            [webhooks]
+            allowed_internal_networks = []
+            delivery_retry_secs = 3600
+            "##,
+        )
+        .expect_err("expected failure");
+        if let LoadErrorKind::Parse(error) = &error.kind {
+            assert!(error.to_string().contains("expected a non-zero value"));
+        } else {
+            panic!(
+                "Got an unexpected error, expected Parse but got {:?}",
+                error
+            );
+        }
+    }
 }
@@ -10,17 +10,35 @@ use super::authz;
 use super::config;
 use super::db;
 use super::Nexus;
+use crate::audit::PendingAuditEntry;
+use crate::authn::external::password::{PasswordStore, PasswordUser};
 use crate::authn::external::session_cookie::{Session, SessionStore};
+use crate::authn::external::token;
+use crate::authn::external::token::ApiTokenStore;
 use crate::authn::Actor;
 use crate::authz::AuthorizedResource;
+use crate::db::model::ApiToken;
 use crate::db::model::ConsoleSession;
+use crate::db::model::User;
 use crate::db::DataStore;
+use crate::external_api::params;
+use crate::external_api::versions::ApiVersion;
+use crate::idempotency::IdempotencyKey;
 use crate::saga_interface::SagaContext;
+use crate::throttle;
+use crate::throttle::HttpResponseThrottled;
+use crate::throttle::Throttle;
 use async_trait::async_trait;
+use authn::external::password::HttpAuthnPassword;
 use authn::external::session_cookie::HttpAuthnSessionCookie;
 use authn::external::spoof::HttpAuthnSpoof;
+use authn::external::token::HttpAuthnToken;
 use authn::external::HttpAuthnScheme;
 use chrono::{DateTime, Duration, Utc};
+use dropshot::HttpError;
+use dropshot::HttpResponse;
+use dropshot::RequestContext;
+use http::StatusCode;
 use omicron_common::api::external::Error;
 use oximeter::types::ProducerRegistry;
 use oximeter_instruments::http::{HttpService, LatencyTracker};
@@ -28,6 +46,8 @@ use slog::Logger;
 use std::collections::BTreeMap;
 use std::env;
 use std::fmt::Debug;
+use std::future::Future;
+use std::marker::PhantomData;
 use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::Instant;
@@ -52,6 +72,8 @@ pub struct ServerContext {
     pub internal_latencies: LatencyTracker,
     /** external API request latency tracker */
     pub external_latencies: LatencyTracker,
+    /** external API rate limiting and load shedding */
+    pub throttle: Arc<Throttle>,
     /** registry of metric producers */
     pub producer_registry: ProducerRegistry,
     /** tunable settings needed for the console at runtime */
@@ -90,6 +112,8 @@ impl ServerContext {
                     config::SchemeName::SessionCookie => {
                         Box::new(HttpAuthnSessionCookie)
                     }
+                    config::SchemeName::Password => Box::new(HttpAuthnPassword),
+                    config::SchemeName::Token => Box::new(HttpAuthnToken),
                 }
             })
             .collect();
@@ -116,6 +140,12 @@ impl ServerContext {
         producer_registry
             .register_producer(external_latencies.clone())
             .unwrap();
+        let throttle = Arc::new(Throttle::new(
+            &config.throttle,
+            external_latencies.service.clone(),
+        ));
+        producer_registry.register_producer(throttle.stats()).unwrap();
+        throttle.monitor_db_wait(&pool, log.new(o!("component" => "Throttle")));
 
         // Support both absolute and relative paths. If configured dir is
         // absolute, use it directly. If not, assume it's relative to the
@@ -153,6 +183,7 @@ impl ServerContext {
             authz,
             internal_latencies,
             external_latencies,
+            throttle,
             producer_registry,
             console_config: ConsoleConfig {
                 session_idle_timeout: Duration::minutes(
@@ -168,6 +199,87 @@ impl ServerContext {
             },
         }))
     }
+
+    /**
+     * Runs `handler` for the external API request `rqctx` (unless it's
+     * throttled), tracking its latency and, if the request may have changed
+     * anything, recording its outcome in the audit log
+     *
+     * If the request is throttled because of a rate limit, the error response
+     * says when to retry in a `Retry-After` header.
+     */
+    pub async fn instrument_external_handler<H, R>(
+        &self,
+        rqctx: &RequestContext<Arc<ServerContext>>,
+        handler: H,
+    ) -> Result<HttpResponseThrottled<R>, HttpError>
+    where
+        R: HttpResponse,
+        H: Future<Output = Result<R, HttpError>>,
+    {
+        /*
+         * Requests that are throttled still count towards the latency metrics,
+         * but they're left out of the audit log, since they never got as far
+         * as changing anything (and writing to the database is the last thing
+         * to do while shedding load).
+         */
+        let (result, retry_after) = throttle::retry_after_scope(async {
+            let admission = self.throttle.admit(&*rqctx.request.lock().await);
+            let audit = match admission {
+                Ok(_) => PendingAuditEntry::begin(rqctx).await,
+                Err(_) => None,
+            };
+            let handler = async move {
+                let _admission = admission?;
+                handler.await
+            };
+            let result = self
+                .external_latencies
+                .instrument_dropshot_handler(rqctx, handler)
+                .await;
+            (result, audit)
+        })
+        .await;
+        /*
+         * Build the response here, rather than leaving that to Dropshot, so
+         * that the audit log records the status that the client actually gets
+         * (which for some responses, like a `HttpResponseMaybeAccepted`,
+         * depends on the request).
+         */
+        let (result, audit) = result;
+        let result = result.and_then(R::to_result);
+        if let Some(audit) = audit {
+            let status = match &result {
+                Ok(response) => response.status(),
+                Err(error) => error.status_code,
+            };
+            /*
+             * The request has already been handled, so there's nothing useful
+             * to tell the client if this fails.
+             */
+            let entry = audit.finish(status);
+            if let Err(error) =
+                self.nexus.datastore().audit_log_insert(entry).await
+            {
+                error!(rqctx.log, "failed to write audit log entry";
+                    "error" => ?error);
+            }
+        }
+        match (result, retry_after) {
+            (Err(error), Some(retry_after))
+                if error.status_code == StatusCode::TOO_MANY_REQUESTS =>
+            {
+                Ok(HttpResponseThrottled::TooManyRequests {
+                    error,
+                    request_id: rqctx.request_id.clone(),
+                    retry_after,
+                })
+            }
+            (result, _) => result.map(|response| {
+                HttpResponseThrottled::Handled(response, PhantomData)
+            }),
+        }
+    }
 }
 
 /// Provides general facilities scoped to whatever operation Nexus is currently
@@ -196,6 +308,8 @@ pub struct OpContext {
     created_walltime: SystemTime,
     metadata: BTreeMap<String, String>,
     kind: OpKind,
+    audit: Option<Arc<PendingAuditEntry>>,
+    idempotency_key: Option<IdempotencyKey>,
 }
 
 enum OpKind {
@@ -221,6 +335,9 @@ impl OpContext {
         let created_walltime = SystemTime::now();
         let apictx = rqctx.context();
         let authn = Arc::new(apictx.external_authn.authn_request(rqctx).await?);
+        if let Some(Actor(actor_id)) = authn.actor() {
+            apictx.throttle.check_actor(*actor_id)?;
+        }
         let datastore = Arc::clone(apictx.nexus.datastore());
         let authz = authz::Context::new(
             Arc::clone(&authn),
@@ -232,6 +349,35 @@ impl OpContext {
             OpContext::log_and_metadata_for_authn(&rqctx.log, &authn);
         OpContext::load_request_metadata(rqctx, &mut metadata).await;
 
+        let audit = PendingAuditEntry::for_request(rqctx).await;
+        let idempotency_key = {
+            let request = rqctx.request.lock().await;
+            /*
+             * Read-only credentials can't be used for anything but reads, no
+             * matter what the handler goes on to check.  `authz::Context`
+             * enforces this too, but not every change is authorized against a
+             * specific action, so this is the check that can't be missed.
+             */
+            if authn.read_only()
+                && !matches!(
+                    *request.method(),
+                    http::Method::GET | http::Method::HEAD
+                )
+            {
+                return Err(Error::Forbidden.into());
+            }
+            /*
+             * Only the current version of the API is served (see
+             * `external_api::versions`), but a client that asks for a version
+             * that isn't supported is told so.
+             */
+            ApiVersion::from_request(&*request)?;
+            IdempotencyKey::from_request(&*request)?
+        };
+        if let (Some(audit), Some(Actor(actor_id))) = (&audit, authn.actor()) {
+            audit.set_actor(*actor_id);
+        }
+
         Ok(OpContext {
             log,
             authz,
@@ -240,6 +386,8 @@ impl OpContext {
             created_walltime,
             metadata,
             kind: OpKind::ExternalApiRequest,
+            audit,
+            idempotency_key,
         })
     }
 
@@ -271,6 +419,8 @@ impl OpContext {
             created_walltime,
             metadata,
             kind: OpKind::InternalApiRequest,
+            audit: None,
+            idempotency_key: None,
         }
     }
 
@@ -348,6 +498,8 @@ impl OpContext {
             created_walltime,
             metadata,
             kind: OpKind::Saga,
+            audit: None,
+            idempotency_key: None,
         }
     }
 
@@ -374,6 +526,8 @@ impl OpContext {
             created_walltime,
             metadata: BTreeMap::new(),
             kind: OpKind::Background,
+            audit: None,
+            idempotency_key: None,
         }
     }
 
@@ -399,6 +553,8 @@ impl OpContext {
             created_walltime,
             metadata: BTreeMap::new(),
             kind: OpKind::Test,
+            audit: None,
+            idempotency_key: None,
         }
     }
 
@@ -432,6 +588,22 @@ impl OpContext {
         );
         result
     }
+
+    /// Records `resource_id` as the resource that this operation acted on, for
+    /// the audit log
+    ///
+    /// This does nothing for operations that aren't being audited.
+    pub fn audit_resource(&self, resource_id: Uuid) {
+        if let Some(audit) = &self.audit {
+            audit.set_resource(resource_id);
+        }
+    }
+
+    /// Returns the idempotency key the client supplied with this operation, if
+    /// any
+    pub fn idempotency_key(&self) -> Option<&IdempotencyKey> {
+        self.idempotency_key.as_ref()
+    }
 }
 
 #[cfg(test)]
@@ -538,3 +710,48 @@ impl Session for ConsoleSession {
         self.time_created
     }
 }
+
+#[async_trait]
+impl PasswordStore for Arc<ServerContext> {
+    type UserModel = User;
+
+    async fn user_fetch_for_password(
+        &self,
+        username: String,
+    ) -> Option<Self::UserModel> {
+        self.nexus.user_fetch_for_authn(&username).await.ok()
+    }
+}
+
+impl PasswordUser for User {
+    fn user_id(&self) -> Uuid {
+        self.identity.id
+    }
+    fn password_hash(&self) -> &str {
+        &self.password_hash
+    }
+}
+
+#[async_trait]
+impl ApiTokenStore for Arc<ServerContext> {
+    type TokenModel = ApiToken;
+
+    async fn api_token_fetch(
+        &self,
+        token_hash: String,
+    ) -> Option<Self::TokenModel> {
+        self.nexus.api_token_fetch_for_authn(token_hash).await.ok()
+    }
+}
+
+impl token::ApiToken for ApiToken {
+    fn user_id(&self) -> Uuid {
+        self.user_id
+    }
+    fn time_expires(&self) -> Option<DateTime<Utc>> {
+        self.time_expires
+    }
+    fn read_only(&self) -> bool {
+        self.scope.0 == params::ApiTokenScope::ReadOnly
+    }
+}
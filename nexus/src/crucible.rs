@@ -0,0 +1,90 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+ * Client for the parts of the Crucible Agent API that act on existing regions
+ *
+ * `crucible-agent-client` only covers creating and deleting regions.  Until it
+ * grows support for snapshots and resizing, this small hand-written client
+ * covers the rest of what Nexus needs.  See
+ * `omicron_common::api::internal::crucible`.
+ */
+
+use omicron_common::api::internal::crucible::{
+    CreateRegionSnapshot, ImportRegionSnapshot, RegionSnapshot, ResizeRegion,
+};
+use std::net::SocketAddr;
+use uuid::Uuid;
+
+pub struct RegionClient {
+    baseurl: String,
+    client: reqwest::Client,
+}
+
+impl RegionClient {
+    pub fn new(address: SocketAddr) -> Self {
+        RegionClient {
+            baseurl: format!("http://{}", address),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /** Take a snapshot of region `region_id`. */
+    pub async fn region_snapshot_create(
+        &self,
+        region_id: Uuid,
+        body: &CreateRegionSnapshot,
+    ) -> Result<RegionSnapshot, reqwest::Error> {
+        let url = format!(
+            "{}/crucible/0/regions/{}/snapshots",
+            self.baseurl, region_id
+        );
+        self.client
+            .post(url)
+            .json(body)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+    }
+
+    /** Delete snapshot `snapshot_id` of region `region_id`. */
+    pub async fn region_snapshot_delete(
+        &self,
+        region_id: Uuid,
+        snapshot_id: Uuid,
+    ) -> Result<(), reqwest::Error> {
+        let url = format!(
+            "{}/crucible/0/regions/{}/snapshots/{}",
+            self.baseurl, region_id, snapshot_id
+        );
+        self.client.delete(url).send().await?.error_for_status()?;
+        Ok(())
+    }
+
+    /** Populate region `region_id` from a snapshot of another region. */
+    pub async fn region_import(
+        &self,
+        region_id: Uuid,
+        body: &ImportRegionSnapshot,
+    ) -> Result<(), reqwest::Error> {
+        let url =
+            format!("{}/crucible/0/regions/{}/import", self.baseurl, region_id);
+        self.client.post(url).json(body).send().await?.error_for_status()?;
+        Ok(())
+    }
+
+    /** Change the number of extents in region `region_id`. */
+    pub async fn region_resize(
+        &self,
+        region_id: Uuid,
+        body: &ResizeRegion,
+    ) -> Result<(), reqwest::Error> {
+        let url =
+            format!("{}/crucible/0/regions/{}/resize", self.baseurl, region_id);
+        self.client.post(url).json(body).send().await?.error_for_status()?;
+        Ok(())
+    }
+}
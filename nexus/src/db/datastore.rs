@@ -28,18 +28,22 @@ use super::collection_insert::{
 use super::error::diesel_pool_result_optional;
 use super::identity::{Asset, Resource};
 use super::pool::DbConnection;
+use super::quota::{self, quota_check, QuotaExceeded};
 use super::Pool;
 use crate::authn;
 use crate::authz;
 use crate::context::OpContext;
 use crate::db::fixed_data::role_assignment_builtin::BUILTIN_ROLE_ASSIGNMENTS;
 use crate::db::fixed_data::role_builtin::BUILTIN_ROLES;
+use crate::external_api::etag::Preconditions;
 use crate::external_api::params;
+use crate::idempotency;
+use crate::placement::SledResources;
 use async_bb8_diesel::{
     AsyncConnection, AsyncRunQueryDsl, ConnectionError, ConnectionManager,
     PoolError,
 };
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use diesel::pg::Pg;
 use diesel::prelude::*;
 use diesel::query_builder::{QueryFragment, QueryId};
@@ -50,6 +54,7 @@ use omicron_common::api;
 use omicron_common::api::external::DataPageParams;
 use omicron_common::api::external::DeleteResult;
 use omicron_common::api::external::Error;
+use omicron_common::api::external::LabelSelector;
 use omicron_common::api::external::ListResultVec;
 use omicron_common::api::external::LookupResult;
 use omicron_common::api::external::LookupType;
@@ -59,25 +64,36 @@ use omicron_common::api::external::{
     CreateResult, IdentityMetadataCreateParams,
 };
 use omicron_common::bail_unless;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
 use std::convert::{TryFrom, TryInto};
+use std::fmt::Debug;
 use std::sync::Arc;
 use uuid::Uuid;
 
 use crate::db::{
     self,
     error::{public_error_from_diesel_pool, ErrorHandler, TransactionError},
+    labels::filter_by_labels,
     model::{
-        ConsoleSession, Dataset, DatasetKind, Disk, DiskRuntimeState,
-        Generation, IncompleteNetworkInterface, Instance, InstanceRuntimeState,
-        Name, NetworkInterface, Organization, OrganizationUpdate, OximeterInfo,
-        ProducerEndpoint, Project, ProjectUpdate, Region,
-        RoleAssignmentBuiltin, RoleBuiltin, RouterRoute, RouterRouteUpdate,
-        Sled, UserBuiltin, Vpc, VpcFirewallRule, VpcRouter, VpcRouterUpdate,
+        AffinityGroup, AffinityGroupClaim, AffinityGroupUpdate, AffinityPolicy,
+        ApiToken, AuditLogEntry, ConsoleSession, Dataset, DatasetKind, Disk,
+        DiskRuntimeState, DiskUpdate, DnsZone, FloatingIp, Generation,
+        IdempotencyKey, IncompleteFloatingIp, IncompleteNetworkInterface,
+        Instance, InstanceRuntimeState, InstanceUpdate, IpPool, IpPoolRange,
+        Name, NetworkInterface, NexusInfo, Operation, OperationState,
+        Organization, OrganizationUpdate, OximeterInfo, ProducerEndpoint,
+        Project, ProjectEvent, ProjectUpdate, ProjectWebhook, Quota, Region,
+        ResourceUsage, RoleAssignment, RoleAssignmentBuiltin, RoleBuiltin,
+        RouterRoute, RouterRouteUpdate, Sled, Snapshot, SshKey, User,
+        UserBuiltin, Vpc, VpcFirewallRule, VpcRouter, VpcRouterUpdate,
         VpcSubnet, VpcSubnetUpdate, VpcUpdate, Zpool,
     },
     pagination::paginated,
     pagination::paginated_multicolumn,
+    subnet_allocation::AllocateFloatingIpQuery,
     subnet_allocation::AllocateIpQuery,
+    subnet_allocation::FilterConflictingIpPoolRangesQuery,
     subnet_allocation::FilterConflictingVpcSubnetRangesQuery,
     subnet_allocation::SubnetError,
     update_and_check::{UpdateAndCheck, UpdateStatus},
@@ -87,6 +103,25 @@ use crate::db::{
 // TODO: This should likely turn into a configuration option.
 const REGION_REDUNDANCY_THRESHOLD: usize = 3;
 
+// Number of times to try allocating a Floating IP's address when concurrent
+// allocations keep choosing the same one.
+const FLOATING_IP_ALLOCATION_ATTEMPTS: usize = 3;
+
+/// Describes how [`DataStore::region_resize_allocate`] grew one of the regions
+/// backing a disk
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct RegionResize {
+    /// the dataset holding the region after the resize
+    pub dataset: Dataset,
+    /// the region after the resize
+    pub region: Region,
+    /// number of extents in the region before the resize
+    pub previous_extent_count: i64,
+    /// if the region could not be grown in place, the dataset and region whose
+    /// contents must be moved into `region`
+    pub replaced: Option<(Dataset, Region)>,
+}
+
 // Represents a query that is ready to be executed.
 //
 // This helper trait lets the statement either be executed or explained.
@@ -108,6 +143,47 @@ impl<U, T> RunnableQuery<U> for T where
 {
 }
 
+// A table whose rows may only be modified when the request's preconditions
+// hold, as checked by [`DataStore::update_with_preconditions`].
+trait PreconditionedTable: Copy + Send + 'static {
+    // Returns the last modification time of the live row `id`, which is what
+    // the preconditions are evaluated against.
+    fn time_modified(
+        self,
+        conn: &DbConnection,
+        id: Uuid,
+    ) -> Result<DateTime<Utc>, diesel::result::Error>;
+}
+
+// Implements `PreconditionedTable` for a table with "id", "time_modified" and
+// "time_deleted" columns.
+macro_rules! impl_preconditioned_table {
+    ($table:ident) => {
+        impl PreconditionedTable for db::schema::$table::table {
+            fn time_modified(
+                self,
+                conn: &DbConnection,
+                id: Uuid,
+            ) -> Result<DateTime<Utc>, diesel::result::Error> {
+                use db::schema::$table::dsl;
+                self.filter(dsl::time_deleted.is_null())
+                    .filter(dsl::id.eq(id))
+                    .select(dsl::time_modified)
+                    .get_result::<DateTime<Utc>>(conn)
+            }
+        }
+    };
+}
+
+impl_preconditioned_table!(organization);
+impl_preconditioned_table!(project);
+impl_preconditioned_table!(disk);
+impl_preconditioned_table!(affinity_group);
+impl_preconditioned_table!(vpc);
+impl_preconditioned_table!(vpc_subnet);
+impl_preconditioned_table!(vpc_router);
+impl_preconditioned_table!(router_route);
+
 pub struct DataStore {
     pool: Arc<Pool>,
 }
@@ -134,6 +210,45 @@ impl DataStore {
         Ok(self.pool.pool())
     }
 
+    /// Runs `update` in a transaction, provided that the live row `id` of
+    /// `table` satisfies `preconditions`
+    ///
+    /// Custom errors returned by `update` (including a failed precondition)
+    /// are passed through unchanged.  Database errors are converted by
+    /// `pool_error`, which typically picks the [`ErrorHandler`] describing the
+    /// row.
+    async fn update_with_preconditions<T, R, F, H>(
+        pool: &bb8::Pool<ConnectionManager<DbConnection>>,
+        table: T,
+        id: Uuid,
+        preconditions: &Preconditions,
+        update: F,
+        pool_error: H,
+    ) -> Result<R, Error>
+    where
+        T: PreconditionedTable,
+        R: 'static + Send,
+        F: 'static
+            + Send
+            + FnOnce(&DbConnection) -> Result<R, TransactionError<Error>>,
+        H: FnOnce(PoolError) -> Error,
+    {
+        type TxnError = TransactionError<Error>;
+        let preconditions = preconditions.clone();
+        pool.transaction(move |conn| {
+            let time_modified = table.time_modified(conn, id)?;
+            preconditions
+                .check(&time_modified)
+                .map_err(TxnError::CustomError)?;
+            update(conn)
+        })
+        .await
+        .map_err(|e| match e {
+            TxnError::CustomError(e) => e,
+            TxnError::Pool(e) => pool_error(e),
+        })
+    }
+
     /// Stores a new sled in the database.
     pub async fn sled_upsert(&self, sled: Sled) -> CreateResult<Sled> {
         use db::schema::sled::dsl;
@@ -145,6 +260,8 @@ impl DataStore {
                 dsl::time_modified.eq(Utc::now()),
                 dsl::ip.eq(sled.ip),
                 dsl::port.eq(sled.port),
+                dsl::usable_hardware_threads.eq(sled.usable_hardware_threads),
+                dsl::usable_physical_ram.eq(sled.usable_physical_ram),
             ))
             .returning(Sled::as_returning())
             .get_result_async(self.pool())
@@ -190,6 +307,72 @@ impl DataStore {
             })
     }
 
+    /// Lists every sled along with the resources that Instances have reserved
+    /// on it
+    ///
+    /// This is used to place new Instances.  An Instance holds its CPUs and
+    /// memory on the sled named by its `active_server_id` from the time its
+    /// record is created until it is destroyed.
+    // TODO-scalability This loads every sled and every live Instance in the
+    // system.  It also doesn't prevent two concurrent callers from choosing
+    // the same nearly-full sled.  Both problems go away once reservations are
+    // recorded in their own table as part of the allocation itself.
+    pub async fn sled_list_with_reservations(
+        &self,
+    ) -> ListResultVec<SledResources> {
+        use db::schema::instance::dsl as instance_dsl;
+        use db::schema::sled::dsl as sled_dsl;
+
+        let sleds = sled_dsl::sled
+            .filter(sled_dsl::time_deleted.is_null())
+            .order(sled_dsl::id.asc())
+            .select(Sled::as_select())
+            .load_async::<Sled>(self.pool())
+            .await
+            .map_err(|e| {
+                public_error_from_diesel_pool(e, ErrorHandler::Server)
+            })?;
+
+        let destroyed = db::model::InstanceState::new(
+            api::external::InstanceState::Destroyed,
+        );
+        let instances = instance_dsl::instance
+            .filter(instance_dsl::time_deleted.is_null())
+            .filter(instance_dsl::state.ne(destroyed))
+            .select((
+                instance_dsl::active_server_id,
+                instance_dsl::ncpus,
+                instance_dsl::memory,
+            ))
+            .load_async::<(Uuid, i64, i64)>(self.pool())
+            .await
+            .map_err(|e| {
+                public_error_from_diesel_pool(e, ErrorHandler::Server)
+            })?;
+
+        let mut reservations: BTreeMap<Uuid, (u64, u64)> = BTreeMap::new();
+        for (sled_id, ncpus, memory) in instances {
+            let reserved = reservations.entry(sled_id).or_insert((0, 0));
+            reserved.0 += u64::try_from(ncpus).unwrap_or(0);
+            reserved.1 += u64::try_from(memory).unwrap_or(0);
+        }
+
+        Ok(sleds
+            .into_iter()
+            .map(|sled| {
+                let (reserved_cpus, reserved_ram) =
+                    reservations.get(&sled.id()).copied().unwrap_or((0, 0));
+                SledResources {
+                    sled_id: sled.id(),
+                    hardware_threads: u64::from(*sled.usable_hardware_threads),
+                    physical_ram: sled.usable_physical_ram.to_bytes(),
+                    reserved_cpus,
+                    reserved_ram,
+                }
+            })
+            .collect())
+    }
+
     /// Stores a new zpool in the database.
     pub async fn zpool_upsert(&self, zpool: Zpool) -> CreateResult<Zpool> {
         use db::schema::zpool::dsl;
@@ -437,6 +620,288 @@ impl DataStore {
             })
     }
 
+    /// Returns the space on zpool `pool_id` not yet used by any of its datasets
+    fn zpool_free_space(
+        conn: &DbConnection,
+        pool_id: Uuid,
+    ) -> Result<i64, diesel::result::Error> {
+        use db::schema::dataset::dsl as dataset_dsl;
+        use db::schema::zpool::dsl as zpool_dsl;
+
+        let total_size = zpool_dsl::zpool
+            .filter(zpool_dsl::id.eq(pool_id))
+            .select(zpool_dsl::total_size)
+            .get_result::<i64>(conn)?;
+        let size_used: i64 = dataset_dsl::dataset
+            .filter(dataset_dsl::pool_id.eq(pool_id))
+            .filter(dataset_dsl::time_deleted.is_null())
+            .select(dataset_dsl::size_used)
+            .get_results::<Option<i64>>(conn)?
+            .into_iter()
+            .flatten()
+            .sum();
+        Ok(total_size - size_used)
+    }
+
+    /// Idempotently grows each region backing a disk to `extent_count` extents.
+    ///
+    /// A region is grown in place if its zpool has room for the new extents.
+    /// Otherwise, a replacement region of the new size is allocated on another
+    /// dataset, favoring datasets with the smallest load as
+    /// [`DataStore::region_allocate`] does, and the original region is removed
+    /// from the database.  The caller is responsible for copying the original
+    /// region's contents into the replacement and then destroying it.
+    ///
+    /// Regions are only moved if `allow_move` is set.  Regions that already
+    /// have at least `extent_count` extents are returned unchanged.
+    pub async fn region_resize_allocate(
+        &self,
+        disk_id: Uuid,
+        extent_count: i64,
+        allow_move: bool,
+    ) -> Result<Vec<RegionResize>, Error> {
+        use db::schema::dataset::dsl as dataset_dsl;
+        use db::schema::region::dsl as region_dsl;
+
+        #[derive(Debug, thiserror::Error)]
+        enum RegionResizeError {
+            #[error("No dataset has room for region {0}")]
+            NotEnoughSpace(Uuid),
+        }
+        type TxnError = TransactionError<RegionResizeError>;
+        self.pool()
+            .transaction(move |conn| {
+                let datasets_and_regions = Self::get_allocated_regions_query(
+                    disk_id,
+                )
+                .get_results::<(Dataset, Region)>(conn)?;
+
+                // Each region of a disk must live on a distinct dataset.
+                let mut disk_dataset_ids: Vec<Uuid> =
+                    datasets_and_regions.iter().map(|(d, _)| d.id()).collect();
+
+                let mut resizes =
+                    Vec::with_capacity(datasets_and_regions.len());
+                for (dataset, region) in datasets_and_regions {
+                    let previous_extent_count = region.extent_count();
+                    if previous_extent_count >= extent_count {
+                        resizes.push(RegionResize {
+                            dataset,
+                            region,
+                            previous_extent_count,
+                            replaced: None,
+                        });
+                        continue;
+                    }
+
+                    let extent_size = i64::from(region.block_size())
+                        * region.blocks_per_extent();
+                    let growth =
+                        extent_size * (extent_count - previous_extent_count);
+                    if Self::zpool_free_space(conn, dataset.pool_id)? >= growth
+                    {
+                        let region = diesel::update(region_dsl::region)
+                            .filter(region_dsl::id.eq(region.id()))
+                            .set(region_dsl::extent_count.eq(extent_count))
+                            .returning(Region::as_returning())
+                            .get_result(conn)?;
+                        let dataset = diesel::update(dataset_dsl::dataset)
+                            .filter(dataset_dsl::id.eq(dataset.id()))
+                            .set(
+                                dataset_dsl::size_used
+                                    .eq(dataset_dsl::size_used + growth),
+                            )
+                            .returning(Dataset::as_returning())
+                            .get_result(conn)?;
+                        resizes.push(RegionResize {
+                            dataset,
+                            region,
+                            previous_extent_count,
+                            replaced: None,
+                        });
+                        continue;
+                    }
+
+                    // There's no room to grow the region where it is.  Find
+                    // the least-loaded dataset that can hold all of it.
+                    if !allow_move {
+                        return Err(TxnError::CustomError(
+                            RegionResizeError::NotEnoughSpace(region.id()),
+                        ));
+                    }
+                    let region_size = extent_size * extent_count;
+                    let candidates = dataset_dsl::dataset
+                        .filter(dataset_dsl::size_used.is_not_null())
+                        .filter(dataset_dsl::time_deleted.is_null())
+                        .filter(dataset_dsl::kind.eq(DatasetKind(
+                            crate::internal_api::params::DatasetKind::Crucible,
+                        )))
+                        .filter(
+                            dataset_dsl::id.ne_all(disk_dataset_ids.clone()),
+                        )
+                        .order(dataset_dsl::size_used.asc())
+                        .select(Dataset::as_select())
+                        .get_results::<Dataset>(conn)?;
+                    let mut target = None;
+                    for candidate in candidates {
+                        if Self::zpool_free_space(conn, candidate.pool_id)?
+                            >= region_size
+                        {
+                            target = Some(candidate);
+                            break;
+                        }
+                    }
+                    let target = target.ok_or_else(|| {
+                        TxnError::CustomError(
+                            RegionResizeError::NotEnoughSpace(region.id()),
+                        )
+                    })?;
+
+                    // Swap the replacement in for the original region,
+                    // updating the tallied sizes of both datasets.
+                    diesel::delete(region_dsl::region)
+                        .filter(region_dsl::id.eq(region.id()))
+                        .execute(conn)?;
+                    let previous_size = extent_size * previous_extent_count;
+                    diesel::update(dataset_dsl::dataset)
+                        .filter(dataset_dsl::id.eq(dataset.id()))
+                        .set(
+                            dataset_dsl::size_used
+                                .eq(dataset_dsl::size_used - previous_size),
+                        )
+                        .execute(conn)?;
+
+                    let replacement = Region::new(
+                        target.id(),
+                        disk_id,
+                        region.block_size().into(),
+                        region.blocks_per_extent(),
+                        extent_count,
+                    );
+                    let replacement = diesel::insert_into(region_dsl::region)
+                        .values(replacement)
+                        .returning(Region::as_returning())
+                        .get_result(conn)?;
+                    let target = diesel::update(dataset_dsl::dataset)
+                        .filter(dataset_dsl::id.eq(target.id()))
+                        .set(
+                            dataset_dsl::size_used
+                                .eq(dataset_dsl::size_used + region_size),
+                        )
+                        .returning(Dataset::as_returning())
+                        .get_result(conn)?;
+
+                    disk_dataset_ids.push(target.id());
+                    resizes.push(RegionResize {
+                        dataset: target,
+                        region: replacement,
+                        previous_extent_count,
+                        replaced: Some((dataset, region)),
+                    });
+                }
+                Ok(resizes)
+            })
+            .await
+            .map_err(|e| match e {
+                TxnError::CustomError(RegionResizeError::NotEnoughSpace(_)) => {
+                    Error::unavail("Not enough space to resize disk")
+                }
+                _ => {
+                    Error::internal_error(&format!("Transaction error: {}", e))
+                }
+            })
+    }
+
+    /// Reverts the changes made by [`DataStore::region_resize_allocate`]:
+    /// regions grown in place are shrunk back to their original size, and
+    /// replacement regions are swapped back out for the originals.
+    ///
+    /// Dataset usage is only adjusted for regions that are actually changed,
+    /// so this may safely be replayed.
+    pub async fn region_resize_undo(
+        &self,
+        resizes: Vec<RegionResize>,
+    ) -> DeleteResult {
+        use db::schema::dataset::dsl as dataset_dsl;
+        use db::schema::region::dsl as region_dsl;
+
+        type TxnError = TransactionError<()>;
+        self.pool()
+            .transaction(move |conn| -> Result<(), TxnError> {
+                for resize in resizes {
+                    let region = &resize.region;
+                    let extent_size = i64::from(region.block_size())
+                        * region.blocks_per_extent();
+                    let size = extent_size * region.extent_count();
+                    let previous_size =
+                        extent_size * resize.previous_extent_count;
+
+                    let original = match resize.replaced {
+                        None => {
+                            let shrunk = diesel::update(region_dsl::region)
+                                .filter(region_dsl::id.eq(region.id()))
+                                .filter(
+                                    region_dsl::extent_count
+                                        .eq(region.extent_count()),
+                                )
+                                .set(
+                                    region_dsl::extent_count
+                                        .eq(resize.previous_extent_count),
+                                )
+                                .execute(conn)?;
+                            if shrunk > 0 {
+                                diesel::update(dataset_dsl::dataset)
+                                    .filter(
+                                        dataset_dsl::id.eq(resize.dataset.id()),
+                                    )
+                                    .set(
+                                        dataset_dsl::size_used
+                                            .eq(dataset_dsl::size_used - size
+                                                + previous_size),
+                                    )
+                                    .execute(conn)?;
+                            }
+                            continue;
+                        }
+                        Some(original) => original,
+                    };
+
+                    let deleted = diesel::delete(region_dsl::region)
+                        .filter(region_dsl::id.eq(region.id()))
+                        .execute(conn)?;
+                    if deleted > 0 {
+                        diesel::update(dataset_dsl::dataset)
+                            .filter(dataset_dsl::id.eq(resize.dataset.id()))
+                            .set(
+                                dataset_dsl::size_used
+                                    .eq(dataset_dsl::size_used - size),
+                            )
+                            .execute(conn)?;
+                    }
+                    let (original_dataset, original_region) = original;
+                    let restored = diesel::insert_into(region_dsl::region)
+                        .values(original_region)
+                        .on_conflict(region_dsl::id)
+                        .do_nothing()
+                        .execute(conn)?;
+                    if restored > 0 {
+                        diesel::update(dataset_dsl::dataset)
+                            .filter(dataset_dsl::id.eq(original_dataset.id()))
+                            .set(
+                                dataset_dsl::size_used
+                                    .eq(dataset_dsl::size_used + previous_size),
+                            )
+                            .execute(conn)?;
+                    }
+                }
+                Ok(())
+            })
+            .await
+            .map_err(|e| {
+                Error::internal_error(&format!("Transaction error: {:?}", e))
+            })
+    }
+
     /// Deletes all regions backing a disk.
     ///
     /// Also updates the storage usage on their corresponding datasets.
@@ -620,15 +1085,16 @@ impl DataStore {
         &self,
         opctx: &OpContext,
         name: &Name,
+        preconditions: &Preconditions,
     ) -> DeleteResult {
         use db::schema::organization::dsl;
         use db::schema::project;
 
-        let (id, rcgen) = dsl::organization
+        let (id, rcgen, time_modified) = dsl::organization
             .filter(dsl::time_deleted.is_null())
             .filter(dsl::name.eq(name.clone()))
-            .select((dsl::id, dsl::rcgen))
-            .get_result_async::<(Uuid, Generation)>(self.pool())
+            .select((dsl::id, dsl::rcgen, dsl::time_modified))
+            .get_result_async::<(Uuid, Generation, DateTime<Utc>)>(self.pool())
             .await
             .map_err(|e| {
                 public_error_from_diesel_pool(
@@ -645,6 +1111,7 @@ impl DataStore {
         let authz_org =
             authz::FLEET.organization(id, LookupType::from(&name.0));
         opctx.authorize(authz::Action::Delete, &authz_org).await?;
+        preconditions.check(&time_modified)?;
 
         // Make sure there are no projects present within this organization.
         let project_found = diesel_pool_result_optional(
@@ -669,6 +1136,7 @@ impl DataStore {
             .filter(dsl::time_deleted.is_null())
             .filter(dsl::id.eq(id))
             .filter(dsl::rcgen.eq(rcgen))
+            .filter(dsl::time_modified.eq(time_modified))
             .set(dsl::time_deleted.eq(now))
             .execute_async(self.pool())
             .await
@@ -683,6 +1151,14 @@ impl DataStore {
             })?;
 
         if updated_rows == 0 {
+            // If the caller provided preconditions, they would no longer be
+            // met.
+            if !preconditions.is_empty() {
+                return Err(Error::PreconditionFailed {
+                    message: "organization was modified concurrently"
+                        .to_string(),
+                });
+            }
             return Err(Error::InvalidRequest {
                 message: "deletion failed due to concurrent modification"
                     .to_string(),
@@ -695,57 +1171,77 @@ impl DataStore {
         &self,
         opctx: &OpContext,
         pagparams: &DataPageParams<'_, Uuid>,
+        label_selector: &LabelSelector,
     ) -> ListResultVec<Organization> {
         use db::schema::organization::dsl;
         opctx.authorize(authz::Action::ListChildren, &authz::FLEET).await?;
-        paginated(dsl::organization, dsl::id, pagparams)
-            .filter(dsl::time_deleted.is_null())
-            .select(Organization::as_select())
-            .load_async::<Organization>(self.pool_authorized(opctx).await?)
-            .await
-            .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
+        filter_by_labels(
+            paginated(dsl::organization, dsl::id, pagparams),
+            dsl::labels,
+            label_selector,
+        )
+        .filter(dsl::time_deleted.is_null())
+        .select(Organization::as_select())
+        .load_async::<Organization>(self.pool_authorized(opctx).await?)
+        .await
+        .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
     }
 
     pub async fn organizations_list_by_name(
         &self,
         opctx: &OpContext,
         pagparams: &DataPageParams<'_, Name>,
+        label_selector: &LabelSelector,
     ) -> ListResultVec<Organization> {
         use db::schema::organization::dsl;
         opctx.authorize(authz::Action::ListChildren, &authz::FLEET).await?;
-        paginated(dsl::organization, dsl::name, pagparams)
-            .filter(dsl::time_deleted.is_null())
-            .select(Organization::as_select())
-            .load_async::<Organization>(self.pool_authorized(opctx).await?)
-            .await
-            .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
+        filter_by_labels(
+            paginated(dsl::organization, dsl::name, pagparams),
+            dsl::labels,
+            label_selector,
+        )
+        .filter(dsl::time_deleted.is_null())
+        .select(Organization::as_select())
+        .load_async::<Organization>(self.pool_authorized(opctx).await?)
+        .await
+        .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
     }
 
-    /// Updates a organization by name (clobbering update -- no etag)
+    /// Updates a organization by name, provided that it satisfies
+    /// `preconditions`
     pub async fn organization_update(
         &self,
         opctx: &OpContext,
         name: &Name,
         updates: OrganizationUpdate,
+        preconditions: &Preconditions,
     ) -> UpdateResult<Organization> {
         use db::schema::organization::dsl;
 
         let authz_org = self.organization_lookup_by_path(name).await?;
         opctx.authorize(authz::Action::Modify, &authz_org).await?;
 
-        diesel::update(dsl::organization)
-            .filter(dsl::time_deleted.is_null())
-            .filter(dsl::id.eq(authz_org.id()))
-            .set(updates)
-            .returning(Organization::as_returning())
-            .get_result_async(self.pool_authorized(opctx).await?)
-            .await
-            .map_err(|e| {
+        let id = authz_org.id();
+        Self::update_with_preconditions(
+            self.pool_authorized(opctx).await?,
+            dsl::organization,
+            id,
+            preconditions,
+            move |conn| {
+                Ok(diesel::update(dsl::organization)
+                    .filter(dsl::id.eq(id))
+                    .set(updates)
+                    .returning(Organization::as_returning())
+                    .get_result(conn)?)
+            },
+            |e| {
                 public_error_from_diesel_pool(
                     e,
                     ErrorHandler::NotFoundByResource(&authz_org),
                 )
-            })
+            },
+        )
+        .await
     }
 
     /// Create a project
@@ -885,26 +1381,53 @@ impl DataStore {
         &self,
         opctx: &OpContext,
         authz_project: &authz::Project,
+        preconditions: &Preconditions,
     ) -> DeleteResult {
         opctx.authorize(authz::Action::Delete, authz_project).await?;
 
+        use db::schema::floating_ip;
         use db::schema::project::dsl;
 
+        type TxnError = TransactionError<Error>;
+        let id = authz_project.id();
         let now = Utc::now();
-        diesel::update(dsl::project)
-            .filter(dsl::time_deleted.is_null())
-            .filter(dsl::id.eq(authz_project.id()))
-            .set(dsl::time_deleted.eq(now))
-            .returning(Project::as_returning())
-            .get_result_async(self.pool_authorized(opctx).await?)
-            .await
-            .map_err(|e| {
+        Self::update_with_preconditions(
+            self.pool_authorized(opctx).await?,
+            dsl::project,
+            id,
+            preconditions,
+            move |conn| {
+                // A Floating IP holds its address until it's released, so the
+                // project can't go away while it still has any.  Checking in
+                // the same transaction keeps one from being allocated to the
+                // project while it's being deleted.
+                let floating_ip_found = floating_ip::dsl::floating_ip
+                    .filter(floating_ip::dsl::project_id.eq(id))
+                    .filter(floating_ip::dsl::time_deleted.is_null())
+                    .select(floating_ip::dsl::id)
+                    .limit(1)
+                    .first::<Uuid>(conn)
+                    .optional()?;
+                if floating_ip_found.is_some() {
+                    return Err(TxnError::CustomError(Error::InvalidRequest {
+                        message: "project to be deleted contains a floating IP"
+                            .to_string(),
+                    }));
+                }
+                diesel::update(dsl::project)
+                    .filter(dsl::id.eq(id))
+                    .set(dsl::time_deleted.eq(now))
+                    .execute(conn)?;
+                Ok(())
+            },
+            |e| {
                 public_error_from_diesel_pool(
                     e,
                     ErrorHandler::NotFoundByResource(authz_project),
                 )
-            })?;
-        Ok(())
+            },
+        )
+        .await
     }
 
     pub async fn projects_list_by_id(
@@ -912,18 +1435,23 @@ impl DataStore {
         opctx: &OpContext,
         authz_org: &authz::Organization,
         pagparams: &DataPageParams<'_, Uuid>,
+        label_selector: &LabelSelector,
     ) -> ListResultVec<Project> {
         use db::schema::project::dsl;
 
         opctx.authorize(authz::Action::ListChildren, authz_org).await?;
 
-        paginated(dsl::project, dsl::id, pagparams)
-            .filter(dsl::organization_id.eq(authz_org.id()))
-            .filter(dsl::time_deleted.is_null())
-            .select(Project::as_select())
-            .load_async(self.pool_authorized(opctx).await?)
-            .await
-            .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
+        filter_by_labels(
+            paginated(dsl::project, dsl::id, pagparams),
+            dsl::labels,
+            label_selector,
+        )
+        .filter(dsl::organization_id.eq(authz_org.id()))
+        .filter(dsl::time_deleted.is_null())
+        .select(Project::as_select())
+        .load_async(self.pool_authorized(opctx).await?)
+        .await
+        .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
     }
 
     pub async fn projects_list_by_name(
@@ -931,45 +1459,169 @@ impl DataStore {
         opctx: &OpContext,
         authz_org: &authz::Organization,
         pagparams: &DataPageParams<'_, Name>,
+        label_selector: &LabelSelector,
     ) -> ListResultVec<Project> {
         use db::schema::project::dsl;
 
         opctx.authorize(authz::Action::ListChildren, authz_org).await?;
 
-        paginated(dsl::project, dsl::name, &pagparams)
-            .filter(dsl::organization_id.eq(authz_org.id()))
-            .filter(dsl::time_deleted.is_null())
-            .select(Project::as_select())
-            .load_async(self.pool_authorized(opctx).await?)
-            .await
-            .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
+        filter_by_labels(
+            paginated(dsl::project, dsl::name, &pagparams),
+            dsl::labels,
+            label_selector,
+        )
+        .filter(dsl::organization_id.eq(authz_org.id()))
+        .filter(dsl::time_deleted.is_null())
+        .select(Project::as_select())
+        .load_async(self.pool_authorized(opctx).await?)
+        .await
+        .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
     }
 
-    /// Updates a project (clobbering update -- no etag)
+    /// Updates a project, provided that it satisfies `preconditions`
     pub async fn project_update(
         &self,
         opctx: &OpContext,
         authz_project: &authz::Project,
         updates: ProjectUpdate,
+        preconditions: &Preconditions,
     ) -> UpdateResult<Project> {
         opctx.authorize(authz::Action::Modify, authz_project).await?;
 
         use db::schema::project::dsl;
-        diesel::update(dsl::project)
-            .filter(dsl::time_deleted.is_null())
-            .filter(dsl::id.eq(authz_project.id()))
-            .set(updates)
-            .returning(Project::as_returning())
-            .get_result_async(self.pool_authorized(opctx).await?)
-            .await
-            .map_err(|e| {
+        let id = authz_project.id();
+        Self::update_with_preconditions(
+            self.pool_authorized(opctx).await?,
+            dsl::project,
+            id,
+            preconditions,
+            move |conn| {
+                Ok(diesel::update(dsl::project)
+                    .filter(dsl::id.eq(id))
+                    .set(updates)
+                    .returning(Project::as_returning())
+                    .get_result(conn)?)
+            },
+            |e| {
                 public_error_from_diesel_pool(
                     e,
                     ErrorHandler::NotFoundByResource(authz_project),
                 )
+            },
+        )
+        .await
+    }
+
+    /*
+     * Quotas
+     */
+
+    /// Fetches the quota on an Organization, along with the resources
+    /// provisioned within it
+    pub async fn organization_quota_fetch(
+        &self,
+        opctx: &OpContext,
+        authz_org: &authz::Organization,
+    ) -> LookupResult<(Quota, ResourceUsage)> {
+        opctx.authorize(authz::Action::Read, authz_org).await?;
+        let organization_id = authz_org.id();
+        self.quota_fetch_with_usage(opctx, organization_id, move |conn| {
+            quota::organization_usage(conn, organization_id, None)
+        })
+        .await
+    }
+
+    /// Fetches the quota on a Project, along with the resources provisioned
+    /// within it
+    pub async fn project_quota_fetch(
+        &self,
+        opctx: &OpContext,
+        authz_project: &authz::Project,
+    ) -> LookupResult<(Quota, ResourceUsage)> {
+        opctx.authorize(authz::Action::Read, authz_project).await?;
+        let project_id = authz_project.id();
+        self.quota_fetch_with_usage(opctx, project_id, move |conn| {
+            quota::project_usage(conn, project_id, None)
+        })
+        .await
+    }
+
+    async fn quota_fetch_with_usage<F>(
+        &self,
+        opctx: &OpContext,
+        collection_id: Uuid,
+        usage: F,
+    ) -> LookupResult<(Quota, ResourceUsage)>
+    where
+        F: FnOnce(
+                &DbConnection,
+            ) -> Result<ResourceUsage, diesel::result::Error>
+            + Send
+            + 'static,
+    {
+        type TxnError = TransactionError<()>;
+        self.pool_authorized(opctx)
+            .await?
+            .transaction(move |conn| -> Result<_, TxnError> {
+                let quota = quota::quota_fetch(conn, collection_id)?
+                    .unwrap_or_else(|| Quota::unlimited(collection_id));
+                Ok((quota, usage(conn)?))
+            })
+            .await
+            .map_err(|e| {
+                Error::internal_error(&format!("Transaction error: {:?}", e))
             })
     }
 
+    /// Replaces the quota on an Organization
+    ///
+    /// Organizations are limited by the operator of the fleet, not by their
+    /// own administrators.
+    pub async fn organization_quota_update(
+        &self,
+        opctx: &OpContext,
+        authz_org: &authz::Organization,
+        params: params::QuotaUpdate,
+    ) -> UpdateResult<Quota> {
+        // Check that the caller can see the Organization first so that those
+        // who can't get a 404 rather than a 403.
+        opctx.authorize(authz::Action::Read, authz_org).await?;
+        opctx.authorize(authz::Action::Modify, &authz::FLEET).await?;
+        self.quota_upsert(opctx, Quota::new(authz_org.id(), params)).await
+    }
+
+    /// Replaces the quota on a Project
+    ///
+    /// Projects are limited by the administrators of their Organization.
+    pub async fn project_quota_update(
+        &self,
+        opctx: &OpContext,
+        authz_org: &authz::Organization,
+        authz_project: &authz::Project,
+        params: params::QuotaUpdate,
+    ) -> UpdateResult<Quota> {
+        opctx.authorize(authz::Action::Modify, authz_org).await?;
+        self.quota_upsert(opctx, Quota::new(authz_project.id(), params)).await
+    }
+
+    async fn quota_upsert(
+        &self,
+        opctx: &OpContext,
+        quota: Quota,
+    ) -> UpdateResult<Quota> {
+        use db::schema::quota::dsl;
+
+        diesel::insert_into(dsl::quota)
+            .values(quota.clone())
+            .on_conflict(dsl::collection_id)
+            .do_update()
+            .set(quota)
+            .returning(Quota::as_returning())
+            .get_result_async(self.pool_authorized(opctx).await?)
+            .await
+            .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
+    }
+
     /*
      * Instances
      */
@@ -1109,21 +1761,39 @@ impl DataStore {
 
         let gen = instance.runtime().gen;
         let name = instance.name().clone();
-        let instance: Instance = diesel::insert_into(dsl::instance)
-            .values(instance)
-            .on_conflict(dsl::id)
-            .do_nothing()
-            .returning(Instance::as_returning())
-            .get_result_async(self.pool())
+        let requested = ResourceUsage {
+            instances: 1,
+            vcpus: i64::from(instance.runtime().ncpus.0 .0),
+            memory: i64::from(instance.runtime().memory.0),
+            ..Default::default()
+        };
+        type TxnError = TransactionError<QuotaExceeded>;
+        let instance: Instance = self
+            .pool()
+            .transaction(move |conn| {
+                quota_check(
+                    conn,
+                    instance.project_id,
+                    instance.id(),
+                    &requested,
+                )?;
+                Ok(diesel::insert_into(dsl::instance)
+                    .values(instance)
+                    .on_conflict(dsl::id)
+                    .do_nothing()
+                    .returning(Instance::as_returning())
+                    .get_result(conn)?)
+            })
             .await
-            .map_err(|e| {
-                public_error_from_diesel_pool(
+            .map_err(|e| match e {
+                TxnError::CustomError(e) => e.into(),
+                TxnError::Pool(e) => public_error_from_diesel_pool(
                     e,
                     ErrorHandler::Conflict(
                         ResourceType::Instance,
                         name.as_str(),
                     ),
-                )
+                ),
             })?;
 
         bail_unless!(
@@ -1145,17 +1815,22 @@ impl DataStore {
         opctx: &OpContext,
         authz_project: &authz::Project,
         pagparams: &DataPageParams<'_, Name>,
+        label_selector: &LabelSelector,
     ) -> ListResultVec<Instance> {
         opctx.authorize(authz::Action::ListChildren, authz_project).await?;
 
         use db::schema::instance::dsl;
-        paginated(dsl::instance, dsl::name, &pagparams)
-            .filter(dsl::time_deleted.is_null())
-            .filter(dsl::project_id.eq(authz_project.id()))
-            .select(Instance::as_select())
-            .load_async::<Instance>(self.pool_authorized(opctx).await?)
-            .await
-            .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
+        filter_by_labels(
+            paginated(dsl::instance, dsl::name, &pagparams),
+            dsl::labels,
+            label_selector,
+        )
+        .filter(dsl::time_deleted.is_null())
+        .filter(dsl::project_id.eq(authz_project.id()))
+        .select(Instance::as_select())
+        .load_async::<Instance>(self.pool_authorized(opctx).await?)
+        .await
+        .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
     }
 
     /// Fetches information about an Instance that the caller has previously
@@ -1185,6 +1860,26 @@ impl DataStore {
             })
     }
 
+    /// Fetches the Instances with the given ids that haven't been deleted,
+    /// without any authorization check
+    ///
+    /// This is for Nexus's own use, such as finding the sleds hosting some
+    /// network interfaces.
+    pub async fn instance_list_all_by_ids(
+        &self,
+        instance_ids: Vec<Uuid>,
+    ) -> ListResultVec<Instance> {
+        use db::schema::instance::dsl;
+
+        dsl::instance
+            .filter(dsl::time_deleted.is_null())
+            .filter(dsl::id.eq_any(instance_ids))
+            .select(Instance::as_select())
+            .load_async::<Instance>(self.pool())
+            .await
+            .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
+    }
+
     /*
      * TODO-design It's tempting to return the updated state of the Instance
      * here because it's convenient for consumers and by using a RETURNING
@@ -1231,14 +1926,101 @@ impl DataStore {
         Ok(updated)
     }
 
-    pub async fn project_delete_instance(
+    /// Updates an Instance's labels
+    pub async fn instance_update(
         &self,
         opctx: &OpContext,
         authz_instance: &authz::Instance,
-    ) -> DeleteResult {
-        opctx.authorize(authz::Action::Delete, authz_instance).await?;
+        updates: InstanceUpdate,
+    ) -> UpdateResult<Instance> {
+        opctx.authorize(authz::Action::Modify, authz_instance).await?;
 
-        /*
+        use db::schema::instance::dsl;
+        diesel::update(dsl::instance)
+            .filter(dsl::time_deleted.is_null())
+            .filter(dsl::id.eq(authz_instance.id()))
+            .set(updates)
+            .returning(Instance::as_returning())
+            .get_result_async(self.pool_authorized(opctx).await?)
+            .await
+            .map_err(|e| {
+                public_error_from_diesel_pool(
+                    e,
+                    ErrorHandler::NotFoundByResource(authz_instance),
+                )
+            })
+    }
+
+    /// Like [`DataStore::instance_update_runtime`], but for a change to the
+    /// vCPUs or memory of a stopped Instance, which is only made if the
+    /// Instance's Project stays within its quotas
+    pub async fn instance_resize(
+        &self,
+        opctx: &OpContext,
+        authz_instance: &authz::Instance,
+        new_runtime: &InstanceRuntimeState,
+    ) -> Result<bool, Error> {
+        opctx.authorize(authz::Action::Modify, authz_instance).await?;
+
+        use db::schema::instance::dsl;
+        type TxnError = TransactionError<QuotaExceeded>;
+        let instance_id = authz_instance.id();
+        let new_runtime = new_runtime.clone();
+        self.pool_authorized(opctx)
+            .await?
+            .transaction(move |conn| {
+                // Only growing an Instance counts against its Project's
+                // quotas.
+                let instance = dsl::instance
+                    .filter(dsl::time_deleted.is_null())
+                    .filter(dsl::id.eq(instance_id))
+                    .select(Instance::as_select())
+                    .get_result::<Instance>(conn)?;
+                let grown =
+                    |new: i64, old: i64| if new > old { new } else { 0 };
+                let requested = ResourceUsage {
+                    vcpus: grown(
+                        i64::from(new_runtime.ncpus.0 .0),
+                        i64::from(instance.runtime().ncpus.0 .0),
+                    ),
+                    memory: grown(
+                        i64::from(new_runtime.memory.0),
+                        i64::from(instance.runtime().memory.0),
+                    ),
+                    ..Default::default()
+                };
+                quota_check(
+                    conn,
+                    instance.project_id,
+                    instance_id,
+                    &requested,
+                )?;
+                let updated = diesel::update(dsl::instance)
+                    .filter(dsl::time_deleted.is_null())
+                    .filter(dsl::id.eq(instance_id))
+                    .filter(dsl::state_generation.lt(new_runtime.gen))
+                    .set(new_runtime)
+                    .execute(conn)?;
+                Ok(updated > 0)
+            })
+            .await
+            .map_err(|e| match e {
+                TxnError::CustomError(e) => e.into(),
+                TxnError::Pool(e) => public_error_from_diesel_pool(
+                    e,
+                    ErrorHandler::NotFoundByResource(authz_instance),
+                ),
+            })
+    }
+
+    pub async fn project_delete_instance(
+        &self,
+        opctx: &OpContext,
+        authz_instance: &authz::Instance,
+    ) -> DeleteResult {
+        opctx.authorize(authz::Action::Delete, authz_instance).await?;
+
+        /*
          * This is subject to change, but for now we're going to say that an
          * instance must be "stopped" or "failed" in order to delete it.  The
          * delete operation sets "time_deleted" (just like with other objects)
@@ -1424,18 +2206,29 @@ impl DataStore {
 
         let gen = disk.runtime().gen;
         let name = disk.name().clone();
-        let disk: Disk = diesel::insert_into(dsl::disk)
-            .values(disk)
-            .on_conflict(dsl::id)
-            .do_nothing()
-            .returning(Disk::as_returning())
-            .get_result_async(self.pool())
+        let requested = ResourceUsage {
+            disk_bytes: i64::from(disk.size.0),
+            ..Default::default()
+        };
+        type TxnError = TransactionError<QuotaExceeded>;
+        let disk: Disk = self
+            .pool()
+            .transaction(move |conn| {
+                quota_check(conn, disk.project_id, disk.id(), &requested)?;
+                Ok(diesel::insert_into(dsl::disk)
+                    .values(disk)
+                    .on_conflict(dsl::id)
+                    .do_nothing()
+                    .returning(Disk::as_returning())
+                    .get_result(conn)?)
+            })
             .await
-            .map_err(|e| {
-                public_error_from_diesel_pool(
+            .map_err(|e| match e {
+                TxnError::CustomError(e) => e.into(),
+                TxnError::Pool(e) => public_error_from_diesel_pool(
                     e,
                     ErrorHandler::Conflict(ResourceType::Disk, name.as_str()),
-                )
+                ),
             })?;
 
         let runtime = disk.runtime();
@@ -1457,17 +2250,57 @@ impl DataStore {
         opctx: &OpContext,
         authz_project: &authz::Project,
         pagparams: &DataPageParams<'_, Name>,
+        label_selector: &LabelSelector,
     ) -> ListResultVec<Disk> {
         opctx.authorize(authz::Action::ListChildren, authz_project).await?;
 
         use db::schema::disk::dsl;
-        paginated(dsl::disk, dsl::name, &pagparams)
-            .filter(dsl::time_deleted.is_null())
-            .filter(dsl::project_id.eq(authz_project.id()))
-            .select(Disk::as_select())
-            .load_async::<Disk>(self.pool_authorized(opctx).await?)
-            .await
-            .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
+        filter_by_labels(
+            paginated(dsl::disk, dsl::name, &pagparams),
+            dsl::labels,
+            label_selector,
+        )
+        .filter(dsl::time_deleted.is_null())
+        .filter(dsl::project_id.eq(authz_project.id()))
+        .select(Disk::as_select())
+        .load_async::<Disk>(self.pool_authorized(opctx).await?)
+        .await
+        .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
+    }
+
+    /// Updates a Disk's labels
+    pub async fn disk_update(
+        &self,
+        opctx: &OpContext,
+        authz_disk: &authz::Disk,
+        updates: DiskUpdate,
+        preconditions: &Preconditions,
+    ) -> UpdateResult<Disk> {
+        opctx.authorize(authz::Action::Modify, authz_disk).await?;
+
+        use db::schema::disk::dsl;
+        let id = authz_disk.id();
+        Self::update_with_preconditions(
+            self.pool_authorized(opctx).await?,
+            dsl::disk,
+            id,
+            preconditions,
+            move |conn| {
+                Ok(diesel::update(dsl::disk)
+                    .filter(dsl::time_deleted.is_null())
+                    .filter(dsl::id.eq(id))
+                    .set(updates)
+                    .returning(Disk::as_returning())
+                    .get_result(conn)?)
+            },
+            |e| {
+                public_error_from_diesel_pool(
+                    e,
+                    ErrorHandler::NotFoundByResource(authz_disk),
+                )
+            },
+        )
+        .await
     }
 
     pub async fn disk_update_runtime(
@@ -1511,6 +2344,123 @@ impl DataStore {
         Ok(updated)
     }
 
+    /// Updates the recorded size of a Disk whose regions have been resized
+    pub async fn disk_update_size(
+        &self,
+        opctx: &OpContext,
+        authz_disk: &authz::Disk,
+        size: api::external::ByteCount,
+    ) -> UpdateResult<Disk> {
+        opctx.authorize(authz::Action::Modify, authz_disk).await?;
+
+        use db::schema::disk::dsl;
+        type TxnError = TransactionError<QuotaExceeded>;
+        let disk_id = authz_disk.id();
+        self.pool_authorized(opctx)
+            .await?
+            .transaction(move |conn| {
+                // Only growing a Disk counts against its Project's quotas.
+                let disk = dsl::disk
+                    .filter(dsl::time_deleted.is_null())
+                    .filter(dsl::id.eq(disk_id))
+                    .select(Disk::as_select())
+                    .get_result::<Disk>(conn)?;
+                if size.to_bytes() > disk.size.to_bytes() {
+                    let requested = ResourceUsage {
+                        disk_bytes: i64::from(size),
+                        ..Default::default()
+                    };
+                    quota_check(conn, disk.project_id, disk_id, &requested)?;
+                }
+                Ok(diesel::update(dsl::disk)
+                    .filter(dsl::id.eq(disk_id))
+                    .set((
+                        dsl::size_bytes.eq(db::model::ByteCount::from(size)),
+                        dsl::time_modified.eq(Utc::now()),
+                    ))
+                    .returning(Disk::as_returning())
+                    .get_result(conn)?)
+            })
+            .await
+            .map_err(|e| match e {
+                TxnError::CustomError(e) => e.into(),
+                TxnError::Pool(e) => public_error_from_diesel_pool(
+                    e,
+                    ErrorHandler::NotFoundByResource(authz_disk),
+                ),
+            })
+    }
+
+    /// Claims a Disk for the resize `resize_id`, so that at most one resize of
+    /// the Disk runs at a time
+    ///
+    /// The claim fails if another resize holds it, or if the Disk is no longer
+    /// `size` bytes, the size from which the resize was planned.  Claiming a
+    /// Disk again for the same resize succeeds, so this may be replayed.
+    pub async fn disk_resize_claim(
+        &self,
+        opctx: &OpContext,
+        authz_disk: &authz::Disk,
+        resize_id: Uuid,
+        size: api::external::ByteCount,
+    ) -> UpdateResult<()> {
+        opctx.authorize(authz::Action::Modify, authz_disk).await?;
+
+        use db::schema::disk::dsl;
+        let disk_id = authz_disk.id();
+        let result = diesel::update(dsl::disk)
+            .filter(dsl::time_deleted.is_null())
+            .filter(dsl::id.eq(disk_id))
+            .filter(dsl::size_bytes.eq(db::model::ByteCount::from(size)))
+            .filter(dsl::resize_id.is_null().or(dsl::resize_id.eq(resize_id)))
+            .set(dsl::resize_id.eq(resize_id))
+            .check_if_exists::<Disk>(disk_id)
+            .execute_and_check(self.pool_authorized(opctx).await?)
+            .await
+            .map_err(|e| {
+                public_error_from_diesel_pool(
+                    e,
+                    ErrorHandler::NotFoundByResource(authz_disk),
+                )
+            })?;
+        match result.status {
+            UpdateStatus::Updated => Ok(()),
+            UpdateStatus::NotUpdatedButExists => {
+                let message = if result.found.resize_id.is_some() {
+                    "disk is already being resized"
+                } else {
+                    "disk changed size while the resize was starting"
+                };
+                Err(Error::InvalidRequest { message: message.to_string() })
+            }
+        }
+    }
+
+    /// Releases a Disk's claim by the resize `resize_id`, if it holds one
+    pub async fn disk_resize_release(
+        &self,
+        opctx: &OpContext,
+        authz_disk: &authz::Disk,
+        resize_id: Uuid,
+    ) -> UpdateResult<()> {
+        opctx.authorize(authz::Action::Modify, authz_disk).await?;
+
+        use db::schema::disk::dsl;
+        diesel::update(dsl::disk)
+            .filter(dsl::id.eq(authz_disk.id()))
+            .filter(dsl::resize_id.eq(resize_id))
+            .set(dsl::resize_id.eq(None::<Uuid>))
+            .execute_async(self.pool_authorized(opctx).await?)
+            .await
+            .map_err(|e| {
+                public_error_from_diesel_pool(
+                    e,
+                    ErrorHandler::NotFoundByResource(authz_disk),
+                )
+            })?;
+        Ok(())
+    }
+
     /// Fetches information about a Disk that the caller has previously fetched
     ///
     /// The principal difference from `disk_fetch` is that this function takes
@@ -1639,1048 +2589,3600 @@ impl DataStore {
     }
 
     /*
-     * Network interfaces
+     * Snapshots
      */
 
-    pub async fn instance_create_network_interface(
-        &self,
-        interface: IncompleteNetworkInterface,
-    ) -> CreateResult<NetworkInterface> {
-        use db::schema::network_interface::dsl;
-
-        // TODO: Longer term, it would be nice to decouple the IP allocation
-        // (and MAC allocation) from the NetworkInterface table, so that
-        // retrying from parallel inserts doesn't need to happen here.
-
-        let name = interface.identity.name.clone();
-        match interface.ip {
-            // Attempt an insert with a requested IP address
-            Some(ip) => {
-                interface.subnet.contains(ip)?;
-                let row = NetworkInterface {
-                    identity: interface.identity,
-                    instance_id: interface.instance_id,
-                    vpc_id: interface.vpc_id,
-                    subnet_id: interface.subnet.id(),
-                    mac: interface.mac,
-                    ip: ip.into(),
-                };
-                diesel::insert_into(dsl::network_interface)
-                    .values(row)
-                    .returning(NetworkInterface::as_returning())
-                    .get_result_async(self.pool())
-                    .await
-                    .map_err(|e| {
-                        public_error_from_diesel_pool(
-                            e,
-                            ErrorHandler::Conflict(
-                                ResourceType::NetworkInterface,
-                                name.as_str(),
-                            ),
-                        )
-                    })
-            }
-            // Insert and allocate an IP address
-            None => {
-                let allocation_query = AllocateIpQuery {
-                    block: ipnetwork::IpNetwork::V4(
-                        interface.subnet.ipv4_block.0 .0,
-                    ),
-                    interface,
-                    now: Utc::now(),
-                };
-                diesel::insert_into(dsl::network_interface)
-                    .values(allocation_query)
-                    .returning(NetworkInterface::as_returning())
-                    .get_result_async(self.pool())
-                    .await
-                    .map_err(|e| {
-                        if let PoolError::Connection(ConnectionError::Query(
-                            diesel::result::Error::NotFound,
-                        )) = e
-                        {
-                            Error::InvalidRequest {
-                                message: "no available IP addresses"
-                                    .to_string(),
-                            }
-                        } else {
-                            public_error_from_diesel_pool(
-                                e,
-                                ErrorHandler::Conflict(
-                                    ResourceType::NetworkInterface,
-                                    name.as_str(),
-                                ),
-                            )
-                        }
-                    })
-            }
-        }
-    }
-
-    pub async fn instance_delete_network_interface(
+    /// Fetches a Snapshot of a Disk from the database and returns both the
+    /// database row and an [`authz::Snapshot`] for doing authz checks
+    ///
+    /// See [`DataStore::organization_lookup_noauthz()`] for intended use cases
+    /// and caveats.
+    // TODO-security See the note on organization_lookup_noauthz().
+    async fn snapshot_lookup_noauthz(
         &self,
-        network_interface_id: &Uuid,
-    ) -> DeleteResult {
-        use db::schema::network_interface::dsl;
-
-        // TODO-correctness: Do not allow deleting interfaces on running
-        // instances until we support hotplug
-
-        let now = Utc::now();
-        diesel::update(dsl::network_interface)
+        authz_disk: &authz::Disk,
+        snapshot_name: &Name,
+    ) -> LookupResult<(authz::Snapshot, Snapshot)> {
+        use db::schema::snapshot::dsl;
+        dsl::snapshot
             .filter(dsl::time_deleted.is_null())
-            .filter(dsl::id.eq(*network_interface_id))
-            .set(dsl::time_deleted.eq(now))
-            .returning(NetworkInterface::as_returning())
-            .get_result_async(self.pool())
+            .filter(dsl::disk_id.eq(authz_disk.id()))
+            .filter(dsl::name.eq(snapshot_name.clone()))
+            .select(Snapshot::as_select())
+            .first_async(self.pool())
             .await
             .map_err(|e| {
                 public_error_from_diesel_pool(
                     e,
                     ErrorHandler::NotFoundByLookup(
-                        ResourceType::NetworkInterface,
-                        LookupType::ById(*network_interface_id),
+                        ResourceType::Snapshot,
+                        LookupType::ByName(snapshot_name.as_str().to_owned()),
                     ),
                 )
-            })?;
-        Ok(())
+            })
+            .map(|s| {
+                (
+                    authz_disk.project().child_generic(
+                        ResourceType::Snapshot,
+                        s.id(),
+                        LookupType::from(&snapshot_name.0),
+                    ),
+                    s,
+                )
+            })
     }
 
-    // Create a record for a new Oximeter instance
-    pub async fn oximeter_create(
+    /// Lookup a Snapshot of a Disk by name and return the full database
+    /// record, along with an [`authz::Snapshot`] for subsequent authorization
+    /// checks
+    pub async fn snapshot_fetch(
         &self,
-        info: &OximeterInfo,
-    ) -> Result<(), Error> {
-        use db::schema::oximeter::dsl;
-
-        // If we get a conflict on the Oximeter ID, this means that collector instance was
-        // previously registered, and it's re-registering due to something like a service restart.
-        // In this case, we update the time modified and the service address, rather than
-        // propagating a constraint violation to the caller.
-        diesel::insert_into(dsl::oximeter)
-            .values(*info)
-            .on_conflict(dsl::id)
-            .do_update()
-            .set((
-                dsl::time_modified.eq(Utc::now()),
-                dsl::ip.eq(info.ip),
-                dsl::port.eq(info.port),
-            ))
-            .execute_async(self.pool())
-            .await
-            .map_err(|e| {
-                public_error_from_diesel_pool(
-                    e,
-                    ErrorHandler::Conflict(
-                        ResourceType::Oximeter,
-                        "Oximeter Info",
-                    ),
-                )
-            })?;
-        Ok(())
+        opctx: &OpContext,
+        authz_disk: &authz::Disk,
+        name: &Name,
+    ) -> LookupResult<(authz::Snapshot, Snapshot)> {
+        let (authz_snapshot, db_snapshot) =
+            self.snapshot_lookup_noauthz(authz_disk, name).await?;
+        opctx.authorize(authz::Action::Read, &authz_snapshot).await?;
+        Ok((authz_snapshot, db_snapshot))
     }
 
-    // Fetch a record for an Oximeter instance, by its ID.
-    pub async fn oximeter_fetch(
+    /// Lookup a Snapshot by id within a Project
+    ///
+    /// This is used when creating a Disk from a Snapshot, where the Snapshot
+    /// is identified by id rather than by name.
+    pub async fn snapshot_fetch_by_id(
         &self,
-        id: Uuid,
-    ) -> Result<OximeterInfo, Error> {
-        use db::schema::oximeter::dsl;
-        dsl::oximeter
-            .filter(dsl::id.eq(id))
-            .first_async::<OximeterInfo>(self.pool())
+        opctx: &OpContext,
+        authz_project: &authz::Project,
+        snapshot_id: Uuid,
+    ) -> LookupResult<(authz::Snapshot, Snapshot)> {
+        use db::schema::snapshot::dsl;
+        let db_snapshot = dsl::snapshot
+            .filter(dsl::time_deleted.is_null())
+            .filter(dsl::project_id.eq(authz_project.id()))
+            .filter(dsl::id.eq(snapshot_id))
+            .select(Snapshot::as_select())
+            .first_async(self.pool())
             .await
             .map_err(|e| {
                 public_error_from_diesel_pool(
                     e,
                     ErrorHandler::NotFoundByLookup(
-                        ResourceType::Oximeter,
-                        LookupType::ById(id),
+                        ResourceType::Snapshot,
+                        LookupType::ById(snapshot_id),
                     ),
                 )
-            })
+            })?;
+        let authz_snapshot = authz_project.child_generic(
+            ResourceType::Snapshot,
+            snapshot_id,
+            LookupType::ById(snapshot_id),
+        );
+        opctx.authorize(authz::Action::Read, &authz_snapshot).await?;
+        Ok((authz_snapshot, db_snapshot))
     }
 
-    // List the oximeter collector instances
-    pub async fn oximeter_list(
+    pub async fn disk_list_snapshots(
         &self,
-        page_params: &DataPageParams<'_, Uuid>,
-    ) -> ListResultVec<OximeterInfo> {
-        use db::schema::oximeter::dsl;
-        paginated(dsl::oximeter, dsl::id, page_params)
-            .load_async::<OximeterInfo>(self.pool())
+        opctx: &OpContext,
+        authz_disk: &authz::Disk,
+        pagparams: &DataPageParams<'_, Name>,
+    ) -> ListResultVec<Snapshot> {
+        opctx.authorize(authz::Action::ListChildren, authz_disk).await?;
+
+        use db::schema::snapshot::dsl;
+        paginated(dsl::snapshot, dsl::name, &pagparams)
+            .filter(dsl::time_deleted.is_null())
+            .filter(dsl::disk_id.eq(authz_disk.id()))
+            .select(Snapshot::as_select())
+            .load_async::<Snapshot>(self.pool_authorized(opctx).await?)
             .await
             .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
     }
 
-    // Create a record for a new producer endpoint
-    pub async fn producer_endpoint_create(
+    pub async fn disk_create_snapshot(
         &self,
-        producer: &ProducerEndpoint,
-    ) -> Result<(), Error> {
-        use db::schema::metric_producer::dsl;
+        snapshot: Snapshot,
+    ) -> CreateResult<Snapshot> {
+        use db::schema::snapshot::dsl;
 
-        // TODO: see https://github.com/oxidecomputer/omicron/issues/323
-        diesel::insert_into(dsl::metric_producer)
-            .values(producer.clone())
+        let name = snapshot.name().clone();
+        diesel::insert_into(dsl::snapshot)
+            .values(snapshot)
             .on_conflict(dsl::id)
-            .do_update()
-            .set((
-                dsl::time_modified.eq(Utc::now()),
-                dsl::ip.eq(producer.ip),
-                dsl::port.eq(producer.port),
-                dsl::interval.eq(producer.interval),
-                dsl::base_route.eq(producer.base_route.clone()),
-            ))
-            .execute_async(self.pool())
+            .do_nothing()
+            .returning(Snapshot::as_returning())
+            .get_result_async(self.pool())
             .await
             .map_err(|e| {
                 public_error_from_diesel_pool(
                     e,
                     ErrorHandler::Conflict(
-                        ResourceType::MetricProducer,
-                        "Producer Endpoint",
+                        ResourceType::Snapshot,
+                        name.as_str(),
                     ),
                 )
-            })?;
-        Ok(())
+            })
     }
 
-    // List the producer endpoint records by the oximeter instance to which they're assigned.
-    pub async fn producers_list_by_oximeter_id(
+    /// Returns whether any (undeleted) Snapshots have been taken of a Disk
+    pub async fn disk_has_snapshots(
         &self,
-        oximeter_id: Uuid,
-        pagparams: &DataPageParams<'_, Uuid>,
-    ) -> ListResultVec<ProducerEndpoint> {
-        use db::schema::metric_producer::dsl;
-        paginated(dsl::metric_producer, dsl::id, &pagparams)
-            .filter(dsl::oximeter_id.eq(oximeter_id))
-            .order_by((dsl::oximeter_id, dsl::id))
-            .select(ProducerEndpoint::as_select())
-            .load_async(self.pool())
-            .await
-            .map_err(|e| {
-                public_error_from_diesel_pool(
-                    e,
-                    ErrorHandler::Conflict(
-                        ResourceType::MetricProducer,
-                        "By Oximeter ID",
-                    ),
-                )
-            })
+        disk_id: Uuid,
+    ) -> LookupResult<bool> {
+        use db::schema::snapshot::dsl;
+        diesel_pool_result_optional(
+            dsl::snapshot
+                .filter(dsl::disk_id.eq(disk_id))
+                .filter(dsl::time_deleted.is_null())
+                .select(dsl::id)
+                .limit(1)
+                .first_async::<Uuid>(self.pool())
+                .await,
+        )
+        .map(|found| found.is_some())
+        .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
     }
 
-    // Sagas
-
-    pub async fn saga_create(
+    /// Updates a snapshot record to indicate it has been deleted, unless some
+    /// Disk was created from it
+    ///
+    /// Does not attempt to remove the region snapshots held by the Crucible
+    /// Agents.  Like [`DataStore::project_delete_disk_no_auth()`], this is
+    /// used from within a saga, so the caller is responsible for authz.
+    pub async fn project_delete_snapshot_no_auth(
         &self,
-        saga: &db::saga_types::Saga,
-    ) -> Result<(), Error> {
-        use db::schema::saga::dsl;
+        snapshot_id: &Uuid,
+    ) -> DeleteResult {
+        use db::schema::disk;
+        use db::schema::snapshot::dsl;
 
-        let name = saga.template_name.clone();
-        diesel::insert_into(dsl::saga)
-            .values(saga.clone())
+        // Make sure no Disks still refer to this snapshot.
+        //
+        // TODO-correctness A Disk could be created from the snapshot between
+        // this check and the deletion below.
+        let disk_found = diesel_pool_result_optional(
+            disk::dsl::disk
+                .filter(disk::dsl::origin_snapshot.eq(*snapshot_id))
+                .filter(disk::dsl::time_deleted.is_null())
+                .select(disk::dsl::id)
+                .limit(1)
+                .first_async::<Uuid>(self.pool())
+                .await,
+        )
+        .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))?;
+        if disk_found.is_some() {
+            return Err(Error::InvalidRequest {
+                message: "snapshot to be deleted is in use by a disk"
+                    .to_string(),
+            });
+        }
+
+        // As with Disks, deleting a Snapshot that's already been deleted is
+        // not an error so that the saga can be replayed.
+        let now = Utc::now();
+        diesel::update(dsl::snapshot)
+            .filter(dsl::time_deleted.is_null())
+            .filter(dsl::id.eq(*snapshot_id))
+            .set(dsl::time_deleted.eq(now))
             .execute_async(self.pool())
             .await
             .map_err(|e| {
                 public_error_from_diesel_pool(
                     e,
-                    ErrorHandler::Conflict(ResourceType::SagaDbg, &name),
+                    ErrorHandler::NotFoundByLookup(
+                        ResourceType::Snapshot,
+                        LookupType::ById(*snapshot_id),
+                    ),
                 )
             })?;
         Ok(())
     }
 
-    pub async fn saga_create_event(
+    /*
+     * Network interfaces
+     */
+
+    pub async fn instance_create_network_interface(
         &self,
-        event: &db::saga_types::SagaNodeEvent,
-    ) -> Result<(), Error> {
-        use db::schema::saga_node_event::dsl;
+        interface: IncompleteNetworkInterface,
+    ) -> CreateResult<NetworkInterface> {
+        use db::schema::network_interface::dsl;
 
-        // TODO-robustness This INSERT ought to be conditional on this SEC still
-        // owning this saga.
-        diesel::insert_into(dsl::saga_node_event)
-            .values(event.clone())
-            .execute_async(self.pool())
+        // TODO: Longer term, it would be nice to decouple the IP allocation
+        // (and MAC allocation) from the NetworkInterface table, so that
+        // retrying from parallel inserts doesn't need to happen here.
+
+        let name = interface.identity.name.clone();
+        match interface.ip {
+            // Attempt an insert with a requested IP address
+            Some(ip) => {
+                interface.subnet.contains(ip)?;
+                let row = NetworkInterface {
+                    identity: interface.identity,
+                    instance_id: interface.instance_id,
+                    vpc_id: interface.vpc_id,
+                    subnet_id: interface.subnet.id(),
+                    mac: interface.mac,
+                    ip: ip.into(),
+                };
+                diesel::insert_into(dsl::network_interface)
+                    .values(row)
+                    .returning(NetworkInterface::as_returning())
+                    .get_result_async(self.pool())
+                    .await
+                    .map_err(|e| {
+                        public_error_from_diesel_pool(
+                            e,
+                            ErrorHandler::Conflict(
+                                ResourceType::NetworkInterface,
+                                name.as_str(),
+                            ),
+                        )
+                    })
+            }
+            // Insert and allocate an IP address
+            None => {
+                let allocation_query = AllocateIpQuery {
+                    block: ipnetwork::IpNetwork::V4(
+                        interface.subnet.ipv4_block.0 .0,
+                    ),
+                    interface,
+                    now: Utc::now(),
+                };
+                diesel::insert_into(dsl::network_interface)
+                    .values(allocation_query)
+                    .returning(NetworkInterface::as_returning())
+                    .get_result_async(self.pool())
+                    .await
+                    .map_err(|e| {
+                        if let PoolError::Connection(ConnectionError::Query(
+                            diesel::result::Error::NotFound,
+                        )) = e
+                        {
+                            Error::InvalidRequest {
+                                message: "no available IP addresses"
+                                    .to_string(),
+                            }
+                        } else {
+                            public_error_from_diesel_pool(
+                                e,
+                                ErrorHandler::Conflict(
+                                    ResourceType::NetworkInterface,
+                                    name.as_str(),
+                                ),
+                            )
+                        }
+                    })
+            }
+        }
+    }
+
+    pub async fn instance_delete_network_interface(
+        &self,
+        network_interface_id: &Uuid,
+    ) -> DeleteResult {
+        use db::schema::network_interface::dsl;
+
+        // TODO-correctness: Do not allow deleting interfaces on running
+        // instances until we support hotplug
+
+        let now = Utc::now();
+        diesel::update(dsl::network_interface)
+            .filter(dsl::time_deleted.is_null())
+            .filter(dsl::id.eq(*network_interface_id))
+            .set(dsl::time_deleted.eq(now))
+            .returning(NetworkInterface::as_returning())
+            .get_result_async(self.pool())
             .await
             .map_err(|e| {
                 public_error_from_diesel_pool(
                     e,
-                    ErrorHandler::Conflict(ResourceType::SagaDbg, "Saga Event"),
+                    ErrorHandler::NotFoundByLookup(
+                        ResourceType::NetworkInterface,
+                        LookupType::ById(*network_interface_id),
+                    ),
                 )
             })?;
         Ok(())
     }
 
-    pub async fn saga_update_state(
+    /// Deletes all of an Instance's network interfaces
+    pub async fn instance_delete_all_network_interfaces(
         &self,
-        saga_id: steno::SagaId,
-        new_state: steno::SagaCachedState,
-        current_sec: db::saga_types::SecId,
-        current_adopt_generation: Generation,
-    ) -> Result<(), Error> {
-        use db::schema::saga::dsl;
+        instance_id: &Uuid,
+    ) -> DeleteResult {
+        use db::schema::network_interface::dsl;
 
-        let saga_id: db::saga_types::SagaId = saga_id.into();
-        let result = diesel::update(dsl::saga)
-            .filter(dsl::id.eq(saga_id))
-            .filter(dsl::current_sec.eq(current_sec))
-            .filter(dsl::adopt_generation.eq(current_adopt_generation))
-            .set(dsl::saga_state.eq(db::saga_types::SagaCachedState(new_state)))
-            .check_if_exists::<db::saga_types::Saga>(saga_id)
-            .execute_and_check(self.pool())
+        let now = Utc::now();
+        diesel::update(dsl::network_interface)
+            .filter(dsl::time_deleted.is_null())
+            .filter(dsl::instance_id.eq(*instance_id))
+            .set(dsl::time_deleted.eq(now))
+            .execute_async(self.pool())
             .await
             .map_err(|e| {
-                public_error_from_diesel_pool(
-                    e,
-                    ErrorHandler::NotFoundByLookup(
-                        ResourceType::SagaDbg,
-                        LookupType::ById(saga_id.0.into()),
-                    ),
-                )
+                public_error_from_diesel_pool(e, ErrorHandler::Server)
             })?;
+        Ok(())
+    }
 
-        match result.status {
-            UpdateStatus::Updated => Ok(()),
-            UpdateStatus::NotUpdatedButExists => Err(Error::InvalidRequest {
-                message: format!(
-                    "failed to update saga {:?} with state {:?}: preconditions not met: \
-                    expected current_sec = {:?}, adopt_generation = {:?}, \
-                    but found current_sec = {:?}, adopt_generation = {:?}, state = {:?}",
-                    saga_id,
-                    new_state,
-                    current_sec,
-                    current_adopt_generation,
-                    result.found.current_sec,
-                    result.found.adopt_generation,
-                    result.found.saga_state,
-                )
-            }),
-        }
+    pub async fn instance_list_network_interfaces(
+        &self,
+        opctx: &OpContext,
+        authz_instance: &authz::Instance,
+        pagparams: &DataPageParams<'_, Name>,
+    ) -> ListResultVec<NetworkInterface> {
+        use db::schema::network_interface::dsl;
+
+        opctx.authorize(authz::Action::ListChildren, authz_instance).await?;
+
+        paginated(dsl::network_interface, dsl::name, pagparams)
+            .filter(dsl::time_deleted.is_null())
+            .filter(dsl::instance_id.eq(authz_instance.id()))
+            .select(NetworkInterface::as_select())
+            .load_async::<NetworkInterface>(self.pool_authorized(opctx).await?)
+            .await
+            .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
     }
 
-    pub async fn saga_list_unfinished_by_id(
+    /// Fetches all of an Instance's network interfaces
+    ///
+    /// This isn't paginated because an Instance has at most
+    /// [`params::MAX_NICS_PER_INSTANCE`] of them.
+    pub async fn instance_list_all_network_interfaces(
         &self,
-        sec_id: &db::SecId,
-        pagparams: &DataPageParams<'_, Uuid>,
-    ) -> ListResultVec<db::saga_types::Saga> {
-        use db::schema::saga::dsl;
-        paginated(dsl::saga, dsl::id, &pagparams)
-            .filter(dsl::saga_state.ne(db::saga_types::SagaCachedState(
-                steno::SagaCachedState::Done,
-            )))
-            .filter(dsl::current_sec.eq(*sec_id))
-            .load_async(self.pool())
+        instance_id: &Uuid,
+    ) -> ListResultVec<NetworkInterface> {
+        use db::schema::network_interface::dsl;
+
+        dsl::network_interface
+            .filter(dsl::time_deleted.is_null())
+            .filter(dsl::instance_id.eq(*instance_id))
+            .order(dsl::name.asc())
+            .select(NetworkInterface::as_select())
+            .load_async::<NetworkInterface>(self.pool())
             .await
-            .map_err(|e| {
-                public_error_from_diesel_pool(
-                    e,
-                    ErrorHandler::NotFoundByLookup(
-                        ResourceType::SagaDbg,
-                        LookupType::ById(sec_id.0),
-                    ),
-                )
-            })
+            .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
     }
 
-    pub async fn saga_node_event_list_by_id(
+    pub async fn instance_fetch_network_interface(
         &self,
-        id: db::saga_types::SagaId,
-        pagparams: &DataPageParams<'_, Uuid>,
-    ) -> ListResultVec<steno::SagaNodeEvent> {
-        use db::schema::saga_node_event::dsl;
-        paginated(dsl::saga_node_event, dsl::saga_id, &pagparams)
-            .filter(dsl::saga_id.eq(id))
-            .load_async::<db::saga_types::SagaNodeEvent>(self.pool())
+        opctx: &OpContext,
+        authz_instance: &authz::Instance,
+        interface_name: &Name,
+    ) -> LookupResult<NetworkInterface> {
+        use db::schema::network_interface::dsl;
+
+        opctx.authorize(authz::Action::Read, authz_instance).await?;
+
+        dsl::network_interface
+            .filter(dsl::time_deleted.is_null())
+            .filter(dsl::instance_id.eq(authz_instance.id()))
+            .filter(dsl::name.eq(interface_name.clone()))
+            .select(NetworkInterface::as_select())
+            .get_result_async(self.pool_authorized(opctx).await?)
             .await
             .map_err(|e| {
                 public_error_from_diesel_pool(
                     e,
                     ErrorHandler::NotFoundByLookup(
-                        ResourceType::SagaDbg,
-                        LookupType::ById(id.0 .0),
+                        ResourceType::NetworkInterface,
+                        LookupType::ByName(interface_name.as_str().to_owned()),
                     ),
                 )
-            })?
-            .into_iter()
-            .map(|db_event| steno::SagaNodeEvent::try_from(db_event))
-            .collect::<Result<_, Error>>()
+            })
     }
 
-    // VPCs
+    /// Fetches the primary network interface of an Instance, which is the
+    /// one created first
+    pub async fn instance_fetch_primary_network_interface(
+        &self,
+        instance_id: &Uuid,
+    ) -> LookupResult<NetworkInterface> {
+        use db::schema::network_interface::dsl;
 
-    pub async fn project_list_vpcs(
+        diesel_pool_result_optional(
+            dsl::network_interface
+                .filter(dsl::time_deleted.is_null())
+                .filter(dsl::instance_id.eq(*instance_id))
+                .order((dsl::time_created.asc(), dsl::id.asc()))
+                .select(NetworkInterface::as_select())
+                .first_async::<NetworkInterface>(self.pool())
+                .await,
+        )
+        .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))?
+        .ok_or_else(|| Error::InvalidRequest {
+            message: "instance has no network interfaces".to_string(),
+        })
+    }
+
+    /*
+     * IP pools
+     */
+
+    pub async fn ip_pools_list_by_name(
         &self,
-        project_id: &Uuid,
+        opctx: &OpContext,
         pagparams: &DataPageParams<'_, Name>,
-    ) -> ListResultVec<Vpc> {
-        use db::schema::vpc::dsl;
-
-        paginated(dsl::vpc, dsl::name, &pagparams)
+    ) -> ListResultVec<IpPool> {
+        use db::schema::ip_pool::dsl;
+        opctx.authorize(authz::Action::ListChildren, &authz::FLEET).await?;
+        paginated(dsl::ip_pool, dsl::name, pagparams)
             .filter(dsl::time_deleted.is_null())
-            .filter(dsl::project_id.eq(*project_id))
-            .select(Vpc::as_select())
-            .load_async(self.pool())
+            .select(IpPool::as_select())
+            .load_async::<IpPool>(self.pool_authorized(opctx).await?)
             .await
             .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
     }
 
-    pub async fn project_create_vpc(&self, vpc: Vpc) -> Result<Vpc, Error> {
-        use db::schema::vpc::dsl;
+    pub async fn ip_pool_create(
+        &self,
+        opctx: &OpContext,
+        pool: IpPool,
+    ) -> CreateResult<IpPool> {
+        use db::schema::ip_pool::dsl;
 
-        let name = vpc.name().clone();
-        let vpc = diesel::insert_into(dsl::vpc)
-            .values(vpc)
-            .on_conflict(dsl::id)
-            .do_nothing()
-            .returning(Vpc::as_returning())
-            .get_result_async(self.pool())
+        // Only fleet administrators may manage IP pools.
+        opctx.authorize(authz::Action::Modify, &authz::FLEET).await?;
+
+        let name = pool.name().as_str().to_string();
+        diesel::insert_into(dsl::ip_pool)
+            .values(pool)
+            .returning(IpPool::as_returning())
+            .get_result_async(self.pool_authorized(opctx).await?)
             .await
             .map_err(|e| {
                 public_error_from_diesel_pool(
                     e,
-                    ErrorHandler::Conflict(ResourceType::Vpc, name.as_str()),
+                    ErrorHandler::Conflict(ResourceType::IpPool, name.as_str()),
                 )
-            })?;
-        Ok(vpc)
+            })
     }
 
-    pub async fn project_update_vpc(
+    /// Fetches an IP pool by name without checking that the caller may see
+    /// it
+    ///
+    /// This is used to allocate Floating IPs, which any user who may create
+    /// resources in a Project may do from any pool.
+    // TODO-security Pools should be visible only to the Projects allowed to
+    // allocate from them.
+    pub async fn ip_pool_lookup_noauthz(
         &self,
-        vpc_id: &Uuid,
-        updates: VpcUpdate,
-    ) -> Result<(), Error> {
-        use db::schema::vpc::dsl;
-
-        diesel::update(dsl::vpc)
+        name: &Name,
+    ) -> LookupResult<IpPool> {
+        use db::schema::ip_pool::dsl;
+        dsl::ip_pool
             .filter(dsl::time_deleted.is_null())
-            .filter(dsl::id.eq(*vpc_id))
-            .set(updates)
-            .execute_async(self.pool())
+            .filter(dsl::name.eq(name.clone()))
+            .select(IpPool::as_select())
+            .first_async::<IpPool>(self.pool())
             .await
             .map_err(|e| {
                 public_error_from_diesel_pool(
                     e,
                     ErrorHandler::NotFoundByLookup(
-                        ResourceType::Vpc,
-                        LookupType::ById(*vpc_id),
+                        ResourceType::IpPool,
+                        LookupType::ByName(name.as_str().to_owned()),
                     ),
                 )
-            })?;
-        Ok(())
+            })
     }
 
-    pub async fn vpc_fetch_by_name(
+    pub async fn ip_pool_fetch(
         &self,
-        project_id: &Uuid,
-        vpc_name: &Name,
-    ) -> LookupResult<Vpc> {
-        use db::schema::vpc::dsl;
-
-        dsl::vpc
-            .filter(dsl::time_deleted.is_null())
-            .filter(dsl::project_id.eq(*project_id))
-            .filter(dsl::name.eq(vpc_name.clone()))
-            .select(Vpc::as_select())
-            .get_result_async(self.pool())
-            .await
-            .map_err(|e| {
-                public_error_from_diesel_pool(
-                    e,
-                    ErrorHandler::NotFoundByLookup(
-                        ResourceType::Vpc,
-                        LookupType::ByName(vpc_name.as_str().to_owned()),
-                    ),
-                )
-            })
+        opctx: &OpContext,
+        name: &Name,
+    ) -> LookupResult<IpPool> {
+        opctx
+            .authorize(
+                authz::Action::Read,
+                &authz::FLEET.child_generic(
+                    ResourceType::IpPool,
+                    LookupType::from(&name.0),
+                ),
+            )
+            .await?;
+        self.ip_pool_lookup_noauthz(name).await
     }
 
-    pub async fn project_delete_vpc(&self, vpc_id: &Uuid) -> DeleteResult {
-        use db::schema::vpc::dsl;
+    /// Deletes an IP pool and its ranges, provided that no Floating IPs are
+    /// still allocated from it
+    pub async fn ip_pool_delete(
+        &self,
+        opctx: &OpContext,
+        pool_id: Uuid,
+    ) -> DeleteResult {
+        use db::schema::floating_ip::dsl as floating_ip_dsl;
+        use db::schema::ip_pool::dsl;
+        use db::schema::ip_pool_range::dsl as range_dsl;
 
-        // Note that we don't ensure the firewall rules are empty here, because
-        // we allow deleting VPCs with firewall rules present. Inserting new
-        // rules is serialized with respect to the deletion by the row lock
-        // associated with the VPC row, since we use the collection insert CTE
-        // pattern to add firewall rules.
+        opctx
+            .authorize(
+                authz::Action::Delete,
+                &authz::FLEET.child_generic(
+                    ResourceType::IpPool,
+                    LookupType::ById(pool_id),
+                ),
+            )
+            .await?;
 
+        type TxnError = TransactionError<Error>;
         let now = Utc::now();
-        diesel::update(dsl::vpc)
-            .filter(dsl::time_deleted.is_null())
-            .filter(dsl::id.eq(*vpc_id))
-            .set(dsl::time_deleted.eq(now))
-            .returning(Vpc::as_returning())
-            .get_result_async(self.pool())
+        self.pool_authorized(opctx)
+            .await?
+            .transaction(move |conn| {
+                let allocated = floating_ip_dsl::floating_ip
+                    .filter(floating_ip_dsl::time_deleted.is_null())
+                    .filter(floating_ip_dsl::ip_pool_id.eq(pool_id))
+                    .select(floating_ip_dsl::id)
+                    .limit(1)
+                    .load::<Uuid>(conn)?;
+                if !allocated.is_empty() {
+                    return Err(TxnError::CustomError(Error::InvalidRequest {
+                        message: "IP pool to be deleted has floating IPs \
+                            allocated from it"
+                            .to_string(),
+                    }));
+                }
+                let updated_rows = diesel::update(dsl::ip_pool)
+                    .filter(dsl::time_deleted.is_null())
+                    .filter(dsl::id.eq(pool_id))
+                    .set(dsl::time_deleted.eq(now))
+                    .execute(conn)?;
+                if updated_rows == 0 {
+                    return Err(TxnError::CustomError(Error::not_found_by_id(
+                        ResourceType::IpPool,
+                        &pool_id,
+                    )));
+                }
+                diesel::update(range_dsl::ip_pool_range)
+                    .filter(range_dsl::time_deleted.is_null())
+                    .filter(range_dsl::ip_pool_id.eq(pool_id))
+                    .set(range_dsl::time_deleted.eq(now))
+                    .execute(conn)?;
+                Ok(())
+            })
             .await
-            .map_err(|e| {
-                public_error_from_diesel_pool(
-                    e,
-                    ErrorHandler::NotFoundByLookup(
-                        ResourceType::Vpc,
-                        LookupType::ById(*vpc_id),
-                    ),
-                )
-            })?;
-        Ok(())
+            .map_err(|e| match e {
+                TxnError::CustomError(e) => e,
+                TxnError::Pool(e) => {
+                    public_error_from_diesel_pool(e, ErrorHandler::Server)
+                }
+            })
     }
 
-    pub async fn vpc_list_firewall_rules(
+    pub async fn ip_pool_list_ranges(
         &self,
-        vpc_id: &Uuid,
-    ) -> ListResultVec<VpcFirewallRule> {
-        use db::schema::vpc_firewall_rule::dsl;
-
-        dsl::vpc_firewall_rule
+        opctx: &OpContext,
+        pool: &IpPool,
+        pagparams: &DataPageParams<'_, Uuid>,
+    ) -> ListResultVec<IpPoolRange> {
+        use db::schema::ip_pool_range::dsl;
+        opctx
+            .authorize(
+                authz::Action::ListChildren,
+                &authz::FLEET.child_generic(
+                    ResourceType::IpPool,
+                    LookupType::ById(pool.id()),
+                ),
+            )
+            .await?;
+        paginated(dsl::ip_pool_range, dsl::id, pagparams)
             .filter(dsl::time_deleted.is_null())
-            .filter(dsl::vpc_id.eq(*vpc_id))
-            .order(dsl::name.asc())
-            .select(VpcFirewallRule::as_select())
-            .load_async(self.pool())
+            .filter(dsl::ip_pool_id.eq(pool.id()))
+            .select(IpPoolRange::as_select())
+            .load_async::<IpPoolRange>(self.pool_authorized(opctx).await?)
             .await
             .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
     }
 
-    pub async fn vpc_delete_all_firewall_rules(
+    /// Adds a range of addresses to an IP pool, provided that it doesn't
+    /// overlap any existing range in any pool
+    pub async fn ip_pool_add_range(
         &self,
-        vpc_id: &Uuid,
+        opctx: &OpContext,
+        range: IpPoolRange,
+    ) -> CreateResult<IpPoolRange> {
+        use db::schema::ip_pool_range::dsl;
+        opctx
+            .authorize(
+                authz::Action::Modify,
+                &authz::FLEET.child_generic(
+                    ResourceType::IpPool,
+                    LookupType::ById(range.ip_pool_id),
+                ),
+            )
+            .await?;
+        let range_id = range.id.to_string();
+        diesel::insert_into(dsl::ip_pool_range)
+            .values(FilterConflictingIpPoolRangesQuery(range))
+            .returning(IpPoolRange::as_returning())
+            .get_result_async(self.pool_authorized(opctx).await?)
+            .await
+            .map_err(|e| {
+                if let PoolError::Connection(ConnectionError::Query(
+                    diesel::result::Error::NotFound,
+                )) = e
+                {
+                    Error::InvalidRequest {
+                        message: "IP pool range overlaps an existing range"
+                            .to_string(),
+                    }
+                } else {
+                    public_error_from_diesel_pool(
+                        e,
+                        ErrorHandler::Conflict(
+                            ResourceType::IpPoolRange,
+                            range_id.as_str(),
+                        ),
+                    )
+                }
+            })
+    }
+
+    /// Removes a range from an IP pool, provided that no Floating IPs are
+    /// still allocated from it
+    pub async fn ip_pool_delete_range(
+        &self,
+        opctx: &OpContext,
+        pool: &IpPool,
+        range_id: Uuid,
     ) -> DeleteResult {
-        use db::schema::vpc_firewall_rule::dsl;
+        use db::schema::floating_ip::dsl as floating_ip_dsl;
+        use db::schema::ip_pool_range::dsl;
+
+        opctx
+            .authorize(
+                authz::Action::Modify,
+                &authz::FLEET.child_generic(
+                    ResourceType::IpPool,
+                    LookupType::ById(pool.id()),
+                ),
+            )
+            .await?;
+
+        // TODO-correctness An address could be allocated from the range
+        // between this check and the deletion below.
+        let allocated = diesel_pool_result_optional(
+            floating_ip_dsl::floating_ip
+                .filter(floating_ip_dsl::time_deleted.is_null())
+                .filter(floating_ip_dsl::ip_pool_range_id.eq(range_id))
+                .select(floating_ip_dsl::id)
+                .limit(1)
+                .first_async::<Uuid>(self.pool_authorized(opctx).await?)
+                .await,
+        )
+        .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))?;
+        if allocated.is_some() {
+            return Err(Error::InvalidRequest {
+                message: "IP pool range to be deleted has floating IPs \
+                    allocated from it"
+                    .to_string(),
+            });
+        }
 
         let now = Utc::now();
-        // TODO-performance: Paginate this update to avoid long queries
-        diesel::update(dsl::vpc_firewall_rule)
+        diesel::update(dsl::ip_pool_range)
             .filter(dsl::time_deleted.is_null())
-            .filter(dsl::vpc_id.eq(*vpc_id))
+            .filter(dsl::ip_pool_id.eq(pool.id()))
+            .filter(dsl::id.eq(range_id))
             .set(dsl::time_deleted.eq(now))
-            .execute_async(self.pool())
+            .returning(IpPoolRange::as_returning())
+            .get_result_async(self.pool_authorized(opctx).await?)
             .await
             .map_err(|e| {
                 public_error_from_diesel_pool(
                     e,
                     ErrorHandler::NotFoundByLookup(
-                        ResourceType::Vpc,
-                        LookupType::ById(*vpc_id),
+                        ResourceType::IpPoolRange,
+                        LookupType::ById(range_id),
                     ),
                 )
             })?;
         Ok(())
     }
 
-    /// Replace all firewall rules with the given rules
-    pub async fn vpc_update_firewall_rules(
-        &self,
-        vpc_id: &Uuid,
-        rules: Vec<VpcFirewallRule>,
-    ) -> UpdateResult<Vec<VpcFirewallRule>> {
-        use db::schema::vpc_firewall_rule::dsl;
+    /*
+     * Floating IPs
+     */
 
-        let now = Utc::now();
-        let delete_old_query = diesel::update(dsl::vpc_firewall_rule)
+    /// Fetches a Floating IP from the database and returns both the database
+    /// row and an [`authz::FloatingIp`] for doing authz checks
+    ///
+    /// See [`DataStore::organization_lookup_noauthz()`] for intended use cases
+    /// and caveats.
+    // TODO-security See the note on organization_lookup_noauthz().
+    async fn floating_ip_lookup_noauthz(
+        &self,
+        authz_project: &authz::Project,
+        floating_ip_name: &Name,
+    ) -> LookupResult<(authz::FloatingIp, FloatingIp)> {
+        use db::schema::floating_ip::dsl;
+        dsl::floating_ip
             .filter(dsl::time_deleted.is_null())
-            .filter(dsl::vpc_id.eq(*vpc_id))
-            .set(dsl::time_deleted.eq(now));
-
-        let insert_new_query = Vpc::insert_resource(
-            *vpc_id,
-            diesel::insert_into(dsl::vpc_firewall_rule).values(rules),
-        );
-
-        #[derive(Debug)]
-        enum FirewallUpdateError {
-            CollectionNotFound,
-        }
-        type TxnError = TransactionError<FirewallUpdateError>;
-
-        // TODO-scalability: Ideally this would be a CTE so we don't need to
-        // hold a transaction open across multiple roundtrips from the database,
-        // but for now we're using a transaction due to the severely decreased
-        // legibility of CTEs via diesel right now.
-        self.pool()
-            .transaction(move |conn| {
-                delete_old_query.execute(conn)?;
-
-                // The generation count update on the vpc table row will take a
-                // write lock on the row, ensuring that the vpc was not deleted
-                // concurently.
-                insert_new_query.insert_and_get_results(conn).map_err(|e| {
-                    match e {
-                        SyncInsertError::CollectionNotFound => {
-                            TxnError::CustomError(
-                                FirewallUpdateError::CollectionNotFound,
-                            )
-                        }
-                        SyncInsertError::DatabaseError(e) => e.into(),
-                    }
-                })
-            })
+            .filter(dsl::project_id.eq(authz_project.id()))
+            .filter(dsl::name.eq(floating_ip_name.clone()))
+            .select(FloatingIp::as_select())
+            .first_async(self.pool())
             .await
-            .map_err(|e| match e {
-                TxnError::CustomError(
-                    FirewallUpdateError::CollectionNotFound,
-                ) => Error::not_found_by_id(ResourceType::Vpc, vpc_id),
-                TxnError::Pool(e) => public_error_from_diesel_pool(
+            .map_err(|e| {
+                public_error_from_diesel_pool(
                     e,
                     ErrorHandler::NotFoundByLookup(
-                        ResourceType::VpcFirewallRule,
-                        LookupType::ById(*vpc_id),
+                        ResourceType::FloatingIp,
+                        LookupType::ByName(
+                            floating_ip_name.as_str().to_owned(),
+                        ),
                     ),
-                ),
+                )
+            })
+            .map(|f| {
+                (
+                    authz_project.child_generic(
+                        ResourceType::FloatingIp,
+                        f.id(),
+                        LookupType::from(&floating_ip_name.0),
+                    ),
+                    f,
+                )
             })
     }
 
-    pub async fn vpc_list_subnets(
+    /// Lookup a Floating IP by name and return the full database record,
+    /// along with an [`authz::FloatingIp`] for subsequent authorization checks
+    pub async fn floating_ip_fetch(
         &self,
-        vpc_id: &Uuid,
+        opctx: &OpContext,
+        authz_project: &authz::Project,
+        name: &Name,
+    ) -> LookupResult<(authz::FloatingIp, FloatingIp)> {
+        let (authz_floating_ip, db_floating_ip) =
+            self.floating_ip_lookup_noauthz(authz_project, name).await?;
+        opctx.authorize(authz::Action::Read, &authz_floating_ip).await?;
+        Ok((authz_floating_ip, db_floating_ip))
+    }
+
+    pub async fn project_list_floating_ips(
+        &self,
+        opctx: &OpContext,
+        authz_project: &authz::Project,
         pagparams: &DataPageParams<'_, Name>,
-    ) -> ListResultVec<VpcSubnet> {
-        use db::schema::vpc_subnet::dsl;
+    ) -> ListResultVec<FloatingIp> {
+        use db::schema::floating_ip::dsl;
 
-        paginated(dsl::vpc_subnet, dsl::name, &pagparams)
+        opctx.authorize(authz::Action::ListChildren, authz_project).await?;
+
+        paginated(dsl::floating_ip, dsl::name, pagparams)
             .filter(dsl::time_deleted.is_null())
-            .filter(dsl::vpc_id.eq(*vpc_id))
-            .select(VpcSubnet::as_select())
-            .load_async(self.pool())
+            .filter(dsl::project_id.eq(authz_project.id()))
+            .select(FloatingIp::as_select())
+            .load_async::<FloatingIp>(self.pool_authorized(opctx).await?)
             .await
             .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
     }
 
-    pub async fn vpc_subnet_fetch_by_name(
+    /// Allocates an address from a Floating IP's pool and inserts it
+    ///
+    /// Concurrent allocations from the same pool may choose the same address,
+    /// in which case all but one of them fail the uniqueness check on live
+    /// addresses.  Those are retried a few times before giving up.
+    pub async fn project_create_floating_ip(
         &self,
-        vpc_id: &Uuid,
-        subnet_name: &Name,
-    ) -> LookupResult<VpcSubnet> {
-        use db::schema::vpc_subnet::dsl;
+        floating_ip: IncompleteFloatingIp,
+    ) -> CreateResult<FloatingIp> {
+        use db::schema::floating_ip::dsl;
+
+        let name = floating_ip.identity.name.clone();
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            let allocation_query = AllocateFloatingIpQuery {
+                floating_ip: floating_ip.clone(),
+                now: Utc::now(),
+            };
+            let result = diesel::insert_into(dsl::floating_ip)
+                .values(allocation_query)
+                .returning(FloatingIp::as_returning())
+                .get_result_async(self.pool())
+                .await;
+            let error = match result {
+                Ok(floating_ip) => return Ok(floating_ip),
+                Err(PoolError::Connection(ConnectionError::Query(
+                    diesel::result::Error::NotFound,
+                ))) => {
+                    return Err(Error::InvalidRequest {
+                        message: "no available addresses in IP pool"
+                            .to_string(),
+                    });
+                }
+                Err(error) => error,
+            };
+
+            // A unique violation is either a name conflict or a lost race for
+            // the chosen address.  Only the latter is worth retrying.
+            let is_unique_violation = matches!(
+                error,
+                PoolError::Connection(ConnectionError::Query(
+                    diesel::result::Error::DatabaseError(
+                        diesel::result::DatabaseErrorKind::UniqueViolation,
+                        _,
+                    ),
+                ))
+            );
+            let retry = is_unique_violation
+                && attempts < FLOATING_IP_ALLOCATION_ATTEMPTS
+                && diesel_pool_result_optional(
+                    dsl::floating_ip
+                        .filter(dsl::time_deleted.is_null())
+                        .filter(dsl::project_id.eq(floating_ip.project_id))
+                        .filter(dsl::name.eq(name.clone()))
+                        .select(dsl::id)
+                        .first_async::<Uuid>(self.pool())
+                        .await,
+                )
+                .map_err(|e| {
+                    public_error_from_diesel_pool(e, ErrorHandler::Server)
+                })?
+                .is_none();
+            if !retry {
+                return Err(public_error_from_diesel_pool(
+                    error,
+                    ErrorHandler::Conflict(
+                        ResourceType::FloatingIp,
+                        name.as_str(),
+                    ),
+                ));
+            }
+        }
+    }
 
-        dsl::vpc_subnet
+    /// Deletes a Floating IP, returning its address to its pool
+    pub async fn project_delete_floating_ip(
+        &self,
+        opctx: &OpContext,
+        authz_floating_ip: &authz::FloatingIp,
+    ) -> DeleteResult {
+        use db::schema::floating_ip::dsl;
+
+        opctx.authorize(authz::Action::Delete, authz_floating_ip).await?;
+
+        let now = Utc::now();
+        diesel::update(dsl::floating_ip)
             .filter(dsl::time_deleted.is_null())
-            .filter(dsl::vpc_id.eq(*vpc_id))
-            .filter(dsl::name.eq(subnet_name.clone()))
-            .select(VpcSubnet::as_select())
-            .get_result_async(self.pool())
+            .filter(dsl::id.eq(authz_floating_ip.id()))
+            .set(dsl::time_deleted.eq(now))
+            .returning(FloatingIp::as_returning())
+            .get_result_async(self.pool_authorized(opctx).await?)
             .await
             .map_err(|e| {
                 public_error_from_diesel_pool(
                     e,
-                    ErrorHandler::NotFoundByLookup(
-                        ResourceType::VpcSubnet,
-                        LookupType::ByName(subnet_name.as_str().to_owned()),
-                    ),
+                    ErrorHandler::NotFoundByResource(authz_floating_ip),
                 )
-            })
+            })?;
+        Ok(())
     }
 
-    /// Insert a VPC Subnet, checking for unique IP address ranges.
-    pub async fn vpc_create_subnet(
+    /// Attaches a Floating IP to a network interface, provided that neither
+    /// is already attached to another
+    pub async fn floating_ip_attach(
         &self,
-        subnet: VpcSubnet,
-    ) -> Result<VpcSubnet, SubnetError> {
-        use db::schema::vpc_subnet::dsl;
-        let name = subnet.name().clone();
-        let values = FilterConflictingVpcSubnetRangesQuery(subnet);
-        diesel::insert_into(dsl::vpc_subnet)
-            .values(values)
-            .returning(VpcSubnet::as_returning())
-            .get_result_async(self.pool())
-            .await
-            .map_err(|err| {
-                if let PoolError::Connection(ConnectionError::Query(
-                    diesel::result::Error::NotFound,
-                )) = err
-                {
-                    SubnetError::OverlappingIpRange
-                } else {
-                    SubnetError::External(public_error_from_diesel_pool(
-                        err,
-                        ErrorHandler::Conflict(
-                            ResourceType::VpcSubnet,
-                            name.to_string().as_str(),
-                        ),
-                    ))
-                }
-            })
+        opctx: &OpContext,
+        authz_floating_ip: &authz::FloatingIp,
+        interface: &NetworkInterface,
+    ) -> UpdateResult<FloatingIp> {
+        use db::schema::floating_ip::dsl;
+
+        opctx.authorize(authz::Action::Modify, authz_floating_ip).await?;
+
+        let now = Utc::now();
+        let updated = diesel_pool_result_optional(
+            diesel::update(dsl::floating_ip)
+                .filter(dsl::time_deleted.is_null())
+                .filter(dsl::id.eq(authz_floating_ip.id()))
+                .filter(dsl::network_interface_id.is_null())
+                .set((
+                    dsl::instance_id.eq(Some(interface.instance_id)),
+                    dsl::network_interface_id.eq(Some(interface.id())),
+                    dsl::time_modified.eq(now),
+                ))
+                .returning(FloatingIp::as_returning())
+                .get_result_async(self.pool_authorized(opctx).await?)
+                .await,
+        )
+        .map_err(|e| match e {
+            PoolError::Connection(ConnectionError::Query(
+                diesel::result::Error::DatabaseError(
+                    diesel::result::DatabaseErrorKind::UniqueViolation,
+                    _,
+                ),
+            )) => Error::InvalidRequest {
+                message: "instance already has a floating IP attached"
+                    .to_string(),
+            },
+            e => public_error_from_diesel_pool(
+                e,
+                ErrorHandler::NotFoundByResource(authz_floating_ip),
+            ),
+        })?;
+        updated.ok_or_else(|| Error::InvalidRequest {
+            message: "floating IP is already attached".to_string(),
+        })
     }
 
-    pub async fn vpc_delete_subnet(&self, subnet_id: &Uuid) -> DeleteResult {
-        use db::schema::vpc_subnet::dsl;
+    /// Detaches a Floating IP from whatever network interface it's attached
+    /// to
+    pub async fn floating_ip_detach(
+        &self,
+        opctx: &OpContext,
+        authz_floating_ip: &authz::FloatingIp,
+    ) -> UpdateResult<FloatingIp> {
+        use db::schema::floating_ip::dsl;
+
+        opctx.authorize(authz::Action::Modify, authz_floating_ip).await?;
 
         let now = Utc::now();
-        diesel::update(dsl::vpc_subnet)
+        diesel::update(dsl::floating_ip)
             .filter(dsl::time_deleted.is_null())
-            .filter(dsl::id.eq(*subnet_id))
-            .set(dsl::time_deleted.eq(now))
-            .returning(VpcSubnet::as_returning())
-            .get_result_async(self.pool())
+            .filter(dsl::id.eq(authz_floating_ip.id()))
+            .set((
+                dsl::instance_id.eq(None::<Uuid>),
+                dsl::network_interface_id.eq(None::<Uuid>),
+                dsl::time_modified.eq(now),
+            ))
+            .returning(FloatingIp::as_returning())
+            .get_result_async(self.pool_authorized(opctx).await?)
             .await
             .map_err(|e| {
                 public_error_from_diesel_pool(
                     e,
-                    ErrorHandler::NotFoundByLookup(
-                        ResourceType::VpcSubnet,
-                        LookupType::ById(*subnet_id),
-                    ),
+                    ErrorHandler::NotFoundByResource(authz_floating_ip),
                 )
+            })
+    }
+
+    /// Detaches any Floating IP attached to a network interface
+    pub async fn network_interface_detach_floating_ips(
+        &self,
+        network_interface_id: &Uuid,
+    ) -> Result<(), Error> {
+        use db::schema::floating_ip::dsl;
+
+        let now = Utc::now();
+        diesel::update(dsl::floating_ip)
+            .filter(dsl::time_deleted.is_null())
+            .filter(dsl::network_interface_id.eq(*network_interface_id))
+            .set((
+                dsl::instance_id.eq(None::<Uuid>),
+                dsl::network_interface_id.eq(None::<Uuid>),
+                dsl::time_modified.eq(now),
+            ))
+            .execute_async(self.pool())
+            .await
+            .map_err(|e| {
+                public_error_from_diesel_pool(e, ErrorHandler::Server)
+            })?;
+        Ok(())
+    }
+
+    /// Deletes the Floating IPs attached to an Instance, returning their
+    /// addresses to their pools
+    pub async fn instance_release_floating_ips(
+        &self,
+        instance_id: &Uuid,
+    ) -> DeleteResult {
+        use db::schema::floating_ip::dsl;
+
+        let now = Utc::now();
+        diesel::update(dsl::floating_ip)
+            .filter(dsl::time_deleted.is_null())
+            .filter(dsl::instance_id.eq(*instance_id))
+            .set(dsl::time_deleted.eq(now))
+            .execute_async(self.pool())
+            .await
+            .map_err(|e| {
+                public_error_from_diesel_pool(e, ErrorHandler::Server)
             })?;
         Ok(())
     }
 
-    pub async fn vpc_update_subnet(
+    /// Fetches the Floating IPs attached to any of the given network
+    /// interfaces
+    pub async fn network_interfaces_list_floating_ips(
+        &self,
+        network_interface_ids: Vec<Uuid>,
+    ) -> ListResultVec<FloatingIp> {
+        use db::schema::floating_ip::dsl;
+
+        dsl::floating_ip
+            .filter(dsl::time_deleted.is_null())
+            .filter(dsl::network_interface_id.eq_any(network_interface_ids))
+            .select(FloatingIp::as_select())
+            .load_async::<FloatingIp>(self.pool())
+            .await
+            .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
+    }
+
+    // Create a record for a new Oximeter instance
+    pub async fn oximeter_create(
+        &self,
+        info: &OximeterInfo,
+    ) -> Result<(), Error> {
+        use db::schema::oximeter::dsl;
+
+        // If we get a conflict on the Oximeter ID, this means that collector instance was
+        // previously registered, and it's re-registering due to something like a service restart.
+        // In this case, we update the time modified and the service address, rather than
+        // propagating a constraint violation to the caller.
+        diesel::insert_into(dsl::oximeter)
+            .values(*info)
+            .on_conflict(dsl::id)
+            .do_update()
+            .set((
+                dsl::time_modified.eq(Utc::now()),
+                dsl::ip.eq(info.ip),
+                dsl::port.eq(info.port),
+            ))
+            .execute_async(self.pool())
+            .await
+            .map_err(|e| {
+                public_error_from_diesel_pool(
+                    e,
+                    ErrorHandler::Conflict(
+                        ResourceType::Oximeter,
+                        "Oximeter Info",
+                    ),
+                )
+            })?;
+        Ok(())
+    }
+
+    // Fetch a record for an Oximeter instance, by its ID.
+    pub async fn oximeter_fetch(
+        &self,
+        id: Uuid,
+    ) -> Result<OximeterInfo, Error> {
+        use db::schema::oximeter::dsl;
+        dsl::oximeter
+            .filter(dsl::id.eq(id))
+            .first_async::<OximeterInfo>(self.pool())
+            .await
+            .map_err(|e| {
+                public_error_from_diesel_pool(
+                    e,
+                    ErrorHandler::NotFoundByLookup(
+                        ResourceType::Oximeter,
+                        LookupType::ById(id),
+                    ),
+                )
+            })
+    }
+
+    // List the oximeter collector instances
+    pub async fn oximeter_list(
+        &self,
+        page_params: &DataPageParams<'_, Uuid>,
+    ) -> ListResultVec<OximeterInfo> {
+        use db::schema::oximeter::dsl;
+        paginated(dsl::oximeter, dsl::id, page_params)
+            .load_async::<OximeterInfo>(self.pool())
+            .await
+            .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
+    }
+
+    /// Fetches every registered Oximeter collector
+    ///
+    /// TODO-scalability This isn't paginated.  It's used to build the internal
+    /// DNS zone, which needs every collector at once.
+    pub async fn oximeter_list_all(&self) -> ListResultVec<OximeterInfo> {
+        use db::schema::oximeter::dsl;
+        dsl::oximeter
+            .order(dsl::id.asc())
+            .load_async::<OximeterInfo>(self.pool())
+            .await
+            .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
+    }
+
+    /// Records that a Nexus server is running, serving its internal API at
+    /// `info`'s address
+    pub async fn nexus_upsert(&self, info: &NexusInfo) -> Result<(), Error> {
+        use db::schema::nexus::dsl;
+
+        // As with Oximeter, a conflict on the ID means that this Nexus
+        // restarted, possibly at a new address.
+        diesel::insert_into(dsl::nexus)
+            .values(*info)
+            .on_conflict(dsl::id)
+            .do_update()
+            .set((
+                dsl::time_modified.eq(Utc::now()),
+                dsl::ip.eq(info.ip),
+                dsl::port.eq(info.port),
+            ))
+            .execute_async(self.pool())
+            .await
+            .map_err(|e| {
+                public_error_from_diesel_pool(e, ErrorHandler::Server)
+            })?;
+        Ok(())
+    }
+
+    /// Fetches every registered Nexus server
+    ///
+    /// TODO-scalability This isn't paginated.  It's used to build the internal
+    /// DNS zone, which needs every server at once.
+    pub async fn nexus_list_all(&self) -> ListResultVec<NexusInfo> {
+        use db::schema::nexus::dsl;
+        dsl::nexus
+            .order(dsl::id.asc())
+            .load_async::<NexusInfo>(self.pool())
+            .await
+            .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
+    }
+
+    /// Fetches every live dataset of the given kind
+    ///
+    /// TODO-scalability This isn't paginated.  It's used to build the internal
+    /// DNS zone, which needs every database dataset at once.
+    pub async fn dataset_list_all_by_kind(
+        &self,
+        kind: DatasetKind,
+    ) -> ListResultVec<Dataset> {
+        use db::schema::dataset::dsl;
+        dsl::dataset
+            .filter(dsl::time_deleted.is_null())
+            .filter(dsl::kind.eq(kind))
+            .order(dsl::id.asc())
+            .select(Dataset::as_select())
+            .load_async::<Dataset>(self.pool())
+            .await
+            .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
+    }
+
+    /// Fetches the hostname, VPC DNS name, project name, and IP address of
+    /// every live instance's network interfaces
+    ///
+    /// TODO-scalability This isn't paginated.  It's used to build the internal
+    /// DNS zone, which needs every interface at once.
+    pub async fn instance_list_all_dns_hosts(
+        &self,
+    ) -> ListResultVec<(String, Name, Name, ipnetwork::IpNetwork)> {
+        use db::schema::instance::dsl as instance_dsl;
+        use db::schema::network_interface::dsl as interface_dsl;
+        use db::schema::project::dsl as project_dsl;
+        use db::schema::vpc::dsl as vpc_dsl;
+
+        interface_dsl::network_interface
+            .inner_join(
+                instance_dsl::instance
+                    .on(interface_dsl::instance_id.eq(instance_dsl::id)),
+            )
+            .inner_join(vpc_dsl::vpc.on(interface_dsl::vpc_id.eq(vpc_dsl::id)))
+            .inner_join(
+                project_dsl::project
+                    .on(instance_dsl::project_id.eq(project_dsl::id)),
+            )
+            .filter(interface_dsl::time_deleted.is_null())
+            .filter(instance_dsl::time_deleted.is_null())
+            .filter(vpc_dsl::time_deleted.is_null())
+            .filter(project_dsl::time_deleted.is_null())
+            .order(interface_dsl::id.asc())
+            .select((
+                instance_dsl::hostname,
+                vpc_dsl::dns_name,
+                project_dsl::name,
+                interface_dsl::ip,
+            ))
+            .load_async(self.pool())
+            .await
+            .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
+    }
+
+    /// Allocates the next generation of the internal DNS zone `zone_name`
+    ///
+    /// Each call returns a newer generation than any returned before, so the
+    /// DNS servers can tell which of several updates is the latest.
+    pub async fn dns_zone_generation_next(
+        &self,
+        zone_name: &str,
+    ) -> Result<Generation, Error> {
+        use db::schema::dns_zone::dsl;
+
+        diesel::insert_into(dsl::dns_zone)
+            .values(DnsZone::new(zone_name))
+            .on_conflict(dsl::name)
+            .do_update()
+            .set((
+                dsl::time_modified.eq(Utc::now()),
+                dsl::generation.eq(dsl::generation + 1i64),
+            ))
+            .returning(dsl::generation)
+            .get_result_async::<Generation>(self.pool())
+            .await
+            .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
+    }
+
+    // Create a record for a new producer endpoint
+    pub async fn producer_endpoint_create(
+        &self,
+        producer: &ProducerEndpoint,
+    ) -> Result<(), Error> {
+        use db::schema::metric_producer::dsl;
+
+        // TODO: see https://github.com/oxidecomputer/omicron/issues/323
+        diesel::insert_into(dsl::metric_producer)
+            .values(producer.clone())
+            .on_conflict(dsl::id)
+            .do_update()
+            .set((
+                dsl::time_modified.eq(Utc::now()),
+                dsl::ip.eq(producer.ip),
+                dsl::port.eq(producer.port),
+                dsl::interval.eq(producer.interval),
+                dsl::base_route.eq(producer.base_route.clone()),
+            ))
+            .execute_async(self.pool())
+            .await
+            .map_err(|e| {
+                public_error_from_diesel_pool(
+                    e,
+                    ErrorHandler::Conflict(
+                        ResourceType::MetricProducer,
+                        "Producer Endpoint",
+                    ),
+                )
+            })?;
+        Ok(())
+    }
+
+    // List the producer endpoint records by the oximeter instance to which they're assigned.
+    pub async fn producers_list_by_oximeter_id(
+        &self,
+        oximeter_id: Uuid,
+        pagparams: &DataPageParams<'_, Uuid>,
+    ) -> ListResultVec<ProducerEndpoint> {
+        use db::schema::metric_producer::dsl;
+        paginated(dsl::metric_producer, dsl::id, &pagparams)
+            .filter(dsl::oximeter_id.eq(oximeter_id))
+            .order_by((dsl::oximeter_id, dsl::id))
+            .select(ProducerEndpoint::as_select())
+            .load_async(self.pool())
+            .await
+            .map_err(|e| {
+                public_error_from_diesel_pool(
+                    e,
+                    ErrorHandler::Conflict(
+                        ResourceType::MetricProducer,
+                        "By Oximeter ID",
+                    ),
+                )
+            })
+    }
+
+    // Sagas
+
+    pub async fn saga_create(
+        &self,
+        saga: &db::saga_types::Saga,
+    ) -> Result<(), Error> {
+        use db::schema::saga::dsl;
+
+        let name = saga.template_name.clone();
+        diesel::insert_into(dsl::saga)
+            .values(saga.clone())
+            .execute_async(self.pool())
+            .await
+            .map_err(|e| {
+                public_error_from_diesel_pool(
+                    e,
+                    ErrorHandler::Conflict(ResourceType::SagaDbg, &name),
+                )
+            })?;
+        Ok(())
+    }
+
+    pub async fn saga_create_event(
+        &self,
+        event: &db::saga_types::SagaNodeEvent,
+    ) -> Result<(), Error> {
+        use db::schema::saga_node_event::dsl;
+
+        // TODO-robustness This INSERT ought to be conditional on this SEC still
+        // owning this saga.
+        diesel::insert_into(dsl::saga_node_event)
+            .values(event.clone())
+            .execute_async(self.pool())
+            .await
+            .map_err(|e| {
+                public_error_from_diesel_pool(
+                    e,
+                    ErrorHandler::Conflict(ResourceType::SagaDbg, "Saga Event"),
+                )
+            })?;
+        Ok(())
+    }
+
+    pub async fn saga_update_state(
+        &self,
+        saga_id: steno::SagaId,
+        new_state: steno::SagaCachedState,
+        current_sec: db::saga_types::SecId,
+        current_adopt_generation: Generation,
+    ) -> Result<(), Error> {
+        use db::schema::saga::dsl;
+
+        let saga_id: db::saga_types::SagaId = saga_id.into();
+        let result = diesel::update(dsl::saga)
+            .filter(dsl::id.eq(saga_id))
+            .filter(dsl::current_sec.eq(current_sec))
+            .filter(dsl::adopt_generation.eq(current_adopt_generation))
+            .set(dsl::saga_state.eq(db::saga_types::SagaCachedState(new_state)))
+            .check_if_exists::<db::saga_types::Saga>(saga_id)
+            .execute_and_check(self.pool())
+            .await
+            .map_err(|e| {
+                public_error_from_diesel_pool(
+                    e,
+                    ErrorHandler::NotFoundByLookup(
+                        ResourceType::SagaDbg,
+                        LookupType::ById(saga_id.0.into()),
+                    ),
+                )
+            })?;
+
+        match result.status {
+            UpdateStatus::Updated => Ok(()),
+            UpdateStatus::NotUpdatedButExists => Err(Error::InvalidRequest {
+                message: format!(
+                    "failed to update saga {:?} with state {:?}: preconditions not met: \
+                    expected current_sec = {:?}, adopt_generation = {:?}, \
+                    but found current_sec = {:?}, adopt_generation = {:?}, state = {:?}",
+                    saga_id,
+                    new_state,
+                    current_sec,
+                    current_adopt_generation,
+                    result.found.current_sec,
+                    result.found.adopt_generation,
+                    result.found.saga_state,
+                )
+            }),
+        }
+    }
+
+    pub async fn saga_list_unfinished_by_id(
+        &self,
+        sec_id: &db::SecId,
+        pagparams: &DataPageParams<'_, Uuid>,
+    ) -> ListResultVec<db::saga_types::Saga> {
+        use db::schema::saga::dsl;
+        paginated(dsl::saga, dsl::id, &pagparams)
+            .filter(dsl::saga_state.ne(db::saga_types::SagaCachedState(
+                steno::SagaCachedState::Done,
+            )))
+            .filter(dsl::current_sec.eq(*sec_id))
+            .load_async(self.pool())
+            .await
+            .map_err(|e| {
+                public_error_from_diesel_pool(
+                    e,
+                    ErrorHandler::NotFoundByLookup(
+                        ResourceType::SagaDbg,
+                        LookupType::ById(sec_id.0),
+                    ),
+                )
+            })
+    }
+
+    pub async fn saga_node_event_list_by_id(
+        &self,
+        id: db::saga_types::SagaId,
+        pagparams: &DataPageParams<'_, Uuid>,
+    ) -> ListResultVec<steno::SagaNodeEvent> {
+        use db::schema::saga_node_event::dsl;
+        paginated(dsl::saga_node_event, dsl::saga_id, &pagparams)
+            .filter(dsl::saga_id.eq(id))
+            .load_async::<db::saga_types::SagaNodeEvent>(self.pool())
+            .await
+            .map_err(|e| {
+                public_error_from_diesel_pool(
+                    e,
+                    ErrorHandler::NotFoundByLookup(
+                        ResourceType::SagaDbg,
+                        LookupType::ById(id.0 .0),
+                    ),
+                )
+            })?
+            .into_iter()
+            .map(|db_event| steno::SagaNodeEvent::try_from(db_event))
+            .collect::<Result<_, Error>>()
+    }
+
+    // Operations
+
+    /// Records a new operation
+    ///
+    /// Like the saga records that operations are tied to, this is bookkeeping
+    /// for an action that the caller has already authorized.
+    pub async fn operation_create(
+        &self,
+        operation: Operation,
+    ) -> Result<(), Error> {
+        use db::schema::operation::dsl;
+        diesel::insert_into(dsl::operation)
+            .values(operation)
+            .execute_async(self.pool())
+            .await
+            .map(|_| ())
+            .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
+    }
+
+    /// Records the resource created by an operation
+    pub async fn operation_set_target(
+        &self,
+        operation_id: Uuid,
+        target_id: Uuid,
+    ) -> Result<(), Error> {
+        use db::schema::operation::dsl;
+        diesel::update(dsl::operation)
+            .filter(dsl::id.eq(operation_id))
+            .filter(dsl::target_id.is_null())
+            .set(dsl::target_id.eq(target_id))
+            .execute_async(self.pool())
+            .await
+            .map(|_| ())
+            .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
+    }
+
+    pub async fn operations_list(
+        &self,
+        opctx: &OpContext,
+        authz_project: &authz::Project,
+        pagparams: &DataPageParams<'_, Uuid>,
+    ) -> ListResultVec<OperationState> {
+        use db::schema::operation::dsl;
+        opctx.authorize(authz::Action::ListChildren, authz_project).await?;
+        let operations = paginated(dsl::operation, dsl::id, pagparams)
+            .filter(dsl::project_id.eq(authz_project.id()))
+            .select(Operation::as_select())
+            .load_async::<Operation>(self.pool_authorized(opctx).await?)
+            .await
+            .map_err(|e| {
+                public_error_from_diesel_pool(e, ErrorHandler::Server)
+            })?;
+        self.operation_states(operations).await
+    }
+
+    pub async fn operation_fetch(
+        &self,
+        opctx: &OpContext,
+        authz_project: &authz::Project,
+        operation_id: Uuid,
+    ) -> LookupResult<OperationState> {
+        let authz_operation = authz_project.child_generic(
+            ResourceType::Operation,
+            operation_id,
+            LookupType::ById(operation_id),
+        );
+        opctx.authorize(authz::Action::Read, &authz_operation).await?;
+        self.operation_fetch_no_auth(Some(authz_project.id()), operation_id)
+            .await
+    }
+
+    /// Fetches an operation without checking authorization
+    ///
+    /// If `project_id` is given, the operation must belong to that Project.
+    /// This is intended for returning an operation to the client that just
+    /// started it.
+    pub async fn operation_fetch_no_auth(
+        &self,
+        project_id: Option<Uuid>,
+        operation_id: Uuid,
+    ) -> LookupResult<OperationState> {
+        use db::schema::operation::dsl;
+        let mut query = dsl::operation
+            .filter(dsl::id.eq(operation_id))
+            .select(Operation::as_select())
+            .into_boxed();
+        if let Some(project_id) = project_id {
+            query = query.filter(dsl::project_id.eq(project_id));
+        }
+        let operation = query
+            .get_result_async::<Operation>(self.pool())
+            .await
+            .map_err(|e| {
+                public_error_from_diesel_pool(
+                    e,
+                    ErrorHandler::NotFoundByLookup(
+                        ResourceType::Operation,
+                        LookupType::ById(operation_id),
+                    ),
+                )
+            })?;
+        let mut states = self.operation_states(vec![operation]).await?;
+        states.pop().ok_or_else(|| {
+            Error::not_found_by_id(ResourceType::Operation, &operation_id)
+        })
+    }
+
+    /// Loads the state of the sagas carrying out `operations`
+    ///
+    /// Operations whose sagas can't be found are left out.  (This can only
+    /// happen if the saga's creation failed after the operation was recorded.)
+    async fn operation_states(
+        &self,
+        operations: Vec<Operation>,
+    ) -> ListResultVec<OperationState> {
+        let ids: Vec<Uuid> = operations.iter().map(|op| op.id).collect();
+        let sagas: BTreeMap<Uuid, (String, db::saga_types::SagaCachedState)> = {
+            use db::schema::saga::dsl;
+            dsl::saga
+                .filter(dsl::id.eq_any(ids.clone()))
+                .select((dsl::id, dsl::template_name, dsl::saga_state))
+                .load_async::<(Uuid, String, db::saga_types::SagaCachedState)>(
+                    self.pool(),
+                )
+                .await
+                .map_err(|e| {
+                    public_error_from_diesel_pool(e, ErrorHandler::Server)
+                })?
+                .into_iter()
+                .map(|(id, template_name, state)| (id, (template_name, state)))
+                .collect()
+        };
+        let mut events: BTreeMap<Uuid, Vec<db::saga_types::SagaNodeEvent>> = {
+            use db::schema::saga_node_event::dsl;
+            let mut events = BTreeMap::new();
+            for event in dsl::saga_node_event
+                .filter(dsl::saga_id.eq_any(ids))
+                .load_async::<db::saga_types::SagaNodeEvent>(self.pool())
+                .await
+                .map_err(|e| {
+                    public_error_from_diesel_pool(e, ErrorHandler::Server)
+                })?
+            {
+                events
+                    .entry(event.saga_id.0 .0)
+                    .or_insert_with(Vec::new)
+                    .push(event);
+            }
+            events
+        };
+        Ok(operations
+            .into_iter()
+            .filter_map(|operation| {
+                let (template_name, saga_state) =
+                    sagas.get(&operation.id)?.clone();
+                let events = events.remove(&operation.id).unwrap_or_default();
+                Some(OperationState {
+                    operation,
+                    template_name,
+                    saga_state,
+                    events,
+                })
+            })
+            .collect())
+    }
+
+    // Affinity groups
+
+    pub async fn project_list_affinity_groups(
+        &self,
+        project_id: &Uuid,
+        pagparams: &DataPageParams<'_, Name>,
+    ) -> ListResultVec<AffinityGroup> {
+        use db::schema::affinity_group::dsl;
+
+        paginated(dsl::affinity_group, dsl::name, &pagparams)
+            .filter(dsl::time_deleted.is_null())
+            .filter(dsl::project_id.eq(*project_id))
+            .select(AffinityGroup::as_select())
+            .load_async(self.pool())
+            .await
+            .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
+    }
+
+    pub async fn project_create_affinity_group(
+        &self,
+        group: AffinityGroup,
+    ) -> CreateResult<AffinityGroup> {
+        use db::schema::affinity_group::dsl;
+
+        let name = group.name().clone();
+        diesel::insert_into(dsl::affinity_group)
+            .values(group)
+            .returning(AffinityGroup::as_returning())
+            .get_result_async(self.pool())
+            .await
+            .map_err(|e| {
+                public_error_from_diesel_pool(
+                    e,
+                    ErrorHandler::Conflict(
+                        ResourceType::AffinityGroup,
+                        name.as_str(),
+                    ),
+                )
+            })
+    }
+
+    pub async fn affinity_group_fetch_by_name(
+        &self,
+        project_id: &Uuid,
+        group_name: &Name,
+    ) -> LookupResult<AffinityGroup> {
+        use db::schema::affinity_group::dsl;
+
+        dsl::affinity_group
+            .filter(dsl::time_deleted.is_null())
+            .filter(dsl::project_id.eq(*project_id))
+            .filter(dsl::name.eq(group_name.clone()))
+            .select(AffinityGroup::as_select())
+            .get_result_async(self.pool())
+            .await
+            .map_err(|e| {
+                public_error_from_diesel_pool(
+                    e,
+                    ErrorHandler::NotFoundByLookup(
+                        ResourceType::AffinityGroup,
+                        LookupType::ByName(group_name.as_str().to_owned()),
+                    ),
+                )
+            })
+    }
+
+    pub async fn affinity_group_fetch(
+        &self,
+        group_id: &Uuid,
+    ) -> LookupResult<AffinityGroup> {
+        use db::schema::affinity_group::dsl;
+
+        dsl::affinity_group
+            .filter(dsl::time_deleted.is_null())
+            .filter(dsl::id.eq(*group_id))
+            .select(AffinityGroup::as_select())
+            .get_result_async(self.pool())
+            .await
+            .map_err(|e| {
+                public_error_from_diesel_pool(
+                    e,
+                    ErrorHandler::NotFoundByLookup(
+                        ResourceType::AffinityGroup,
+                        LookupType::ById(*group_id),
+                    ),
+                )
+            })
+    }
+
+    pub async fn project_update_affinity_group(
+        &self,
+        group_id: &Uuid,
+        updates: AffinityGroupUpdate,
+        preconditions: &Preconditions,
+    ) -> UpdateResult<AffinityGroup> {
+        use db::schema::affinity_group::dsl;
+
+        let id = *group_id;
+        Self::update_with_preconditions(
+            self.pool(),
+            dsl::affinity_group,
+            id,
+            preconditions,
+            move |conn| {
+                Ok(diesel::update(dsl::affinity_group)
+                    .filter(dsl::time_deleted.is_null())
+                    .filter(dsl::id.eq(id))
+                    .set(updates)
+                    .returning(AffinityGroup::as_returning())
+                    .get_result(conn)?)
+            },
+            |e| {
+                public_error_from_diesel_pool(
+                    e,
+                    ErrorHandler::NotFoundByLookup(
+                        ResourceType::AffinityGroup,
+                        LookupType::ById(id),
+                    ),
+                )
+            },
+        )
+        .await
+    }
+
+    pub async fn project_delete_affinity_group(
+        &self,
+        group_id: &Uuid,
+    ) -> DeleteResult {
+        use db::schema::affinity_group::dsl;
+        use db::schema::instance;
+
+        // Make sure no Instances still belong to this group.
+        //
+        // TODO-correctness An Instance could join the group between this check
+        // and the deletion below.  The group should carry a generation number
+        // (like Organizations do) so that the two can be serialized.
+        let member_found = diesel_pool_result_optional(
+            instance::dsl::instance
+                .filter(instance::dsl::affinity_group_id.eq(*group_id))
+                .filter(instance::dsl::time_deleted.is_null())
+                .select(instance::dsl::id)
+                .limit(1)
+                .first_async::<Uuid>(self.pool())
+                .await,
+        )
+        .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))?;
+        if member_found.is_some() {
+            return Err(Error::InvalidRequest {
+                message: "affinity group to be deleted contains an instance"
+                    .to_string(),
+            });
+        }
+
+        let now = Utc::now();
+        diesel::update(dsl::affinity_group)
+            .filter(dsl::time_deleted.is_null())
+            .filter(dsl::id.eq(*group_id))
+            .set(dsl::time_deleted.eq(now))
+            .returning(AffinityGroup::as_returning())
+            .get_result_async(self.pool())
+            .await
+            .map_err(|e| {
+                public_error_from_diesel_pool(
+                    e,
+                    ErrorHandler::NotFoundByLookup(
+                        ResourceType::AffinityGroup,
+                        LookupType::ById(*group_id),
+                    ),
+                )
+            })?;
+        Ok(())
+    }
+
+    /// Returns the ids of the sleds running the existing members of an
+    /// affinity group, other than Instance `except_instance_id`
+    ///
+    /// This includes the sleds claimed by Instances still being created (see
+    /// [`DataStore::affinity_group_claim`]).
+    pub async fn affinity_group_member_sleds(
+        &self,
+        group_id: &Uuid,
+        except_instance_id: Option<Uuid>,
+    ) -> ListResultVec<Uuid> {
+        type TxnError = TransactionError<Error>;
+        let group_id = *group_id;
+        self.pool()
+            .transaction(move |conn| {
+                Ok(Self::affinity_group_member_sleds_sync(
+                    conn,
+                    group_id,
+                    except_instance_id,
+                )?
+                .into_iter()
+                .collect())
+            })
+            .await
+            .map_err(|e| match e {
+                TxnError::CustomError(e) => e,
+                TxnError::Pool(e) => {
+                    public_error_from_diesel_pool(e, ErrorHandler::Server)
+                }
+            })
+    }
+
+    // Returns the sleds of the Instances in affinity group `group_id`, and of
+    // the claims made by Instances still being created, other than Instance
+    // `except_instance_id`.
+    fn affinity_group_member_sleds_sync(
+        conn: &DbConnection,
+        group_id: Uuid,
+        except_instance_id: Option<Uuid>,
+    ) -> Result<BTreeSet<Uuid>, diesel::result::Error> {
+        use db::schema::affinity_group_claim::dsl as claim_dsl;
+        use db::schema::instance::dsl;
+
+        let destroyed = db::model::InstanceState::new(
+            api::external::InstanceState::Destroyed,
+        );
+        let mut instances = dsl::instance
+            .filter(dsl::time_deleted.is_null())
+            .filter(dsl::affinity_group_id.eq(group_id))
+            .filter(dsl::state.ne(destroyed))
+            .into_boxed();
+        let mut claims = claim_dsl::affinity_group_claim
+            .filter(claim_dsl::affinity_group_id.eq(group_id))
+            .into_boxed();
+        if let Some(instance_id) = except_instance_id {
+            instances = instances.filter(dsl::id.ne(instance_id));
+            claims = claims.filter(claim_dsl::instance_id.ne(instance_id));
+        }
+        let mut sleds: BTreeSet<Uuid> = instances
+            .select(dsl::active_server_id)
+            .load::<Uuid>(conn)?
+            .into_iter()
+            .collect();
+        sleds.extend(claims.select(claim_dsl::sled_id).load::<Uuid>(conn)?);
+        Ok(sleds)
+    }
+
+    /// Claims sled `claim.sled_id` for an Instance being created in affinity
+    /// group `claim.affinity_group_id`, replacing any earlier claim by the
+    /// same Instance
+    ///
+    /// The claim is only made if the sled still satisfies the group's policy
+    /// given its other members, including those claimed concurrently.  Returns
+    /// whether the claim was made; if not, the caller should choose another
+    /// sled.
+    pub async fn affinity_group_claim(
+        &self,
+        claim: AffinityGroupClaim,
+    ) -> Result<bool, Error> {
+        use db::schema::affinity_group::dsl as group_dsl;
+        use db::schema::affinity_group_claim::dsl;
+
+        type TxnError = TransactionError<Error>;
+        let group_id = claim.affinity_group_id;
+        self.pool()
+            .transaction(move |conn| {
+                // Locking the group's row serializes the claims made in it, so
+                // that each one sees the others.
+                let policy = group_dsl::affinity_group
+                    .filter(group_dsl::time_deleted.is_null())
+                    .filter(group_dsl::id.eq(group_id))
+                    .select(group_dsl::policy)
+                    .for_update()
+                    .get_result::<AffinityPolicy>(conn)?;
+                let member_sleds = Self::affinity_group_member_sleds_sync(
+                    conn,
+                    group_id,
+                    Some(claim.instance_id),
+                )?;
+                let allowed = match policy.0 {
+                    api::external::AffinityPolicy::Affinity => {
+                        member_sleds.iter().all(|id| *id == claim.sled_id)
+                    }
+                    api::external::AffinityPolicy::AntiAffinity => {
+                        !member_sleds.contains(&claim.sled_id)
+                    }
+                };
+                if allowed {
+                    diesel::delete(dsl::affinity_group_claim)
+                        .filter(dsl::instance_id.eq(claim.instance_id))
+                        .execute(conn)?;
+                    diesel::insert_into(dsl::affinity_group_claim)
+                        .values(claim)
+                        .execute(conn)?;
+                }
+                Ok(allowed)
+            })
+            .await
+            .map_err(|e| match e {
+                TxnError::CustomError(e) => e,
+                TxnError::Pool(e) => public_error_from_diesel_pool(
+                    e,
+                    ErrorHandler::NotFoundByLookup(
+                        ResourceType::AffinityGroup,
+                        LookupType::ById(group_id),
+                    ),
+                ),
+            })
+    }
+
+    /// Releases the sled claimed for Instance `instance_id` in its affinity
+    /// group, if any
+    pub async fn affinity_group_claim_release(
+        &self,
+        instance_id: &Uuid,
+    ) -> DeleteResult {
+        use db::schema::affinity_group_claim::dsl;
+
+        diesel::delete(dsl::affinity_group_claim)
+            .filter(dsl::instance_id.eq(*instance_id))
+            .execute_async(self.pool())
+            .await
+            .map_err(|e| {
+                public_error_from_diesel_pool(e, ErrorHandler::Server)
+            })?;
+        Ok(())
+    }
+
+    // VPCs
+
+    pub async fn project_list_vpcs(
+        &self,
+        project_id: &Uuid,
+        pagparams: &DataPageParams<'_, Name>,
+        label_selector: &LabelSelector,
+    ) -> ListResultVec<Vpc> {
+        use db::schema::vpc::dsl;
+
+        filter_by_labels(
+            paginated(dsl::vpc, dsl::name, &pagparams),
+            dsl::labels,
+            label_selector,
+        )
+        .filter(dsl::time_deleted.is_null())
+        .filter(dsl::project_id.eq(*project_id))
+        .select(Vpc::as_select())
+        .load_async(self.pool())
+        .await
+        .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
+    }
+
+    /// Fetches all of a Project's VPCs
+    ///
+    /// TODO-scalability This isn't paginated.  It's used to update firewall
+    /// rules, which may refer to any VPC in the Project.
+    pub async fn project_list_all_vpcs(
+        &self,
+        project_id: &Uuid,
+    ) -> ListResultVec<Vpc> {
+        use db::schema::vpc::dsl;
+
+        dsl::vpc
+            .filter(dsl::time_deleted.is_null())
+            .filter(dsl::project_id.eq(*project_id))
+            .order(dsl::name.asc())
+            .select(Vpc::as_select())
+            .load_async(self.pool())
+            .await
+            .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
+    }
+
+    pub async fn project_create_vpc(&self, vpc: Vpc) -> Result<Vpc, Error> {
+        use db::schema::vpc::dsl;
+
+        let name = vpc.name().clone();
+        let requested = ResourceUsage { vpcs: 1, ..Default::default() };
+        type TxnError = TransactionError<QuotaExceeded>;
+        self.pool()
+            .transaction(move |conn| {
+                quota_check(conn, vpc.project_id, vpc.id(), &requested)?;
+                Ok(diesel::insert_into(dsl::vpc)
+                    .values(vpc)
+                    .on_conflict(dsl::id)
+                    .do_nothing()
+                    .returning(Vpc::as_returning())
+                    .get_result(conn)?)
+            })
+            .await
+            .map_err(|e| match e {
+                TxnError::CustomError(e) => e.into(),
+                TxnError::Pool(e) => public_error_from_diesel_pool(
+                    e,
+                    ErrorHandler::Conflict(ResourceType::Vpc, name.as_str()),
+                ),
+            })
+    }
+
+    pub async fn project_update_vpc(
+        &self,
+        vpc_id: &Uuid,
+        updates: VpcUpdate,
+        preconditions: &Preconditions,
+    ) -> Result<(), Error> {
+        use db::schema::vpc::dsl;
+
+        let id = *vpc_id;
+        Self::update_with_preconditions(
+            self.pool(),
+            dsl::vpc,
+            id,
+            preconditions,
+            move |conn| {
+                diesel::update(dsl::vpc)
+                    .filter(dsl::id.eq(id))
+                    .set(updates)
+                    .execute(conn)?;
+                Ok(())
+            },
+            |e| {
+                public_error_from_diesel_pool(
+                    e,
+                    ErrorHandler::NotFoundByLookup(
+                        ResourceType::Vpc,
+                        LookupType::ById(id),
+                    ),
+                )
+            },
+        )
+        .await
+    }
+
+    pub async fn vpc_fetch_by_name(
+        &self,
+        project_id: &Uuid,
+        vpc_name: &Name,
+    ) -> LookupResult<Vpc> {
+        use db::schema::vpc::dsl;
+
+        dsl::vpc
+            .filter(dsl::time_deleted.is_null())
+            .filter(dsl::project_id.eq(*project_id))
+            .filter(dsl::name.eq(vpc_name.clone()))
+            .select(Vpc::as_select())
+            .get_result_async(self.pool())
+            .await
+            .map_err(|e| {
+                public_error_from_diesel_pool(
+                    e,
+                    ErrorHandler::NotFoundByLookup(
+                        ResourceType::Vpc,
+                        LookupType::ByName(vpc_name.as_str().to_owned()),
+                    ),
+                )
+            })
+    }
+
+    pub async fn vpc_fetch_by_id(&self, vpc_id: &Uuid) -> LookupResult<Vpc> {
+        use db::schema::vpc::dsl;
+
+        dsl::vpc
+            .filter(dsl::time_deleted.is_null())
+            .filter(dsl::id.eq(*vpc_id))
+            .select(Vpc::as_select())
+            .get_result_async(self.pool())
+            .await
+            .map_err(|e| {
+                public_error_from_diesel_pool(
+                    e,
+                    ErrorHandler::NotFoundByLookup(
+                        ResourceType::Vpc,
+                        LookupType::ById(*vpc_id),
+                    ),
+                )
+            })
+    }
+
+    pub async fn project_delete_vpc(
+        &self,
+        vpc_id: &Uuid,
+        preconditions: &Preconditions,
+    ) -> DeleteResult {
+        use db::schema::vpc::dsl;
+
+        // Note that we don't ensure the firewall rules are empty here, because
+        // we allow deleting VPCs with firewall rules present. Inserting new
+        // rules is serialized with respect to the deletion by the row lock
+        // associated with the VPC row, since we use the collection insert CTE
+        // pattern to add firewall rules.
+
+        let id = *vpc_id;
+        let now = Utc::now();
+        Self::update_with_preconditions(
+            self.pool(),
+            dsl::vpc,
+            id,
+            preconditions,
+            move |conn| {
+                diesel::update(dsl::vpc)
+                    .filter(dsl::id.eq(id))
+                    .set(dsl::time_deleted.eq(now))
+                    .execute(conn)?;
+                Ok(())
+            },
+            |e| {
+                public_error_from_diesel_pool(
+                    e,
+                    ErrorHandler::NotFoundByLookup(
+                        ResourceType::Vpc,
+                        LookupType::ById(id),
+                    ),
+                )
+            },
+        )
+        .await
+    }
+
+    pub async fn vpc_list_firewall_rules(
+        &self,
+        vpc_id: &Uuid,
+    ) -> ListResultVec<VpcFirewallRule> {
+        use db::schema::vpc_firewall_rule::dsl;
+
+        dsl::vpc_firewall_rule
+            .filter(dsl::time_deleted.is_null())
+            .filter(dsl::vpc_id.eq(*vpc_id))
+            .order(dsl::name.asc())
+            .select(VpcFirewallRule::as_select())
+            .load_async(self.pool())
+            .await
+            .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
+    }
+
+    pub async fn vpc_delete_all_firewall_rules(
+        &self,
+        vpc_id: &Uuid,
+    ) -> DeleteResult {
+        use db::schema::vpc_firewall_rule::dsl;
+
+        let now = Utc::now();
+        // TODO-performance: Paginate this update to avoid long queries
+        diesel::update(dsl::vpc_firewall_rule)
+            .filter(dsl::time_deleted.is_null())
+            .filter(dsl::vpc_id.eq(*vpc_id))
+            .set(dsl::time_deleted.eq(now))
+            .execute_async(self.pool())
+            .await
+            .map_err(|e| {
+                public_error_from_diesel_pool(
+                    e,
+                    ErrorHandler::NotFoundByLookup(
+                        ResourceType::Vpc,
+                        LookupType::ById(*vpc_id),
+                    ),
+                )
+            })?;
+        Ok(())
+    }
+
+    /// Replace all firewall rules with the given rules, provided that the VPC
+    /// satisfies `preconditions`
+    ///
+    /// The rules' entity tag is the VPC's, so this also bumps the VPC's
+    /// `time_modified`, which is returned along with the new rules.
+    pub async fn vpc_update_firewall_rules(
+        &self,
+        vpc_id: &Uuid,
+        rules: Vec<VpcFirewallRule>,
+        preconditions: &Preconditions,
+    ) -> UpdateResult<(Vec<VpcFirewallRule>, DateTime<Utc>)> {
+        use db::schema::vpc::dsl as vpc_dsl;
+        use db::schema::vpc_firewall_rule::dsl;
+
+        let id = *vpc_id;
+        let now = Utc::now();
+        let delete_old_query = diesel::update(dsl::vpc_firewall_rule)
+            .filter(dsl::time_deleted.is_null())
+            .filter(dsl::vpc_id.eq(id))
+            .set(dsl::time_deleted.eq(now));
+
+        let insert_new_query = Vpc::insert_resource(
+            id,
+            diesel::insert_into(dsl::vpc_firewall_rule).values(rules),
+        );
+
+        let touch_vpc_query = diesel::update(vpc_dsl::vpc)
+            .filter(vpc_dsl::id.eq(id))
+            .set(vpc_dsl::time_modified.eq(now))
+            .returning(vpc_dsl::time_modified);
+
+        // TODO-scalability: Ideally this would be a CTE so we don't need to
+        // hold a transaction open across multiple roundtrips from the database,
+        // but for now we're using a transaction due to the severely decreased
+        // legibility of CTEs via diesel right now.
+        Self::update_with_preconditions(
+            self.pool(),
+            vpc_dsl::vpc,
+            id,
+            preconditions,
+            move |conn| {
+                delete_old_query.execute(conn)?;
+
+                // The generation count update on the vpc table row will take a
+                // write lock on the row, ensuring that the vpc was not deleted
+                // concurently.
+                let rules = insert_new_query
+                    .insert_and_get_results(conn)
+                    .map_err(|e| match e {
+                        SyncInsertError::CollectionNotFound => {
+                            TransactionError::CustomError(
+                                Error::not_found_by_id(ResourceType::Vpc, &id),
+                            )
+                        }
+                        SyncInsertError::DatabaseError(e) => e.into(),
+                    })?;
+                // The time is read back rather than using `now`, since the
+                // database keeps it with less precision.
+                let time_modified = touch_vpc_query.get_result(conn)?;
+                Ok((rules, time_modified))
+            },
+            |e| {
+                public_error_from_diesel_pool(
+                    e,
+                    ErrorHandler::NotFoundByLookup(
+                        ResourceType::Vpc,
+                        LookupType::ById(id),
+                    ),
+                )
+            },
+        )
+        .await
+    }
+
+    pub async fn vpc_list_subnets(
+        &self,
+        vpc_id: &Uuid,
+        pagparams: &DataPageParams<'_, Name>,
+    ) -> ListResultVec<VpcSubnet> {
+        use db::schema::vpc_subnet::dsl;
+
+        paginated(dsl::vpc_subnet, dsl::name, &pagparams)
+            .filter(dsl::time_deleted.is_null())
+            .filter(dsl::vpc_id.eq(*vpc_id))
+            .select(VpcSubnet::as_select())
+            .load_async(self.pool())
+            .await
+            .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
+    }
+
+    /// Fetches all of a VPC's subnets
+    ///
+    /// TODO-scalability This isn't paginated.  It's used to resolve firewall
+    /// rules, which need every subnet at once.
+    pub async fn vpc_list_all_subnets(
+        &self,
+        vpc_id: &Uuid,
+    ) -> ListResultVec<VpcSubnet> {
+        use db::schema::vpc_subnet::dsl;
+
+        dsl::vpc_subnet
+            .filter(dsl::time_deleted.is_null())
+            .filter(dsl::vpc_id.eq(*vpc_id))
+            .order(dsl::name.asc())
+            .select(VpcSubnet::as_select())
+            .load_async(self.pool())
+            .await
+            .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
+    }
+
+    pub async fn vpc_subnet_fetch_by_name(
+        &self,
+        vpc_id: &Uuid,
+        subnet_name: &Name,
+    ) -> LookupResult<VpcSubnet> {
+        use db::schema::vpc_subnet::dsl;
+
+        dsl::vpc_subnet
+            .filter(dsl::time_deleted.is_null())
+            .filter(dsl::vpc_id.eq(*vpc_id))
+            .filter(dsl::name.eq(subnet_name.clone()))
+            .select(VpcSubnet::as_select())
+            .get_result_async(self.pool())
+            .await
+            .map_err(|e| {
+                public_error_from_diesel_pool(
+                    e,
+                    ErrorHandler::NotFoundByLookup(
+                        ResourceType::VpcSubnet,
+                        LookupType::ByName(subnet_name.as_str().to_owned()),
+                    ),
+                )
+            })
+    }
+
+    /// Insert a VPC Subnet, checking for unique IP address ranges.
+    pub async fn vpc_create_subnet(
+        &self,
+        subnet: VpcSubnet,
+    ) -> Result<VpcSubnet, SubnetError> {
+        use db::schema::vpc_subnet::dsl;
+        let name = subnet.name().clone();
+        let values = FilterConflictingVpcSubnetRangesQuery(subnet);
+        diesel::insert_into(dsl::vpc_subnet)
+            .values(values)
+            .returning(VpcSubnet::as_returning())
+            .get_result_async(self.pool())
+            .await
+            .map_err(|err| {
+                if let PoolError::Connection(ConnectionError::Query(
+                    diesel::result::Error::NotFound,
+                )) = err
+                {
+                    SubnetError::OverlappingIpRange
+                } else {
+                    SubnetError::External(public_error_from_diesel_pool(
+                        err,
+                        ErrorHandler::Conflict(
+                            ResourceType::VpcSubnet,
+                            name.to_string().as_str(),
+                        ),
+                    ))
+                }
+            })
+    }
+
+    pub async fn vpc_delete_subnet(
+        &self,
+        subnet_id: &Uuid,
+        preconditions: &Preconditions,
+    ) -> DeleteResult {
+        use db::schema::vpc_subnet::dsl;
+
+        let id = *subnet_id;
+        let now = Utc::now();
+        Self::update_with_preconditions(
+            self.pool(),
+            dsl::vpc_subnet,
+            id,
+            preconditions,
+            move |conn| {
+                diesel::update(dsl::vpc_subnet)
+                    .filter(dsl::id.eq(id))
+                    .set(dsl::time_deleted.eq(now))
+                    .execute(conn)?;
+                Ok(())
+            },
+            |e| {
+                public_error_from_diesel_pool(
+                    e,
+                    ErrorHandler::NotFoundByLookup(
+                        ResourceType::VpcSubnet,
+                        LookupType::ById(id),
+                    ),
+                )
+            },
+        )
+        .await
+    }
+
+    pub async fn vpc_update_subnet(
+        &self,
+        subnet_id: &Uuid,
+        updates: VpcSubnetUpdate,
+        preconditions: &Preconditions,
+    ) -> Result<(), Error> {
+        use db::schema::vpc_subnet::dsl;
+
+        let id = *subnet_id;
+        Self::update_with_preconditions(
+            self.pool(),
+            dsl::vpc_subnet,
+            id,
+            preconditions,
+            move |conn| {
+                diesel::update(dsl::vpc_subnet)
+                    .filter(dsl::id.eq(id))
+                    .set(updates)
+                    .execute(conn)?;
+                Ok(())
+            },
+            |e| {
+                public_error_from_diesel_pool(
+                    e,
+                    ErrorHandler::NotFoundByLookup(
+                        ResourceType::VpcSubnet,
+                        LookupType::ById(id),
+                    ),
+                )
+            },
+        )
+        .await
+    }
+
+    /// Attaches the custom router `router_id` to a subnet, or detaches the
+    /// subnet's custom router if `router_id` is `None`
+    ///
+    /// The router must belong to the same VPC as the subnet.
+    pub async fn vpc_subnet_set_custom_router(
+        &self,
+        subnet_id: &Uuid,
+        router_id: Option<Uuid>,
+    ) -> UpdateResult<VpcSubnet> {
+        use db::schema::vpc_router::dsl as router_dsl;
+        use db::schema::vpc_subnet::dsl as subnet_dsl;
+
+        type TxnError = TransactionError<Error>;
+        let id = *subnet_id;
+        self.pool()
+            .transaction(move |conn| {
+                let vpc_id = subnet_dsl::vpc_subnet
+                    .filter(subnet_dsl::time_deleted.is_null())
+                    .filter(subnet_dsl::id.eq(id))
+                    .select(subnet_dsl::vpc_id)
+                    .get_result::<Uuid>(conn)?;
+                if let Some(router_id) = router_id {
+                    let routers = router_dsl::vpc_router
+                        .filter(router_dsl::time_deleted.is_null())
+                        .filter(router_dsl::id.eq(router_id))
+                        .filter(router_dsl::vpc_id.eq(vpc_id))
+                        .count()
+                        .get_result::<i64>(conn)?;
+                    if routers == 0 {
+                        return Err(TxnError::CustomError(
+                            Error::ObjectNotFound {
+                                type_name: ResourceType::VpcRouter,
+                                lookup_type: LookupType::ById(router_id),
+                            },
+                        ));
+                    }
+                }
+                let subnet = diesel::update(subnet_dsl::vpc_subnet)
+                    .filter(subnet_dsl::id.eq(id))
+                    .set((
+                        subnet_dsl::custom_router_id.eq(router_id),
+                        subnet_dsl::time_modified.eq(Utc::now()),
+                    ))
+                    .returning(VpcSubnet::as_returning())
+                    .get_result(conn)?;
+                Ok(subnet)
+            })
+            .await
+            .map_err(|e| match e {
+                TxnError::CustomError(e) => e,
+                TxnError::Pool(e) => public_error_from_diesel_pool(
+                    e,
+                    ErrorHandler::NotFoundByLookup(
+                        ResourceType::VpcSubnet,
+                        LookupType::ById(id),
+                    ),
+                ),
+            })
+    }
+
+    pub async fn subnet_list_network_interfaces(
+        &self,
+        subnet_id: &Uuid,
+        pagparams: &DataPageParams<'_, Name>,
+    ) -> ListResultVec<NetworkInterface> {
+        use db::schema::network_interface::dsl;
+
+        paginated(dsl::network_interface, dsl::name, pagparams)
+            .filter(dsl::time_deleted.is_null())
+            .filter(dsl::subnet_id.eq(*subnet_id))
+            .select(NetworkInterface::as_select())
+            .load_async::<db::model::NetworkInterface>(self.pool())
+            .await
+            .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
+    }
+
+    /// Fetches all of the network interfaces in a VPC
+    ///
+    /// TODO-scalability This isn't paginated.  It's used to resolve firewall
+    /// rules, which need every interface at once.
+    pub async fn vpc_list_all_network_interfaces(
+        &self,
+        vpc_id: &Uuid,
+    ) -> ListResultVec<NetworkInterface> {
+        use db::schema::network_interface::dsl;
+
+        dsl::network_interface
+            .filter(dsl::time_deleted.is_null())
+            .filter(dsl::vpc_id.eq(*vpc_id))
+            .order(dsl::id.asc())
+            .select(NetworkInterface::as_select())
+            .load_async::<NetworkInterface>(self.pool())
+            .await
+            .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
+    }
+
+    pub async fn vpc_list_routers(
+        &self,
+        vpc_id: &Uuid,
+        pagparams: &DataPageParams<'_, Name>,
+    ) -> ListResultVec<VpcRouter> {
+        use db::schema::vpc_router::dsl;
+
+        paginated(dsl::vpc_router, dsl::name, pagparams)
+            .filter(dsl::time_deleted.is_null())
+            .filter(dsl::vpc_id.eq(*vpc_id))
+            .select(VpcRouter::as_select())
+            .load_async::<db::model::VpcRouter>(self.pool())
+            .await
+            .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
+    }
+
+    pub async fn vpc_router_fetch_by_name(
+        &self,
+        vpc_id: &Uuid,
+        router_name: &Name,
+    ) -> LookupResult<VpcRouter> {
+        use db::schema::vpc_router::dsl;
+
+        dsl::vpc_router
+            .filter(dsl::time_deleted.is_null())
+            .filter(dsl::vpc_id.eq(*vpc_id))
+            .filter(dsl::name.eq(router_name.clone()))
+            .select(VpcRouter::as_select())
+            .get_result_async(self.pool())
+            .await
+            .map_err(|e| {
+                public_error_from_diesel_pool(
+                    e,
+                    ErrorHandler::NotFoundByLookup(
+                        ResourceType::VpcRouter,
+                        LookupType::ByName(router_name.as_str().to_owned()),
+                    ),
+                )
+            })
+    }
+
+    pub async fn vpc_router_fetch_by_id(
+        &self,
+        router_id: &Uuid,
+    ) -> LookupResult<VpcRouter> {
+        use db::schema::vpc_router::dsl;
+
+        dsl::vpc_router
+            .filter(dsl::time_deleted.is_null())
+            .filter(dsl::id.eq(*router_id))
+            .select(VpcRouter::as_select())
+            .get_result_async(self.pool())
+            .await
+            .map_err(|e| {
+                public_error_from_diesel_pool(
+                    e,
+                    ErrorHandler::NotFoundByLookup(
+                        ResourceType::VpcRouter,
+                        LookupType::ById(*router_id),
+                    ),
+                )
+            })
+    }
+
+    pub async fn vpc_create_router(
+        &self,
+        router: VpcRouter,
+    ) -> CreateResult<VpcRouter> {
+        use db::schema::vpc_router::dsl;
+
+        let name = router.name().clone();
+        let router = diesel::insert_into(dsl::vpc_router)
+            .values(router)
+            .on_conflict(dsl::id)
+            .do_nothing()
+            .returning(VpcRouter::as_returning())
+            .get_result_async(self.pool())
+            .await
+            .map_err(|e| {
+                public_error_from_diesel_pool(
+                    e,
+                    ErrorHandler::Conflict(
+                        ResourceType::VpcRouter,
+                        name.as_str(),
+                    ),
+                )
+            })?;
+        Ok(router)
+    }
+
+    /// Deletes a router, detaching it from any subnets to which it's attached
+    pub async fn vpc_delete_router(
+        &self,
+        router_id: &Uuid,
+        preconditions: &Preconditions,
+    ) -> DeleteResult {
+        use db::schema::vpc_router::dsl;
+        use db::schema::vpc_subnet::dsl as subnet_dsl;
+
+        let id = *router_id;
+        let now = Utc::now();
+        Self::update_with_preconditions(
+            self.pool(),
+            dsl::vpc_router,
+            id,
+            preconditions,
+            move |conn| {
+                diesel::update(dsl::vpc_router)
+                    .filter(dsl::id.eq(id))
+                    .set(dsl::time_deleted.eq(now))
+                    .execute(conn)?;
+                diesel::update(subnet_dsl::vpc_subnet)
+                    .filter(subnet_dsl::time_deleted.is_null())
+                    .filter(subnet_dsl::custom_router_id.eq(id))
+                    .set((
+                        subnet_dsl::custom_router_id.eq(None::<Uuid>),
+                        subnet_dsl::time_modified.eq(now),
+                    ))
+                    .execute(conn)?;
+                Ok(())
+            },
+            |e| {
+                public_error_from_diesel_pool(
+                    e,
+                    ErrorHandler::NotFoundByLookup(
+                        ResourceType::VpcRouter,
+                        LookupType::ById(id),
+                    ),
+                )
+            },
+        )
+        .await
+    }
+
+    pub async fn vpc_update_router(
+        &self,
+        router_id: &Uuid,
+        updates: VpcRouterUpdate,
+        preconditions: &Preconditions,
+    ) -> Result<(), Error> {
+        use db::schema::vpc_router::dsl;
+
+        let id = *router_id;
+        Self::update_with_preconditions(
+            self.pool(),
+            dsl::vpc_router,
+            id,
+            preconditions,
+            move |conn| {
+                diesel::update(dsl::vpc_router)
+                    .filter(dsl::id.eq(id))
+                    .set(updates)
+                    .execute(conn)?;
+                Ok(())
+            },
+            |e| {
+                public_error_from_diesel_pool(
+                    e,
+                    ErrorHandler::NotFoundByLookup(
+                        ResourceType::VpcRouter,
+                        LookupType::ById(id),
+                    ),
+                )
+            },
+        )
+        .await
+    }
+
+    pub async fn router_list_routes(
+        &self,
+        router_id: &Uuid,
+        pagparams: &DataPageParams<'_, Name>,
+    ) -> ListResultVec<RouterRoute> {
+        use db::schema::router_route::dsl;
+
+        paginated(dsl::router_route, dsl::name, pagparams)
+            .filter(dsl::time_deleted.is_null())
+            .filter(dsl::router_id.eq(*router_id))
+            .select(RouterRoute::as_select())
+            .load_async::<db::model::RouterRoute>(self.pool())
+            .await
+            .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
+    }
+
+    /// Fetches all of a router's routes
+    ///
+    /// TODO-scalability This isn't paginated.  It's used to resolve the
+    /// effective route table of a subnet, which needs every route at once.
+    pub async fn router_list_all_routes(
+        &self,
+        router_id: &Uuid,
+    ) -> ListResultVec<RouterRoute> {
+        use db::schema::router_route::dsl;
+
+        dsl::router_route
+            .filter(dsl::time_deleted.is_null())
+            .filter(dsl::router_id.eq(*router_id))
+            .order(dsl::name.asc())
+            .select(RouterRoute::as_select())
+            .load_async::<RouterRoute>(self.pool())
+            .await
+            .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
+    }
+
+    pub async fn router_route_fetch_by_name(
+        &self,
+        router_id: &Uuid,
+        route_name: &Name,
+    ) -> LookupResult<RouterRoute> {
+        use db::schema::router_route::dsl;
+
+        dsl::router_route
+            .filter(dsl::time_deleted.is_null())
+            .filter(dsl::router_id.eq(*router_id))
+            .filter(dsl::name.eq(route_name.clone()))
+            .select(RouterRoute::as_select())
+            .get_result_async(self.pool())
+            .await
+            .map_err(|e| {
+                public_error_from_diesel_pool(
+                    e,
+                    ErrorHandler::NotFoundByLookup(
+                        ResourceType::RouterRoute,
+                        LookupType::ByName(route_name.as_str().to_owned()),
+                    ),
+                )
+            })
+    }
+
+    pub async fn router_create_route(
+        &self,
+        route: RouterRoute,
+    ) -> CreateResult<RouterRoute> {
+        use db::schema::router_route::dsl;
+        let router_id = route.router_id;
+        let name = route.name().clone();
+
+        VpcRouter::insert_resource(
+            router_id,
+            diesel::insert_into(dsl::router_route).values(route),
+        )
+        .insert_and_get_result_async(self.pool())
+        .await
+        .map_err(|e| match e {
+            AsyncInsertError::CollectionNotFound => Error::ObjectNotFound {
+                type_name: ResourceType::VpcRouter,
+                lookup_type: LookupType::ById(router_id),
+            },
+            AsyncInsertError::DatabaseError(e) => {
+                public_error_from_diesel_pool(
+                    e,
+                    ErrorHandler::Conflict(
+                        ResourceType::RouterRoute,
+                        name.as_str(),
+                    ),
+                )
+            }
+        })
+    }
+
+    pub async fn router_delete_route(
+        &self,
+        route_id: &Uuid,
+        preconditions: &Preconditions,
+    ) -> DeleteResult {
+        use db::schema::router_route::dsl;
+
+        let id = *route_id;
+        let now = Utc::now();
+        Self::update_with_preconditions(
+            self.pool(),
+            dsl::router_route,
+            id,
+            preconditions,
+            move |conn| {
+                diesel::update(dsl::router_route)
+                    .filter(dsl::id.eq(id))
+                    .set(dsl::time_deleted.eq(now))
+                    .execute(conn)?;
+                Ok(())
+            },
+            |e| {
+                public_error_from_diesel_pool(
+                    e,
+                    ErrorHandler::NotFoundByLookup(
+                        ResourceType::RouterRoute,
+                        LookupType::ById(id),
+                    ),
+                )
+            },
+        )
+        .await
+    }
+
+    pub async fn router_update_route(
+        &self,
+        route_id: &Uuid,
+        updates: RouterRouteUpdate,
+        preconditions: &Preconditions,
+    ) -> Result<(), Error> {
+        use db::schema::router_route::dsl;
+
+        let id = *route_id;
+        Self::update_with_preconditions(
+            self.pool(),
+            dsl::router_route,
+            id,
+            preconditions,
+            move |conn| {
+                diesel::update(dsl::router_route)
+                    .filter(dsl::id.eq(id))
+                    .set(updates)
+                    .execute(conn)?;
+                Ok(())
+            },
+            |e| {
+                public_error_from_diesel_pool(
+                    e,
+                    ErrorHandler::NotFoundByLookup(
+                        ResourceType::RouterRoute,
+                        LookupType::ById(id),
+                    ),
+                )
+            },
+        )
+        .await
+    }
+
+    // TODO-correctness: fix session method errors. the map_errs turn all errors
+    // into 500s, most notably (and most frequently) session not found. they
+    // don't end up as 500 in the http response because they get turned into a
+    // 4xx error by calling code, the session cookie authn scheme. this is
+    // necessary for now in order to avoid the possibility of leaking out a
+    // too-friendly 404 to the client. once datastore has its own error type and
+    // the conversion to serializable user-facing errors happens elsewhere (see
+    // issue #347) these methods can safely return more accurate errors, and
+    // showing/hiding that info as appropriate will be handled higher up
+
+    pub async fn session_fetch(
+        &self,
+        token: String,
+    ) -> LookupResult<ConsoleSession> {
+        use db::schema::console_session::dsl;
+        dsl::console_session
+            .filter(dsl::token.eq(token.clone()))
+            .select(ConsoleSession::as_select())
+            .first_async(self.pool())
+            .await
+            .map_err(|e| {
+                Error::internal_error(&format!(
+                    "error fetching session: {:?}",
+                    e
+                ))
+            })
+    }
+
+    pub async fn session_create(
+        &self,
+        session: ConsoleSession,
+    ) -> CreateResult<ConsoleSession> {
+        use db::schema::console_session::dsl;
+
+        diesel::insert_into(dsl::console_session)
+            .values(session)
+            .returning(ConsoleSession::as_returning())
+            .get_result_async(self.pool())
+            .await
+            .map_err(|e| {
+                Error::internal_error(&format!(
+                    "error creating session: {:?}",
+                    e
+                ))
+            })
+    }
+
+    pub async fn session_update_last_used(
+        &self,
+        token: String,
+    ) -> UpdateResult<ConsoleSession> {
+        use db::schema::console_session::dsl;
+
+        diesel::update(dsl::console_session)
+            .filter(dsl::token.eq(token.clone()))
+            .set((dsl::time_last_used.eq(Utc::now()),))
+            .returning(ConsoleSession::as_returning())
+            .get_result_async(self.pool())
+            .await
+            .map_err(|e| {
+                Error::internal_error(&format!(
+                    "error renewing session: {:?}",
+                    e
+                ))
+            })
+    }
+
+    // putting "hard" in the name because we don't do this with any other model
+    pub async fn session_hard_delete(&self, token: String) -> DeleteResult {
+        use db::schema::console_session::dsl;
+
+        diesel::delete(dsl::console_session)
+            .filter(dsl::token.eq(token.clone()))
+            .execute_async(self.pool())
+            .await
+            .map(|_rows_deleted| ())
+            .map_err(|e| {
+                Error::internal_error(&format!(
+                    "error deleting session: {:?}",
+                    e
+                ))
+            })
+    }
+
+    /*
+     * API tokens
+     *
+     * These belong to the user that created them, so rather than checking
+     * roles, callers only ever operate on the authenticated actor's tokens.
+     */
+
+    pub async fn api_token_create(
+        &self,
+        opctx: &OpContext,
+        token: ApiToken,
+    ) -> CreateResult<ApiToken> {
+        use db::schema::api_token::dsl;
+        diesel::insert_into(dsl::api_token)
+            .values(token)
+            .returning(ApiToken::as_returning())
+            .get_result_async(self.pool_authorized(opctx).await?)
+            .await
+            .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
+    }
+
+    pub async fn api_tokens_list(
+        &self,
+        opctx: &OpContext,
+        user_id: Uuid,
+        pagparams: &DataPageParams<'_, Uuid>,
+    ) -> ListResultVec<ApiToken> {
+        use db::schema::api_token::dsl;
+        paginated(dsl::api_token, dsl::id, pagparams)
+            .filter(dsl::user_id.eq(user_id))
+            .select(ApiToken::as_select())
+            .load_async::<ApiToken>(self.pool_authorized(opctx).await?)
+            .await
+            .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
+    }
+
+    // Tokens are hard-deleted so that they stop working immediately.
+    pub async fn api_token_delete(
+        &self,
+        opctx: &OpContext,
+        user_id: Uuid,
+        token_id: Uuid,
+    ) -> DeleteResult {
+        use db::schema::api_token::dsl;
+        let deleted_rows = diesel::delete(dsl::api_token)
+            .filter(dsl::user_id.eq(user_id))
+            .filter(dsl::id.eq(token_id))
+            .execute_async(self.pool_authorized(opctx).await?)
+            .await
+            .map_err(|e| {
+                public_error_from_diesel_pool(e, ErrorHandler::Server)
+            })?;
+        if deleted_rows == 0 {
+            return Err(Error::not_found_by_id(
+                ResourceType::ApiToken,
+                &token_id,
+            ));
+        }
+        Ok(())
+    }
+
+    /// Fetch an API token by its hash in order to authenticate a request
+    ///
+    /// Like [`DataStore::session_fetch()`], this is not subject to
+    /// authorization.
+    pub async fn api_token_fetch_for_authn(
+        &self,
+        token_hash: String,
+    ) -> LookupResult<ApiToken> {
+        use db::schema::api_token::dsl;
+        dsl::api_token
+            .filter(dsl::token_hash.eq(token_hash))
+            .select(ApiToken::as_select())
+            .first_async::<ApiToken>(self.pool())
+            .await
+            .map_err(|e| {
+                Error::internal_error(&format!(
+                    "error fetching API token: {:?}",
+                    e
+                ))
+            })
+    }
+
+    /*
+     * Idempotency keys
+     *
+     * These are bookkeeping for requests that the authenticated actor has
+     * already been authorized to make, so (like sessions) they're not subject
+     * to authorization themselves.
+     */
+
+    /// Attempts to claim an idempotency key for a new request, returning
+    /// whether it was claimed
+    ///
+    /// A claim made before `expired_before` is discarded first.  If some other
+    /// claim exists, it can be fetched with
+    /// [`DataStore::idempotency_key_fetch()`].
+    pub async fn idempotency_key_claim(
+        &self,
+        claim: IdempotencyKey,
+        expired_before: DateTime<Utc>,
+    ) -> Result<bool, Error> {
+        use db::schema::idempotency_key::dsl;
+        diesel::delete(dsl::idempotency_key)
+            .filter(dsl::actor_id.eq(claim.actor_id))
+            .filter(dsl::client_key.eq(claim.client_key.clone()))
+            .filter(dsl::time_created.lt(expired_before))
+            .execute_async(self.pool())
+            .await
+            .map_err(|e| {
+                public_error_from_diesel_pool(e, ErrorHandler::Server)
+            })?;
+        diesel::insert_into(dsl::idempotency_key)
+            .values(claim)
+            .on_conflict((dsl::actor_id, dsl::client_key))
+            .do_nothing()
+            .execute_async(self.pool())
+            .await
+            .map(|inserted_rows| inserted_rows > 0)
+            .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
+    }
+
+    pub async fn idempotency_key_fetch(
+        &self,
+        actor_id: Uuid,
+        client_key: String,
+    ) -> Result<Option<IdempotencyKey>, Error> {
+        use db::schema::idempotency_key::dsl;
+        dsl::idempotency_key
+            .filter(dsl::actor_id.eq(actor_id))
+            .filter(dsl::client_key.eq(client_key))
+            .select(IdempotencyKey::as_select())
+            .load_async::<IdempotencyKey>(self.pool())
+            .await
+            .map(|mut claims| claims.pop())
+            .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
+    }
+
+    /// Records the saga carrying out the request that claimed `key`
+    ///
+    /// This does nothing if the key wasn't claimed by a request for the same
+    /// method and path, or if the claim already has a saga.
+    pub async fn idempotency_key_set_saga(
         &self,
-        subnet_id: &Uuid,
-        updates: VpcSubnetUpdate,
+        actor_id: Uuid,
+        key: &idempotency::IdempotencyKey,
+        saga_id: Uuid,
     ) -> Result<(), Error> {
-        use db::schema::vpc_subnet::dsl;
-
-        diesel::update(dsl::vpc_subnet)
-            .filter(dsl::time_deleted.is_null())
-            .filter(dsl::id.eq(*subnet_id))
-            .set(updates)
+        use db::schema::idempotency_key::dsl;
+        diesel::update(dsl::idempotency_key)
+            .filter(dsl::actor_id.eq(actor_id))
+            .filter(dsl::client_key.eq(key.key.clone()))
+            .filter(dsl::http_method.eq(key.http_method.clone()))
+            .filter(dsl::http_path.eq(key.http_path.clone()))
+            .filter(dsl::saga_id.is_null())
+            .filter(dsl::response_body.is_null())
+            .set(dsl::saga_id.eq(saga_id))
             .execute_async(self.pool())
             .await
-            .map_err(|e| {
-                public_error_from_diesel_pool(
-                    e,
-                    ErrorHandler::NotFoundByLookup(
-                        ResourceType::VpcSubnet,
-                        LookupType::ById(*subnet_id),
-                    ),
-                )
-            })?;
-        Ok(())
+            .map(|_| ())
+            .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
     }
 
-    pub async fn subnet_list_network_interfaces(
+    /// Stores the response to the request that claimed an idempotency key
+    pub async fn idempotency_key_complete(
         &self,
-        subnet_id: &Uuid,
-        pagparams: &DataPageParams<'_, Name>,
-    ) -> ListResultVec<NetworkInterface> {
-        use db::schema::network_interface::dsl;
-
-        paginated(dsl::network_interface, dsl::name, pagparams)
-            .filter(dsl::time_deleted.is_null())
-            .filter(dsl::subnet_id.eq(*subnet_id))
-            .select(NetworkInterface::as_select())
-            .load_async::<db::model::NetworkInterface>(self.pool())
+        actor_id: Uuid,
+        client_key: String,
+        response_body: serde_json::Value,
+    ) -> Result<(), Error> {
+        use db::schema::idempotency_key::dsl;
+        diesel::update(dsl::idempotency_key)
+            .filter(dsl::actor_id.eq(actor_id))
+            .filter(dsl::client_key.eq(client_key))
+            .set(dsl::response_body.eq(response_body))
+            .execute_async(self.pool())
             .await
+            .map(|_| ())
             .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
     }
 
-    pub async fn vpc_list_routers(
+    /// Releases the claim on an idempotency key by a request that failed, so
+    /// that the request can be retried
+    pub async fn idempotency_key_release(
         &self,
-        vpc_id: &Uuid,
-        pagparams: &DataPageParams<'_, Name>,
-    ) -> ListResultVec<VpcRouter> {
-        use db::schema::vpc_router::dsl;
-
-        paginated(dsl::vpc_router, dsl::name, pagparams)
-            .filter(dsl::time_deleted.is_null())
-            .filter(dsl::vpc_id.eq(*vpc_id))
-            .select(VpcRouter::as_select())
-            .load_async::<db::model::VpcRouter>(self.pool())
+        actor_id: Uuid,
+        client_key: String,
+    ) -> Result<(), Error> {
+        use db::schema::idempotency_key::dsl;
+        diesel::delete(dsl::idempotency_key)
+            .filter(dsl::actor_id.eq(actor_id))
+            .filter(dsl::client_key.eq(client_key))
+            .filter(dsl::response_body.is_null())
+            .execute_async(self.pool())
             .await
+            .map(|_| ())
             .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
     }
 
-    pub async fn vpc_router_fetch_by_name(
-        &self,
-        vpc_id: &Uuid,
-        router_name: &Name,
-    ) -> LookupResult<VpcRouter> {
-        use db::schema::vpc_router::dsl;
+    /*
+     * SSH keys
+     *
+     * Like API tokens, these belong to the user that uploaded them, and callers
+     * only ever operate on the authenticated actor's keys.
+     */
 
-        dsl::vpc_router
-            .filter(dsl::time_deleted.is_null())
-            .filter(dsl::vpc_id.eq(*vpc_id))
-            .filter(dsl::name.eq(router_name.clone()))
-            .select(VpcRouter::as_select())
-            .get_result_async(self.pool())
+    pub async fn ssh_key_create(
+        &self,
+        opctx: &OpContext,
+        key: SshKey,
+    ) -> CreateResult<SshKey> {
+        use db::schema::ssh_key::dsl;
+        let fingerprint = key.fingerprint.clone();
+        diesel::insert_into(dsl::ssh_key)
+            .values(key)
+            .returning(SshKey::as_returning())
+            .get_result_async(self.pool_authorized(opctx).await?)
             .await
             .map_err(|e| {
                 public_error_from_diesel_pool(
                     e,
-                    ErrorHandler::NotFoundByLookup(
-                        ResourceType::VpcRouter,
-                        LookupType::ByName(router_name.as_str().to_owned()),
-                    ),
+                    ErrorHandler::Conflict(ResourceType::SshKey, &fingerprint),
                 )
             })
     }
 
-    pub async fn vpc_create_router(
+    pub async fn ssh_keys_list(
         &self,
-        router: VpcRouter,
-    ) -> CreateResult<VpcRouter> {
-        use db::schema::vpc_router::dsl;
-
-        let name = router.name().clone();
-        let router = diesel::insert_into(dsl::vpc_router)
-            .values(router)
-            .on_conflict(dsl::id)
-            .do_nothing()
-            .returning(VpcRouter::as_returning())
-            .get_result_async(self.pool())
+        opctx: &OpContext,
+        user_id: Uuid,
+        pagparams: &DataPageParams<'_, Uuid>,
+    ) -> ListResultVec<SshKey> {
+        use db::schema::ssh_key::dsl;
+        paginated(dsl::ssh_key, dsl::id, pagparams)
+            .filter(dsl::user_id.eq(user_id))
+            .select(SshKey::as_select())
+            .load_async::<SshKey>(self.pool_authorized(opctx).await?)
             .await
-            .map_err(|e| {
-                public_error_from_diesel_pool(
-                    e,
-                    ErrorHandler::Conflict(
-                        ResourceType::VpcRouter,
-                        name.as_str(),
-                    ),
-                )
-            })?;
-        Ok(router)
+            .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
     }
 
-    pub async fn vpc_delete_router(&self, router_id: &Uuid) -> DeleteResult {
-        use db::schema::vpc_router::dsl;
+    /// Fetch all of a user's keys, in the order they were uploaded
+    pub async fn ssh_keys_list_all(
+        &self,
+        opctx: &OpContext,
+        user_id: Uuid,
+    ) -> ListResultVec<SshKey> {
+        use db::schema::ssh_key::dsl;
+        dsl::ssh_key
+            .filter(dsl::user_id.eq(user_id))
+            .order((dsl::time_created.asc(), dsl::id.asc()))
+            .select(SshKey::as_select())
+            .load_async::<SshKey>(self.pool_authorized(opctx).await?)
+            .await
+            .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
+    }
 
-        let now = Utc::now();
-        diesel::update(dsl::vpc_router)
-            .filter(dsl::time_deleted.is_null())
-            .filter(dsl::id.eq(*router_id))
-            .set(dsl::time_deleted.eq(now))
-            .returning(VpcRouter::as_returning())
-            .get_result_async(self.pool())
+    pub async fn ssh_key_delete(
+        &self,
+        opctx: &OpContext,
+        user_id: Uuid,
+        ssh_key_id: Uuid,
+    ) -> DeleteResult {
+        use db::schema::ssh_key::dsl;
+        let deleted_rows = diesel::delete(dsl::ssh_key)
+            .filter(dsl::user_id.eq(user_id))
+            .filter(dsl::id.eq(ssh_key_id))
+            .execute_async(self.pool_authorized(opctx).await?)
             .await
             .map_err(|e| {
-                public_error_from_diesel_pool(
-                    e,
-                    ErrorHandler::NotFoundByLookup(
-                        ResourceType::VpcRouter,
-                        LookupType::ById(*router_id),
-                    ),
-                )
+                public_error_from_diesel_pool(e, ErrorHandler::Server)
             })?;
+        if deleted_rows == 0 {
+            return Err(Error::not_found_by_id(
+                ResourceType::SshKey,
+                &ssh_key_id,
+            ));
+        }
         Ok(())
     }
 
-    pub async fn vpc_update_router(
+    /*
+     * Audit log
+     */
+
+    /// Records an entry in the audit log
+    ///
+    /// This is done on behalf of whoever made the request being audited, so
+    /// there's no authorization check here.
+    pub async fn audit_log_insert(
         &self,
-        router_id: &Uuid,
-        updates: VpcRouterUpdate,
+        entry: AuditLogEntry,
     ) -> Result<(), Error> {
-        use db::schema::vpc_router::dsl;
+        use db::schema::audit_log::dsl;
 
-        diesel::update(dsl::vpc_router)
-            .filter(dsl::time_deleted.is_null())
-            .filter(dsl::id.eq(*router_id))
-            .set(updates)
+        diesel::insert_into(dsl::audit_log)
+            .values(entry)
             .execute_async(self.pool())
             .await
-            .map_err(|e| {
-                public_error_from_diesel_pool(
-                    e,
-                    ErrorHandler::NotFoundByLookup(
-                        ResourceType::VpcRouter,
-                        LookupType::ById(*router_id),
-                    ),
-                )
-            })?;
-        Ok(())
+            .map(|_| ())
+            .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
     }
 
-    pub async fn router_list_routes(
+    /// Lists the audit log in chronological order, optionally restricted to
+    /// entries recorded at or after `start_time` and before `end_time`
+    pub async fn audit_log_list(
         &self,
-        router_id: &Uuid,
-        pagparams: &DataPageParams<'_, Name>,
-    ) -> ListResultVec<RouterRoute> {
-        use db::schema::router_route::dsl;
+        opctx: &OpContext,
+        start_time: Option<DateTime<Utc>>,
+        end_time: Option<DateTime<Utc>>,
+        pagparams: &DataPageParams<'_, (DateTime<Utc>, Uuid)>,
+    ) -> ListResultVec<AuditLogEntry> {
+        use db::schema::audit_log::dsl;
+
+        // Only fleet administrators may see the audit log.
+        opctx.authorize(authz::Action::Modify, &authz::FLEET).await?;
+        let mut query = paginated_multicolumn(
+            dsl::audit_log,
+            (dsl::time_created, dsl::id),
+            pagparams,
+        );
+        if let Some(start_time) = start_time {
+            query = query.filter(dsl::time_created.ge(start_time));
+        }
+        if let Some(end_time) = end_time {
+            query = query.filter(dsl::time_created.lt(end_time));
+        }
+        query
+            .select(AuditLogEntry::as_select())
+            .load_async::<AuditLogEntry>(self.pool_authorized(opctx).await?)
+            .await
+            .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
+    }
 
-        paginated(dsl::router_route, dsl::name, pagparams)
-            .filter(dsl::time_deleted.is_null())
-            .filter(dsl::router_id.eq(*router_id))
-            .select(RouterRoute::as_select())
-            .load_async::<db::model::RouterRoute>(self.pool())
+    /*
+     * Project events and webhooks
+     */
+
+    /// Appends `event` to its Project's event log, assigning it the next
+    /// position in the log
+    ///
+    /// This is done on behalf of whatever changed the resource, so there's no
+    /// authorization check here.
+    pub async fn project_event_append(
+        &self,
+        event: ProjectEvent,
+    ) -> CreateResult<ProjectEvent> {
+        use db::schema::project_event::dsl;
+
+        /*
+         * Concurrent appends to the same log read the same last position, and
+         * CockroachDB fails all but one of their transactions.  Those are
+         * retried a few times before giving up.
+         */
+        const MAX_ATTEMPTS: usize = 3;
+        type TxnError = TransactionError<()>;
+        let mut attempt = 1;
+        loop {
+            let mut event = event.clone();
+            let result = self
+                .pool()
+                .transaction(move |conn| -> Result<_, TxnError> {
+                    let last_seq = dsl::project_event
+                        .filter(dsl::project_id.eq(event.project_id))
+                        .select(diesel::dsl::max(dsl::seq))
+                        .get_result::<Option<i64>>(conn)?;
+                    event.seq = last_seq.unwrap_or(0) + 1;
+                    Ok(diesel::insert_into(dsl::project_event)
+                        .values(event)
+                        .returning(ProjectEvent::as_returning())
+                        .get_result(conn)?)
+                })
+                .await;
+            match result {
+                Ok(event) => return Ok(event),
+                Err(_) if attempt < MAX_ATTEMPTS => attempt += 1,
+                Err(e) => {
+                    return Err(Error::internal_error(&format!(
+                        "Transaction error: {:?}",
+                        e
+                    )))
+                }
+            }
+        }
+    }
+
+    /// Lists up to `limit` events in a Project's log that come after position
+    /// `after_seq`, in order
+    pub async fn project_events_list(
+        &self,
+        opctx: &OpContext,
+        authz_project: &authz::Project,
+        after_seq: i64,
+        limit: u32,
+    ) -> ListResultVec<ProjectEvent> {
+        opctx.authorize(authz::Action::Read, authz_project).await?;
+        self.project_events_list_no_auth(authz_project.id(), after_seq, limit)
+            .await
+    }
+
+    /// Like [`DataStore::project_events_list`], but without checking
+    /// authorization, for delivering events to webhooks
+    pub async fn project_events_list_no_auth(
+        &self,
+        project_id: Uuid,
+        after_seq: i64,
+        limit: u32,
+    ) -> ListResultVec<ProjectEvent> {
+        use db::schema::project_event::dsl;
+        dsl::project_event
+            .filter(dsl::project_id.eq(project_id))
+            .filter(dsl::seq.gt(after_seq))
+            .order(dsl::seq.asc())
+            .limit(i64::from(limit))
+            .select(ProjectEvent::as_select())
+            .load_async::<ProjectEvent>(self.pool())
             .await
             .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
     }
 
-    pub async fn router_route_fetch_by_name(
+    /// Registers a webhook to which the Project's events are delivered
+    ///
+    /// Only events appended after the webhook is created are delivered to it.
+    pub async fn project_webhook_create(
         &self,
-        router_id: &Uuid,
-        route_name: &Name,
-    ) -> LookupResult<RouterRoute> {
-        use db::schema::router_route::dsl;
+        opctx: &OpContext,
+        authz_project: &authz::Project,
+        mut webhook: ProjectWebhook,
+    ) -> CreateResult<ProjectWebhook> {
+        use db::schema::project_event::dsl as event_dsl;
+        use db::schema::project_webhook::dsl;
 
-        dsl::router_route
-            .filter(dsl::time_deleted.is_null())
-            .filter(dsl::router_id.eq(*router_id))
-            .filter(dsl::name.eq(route_name.clone()))
-            .select(RouterRoute::as_select())
-            .get_result_async(self.pool())
+        opctx.authorize(authz::Action::Modify, authz_project).await?;
+        let pool = self.pool_authorized(opctx).await?;
+        webhook.delivered_seq = event_dsl::project_event
+            .filter(event_dsl::project_id.eq(authz_project.id()))
+            .select(diesel::dsl::max(event_dsl::seq))
+            .get_result_async::<Option<i64>>(pool)
             .await
             .map_err(|e| {
-                public_error_from_diesel_pool(
-                    e,
-                    ErrorHandler::NotFoundByLookup(
-                        ResourceType::RouterRoute,
-                        LookupType::ByName(route_name.as_str().to_owned()),
-                    ),
-                )
-            })
+                public_error_from_diesel_pool(e, ErrorHandler::Server)
+            })?
+            .unwrap_or(0);
+        diesel::insert_into(dsl::project_webhook)
+            .values(webhook)
+            .returning(ProjectWebhook::as_returning())
+            .get_result_async(pool)
+            .await
+            .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
     }
 
-    pub async fn router_create_route(
+    pub async fn project_webhooks_list(
         &self,
-        route: RouterRoute,
-    ) -> CreateResult<RouterRoute> {
-        use db::schema::router_route::dsl;
-        let router_id = route.router_id;
-        let name = route.name().clone();
+        opctx: &OpContext,
+        authz_project: &authz::Project,
+        pagparams: &DataPageParams<'_, Uuid>,
+    ) -> ListResultVec<ProjectWebhook> {
+        use db::schema::project_webhook::dsl;
 
-        VpcRouter::insert_resource(
-            router_id,
-            diesel::insert_into(dsl::router_route).values(route),
-        )
-        .insert_and_get_result_async(self.pool())
-        .await
-        .map_err(|e| match e {
-            AsyncInsertError::CollectionNotFound => Error::ObjectNotFound {
-                type_name: ResourceType::VpcRouter,
-                lookup_type: LookupType::ById(router_id),
-            },
-            AsyncInsertError::DatabaseError(e) => {
-                public_error_from_diesel_pool(
-                    e,
-                    ErrorHandler::Conflict(
-                        ResourceType::RouterRoute,
-                        name.as_str(),
-                    ),
-                )
-            }
-        })
+        opctx.authorize(authz::Action::Read, authz_project).await?;
+        paginated(dsl::project_webhook, dsl::id, pagparams)
+            .filter(dsl::time_deleted.is_null())
+            .filter(dsl::project_id.eq(authz_project.id()))
+            .select(ProjectWebhook::as_select())
+            .load_async::<ProjectWebhook>(self.pool_authorized(opctx).await?)
+            .await
+            .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
     }
 
-    pub async fn router_delete_route(&self, route_id: &Uuid) -> DeleteResult {
-        use db::schema::router_route::dsl;
+    /// Lists the webhooks of every Project, for delivering events to them
+    pub async fn project_webhooks_list_all_no_auth(
+        &self,
+    ) -> ListResultVec<ProjectWebhook> {
+        use db::schema::project_webhook::dsl;
+        dsl::project_webhook
+            .filter(dsl::time_deleted.is_null())
+            .select(ProjectWebhook::as_select())
+            .load_async::<ProjectWebhook>(self.pool())
+            .await
+            .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
+    }
 
-        let now = Utc::now();
-        diesel::update(dsl::router_route)
+    pub async fn project_webhook_delete(
+        &self,
+        opctx: &OpContext,
+        authz_project: &authz::Project,
+        webhook_id: Uuid,
+    ) -> DeleteResult {
+        use db::schema::project_webhook::dsl;
+
+        opctx.authorize(authz::Action::Modify, authz_project).await?;
+        let deleted_rows = diesel::update(dsl::project_webhook)
             .filter(dsl::time_deleted.is_null())
-            .filter(dsl::id.eq(*route_id))
-            .set(dsl::time_deleted.eq(now))
-            .returning(RouterRoute::as_returning())
-            .get_result_async(self.pool())
+            .filter(dsl::project_id.eq(authz_project.id()))
+            .filter(dsl::id.eq(webhook_id))
+            .set(dsl::time_deleted.eq(Utc::now()))
+            .execute_async(self.pool_authorized(opctx).await?)
             .await
             .map_err(|e| {
-                public_error_from_diesel_pool(
-                    e,
-                    ErrorHandler::NotFoundByLookup(
-                        ResourceType::RouterRoute,
-                        LookupType::ById(*route_id),
-                    ),
-                )
+                public_error_from_diesel_pool(e, ErrorHandler::Server)
             })?;
+        if deleted_rows == 0 {
+            return Err(Error::not_found_by_id(
+                ResourceType::ProjectWebhook,
+                &webhook_id,
+            ));
+        }
         Ok(())
     }
 
-    pub async fn router_update_route(
+    /// Records that the events up to and including position `seq` have been
+    /// delivered to a webhook
+    pub async fn project_webhook_set_delivered(
         &self,
-        route_id: &Uuid,
-        route_update: RouterRouteUpdate,
+        webhook_id: Uuid,
+        seq: i64,
     ) -> Result<(), Error> {
-        use db::schema::router_route::dsl;
+        use db::schema::project_webhook::dsl;
+        diesel::update(dsl::project_webhook)
+            .filter(dsl::id.eq(webhook_id))
+            .filter(dsl::delivered_seq.lt(seq))
+            .set(dsl::delivered_seq.eq(seq))
+            .execute_async(self.pool())
+            .await
+            .map(|_| ())
+            .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
+    }
 
-        diesel::update(dsl::router_route)
-            .filter(dsl::time_deleted.is_null())
-            .filter(dsl::id.eq(*route_id))
-            .set(route_update)
+    /// Records that the event at position `seq` couldn't be delivered to a
+    /// webhook
+    pub async fn project_webhook_set_failed(
+        &self,
+        webhook_id: Uuid,
+        seq: i64,
+    ) -> Result<(), Error> {
+        use db::schema::project_webhook::dsl;
+        diesel::update(dsl::project_webhook)
+            .filter(dsl::id.eq(webhook_id))
+            .set(dsl::last_failed_seq.eq(seq))
             .execute_async(self.pool())
             .await
+            .map(|_| ())
+            .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
+    }
+
+    /// Create a user who logs in with a password
+    pub async fn user_create(
+        &self,
+        opctx: &OpContext,
+        user: User,
+    ) -> CreateResult<User> {
+        use db::schema::user::dsl;
+
+        // Only fleet administrators may manage users.
+        opctx.authorize(authz::Action::Modify, &authz::FLEET).await?;
+
+        let name = user.name().as_str().to_string();
+        diesel::insert_into(dsl::user)
+            .values(user)
+            .returning(User::as_returning())
+            .get_result_async(self.pool_authorized(opctx).await?)
+            .await
             .map_err(|e| {
                 public_error_from_diesel_pool(
                     e,
-                    ErrorHandler::NotFoundByLookup(
-                        ResourceType::RouterRoute,
-                        LookupType::ById(*route_id),
-                    ),
+                    ErrorHandler::Conflict(ResourceType::User, name.as_str()),
                 )
-            })?;
-        Ok(())
+            })
     }
 
-    // TODO-correctness: fix session method errors. the map_errs turn all errors
-    // into 500s, most notably (and most frequently) session not found. they
-    // don't end up as 500 in the http response because they get turned into a
-    // 4xx error by calling code, the session cookie authn scheme. this is
-    // necessary for now in order to avoid the possibility of leaking out a
-    // too-friendly 404 to the client. once datastore has its own error type and
-    // the conversion to serializable user-facing errors happens elsewhere (see
-    // issue #347) these methods can safely return more accurate errors, and
-    // showing/hiding that info as appropriate will be handled higher up
-
-    pub async fn session_fetch(
+    pub async fn users_list_by_name(
         &self,
-        token: String,
-    ) -> LookupResult<ConsoleSession> {
-        use db::schema::console_session::dsl;
-        dsl::console_session
-            .filter(dsl::token.eq(token.clone()))
-            .select(ConsoleSession::as_select())
-            .first_async(self.pool())
+        opctx: &OpContext,
+        pagparams: &DataPageParams<'_, Name>,
+    ) -> ListResultVec<User> {
+        use db::schema::user::dsl;
+
+        // Only fleet administrators may manage users.
+        opctx.authorize(authz::Action::Modify, &authz::FLEET).await?;
+
+        paginated(dsl::user, dsl::name, pagparams)
+            .filter(dsl::time_deleted.is_null())
+            .select(User::as_select())
+            .load_async::<User>(self.pool_authorized(opctx).await?)
             .await
-            .map_err(|e| {
-                Error::internal_error(&format!(
-                    "error fetching session: {:?}",
-                    e
-                ))
-            })
+            .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
     }
 
-    pub async fn session_create(
+    pub async fn user_fetch(
         &self,
-        session: ConsoleSession,
-    ) -> CreateResult<ConsoleSession> {
-        use db::schema::console_session::dsl;
-
-        diesel::insert_into(dsl::console_session)
-            .values(session)
-            .returning(ConsoleSession::as_returning())
-            .get_result_async(self.pool())
+        opctx: &OpContext,
+        name: &Name,
+    ) -> LookupResult<User> {
+        use db::schema::user::dsl;
+        opctx
+            .authorize(
+                authz::Action::Read,
+                &authz::FLEET.child_generic(
+                    ResourceType::User,
+                    LookupType::from(&name.0),
+                ),
+            )
+            .await?;
+        dsl::user
+            .filter(dsl::time_deleted.is_null())
+            .filter(dsl::name.eq(name.clone()))
+            .select(User::as_select())
+            .first_async::<User>(self.pool_authorized(opctx).await?)
             .await
             .map_err(|e| {
-                Error::internal_error(&format!(
-                    "error creating session: {:?}",
-                    e
-                ))
+                public_error_from_diesel_pool(
+                    e,
+                    ErrorHandler::NotFoundByLookup(
+                        ResourceType::User,
+                        LookupType::ByName(name.as_str().to_owned()),
+                    ),
+                )
             })
     }
 
-    pub async fn session_update_last_used(
+    /// Fetch a user by name in order to check their password
+    ///
+    /// This is used to authenticate requests, so it's not subject to
+    /// authorization (just like [`DataStore::session_fetch()`]).
+    pub async fn user_fetch_for_authn(
         &self,
-        token: String,
-    ) -> UpdateResult<ConsoleSession> {
-        use db::schema::console_session::dsl;
-
-        diesel::update(dsl::console_session)
-            .filter(dsl::token.eq(token.clone()))
-            .set((dsl::time_last_used.eq(Utc::now()),))
-            .returning(ConsoleSession::as_returning())
-            .get_result_async(self.pool())
+        name: &Name,
+    ) -> LookupResult<User> {
+        use db::schema::user::dsl;
+        dsl::user
+            .filter(dsl::time_deleted.is_null())
+            .filter(dsl::name.eq(name.clone()))
+            .select(User::as_select())
+            .first_async::<User>(self.pool())
             .await
             .map_err(|e| {
-                Error::internal_error(&format!(
-                    "error renewing session: {:?}",
-                    e
-                ))
+                public_error_from_diesel_pool(
+                    e,
+                    ErrorHandler::NotFoundByLookup(
+                        ResourceType::User,
+                        LookupType::ByName(name.as_str().to_owned()),
+                    ),
+                )
             })
     }
 
-    // putting "hard" in the name because we don't do this with any other model
-    pub async fn session_hard_delete(&self, token: String) -> DeleteResult {
-        use db::schema::console_session::dsl;
+    /// Delete a user, ending their console sessions, revoking their API
+    /// tokens, removing their SSH keys, and removing any roles that they've
+    /// been granted
+    pub async fn user_delete(
+        &self,
+        opctx: &OpContext,
+        user_id: Uuid,
+    ) -> DeleteResult {
+        use db::schema::api_token::dsl as token_dsl;
+        use db::schema::console_session::dsl as session_dsl;
+        use db::schema::role_assignment::dsl as role_dsl;
+        use db::schema::ssh_key::dsl as ssh_key_dsl;
+        use db::schema::user::dsl;
 
-        diesel::delete(dsl::console_session)
-            .filter(dsl::token.eq(token.clone()))
-            .execute_async(self.pool())
+        opctx
+            .authorize(
+                authz::Action::Delete,
+                &authz::FLEET.child_generic(
+                    ResourceType::User,
+                    LookupType::ById(user_id),
+                ),
+            )
+            .await?;
+
+        type TxnError = TransactionError<Error>;
+        let now = Utc::now();
+        self.pool_authorized(opctx)
+            .await?
+            .transaction(move |conn| {
+                let updated_rows = diesel::update(dsl::user)
+                    .filter(dsl::time_deleted.is_null())
+                    .filter(dsl::id.eq(user_id))
+                    .set(dsl::time_deleted.eq(now))
+                    .execute(conn)?;
+                if updated_rows == 0 {
+                    return Err(TxnError::CustomError(Error::not_found_by_id(
+                        ResourceType::User,
+                        &user_id,
+                    )));
+                }
+                diesel::delete(session_dsl::console_session)
+                    .filter(session_dsl::user_id.eq(user_id))
+                    .execute(conn)?;
+                diesel::delete(token_dsl::api_token)
+                    .filter(token_dsl::user_id.eq(user_id))
+                    .execute(conn)?;
+                diesel::delete(ssh_key_dsl::ssh_key)
+                    .filter(ssh_key_dsl::user_id.eq(user_id))
+                    .execute(conn)?;
+                diesel::delete(role_dsl::role_assignment)
+                    .filter(role_dsl::user_id.eq(user_id))
+                    .execute(conn)?;
+                Ok(())
+            })
             .await
-            .map(|_rows_deleted| ())
-            .map_err(|e| {
-                Error::internal_error(&format!(
-                    "error deleting session: {:?}",
-                    e
-                ))
+            .map_err(|e| match e {
+                TxnError::CustomError(e) => e,
+                TxnError::Pool(e) => {
+                    public_error_from_diesel_pool(e, ErrorHandler::Server)
+                }
             })
     }
 
@@ -2754,6 +6256,7 @@ impl DataStore {
                     identity: IdentityMetadataCreateParams {
                         name: u.name.clone(),
                         description: String::from(u.description),
+                        labels: Default::default(),
                     },
                 },
             )
@@ -2924,6 +6427,150 @@ impl DataStore {
             .await
             .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
     }
+
+    /// Return the roles that the given user has on the given resource through
+    /// the resource's policy
+    pub async fn role_asgn_list_for(
+        &self,
+        opctx: &OpContext,
+        user_id: Uuid,
+        resource_type: ResourceType,
+        resource_id: Uuid,
+    ) -> Result<Vec<RoleAssignment>, Error> {
+        use db::schema::role_assignment::dsl;
+
+        // As with role_asgn_builtin_list_for(), there's no resource-specific
+        // authorization check here.
+        dsl::role_assignment
+            .filter(dsl::user_id.eq(user_id))
+            .filter(dsl::resource_type.eq(resource_type.to_string()))
+            .filter(dsl::resource_id.eq(resource_id))
+            .select(RoleAssignment::as_select())
+            .load_async::<RoleAssignment>(self.pool_authorized(opctx).await?)
+            .await
+            .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
+    }
+
+    /// Fetch the role assignments that make up the policy for `authz_resource`
+    pub async fn role_assignments_fetch<R>(
+        &self,
+        opctx: &OpContext,
+        authz_resource: &R,
+    ) -> ListResultVec<RoleAssignment>
+    where
+        R: authz::ApiResource + authz::AuthorizedResource + Debug,
+    {
+        use db::schema::role_assignment::dsl;
+
+        opctx.authorize(authz::Action::Read, authz_resource).await?;
+        let (resource_type, resource_id) = policy_resource(authz_resource)?;
+
+        // TODO-scalability This should probably be paginated, but a policy is
+        // fetched and replaced as a whole.
+        dsl::role_assignment
+            .filter(dsl::resource_type.eq(resource_type.to_string()))
+            .filter(dsl::resource_id.eq(resource_id))
+            .order((dsl::user_id, dsl::role_name))
+            .select(RoleAssignment::as_select())
+            .load_async::<RoleAssignment>(self.pool_authorized(opctx).await?)
+            .await
+            .map_err(|e| public_error_from_diesel_pool(e, ErrorHandler::Server))
+    }
+
+    /// Replace the policy for `authz_resource` with `role_assignments`,
+    /// returning the new policy
+    ///
+    /// Every assignment must be for `authz_resource`.
+    pub async fn role_assignments_replace<R>(
+        &self,
+        opctx: &OpContext,
+        authz_resource: &R,
+        role_assignments: Vec<RoleAssignment>,
+    ) -> ListResultVec<RoleAssignment>
+    where
+        R: authz::ApiResource + authz::AuthorizedResource + Debug,
+    {
+        use db::schema::role_assignment::dsl;
+        use db::schema::user::dsl as user_dsl;
+        use db::schema::user_builtin::dsl as user_builtin_dsl;
+
+        opctx.authorize(authz::Action::Modify, authz_resource).await?;
+        let (resource_type, resource_id) = policy_resource(authz_resource)?;
+        let resource_type = resource_type.to_string();
+        bail_unless!(
+            role_assignments.iter().all(|r| r.resource_type == resource_type
+                && r.resource_id == resource_id),
+            "role assignment is for another resource"
+        );
+
+        #[derive(Debug, thiserror::Error)]
+        enum PolicyUpdateError {
+            #[error("no such user: {0}")]
+            NoSuchUser(Uuid),
+        }
+        type TxnError = TransactionError<PolicyUpdateError>;
+        self.pool_authorized(opctx)
+            .await?
+            .transaction(move |conn| {
+                let user_ids: BTreeSet<Uuid> =
+                    role_assignments.iter().map(|r| r.user_id).collect();
+                let user_ids_vec = user_ids.iter().copied().collect::<Vec<_>>();
+                let mut found_ids: BTreeSet<Uuid> = user_dsl::user
+                    .filter(user_dsl::time_deleted.is_null())
+                    .filter(user_dsl::id.eq_any(user_ids_vec.clone()))
+                    .select(user_dsl::id)
+                    .get_results::<Uuid>(conn)?
+                    .into_iter()
+                    .collect();
+                found_ids.extend(
+                    user_builtin_dsl::user_builtin
+                        .filter(user_builtin_dsl::time_deleted.is_null())
+                        .filter(user_builtin_dsl::id.eq_any(user_ids_vec))
+                        .select(user_builtin_dsl::id)
+                        .get_results::<Uuid>(conn)?,
+                );
+                if let Some(user_id) = user_ids.difference(&found_ids).next() {
+                    return Err(TxnError::CustomError(
+                        PolicyUpdateError::NoSuchUser(*user_id),
+                    ));
+                }
+
+                diesel::delete(dsl::role_assignment)
+                    .filter(dsl::resource_type.eq(resource_type.clone()))
+                    .filter(dsl::resource_id.eq(resource_id))
+                    .execute(conn)?;
+                if !role_assignments.is_empty() {
+                    diesel::insert_into(dsl::role_assignment)
+                        .values(role_assignments)
+                        .execute(conn)?;
+                }
+                Ok(dsl::role_assignment
+                    .filter(dsl::resource_type.eq(resource_type))
+                    .filter(dsl::resource_id.eq(resource_id))
+                    .order((dsl::user_id, dsl::role_name))
+                    .select(RoleAssignment::as_select())
+                    .get_results::<RoleAssignment>(conn)?)
+            })
+            .await
+            .map_err(|e| match e {
+                TxnError::CustomError(e) => Error::InvalidValue {
+                    label: String::from("user_id"),
+                    message: e.to_string(),
+                },
+                TxnError::Pool(e) => {
+                    public_error_from_diesel_pool(e, ErrorHandler::Server)
+                }
+            })
+    }
+}
+
+/// Returns the type and id under which roles on `authz_resource` are stored
+fn policy_resource<R: authz::ApiResource>(
+    authz_resource: &R,
+) -> Result<(ResourceType, Uuid), Error> {
+    authz_resource.db_resource().ok_or_else(|| {
+        Error::internal_error("roles cannot be assigned on this resource")
+    })
 }
 
 /// Constructs a DataStore for use in test suites that has preloaded the
@@ -2986,6 +6633,7 @@ mod test {
             identity: IdentityMetadataCreateParams {
                 name: "org".parse().unwrap(),
                 description: "desc".to_string(),
+                labels: Default::default(),
             },
         });
         let organization =
@@ -2997,6 +6645,7 @@ mod test {
                 identity: IdentityMetadataCreateParams {
                     name: "project".parse().unwrap(),
                     description: "desc".to_string(),
+                    labels: Default::default(),
                 },
             },
         );
@@ -3015,6 +6664,109 @@ mod test {
         logctx.cleanup_successful();
     }
 
+    #[tokio::test]
+    async fn test_disk_resize_claim() {
+        let logctx = dev::test_setup_log("test_disk_resize_claim");
+        let mut db = test_setup_database(&logctx.log).await;
+        let (opctx, datastore) = datastore_test(&logctx, &db).await;
+        let organization = Organization::new(params::OrganizationCreate {
+            identity: IdentityMetadataCreateParams {
+                name: "org".parse().unwrap(),
+                description: "desc".to_string(),
+                labels: Default::default(),
+            },
+        });
+        let organization =
+            datastore.organization_create(&opctx, organization).await.unwrap();
+        let project = Project::new(
+            organization.id(),
+            params::ProjectCreate {
+                identity: IdentityMetadataCreateParams {
+                    name: "project".parse().unwrap(),
+                    description: "desc".to_string(),
+                    labels: Default::default(),
+                },
+            },
+        );
+        let org = authz::FLEET.organization(
+            organization.id(),
+            LookupType::ById(organization.id()),
+        );
+        let project =
+            datastore.project_create(&opctx, &org, project).await.unwrap();
+        let size = ByteCount::from_gibibytes_u32(1);
+        let disk = datastore
+            .project_create_disk(db::model::Disk::new(
+                Uuid::new_v4(),
+                project.id(),
+                create_test_disk_create_params("disk", size),
+                db::model::DiskRuntimeState::new(),
+            ))
+            .await
+            .unwrap();
+        let authz_disk = datastore.disk_lookup_by_id(disk.id()).await.unwrap();
+
+        // A resize can claim the Disk again, but no other resize can claim it
+        // until the first releases it.
+        let resize1 = Uuid::new_v4();
+        let resize2 = Uuid::new_v4();
+        datastore
+            .disk_resize_claim(&opctx, &authz_disk, resize1, size)
+            .await
+            .unwrap();
+        datastore
+            .disk_resize_claim(&opctx, &authz_disk, resize1, size)
+            .await
+            .unwrap();
+        let error = datastore
+            .disk_resize_claim(&opctx, &authz_disk, resize2, size)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error,
+            Error::InvalidRequest {
+                message: String::from("disk is already being resized")
+            }
+        );
+        datastore
+            .disk_resize_release(&opctx, &authz_disk, resize2)
+            .await
+            .unwrap();
+        datastore
+            .disk_resize_claim(&opctx, &authz_disk, resize2, size)
+            .await
+            .unwrap_err();
+        datastore
+            .disk_resize_release(&opctx, &authz_disk, resize1)
+            .await
+            .unwrap();
+
+        // A resize planned from a size the Disk no longer has can't claim it.
+        datastore
+            .disk_update_size(
+                &opctx,
+                &authz_disk,
+                ByteCount::from_gibibytes_u32(2),
+            )
+            .await
+            .unwrap();
+        let error = datastore
+            .disk_resize_claim(&opctx, &authz_disk, resize2, size)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error,
+            Error::InvalidRequest {
+                message: String::from(
+                    "disk changed size while the resize was starting"
+                )
+            }
+        );
+
+        db.cleanup().await.unwrap();
+        logctx.cleanup_successful();
+    }
+
     #[tokio::test]
     async fn test_session_methods() {
         let logctx = dev::test_setup_log("test_session_methods");
@@ -3074,7 +6826,12 @@ mod test {
         let bogus_addr =
             SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 8080);
         let sled_id = Uuid::new_v4();
-        let sled = Sled::new(sled_id, bogus_addr.clone());
+        let sled = Sled::new(
+            sled_id,
+            bogus_addr.clone(),
+            16,
+            ByteCount::from_gibibytes_u32(64),
+        );
         datastore.sled_upsert(sled).await.unwrap();
         sled_id
     }
@@ -3105,6 +6862,7 @@ mod test {
             identity: IdentityMetadataCreateParams {
                 name: Name::try_from(name.to_string()).unwrap(),
                 description: name.to_string(),
+                labels: Default::default(),
             },
             snapshot_id: None,
             size,
@@ -3375,6 +7133,7 @@ mod test {
             external::IdentityMetadataCreateParams {
                 name: external::Name::try_from(String::from("name")).unwrap(),
                 description: String::from("description"),
+                labels: Default::default(),
             },
             external::Ipv4Net("172.30.0.0/22".parse().unwrap()),
             external::Ipv6Net("fd00::/64".parse().unwrap()),
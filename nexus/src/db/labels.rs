@@ -0,0 +1,84 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Interface for filtering database queries by resources' labels.
+
+use diesel::helper_types::*;
+use diesel::pg::Pg;
+use diesel::query_builder::AsQuery;
+use diesel::query_builder::BoxedSelectStatement;
+use diesel::query_dsl::methods as query_methods;
+use diesel::sql_types::{Jsonb, Text};
+use diesel::AppearsOnTable;
+use diesel::Column;
+use diesel::{ExpressionMethods, IntoSql, QueryDsl};
+use omicron_common::api::external::LabelRequirement;
+use omicron_common::api::external::LabelSelector;
+
+// "jsonb @> jsonb": the left operand contains all of the right operand's
+// key/value pairs.
+diesel::infix_operator!(JsonbContains, " @> ", backend: Pg);
+// "jsonb ? text": the left operand has the right operand as a top-level key.
+diesel::infix_operator!(JsonbHasKey, " ? ", backend: Pg);
+
+// Shorthand alias for "the SQL type of the whole table".
+type TableSqlType<T> = <T as AsQuery>::SqlType;
+
+// Shorthand alias for the type made from "table.into_boxed()".
+type BoxedQuery<T> = BoxedSelectStatement<'static, TableSqlType<T>, T, Pg>;
+
+// Shorthand aliases for the expressions we filter on.
+type Contains<C> = JsonbContains<C, AsExprOf<serde_json::Value, Jsonb>>;
+type HasKey<C> = JsonbHasKey<C, AsExprOf<String, Text>>;
+
+/// Restricts `query` to rows whose labels, stored in `column`, match
+/// `selector`.
+pub fn filter_by_labels<T, C>(
+    mut query: BoxedQuery<T>,
+    column: C,
+    selector: &LabelSelector,
+) -> BoxedQuery<T>
+where
+    // T is a table which can create a BoxedQuery.
+    T: diesel::Table,
+    // C is a JSONB column which appears in T.
+    C: 'static + Column<SqlType = Jsonb> + Copy + AppearsOnTable<T>,
+    // Defines the filters which can be applied to "query", and tells the
+    // compiler we're gonna output a BoxedQuery each time.
+    BoxedQuery<T>:
+        query_methods::FilterDsl<Contains<C>, Output = BoxedQuery<T>>,
+    BoxedQuery<T>:
+        query_methods::FilterDsl<Eq<Contains<C>, bool>, Output = BoxedQuery<T>>,
+    BoxedQuery<T>: query_methods::FilterDsl<HasKey<C>, Output = BoxedQuery<T>>,
+    BoxedQuery<T>:
+        query_methods::FilterDsl<Eq<HasKey<C>, bool>, Output = BoxedQuery<T>>,
+{
+    let contains = |key: &str, value: &str| {
+        let mut pair = serde_json::Map::new();
+        pair.insert(key.to_string(), serde_json::Value::from(value));
+        JsonbContains::new(
+            column,
+            serde_json::Value::Object(pair).into_sql::<Jsonb>(),
+        )
+    };
+    let has_key = |key: &str| {
+        JsonbHasKey::new(column, key.to_string().into_sql::<Text>())
+    };
+
+    for requirement in selector.requirements() {
+        query = match requirement {
+            LabelRequirement::Equals { key, value } => {
+                query.filter(contains(key, value))
+            }
+            LabelRequirement::NotEquals { key, value } => {
+                query.filter(contains(key, value).eq(false))
+            }
+            LabelRequirement::Exists { key } => query.filter(has_key(key)),
+            LabelRequirement::NotExists { key } => {
+                query.filter(has_key(key).eq(false))
+            }
+        };
+    }
+    query
+}
@@ -16,8 +16,10 @@ pub mod datastore;
 mod error;
 mod explain;
 pub mod fixed_data;
+mod labels;
 mod pagination;
 mod pool;
+mod quota;
 mod saga_recovery;
 mod saga_types;
 mod sec_store;
@@ -6,19 +6,26 @@
 
 use crate::db::collection_insert::DatastoreCollection;
 use crate::db::identity::{Asset, Resource};
+use crate::db::saga_types::{SagaCachedState, SagaNodeEvent};
 use crate::db::schema::{
-    console_session, dataset, disk, instance, metric_producer,
-    network_interface, organization, oximeter, project, rack, region,
-    role_assignment_builtin, role_builtin, router_route, sled, user_builtin,
-    vpc, vpc_firewall_rule, vpc_router, vpc_subnet, zpool,
+    affinity_group, affinity_group_claim, api_token, audit_log,
+    console_session, dataset, disk, dns_zone, floating_ip, idempotency_key,
+    instance, ip_pool, ip_pool_range, metric_producer, network_interface,
+    nexus, operation, organization, oximeter, project, project_event,
+    project_webhook, quota, rack, region, role_assignment,
+    role_assignment_builtin, role_builtin, router_route, sled, snapshot,
+    ssh_key, user, user_builtin, vpc, vpc_firewall_rule, vpc_router,
+    vpc_subnet, zpool,
 };
 use crate::defaults;
+use crate::events::EventKind;
 use crate::external_api::params;
 use crate::internal_api;
 use chrono::{DateTime, Utc};
 use db_macros::{Asset, Resource};
 use diesel::backend::{Backend, BinaryRawValue, RawValue};
 use diesel::deserialize::{self, FromSql};
+use diesel::pg::Pg;
 use diesel::serialize::{self, IsNull, ToSql};
 use diesel::sql_types;
 use ipnetwork::IpNetwork;
@@ -280,6 +287,48 @@ where
     }
 }
 
+/// Representation of a [`u32`] in the database.
+/// We need this because the database does not support unsigned types.
+/// This handles converting from the database's INT8 to the actual u32.
+#[derive(
+    Copy, Clone, Debug, Eq, Ord, PartialEq, PartialOrd, AsExpression, FromSqlRow,
+)]
+#[sql_type = "sql_types::BigInt"]
+#[repr(transparent)]
+pub struct SqlU32(pub u32);
+
+NewtypeFrom! { () pub struct SqlU32(u32); }
+NewtypeDeref! { () pub struct SqlU32(u32); }
+
+impl SqlU32 {
+    pub fn new(value: u32) -> Self {
+        Self(value)
+    }
+}
+
+impl<DB> ToSql<sql_types::BigInt, DB> for SqlU32
+where
+    DB: Backend,
+    i64: ToSql<sql_types::BigInt, DB>,
+{
+    fn to_sql<W: std::io::Write>(
+        &self,
+        out: &mut serialize::Output<W, DB>,
+    ) -> serialize::Result {
+        i64::from(self.0).to_sql(out)
+    }
+}
+
+impl<DB> FromSql<sql_types::BigInt, DB> for SqlU32
+where
+    DB: Backend,
+    i64: FromSql<sql_types::BigInt, DB>,
+{
+    fn from_sql(bytes: RawValue<DB>) -> deserialize::Result<Self> {
+        u32::try_from(i64::from_sql(bytes)?).map(SqlU32).map_err(|e| e.into())
+    }
+}
+
 #[derive(Copy, Clone, Debug, AsExpression, FromSqlRow)]
 #[sql_type = "sql_types::BigInt"]
 pub struct InstanceCpuCount(pub external::InstanceCpuCount);
@@ -485,6 +534,43 @@ where
     }
 }
 
+/// Newtype wrapper around [`external::Labels`], stored as a JSON object
+#[derive(
+    Clone,
+    Debug,
+    Default,
+    AsExpression,
+    FromSqlRow,
+    Serialize,
+    Deserialize,
+    PartialEq,
+)]
+#[sql_type = "sql_types::Jsonb"]
+pub struct Labels(pub external::Labels);
+
+NewtypeFrom! { () pub struct Labels(external::Labels); }
+NewtypeDeref! { () pub struct Labels(external::Labels); }
+
+impl ToSql<sql_types::Jsonb, Pg> for Labels {
+    fn to_sql<W: std::io::Write>(
+        &self,
+        out: &mut serialize::Output<W, Pg>,
+    ) -> serialize::Result {
+        let value = serde_json::to_value(&self.0)?;
+        <serde_json::Value as ToSql<sql_types::Jsonb, Pg>>::to_sql(&value, out)
+    }
+}
+
+impl FromSql<sql_types::Jsonb, Pg> for Labels {
+    fn from_sql(bytes: RawValue<Pg>) -> deserialize::Result<Self> {
+        let value =
+            <serde_json::Value as FromSql<sql_types::Jsonb, Pg>>::from_sql(
+                bytes,
+            )?;
+        Ok(Labels(serde_json::from_value(value)?))
+    }
+}
+
 // NOTE: This object is not currently stored in the database.
 //
 // However, it likely will be in the future - for the single-rack
@@ -509,16 +595,27 @@ pub struct Sled {
     pub ip: ipnetwork::IpNetwork,
     // TODO: Make use of SqlU16
     pub port: i32,
+
+    // Capacity available to Instances.
+    pub usable_hardware_threads: SqlU32,
+    pub usable_physical_ram: ByteCount,
 }
 
 impl Sled {
-    pub fn new(id: Uuid, addr: SocketAddr) -> Self {
+    pub fn new(
+        id: Uuid,
+        addr: SocketAddr,
+        usable_hardware_threads: u32,
+        usable_physical_ram: external::ByteCount,
+    ) -> Self {
         Self {
             identity: SledIdentity::new(id),
             time_deleted: None,
             rcgen: Generation::new(),
             ip: addr.ip().into(),
             port: addr.port().into(),
+            usable_hardware_threads: SqlU32::new(usable_hardware_threads),
+            usable_physical_ram: usable_physical_ram.into(),
         }
     }
 
@@ -657,6 +754,10 @@ impl Dataset {
         // TODO: avoid this unwrap
         SocketAddr::new(self.ip.ip(), u16::try_from(self.port).unwrap())
     }
+
+    pub fn kind(&self) -> internal_api::params::DatasetKind {
+        self.kind.0
+    }
 }
 
 // Datasets contain regions
@@ -752,15 +853,19 @@ pub struct Organization {
 
     /// child resource generation number, per RFD 192
     pub rcgen: Generation,
+
+    pub labels: Labels,
 }
 
 impl Organization {
     /// Creates a new database Organization object.
     pub fn new(params: params::OrganizationCreate) -> Self {
         let id = Uuid::new_v4();
+        let labels = params.identity.labels.clone().into();
         Self {
             identity: OrganizationIdentity::new(id, params.identity),
             rcgen: Generation::new(),
+            labels,
         }
     }
 }
@@ -778,6 +883,7 @@ impl DatastoreCollection<Project> for Organization {
 pub struct OrganizationUpdate {
     pub name: Option<Name>,
     pub description: Option<String>,
+    pub labels: Option<Labels>,
     pub time_modified: DateTime<Utc>,
 }
 
@@ -786,6 +892,7 @@ impl From<params::OrganizationUpdate> for OrganizationUpdate {
         Self {
             name: params.identity.name.map(|n| n.into()),
             description: params.identity.description,
+            labels: params.identity.labels.map(Labels),
             time_modified: Utc::now(),
         }
     }
@@ -799,14 +906,17 @@ pub struct Project {
     identity: ProjectIdentity,
 
     pub organization_id: Uuid,
+    pub labels: Labels,
 }
 
 impl Project {
     /// Creates a new database Project object.
     pub fn new(organization_id: Uuid, params: params::ProjectCreate) -> Self {
+        let labels = params.identity.labels.clone().into();
         Self {
             identity: ProjectIdentity::new(Uuid::new_v4(), params.identity),
             organization_id,
+            labels,
         }
     }
 }
@@ -817,6 +927,7 @@ impl Project {
 pub struct ProjectUpdate {
     pub name: Option<Name>,
     pub description: Option<String>,
+    pub labels: Option<Labels>,
     pub time_modified: DateTime<Utc>,
 }
 
@@ -825,11 +936,75 @@ impl From<params::ProjectUpdate> for ProjectUpdate {
         Self {
             name: params.identity.name.map(Name),
             description: params.identity.description,
+            labels: params.identity.labels.map(Labels),
             time_modified: Utc::now(),
         }
     }
 }
 
+/// Limits on the resources provisioned within an Organization or Project
+///
+/// A limit of `None` is unlimited.
+#[derive(Queryable, Insertable, AsChangeset, Clone, Debug, Selectable)]
+#[table_name = "quota"]
+// Updating a quota replaces all of its limits, so a limit that is set to None
+// must be cleared rather than ignored.
+#[changeset_options(treat_none_as_null = "true")]
+pub struct Quota {
+    /// id of the Organization or Project to which this quota applies
+    pub collection_id: Uuid,
+    pub time_modified: DateTime<Utc>,
+
+    /// number of Instances
+    pub instances: Option<i64>,
+    /// total vCPUs of all Instances
+    pub vcpus: Option<i64>,
+    /// total memory of all Instances
+    pub memory: Option<ByteCount>,
+    /// total size of all Disks
+    pub disk_bytes: Option<ByteCount>,
+    /// number of VPCs
+    pub vpcs: Option<i64>,
+}
+
+impl Quota {
+    /// Returns a quota on `collection_id` that does not limit anything
+    pub fn unlimited(collection_id: Uuid) -> Self {
+        Self {
+            collection_id,
+            time_modified: Utc::now(),
+            instances: None,
+            vcpus: None,
+            memory: None,
+            disk_bytes: None,
+            vpcs: None,
+        }
+    }
+
+    pub fn new(collection_id: Uuid, params: params::QuotaUpdate) -> Self {
+        Self {
+            collection_id,
+            time_modified: Utc::now(),
+            instances: params.instances.map(i64::from),
+            vcpus: params.vcpus.map(i64::from),
+            memory: params.memory.map(ByteCount),
+            disk_bytes: params.disk_bytes.map(ByteCount),
+            vpcs: params.vpcs.map(i64::from),
+        }
+    }
+}
+
+/// The resources provisioned within an Organization or Project, as counted
+/// against its [`Quota`]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ResourceUsage {
+    pub instances: i64,
+    pub vcpus: i64,
+    pub memory: i64,
+    pub disk_bytes: i64,
+    pub vpcs: i64,
+}
+
 /// An Instance (VM).
 #[derive(Queryable, Insertable, Debug, Selectable, Resource)]
 #[table_name = "instance"]
@@ -843,6 +1018,17 @@ pub struct Instance {
     /// runtime state of the Instance
     #[diesel(embed)]
     pub runtime_state: InstanceRuntimeState,
+
+    /// id for the affinity group containing this Instance, if any
+    pub affinity_group_id: Option<Uuid>,
+
+    pub labels: Labels,
+
+    /// user data for the guest's initialization system
+    pub user_data: Vec<u8>,
+
+    /// SSH public keys to authorize for logging in to the guest
+    pub ssh_public_keys: Vec<String>,
 }
 
 impl Instance {
@@ -850,16 +1036,35 @@ impl Instance {
         instance_id: Uuid,
         project_id: Uuid,
         params: &params::InstanceCreate,
+        affinity_group_id: Option<Uuid>,
         runtime: InstanceRuntimeState,
     ) -> Self {
         let identity =
             InstanceIdentity::new(instance_id, params.identity.clone());
-        Self { identity, project_id, runtime_state: runtime }
+        Self {
+            identity,
+            project_id,
+            runtime_state: runtime,
+            affinity_group_id,
+            labels: params.identity.labels.clone().into(),
+            user_data: params.user_data.clone(),
+            ssh_public_keys: params.ssh_public_keys.clone(),
+        }
     }
 
     pub fn runtime(&self) -> &InstanceRuntimeState {
         &self.runtime_state
     }
+
+    /// Returns the data the guest initializes itself from, to be passed along
+    /// to the sled agent.  Unlike the rest of the Instance, this never appears
+    /// in the external API.
+    pub fn guest_metadata(&self) -> internal::sled_agent::GuestMetadata {
+        internal::sled_agent::GuestMetadata {
+            user_data: self.user_data.clone(),
+            ssh_public_keys: self.ssh_public_keys.clone(),
+        }
+    }
 }
 
 /// Conversion to the external API type.
@@ -867,15 +1072,33 @@ impl Into<external::Instance> for Instance {
     fn into(self) -> external::Instance {
         external::Instance {
             identity: self.identity(),
+            labels: self.labels.0.clone(),
             project_id: self.project_id,
             ncpus: self.runtime().ncpus.into(),
             memory: self.runtime().memory.into(),
             hostname: self.runtime().hostname.clone(),
+            affinity_group_id: self.affinity_group_id,
             runtime: self.runtime().clone().into(),
         }
     }
 }
 
+/// Describes a set of updates for the [`Instance`] model.
+///
+/// Changes to the vCPUs or memory go through [`InstanceRuntimeState`] instead.
+#[derive(AsChangeset)]
+#[table_name = "instance"]
+pub struct InstanceUpdate {
+    pub labels: Option<Labels>,
+    pub time_modified: DateTime<Utc>,
+}
+
+impl From<params::InstanceUpdate> for InstanceUpdate {
+    fn from(params: params::InstanceUpdate) -> Self {
+        Self { labels: params.labels.map(Labels), time_modified: Utc::now() }
+    }
+}
+
 /// Runtime state of the Instance, including the actual running state and minimal
 /// metadata
 ///
@@ -996,6 +1219,72 @@ impl InstanceState {
     }
 }
 
+impl_enum_type!(
+    #[derive(SqlType, Debug)]
+    #[postgres(type_name = "affinity_policy", type_schema = "public")]
+    pub struct AffinityPolicyEnum;
+
+    #[derive(Clone, Debug, AsExpression, FromSqlRow)]
+    #[sql_type = "AffinityPolicyEnum"]
+    pub struct AffinityPolicy(pub external::AffinityPolicy);
+
+    // Enum values
+    Affinity => b"affinity"
+    AntiAffinity => b"anti_affinity"
+);
+
+/// A group of Instances whose placement is constrained relative to one
+/// another
+#[derive(Queryable, Insertable, Clone, Debug, Selectable, Resource)]
+#[table_name = "affinity_group"]
+pub struct AffinityGroup {
+    #[diesel(embed)]
+    identity: AffinityGroupIdentity,
+
+    /// id for the project containing this group
+    pub project_id: Uuid,
+    pub policy: AffinityPolicy,
+}
+
+impl AffinityGroup {
+    pub fn new(
+        group_id: Uuid,
+        project_id: Uuid,
+        params: params::AffinityGroupCreate,
+    ) -> Self {
+        let identity = AffinityGroupIdentity::new(group_id, params.identity);
+        Self { identity, project_id, policy: AffinityPolicy(params.policy) }
+    }
+}
+
+/// A sled chosen for an Instance in an affinity group, held while the
+/// Instance is being created
+#[derive(Queryable, Insertable, Clone, Debug, Selectable)]
+#[table_name = "affinity_group_claim"]
+pub struct AffinityGroupClaim {
+    pub instance_id: Uuid,
+    pub affinity_group_id: Uuid,
+    pub sled_id: Uuid,
+}
+
+#[derive(AsChangeset)]
+#[table_name = "affinity_group"]
+pub struct AffinityGroupUpdate {
+    pub name: Option<Name>,
+    pub description: Option<String>,
+    pub time_modified: DateTime<Utc>,
+}
+
+impl From<params::AffinityGroupUpdate> for AffinityGroupUpdate {
+    fn from(params: params::AffinityGroupUpdate) -> Self {
+        Self {
+            name: params.identity.name.map(Name),
+            description: params.identity.description,
+            time_modified: Utc::now(),
+        }
+    }
+}
+
 /// A Disk (network block device).
 #[derive(
     Queryable,
@@ -1029,6 +1318,10 @@ pub struct Disk {
     /// disk)
     #[column_name = "origin_snapshot"]
     pub create_snapshot_id: Option<Uuid>,
+    /// id of the resize in progress, if any
+    pub resize_id: Option<Uuid>,
+
+    pub labels: Labels,
 }
 
 impl Disk {
@@ -1038,6 +1331,7 @@ impl Disk {
         params: params::DiskCreate,
         runtime_initial: DiskRuntimeState,
     ) -> Self {
+        let labels = params.identity.labels.clone().into();
         let identity = DiskIdentity::new(disk_id, params.identity);
         Self {
             identity,
@@ -1046,6 +1340,8 @@ impl Disk {
             runtime_state: runtime_initial,
             size: params.size.into(),
             create_snapshot_id: params.snapshot_id,
+            resize_id: None,
+            labels,
         }
     }
 
@@ -1064,6 +1360,7 @@ impl Into<external::Disk> for Disk {
         let device_path = format!("/mnt/{}", self.name().as_str());
         external::Disk {
             identity: self.identity(),
+            labels: self.labels.0.clone(),
             project_id: self.project_id,
             snapshot_id: self.create_snapshot_id,
             size: self.size.into(),
@@ -1073,6 +1370,20 @@ impl Into<external::Disk> for Disk {
     }
 }
 
+/// Describes a set of updates for the [`Disk`] model.
+#[derive(AsChangeset)]
+#[table_name = "disk"]
+pub struct DiskUpdate {
+    pub labels: Option<Labels>,
+    pub time_modified: DateTime<Utc>,
+}
+
+impl From<params::DiskUpdate> for DiskUpdate {
+    fn from(params: params::DiskUpdate) -> Self {
+        Self { labels: params.labels.map(Labels), time_modified: Utc::now() }
+    }
+}
+
 #[derive(
     AsChangeset,
     Clone,
@@ -1191,6 +1502,48 @@ impl Into<external::DiskState> for DiskState {
     }
 }
 
+/// A point-in-time snapshot of a Disk
+#[derive(
+    Queryable,
+    Insertable,
+    Clone,
+    Debug,
+    Selectable,
+    Resource,
+    Serialize,
+    Deserialize,
+)]
+#[table_name = "snapshot"]
+pub struct Snapshot {
+    #[diesel(embed)]
+    identity: SnapshotIdentity,
+
+    /// id for the project containing this Snapshot
+    pub project_id: Uuid,
+    /// id for the Disk from which this Snapshot was taken
+    pub disk_id: Uuid,
+
+    /// size of the Disk when the Snapshot was taken
+    #[column_name = "size_bytes"]
+    pub size: ByteCount,
+}
+
+impl Snapshot {
+    pub fn new(
+        snapshot_id: Uuid,
+        disk: &Disk,
+        params: params::SnapshotCreate,
+    ) -> Self {
+        let identity = SnapshotIdentity::new(snapshot_id, params.identity);
+        Self {
+            identity,
+            project_id: disk.project_id,
+            disk_id: disk.id(),
+            size: disk.size,
+        }
+    }
+}
+
 /// Information announced by a metric server, used so that clients can contact it and collect
 /// available metric data from it.
 #[derive(Queryable, Insertable, Debug, Clone, Selectable, Asset)]
@@ -1257,6 +1610,70 @@ impl OximeterInfo {
             port: info.address.port().into(),
         }
     }
+
+    pub fn address(&self) -> SocketAddr {
+        // TODO: avoid this unwrap
+        SocketAddr::new(self.ip.ip(), u16::try_from(self.port).unwrap())
+    }
+}
+
+/// A Nexus server, registered with the control plane when it starts.
+#[derive(Queryable, Insertable, Debug, Clone, Copy)]
+#[table_name = "nexus"]
+pub struct NexusInfo {
+    /// The ID for this Nexus instance.
+    pub id: Uuid,
+    /// When this resource was created.
+    pub time_created: DateTime<Utc>,
+    /// When this resource was last modified.
+    pub time_modified: DateTime<Utc>,
+    /// The address on which this Nexus instance serves its internal API
+    pub ip: ipnetwork::IpNetwork,
+    // TODO: Make use of SqlU16
+    pub port: i32,
+}
+
+impl NexusInfo {
+    pub fn new(id: Uuid, address: SocketAddr) -> Self {
+        let now = Utc::now();
+        Self {
+            id,
+            time_created: now,
+            time_modified: now,
+            ip: address.ip().into(),
+            port: address.port().into(),
+        }
+    }
+
+    pub fn address(&self) -> SocketAddr {
+        // TODO: avoid this unwrap
+        SocketAddr::new(self.ip.ip(), u16::try_from(self.port).unwrap())
+    }
+}
+
+/// The generation of an internal DNS zone's contents most recently sent to
+/// the DNS servers.
+#[derive(Queryable, Insertable, Debug, Clone)]
+#[table_name = "dns_zone"]
+pub struct DnsZone {
+    pub name: String,
+    pub time_modified: DateTime<Utc>,
+    pub generation: Generation,
+}
+
+impl DnsZone {
+    /// Returns the record for a zone that has never been sent to the DNS
+    /// servers.
+    ///
+    /// DNS servers start out serving the first generation, with no records, so
+    /// the first contents sent are the second generation.
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            time_modified: Utc::now(),
+            generation: Generation::new().next().into(),
+        }
+    }
 }
 
 #[derive(Queryable, Insertable, Clone, Debug, Selectable, Resource)]
@@ -1273,6 +1690,8 @@ pub struct Vpc {
     /// firewall generation number, used as a child resource generation number
     /// per RFD 192
     pub firewall_gen: Generation,
+
+    pub labels: Labels,
 }
 
 impl Vpc {
@@ -1282,6 +1701,7 @@ impl Vpc {
         system_router_id: Uuid,
         params: params::VpcCreate,
     ) -> Result<Self, external::Error> {
+        let labels = params.identity.labels.clone().into();
         let identity = VpcIdentity::new(vpc_id, params.identity);
         let ipv6_prefix = match params.ipv6_prefix {
             None => defaults::random_vpc_ipv6_prefix(),
@@ -1304,6 +1724,7 @@ impl Vpc {
             ipv6_prefix,
             dns_name: params.dns_name.into(),
             firewall_gen: Generation::new(),
+            labels,
         })
     }
 }
@@ -1320,6 +1741,7 @@ impl DatastoreCollection<VpcFirewallRule> for Vpc {
 pub struct VpcUpdate {
     pub name: Option<Name>,
     pub description: Option<String>,
+    pub labels: Option<Labels>,
     pub time_modified: DateTime<Utc>,
     pub dns_name: Option<Name>,
 }
@@ -1329,6 +1751,7 @@ impl From<params::VpcUpdate> for VpcUpdate {
         Self {
             name: params.identity.name.map(Name),
             description: params.identity.description,
+            labels: params.identity.labels.map(Labels),
             time_modified: Utc::now(),
             dns_name: params.dns_name.map(Name),
         }
@@ -1344,6 +1767,7 @@ pub struct VpcSubnet {
     pub vpc_id: Uuid,
     pub ipv4_block: Ipv4Net,
     pub ipv6_block: Ipv6Net,
+    pub custom_router_id: Option<Uuid>,
 }
 
 impl VpcSubnet {
@@ -1364,6 +1788,7 @@ impl VpcSubnet {
             vpc_id,
             ipv4_block: Ipv4Net(ipv4_block),
             ipv6_block: Ipv6Net(ipv6_block),
+            custom_router_id: None,
         }
     }
 
@@ -1875,6 +2300,7 @@ impl VpcFirewallRule {
             external::IdentityMetadataCreateParams {
                 name: rule.name.clone(),
                 description: rule.description.clone(),
+                labels: Default::default(),
             },
         );
         Self {
@@ -1996,10 +2422,87 @@ impl From<NetworkInterface> for external::NetworkInterface {
             subnet_id: iface.subnet_id,
             ip: iface.ip.ip(),
             mac: *iface.mac,
+            external_ip: None,
+        }
+    }
+}
+
+/// A fleet-wide set of ranges of external IP addresses
+#[derive(Queryable, Insertable, Clone, Debug, Selectable, Resource)]
+#[table_name = "ip_pool"]
+pub struct IpPool {
+    #[diesel(embed)]
+    identity: IpPoolIdentity,
+}
+
+impl IpPool {
+    pub fn new(pool_id: Uuid, params: params::IpPoolCreate) -> Self {
+        Self { identity: IpPoolIdentity::new(pool_id, params.identity) }
+    }
+}
+
+/// An inclusive range of addresses in an [`IpPool`]
+#[derive(Queryable, Insertable, Clone, Debug, Selectable)]
+#[table_name = "ip_pool_range"]
+pub struct IpPoolRange {
+    pub id: Uuid,
+    pub time_created: DateTime<Utc>,
+    pub time_deleted: Option<DateTime<Utc>>,
+    pub ip_pool_id: Uuid,
+    pub first_address: IpNetwork,
+    pub last_address: IpNetwork,
+}
+
+impl IpPoolRange {
+    pub fn new(ip_pool_id: Uuid, first: IpAddr, last: IpAddr) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            time_created: Utc::now(),
+            time_deleted: None,
+            ip_pool_id,
+            first_address: IpNetwork::from(first),
+            last_address: IpNetwork::from(last),
         }
     }
 }
 
+/// A not fully constructed FloatingIp, whose address has not yet been
+/// allocated from its pool
+#[derive(Clone, Debug)]
+pub struct IncompleteFloatingIp {
+    pub identity: FloatingIpIdentity,
+    pub project_id: Uuid,
+    pub ip_pool_id: Uuid,
+}
+
+impl IncompleteFloatingIp {
+    pub fn new(
+        floating_ip_id: Uuid,
+        project_id: Uuid,
+        ip_pool_id: Uuid,
+        params: params::FloatingIpCreate,
+    ) -> Self {
+        let identity = FloatingIpIdentity::new(floating_ip_id, params.identity);
+        Self { identity, project_id, ip_pool_id }
+    }
+}
+
+/// An external address allocated from an [`IpPool`] into a Project, which
+/// may be attached to the primary network interface of one of its Instances
+#[derive(Queryable, Insertable, Clone, Debug, Selectable, Resource)]
+#[table_name = "floating_ip"]
+pub struct FloatingIp {
+    #[diesel(embed)]
+    pub identity: FloatingIpIdentity,
+
+    pub project_id: Uuid,
+    pub ip_pool_id: Uuid,
+    pub ip_pool_range_id: Uuid,
+    pub ip: IpNetwork,
+    pub instance_id: Option<Uuid>,
+    pub network_interface_id: Option<Uuid>,
+}
+
 // TODO: `struct SessionToken(String)` for session token
 
 #[derive(Queryable, Insertable, Clone, Debug, Selectable)]
@@ -2018,6 +2521,305 @@ impl ConsoleSession {
     }
 }
 
+impl_enum_type!(
+    #[derive(SqlType, Debug)]
+    #[postgres(type_name = "api_token_scope", type_schema = "public")]
+    pub struct ApiTokenScopeEnum;
+
+    #[derive(Clone, Copy, Debug, AsExpression, FromSqlRow, PartialEq)]
+    #[sql_type = "ApiTokenScopeEnum"]
+    pub struct ApiTokenScope(pub params::ApiTokenScope);
+
+    // Enum values
+    ReadWrite => b"read_write"
+    ReadOnly => b"read_only"
+);
+
+impl From<params::ApiTokenScope> for ApiTokenScope {
+    fn from(scope: params::ApiTokenScope) -> Self {
+        Self(scope)
+    }
+}
+
+/// An API token that a user created for automation
+///
+/// Only a hash of the token itself is stored.  The token is only ever shown to
+/// the user when it's created.
+#[derive(Queryable, Insertable, Clone, Debug, Selectable)]
+#[table_name = "api_token"]
+pub struct ApiToken {
+    pub id: Uuid,
+    pub description: String,
+    pub time_created: DateTime<Utc>,
+    pub time_expires: Option<DateTime<Utc>>,
+    pub user_id: Uuid,
+    pub token_hash: String,
+    pub scope: ApiTokenScope,
+}
+
+impl ApiToken {
+    pub fn new(
+        user_id: Uuid,
+        token_hash: String,
+        params: params::ApiTokenCreate,
+    ) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            description: params.description,
+            time_created: Utc::now(),
+            time_expires: params.time_expires,
+            user_id,
+            token_hash,
+            scope: params.scope.into(),
+        }
+    }
+}
+
+/// An idempotency key that a client provided with a create request, along
+/// with the request's result once it has succeeded
+///
+/// See [`crate::idempotency`].
+#[derive(Queryable, Insertable, Clone, Debug, Selectable)]
+#[table_name = "idempotency_key"]
+pub struct IdempotencyKey {
+    pub actor_id: Uuid,
+    pub client_key: String,
+    pub time_created: DateTime<Utc>,
+    pub http_method: String,
+    pub http_path: String,
+    pub request_hash: String,
+    pub saga_id: Option<Uuid>,
+    pub response_body: Option<serde_json::Value>,
+}
+
+impl IdempotencyKey {
+    pub fn new(
+        actor_id: Uuid,
+        key: &crate::idempotency::IdempotencyKey,
+        request_hash: String,
+    ) -> Self {
+        Self {
+            actor_id,
+            client_key: key.key.clone(),
+            time_created: Utc::now(),
+            http_method: key.http_method.clone(),
+            http_path: key.http_path.clone(),
+            request_hash,
+            saga_id: None,
+            response_body: None,
+        }
+    }
+
+    /// Returns whether `other` describes the same request as this one
+    pub fn same_request(&self, other: &IdempotencyKey) -> bool {
+        self.http_method == other.http_method
+            && self.http_path == other.http_path
+            && self.request_hash == other.request_hash
+    }
+}
+
+/// A long-running operation carried out by a saga on behalf of the external
+/// API
+///
+/// The operation's id is the id of its saga, which records the operation's
+/// progress.  See [`OperationState`].
+#[derive(Queryable, Insertable, Clone, Debug, Selectable)]
+#[table_name = "operation"]
+pub struct Operation {
+    pub id: Uuid,
+    pub time_created: DateTime<Utc>,
+    pub project_id: Uuid,
+    pub target_type: String,
+    pub target_id: Option<Uuid>,
+}
+
+impl Operation {
+    pub fn new(
+        saga_id: Uuid,
+        project_id: Uuid,
+        target_type: external::ResourceType,
+        target_id: Option<Uuid>,
+    ) -> Self {
+        Self {
+            id: saga_id,
+            time_created: Utc::now(),
+            project_id,
+            target_type: target_type.to_string(),
+            target_id,
+        }
+    }
+}
+
+/// An [`Operation`] along with the state of the saga carrying it out
+#[derive(Clone, Debug)]
+pub struct OperationState {
+    pub operation: Operation,
+    /// name of the saga's template, which says what the operation does
+    pub template_name: String,
+    pub saga_state: SagaCachedState,
+    pub events: Vec<SagaNodeEvent>,
+}
+
+/// An SSH public key that a user uploaded
+#[derive(Queryable, Insertable, Clone, Debug, Selectable)]
+#[table_name = "ssh_key"]
+pub struct SshKey {
+    pub id: Uuid,
+    pub description: String,
+    pub time_created: DateTime<Utc>,
+    pub user_id: Uuid,
+    pub public_key: String,
+    pub fingerprint: String,
+}
+
+impl SshKey {
+    pub fn new(
+        user_id: Uuid,
+        description: String,
+        public_key: String,
+        fingerprint: String,
+    ) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            description,
+            time_created: Utc::now(),
+            user_id,
+            public_key,
+            fingerprint,
+        }
+    }
+}
+
+/// An entry in the audit log of mutating external API calls
+#[derive(Queryable, Insertable, Clone, Debug, Selectable)]
+#[table_name = "audit_log"]
+pub struct AuditLogEntry {
+    pub id: Uuid,
+    pub time_created: DateTime<Utc>,
+    pub actor_id: Option<Uuid>,
+    pub request_id: String,
+    pub http_method: String,
+    pub http_path: String,
+    pub resource_id: Option<Uuid>,
+    pub http_status: i32,
+}
+
+impl AuditLogEntry {
+    pub fn new(
+        actor_id: Option<Uuid>,
+        request_id: String,
+        http_method: String,
+        http_path: String,
+        resource_id: Option<Uuid>,
+        http_status: http::StatusCode,
+    ) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            time_created: Utc::now(),
+            actor_id,
+            request_id,
+            http_method,
+            http_path,
+            resource_id,
+            http_status: i32::from(http_status.as_u16()),
+        }
+    }
+}
+
+/// An entry in a Project's event log
+#[derive(Queryable, Insertable, Clone, Debug, Selectable)]
+#[table_name = "project_event"]
+pub struct ProjectEvent {
+    pub id: Uuid,
+    pub project_id: Uuid,
+    /// position in the Project's log, which is assigned when the event is
+    /// appended to it
+    pub seq: i64,
+    pub time_created: DateTime<Utc>,
+    pub resource_type: String,
+    pub resource_id: Uuid,
+    pub event_type: String,
+    pub state: Option<String>,
+}
+
+impl ProjectEvent {
+    pub fn new(
+        project_id: Uuid,
+        resource_type: external::ResourceType,
+        resource_id: Uuid,
+        kind: EventKind,
+    ) -> Self {
+        let (event_type, state) = match kind {
+            EventKind::Created => ("created", None),
+            EventKind::StateChanged(state) => ("state_changed", Some(state)),
+            EventKind::Deleted => ("deleted", None),
+        };
+        Self {
+            id: Uuid::new_v4(),
+            project_id,
+            seq: 0,
+            time_created: Utc::now(),
+            resource_type: resource_type.to_string(),
+            resource_id,
+            event_type: event_type.to_string(),
+            state,
+        }
+    }
+}
+
+/// A URL to which a Project's events are delivered
+#[derive(Queryable, Insertable, Clone, Debug, Selectable)]
+#[table_name = "project_webhook"]
+pub struct ProjectWebhook {
+    pub id: Uuid,
+    pub project_id: Uuid,
+    pub time_created: DateTime<Utc>,
+    pub time_deleted: Option<DateTime<Utc>>,
+    pub url: String,
+    pub secret: String,
+    /// `seq` of the last event delivered to the webhook
+    pub delivered_seq: i64,
+    /// `seq` of the last event that couldn't be delivered, if any
+    pub last_failed_seq: Option<i64>,
+}
+
+impl ProjectWebhook {
+    pub fn new(project_id: Uuid, params: params::ProjectWebhookCreate) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            project_id,
+            time_created: Utc::now(),
+            time_deleted: None,
+            url: params.url,
+            secret: params.secret,
+            delivered_seq: 0,
+            last_failed_seq: None,
+        }
+    }
+}
+
+/// Describes a user who logs in with a password, as stored in the database
+#[derive(Queryable, Insertable, Debug, Resource, Selectable)]
+#[table_name = "user"]
+pub struct User {
+    #[diesel(embed)]
+    pub identity: UserIdentity,
+
+    /// PHC-format hash of the user's password
+    pub password_hash: String,
+}
+
+impl User {
+    /// Creates a new database User object.
+    pub fn new(
+        id: Uuid,
+        params: external::IdentityMetadataCreateParams,
+        password_hash: String,
+    ) -> Self {
+        Self { identity: UserIdentity::new(id, params), password_hash }
+    }
+}
+
 /// Describes a built-in user, as stored in the database
 #[derive(Queryable, Insertable, Debug, Resource, Selectable)]
 #[table_name = "user_builtin"]
@@ -2084,6 +2886,34 @@ impl RoleAssignmentBuiltin {
     }
 }
 
+/// Describes an assignment of a built-in role to a user, made through the
+/// policy of the resource that it applies to
+#[derive(Queryable, Insertable, Clone, Debug, Selectable)]
+#[table_name = "role_assignment"]
+pub struct RoleAssignment {
+    pub user_id: Uuid,
+    pub resource_type: String,
+    pub resource_id: Uuid,
+    pub role_name: String,
+}
+
+impl RoleAssignment {
+    /// Creates a new database RoleAssignment object.
+    pub fn new(
+        user_id: Uuid,
+        resource_type: omicron_common::api::external::ResourceType,
+        resource_id: Uuid,
+        role_name: &str,
+    ) -> Self {
+        Self {
+            user_id,
+            resource_type: resource_type.to_string(),
+            resource_id,
+            role_name: String::from(role_name),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::Uuid;
@@ -2105,6 +2935,7 @@ mod tests {
         let identity = IdentityMetadataCreateParams {
             name: "net-test-vpc".parse().unwrap(),
             description: "A test VPC".parse().unwrap(),
+            labels: Default::default(),
         };
         let vpc = VpcSubnet::new(
             Uuid::new_v4(),
@@ -0,0 +1,210 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Queries used to enforce resource quotas on Organizations and Projects
+//!
+//! These are meant to be run inside the same transaction that provisions the
+//! new resource.  Since CockroachDB transactions are serializable, two
+//! concurrent requests can never both observe the same usage and together
+//! exceed a quota: one of the transactions will be forced to retry and will
+//! then see the other's resource.
+
+use super::error::TransactionError;
+use super::pool::DbConnection;
+use crate::db;
+use crate::db::model::{Quota, ResourceUsage};
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use omicron_common::api::external::Error;
+use std::convert::TryFrom;
+use uuid::Uuid;
+
+/// Describes a request that would exceed a quota
+#[derive(Debug, thiserror::Error)]
+#[error(
+    "{scope} quota exceeded: {resource} is limited to {limit} \
+    ({in_use} in use, {requested} requested)"
+)]
+pub struct QuotaExceeded {
+    scope: &'static str,
+    resource: &'static str,
+    limit: i64,
+    in_use: i64,
+    requested: i64,
+}
+
+impl From<QuotaExceeded> for Error {
+    fn from(e: QuotaExceeded) -> Self {
+        Error::InvalidRequest { message: e.to_string() }
+    }
+}
+
+/// Returns the quota on Organization or Project `collection_id`, if one has
+/// been set
+pub fn quota_fetch(
+    conn: &DbConnection,
+    collection_id: Uuid,
+) -> Result<Option<Quota>, DieselError> {
+    use db::schema::quota::dsl;
+
+    dsl::quota
+        .filter(dsl::collection_id.eq(collection_id))
+        .select(Quota::as_select())
+        .get_result::<Quota>(conn)
+        .optional()
+}
+
+/// Returns the resources provisioned within Organization `organization_id`
+pub fn organization_usage(
+    conn: &DbConnection,
+    organization_id: Uuid,
+    except_id: Option<Uuid>,
+) -> Result<ResourceUsage, DieselError> {
+    use db::schema::project::dsl;
+
+    // Resources in a deleted Project still count until they are deleted
+    // themselves.
+    let project_ids = dsl::project
+        .filter(dsl::organization_id.eq(organization_id))
+        .select(dsl::id)
+        .get_results::<Uuid>(conn)?;
+    usage(conn, project_ids, except_id)
+}
+
+/// Returns the resources provisioned within Project `project_id`
+pub fn project_usage(
+    conn: &DbConnection,
+    project_id: Uuid,
+    except_id: Option<Uuid>,
+) -> Result<ResourceUsage, DieselError> {
+    usage(conn, vec![project_id], except_id)
+}
+
+/// Returns the resources provisioned within any of the Projects
+/// `project_ids`, not counting the resource `except_id`
+// TODO-performance This loads a row for every resource.  If quotas see much
+// use, the database should do the summing, or we should track usage alongside
+// each quota.
+fn usage(
+    conn: &DbConnection,
+    project_ids: Vec<Uuid>,
+    except_id: Option<Uuid>,
+) -> Result<ResourceUsage, DieselError> {
+    use db::schema::disk::dsl as disk_dsl;
+    use db::schema::instance::dsl as instance_dsl;
+    use db::schema::vpc::dsl as vpc_dsl;
+
+    let mut instances = instance_dsl::instance
+        .filter(instance_dsl::time_deleted.is_null())
+        .filter(instance_dsl::project_id.eq_any(project_ids.clone()))
+        .into_boxed();
+    let mut disks = disk_dsl::disk
+        .filter(disk_dsl::time_deleted.is_null())
+        .filter(disk_dsl::project_id.eq_any(project_ids.clone()))
+        .into_boxed();
+    let mut vpcs = vpc_dsl::vpc
+        .filter(vpc_dsl::time_deleted.is_null())
+        .filter(vpc_dsl::project_id.eq_any(project_ids))
+        .into_boxed();
+    if let Some(id) = except_id {
+        instances = instances.filter(instance_dsl::id.ne(id));
+        disks = disks.filter(disk_dsl::id.ne(id));
+        vpcs = vpcs.filter(vpc_dsl::id.ne(id));
+    }
+
+    let instances = instances
+        .select((instance_dsl::ncpus, instance_dsl::memory))
+        .get_results::<(i64, i64)>(conn)?;
+    let disk_sizes =
+        disks.select(disk_dsl::size_bytes).get_results::<i64>(conn)?;
+    let vpcs = vpcs.select(vpc_dsl::id).get_results::<Uuid>(conn)?;
+
+    Ok(ResourceUsage {
+        instances: count(instances.len()),
+        vcpus: instances.iter().map(|(ncpus, _)| ncpus).sum(),
+        memory: instances.iter().map(|(_, memory)| memory).sum(),
+        disk_bytes: disk_sizes.iter().sum(),
+        vpcs: count(vpcs.len()),
+    })
+}
+
+fn count(n: usize) -> i64 {
+    i64::try_from(n).unwrap_or(i64::MAX)
+}
+
+/// Checks that provisioning `requested` within Project `project_id` would
+/// stay within the quotas on the Project and its Organization
+///
+/// `resource_id` identifies the resource being provisioned.  If it already
+/// exists (e.g., because a saga action is being replayed, or because the
+/// resource is being resized), it does not count against the quota, so
+/// `requested` should describe its new size in full.
+///
+/// Only the kinds of resources being requested are checked, so that a
+/// request is not rejected because usage of some unrelated resource is
+/// already over a quota that was lowered after the fact.
+pub fn quota_check(
+    conn: &DbConnection,
+    project_id: Uuid,
+    resource_id: Uuid,
+    requested: &ResourceUsage,
+) -> Result<(), TransactionError<QuotaExceeded>> {
+    use db::schema::project::dsl;
+
+    let organization_id = dsl::project
+        .filter(dsl::id.eq(project_id))
+        .select(dsl::organization_id)
+        .get_result::<Uuid>(conn)?;
+
+    if let Some(quota) = quota_fetch(conn, project_id)? {
+        let usage = project_usage(conn, project_id, Some(resource_id))?;
+        check_limits("project", &quota, &usage, requested)?;
+    }
+    if let Some(quota) = quota_fetch(conn, organization_id)? {
+        let usage =
+            organization_usage(conn, organization_id, Some(resource_id))?;
+        check_limits("organization", &quota, &usage, requested)?;
+    }
+    Ok(())
+}
+
+fn check_limits(
+    scope: &'static str,
+    quota: &Quota,
+    usage: &ResourceUsage,
+    requested: &ResourceUsage,
+) -> Result<(), TransactionError<QuotaExceeded>> {
+    let limits = [
+        ("instances", quota.instances, usage.instances, requested.instances),
+        ("vCPUs", quota.vcpus, usage.vcpus, requested.vcpus),
+        (
+            "memory (bytes)",
+            quota.memory.map(|b| i64::from(b.0)),
+            usage.memory,
+            requested.memory,
+        ),
+        (
+            "disk space (bytes)",
+            quota.disk_bytes.map(|b| i64::from(b.0)),
+            usage.disk_bytes,
+            requested.disk_bytes,
+        ),
+        ("VPCs", quota.vpcs, usage.vpcs, requested.vpcs),
+    ];
+    for (resource, limit, in_use, requested) in limits.iter().copied() {
+        match limit {
+            Some(limit) if requested > 0 && in_use + requested > limit => {
+                return Err(TransactionError::CustomError(QuotaExceeded {
+                    scope,
+                    resource,
+                    limit,
+                    in_use,
+                    requested,
+                }));
+            }
+            _ => (),
+        }
+    }
+    Ok(())
+}
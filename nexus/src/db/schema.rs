@@ -22,6 +22,22 @@ table! {
         time_state_updated -> Timestamptz,
         size_bytes -> Int8,
         origin_snapshot -> Nullable<Uuid>,
+        resize_id -> Nullable<Uuid>,
+        labels -> Jsonb,
+    }
+}
+
+table! {
+    snapshot (id) {
+        id -> Uuid,
+        name -> Text,
+        description -> Text,
+        time_created -> Timestamptz,
+        time_modified -> Timestamptz,
+        time_deleted -> Nullable<Timestamptz>,
+        project_id -> Uuid,
+        disk_id -> Uuid,
+        size_bytes -> Int8,
     }
 }
 
@@ -45,6 +61,31 @@ table! {
         ncpus -> Int8,
         memory -> Int8,
         hostname -> Text,
+        affinity_group_id -> Nullable<Uuid>,
+        labels -> Jsonb,
+        user_data -> Binary,
+        ssh_public_keys -> Array<Text>,
+    }
+}
+
+table! {
+    affinity_group (id) {
+        id -> Uuid,
+        name -> Text,
+        description -> Text,
+        time_created -> Timestamptz,
+        time_modified -> Timestamptz,
+        time_deleted -> Nullable<Timestamptz>,
+        project_id -> Uuid,
+        policy -> crate::db::model::AffinityPolicyEnum,
+    }
+}
+
+table! {
+    affinity_group_claim (instance_id) {
+        instance_id -> Uuid,
+        affinity_group_id -> Uuid,
+        sled_id -> Uuid,
     }
 }
 
@@ -77,6 +118,45 @@ table! {
     }
 }
 
+table! {
+    ip_pool (id) {
+        id -> Uuid,
+        name -> Text,
+        description -> Text,
+        time_created -> Timestamptz,
+        time_modified -> Timestamptz,
+        time_deleted -> Nullable<Timestamptz>,
+    }
+}
+
+table! {
+    ip_pool_range (id) {
+        id -> Uuid,
+        time_created -> Timestamptz,
+        time_deleted -> Nullable<Timestamptz>,
+        ip_pool_id -> Uuid,
+        first_address -> Inet,
+        last_address -> Inet,
+    }
+}
+
+table! {
+    floating_ip (id) {
+        id -> Uuid,
+        name -> Text,
+        description -> Text,
+        time_created -> Timestamptz,
+        time_modified -> Timestamptz,
+        time_deleted -> Nullable<Timestamptz>,
+        project_id -> Uuid,
+        ip_pool_id -> Uuid,
+        ip_pool_range_id -> Uuid,
+        ip -> Inet,
+        instance_id -> Nullable<Uuid>,
+        network_interface_id -> Nullable<Uuid>,
+    }
+}
+
 table! {
     organization (id) {
         id -> Uuid,
@@ -86,6 +166,7 @@ table! {
         time_modified -> Timestamptz,
         time_deleted -> Nullable<Timestamptz>,
         rcgen -> Int8,
+        labels -> Jsonb,
     }
 }
 
@@ -99,6 +180,24 @@ table! {
     }
 }
 
+table! {
+    nexus (id) {
+        id -> Uuid,
+        time_created -> Timestamptz,
+        time_modified -> Timestamptz,
+        ip -> Inet,
+        port -> Int4,
+    }
+}
+
+table! {
+    dns_zone (name) {
+        name -> Text,
+        time_modified -> Timestamptz,
+        generation -> Int8,
+    }
+}
+
 table! {
     project (id) {
         id -> Uuid,
@@ -108,6 +207,19 @@ table! {
         time_modified -> Timestamptz,
         time_deleted -> Nullable<Timestamptz>,
         organization_id -> Uuid,
+        labels -> Jsonb,
+    }
+}
+
+table! {
+    quota (collection_id) {
+        collection_id -> Uuid,
+        time_modified -> Timestamptz,
+        instances -> Nullable<Int8>,
+        vcpus -> Nullable<Int8>,
+        memory -> Nullable<Int8>,
+        disk_bytes -> Nullable<Int8>,
+        vpcs -> Nullable<Int8>,
     }
 }
 
@@ -136,6 +248,16 @@ table! {
     }
 }
 
+table! {
+    operation (id) {
+        id -> Uuid,
+        time_created -> Timestamptz,
+        project_id -> Uuid,
+        target_type -> Text,
+        target_id -> Nullable<Uuid>,
+    }
+}
+
 table! {
     rack (id) {
         id -> Uuid,
@@ -144,6 +266,19 @@ table! {
     }
 }
 
+table! {
+    audit_log (id) {
+        id -> Uuid,
+        time_created -> Timestamptz,
+        actor_id -> Nullable<Uuid>,
+        request_id -> Text,
+        http_method -> Text,
+        http_path -> Text,
+        resource_id -> Nullable<Uuid>,
+        http_status -> Int4,
+    }
+}
+
 table! {
     console_session (token) {
         token -> Text,
@@ -153,6 +288,68 @@ table! {
     }
 }
 
+table! {
+    api_token (id) {
+        id -> Uuid,
+        description -> Text,
+        time_created -> Timestamptz,
+        time_expires -> Nullable<Timestamptz>,
+        user_id -> Uuid,
+        token_hash -> Text,
+        scope -> crate::db::model::ApiTokenScopeEnum,
+    }
+}
+
+table! {
+    ssh_key (id) {
+        id -> Uuid,
+        description -> Text,
+        time_created -> Timestamptz,
+        user_id -> Uuid,
+        public_key -> Text,
+        fingerprint -> Text,
+    }
+}
+
+table! {
+    project_event (id) {
+        id -> Uuid,
+        project_id -> Uuid,
+        seq -> Int8,
+        time_created -> Timestamptz,
+        resource_type -> Text,
+        resource_id -> Uuid,
+        event_type -> Text,
+        state -> Nullable<Text>,
+    }
+}
+
+table! {
+    project_webhook (id) {
+        id -> Uuid,
+        project_id -> Uuid,
+        time_created -> Timestamptz,
+        time_deleted -> Nullable<Timestamptz>,
+        url -> Text,
+        secret -> Text,
+        delivered_seq -> Int8,
+        last_failed_seq -> Nullable<Int8>,
+    }
+}
+
+table! {
+    idempotency_key (actor_id, client_key) {
+        actor_id -> Uuid,
+        client_key -> Text,
+        time_created -> Timestamptz,
+        http_method -> Text,
+        http_path -> Text,
+        request_hash -> Text,
+        saga_id -> Nullable<Uuid>,
+        response_body -> Nullable<Jsonb>,
+    }
+}
+
 table! {
     sled (id) {
         id -> Uuid,
@@ -163,6 +360,9 @@ table! {
 
         ip -> Inet,
         port -> Int4,
+
+        usable_hardware_threads -> Int8,
+        usable_physical_ram -> Int8,
     }
 }
 
@@ -227,6 +427,7 @@ table! {
         ipv6_prefix -> Inet,
         dns_name -> Text,
         firewall_gen -> Int8,
+        labels -> Jsonb,
     }
 }
 
@@ -241,6 +442,7 @@ table! {
         vpc_id -> Uuid,
         ipv4_block -> Inet,
         ipv6_block -> Inet,
+        custom_router_id -> Nullable<Uuid>,
     }
 }
 
@@ -296,6 +498,18 @@ table! {
     }
 }
 
+table! {
+    user (id) {
+        id -> Uuid,
+        name -> Text,
+        description -> Text,
+        time_created -> Timestamptz,
+        time_modified -> Timestamptz,
+        time_deleted -> Nullable<Timestamptz>,
+        password_hash -> Text,
+    }
+}
+
 table! {
     user_builtin (id) {
         id -> Uuid,
@@ -329,27 +543,54 @@ table! {
     }
 }
 
+table! {
+    role_assignment (user_id, resource_type, resource_id, role_name) {
+        resource_type -> Text,
+        role_name -> Text,
+        resource_id -> Uuid,
+        user_id -> Uuid,
+    }
+}
+
 allow_tables_to_appear_in_same_query!(
+    affinity_group,
+    affinity_group_claim,
+    api_token,
+    audit_log,
     dataset,
     disk,
+    dns_zone,
+    floating_ip,
+    idempotency_key,
     instance,
+    ip_pool,
+    ip_pool_range,
     metric_producer,
     network_interface,
+    nexus,
+    operation,
     organization,
     oximeter,
     project,
+    project_event,
+    project_webhook,
+    quota,
     region,
     saga,
     saga_node_event,
     console_session,
+    snapshot,
+    ssh_key,
     sled,
     router_route,
     vpc,
     vpc_subnet,
     vpc_router,
     vpc_firewall_rule,
+    user,
     user_builtin,
     role_builtin,
+    role_assignment,
     role_assignment_builtin,
     zpool,
 );
@@ -6,7 +6,9 @@
 
 use crate::db;
 use crate::db::identity::Resource;
+use crate::db::model::IncompleteFloatingIp;
 use crate::db::model::IncompleteNetworkInterface;
+use crate::db::model::IpPoolRange;
 use chrono::{DateTime, Utc};
 use diesel::pg::Pg;
 use diesel::prelude::*;
@@ -490,13 +492,402 @@ impl QueryFragment<Pg> for FilterConflictingVpcSubnetRangesQueryValues {
     }
 }
 
+/// Used for allocating an external address when creating a [`FloatingIp`].
+///
+/// This is a query equivalent to:
+/// SELECT <id> AS id, <name> AS name, <description> AS description,
+///        <time_created> AS time_created, <time_modified> AS time_modified,
+///        <project_id> AS project_id, <ip_pool_id> AS ip_pool_id,
+///        range_id AS ip_pool_range_id, address AS ip,
+///        NULL::UUID AS instance_id, NULL::UUID AS network_interface_id
+///   FROM (
+///        SELECT id AS range_id,
+///               first_address +
+///                   generate_series(0, last_address - first_address)
+///                   AS address
+///          FROM ip_pool_range
+///         WHERE ip_pool_id = <ip_pool_id> AND time_deleted IS NULL
+///   ) AS candidate
+///   LEFT OUTER JOIN
+///        floating_ip
+///   ON (floating_ip.ip, floating_ip.time_deleted IS NULL) = (address, TRUE)
+///   WHERE floating_ip.ip IS NULL LIMIT 1;
+///
+/// Two parallel allocations may pick the same candidate address.  The unique
+/// index on live floating IP addresses turns that into a unique constraint
+/// violation for one of them, which can then retry.
+// TODO-performance: Like `AllocateIpQuery`, this scales linearly with the
+// number of addresses already allocated from the pool.
+pub struct AllocateFloatingIpQuery {
+    pub floating_ip: IncompleteFloatingIp,
+    pub now: DateTime<Utc>,
+}
+
+/// Used for using AllocateFloatingIpQuery with an INSERT statement. Do not use
+/// this directly, instead pass an instance of [`AllocateFloatingIpQuery`] to
+/// [`InsertStatement::values`].
+pub struct AllocateFloatingIpQueryValues(AllocateFloatingIpQuery);
+
+impl QueryId for AllocateFloatingIpQuery {
+    type QueryId = ();
+    const HAS_STATIC_QUERY_ID: bool = false;
+}
+
+impl Insertable<db::schema::floating_ip::table> for AllocateFloatingIpQuery {
+    type Values = AllocateFloatingIpQueryValues;
+
+    fn values(self) -> Self::Values {
+        AllocateFloatingIpQueryValues(self)
+    }
+}
+
+impl QueryFragment<Pg> for AllocateFloatingIpQuery {
+    fn walk_ast(&self, mut out: AstPass<Pg>) -> diesel::QueryResult<()> {
+        use db::schema::floating_ip::dsl;
+        use db::schema::ip_pool_range::dsl as range_dsl;
+
+        out.push_sql("SELECT ");
+
+        out.push_bind_param::<sql_types::Uuid, Uuid>(
+            &self.floating_ip.identity.id,
+        )?;
+        out.push_sql(" AS ");
+        out.push_identifier(dsl::id::NAME)?;
+        out.push_sql(", ");
+
+        out.push_bind_param::<sql_types::Text, String>(
+            &self.floating_ip.identity.name.to_string(),
+        )?;
+        out.push_sql(" AS ");
+        out.push_identifier(dsl::name::NAME)?;
+        out.push_sql(", ");
+
+        out.push_bind_param::<sql_types::Text, String>(
+            &self.floating_ip.identity.description,
+        )?;
+        out.push_sql(" AS ");
+        out.push_identifier(dsl::description::NAME)?;
+        out.push_sql(", ");
+
+        out.push_bind_param::<sql_types::Timestamptz, _>(&self.now)?;
+        out.push_sql(" AS ");
+        out.push_identifier(dsl::time_created::NAME)?;
+        out.push_sql(", ");
+
+        out.push_bind_param::<sql_types::Timestamptz, _>(&self.now)?;
+        out.push_sql(" AS ");
+        out.push_identifier(dsl::time_modified::NAME)?;
+        out.push_sql(", ");
+
+        out.push_bind_param::<sql_types::Uuid, Uuid>(
+            &self.floating_ip.project_id,
+        )?;
+        out.push_sql(" AS ");
+        out.push_identifier(dsl::project_id::NAME)?;
+        out.push_sql(", ");
+
+        out.push_bind_param::<sql_types::Uuid, Uuid>(
+            &self.floating_ip.ip_pool_id,
+        )?;
+        out.push_sql(" AS ");
+        out.push_identifier(dsl::ip_pool_id::NAME)?;
+        out.push_sql(", ");
+
+        out.push_identifier("range_id")?;
+        out.push_sql(" AS ");
+        out.push_identifier(dsl::ip_pool_range_id::NAME)?;
+        out.push_sql(", ");
+
+        out.push_identifier("address")?;
+        out.push_sql(" AS ");
+        out.push_identifier(dsl::ip::NAME)?;
+        out.push_sql(", ");
+
+        out.push_sql("NULL::UUID AS ");
+        out.push_identifier(dsl::instance_id::NAME)?;
+        out.push_sql(", ");
+
+        out.push_sql("NULL::UUID AS ");
+        out.push_identifier(dsl::network_interface_id::NAME)?;
+
+        // Enumerate every address in each live range of the pool.
+        out.push_sql(" FROM (SELECT ");
+        out.push_identifier(range_dsl::id::NAME)?;
+        out.push_sql(" AS ");
+        out.push_identifier("range_id")?;
+        out.push_sql(", ");
+        out.push_identifier(range_dsl::first_address::NAME)?;
+        out.push_sql(" + generate_series(0, ");
+        out.push_identifier(range_dsl::last_address::NAME)?;
+        out.push_sql(" - ");
+        out.push_identifier(range_dsl::first_address::NAME)?;
+        out.push_sql(") AS ");
+        out.push_identifier("address")?;
+        out.push_sql(" FROM ");
+        range_dsl::ip_pool_range.from_clause().walk_ast(out.reborrow())?;
+        out.push_sql(" WHERE ");
+        out.push_identifier(range_dsl::ip_pool_id::NAME)?;
+        out.push_sql(" = ");
+        out.push_bind_param::<sql_types::Uuid, Uuid>(
+            &self.floating_ip.ip_pool_id,
+        )?;
+        out.push_sql(" AND ");
+        out.push_identifier(range_dsl::time_deleted::NAME)?;
+        out.push_sql(" IS NULL) AS ");
+        out.push_identifier("candidate")?;
+
+        //   LEFT OUTER JOIN floating_ip
+        //   ON (floating_ip.ip, floating_ip.time_deleted IS NULL) =
+        //      (address, TRUE)
+        out.push_sql(" LEFT OUTER JOIN ");
+        dsl::floating_ip.from_clause().walk_ast(out.reborrow())?;
+        out.push_sql(" ON (");
+        dsl::floating_ip.from_clause().walk_ast(out.reborrow())?;
+        out.push_sql(".");
+        out.push_identifier(dsl::ip::NAME)?;
+        out.push_sql(", ");
+        dsl::floating_ip.from_clause().walk_ast(out.reborrow())?;
+        out.push_sql(".");
+        out.push_identifier(dsl::time_deleted::NAME)?;
+        out.push_sql(" IS NULL) = (");
+        out.push_identifier("address")?;
+        out.push_sql(", TRUE) ");
+
+        //   WHERE floating_ip.ip IS NULL LIMIT 1;
+        out.push_sql("WHERE ");
+        dsl::floating_ip.from_clause().walk_ast(out.reborrow())?;
+        out.push_sql(".");
+        out.push_identifier(dsl::ip::NAME)?;
+        out.push_sql(" IS NULL LIMIT 1");
+        Ok(())
+    }
+}
+
+impl QueryId for AllocateFloatingIpQueryValues {
+    type QueryId = ();
+    const HAS_STATIC_QUERY_ID: bool = false;
+}
+
+impl diesel::insertable::CanInsertInSingleQuery<Pg>
+    for AllocateFloatingIpQueryValues
+{
+    fn rows_to_insert(&self) -> Option<usize> {
+        Some(1)
+    }
+}
+
+impl QueryFragment<Pg> for AllocateFloatingIpQueryValues {
+    fn walk_ast(&self, mut out: AstPass<Pg>) -> diesel::QueryResult<()> {
+        use db::schema::floating_ip::dsl;
+        out.push_sql("(");
+        out.push_identifier(dsl::id::NAME)?;
+        out.push_sql(", ");
+        out.push_identifier(dsl::name::NAME)?;
+        out.push_sql(", ");
+        out.push_identifier(dsl::description::NAME)?;
+        out.push_sql(", ");
+        out.push_identifier(dsl::time_created::NAME)?;
+        out.push_sql(", ");
+        out.push_identifier(dsl::time_modified::NAME)?;
+        out.push_sql(", ");
+        out.push_identifier(dsl::project_id::NAME)?;
+        out.push_sql(", ");
+        out.push_identifier(dsl::ip_pool_id::NAME)?;
+        out.push_sql(", ");
+        out.push_identifier(dsl::ip_pool_range_id::NAME)?;
+        out.push_sql(", ");
+        out.push_identifier(dsl::ip::NAME)?;
+        out.push_sql(", ");
+        out.push_identifier(dsl::instance_id::NAME)?;
+        out.push_sql(", ");
+        out.push_identifier(dsl::network_interface_id::NAME)?;
+        out.push_sql(") ");
+        self.0.walk_ast(out)
+    }
+}
+
+/// Generate a CTE that can be used to insert an IP pool range, only if it
+/// doesn't overlap any live range in any pool.
+///
+/// In particular, this generates a CTE like so:
+///
+/// ```sql
+/// WITH candidate(
+///     id,
+///     time_created,
+///     time_deleted,
+///     ip_pool_id,
+///     first_address,
+///     last_address
+/// ) AS (VALUES (
+///     <id>,
+///     <time_created>,
+///     NULL::TIMESTAMPTZ,
+///     <ip_pool_id>,
+///     <first_address>,
+///     <last_address>
+/// ))
+/// SELECT *
+/// FROM candidate
+/// WHERE NOT EXISTS (
+///     SELECT id
+///     FROM ip_pool_range
+///     WHERE
+///         time_deleted IS NULL AND
+///         first_address <= <last_address> AND
+///         last_address >= <first_address>
+/// )
+/// ```
+///
+/// Addresses of different families never compare as overlapping, because all
+/// IPv4 addresses sort before all IPv6 addresses.
+pub struct FilterConflictingIpPoolRangesQuery(pub IpPoolRange);
+
+impl QueryId for FilterConflictingIpPoolRangesQuery {
+    type QueryId = ();
+    const HAS_STATIC_QUERY_ID: bool = false;
+}
+
+impl QueryFragment<Pg> for FilterConflictingIpPoolRangesQuery {
+    fn walk_ast(&self, mut out: AstPass<Pg>) -> diesel::QueryResult<()> {
+        use db::schema::ip_pool_range::dsl;
+
+        // "SELECT * FROM (WITH candidate("
+        out.push_sql("SELECT * FROM (WITH candidate(");
+
+        // "id, time_created, time_deleted, "
+        out.push_identifier(dsl::id::NAME)?;
+        out.push_sql(", ");
+        out.push_identifier(dsl::time_created::NAME)?;
+        out.push_sql(", ");
+        out.push_identifier(dsl::time_deleted::NAME)?;
+        out.push_sql(", ");
+
+        // "ip_pool_id, first_address, last_address) AS (VALUES ("
+        out.push_identifier(dsl::ip_pool_id::NAME)?;
+        out.push_sql(", ");
+        out.push_identifier(dsl::first_address::NAME)?;
+        out.push_sql(", ");
+        out.push_identifier(dsl::last_address::NAME)?;
+        out.push_sql(") AS (VALUES (");
+
+        // "<id>, <time_created>, NULL::TIMESTAMPTZ, "
+        out.push_bind_param::<sql_types::Uuid, Uuid>(&self.0.id)?;
+        out.push_sql(", ");
+        out.push_bind_param::<sql_types::Timestamptz, DateTime<Utc>>(
+            &self.0.time_created,
+        )?;
+        out.push_sql(", NULL::TIMESTAMPTZ, ");
+
+        // "<ip_pool_id>, <first_address>, <last_address>))"
+        out.push_bind_param::<sql_types::Uuid, Uuid>(&self.0.ip_pool_id)?;
+        out.push_sql(", ");
+        out.push_bind_param::<sql_types::Inet, ipnetwork::IpNetwork>(
+            &self.0.first_address,
+        )?;
+        out.push_sql(", ");
+        out.push_bind_param::<sql_types::Inet, ipnetwork::IpNetwork>(
+            &self.0.last_address,
+        )?;
+        out.push_sql("))");
+
+        /*
+         * Filter the candidate row if any live range, in any pool, shares an
+         * address with it.  External addresses must be unique fleet-wide.
+         */
+
+        // " SELECT * FROM candidate WHERE NOT EXISTS ("
+        out.push_sql(" SELECT * FROM candidate WHERE NOT EXISTS (");
+
+        // "SELECT id FROM ip_pool_range WHERE time_deleted IS NULL AND "
+        out.push_sql("SELECT ");
+        out.push_identifier(dsl::id::NAME)?;
+        out.push_sql(" FROM ");
+        dsl::ip_pool_range.from_clause().walk_ast(out.reborrow())?;
+        out.push_sql(" WHERE ");
+        out.push_identifier(dsl::time_deleted::NAME)?;
+        out.push_sql(" IS NULL AND ");
+
+        // "first_address <= <last_address> AND "
+        out.push_identifier(dsl::first_address::NAME)?;
+        out.push_sql(" <= ");
+        out.push_bind_param::<sql_types::Inet, ipnetwork::IpNetwork>(
+            &self.0.last_address,
+        )?;
+        out.push_sql(" AND ");
+
+        // "last_address >= <first_address>))"
+        out.push_identifier(dsl::last_address::NAME)?;
+        out.push_sql(" >= ");
+        out.push_bind_param::<sql_types::Inet, ipnetwork::IpNetwork>(
+            &self.0.first_address,
+        )?;
+        out.push_sql("))");
+
+        Ok(())
+    }
+}
+
+impl Insertable<db::schema::ip_pool_range::table>
+    for FilterConflictingIpPoolRangesQuery
+{
+    type Values = FilterConflictingIpPoolRangesQueryValues;
+
+    fn values(self) -> Self::Values {
+        FilterConflictingIpPoolRangesQueryValues(self)
+    }
+}
+
+/// Used to allow inserting the result of the
+/// `FilterConflictingIpPoolRangesQuery`, as in
+/// `diesel::insert_into(foo).values(_). Should not be used directly.
+pub struct FilterConflictingIpPoolRangesQueryValues(
+    pub FilterConflictingIpPoolRangesQuery,
+);
+
+impl QueryId for FilterConflictingIpPoolRangesQueryValues {
+    type QueryId = ();
+    const HAS_STATIC_QUERY_ID: bool = false;
+}
+
+impl diesel::insertable::CanInsertInSingleQuery<Pg>
+    for FilterConflictingIpPoolRangesQueryValues
+{
+    fn rows_to_insert(&self) -> Option<usize> {
+        Some(1)
+    }
+}
+
+impl QueryFragment<Pg> for FilterConflictingIpPoolRangesQueryValues {
+    fn walk_ast(&self, mut out: AstPass<Pg>) -> diesel::QueryResult<()> {
+        use db::schema::ip_pool_range::dsl;
+        out.push_sql("(");
+        out.push_identifier(dsl::id::NAME)?;
+        out.push_sql(", ");
+        out.push_identifier(dsl::time_created::NAME)?;
+        out.push_sql(", ");
+        out.push_identifier(dsl::time_deleted::NAME)?;
+        out.push_sql(", ");
+        out.push_identifier(dsl::ip_pool_id::NAME)?;
+        out.push_sql(", ");
+        out.push_identifier(dsl::first_address::NAME)?;
+        out.push_sql(", ");
+        out.push_identifier(dsl::last_address::NAME)?;
+        out.push_sql(") ");
+        self.0.walk_ast(out)
+    }
+}
+
 #[cfg(test)]
 mod test {
+    use super::AllocateFloatingIpQuery;
     use super::AllocateIpQuery;
+    use super::FilterConflictingIpPoolRangesQuery;
     use super::FilterConflictingVpcSubnetRangesQuery;
     use super::SubnetError;
     use crate::db::model::{
-        IncompleteNetworkInterface, NetworkInterface, VpcSubnet,
+        IncompleteFloatingIp, IncompleteNetworkInterface, IpPoolRange,
+        NetworkInterface, VpcSubnet,
     };
     use crate::db::schema::network_interface;
     use crate::external_api::params;
@@ -535,6 +926,7 @@ mod test {
             IdentityMetadataCreateParams {
                 name: "test-subnet".to_string().try_into().unwrap(),
                 description: "subnet description".to_string(),
+                labels: Default::default(),
             },
             Ipv4Net(ipv4_block.clone()).into(),
             Ipv6Net(ipv6_block),
@@ -553,6 +945,7 @@ mod test {
                 identity: IdentityMetadataCreateParams {
                     name: "test-iface".to_string().try_into().unwrap(),
                     description: "interface description".to_string(),
+                    labels: Default::default(),
                 },
             },
         );
@@ -633,7 +1026,11 @@ mod test {
         let ipv6_block = Ipv6Net("fd12:3456:7890::/64".parse().unwrap());
         let name = "a-name".to_string().try_into().unwrap();
         let description = "some description".to_string();
-        let identity = IdentityMetadataCreateParams { name, description };
+        let identity = IdentityMetadataCreateParams {
+            name,
+            description,
+            labels: Default::default(),
+        };
         let vpc_id = Uuid::new_v4();
         let subnet_id = Uuid::new_v4();
         let row =
@@ -666,12 +1063,102 @@ mod test {
         assert_eq!(query_str, expected_query);
     }
 
+    #[test]
+    fn test_allocate_floating_ip_query_string() {
+        let floating_ip_id =
+            uuid::Uuid::parse_str("223cb7f7-0d3a-4a4e-a5e1-ad38ecb785d0")
+                .unwrap();
+        let project_id =
+            uuid::Uuid::parse_str("223cb7f7-0d3a-4a4e-a5e1-ad38ecb785d1")
+                .unwrap();
+        let ip_pool_id =
+            uuid::Uuid::parse_str("223cb7f7-0d3a-4a4e-a5e1-ad38ecb785d2")
+                .unwrap();
+        let floating_ip = IncompleteFloatingIp::new(
+            floating_ip_id,
+            project_id,
+            ip_pool_id,
+            params::FloatingIpCreate {
+                identity: IdentityMetadataCreateParams {
+                    name: "test-fip".to_string().try_into().unwrap(),
+                    description: "floating ip description".to_string(),
+                    labels: Default::default(),
+                },
+                pool_name: "test-pool".to_string().try_into().unwrap(),
+            },
+        );
+        let select = AllocateFloatingIpQuery {
+            floating_ip,
+            now: DateTime::<Utc>::from_utc(
+                NaiveDateTime::from_timestamp(0, 0),
+                Utc,
+            ),
+        };
+        let query = diesel::debug_query::<Pg, _>(&select).to_string();
+        let expected_query = "SELECT \
+            $1 AS \"id\", $2 AS \"name\", $3 AS \"description\", \
+            $4 AS \"time_created\", $5 AS \"time_modified\", \
+            $6 AS \"project_id\", $7 AS \"ip_pool_id\", \
+            \"range_id\" AS \"ip_pool_range_id\", \"address\" AS \"ip\", \
+            NULL::UUID AS \"instance_id\", \
+            NULL::UUID AS \"network_interface_id\" \
+            FROM (SELECT \"id\" AS \"range_id\", \
+                \"first_address\" + generate_series(0, \
+                    \"last_address\" - \"first_address\") AS \"address\" \
+                FROM \"ip_pool_range\" \
+                WHERE \"ip_pool_id\" = $8 AND \"time_deleted\" IS NULL) \
+                AS \"candidate\" \
+            LEFT OUTER JOIN \"floating_ip\" ON \
+                (\"floating_ip\".\"ip\", \
+                 \"floating_ip\".\"time_deleted\" IS NULL) = \
+                    (\"address\", TRUE) \
+            WHERE \"floating_ip\".\"ip\" IS NULL LIMIT 1 -- \
+            binds: [223cb7f7-0d3a-4a4e-a5e1-ad38ecb785d0, \"test-fip\", \
+                \"floating ip description\", 1970-01-01T00:00:00Z, \
+                1970-01-01T00:00:00Z, \
+                223cb7f7-0d3a-4a4e-a5e1-ad38ecb785d1, \
+                223cb7f7-0d3a-4a4e-a5e1-ad38ecb785d2, \
+                223cb7f7-0d3a-4a4e-a5e1-ad38ecb785d2]";
+        assert_eq!(query, expected_query);
+    }
+
+    #[test]
+    fn test_filter_conflicting_ip_pool_ranges_query_string() {
+        let ip_pool_id = Uuid::new_v4();
+        let first: std::net::IpAddr = "203.0.113.10".parse().unwrap();
+        let last: std::net::IpAddr = "203.0.113.20".parse().unwrap();
+        let row = IpPoolRange::new(ip_pool_id, first, last);
+        let query = FilterConflictingIpPoolRangesQuery(row.clone());
+        let query_str = diesel::debug_query::<Pg, _>(&query).to_string();
+        let expected_query = format!(
+            concat!(
+                "SELECT * FROM (WITH candidate(",
+                r#""id", "time_created", "time_deleted", "ip_pool_id", "#,
+                r#""first_address", "last_address") AS "#,
+                "(VALUES ($1, $2, NULL::TIMESTAMPTZ, $3, $4, $5)) ",
+                "SELECT * FROM candidate WHERE NOT EXISTS (",
+                r#"SELECT "id" FROM "ip_pool_range" WHERE "#,
+                r#""time_deleted" IS NULL AND "first_address" <= $6 AND "#,
+                r#""last_address" >= $7)) "#,
+                r#"-- binds: [{id}, {time_created:?}, {ip_pool_id}, "#,
+                r#"{first:?}, {last:?}, {last:?}, {first:?}]"#,
+            ),
+            id = row.id,
+            time_created = row.time_created,
+            ip_pool_id = ip_pool_id,
+            first = row.first_address,
+            last = row.last_address,
+        );
+        assert_eq!(query_str, expected_query);
+    }
+
     #[tokio::test]
     async fn test_filter_conflicting_vpc_subnet_ranges_query() {
         let make_id =
             |name: &Name, description: &str| IdentityMetadataCreateParams {
                 name: name.clone(),
                 description: description.to_string(),
+                labels: Default::default(),
             };
         let ipv4_block = Ipv4Net("172.30.0.0/22".parse().unwrap());
         let other_ipv4_block = Ipv4Net("172.31.0.0/22".parse().unwrap());
@@ -23,6 +23,11 @@ pub const MIN_VPC_IPV4_SUBNET_PREFIX: u8 = 8;
 /// NOTE: This is the maximum _prefix_, which sets the minimum subnet size.
 pub const MAX_VPC_IPV4_SUBNET_PREFIX: u8 = 26;
 
+/// Maximum length (in bytes) of a user's password.
+///
+/// This bounds how much work a single request can make us do to hash one.
+pub const MAX_PASSWORD_LENGTH: usize = 512;
+
 lazy_static! {
     /// The default IPv4 subnet range assigned to the default VPC Subnet, when
     /// the VPC is created, if one is not provided in the request. See
@@ -0,0 +1,233 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Building the records of the internal DNS zone
+//!
+//! The zone ([`DNS_ZONE`]) has two kinds of names:
+//!
+//! * Each Instance is `<hostname>.<vpc>.<project>`, where `<vpc>` is the VPC's
+//!   DNS name, with an A or AAAA record for the address of each of its network
+//!   interfaces in that VPC.
+//! * Each control plane service has an SRV record for every server providing
+//!   it, like `_nexus._tcp`.  Each SRV record targets a name for the server
+//!   itself, `<id>.host`, which has the server's address.
+//!
+//! Names are relative to the zone, so `web.default.myproject` is served as
+//! `web.default.myproject.internal`.  The zone is rebuilt from scratch
+//! whenever it changes; see [`crate::nexus::Nexus::dns_zone_push`].
+//!
+//! [`DNS_ZONE`]: omicron_common::api::internal::dns::DNS_ZONE
+
+use omicron_common::api::external;
+use omicron_common::api::internal::dns::{DnsRecord, Srv};
+use std::collections::BTreeMap;
+use std::net::{IpAddr, SocketAddr};
+use uuid::Uuid;
+
+/// An Instance's address in a VPC
+#[derive(Clone, Debug)]
+pub struct InstanceHost {
+    pub hostname: String,
+    pub vpc_dns_name: external::Name,
+    pub project_name: external::Name,
+    pub ip: IpAddr,
+}
+
+/// The control plane services found through the internal DNS zone
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ServiceKind {
+    Nexus,
+    Oximeter,
+    Cockroach,
+    Clickhouse,
+}
+
+impl ServiceKind {
+    /// Returns the name of the service's SRV record
+    fn srv_name(&self) -> &'static str {
+        match self {
+            ServiceKind::Nexus => "_nexus._tcp",
+            ServiceKind::Oximeter => "_oximeter._tcp",
+            ServiceKind::Cockroach => "_cockroach._tcp",
+            ServiceKind::Clickhouse => "_clickhouse._tcp",
+        }
+    }
+}
+
+/// A server providing one of the control plane services
+#[derive(Clone, Debug)]
+pub struct ServiceHost {
+    pub kind: ServiceKind,
+    pub id: Uuid,
+    pub address: SocketAddr,
+}
+
+/// Returns the records of the internal DNS zone for the given Instances and
+/// services, indexed by name relative to the zone
+///
+/// Instances whose hostnames aren't valid DNS labels are left out.  Instances
+/// in the same VPC with the same hostname share a name, which then has all of
+/// their addresses.
+pub fn zone_records(
+    instances: &[InstanceHost],
+    services: &[ServiceHost],
+) -> BTreeMap<String, Vec<DnsRecord>> {
+    let mut records: BTreeMap<String, Vec<DnsRecord>> = BTreeMap::new();
+
+    for instance in instances {
+        if !is_valid_label(&instance.hostname) {
+            continue;
+        }
+        let name = format!(
+            "{}.{}.{}",
+            instance.hostname, instance.vpc_dns_name, instance.project_name
+        )
+        .to_lowercase();
+        records.entry(name).or_default().push(address_record(instance.ip));
+    }
+
+    for service in services {
+        let target = format!("{}.host", service.id);
+        records.entry(service.kind.srv_name().to_string()).or_default().push(
+            DnsRecord::Srv(Srv {
+                prio: 0,
+                weight: 0,
+                port: service.address.port(),
+                target: target.clone(),
+            }),
+        );
+        let host_records = records.entry(target).or_default();
+        let record = address_record(service.address.ip());
+        if !host_records.contains(&record) {
+            host_records.push(record);
+        }
+    }
+
+    records
+}
+
+fn address_record(ip: IpAddr) -> DnsRecord {
+    match ip {
+        IpAddr::V4(ip) => DnsRecord::A(ip),
+        IpAddr::V6(ip) => DnsRecord::Aaaa(ip),
+    }
+}
+
+/// Returns whether `label` can be used as one label of a DNS name (RFC 1123
+/// section 2.1)
+fn is_valid_label(label: &str) -> bool {
+    !label.is_empty()
+        && label.len() <= 63
+        && label.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-')
+        && !label.starts_with('-')
+        && !label.ends_with('-')
+}
+
+#[cfg(test)]
+mod test {
+    use super::{zone_records, InstanceHost, ServiceHost, ServiceKind};
+    use omicron_common::api::internal::dns::{DnsRecord, Srv};
+    use std::collections::BTreeMap;
+    use uuid::Uuid;
+
+    fn instance(hostname: &str, vpc: &str, ip: &str) -> InstanceHost {
+        InstanceHost {
+            hostname: hostname.to_string(),
+            vpc_dns_name: vpc.parse().unwrap(),
+            project_name: "myproject".parse().unwrap(),
+            ip: ip.parse().unwrap(),
+        }
+    }
+
+    fn srv(port: u16, id: Uuid) -> DnsRecord {
+        DnsRecord::Srv(Srv {
+            prio: 0,
+            weight: 0,
+            port,
+            target: format!("{}.host", id),
+        })
+    }
+
+    #[test]
+    fn test_instance_records() {
+        let records = zone_records(
+            &[
+                instance("web", "default", "172.30.0.5"),
+                instance("web", "default", "fd00::5"),
+                instance("web", "backend", "172.31.0.5"),
+                instance("DB", "backend", "172.31.0.6"),
+                instance("-web", "default", "172.30.0.7"),
+                instance("web.other", "default", "172.30.0.8"),
+                instance("", "default", "172.30.0.9"),
+            ],
+            &[],
+        );
+        let expected: BTreeMap<_, _> = vec![
+            (
+                "web.default.myproject",
+                vec![
+                    DnsRecord::A("172.30.0.5".parse().unwrap()),
+                    DnsRecord::Aaaa("fd00::5".parse().unwrap()),
+                ],
+            ),
+            (
+                "web.backend.myproject",
+                vec![DnsRecord::A("172.31.0.5".parse().unwrap())],
+            ),
+            (
+                "db.backend.myproject",
+                vec![DnsRecord::A("172.31.0.6".parse().unwrap())],
+            ),
+        ]
+        .into_iter()
+        .map(|(name, records)| (name.to_string(), records))
+        .collect();
+        assert_eq!(records, expected);
+    }
+
+    #[test]
+    fn test_service_records() {
+        let nexus1 = Uuid::new_v4();
+        let nexus2 = Uuid::new_v4();
+        let oximeter = Uuid::new_v4();
+        let cockroach = Uuid::new_v4();
+        let clickhouse = Uuid::new_v4();
+        let service = |kind, id, address: &str| ServiceHost {
+            kind,
+            id,
+            address: address.parse().unwrap(),
+        };
+        let records = zone_records(
+            &[],
+            &[
+                service(ServiceKind::Nexus, nexus1, "[fd00::1]:12221"),
+                service(ServiceKind::Nexus, nexus2, "[fd00::2]:12221"),
+                service(ServiceKind::Oximeter, oximeter, "[fd00::3]:12223"),
+                service(ServiceKind::Cockroach, cockroach, "[fd00::4]:32221"),
+                service(ServiceKind::Clickhouse, clickhouse, "10.0.0.5:8123"),
+            ],
+        );
+
+        let nexus1_host = format!("{}.host", nexus1);
+        let clickhouse_host = format!("{}.host", clickhouse);
+        let cases = [
+            ("_nexus._tcp", vec![srv(12221, nexus1), srv(12221, nexus2)]),
+            ("_oximeter._tcp", vec![srv(12223, oximeter)]),
+            ("_cockroach._tcp", vec![srv(32221, cockroach)]),
+            ("_clickhouse._tcp", vec![srv(8123, clickhouse)]),
+            (
+                nexus1_host.as_str(),
+                vec![DnsRecord::Aaaa("fd00::1".parse().unwrap())],
+            ),
+            (
+                clickhouse_host.as_str(),
+                vec![DnsRecord::A("10.0.0.5".parse().unwrap())],
+            ),
+        ];
+        for (name, expected) in cases {
+            assert_eq!(records.get(name), Some(&expected), "name {}", name);
+        }
+        assert_eq!(records.len(), 9);
+    }
+}
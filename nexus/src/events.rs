@@ -0,0 +1,435 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Per-Project event logs, and their delivery to webhooks
+//!
+//! Nexus appends an event to a Project's log whenever an Instance, Disk, or
+//! Snapshot in the Project is created or deleted, and whenever an Instance or
+//! Disk changes state (whether a sled agent reported the change or Nexus asked
+//! for it).  Each event has a position in its Project's log, counting up from
+//! 1, which clients use as a cursor: they ask for the events after the last one
+//! they saw, waiting for more to arrive if there aren't any yet.
+//!
+//! A Project may also have webhooks, to which its events are POSTed as JSON in
+//! order, one per request.  Each request is signed with the webhook's secret:
+//! its [`SIGNATURE_HEADER`] header is "sha256=" followed by the hex-encoded
+//! HMAC-SHA256 of the request body.  Failed deliveries are retried with
+//! backoff, and an event isn't delivered until the ones before it have been, so
+//! a webhook that's down for a while gets what it missed once it comes back.
+//! Retries stop after the configured `delivery_retry_secs`, though, or as soon
+//! as the webhook rejects the event with a 4xx status (other than 408 or 429):
+//! the event is then recorded as the webhook's `last_failed_seq` and skipped,
+//! so that one bad event (or a receiver that's gone for good) doesn't hold up
+//! the rest.  Delivery is at least once: a webhook may see an event again if
+//! Nexus restarts between delivering it and recording that it did.
+//!
+//! Webhooks may not be delivered to the host's own addresses or to those of
+//! the control plane's networks (see [`is_internal`]), so that they can't be
+//! used to reach services that aren't otherwise exposed.  The URL's host is
+//! checked both when the webhook is created and each time an event is
+//! delivered, and deliveries go to the address that was checked.  Redirects
+//! aren't followed.
+//!
+//! TODO-robustness Events are appended after the change that they describe has
+//! been made, not in the same transaction, so an event is lost if Nexus
+//! crashes in between (or if appending it fails).
+//!
+//! TODO-scalability Every Nexus delivers events to every webhook, so a fleet
+//! with more than one Nexus delivers each event more than once.
+
+use crate::config::WebhooksConfig;
+use crate::db::model::{ProjectEvent, ProjectWebhook};
+use crate::db::DataStore;
+use crate::external_api::views;
+use crate::nexus::Nexus;
+use hmac::{Hmac, Mac};
+use http::StatusCode;
+use omicron_common::backoff;
+use sha2::Sha256;
+use slog::Logger;
+use std::collections::{BTreeMap, BTreeSet};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+
+/// Header carrying the signature of a webhook delivery
+pub const SIGNATURE_HEADER: &str = "x-oxide-signature";
+
+/// How often the log is checked for events appended by other Nexus instances,
+/// which this one isn't told about
+pub const EVENTS_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often the list of webhooks is refreshed
+const WEBHOOKS_REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long a webhook has to respond to a delivery
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Longest wait between attempts to deliver an event
+const DELIVERY_MAX_RETRY_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Most events fetched at once for delivery to a webhook
+const DELIVERY_BATCH_SIZE: u32 = 100;
+
+/// What happened to the resource that an event is about
+#[derive(Clone, Debug, PartialEq)]
+pub enum EventKind {
+    Created,
+    /// the resource changed to the given state
+    StateChanged(String),
+    Deleted,
+}
+
+/// Reasons a webhook's URL can't be delivered to
+#[derive(Debug, thiserror::Error)]
+pub enum DestinationError {
+    #[error("expected an http or https URL")]
+    BadUrl,
+    #[error("failed to resolve {0:?}: {1}")]
+    Unresolvable(String, std::io::Error),
+    #[error("{0} is an internal address")]
+    Internal(IpAddr),
+}
+
+/// Returns whether `ip` belongs to the host or to one of the control plane's
+/// networks (including the underlay, which is IPv6 unique local)
+pub fn is_internal(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let octets = ip.octets();
+            ip.is_unspecified()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_private()
+                || ip.is_broadcast()
+                || ip.is_multicast()
+                /* shared address space (RFC 6598) */
+                || (octets[0] == 100 && octets[1] & 0xc0 == 64)
+        }
+        IpAddr::V6(ip) => {
+            let first = ip.segments()[0];
+            ip.is_unspecified()
+                || ip.is_loopback()
+                || ip.is_multicast()
+                /* link-local (fe80::/10) */
+                || first & 0xffc0 == 0xfe80
+                /* unique local (fc00::/7) */
+                || first & 0xfe00 == 0xfc00
+                /* IPv4-mapped or -compatible addresses */
+                || ip.to_ipv4().map_or(false, |ip| is_internal(IpAddr::V4(ip)))
+        }
+    }
+}
+
+/// Resolves the host of a webhook's URL, returning the address to deliver to
+///
+/// Fails unless the URL is http or https and every address its host resolves
+/// to is either outside the host and control plane's networks or in one of
+/// `config`'s allowed networks.
+pub async fn destination_resolve(
+    url: &str,
+    config: &WebhooksConfig,
+) -> Result<SocketAddr, DestinationError> {
+    let url = reqwest::Url::parse(url).map_err(|_| DestinationError::BadUrl)?;
+    if url.scheme() != "http" && url.scheme() != "https" {
+        return Err(DestinationError::BadUrl);
+    }
+    let port = url.port_or_known_default().ok_or(DestinationError::BadUrl)?;
+    let host = url.host_str().ok_or(DestinationError::BadUrl)?;
+    let addresses: Vec<SocketAddr> = match url.domain() {
+        Some(domain) => tokio::net::lookup_host((domain, port))
+            .await
+            .map_err(|error| {
+                DestinationError::Unresolvable(domain.to_string(), error)
+            })?
+            .collect(),
+        None => {
+            /* IPv6 hosts are bracketed. */
+            let ip: IpAddr = host
+                .trim_start_matches('[')
+                .trim_end_matches(']')
+                .parse()
+                .map_err(|_| DestinationError::BadUrl)?;
+            vec![SocketAddr::new(ip, port)]
+        }
+    };
+    for address in &addresses {
+        let ip = address.ip();
+        if is_internal(ip)
+            && !config.allowed_internal_networks.iter().any(|n| n.contains(ip))
+        {
+            return Err(DestinationError::Internal(ip));
+        }
+    }
+    addresses.into_iter().next().ok_or_else(|| {
+        DestinationError::Unresolvable(
+            host.to_string(),
+            std::io::Error::from(std::io::ErrorKind::NotFound),
+        )
+    })
+}
+
+/// Returns the value of [`SIGNATURE_HEADER`] for a delivery of `body` to a
+/// webhook with secret `secret`
+pub fn signature(secret: &str, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// Waits up to `timeout` to be told that an event has been appended to some
+/// log, returning `false` if Nexus has shut down
+pub async fn wait_for_events(
+    published: &mut watch::Receiver<()>,
+    timeout: Duration,
+) -> bool {
+    !matches!(
+        tokio::time::timeout(timeout, published.changed()).await,
+        Ok(Err(_))
+    )
+}
+
+/// Starts delivering events to the webhooks of every Project, for as long as
+/// `nexus` is running
+pub fn webhooks_deliver_start(
+    nexus: &Arc<Nexus>,
+    config: WebhooksConfig,
+    log: Logger,
+) {
+    let nexus = Arc::downgrade(nexus);
+    tokio::spawn(webhooks_deliver(nexus, Arc::new(config), log));
+}
+
+async fn webhooks_deliver(
+    nexus: Weak<Nexus>,
+    config: Arc<WebhooksConfig>,
+    log: Logger,
+) {
+    let mut deliveries: BTreeMap<Uuid, JoinHandle<()>> = BTreeMap::new();
+    let mut interval = tokio::time::interval(WEBHOOKS_REFRESH_INTERVAL);
+    loop {
+        interval.tick().await;
+        let nexus = match nexus.upgrade() {
+            Some(nexus) => nexus,
+            None => break,
+        };
+        let webhooks =
+            match nexus.datastore().project_webhooks_list_all_no_auth().await {
+                Ok(webhooks) => webhooks,
+                Err(error) => {
+                    warn!(log, "failed to list webhooks"; "error" => ?error);
+                    continue;
+                }
+            };
+
+        /* Stop delivering to webhooks that have been deleted... */
+        let ids = webhooks.iter().map(|w| w.id).collect::<BTreeSet<_>>();
+        deliveries.retain(|id, task| {
+            if !ids.contains(id) {
+                task.abort();
+            }
+            ids.contains(id)
+        });
+
+        /* ... and start delivering to new ones. */
+        for webhook in webhooks {
+            if deliveries.contains_key(&webhook.id) {
+                continue;
+            }
+            let log = log.new(o!(
+                "webhook_id" => webhook.id.to_string(),
+                "project_id" => webhook.project_id.to_string(),
+            ));
+            info!(log, "delivering events to webhook"; "url" => %webhook.url);
+            deliveries.insert(
+                webhook.id,
+                tokio::spawn(webhook_deliver(
+                    Arc::clone(nexus.datastore()),
+                    Arc::clone(&config),
+                    log,
+                    webhook,
+                    nexus.project_events_subscribe(),
+                )),
+            );
+        }
+    }
+
+    for task in deliveries.values() {
+        task.abort();
+    }
+}
+
+/// Delivers the events in a Project's log to one of its webhooks, as they're
+/// appended
+async fn webhook_deliver(
+    datastore: Arc<DataStore>,
+    config: Arc<WebhooksConfig>,
+    log: Logger,
+    mut webhook: ProjectWebhook,
+    mut published: watch::Receiver<()>,
+) {
+    loop {
+        let events = datastore
+            .project_events_list_no_auth(
+                webhook.project_id,
+                webhook.delivered_seq,
+                DELIVERY_BATCH_SIZE,
+            )
+            .await
+            .unwrap_or_else(|error| {
+                warn!(log, "failed to list events"; "error" => ?error);
+                Vec::new()
+            });
+        let caught_up = events.len() < DELIVERY_BATCH_SIZE as usize;
+        for event in events {
+            let seq = event.seq;
+            if !event_deliver(&config, &log, &webhook, event).await {
+                if let Err(error) =
+                    datastore.project_webhook_set_failed(webhook.id, seq).await
+                {
+                    warn!(log, "failed to record failed event delivery";
+                        "seq" => seq, "error" => ?error);
+                }
+            }
+            if let Err(error) =
+                datastore.project_webhook_set_delivered(webhook.id, seq).await
+            {
+                warn!(log, "failed to record event delivery";
+                    "seq" => seq, "error" => ?error);
+            }
+            webhook.delivered_seq = seq;
+        }
+        if caught_up
+            && !wait_for_events(&mut published, EVENTS_POLL_INTERVAL).await
+        {
+            break;
+        }
+    }
+}
+
+/// Delivers one event to a webhook, retrying until it's accepted or rejected
+/// or until `config.delivery_retry_secs` have passed
+///
+/// Returns whether the event was delivered.
+async fn event_deliver(
+    config: &WebhooksConfig,
+    log: &Logger,
+    webhook: &ProjectWebhook,
+    event: ProjectEvent,
+) -> bool {
+    let seq = event.seq;
+    let event: views::ProjectEvent = event.into();
+    let body =
+        serde_json::to_vec(&event).expect("failed to serialize an event");
+    let signature = signature(&webhook.secret, &body);
+
+    let deliver = || async {
+        /*
+         * Resolve the webhook's host afresh each time, and connect to the
+         * address that was checked rather than letting the client resolve the
+         * name again.
+         */
+        let address = destination_resolve(&webhook.url, config).await.map_err(
+            |error| match error {
+                DestinationError::Unresolvable(..) => {
+                    backoff::BackoffError::Transient(error.to_string())
+                }
+                _ => backoff::BackoffError::Permanent(error.to_string()),
+            },
+        )?;
+        let mut client = reqwest::Client::builder()
+            .timeout(DELIVERY_TIMEOUT)
+            .redirect(reqwest::redirect::Policy::none());
+        if let Some(domain) = reqwest::Url::parse(&webhook.url)
+            .ok()
+            .and_then(|url| url.domain().map(String::from))
+        {
+            client = client.resolve(&domain, address);
+        }
+        let client = client.build().map_err(|error| {
+            backoff::BackoffError::Transient(error.to_string())
+        })?;
+        client
+            .post(&webhook.url)
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .header(SIGNATURE_HEADER, &signature)
+            .body(body.clone())
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map(|_| ())
+            .map_err(|error| match error.status() {
+                Some(status)
+                    if status.is_client_error()
+                        && status != StatusCode::REQUEST_TIMEOUT
+                        && status != StatusCode::TOO_MANY_REQUESTS =>
+                {
+                    backoff::BackoffError::Permanent(error.to_string())
+                }
+                _ => backoff::BackoffError::Transient(error.to_string()),
+            })
+    };
+    let log_failure = |error: String, delay| {
+        warn!(log, "failed to deliver event to webhook, will retry in {:?}",
+            delay; "seq" => seq, "error" => %error);
+    };
+    let policy = backoff::ExponentialBackoff {
+        max_interval: DELIVERY_MAX_RETRY_INTERVAL,
+        max_elapsed_time: Some(Duration::from_secs(config.delivery_retry_secs)),
+        ..backoff::internal_service_policy()
+    };
+    match backoff::retry_notify(policy, deliver, log_failure).await {
+        Ok(()) => true,
+        Err(error) => {
+            warn!(log, "giving up on delivering event to webhook";
+                "seq" => seq, "error" => %error);
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{is_internal, signature};
+
+    #[test]
+    fn test_signature() {
+        // This is test case 2 from RFC 4231.
+        assert_eq!(
+            signature("Jefe", b"what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b9\
+             64ec3843"
+        );
+    }
+
+    #[test]
+    fn test_is_internal() {
+        for ip in &[
+            "0.0.0.0",
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.20.0.1",
+            "192.168.1.20",
+            "169.254.169.254",
+            "100.64.0.1",
+            "255.255.255.255",
+            "::",
+            "::1",
+            "fe80::1",
+            "fd00:1122:3344:101::1",
+            "fc00::1",
+            "::ffff:127.0.0.1",
+            "::ffff:169.254.169.254",
+        ] {
+            assert!(is_internal(ip.parse().unwrap()), "{}", ip);
+        }
+        for ip in &["1.1.1.1", "172.32.0.1", "100.128.0.1", "2001:db8::1"] {
+            assert!(!is_internal(ip.parse().unwrap()), "{}", ip);
+        }
+    }
+}
@@ -17,8 +17,8 @@ use crate::authn::external::{
         SessionStore, SESSION_COOKIE_COOKIE_NAME,
     },
 };
-use crate::authn::{USER_TEST_PRIVILEGED, USER_TEST_UNPRIVILEGED};
 use crate::context::OpContext;
+use crate::throttle::HttpResponseThrottled;
 use crate::ServerContext;
 use dropshot::{
     endpoint, HttpError, HttpResponseOk, Path, Query, RequestContext, TypedBody,
@@ -31,15 +31,26 @@ use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use serde_urlencoded;
 use std::{collections::HashSet, ffi::OsString, path::PathBuf, sync::Arc};
-use uuid::Uuid;
 
-#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[derive(Clone, Deserialize, Serialize, JsonSchema)]
 pub struct LoginParams {
     pub username: String,
+    pub password: String,
 }
 
-// This is just for demo purposes. we will probably end up with a real username/password login
-// endpoint, but I think it will only be for use while setting up the rack
+impl std::fmt::Debug for LoginParams {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LoginParams")
+            .field("username", &self.username)
+            .field("password", &"<redacted>")
+            .finish()
+    }
+}
+
+// Log a user in by checking their password and starting a session for them.
+// Eventually, users may log in through an external identity provider instead.
+// Like the rest of the external API, this is rate-limited by source address
+// when that's known, which slows down password guessing.
 #[endpoint {
    method = POST,
    path = "/login",
@@ -47,40 +58,44 @@ pub struct LoginParams {
    // console to use the generated client for this request
    tags = ["hidden"],
 }]
-pub async fn spoof_login(
+pub async fn login(
     rqctx: Arc<RequestContext<Arc<ServerContext>>>,
     params: TypedBody<LoginParams>,
-) -> Result<Response<Body>, HttpError> {
+) -> Result<HttpResponseThrottled<Response<Body>>, HttpError> {
     let apictx = rqctx.context();
     let nexus = &apictx.nexus;
     let params = params.into_inner();
-    let user_id: Option<Uuid> = match params.username.as_str() {
-        "privileged" => Some(USER_TEST_PRIVILEGED.id),
-        "unprivileged" => Some(USER_TEST_UNPRIVILEGED.id),
-        _ => None,
+    let handler = async {
+        let user_id = nexus
+            .user_password_check(&params.username, &params.password)
+            .await?;
+
+        let user_id = match user_id {
+            Some(user_id) => user_id,
+            None => {
+                return Ok(Response::builder()
+                    .status(StatusCode::UNAUTHORIZED)
+                    .header(
+                        header::SET_COOKIE,
+                        clear_session_cookie_header_value(),
+                    )
+                    .body("".into())?); // TODO: failed login response body?
+            }
+        };
+
+        let session = nexus.session_create(user_id).await?;
+        Ok(Response::builder()
+            .status(StatusCode::OK)
+            .header(
+                header::SET_COOKIE,
+                session_cookie_header_value(
+                    &session.token,
+                    apictx.session_idle_timeout(),
+                ),
+            )
+            .body("ok".into())?) // TODO: what do we return from login?
     };
-
-    if user_id.is_none() {
-        return Ok(Response::builder()
-            .status(StatusCode::UNAUTHORIZED)
-            .header(header::SET_COOKIE, clear_session_cookie_header_value())
-            .body("".into())?); // TODO: failed login response body?
-    }
-
-    let session = nexus
-        // TODO: obviously
-        .session_create(user_id.unwrap())
-        .await?;
-    Ok(Response::builder()
-        .status(StatusCode::OK)
-        .header(
-            header::SET_COOKIE,
-            session_cookie_header_value(
-                &session.token,
-                apictx.session_idle_timeout(),
-            ),
-        )
-        .body("ok".into())?) // TODO: what do we return from login?
+    apictx.instrument_external_handler(&rqctx, handler).await
 }
 
 // Log user out of web console by deleting session in both server and browser
@@ -207,7 +222,8 @@ pub async fn login_redirect(
 }]
 pub async fn session_me(
     rqctx: Arc<RequestContext<Arc<ServerContext>>>,
-) -> Result<HttpResponseOk<views::SessionUser>, HttpError> {
+) -> Result<HttpResponseThrottled<HttpResponseOk<views::SessionUser>>, HttpError>
+{
     let apictx = rqctx.context();
     let handler = async {
         // TODO: we don't care about authentication method, as long as they are
@@ -217,7 +233,7 @@ pub async fn session_me(
         let &actor = opctx.authn.actor_required()?;
         Ok(HttpResponseOk(actor.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_external_handler(&rqctx, handler).await
 }
 
 // Dropshot does not have route match ranking and does not allow overlapping
@@ -0,0 +1,307 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+ * Entity tags and conditional requests (RFC 7232)
+ *
+ * The entity tag for a resource is derived from its `time_modified`, which
+ * changes with every update.  GET requests for resources that can be updated
+ * return it in the `ETag` header.  PUT and DELETE requests for those
+ * resources honor `If-Match` and `If-None-Match`, failing with 412
+ * ("Precondition Failed") if the resource has changed since the client last
+ * saw it.  This lets clients avoid clobbering each other's changes.  (Not
+ * every update honors them yet: see the TODO-correctness comments on the
+ * endpoints that don't.)
+ *
+ * Some things that can be updated aren't resources with a `time_modified` of
+ * their own.  A VPC's firewall rules, for example, are replaced as a set, and
+ * their entity tag is the VPC's (whose `time_modified` changes along with
+ * them).
+ */
+
+use crate::ServerContext;
+use chrono::{DateTime, Utc};
+use dropshot::ApiEndpointResponse;
+use dropshot::HttpError;
+use dropshot::HttpResponse;
+use dropshot::HttpResponseOk;
+use dropshot::RequestContext;
+use http::header;
+use http::HeaderMap;
+use http::HeaderValue;
+use http::Response;
+use hyper::Body;
+use omicron_common::api::external::Error;
+use omicron_common::api::external::ObjectIdentity;
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::sync::Arc;
+
+/**
+ * The current entity tag of a resource
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct ETag(String);
+
+impl ETag {
+    /** Returns the entity tag for a resource last modified at `time_modified` */
+    pub fn from_time_modified(time_modified: &DateTime<Utc>) -> ETag {
+        ETag(format!("\"{:x}\"", time_modified.timestamp_nanos()))
+    }
+
+    fn header_value(&self) -> HeaderValue {
+        /* This cannot fail because the tag is entirely ASCII. */
+        HeaderValue::from_str(&self.0).unwrap()
+    }
+}
+
+/**
+ * Like `HttpResponseOk`, but also returns the object's entity tag in the
+ * `ETag` header
+ */
+pub struct HttpResponseOkWithETag<T>(pub T);
+
+impl<T> HttpResponse for HttpResponseOkWithETag<T>
+where
+    T: ObjectIdentity + JsonSchema + Serialize + Send + Sync + 'static,
+{
+    fn to_result(self) -> Result<Response<Body>, HttpError> {
+        let etag = ETag::from_time_modified(&self.0.identity().time_modified);
+        HttpResponseOkTagged(self.0, etag).to_result()
+    }
+
+    fn metadata() -> ApiEndpointResponse {
+        HttpResponseOk::<T>::metadata()
+    }
+}
+
+/**
+ * Like `HttpResponseOkWithETag`, but for an object whose entity tag is given
+ * rather than derived from its own `time_modified`
+ */
+pub struct HttpResponseOkTagged<T>(pub T, pub ETag);
+
+impl<T> HttpResponse for HttpResponseOkTagged<T>
+where
+    T: JsonSchema + Serialize + Send + Sync + 'static,
+{
+    fn to_result(self) -> Result<Response<Body>, HttpError> {
+        let mut response = HttpResponseOk(self.0).to_result()?;
+        response.headers_mut().insert(header::ETAG, self.1.header_value());
+        Ok(response)
+    }
+
+    fn metadata() -> ApiEndpointResponse {
+        HttpResponseOk::<T>::metadata()
+    }
+}
+
+/**
+ * The value of an `If-Match` or `If-None-Match` header
+ */
+#[derive(Clone, Debug, PartialEq)]
+enum EntityTagCondition {
+    /** `*`, which matches any current entity tag */
+    Any,
+    /** a list of entity tags, with weak ones marked as such */
+    Tags(Vec<(String, bool)>),
+}
+
+impl EntityTagCondition {
+    fn parse(values: header::GetAll<'_, HeaderValue>) -> Result<Self, String> {
+        let mut tags = Vec::new();
+        for value in values {
+            let value = value
+                .to_str()
+                .map_err(|_| String::from("entity tags must be ASCII"))?;
+            for tag in value.split(',').map(str::trim) {
+                if tag.is_empty() {
+                    continue;
+                }
+                if tag == "*" {
+                    return Ok(EntityTagCondition::Any);
+                }
+                let (opaque, weak) = match tag.strip_prefix("W/") {
+                    Some(opaque) => (opaque, true),
+                    None => (tag, false),
+                };
+                if opaque.len() < 2
+                    || !opaque.starts_with('"')
+                    || !opaque.ends_with('"')
+                    || opaque[1..opaque.len() - 1].contains('"')
+                {
+                    return Err(format!("invalid entity tag: {}", tag));
+                }
+                tags.push((opaque.to_string(), weak));
+            }
+        }
+        Ok(EntityTagCondition::Tags(tags))
+    }
+
+    /**
+     * Returns whether `etag` matches this condition, using the strong
+     * comparison function (for `If-Match`) or the weak one (for
+     * `If-None-Match`)
+     */
+    fn matches(&self, etag: &ETag, strong: bool) -> bool {
+        match self {
+            EntityTagCondition::Any => true,
+            EntityTagCondition::Tags(tags) => tags
+                .iter()
+                .any(|(opaque, weak)| !(strong && *weak) && *opaque == etag.0),
+        }
+    }
+}
+
+/**
+ * Preconditions on a PUT or DELETE request, from its `If-Match` and
+ * `If-None-Match` headers
+ */
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Preconditions {
+    if_match: Option<EntityTagCondition>,
+    if_none_match: Option<EntityTagCondition>,
+}
+
+impl Preconditions {
+    /** Returns an empty set of preconditions, which any resource satisfies */
+    pub fn none() -> Preconditions {
+        Preconditions::default()
+    }
+
+    /** Parses the preconditions from the headers of the request `rqctx` */
+    pub async fn from_request(
+        rqctx: &Arc<RequestContext<Arc<ServerContext>>>,
+    ) -> Result<Preconditions, HttpError> {
+        let request = rqctx.request.lock().await;
+        Preconditions::from_headers(request.headers()).map_err(|message| {
+            HttpError::for_bad_request(
+                Some(String::from("InvalidPrecondition")),
+                message,
+            )
+        })
+    }
+
+    fn from_headers(headers: &HeaderMap) -> Result<Preconditions, String> {
+        let parse = |name: header::HeaderName| {
+            if headers.contains_key(&name) {
+                EntityTagCondition::parse(headers.get_all(&name))
+                    .map(Some)
+                    .map_err(|e| format!("header \"{}\": {}", name, e))
+            } else {
+                Ok(None)
+            }
+        };
+        Ok(Preconditions {
+            if_match: parse(header::IF_MATCH)?,
+            if_none_match: parse(header::IF_NONE_MATCH)?,
+        })
+    }
+
+    /** Returns whether there are no preconditions to check */
+    pub fn is_empty(&self) -> bool {
+        self.if_match.is_none() && self.if_none_match.is_none()
+    }
+
+    /**
+     * Checks these preconditions against a resource last modified at
+     * `time_modified`
+     *
+     * Following RFC 7232, `If-None-Match` is ignored when `If-Match` is
+     * present.
+     */
+    pub fn check(&self, time_modified: &DateTime<Utc>) -> Result<(), Error> {
+        let etag = ETag::from_time_modified(time_modified);
+        let ok = match (&self.if_match, &self.if_none_match) {
+            (Some(if_match), _) => if_match.matches(&etag, true),
+            (None, Some(if_none_match)) => !if_none_match.matches(&etag, false),
+            (None, None) => true,
+        };
+        if ok {
+            Ok(())
+        } else {
+            Err(Error::PreconditionFailed {
+                message: String::from(
+                    "resource does not match the request's preconditions",
+                ),
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ETag, EntityTagCondition, Preconditions};
+    use chrono::{TimeZone, Utc};
+    use http::header;
+    use http::HeaderMap;
+    use http::HeaderValue;
+
+    fn preconditions(
+        if_match: Option<&'static str>,
+        if_none_match: Option<&'static str>,
+    ) -> Result<Preconditions, String> {
+        let mut headers = HeaderMap::new();
+        if let Some(value) = if_match {
+            headers.insert(header::IF_MATCH, HeaderValue::from_static(value));
+        }
+        if let Some(value) = if_none_match {
+            headers
+                .insert(header::IF_NONE_MATCH, HeaderValue::from_static(value));
+        }
+        Preconditions::from_headers(&headers)
+    }
+
+    #[test]
+    fn test_parse() {
+        assert!(preconditions(None, None).unwrap().is_empty());
+        assert_eq!(
+            preconditions(Some("*"), None).unwrap().if_match,
+            Some(EntityTagCondition::Any)
+        );
+        assert_eq!(
+            preconditions(None, Some("\"a\", W/\"b\"")).unwrap().if_none_match,
+            Some(EntityTagCondition::Tags(vec![
+                (String::from("\"a\""), false),
+                (String::from("\"b\""), true),
+            ]))
+        );
+        assert_eq!(
+            preconditions(Some("a"), None).unwrap_err(),
+            "header \"if-match\": invalid entity tag: a"
+        );
+        assert_eq!(
+            preconditions(None, Some("\"a\"b\"")).unwrap_err(),
+            "header \"if-none-match\": invalid entity tag: \"a\"b\""
+        );
+    }
+
+    #[test]
+    fn test_check() {
+        let time_modified = Utc.timestamp(1_600_000_000, 0);
+        let other_time = Utc.timestamp(1_600_000_001, 0);
+        let etag = ETag::from_time_modified(&time_modified);
+        assert_eq!(etag.0, "\"16345785d8a00000\"");
+        assert_ne!(etag, ETag::from_time_modified(&other_time));
+
+        let check = |if_match, if_none_match| {
+            preconditions(if_match, if_none_match)
+                .unwrap()
+                .check(&time_modified)
+                .is_ok()
+        };
+        assert!(check(None, None));
+        assert!(check(Some("*"), None));
+        assert!(check(Some("\"16345785d8a00000\""), None));
+        assert!(check(Some("\"0\", \"16345785d8a00000\""), None));
+        assert!(!check(Some("\"0\""), None));
+        /* If-Match uses the strong comparison function. */
+        assert!(!check(Some("W/\"16345785d8a00000\""), None));
+        assert!(!check(None, Some("*")));
+        assert!(!check(None, Some("W/\"16345785d8a00000\"")));
+        assert!(check(None, Some("\"0\"")));
+        /* If-None-Match is ignored when If-Match is present. */
+        assert!(check(Some("*"), Some("*")));
+    }
+}
@@ -12,9 +12,24 @@ use crate::ServerContext;
 
 use super::{
     console_api, params,
-    views::{Organization, Project, Rack, Role, Sled, User, Vpc, VpcSubnet},
+    views::{
+        AffinityGroup, ApiToken, ApiTokenWithSecret, AuditLogEntry, FloatingIp,
+        InstanceSerialConsoleData, IpPool, IpPoolRange, Operation,
+        Organization, Policy, Project, ProjectEvent, ProjectEventsPage,
+        ProjectWebhook, Quota, Rack, Role, Sled, Snapshot, SshKey, User, Vpc,
+        VpcSubnet,
+    },
 };
 use crate::context::OpContext;
+use crate::external_api::etag::ETag;
+use crate::external_api::etag::HttpResponseOkTagged;
+use crate::external_api::etag::HttpResponseOkWithETag;
+use crate::external_api::etag::Preconditions;
+use crate::external_api::operation;
+use crate::external_api::operation::HttpResponseMaybeAccepted;
+use crate::external_api::operation::MaybeAccepted;
+use crate::throttle::HttpResponseThrottled;
+use chrono::{DateTime, Utc};
 use dropshot::ApiDescription;
 use dropshot::HttpError;
 use dropshot::HttpResponseAccepted;
@@ -29,6 +44,7 @@ use dropshot::ResultsPage;
 use dropshot::TypedBody;
 use dropshot::WhichPage;
 use dropshot::{endpoint, EmptyScanParams, PaginationOrder, PaginationParams};
+use hyper::{Body, Response};
 use omicron_common::api::external::http_pagination::data_page_params_for;
 use omicron_common::api::external::http_pagination::data_page_params_nameid_id;
 use omicron_common::api::external::http_pagination::data_page_params_nameid_name;
@@ -37,13 +53,16 @@ use omicron_common::api::external::http_pagination::PagField;
 use omicron_common::api::external::http_pagination::PaginatedById;
 use omicron_common::api::external::http_pagination::PaginatedByName;
 use omicron_common::api::external::http_pagination::PaginatedByNameOrId;
+use omicron_common::api::external::http_pagination::PaginatedByNameWithLabels;
 use omicron_common::api::external::http_pagination::ScanById;
 use omicron_common::api::external::http_pagination::ScanByName;
 use omicron_common::api::external::http_pagination::ScanByNameOrId;
+use omicron_common::api::external::http_pagination::ScanByNameWithLabels;
 use omicron_common::api::external::http_pagination::ScanParams;
 use omicron_common::api::external::to_list;
 use omicron_common::api::external::DataPageParams;
 use omicron_common::api::external::Disk;
+use omicron_common::api::external::EffectiveRouteTable;
 use omicron_common::api::external::Error;
 use omicron_common::api::external::Instance;
 use omicron_common::api::external::NetworkInterface;
@@ -51,16 +70,18 @@ use omicron_common::api::external::RouterRoute;
 use omicron_common::api::external::RouterRouteCreateParams;
 use omicron_common::api::external::RouterRouteKind;
 use omicron_common::api::external::RouterRouteUpdateParams;
-use omicron_common::api::external::Saga;
 use omicron_common::api::external::VpcFirewallRuleUpdateParams;
 use omicron_common::api::external::VpcFirewallRules;
 use omicron_common::api::external::VpcRouter;
 use omicron_common::api::external::VpcRouterKind;
+use omicron_common::websocket;
 use ref_cast::RefCast;
 use schemars::JsonSchema;
 use serde::Deserialize;
 use serde::Serialize;
+use std::num::NonZeroU32;
 use std::sync::Arc;
+use std::time::Duration;
 use uuid::Uuid;
 
 type NexusApiDescription = ApiDescription<Arc<ServerContext>>;
@@ -75,31 +96,75 @@ pub fn external_api() -> NexusApiDescription {
         api.register(organizations_get_organization)?;
         api.register(organizations_delete_organization)?;
         api.register(organizations_put_organization)?;
+        api.register(organizations_get_organization_quota)?;
+        api.register(organizations_put_organization_quota)?;
+        api.register(organizations_get_organization_policy)?;
+        api.register(organizations_put_organization_policy)?;
 
         api.register(organization_projects_get)?;
         api.register(organization_projects_post)?;
         api.register(organization_projects_get_project)?;
         api.register(organization_projects_delete_project)?;
         api.register(organization_projects_put_project)?;
+        api.register(organization_projects_get_project_quota)?;
+        api.register(organization_projects_put_project_quota)?;
+        api.register(organization_projects_get_project_policy)?;
+        api.register(organization_projects_put_project_policy)?;
 
         api.register(project_disks_get)?;
         api.register(project_disks_post)?;
         api.register(project_disks_get_disk)?;
+        api.register(project_disks_put_disk)?;
         api.register(project_disks_delete_disk)?;
+        api.register(project_disks_disk_resize)?;
+
+        api.register(disk_snapshots_get)?;
+        api.register(disk_snapshots_post)?;
+        api.register(disk_snapshots_get_snapshot)?;
+        api.register(disk_snapshots_delete_snapshot)?;
 
         api.register(project_instances_get)?;
         api.register(project_instances_post)?;
         api.register(project_instances_get_instance)?;
+        api.register(project_instances_put_instance)?;
         api.register(project_instances_delete_instance)?;
         api.register(project_instances_migrate_instance)?;
         api.register(project_instances_instance_reboot)?;
         api.register(project_instances_instance_start)?;
         api.register(project_instances_instance_stop)?;
+        api.register(instance_serial_console)?;
+        api.register(instance_serial_console_history)?;
 
         api.register(instance_disks_get)?;
         api.register(instance_disks_attach)?;
         api.register(instance_disks_detach)?;
 
+        api.register(instance_network_interfaces_get)?;
+        api.register(instance_network_interfaces_post)?;
+        api.register(instance_network_interfaces_get_interface)?;
+        api.register(instance_network_interfaces_delete_interface)?;
+
+        api.register(project_affinity_groups_get)?;
+        api.register(project_affinity_groups_post)?;
+        api.register(project_affinity_groups_get_group)?;
+        api.register(project_affinity_groups_put_group)?;
+        api.register(project_affinity_groups_delete_group)?;
+
+        api.register(ip_pools_get)?;
+        api.register(ip_pools_post)?;
+        api.register(ip_pools_get_ip_pool)?;
+        api.register(ip_pools_delete_ip_pool)?;
+        api.register(ip_pool_ranges_get)?;
+        api.register(ip_pool_ranges_post)?;
+        api.register(ip_pool_ranges_delete_range)?;
+
+        api.register(project_floating_ips_get)?;
+        api.register(project_floating_ips_post)?;
+        api.register(project_floating_ips_get_floating_ip)?;
+        api.register(project_floating_ips_delete_floating_ip)?;
+        api.register(project_floating_ips_attach)?;
+        api.register(project_floating_ips_detach)?;
+
         api.register(project_vpcs_get)?;
         api.register(project_vpcs_post)?;
         api.register(project_vpcs_get_vpc)?;
@@ -113,6 +178,9 @@ pub fn external_api() -> NexusApiDescription {
         api.register(vpc_subnets_put_subnet)?;
 
         api.register(subnets_ips_get)?;
+        api.register(vpc_subnets_attach_router)?;
+        api.register(vpc_subnets_detach_router)?;
+        api.register(vpc_subnets_get_effective_routes)?;
 
         api.register(vpc_routers_get)?;
         api.register(vpc_routers_get_router)?;
@@ -134,18 +202,37 @@ pub fn external_api() -> NexusApiDescription {
         api.register(hardware_sleds_get)?;
         api.register(hardware_sleds_get_sled)?;
 
-        api.register(sagas_get)?;
-        api.register(sagas_get_saga)?;
+        api.register(project_operations_get)?;
+        api.register(project_operations_get_operation)?;
+
+        api.register(project_events_get)?;
+        api.register(project_webhooks_get)?;
+        api.register(project_webhooks_post)?;
+        api.register(project_webhooks_delete_webhook)?;
 
         api.register(users_get)?;
+        api.register(users_post)?;
         api.register(users_get_user)?;
+        api.register(users_delete_user)?;
+        api.register(system_users_get)?;
+        api.register(system_users_get_user)?;
+
+        api.register(session_me_tokens_get)?;
+        api.register(session_me_tokens_post)?;
+        api.register(session_me_tokens_delete_token)?;
+
+        api.register(session_me_sshkeys_get)?;
+        api.register(session_me_sshkeys_post)?;
+        api.register(session_me_sshkeys_delete_key)?;
 
         api.register(timeseries_schema_get)?;
 
         api.register(roles_get)?;
         api.register(roles_get_role)?;
 
-        api.register(console_api::spoof_login)?;
+        api.register(audit_log_get)?;
+
+        api.register(console_api::login)?;
         api.register(console_api::spoof_login_form)?;
         api.register(console_api::login_redirect)?;
         api.register(console_api::session_me)?;
@@ -212,7 +299,10 @@ pub fn external_api() -> NexusApiDescription {
 async fn organizations_get(
     rqctx: Arc<RequestContext<Arc<ServerContext>>>,
     query_params: Query<PaginatedByNameOrId>,
-) -> Result<HttpResponseOk<ResultsPage<Organization>>, HttpError> {
+) -> Result<
+    HttpResponseThrottled<HttpResponseOk<ResultsPage<Organization>>>,
+    HttpError,
+> {
     let apictx = rqctx.context();
     let nexus = &apictx.nexus;
     let handler = async {
@@ -220,18 +310,31 @@ async fn organizations_get(
         let query = query_params.into_inner();
         let params = ScanByNameOrId::from_query(&query)?;
         let field = pagination_field_for_scan_params(params);
+        let label_selector = params.label_selector();
 
         let organizations = match field {
             PagField::Id => {
                 let page_selector = data_page_params_nameid_id(&rqctx, &query)?;
-                nexus.organizations_list_by_id(&opctx, &page_selector).await?
+                nexus
+                    .organizations_list_by_id(
+                        &opctx,
+                        &page_selector,
+                        label_selector,
+                    )
+                    .await?
             }
 
             PagField::Name => {
                 let page_selector =
                     data_page_params_nameid_name(&rqctx, &query)?
                         .map_name(|n| Name::ref_cast(n));
-                nexus.organizations_list_by_name(&opctx, &page_selector).await?
+                nexus
+                    .organizations_list_by_name(
+                        &opctx,
+                        &page_selector,
+                        label_selector,
+                    )
+                    .await?
             }
         }
         .into_iter()
@@ -239,7 +342,7 @@ async fn organizations_get(
         .collect();
         Ok(HttpResponseOk(ScanByNameOrId::results_page(&query, organizations)?))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_external_handler(&rqctx, handler).await
 }
 
 /**
@@ -253,17 +356,27 @@ async fn organizations_get(
 async fn organizations_post(
     rqctx: Arc<RequestContext<Arc<ServerContext>>>,
     new_organization: TypedBody<params::OrganizationCreate>,
-) -> Result<HttpResponseCreated<Organization>, HttpError> {
+) -> Result<HttpResponseThrottled<HttpResponseCreated<Organization>>, HttpError>
+{
     let apictx = rqctx.context();
     let nexus = &apictx.nexus;
     let handler = async {
         let opctx = OpContext::for_external_api(&rqctx).await?;
-        let organization = nexus
-            .organization_create(&opctx, &new_organization.into_inner())
+        let organization: Organization = nexus
+            .idempotent_create(
+                opctx,
+                new_organization.into_inner(),
+                |nexus, opctx, new_organization| async move {
+                    let organization = nexus
+                        .organization_create(&opctx, &new_organization)
+                        .await?;
+                    Ok(organization.into())
+                },
+            )
             .await?;
-        Ok(HttpResponseCreated(organization.into()))
+        Ok(HttpResponseCreated(organization))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_external_handler(&rqctx, handler).await
 }
 
 /**
@@ -286,7 +399,10 @@ struct OrganizationPathParam {
 async fn organizations_get_organization(
     rqctx: Arc<RequestContext<Arc<ServerContext>>>,
     path_params: Path<OrganizationPathParam>,
-) -> Result<HttpResponseOk<Organization>, HttpError> {
+) -> Result<
+    HttpResponseThrottled<HttpResponseOkWithETag<Organization>>,
+    HttpError,
+> {
     let apictx = rqctx.context();
     let nexus = &apictx.nexus;
     let path = path_params.into_inner();
@@ -295,9 +411,9 @@ async fn organizations_get_organization(
         let opctx = OpContext::for_external_api(&rqctx).await?;
         let organization =
             nexus.organization_fetch(&opctx, &organization_name).await?;
-        Ok(HttpResponseOk(organization.into()))
+        Ok(HttpResponseOkWithETag(organization.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_external_handler(&rqctx, handler).await
 }
 
 /**
@@ -311,17 +427,20 @@ async fn organizations_get_organization(
 async fn organizations_delete_organization(
     rqctx: Arc<RequestContext<Arc<ServerContext>>>,
     path_params: Path<OrganizationPathParam>,
-) -> Result<HttpResponseDeleted, HttpError> {
+) -> Result<HttpResponseThrottled<HttpResponseDeleted>, HttpError> {
     let apictx = rqctx.context();
     let nexus = &apictx.nexus;
     let params = path_params.into_inner();
     let organization_name = &params.organization_name;
     let handler = async {
         let opctx = OpContext::for_external_api(&rqctx).await?;
-        nexus.organization_delete(&opctx, &organization_name).await?;
+        let preconditions = Preconditions::from_request(&rqctx).await?;
+        nexus
+            .organization_delete(&opctx, &organization_name, &preconditions)
+            .await?;
         Ok(HttpResponseDeleted())
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_external_handler(&rqctx, handler).await
 }
 
 /**
@@ -343,23 +462,151 @@ async fn organizations_put_organization(
     rqctx: Arc<RequestContext<Arc<ServerContext>>>,
     path_params: Path<OrganizationPathParam>,
     updated_organization: TypedBody<params::OrganizationUpdate>,
-) -> Result<HttpResponseOk<Organization>, HttpError> {
+) -> Result<
+    HttpResponseThrottled<HttpResponseOkWithETag<Organization>>,
+    HttpError,
+> {
     let apictx = rqctx.context();
     let nexus = &apictx.nexus;
     let path = path_params.into_inner();
     let organization_name = &path.organization_name;
     let handler = async {
         let opctx = OpContext::for_external_api(&rqctx).await?;
+        let preconditions = Preconditions::from_request(&rqctx).await?;
         let new_organization = nexus
             .organization_update(
                 &opctx,
                 &organization_name,
                 &updated_organization.into_inner(),
+                &preconditions,
+            )
+            .await?;
+        Ok(HttpResponseOkWithETag(new_organization.into()))
+    };
+    apictx.instrument_external_handler(&rqctx, handler).await
+}
+
+/**
+ * Fetch an organization's quota and its current usage.
+ */
+#[endpoint {
+    method = GET,
+    path = "/organizations/{organization_name}/quota",
+    tags = ["organizations"],
+}]
+async fn organizations_get_organization_quota(
+    rqctx: Arc<RequestContext<Arc<ServerContext>>>,
+    path_params: Path<OrganizationPathParam>,
+) -> Result<HttpResponseThrottled<HttpResponseOk<Quota>>, HttpError> {
+    let apictx = rqctx.context();
+    let nexus = &apictx.nexus;
+    let path = path_params.into_inner();
+    let organization_name = &path.organization_name;
+    let handler = async {
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let quota =
+            nexus.organization_quota_fetch(&opctx, &organization_name).await?;
+        Ok(HttpResponseOk(quota.into()))
+    };
+    apictx.instrument_external_handler(&rqctx, handler).await
+}
+
+/**
+ * Replace an organization's quota.
+ */
+/*
+ * TODO-correctness Quotas have no modification time from which to derive an
+ * entity tag, so this ignores `If-Match` and `If-None-Match` (see
+ * [`crate::external_api::etag`]), and concurrent updates clobber each other.
+ */
+#[endpoint {
+    method = PUT,
+    path = "/organizations/{organization_name}/quota",
+    tags = ["organizations"],
+}]
+async fn organizations_put_organization_quota(
+    rqctx: Arc<RequestContext<Arc<ServerContext>>>,
+    path_params: Path<OrganizationPathParam>,
+    new_quota: TypedBody<params::QuotaUpdate>,
+) -> Result<HttpResponseThrottled<HttpResponseOk<Quota>>, HttpError> {
+    let apictx = rqctx.context();
+    let nexus = &apictx.nexus;
+    let path = path_params.into_inner();
+    let organization_name = &path.organization_name;
+    let handler = async {
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let quota = nexus
+            .organization_quota_update(
+                &opctx,
+                &organization_name,
+                &new_quota.into_inner(),
+            )
+            .await?;
+        Ok(HttpResponseOk(quota.into()))
+    };
+    apictx.instrument_external_handler(&rqctx, handler).await
+}
+
+/**
+ * Fetch an organization's policy, which grants users roles on it.
+ */
+#[endpoint {
+    method = GET,
+    path = "/organizations/{organization_name}/policy",
+    tags = ["organizations"],
+}]
+async fn organizations_get_organization_policy(
+    rqctx: Arc<RequestContext<Arc<ServerContext>>>,
+    path_params: Path<OrganizationPathParam>,
+) -> Result<HttpResponseThrottled<HttpResponseOk<Policy>>, HttpError> {
+    let apictx = rqctx.context();
+    let nexus = &apictx.nexus;
+    let path = path_params.into_inner();
+    let organization_name = &path.organization_name;
+    let handler = async {
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let policy =
+            nexus.organization_fetch_policy(&opctx, &organization_name).await?;
+        Ok(HttpResponseOk(policy.into()))
+    };
+    apictx.instrument_external_handler(&rqctx, handler).await
+}
+
+/**
+ * Replace an organization's policy.
+ */
+/*
+ * TODO-correctness A policy is just the set of role assignments, with no
+ * modification time from which to derive an entity tag, so this ignores
+ * `If-Match` and `If-None-Match` (see [`crate::external_api::etag`]), and
+ * concurrent updates clobber each other.
+ */
+#[endpoint {
+    method = PUT,
+    path = "/organizations/{organization_name}/policy",
+    tags = ["organizations"],
+}]
+async fn organizations_put_organization_policy(
+    rqctx: Arc<RequestContext<Arc<ServerContext>>>,
+    path_params: Path<OrganizationPathParam>,
+    new_policy: TypedBody<Policy>,
+) -> Result<HttpResponseThrottled<HttpResponseOk<Policy>>, HttpError> {
+    let apictx = rqctx.context();
+    let nexus = &apictx.nexus;
+    let path = path_params.into_inner();
+    let organization_name = &path.organization_name;
+    let handler = async {
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let policy = nexus
+            .organization_update_policy(
+                &opctx,
+                &organization_name,
+                &new_policy.into_inner(),
             )
             .await?;
-        Ok(HttpResponseOk(new_organization.into()))
+        Ok(HttpResponseOk(policy.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_external_handler(&rqctx, handler).await
 }
 
 /**
@@ -374,7 +621,10 @@ async fn organization_projects_get(
     rqctx: Arc<RequestContext<Arc<ServerContext>>>,
     query_params: Query<PaginatedByNameOrId>,
     path_params: Path<OrganizationPathParam>,
-) -> Result<HttpResponseOk<ResultsPage<Project>>, HttpError> {
+) -> Result<
+    HttpResponseThrottled<HttpResponseOk<ResultsPage<Project>>>,
+    HttpError,
+> {
     let apictx = rqctx.context();
     let nexus = &apictx.nexus;
     let query = query_params.into_inner();
@@ -385,6 +635,7 @@ async fn organization_projects_get(
         let opctx = OpContext::for_external_api(&rqctx).await?;
         let params = ScanByNameOrId::from_query(&query)?;
         let field = pagination_field_for_scan_params(params);
+        let label_selector = params.label_selector();
         let projects = match field {
             PagField::Id => {
                 let page_selector = data_page_params_nameid_id(&rqctx, &query)?;
@@ -393,6 +644,7 @@ async fn organization_projects_get(
                         &opctx,
                         &organization_name,
                         &page_selector,
+                        label_selector,
                     )
                     .await?
             }
@@ -406,6 +658,7 @@ async fn organization_projects_get(
                         &opctx,
                         &organization_name,
                         &page_selector,
+                        label_selector,
                     )
                     .await?
             }
@@ -415,7 +668,7 @@ async fn organization_projects_get(
         .collect();
         Ok(HttpResponseOk(ScanByNameOrId::results_page(&query, projects)?))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_external_handler(&rqctx, handler).await
 }
 
 /**
@@ -430,23 +683,32 @@ async fn organization_projects_post(
     rqctx: Arc<RequestContext<Arc<ServerContext>>>,
     path_params: Path<OrganizationPathParam>,
     new_project: TypedBody<params::ProjectCreate>,
-) -> Result<HttpResponseCreated<Project>, HttpError> {
+) -> Result<HttpResponseThrottled<HttpResponseCreated<Project>>, HttpError> {
     let apictx = rqctx.context();
     let nexus = &apictx.nexus;
     let params = path_params.into_inner();
-    let organization_name = &params.organization_name;
+    let organization_name = params.organization_name;
     let handler = async {
         let opctx = OpContext::for_external_api(&rqctx).await?;
-        let project = nexus
-            .project_create(
-                &opctx,
-                &organization_name,
-                &new_project.into_inner(),
+        let project: Project = nexus
+            .idempotent_create(
+                opctx,
+                new_project.into_inner(),
+                move |nexus, opctx, new_project| async move {
+                    let project = nexus
+                        .project_create(
+                            &opctx,
+                            &organization_name,
+                            &new_project,
+                        )
+                        .await?;
+                    Ok(project.into())
+                },
             )
             .await?;
-        Ok(HttpResponseCreated(project.into()))
+        Ok(HttpResponseCreated(project))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_external_handler(&rqctx, handler).await
 }
 
 /**
@@ -471,7 +733,7 @@ struct ProjectPathParam {
 async fn organization_projects_get_project(
     rqctx: Arc<RequestContext<Arc<ServerContext>>>,
     path_params: Path<ProjectPathParam>,
-) -> Result<HttpResponseOk<Project>, HttpError> {
+) -> Result<HttpResponseThrottled<HttpResponseOkWithETag<Project>>, HttpError> {
     let apictx = rqctx.context();
     let nexus = &apictx.nexus;
     let path = path_params.into_inner();
@@ -482,9 +744,9 @@ async fn organization_projects_get_project(
         let project = nexus
             .project_fetch(&opctx, &organization_name, &project_name)
             .await?;
-        Ok(HttpResponseOk(project.into()))
+        Ok(HttpResponseOkWithETag(project.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_external_handler(&rqctx, handler).await
 }
 
 /**
@@ -498,7 +760,7 @@ async fn organization_projects_get_project(
 async fn organization_projects_delete_project(
     rqctx: Arc<RequestContext<Arc<ServerContext>>>,
     path_params: Path<ProjectPathParam>,
-) -> Result<HttpResponseDeleted, HttpError> {
+) -> Result<HttpResponseThrottled<HttpResponseDeleted>, HttpError> {
     let apictx = rqctx.context();
     let nexus = &apictx.nexus;
     let params = path_params.into_inner();
@@ -506,10 +768,18 @@ async fn organization_projects_delete_project(
     let project_name = &params.project_name;
     let handler = async {
         let opctx = OpContext::for_external_api(&rqctx).await?;
-        nexus.project_delete(&opctx, &organization_name, &project_name).await?;
+        let preconditions = Preconditions::from_request(&rqctx).await?;
+        nexus
+            .project_delete(
+                &opctx,
+                &organization_name,
+                &project_name,
+                &preconditions,
+            )
+            .await?;
         Ok(HttpResponseDeleted())
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_external_handler(&rqctx, handler).await
 }
 
 /**
@@ -531,7 +801,7 @@ async fn organization_projects_put_project(
     rqctx: Arc<RequestContext<Arc<ServerContext>>>,
     path_params: Path<ProjectPathParam>,
     updated_project: TypedBody<params::ProjectUpdate>,
-) -> Result<HttpResponseOk<Project>, HttpError> {
+) -> Result<HttpResponseThrottled<HttpResponseOkWithETag<Project>>, HttpError> {
     let apictx = rqctx.context();
     let nexus = &apictx.nexus;
     let path = path_params.into_inner();
@@ -539,186 +809,170 @@ async fn organization_projects_put_project(
     let project_name = &path.project_name;
     let handler = async {
         let opctx = OpContext::for_external_api(&rqctx).await?;
+        let preconditions = Preconditions::from_request(&rqctx).await?;
         let newproject = nexus
             .project_update(
                 &opctx,
                 &organization_name,
                 &project_name,
                 &updated_project.into_inner(),
+                &preconditions,
             )
             .await?;
-        Ok(HttpResponseOk(newproject.into()))
+        Ok(HttpResponseOkWithETag(newproject.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_external_handler(&rqctx, handler).await
 }
 
-/*
- * Disks
- */
-
 /**
- * List disks in a project.
+ * Fetch a project's quota and its current usage.
  */
 #[endpoint {
     method = GET,
-    path = "/organizations/{organization_name}/projects/{project_name}/disks",
-    tags = ["disks"]
+    path = "/organizations/{organization_name}/projects/{project_name}/quota",
+    tags = ["projects"],
 }]
-async fn project_disks_get(
+async fn organization_projects_get_project_quota(
     rqctx: Arc<RequestContext<Arc<ServerContext>>>,
-    query_params: Query<PaginatedByName>,
     path_params: Path<ProjectPathParam>,
-) -> Result<HttpResponseOk<ResultsPage<Disk>>, HttpError> {
+) -> Result<HttpResponseThrottled<HttpResponseOk<Quota>>, HttpError> {
     let apictx = rqctx.context();
     let nexus = &apictx.nexus;
-    let query = query_params.into_inner();
     let path = path_params.into_inner();
     let organization_name = &path.organization_name;
     let project_name = &path.project_name;
     let handler = async {
         let opctx = OpContext::for_external_api(&rqctx).await?;
-        let disks = nexus
-            .project_list_disks(
-                &opctx,
-                organization_name,
-                project_name,
-                &data_page_params_for(&rqctx, &query)?
-                    .map_name(|n| Name::ref_cast(n)),
-            )
-            .await?
-            .into_iter()
-            .map(|d| d.into())
-            .collect();
-        Ok(HttpResponseOk(ScanByName::results_page(&query, disks)?))
+        let quota = nexus
+            .project_quota_fetch(&opctx, &organization_name, &project_name)
+            .await?;
+        Ok(HttpResponseOk(quota.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_external_handler(&rqctx, handler).await
 }
 
 /**
- * Create a disk in a project.
+ * Replace a project's quota.
  */
 /*
- * TODO-correctness See note about instance create.  This should be async.
+ * TODO-correctness Quotas have no modification time from which to derive an
+ * entity tag, so this ignores `If-Match` and `If-None-Match` (see
+ * [`crate::external_api::etag`]), and concurrent updates clobber each other.
  */
 #[endpoint {
-    method = POST,
-    path = "/organizations/{organization_name}/projects/{project_name}/disks",
-    tags = ["disks"]
+    method = PUT,
+    path = "/organizations/{organization_name}/projects/{project_name}/quota",
+    tags = ["projects"],
 }]
-async fn project_disks_post(
+async fn organization_projects_put_project_quota(
     rqctx: Arc<RequestContext<Arc<ServerContext>>>,
     path_params: Path<ProjectPathParam>,
-    new_disk: TypedBody<params::DiskCreate>,
-) -> Result<HttpResponseCreated<Disk>, HttpError> {
+    new_quota: TypedBody<params::QuotaUpdate>,
+) -> Result<HttpResponseThrottled<HttpResponseOk<Quota>>, HttpError> {
     let apictx = rqctx.context();
     let nexus = &apictx.nexus;
     let path = path_params.into_inner();
     let organization_name = &path.organization_name;
     let project_name = &path.project_name;
-    let new_disk_params = &new_disk.into_inner();
     let handler = async {
         let opctx = OpContext::for_external_api(&rqctx).await?;
-        let disk = nexus
-            .project_create_disk(
+        let quota = nexus
+            .project_quota_update(
                 &opctx,
                 &organization_name,
                 &project_name,
-                &new_disk_params,
+                &new_quota.into_inner(),
             )
             .await?;
-        Ok(HttpResponseCreated(disk.into()))
+        Ok(HttpResponseOk(quota.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
-}
-
-/**
- * Path parameters for Disk requests
- */
-#[derive(Deserialize, JsonSchema)]
-struct DiskPathParam {
-    organization_name: Name,
-    project_name: Name,
-    disk_name: Name,
+    apictx.instrument_external_handler(&rqctx, handler).await
 }
 
 /**
- * Fetch a single disk in a project.
+ * Fetch a project's policy, which grants users roles on it.
  */
 #[endpoint {
     method = GET,
-    path = "/organizations/{organization_name}/projects/{project_name}/disks/{disk_name}",
-    tags = ["disks"],
+    path = "/organizations/{organization_name}/projects/{project_name}/policy",
+    tags = ["projects"],
 }]
-async fn project_disks_get_disk(
+async fn organization_projects_get_project_policy(
     rqctx: Arc<RequestContext<Arc<ServerContext>>>,
-    path_params: Path<DiskPathParam>,
-) -> Result<HttpResponseOk<Disk>, HttpError> {
+    path_params: Path<ProjectPathParam>,
+) -> Result<HttpResponseThrottled<HttpResponseOk<Policy>>, HttpError> {
     let apictx = rqctx.context();
     let nexus = &apictx.nexus;
     let path = path_params.into_inner();
     let organization_name = &path.organization_name;
     let project_name = &path.project_name;
-    let disk_name = &path.disk_name;
     let handler = async {
         let opctx = OpContext::for_external_api(&rqctx).await?;
-        let disk = nexus
-            .disk_fetch(&opctx, &organization_name, &project_name, &disk_name)
+        let policy = nexus
+            .project_fetch_policy(&opctx, &organization_name, &project_name)
             .await?;
-        Ok(HttpResponseOk(disk.into()))
+        Ok(HttpResponseOk(policy.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_external_handler(&rqctx, handler).await
 }
 
 /**
- * Delete a disk from a project.
+ * Replace a project's policy.
+ */
+/*
+ * TODO-correctness A policy is just the set of role assignments, with no
+ * modification time from which to derive an entity tag, so this ignores
+ * `If-Match` and `If-None-Match` (see [`crate::external_api::etag`]), and
+ * concurrent updates clobber each other.
  */
 #[endpoint {
-    method = DELETE,
-    path = "/organizations/{organization_name}/projects/{project_name}/disks/{disk_name}",
-    tags = ["disks"],
+    method = PUT,
+    path = "/organizations/{organization_name}/projects/{project_name}/policy",
+    tags = ["projects"],
 }]
-async fn project_disks_delete_disk(
+async fn organization_projects_put_project_policy(
     rqctx: Arc<RequestContext<Arc<ServerContext>>>,
-    path_params: Path<DiskPathParam>,
-) -> Result<HttpResponseDeleted, HttpError> {
+    path_params: Path<ProjectPathParam>,
+    new_policy: TypedBody<Policy>,
+) -> Result<HttpResponseThrottled<HttpResponseOk<Policy>>, HttpError> {
     let apictx = rqctx.context();
     let nexus = &apictx.nexus;
     let path = path_params.into_inner();
     let organization_name = &path.organization_name;
     let project_name = &path.project_name;
-    let disk_name = &path.disk_name;
     let handler = async {
         let opctx = OpContext::for_external_api(&rqctx).await?;
-        nexus
-            .project_delete_disk(
+        let policy = nexus
+            .project_update_policy(
                 &opctx,
                 &organization_name,
                 &project_name,
-                &disk_name,
+                &new_policy.into_inner(),
             )
             .await?;
-        Ok(HttpResponseDeleted())
+        Ok(HttpResponseOk(policy.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_external_handler(&rqctx, handler).await
 }
 
 /*
- * Instances
+ * Disks
  */
 
 /**
- * List instances in a project.
+ * List disks in a project.
  */
 #[endpoint {
     method = GET,
-    path = "/organizations/{organization_name}/projects/{project_name}/instances",
-    tags = ["instances"],
+    path = "/organizations/{organization_name}/projects/{project_name}/disks",
+    tags = ["disks"]
 }]
-async fn project_instances_get(
+async fn project_disks_get(
     rqctx: Arc<RequestContext<Arc<ServerContext>>>,
-    query_params: Query<PaginatedByName>,
+    query_params: Query<PaginatedByNameWithLabels>,
     path_params: Path<ProjectPathParam>,
-) -> Result<HttpResponseOk<ResultsPage<Instance>>, HttpError> {
+) -> Result<HttpResponseThrottled<HttpResponseOk<ResultsPage<Disk>>>, HttpError>
+{
     let apictx = rqctx.context();
     let nexus = &apictx.nexus;
     let query = query_params.into_inner();
@@ -727,570 +981,2352 @@ async fn project_instances_get(
     let project_name = &path.project_name;
     let handler = async {
         let opctx = OpContext::for_external_api(&rqctx).await?;
-        let instances = nexus
-            .project_list_instances(
+        let disks = nexus
+            .project_list_disks(
                 &opctx,
-                &organization_name,
-                &project_name,
+                organization_name,
+                project_name,
                 &data_page_params_for(&rqctx, &query)?
                     .map_name(|n| Name::ref_cast(n)),
+                ScanByNameWithLabels::from_query(&query)?.label_selector(),
             )
             .await?
             .into_iter()
-            .map(|i| i.into())
+            .map(|d| d.into())
             .collect();
-        Ok(HttpResponseOk(ScanByName::results_page(&query, instances)?))
+        Ok(HttpResponseOk(ScanByNameWithLabels::results_page(&query, disks)?))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_external_handler(&rqctx, handler).await
 }
 
 /**
- * Create an instance in a project.
- */
-/*
- * TODO-correctness This is supposed to be async.  Is that right?  We can create
- * the instance immediately -- it's just not booted yet.  Maybe the boot
- * operation is what's a separate operation_id.  What about the response code
- * (201 Created vs 202 Accepted)?  Is that orthogonal?  Things can return a
- * useful response, including an operation id, with either response code.  Maybe
- * a "reboot" operation would return a 202 Accepted because there's no actual
- * resource created?
+ * Create a disk in a project.
+ *
+ * With `Prefer: respond-async`, this returns 202 ("Accepted") and the
+ * operation creating the disk rather than waiting for it to be created.
  */
 #[endpoint {
     method = POST,
-     path = "/organizations/{organization_name}/projects/{project_name}/instances",
-    tags = ["instances"],
+    path = "/organizations/{organization_name}/projects/{project_name}/disks",
+    tags = ["disks"]
 }]
-async fn project_instances_post(
+async fn project_disks_post(
     rqctx: Arc<RequestContext<Arc<ServerContext>>>,
     path_params: Path<ProjectPathParam>,
-    new_instance: TypedBody<params::InstanceCreate>,
-) -> Result<HttpResponseCreated<Instance>, HttpError> {
+    new_disk: TypedBody<params::DiskCreate>,
+) -> Result<
+    HttpResponseThrottled<HttpResponseMaybeAccepted<HttpResponseCreated<Disk>>>,
+    HttpError,
+> {
     let apictx = rqctx.context();
     let nexus = &apictx.nexus;
     let path = path_params.into_inner();
     let organization_name = &path.organization_name;
     let project_name = &path.project_name;
-    let new_instance_params = &new_instance.into_inner();
     let handler = async {
         let opctx = OpContext::for_external_api(&rqctx).await?;
-        let instance = nexus
-            .project_create_instance(
-                &opctx,
-                &organization_name,
-                &project_name,
-                &new_instance_params,
+        let respond_async = operation::respond_async(&rqctx).await;
+        let (org, project) = (organization_name.clone(), project_name.clone());
+        let result: MaybeAccepted<Disk> = nexus
+            .idempotent_create(
+                opctx,
+                new_disk.into_inner(),
+                move |nexus, opctx, new_disk_params| async move {
+                    let result = nexus
+                        .project_create_disk(
+                            &opctx,
+                            &org,
+                            &project,
+                            &new_disk_params,
+                            respond_async,
+                        )
+                        .await?;
+                    Ok(result.map(|disk| disk.into()))
+                },
             )
             .await?;
-        Ok(HttpResponseCreated(instance.into()))
+        Ok(result.into_response(
+            organization_name,
+            project_name,
+            HttpResponseCreated,
+        ))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_external_handler(&rqctx, handler).await
 }
 
 /**
- * Path parameters for Instance requests
+ * Path parameters for Disk requests
  */
 #[derive(Deserialize, JsonSchema)]
-struct InstancePathParam {
+struct DiskPathParam {
     organization_name: Name,
     project_name: Name,
-    instance_name: Name,
+    disk_name: Name,
 }
 
 /**
- * Get an instance in a project.
+ * Fetch a single disk in a project.
  */
 #[endpoint {
     method = GET,
-    path = "/organizations/{organization_name}/projects/{project_name}/instances/{instance_name}",
-    tags = ["instances"],
+    path = "/organizations/{organization_name}/projects/{project_name}/disks/{disk_name}",
+    tags = ["disks"],
 }]
-async fn project_instances_get_instance(
+async fn project_disks_get_disk(
     rqctx: Arc<RequestContext<Arc<ServerContext>>>,
-    path_params: Path<InstancePathParam>,
-) -> Result<HttpResponseOk<Instance>, HttpError> {
+    path_params: Path<DiskPathParam>,
+) -> Result<HttpResponseThrottled<HttpResponseOkWithETag<Disk>>, HttpError> {
     let apictx = rqctx.context();
     let nexus = &apictx.nexus;
     let path = path_params.into_inner();
     let organization_name = &path.organization_name;
     let project_name = &path.project_name;
-    let instance_name = &path.instance_name;
+    let disk_name = &path.disk_name;
     let handler = async {
         let opctx = OpContext::for_external_api(&rqctx).await?;
-        let instance = nexus
-            .instance_fetch(
-                &opctx,
-                &organization_name,
-                &project_name,
-                &instance_name,
-            )
+        let disk = nexus
+            .disk_fetch(&opctx, &organization_name, &project_name, &disk_name)
             .await?;
-        Ok(HttpResponseOk(instance.into()))
+        Ok(HttpResponseOkWithETag(disk.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_external_handler(&rqctx, handler).await
 }
 
 /**
- * Delete an instance from a project.
+ * Update a disk's labels.
  */
 #[endpoint {
-    method = DELETE,
-    path = "/organizations/{organization_name}/projects/{project_name}/instances/{instance_name}",
-    tags = ["instances"],
-}]
-async fn project_instances_delete_instance(
+    method = PUT,
+    path = "/organizations/{organization_name}/projects/{project_name}/disks/{disk_name}",
+    tags = ["disks"],
+}]
+async fn project_disks_put_disk(
     rqctx: Arc<RequestContext<Arc<ServerContext>>>,
-    path_params: Path<InstancePathParam>,
-) -> Result<HttpResponseDeleted, HttpError> {
+    path_params: Path<DiskPathParam>,
+    updated_disk: TypedBody<params::DiskUpdate>,
+) -> Result<HttpResponseThrottled<HttpResponseOkWithETag<Disk>>, HttpError> {
     let apictx = rqctx.context();
     let nexus = &apictx.nexus;
     let path = path_params.into_inner();
     let organization_name = &path.organization_name;
     let project_name = &path.project_name;
-    let instance_name = &path.instance_name;
+    let disk_name = &path.disk_name;
     let handler = async {
         let opctx = OpContext::for_external_api(&rqctx).await?;
-        nexus
-            .project_destroy_instance(
+        let preconditions = Preconditions::from_request(&rqctx).await?;
+        let disk = nexus
+            .project_update_disk(
                 &opctx,
                 &organization_name,
                 &project_name,
-                &instance_name,
+                &disk_name,
+                &updated_disk.into_inner(),
+                &preconditions,
             )
             .await?;
-        Ok(HttpResponseDeleted())
+        Ok(HttpResponseOkWithETag(disk.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_external_handler(&rqctx, handler).await
 }
 
 /**
- * Migrate an instance to a different propolis-server, possibly on a different sled.
+ * Delete a disk from a project.
+ *
+ * With `Prefer: respond-async`, this returns 202 ("Accepted") and the
+ * operation deleting the disk rather than waiting for it to be deleted.
  */
 #[endpoint {
-    method = POST,
-    path = "/organizations/{organization_name}/projects/{project_name}/instances/{instance_name}/migrate",
-    tags = ["instances"],
+    method = DELETE,
+    path = "/organizations/{organization_name}/projects/{project_name}/disks/{disk_name}",
+    tags = ["disks"],
 }]
-async fn project_instances_migrate_instance(
+async fn project_disks_delete_disk(
     rqctx: Arc<RequestContext<Arc<ServerContext>>>,
-    path_params: Path<InstancePathParam>,
-    migrate_params: TypedBody<params::InstanceMigrate>,
-) -> Result<HttpResponseOk<Instance>, HttpError> {
+    path_params: Path<DiskPathParam>,
+) -> Result<
+    HttpResponseThrottled<HttpResponseMaybeAccepted<HttpResponseDeleted>>,
+    HttpError,
+> {
     let apictx = rqctx.context();
     let nexus = &apictx.nexus;
     let path = path_params.into_inner();
     let organization_name = &path.organization_name;
     let project_name = &path.project_name;
-    let instance_name = &path.instance_name;
-    let migrate_instance_params = migrate_params.into_inner();
+    let disk_name = &path.disk_name;
     let handler = async {
         let opctx = OpContext::for_external_api(&rqctx).await?;
-        let instance = nexus
-            .project_migrate_instance(
+        let respond_async = operation::respond_async(&rqctx).await;
+        let result = nexus
+            .project_delete_disk(
                 &opctx,
                 &organization_name,
                 &project_name,
-                &instance_name,
-                migrate_instance_params,
+                &disk_name,
+                respond_async,
             )
             .await?;
-        Ok(HttpResponseOk(instance.into()))
+        Ok(result.into_response(organization_name, project_name, |()| {
+            HttpResponseDeleted()
+        }))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_external_handler(&rqctx, handler).await
 }
 
 /**
- * Reboot an instance.
+ * Grow a disk to a new size.
  */
 #[endpoint {
     method = POST,
-    path = "/organizations/{organization_name}/projects/{project_name}/instances/{instance_name}/reboot",
-    tags = ["instances"],
+    path = "/organizations/{organization_name}/projects/{project_name}/disks/{disk_name}/resize",
+    tags = ["disks"],
 }]
-async fn project_instances_instance_reboot(
+async fn project_disks_disk_resize(
     rqctx: Arc<RequestContext<Arc<ServerContext>>>,
-    path_params: Path<InstancePathParam>,
-) -> Result<HttpResponseAccepted<Instance>, HttpError> {
+    path_params: Path<DiskPathParam>,
+    resize_params: TypedBody<params::DiskResize>,
+) -> Result<HttpResponseThrottled<HttpResponseOk<Disk>>, HttpError> {
     let apictx = rqctx.context();
     let nexus = &apictx.nexus;
     let path = path_params.into_inner();
     let organization_name = &path.organization_name;
     let project_name = &path.project_name;
-    let instance_name = &path.instance_name;
+    let disk_name = &path.disk_name;
     let handler = async {
         let opctx = OpContext::for_external_api(&rqctx).await?;
-        let instance = nexus
-            .instance_reboot(
+        let disk = nexus
+            .project_resize_disk(
                 &opctx,
                 &organization_name,
                 &project_name,
-                &instance_name,
+                &disk_name,
+                &resize_params.into_inner(),
             )
             .await?;
-        Ok(HttpResponseAccepted(instance.into()))
+        Ok(HttpResponseOk(disk.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_external_handler(&rqctx, handler).await
 }
 
+/*
+ * Snapshots
+ */
+
 /**
- * Boot an instance.
+ * List snapshots of a disk.
  */
 #[endpoint {
-    method = POST,
-    path = "/organizations/{organization_name}/projects/{project_name}/instances/{instance_name}/start",
-    tags = ["instances"],
+    method = GET,
+    path = "/organizations/{organization_name}/projects/{project_name}/disks/{disk_name}/snapshots",
+    tags = ["snapshots"],
 }]
-async fn project_instances_instance_start(
+async fn disk_snapshots_get(
     rqctx: Arc<RequestContext<Arc<ServerContext>>>,
-    path_params: Path<InstancePathParam>,
-) -> Result<HttpResponseAccepted<Instance>, HttpError> {
+    query_params: Query<PaginatedByName>,
+    path_params: Path<DiskPathParam>,
+) -> Result<
+    HttpResponseThrottled<HttpResponseOk<ResultsPage<Snapshot>>>,
+    HttpError,
+> {
     let apictx = rqctx.context();
     let nexus = &apictx.nexus;
+    let query = query_params.into_inner();
     let path = path_params.into_inner();
     let organization_name = &path.organization_name;
     let project_name = &path.project_name;
-    let instance_name = &path.instance_name;
+    let disk_name = &path.disk_name;
     let handler = async {
         let opctx = OpContext::for_external_api(&rqctx).await?;
-        let instance = nexus
-            .instance_start(
+        let snapshots = nexus
+            .disk_list_snapshots(
                 &opctx,
-                &organization_name,
-                &project_name,
-                &instance_name,
+                organization_name,
+                project_name,
+                disk_name,
+                &data_page_params_for(&rqctx, &query)?
+                    .map_name(|n| Name::ref_cast(n)),
             )
-            .await?;
-        Ok(HttpResponseAccepted(instance.into()))
+            .await?
+            .into_iter()
+            .map(|s| s.into())
+            .collect();
+        Ok(HttpResponseOk(ScanByName::results_page(&query, snapshots)?))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_external_handler(&rqctx, handler).await
 }
 
 /**
- * Halt an instance.
+ * Take a snapshot of a disk.
  */
 #[endpoint {
     method = POST,
-    path = "/organizations/{organization_name}/projects/{project_name}/instances/{instance_name}/stop",
-    tags = ["instances"],
+    path = "/organizations/{organization_name}/projects/{project_name}/disks/{disk_name}/snapshots",
+    tags = ["snapshots"],
 }]
-/* Our naming convention kind of falls apart here. */
-async fn project_instances_instance_stop(
+async fn disk_snapshots_post(
     rqctx: Arc<RequestContext<Arc<ServerContext>>>,
-    path_params: Path<InstancePathParam>,
-) -> Result<HttpResponseAccepted<Instance>, HttpError> {
+    path_params: Path<DiskPathParam>,
+    new_snapshot: TypedBody<params::SnapshotCreate>,
+) -> Result<HttpResponseThrottled<HttpResponseCreated<Snapshot>>, HttpError> {
     let apictx = rqctx.context();
     let nexus = &apictx.nexus;
     let path = path_params.into_inner();
-    let organization_name = &path.organization_name;
-    let project_name = &path.project_name;
-    let instance_name = &path.instance_name;
     let handler = async {
         let opctx = OpContext::for_external_api(&rqctx).await?;
-        let instance = nexus
-            .instance_stop(
-                &opctx,
-                &organization_name,
-                &project_name,
-                &instance_name,
+        let snapshot: Snapshot = nexus
+            .idempotent_create(
+                opctx,
+                new_snapshot.into_inner(),
+                move |nexus, opctx, new_snapshot_params| async move {
+                    let snapshot = nexus
+                        .disk_create_snapshot(
+                            &opctx,
+                            &path.organization_name,
+                            &path.project_name,
+                            &path.disk_name,
+                            &new_snapshot_params,
+                        )
+                        .await?;
+                    Ok(snapshot.into())
+                },
             )
             .await?;
-        Ok(HttpResponseAccepted(instance.into()))
+        Ok(HttpResponseCreated(snapshot))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_external_handler(&rqctx, handler).await
 }
 
 /**
- * List disks attached to this instance.
+ * Path parameters for Snapshot requests
+ */
+#[derive(Deserialize, JsonSchema)]
+struct SnapshotPathParam {
+    organization_name: Name,
+    project_name: Name,
+    disk_name: Name,
+    snapshot_name: Name,
+}
+
+/**
+ * Fetch a single snapshot of a disk.
  */
-/* TODO-scalability needs to be paginated */
 #[endpoint {
     method = GET,
-    path = "/organizations/{organization_name}/projects/{project_name}/instances/{instance_name}/disks",
-    tags = ["instances"],
+    path = "/organizations/{organization_name}/projects/{project_name}/disks/{disk_name}/snapshots/{snapshot_name}",
+    tags = ["snapshots"],
 }]
-async fn instance_disks_get(
+async fn disk_snapshots_get_snapshot(
     rqctx: Arc<RequestContext<Arc<ServerContext>>>,
-    query_params: Query<PaginatedByName>,
-    path_params: Path<InstancePathParam>,
-) -> Result<HttpResponseOk<ResultsPage<Disk>>, HttpError> {
+    path_params: Path<SnapshotPathParam>,
+) -> Result<HttpResponseThrottled<HttpResponseOk<Snapshot>>, HttpError> {
     let apictx = rqctx.context();
     let nexus = &apictx.nexus;
-    let query = query_params.into_inner();
     let path = path_params.into_inner();
     let organization_name = &path.organization_name;
     let project_name = &path.project_name;
-    let instance_name = &path.instance_name;
+    let disk_name = &path.disk_name;
+    let snapshot_name = &path.snapshot_name;
     let handler = async {
         let opctx = OpContext::for_external_api(&rqctx).await?;
-        let disks = nexus
-            .instance_list_disks(
+        let snapshot = nexus
+            .snapshot_fetch(
                 &opctx,
                 &organization_name,
                 &project_name,
-                &instance_name,
-                &data_page_params_for(&rqctx, &query)?
-                    .map_name(|n| Name::ref_cast(n)),
+                &disk_name,
+                &snapshot_name,
             )
-            .await?
-            .into_iter()
-            .map(|d| d.into())
-            .collect();
-        Ok(HttpResponseOk(ScanByName::results_page(&query, disks)?))
+            .await?;
+        Ok(HttpResponseOk(snapshot.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_external_handler(&rqctx, handler).await
 }
 
+/**
+ * Delete a snapshot of a disk.
+ */
 #[endpoint {
-    method = POST,
-    path = "/organizations/{organization_name}/projects/{project_name}/instances/{instance_name}/disks/attach",
-    tags = ["instances"],
+    method = DELETE,
+    path = "/organizations/{organization_name}/projects/{project_name}/disks/{disk_name}/snapshots/{snapshot_name}",
+    tags = ["snapshots"],
 }]
-async fn instance_disks_attach(
+async fn disk_snapshots_delete_snapshot(
     rqctx: Arc<RequestContext<Arc<ServerContext>>>,
-    path_params: Path<InstancePathParam>,
-    disk_to_attach: TypedBody<params::DiskIdentifier>,
-) -> Result<HttpResponseAccepted<Disk>, HttpError> {
+    path_params: Path<SnapshotPathParam>,
+) -> Result<HttpResponseThrottled<HttpResponseDeleted>, HttpError> {
     let apictx = rqctx.context();
     let nexus = &apictx.nexus;
     let path = path_params.into_inner();
     let organization_name = &path.organization_name;
     let project_name = &path.project_name;
-    let instance_name = &path.instance_name;
+    let disk_name = &path.disk_name;
+    let snapshot_name = &path.snapshot_name;
     let handler = async {
         let opctx = OpContext::for_external_api(&rqctx).await?;
-        let disk = nexus
-            .instance_attach_disk(
+        nexus
+            .disk_delete_snapshot(
                 &opctx,
                 &organization_name,
                 &project_name,
-                &instance_name,
-                &disk_to_attach.into_inner().disk.into(),
+                &disk_name,
+                &snapshot_name,
             )
             .await?;
-        Ok(HttpResponseAccepted(disk.into()))
+        Ok(HttpResponseDeleted())
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_external_handler(&rqctx, handler).await
 }
 
+/*
+ * Instances
+ */
+
+/**
+ * List instances in a project.
+ */
 #[endpoint {
-    method = POST,
-    path = "/organizations/{organization_name}/projects/{project_name}/instances/{instance_name}/disks/detach",
+    method = GET,
+    path = "/organizations/{organization_name}/projects/{project_name}/instances",
     tags = ["instances"],
 }]
-async fn instance_disks_detach(
+async fn project_instances_get(
     rqctx: Arc<RequestContext<Arc<ServerContext>>>,
-    path_params: Path<InstancePathParam>,
-    disk_to_detach: TypedBody<params::DiskIdentifier>,
-) -> Result<HttpResponseAccepted<Disk>, HttpError> {
+    query_params: Query<PaginatedByNameWithLabels>,
+    path_params: Path<ProjectPathParam>,
+) -> Result<
+    HttpResponseThrottled<HttpResponseOk<ResultsPage<Instance>>>,
+    HttpError,
+> {
     let apictx = rqctx.context();
     let nexus = &apictx.nexus;
+    let query = query_params.into_inner();
     let path = path_params.into_inner();
     let organization_name = &path.organization_name;
     let project_name = &path.project_name;
-    let instance_name = &path.instance_name;
     let handler = async {
         let opctx = OpContext::for_external_api(&rqctx).await?;
-        let disk = nexus
-            .instance_detach_disk(
+        let instances = nexus
+            .project_list_instances(
                 &opctx,
                 &organization_name,
                 &project_name,
-                &instance_name,
-                &disk_to_detach.into_inner().disk.into(),
+                &data_page_params_for(&rqctx, &query)?
+                    .map_name(|n| Name::ref_cast(n)),
+                ScanByNameWithLabels::from_query(&query)?.label_selector(),
             )
-            .await?;
-        Ok(HttpResponseAccepted(disk.into()))
+            .await?
+            .into_iter()
+            .map(|i| i.into())
+            .collect();
+        Ok(HttpResponseOk(ScanByNameWithLabels::results_page(
+            &query, instances,
+        )?))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_external_handler(&rqctx, handler).await
 }
 
-/*
- * VPCs
- */
-
 /**
- * List VPCs in a project.
+ * Create an instance in a project.
+ *
+ * With `Prefer: respond-async`, this returns 202 ("Accepted") and the
+ * operation creating the instance rather than waiting for it to be created.
  */
 #[endpoint {
-    method = GET,
-    path = "/organizations/{organization_name}/projects/{project_name}/vpcs",
-    tags = ["vpcs"],
+    method = POST,
+     path = "/organizations/{organization_name}/projects/{project_name}/instances",
+    tags = ["instances"],
 }]
-async fn project_vpcs_get(
+async fn project_instances_post(
     rqctx: Arc<RequestContext<Arc<ServerContext>>>,
-    query_params: Query<PaginatedByName>,
     path_params: Path<ProjectPathParam>,
-) -> Result<HttpResponseOk<ResultsPage<Vpc>>, HttpError> {
+    new_instance: TypedBody<params::InstanceCreate>,
+) -> Result<
+    HttpResponseThrottled<
+        HttpResponseMaybeAccepted<HttpResponseCreated<Instance>>,
+    >,
+    HttpError,
+> {
     let apictx = rqctx.context();
     let nexus = &apictx.nexus;
-    let query = query_params.into_inner();
     let path = path_params.into_inner();
     let organization_name = &path.organization_name;
     let project_name = &path.project_name;
     let handler = async {
-        let vpcs = nexus
-            .project_list_vpcs(
-                &organization_name,
-                &project_name,
-                &data_page_params_for(&rqctx, &query)?
-                    .map_name(|n| Name::ref_cast(n)),
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let respond_async = operation::respond_async(&rqctx).await;
+        let (org, project) = (organization_name.clone(), project_name.clone());
+        let result: MaybeAccepted<Instance> = nexus
+            .idempotent_create(
+                opctx,
+                new_instance.into_inner(),
+                move |nexus, opctx, new_instance_params| async move {
+                    let result = nexus
+                        .project_create_instance(
+                            &opctx,
+                            &org,
+                            &project,
+                            &new_instance_params,
+                            respond_async,
+                        )
+                        .await?;
+                    Ok(result.map(|instance| instance.into()))
+                },
             )
-            .await?
-            .into_iter()
-            .map(|p| p.into())
-            .collect();
-
-        Ok(HttpResponseOk(ScanByName::results_page(&query, vpcs)?))
+            .await?;
+        Ok(result.into_response(
+            organization_name,
+            project_name,
+            HttpResponseCreated,
+        ))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_external_handler(&rqctx, handler).await
 }
 
 /**
- * Path parameters for VPC requests
+ * Path parameters for Instance requests
  */
 #[derive(Deserialize, JsonSchema)]
-struct VpcPathParam {
+struct InstancePathParam {
     organization_name: Name,
     project_name: Name,
-    vpc_name: Name,
+    instance_name: Name,
 }
 
 /**
- * Get a VPC in a project.
+ * Get an instance in a project.
  */
 #[endpoint {
     method = GET,
-    path = "/organizations/{organization_name}/projects/{project_name}/vpcs/{vpc_name}",
-    tags = ["vpcs"],
+    path = "/organizations/{organization_name}/projects/{project_name}/instances/{instance_name}",
+    tags = ["instances"],
 }]
-async fn project_vpcs_get_vpc(
+async fn project_instances_get_instance(
     rqctx: Arc<RequestContext<Arc<ServerContext>>>,
-    path_params: Path<VpcPathParam>,
-) -> Result<HttpResponseOk<Vpc>, HttpError> {
+    path_params: Path<InstancePathParam>,
+) -> Result<HttpResponseThrottled<HttpResponseOk<Instance>>, HttpError> {
     let apictx = rqctx.context();
     let nexus = &apictx.nexus;
     let path = path_params.into_inner();
     let organization_name = &path.organization_name;
     let project_name = &path.project_name;
-    let vpc_name = &path.vpc_name;
+    let instance_name = &path.instance_name;
     let handler = async {
-        let vpc = nexus
-            .project_lookup_vpc(&organization_name, &project_name, &vpc_name)
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let instance = nexus
+            .instance_fetch(
+                &opctx,
+                &organization_name,
+                &project_name,
+                &instance_name,
+            )
             .await?;
-        Ok(HttpResponseOk(vpc.into()))
+        Ok(HttpResponseOk(instance.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_external_handler(&rqctx, handler).await
 }
 
 /**
- * Create a VPC in a project.
+ * Update an instance's labels, or the vCPUs or memory of a stopped instance.
+ */
+/*
+ * TODO-correctness This ignores `If-Match` and `If-None-Match` (see
+ * [`crate::external_api::etag`]).  A resize is made in several steps (checking
+ * the Instance's state, picking a sled, and then updating it), not in one
+ * transaction in which the preconditions could be checked.
  */
 #[endpoint {
-    method = POST,
-    path = "/organizations/{organization_name}/projects/{project_name}/vpcs",
-    tags = ["vpcs"],
+    method = PUT,
+    path = "/organizations/{organization_name}/projects/{project_name}/instances/{instance_name}",
+    tags = ["instances"],
 }]
-async fn project_vpcs_post(
+async fn project_instances_put_instance(
+    rqctx: Arc<RequestContext<Arc<ServerContext>>>,
+    path_params: Path<InstancePathParam>,
+    updated_instance: TypedBody<params::InstanceUpdate>,
+) -> Result<HttpResponseThrottled<HttpResponseOk<Instance>>, HttpError> {
+    let apictx = rqctx.context();
+    let nexus = &apictx.nexus;
+    let path = path_params.into_inner();
+    let organization_name = &path.organization_name;
+    let project_name = &path.project_name;
+    let instance_name = &path.instance_name;
+    let handler = async {
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let instance = nexus
+            .project_update_instance(
+                &opctx,
+                &organization_name,
+                &project_name,
+                &instance_name,
+                &updated_instance.into_inner(),
+            )
+            .await?;
+        Ok(HttpResponseOk(instance.into()))
+    };
+    apictx.instrument_external_handler(&rqctx, handler).await
+}
+
+/**
+ * Delete an instance from a project.
+ */
+#[endpoint {
+    method = DELETE,
+    path = "/organizations/{organization_name}/projects/{project_name}/instances/{instance_name}",
+    tags = ["instances"],
+}]
+async fn project_instances_delete_instance(
+    rqctx: Arc<RequestContext<Arc<ServerContext>>>,
+    path_params: Path<InstancePathParam>,
+) -> Result<HttpResponseThrottled<HttpResponseDeleted>, HttpError> {
+    let apictx = rqctx.context();
+    let nexus = &apictx.nexus;
+    let path = path_params.into_inner();
+    let organization_name = &path.organization_name;
+    let project_name = &path.project_name;
+    let instance_name = &path.instance_name;
+    let handler = async {
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        nexus
+            .project_destroy_instance(
+                &opctx,
+                &organization_name,
+                &project_name,
+                &instance_name,
+            )
+            .await?;
+        Ok(HttpResponseDeleted())
+    };
+    apictx.instrument_external_handler(&rqctx, handler).await
+}
+
+/**
+ * Migrate an instance to a different propolis-server, possibly on a different sled.
+ *
+ * With `Prefer: respond-async`, this returns 202 ("Accepted") and the
+ * operation migrating the instance rather than waiting for the migration to
+ * finish.
+ */
+#[endpoint {
+    method = POST,
+    path = "/organizations/{organization_name}/projects/{project_name}/instances/{instance_name}/migrate",
+    tags = ["instances"],
+}]
+async fn project_instances_migrate_instance(
+    rqctx: Arc<RequestContext<Arc<ServerContext>>>,
+    path_params: Path<InstancePathParam>,
+    migrate_params: TypedBody<params::InstanceMigrate>,
+) -> Result<
+    HttpResponseThrottled<HttpResponseMaybeAccepted<HttpResponseOk<Instance>>>,
+    HttpError,
+> {
+    let apictx = rqctx.context();
+    let nexus = &apictx.nexus;
+    let path = path_params.into_inner();
+    let organization_name = &path.organization_name;
+    let project_name = &path.project_name;
+    let instance_name = &path.instance_name;
+    let migrate_instance_params = migrate_params.into_inner();
+    let handler = async {
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let respond_async = operation::respond_async(&rqctx).await;
+        let result = nexus
+            .project_migrate_instance(
+                &opctx,
+                &organization_name,
+                &project_name,
+                &instance_name,
+                migrate_instance_params,
+                respond_async,
+            )
+            .await?;
+        Ok(result.into_response(organization_name, project_name, |instance| {
+            HttpResponseOk(instance.into())
+        }))
+    };
+    apictx.instrument_external_handler(&rqctx, handler).await
+}
+
+/**
+ * Reboot an instance.
+ */
+#[endpoint {
+    method = POST,
+    path = "/organizations/{organization_name}/projects/{project_name}/instances/{instance_name}/reboot",
+    tags = ["instances"],
+}]
+async fn project_instances_instance_reboot(
+    rqctx: Arc<RequestContext<Arc<ServerContext>>>,
+    path_params: Path<InstancePathParam>,
+) -> Result<HttpResponseThrottled<HttpResponseAccepted<Instance>>, HttpError> {
+    let apictx = rqctx.context();
+    let nexus = &apictx.nexus;
+    let path = path_params.into_inner();
+    let organization_name = &path.organization_name;
+    let project_name = &path.project_name;
+    let instance_name = &path.instance_name;
+    let handler = async {
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let instance = nexus
+            .instance_reboot(
+                &opctx,
+                &organization_name,
+                &project_name,
+                &instance_name,
+            )
+            .await?;
+        Ok(HttpResponseAccepted(instance.into()))
+    };
+    apictx.instrument_external_handler(&rqctx, handler).await
+}
+
+/**
+ * Boot an instance.
+ */
+#[endpoint {
+    method = POST,
+    path = "/organizations/{organization_name}/projects/{project_name}/instances/{instance_name}/start",
+    tags = ["instances"],
+}]
+async fn project_instances_instance_start(
+    rqctx: Arc<RequestContext<Arc<ServerContext>>>,
+    path_params: Path<InstancePathParam>,
+) -> Result<HttpResponseThrottled<HttpResponseAccepted<Instance>>, HttpError> {
+    let apictx = rqctx.context();
+    let nexus = &apictx.nexus;
+    let path = path_params.into_inner();
+    let organization_name = &path.organization_name;
+    let project_name = &path.project_name;
+    let instance_name = &path.instance_name;
+    let handler = async {
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let instance = nexus
+            .instance_start(
+                &opctx,
+                &organization_name,
+                &project_name,
+                &instance_name,
+            )
+            .await?;
+        Ok(HttpResponseAccepted(instance.into()))
+    };
+    apictx.instrument_external_handler(&rqctx, handler).await
+}
+
+/**
+ * Halt an instance.
+ */
+#[endpoint {
+    method = POST,
+    path = "/organizations/{organization_name}/projects/{project_name}/instances/{instance_name}/stop",
+    tags = ["instances"],
+}]
+/* Our naming convention kind of falls apart here. */
+async fn project_instances_instance_stop(
+    rqctx: Arc<RequestContext<Arc<ServerContext>>>,
+    path_params: Path<InstancePathParam>,
+) -> Result<HttpResponseThrottled<HttpResponseAccepted<Instance>>, HttpError> {
+    let apictx = rqctx.context();
+    let nexus = &apictx.nexus;
+    let path = path_params.into_inner();
+    let organization_name = &path.organization_name;
+    let project_name = &path.project_name;
+    let instance_name = &path.instance_name;
+    let handler = async {
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let instance = nexus
+            .instance_stop(
+                &opctx,
+                &organization_name,
+                &project_name,
+                &instance_name,
+            )
+            .await?;
+        Ok(HttpResponseAccepted(instance.into()))
+    };
+    apictx.instrument_external_handler(&rqctx, handler).await
+}
+
+/**
+ * Attach to an instance's serial console.
+ *
+ * The connection is upgraded to a WebSocket.  Binary or text messages sent on
+ * it are typed into the console, and the console's output comes back as
+ * binary messages, starting with the output retained so far.
+ */
+#[endpoint {
+    method = GET,
+    path = "/organizations/{organization_name}/projects/{project_name}/instances/{instance_name}/serial-console",
+    tags = ["instances"],
+}]
+async fn instance_serial_console(
+    rqctx: Arc<RequestContext<Arc<ServerContext>>>,
+    path_params: Path<InstancePathParam>,
+) -> Result<HttpResponseThrottled<Response<Body>>, HttpError> {
+    let apictx = rqctx.context();
+    let nexus = &apictx.nexus;
+    let path = path_params.into_inner();
+    let organization_name = &path.organization_name;
+    let project_name = &path.project_name;
+    let instance_name = &path.instance_name;
+    let handler = async {
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        /*
+         * Only dial the sled once the client's handshake has been accepted,
+         * so that requests which can't be upgraded don't open connections to
+         * it.
+         */
+        let (response, on_upgrade) =
+            websocket::upgrade(&mut *rqctx.request.lock().await)?;
+        let sled_websocket = nexus
+            .instance_serial_console_connect(
+                &opctx,
+                &organization_name,
+                &project_name,
+                &instance_name,
+            )
+            .await?;
+        let log = rqctx.log.new(o!("instance" => instance_name.to_string()));
+        tokio::spawn(async move {
+            let client_websocket = match websocket::accept(on_upgrade).await {
+                Ok(client_websocket) => client_websocket,
+                Err(e) => {
+                    warn!(log, "serial console upgrade failed: {}", e);
+                    return;
+                }
+            };
+            if let Err(e) =
+                websocket::relay(client_websocket, sled_websocket).await
+            {
+                warn!(log, "serial console relay failed: {}", e);
+            }
+        });
+        Ok(response)
+    };
+    apictx.instrument_external_handler(&rqctx, handler).await
+}
+
+/**
+ * Fetch recent output from an instance's serial console.
+ */
+#[endpoint {
+    method = GET,
+    path = "/organizations/{organization_name}/projects/{project_name}/instances/{instance_name}/serial-console/history",
+    tags = ["instances"],
+}]
+async fn instance_serial_console_history(
+    rqctx: Arc<RequestContext<Arc<ServerContext>>>,
+    path_params: Path<InstancePathParam>,
+    query_params: Query<params::InstanceSerialConsoleRequest>,
+) -> Result<
+    HttpResponseThrottled<HttpResponseOk<InstanceSerialConsoleData>>,
+    HttpError,
+> {
+    let apictx = rqctx.context();
+    let nexus = &apictx.nexus;
+    let path = path_params.into_inner();
+    let organization_name = &path.organization_name;
+    let project_name = &path.project_name;
+    let instance_name = &path.instance_name;
+    let max_bytes = query_params.into_inner().max_bytes;
+    let handler = async {
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let data = nexus
+            .instance_serial_console_history(
+                &opctx,
+                &organization_name,
+                &project_name,
+                &instance_name,
+                max_bytes,
+            )
+            .await?;
+        Ok(HttpResponseOk(data.into()))
+    };
+    apictx.instrument_external_handler(&rqctx, handler).await
+}
+
+/**
+ * List disks attached to this instance.
+ */
+/* TODO-scalability needs to be paginated */
+#[endpoint {
+    method = GET,
+    path = "/organizations/{organization_name}/projects/{project_name}/instances/{instance_name}/disks",
+    tags = ["instances"],
+}]
+async fn instance_disks_get(
+    rqctx: Arc<RequestContext<Arc<ServerContext>>>,
+    query_params: Query<PaginatedByName>,
+    path_params: Path<InstancePathParam>,
+) -> Result<HttpResponseThrottled<HttpResponseOk<ResultsPage<Disk>>>, HttpError>
+{
+    let apictx = rqctx.context();
+    let nexus = &apictx.nexus;
+    let query = query_params.into_inner();
+    let path = path_params.into_inner();
+    let organization_name = &path.organization_name;
+    let project_name = &path.project_name;
+    let instance_name = &path.instance_name;
+    let handler = async {
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let disks = nexus
+            .instance_list_disks(
+                &opctx,
+                &organization_name,
+                &project_name,
+                &instance_name,
+                &data_page_params_for(&rqctx, &query)?
+                    .map_name(|n| Name::ref_cast(n)),
+            )
+            .await?
+            .into_iter()
+            .map(|d| d.into())
+            .collect();
+        Ok(HttpResponseOk(ScanByName::results_page(&query, disks)?))
+    };
+    apictx.instrument_external_handler(&rqctx, handler).await
+}
+
+#[endpoint {
+    method = POST,
+    path = "/organizations/{organization_name}/projects/{project_name}/instances/{instance_name}/disks/attach",
+    tags = ["instances"],
+}]
+async fn instance_disks_attach(
+    rqctx: Arc<RequestContext<Arc<ServerContext>>>,
+    path_params: Path<InstancePathParam>,
+    disk_to_attach: TypedBody<params::DiskIdentifier>,
+) -> Result<HttpResponseThrottled<HttpResponseAccepted<Disk>>, HttpError> {
+    let apictx = rqctx.context();
+    let nexus = &apictx.nexus;
+    let path = path_params.into_inner();
+    let organization_name = &path.organization_name;
+    let project_name = &path.project_name;
+    let instance_name = &path.instance_name;
+    let handler = async {
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let disk = nexus
+            .instance_attach_disk(
+                &opctx,
+                &organization_name,
+                &project_name,
+                &instance_name,
+                &disk_to_attach.into_inner().disk.into(),
+            )
+            .await?;
+        Ok(HttpResponseAccepted(disk.into()))
+    };
+    apictx.instrument_external_handler(&rqctx, handler).await
+}
+
+#[endpoint {
+    method = POST,
+    path = "/organizations/{organization_name}/projects/{project_name}/instances/{instance_name}/disks/detach",
+    tags = ["instances"],
+}]
+async fn instance_disks_detach(
+    rqctx: Arc<RequestContext<Arc<ServerContext>>>,
+    path_params: Path<InstancePathParam>,
+    disk_to_detach: TypedBody<params::DiskIdentifier>,
+) -> Result<HttpResponseThrottled<HttpResponseAccepted<Disk>>, HttpError> {
+    let apictx = rqctx.context();
+    let nexus = &apictx.nexus;
+    let path = path_params.into_inner();
+    let organization_name = &path.organization_name;
+    let project_name = &path.project_name;
+    let instance_name = &path.instance_name;
+    let handler = async {
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let disk = nexus
+            .instance_detach_disk(
+                &opctx,
+                &organization_name,
+                &project_name,
+                &instance_name,
+                &disk_to_detach.into_inner().disk.into(),
+            )
+            .await?;
+        Ok(HttpResponseAccepted(disk.into()))
+    };
+    apictx.instrument_external_handler(&rqctx, handler).await
+}
+
+/**
+ * List network interfaces attached to this instance.
+ */
+#[endpoint {
+    method = GET,
+    path = "/organizations/{organization_name}/projects/{project_name}/instances/{instance_name}/network-interfaces",
+    tags = ["instances"],
+}]
+async fn instance_network_interfaces_get(
+    rqctx: Arc<RequestContext<Arc<ServerContext>>>,
+    query_params: Query<PaginatedByName>,
+    path_params: Path<InstancePathParam>,
+) -> Result<
+    HttpResponseThrottled<HttpResponseOk<ResultsPage<NetworkInterface>>>,
+    HttpError,
+> {
+    let apictx = rqctx.context();
+    let nexus = &apictx.nexus;
+    let query = query_params.into_inner();
+    let path = path_params.into_inner();
+    let organization_name = &path.organization_name;
+    let project_name = &path.project_name;
+    let instance_name = &path.instance_name;
+    let handler = async {
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let interfaces = nexus
+            .instance_list_network_interfaces(
+                &opctx,
+                &organization_name,
+                &project_name,
+                &instance_name,
+                &data_page_params_for(&rqctx, &query)?
+                    .map_name(|n| Name::ref_cast(n)),
+            )
+            .await?;
+        Ok(HttpResponseOk(ScanByName::results_page(&query, interfaces)?))
+    };
+    apictx.instrument_external_handler(&rqctx, handler).await
+}
+
+/**
+ * Add a network interface to a stopped instance.
+ */
+#[endpoint {
+    method = POST,
+    path = "/organizations/{organization_name}/projects/{project_name}/instances/{instance_name}/network-interfaces",
+    tags = ["instances"],
+}]
+async fn instance_network_interfaces_post(
+    rqctx: Arc<RequestContext<Arc<ServerContext>>>,
+    path_params: Path<InstancePathParam>,
+    new_interface: TypedBody<params::NetworkInterfaceCreate>,
+) -> Result<
+    HttpResponseThrottled<HttpResponseCreated<NetworkInterface>>,
+    HttpError,
+> {
+    let apictx = rqctx.context();
+    let nexus = &apictx.nexus;
+    let path = path_params.into_inner();
+    let organization_name = &path.organization_name;
+    let project_name = &path.project_name;
+    let instance_name = &path.instance_name;
+    let handler = async {
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let interface = nexus
+            .instance_create_network_interface(
+                &opctx,
+                &organization_name,
+                &project_name,
+                &instance_name,
+                &new_interface.into_inner(),
+            )
+            .await?;
+        Ok(HttpResponseCreated(interface.into()))
+    };
+    apictx.instrument_external_handler(&rqctx, handler).await
+}
+
+/**
+ * Path parameters for an Instance's network interface requests
+ */
+#[derive(Deserialize, JsonSchema)]
+struct NetworkInterfacePathParam {
+    organization_name: Name,
+    project_name: Name,
+    instance_name: Name,
+    interface_name: Name,
+}
+
+/**
+ * Get a network interface attached to this instance.
+ */
+#[endpoint {
+    method = GET,
+    path = "/organizations/{organization_name}/projects/{project_name}/instances/{instance_name}/network-interfaces/{interface_name}",
+    tags = ["instances"],
+}]
+async fn instance_network_interfaces_get_interface(
+    rqctx: Arc<RequestContext<Arc<ServerContext>>>,
+    path_params: Path<NetworkInterfacePathParam>,
+) -> Result<HttpResponseThrottled<HttpResponseOk<NetworkInterface>>, HttpError>
+{
+    let apictx = rqctx.context();
+    let nexus = &apictx.nexus;
+    let path = path_params.into_inner();
+    let handler = async {
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let interface = nexus
+            .instance_fetch_network_interface(
+                &opctx,
+                &path.organization_name,
+                &path.project_name,
+                &path.instance_name,
+                &path.interface_name,
+            )
+            .await?;
+        Ok(HttpResponseOk(interface))
+    };
+    apictx.instrument_external_handler(&rqctx, handler).await
+}
+
+/**
+ * Remove a network interface from a stopped instance.
+ */
+#[endpoint {
+    method = DELETE,
+    path = "/organizations/{organization_name}/projects/{project_name}/instances/{instance_name}/network-interfaces/{interface_name}",
+    tags = ["instances"],
+}]
+async fn instance_network_interfaces_delete_interface(
+    rqctx: Arc<RequestContext<Arc<ServerContext>>>,
+    path_params: Path<NetworkInterfacePathParam>,
+) -> Result<HttpResponseThrottled<HttpResponseDeleted>, HttpError> {
+    let apictx = rqctx.context();
+    let nexus = &apictx.nexus;
+    let path = path_params.into_inner();
+    let handler = async {
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        nexus
+            .instance_delete_network_interface(
+                &opctx,
+                &path.organization_name,
+                &path.project_name,
+                &path.instance_name,
+                &path.interface_name,
+            )
+            .await?;
+        Ok(HttpResponseDeleted())
+    };
+    apictx.instrument_external_handler(&rqctx, handler).await
+}
+
+/*
+ * VPCs
+ */
+
+/*
+ * Affinity groups
+ */
+
+/**
+ * List affinity groups in a project.
+ */
+#[endpoint {
+    method = GET,
+    path = "/organizations/{organization_name}/projects/{project_name}/affinity-groups",
+    tags = ["affinity-groups"],
+}]
+async fn project_affinity_groups_get(
+    rqctx: Arc<RequestContext<Arc<ServerContext>>>,
+    query_params: Query<PaginatedByName>,
+    path_params: Path<ProjectPathParam>,
+) -> Result<
+    HttpResponseThrottled<HttpResponseOk<ResultsPage<AffinityGroup>>>,
+    HttpError,
+> {
+    let apictx = rqctx.context();
+    let nexus = &apictx.nexus;
+    let query = query_params.into_inner();
+    let path = path_params.into_inner();
+    let handler = async {
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let groups = nexus
+            .project_list_affinity_groups(
+                &opctx,
+                &path.organization_name,
+                &path.project_name,
+                &data_page_params_for(&rqctx, &query)?
+                    .map_name(|n| Name::ref_cast(n)),
+            )
+            .await?
+            .into_iter()
+            .map(|g| g.into())
+            .collect();
+        Ok(HttpResponseOk(ScanByName::results_page(&query, groups)?))
+    };
+    apictx.instrument_external_handler(&rqctx, handler).await
+}
+
+/**
+ * Path parameters for affinity group requests
+ */
+#[derive(Deserialize, JsonSchema)]
+struct AffinityGroupPathParam {
+    organization_name: Name,
+    project_name: Name,
+    group_name: Name,
+}
+
+/**
+ * Create an affinity group in a project.
+ */
+#[endpoint {
+    method = POST,
+    path = "/organizations/{organization_name}/projects/{project_name}/affinity-groups",
+    tags = ["affinity-groups"],
+}]
+async fn project_affinity_groups_post(
+    rqctx: Arc<RequestContext<Arc<ServerContext>>>,
+    path_params: Path<ProjectPathParam>,
+    new_group: TypedBody<params::AffinityGroupCreate>,
+) -> Result<HttpResponseThrottled<HttpResponseCreated<AffinityGroup>>, HttpError>
+{
+    let apictx = rqctx.context();
+    let nexus = &apictx.nexus;
+    let path = path_params.into_inner();
+    let handler = async {
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let group: AffinityGroup = nexus
+            .idempotent_create(
+                opctx,
+                new_group.into_inner(),
+                move |nexus, opctx, new_group| async move {
+                    let group = nexus
+                        .project_create_affinity_group(
+                            &opctx,
+                            &path.organization_name,
+                            &path.project_name,
+                            &new_group,
+                        )
+                        .await?;
+                    Ok(group.into())
+                },
+            )
+            .await?;
+        Ok(HttpResponseCreated(group))
+    };
+    apictx.instrument_external_handler(&rqctx, handler).await
+}
+
+/**
+ * Get an affinity group in a project.
+ */
+#[endpoint {
+    method = GET,
+    path = "/organizations/{organization_name}/projects/{project_name}/affinity-groups/{group_name}",
+    tags = ["affinity-groups"],
+}]
+async fn project_affinity_groups_get_group(
+    rqctx: Arc<RequestContext<Arc<ServerContext>>>,
+    path_params: Path<AffinityGroupPathParam>,
+) -> Result<
+    HttpResponseThrottled<HttpResponseOkWithETag<AffinityGroup>>,
+    HttpError,
+> {
+    let apictx = rqctx.context();
+    let nexus = &apictx.nexus;
+    let path = path_params.into_inner();
+    let handler = async {
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let group = nexus
+            .project_lookup_affinity_group(
+                &opctx,
+                &path.organization_name,
+                &path.project_name,
+                &path.group_name,
+            )
+            .await?;
+        Ok(HttpResponseOkWithETag(group.into()))
+    };
+    apictx.instrument_external_handler(&rqctx, handler).await
+}
+
+/**
+ * Update an affinity group.
+ */
+#[endpoint {
+    method = PUT,
+    path = "/organizations/{organization_name}/projects/{project_name}/affinity-groups/{group_name}",
+    tags = ["affinity-groups"],
+}]
+async fn project_affinity_groups_put_group(
+    rqctx: Arc<RequestContext<Arc<ServerContext>>>,
+    path_params: Path<AffinityGroupPathParam>,
+    updated_group: TypedBody<params::AffinityGroupUpdate>,
+) -> Result<
+    HttpResponseThrottled<HttpResponseOkWithETag<AffinityGroup>>,
+    HttpError,
+> {
+    let apictx = rqctx.context();
+    let nexus = &apictx.nexus;
+    let path = path_params.into_inner();
+    let handler = async {
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let preconditions = Preconditions::from_request(&rqctx).await?;
+        let group = nexus
+            .project_update_affinity_group(
+                &opctx,
+                &path.organization_name,
+                &path.project_name,
+                &path.group_name,
+                &updated_group.into_inner(),
+                &preconditions,
+            )
+            .await?;
+        Ok(HttpResponseOkWithETag(group.into()))
+    };
+    apictx.instrument_external_handler(&rqctx, handler).await
+}
+
+/**
+ * Delete an affinity group from a project.
+ */
+#[endpoint {
+    method = DELETE,
+    path = "/organizations/{organization_name}/projects/{project_name}/affinity-groups/{group_name}",
+    tags = ["affinity-groups"],
+}]
+async fn project_affinity_groups_delete_group(
+    rqctx: Arc<RequestContext<Arc<ServerContext>>>,
+    path_params: Path<AffinityGroupPathParam>,
+) -> Result<HttpResponseThrottled<HttpResponseDeleted>, HttpError> {
+    let apictx = rqctx.context();
+    let nexus = &apictx.nexus;
+    let path = path_params.into_inner();
+    let handler = async {
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        nexus
+            .project_delete_affinity_group(
+                &opctx,
+                &path.organization_name,
+                &path.project_name,
+                &path.group_name,
+            )
+            .await?;
+        Ok(HttpResponseDeleted())
+    };
+    apictx.instrument_external_handler(&rqctx, handler).await
+}
+
+/*
+ * IP pools
+ */
+
+/**
+ * List IP pools.
+ */
+#[endpoint {
+    method = GET,
+    path = "/ip-pools",
+    tags = ["ip-pools"],
+}]
+async fn ip_pools_get(
+    rqctx: Arc<RequestContext<Arc<ServerContext>>>,
+    query_params: Query<PaginatedByName>,
+) -> Result<HttpResponseThrottled<HttpResponseOk<ResultsPage<IpPool>>>, HttpError>
+{
+    let apictx = rqctx.context();
+    let nexus = &apictx.nexus;
+    let query = query_params.into_inner();
+    let handler = async {
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let pools = nexus
+            .ip_pools_list(
+                &opctx,
+                &data_page_params_for(&rqctx, &query)?
+                    .map_name(|n| Name::ref_cast(n)),
+            )
+            .await?
+            .into_iter()
+            .map(|p| p.into())
+            .collect();
+        Ok(HttpResponseOk(ScanByName::results_page(&query, pools)?))
+    };
+    apictx.instrument_external_handler(&rqctx, handler).await
+}
+
+/**
+ * Create an IP pool.
+ */
+#[endpoint {
+    method = POST,
+    path = "/ip-pools",
+    tags = ["ip-pools"],
+}]
+async fn ip_pools_post(
+    rqctx: Arc<RequestContext<Arc<ServerContext>>>,
+    new_pool: TypedBody<params::IpPoolCreate>,
+) -> Result<HttpResponseThrottled<HttpResponseCreated<IpPool>>, HttpError> {
+    let apictx = rqctx.context();
+    let nexus = &apictx.nexus;
+    let new_pool = new_pool.into_inner();
+    let handler = async {
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let pool = nexus.ip_pool_create(&opctx, &new_pool).await?;
+        Ok(HttpResponseCreated(pool.into()))
+    };
+    apictx.instrument_external_handler(&rqctx, handler).await
+}
+
+/**
+ * Path parameters for IP pool requests
+ */
+#[derive(Deserialize, JsonSchema)]
+struct IpPoolPathParam {
+    pool_name: Name,
+}
+
+/**
+ * Fetch an IP pool.
+ */
+#[endpoint {
+    method = GET,
+    path = "/ip-pools/{pool_name}",
+    tags = ["ip-pools"],
+}]
+async fn ip_pools_get_ip_pool(
+    rqctx: Arc<RequestContext<Arc<ServerContext>>>,
+    path_params: Path<IpPoolPathParam>,
+) -> Result<HttpResponseThrottled<HttpResponseOk<IpPool>>, HttpError> {
+    let apictx = rqctx.context();
+    let nexus = &apictx.nexus;
+    let path = path_params.into_inner();
+    let handler = async {
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let pool = nexus.ip_pool_fetch(&opctx, &path.pool_name).await?;
+        Ok(HttpResponseOk(pool.into()))
+    };
+    apictx.instrument_external_handler(&rqctx, handler).await
+}
+
+/**
+ * Delete an IP pool and all of its ranges.
+ */
+#[endpoint {
+    method = DELETE,
+    path = "/ip-pools/{pool_name}",
+    tags = ["ip-pools"],
+}]
+async fn ip_pools_delete_ip_pool(
+    rqctx: Arc<RequestContext<Arc<ServerContext>>>,
+    path_params: Path<IpPoolPathParam>,
+) -> Result<HttpResponseThrottled<HttpResponseDeleted>, HttpError> {
+    let apictx = rqctx.context();
+    let nexus = &apictx.nexus;
+    let path = path_params.into_inner();
+    let handler = async {
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        nexus.ip_pool_delete(&opctx, &path.pool_name).await?;
+        Ok(HttpResponseDeleted())
+    };
+    apictx.instrument_external_handler(&rqctx, handler).await
+}
+
+/**
+ * List the address ranges in an IP pool.
+ */
+#[endpoint {
+    method = GET,
+    path = "/ip-pools/{pool_name}/ranges",
+    tags = ["ip-pools"],
+}]
+async fn ip_pool_ranges_get(
+    rqctx: Arc<RequestContext<Arc<ServerContext>>>,
+    query_params: Query<PaginatedById>,
+    path_params: Path<IpPoolPathParam>,
+) -> Result<
+    HttpResponseThrottled<HttpResponseOk<ResultsPage<IpPoolRange>>>,
+    HttpError,
+> {
+    let apictx = rqctx.context();
+    let nexus = &apictx.nexus;
+    let query = query_params.into_inner();
+    let path = path_params.into_inner();
+    let handler = async {
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let ranges = nexus
+            .ip_pool_list_ranges(
+                &opctx,
+                &path.pool_name,
+                &data_page_params_for(&rqctx, &query)?,
+            )
+            .await?
+            .into_iter()
+            .map(|r| r.into())
+            .collect();
+        Ok(HttpResponseOk(ScanById::results_page_by_id(
+            &query,
+            ranges,
+            |range: &IpPoolRange| range.id,
+        )?))
+    };
+    apictx.instrument_external_handler(&rqctx, handler).await
+}
+
+/**
+ * Add an address range to an IP pool.
+ */
+#[endpoint {
+    method = POST,
+    path = "/ip-pools/{pool_name}/ranges",
+    tags = ["ip-pools"],
+}]
+async fn ip_pool_ranges_post(
+    rqctx: Arc<RequestContext<Arc<ServerContext>>>,
+    path_params: Path<IpPoolPathParam>,
+    new_range: TypedBody<params::IpPoolRangeCreate>,
+) -> Result<HttpResponseThrottled<HttpResponseCreated<IpPoolRange>>, HttpError>
+{
+    let apictx = rqctx.context();
+    let nexus = &apictx.nexus;
+    let path = path_params.into_inner();
+    let handler = async {
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let range = nexus
+            .ip_pool_add_range(&opctx, &path.pool_name, &new_range.into_inner())
+            .await?;
+        Ok(HttpResponseCreated(range.into()))
+    };
+    apictx.instrument_external_handler(&rqctx, handler).await
+}
+
+/**
+ * Path parameters for IP pool range requests
+ */
+#[derive(Deserialize, JsonSchema)]
+struct IpPoolRangePathParam {
+    pool_name: Name,
+    /** The range's unique ID. */
+    range_id: Uuid,
+}
+
+/**
+ * Remove an address range from an IP pool.
+ */
+#[endpoint {
+    method = DELETE,
+    path = "/ip-pools/{pool_name}/ranges/{range_id}",
+    tags = ["ip-pools"],
+}]
+async fn ip_pool_ranges_delete_range(
+    rqctx: Arc<RequestContext<Arc<ServerContext>>>,
+    path_params: Path<IpPoolRangePathParam>,
+) -> Result<HttpResponseThrottled<HttpResponseDeleted>, HttpError> {
+    let apictx = rqctx.context();
+    let nexus = &apictx.nexus;
+    let path = path_params.into_inner();
+    let handler = async {
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        nexus
+            .ip_pool_delete_range(&opctx, &path.pool_name, path.range_id)
+            .await?;
+        Ok(HttpResponseDeleted())
+    };
+    apictx.instrument_external_handler(&rqctx, handler).await
+}
+
+/*
+ * Floating IPs
+ */
+
+/**
+ * List Floating IPs in a project.
+ */
+#[endpoint {
+    method = GET,
+    path = "/organizations/{organization_name}/projects/{project_name}/floating-ips",
+    tags = ["floating-ips"],
+}]
+async fn project_floating_ips_get(
+    rqctx: Arc<RequestContext<Arc<ServerContext>>>,
+    query_params: Query<PaginatedByName>,
+    path_params: Path<ProjectPathParam>,
+) -> Result<
+    HttpResponseThrottled<HttpResponseOk<ResultsPage<FloatingIp>>>,
+    HttpError,
+> {
+    let apictx = rqctx.context();
+    let nexus = &apictx.nexus;
+    let query = query_params.into_inner();
+    let path = path_params.into_inner();
+    let handler = async {
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let floating_ips = nexus
+            .project_list_floating_ips(
+                &opctx,
+                &path.organization_name,
+                &path.project_name,
+                &data_page_params_for(&rqctx, &query)?
+                    .map_name(|n| Name::ref_cast(n)),
+            )
+            .await?
+            .into_iter()
+            .map(|f| f.into())
+            .collect();
+        Ok(HttpResponseOk(ScanByName::results_page(&query, floating_ips)?))
+    };
+    apictx.instrument_external_handler(&rqctx, handler).await
+}
+
+/**
+ * Allocate a Floating IP from an IP pool.
+ */
+#[endpoint {
+    method = POST,
+    path = "/organizations/{organization_name}/projects/{project_name}/floating-ips",
+    tags = ["floating-ips"],
+}]
+async fn project_floating_ips_post(
+    rqctx: Arc<RequestContext<Arc<ServerContext>>>,
+    path_params: Path<ProjectPathParam>,
+    new_floating_ip: TypedBody<params::FloatingIpCreate>,
+) -> Result<HttpResponseThrottled<HttpResponseCreated<FloatingIp>>, HttpError> {
+    let apictx = rqctx.context();
+    let nexus = &apictx.nexus;
+    let path = path_params.into_inner();
+    let new_floating_ip = new_floating_ip.into_inner();
+    let handler = async {
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let floating_ip = nexus
+            .project_create_floating_ip(
+                &opctx,
+                &path.organization_name,
+                &path.project_name,
+                &new_floating_ip,
+            )
+            .await?;
+        Ok(HttpResponseCreated(floating_ip.into()))
+    };
+    apictx.instrument_external_handler(&rqctx, handler).await
+}
+
+/**
+ * Path parameters for Floating IP requests
+ */
+#[derive(Deserialize, JsonSchema)]
+struct FloatingIpPathParam {
+    organization_name: Name,
+    project_name: Name,
+    floating_ip_name: Name,
+}
+
+/**
+ * Get a Floating IP in a project.
+ */
+#[endpoint {
+    method = GET,
+    path = "/organizations/{organization_name}/projects/{project_name}/floating-ips/{floating_ip_name}",
+    tags = ["floating-ips"],
+}]
+async fn project_floating_ips_get_floating_ip(
+    rqctx: Arc<RequestContext<Arc<ServerContext>>>,
+    path_params: Path<FloatingIpPathParam>,
+) -> Result<HttpResponseThrottled<HttpResponseOk<FloatingIp>>, HttpError> {
+    let apictx = rqctx.context();
+    let nexus = &apictx.nexus;
+    let path = path_params.into_inner();
+    let handler = async {
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let floating_ip = nexus
+            .project_fetch_floating_ip(
+                &opctx,
+                &path.organization_name,
+                &path.project_name,
+                &path.floating_ip_name,
+            )
+            .await?;
+        Ok(HttpResponseOk(floating_ip.into()))
+    };
+    apictx.instrument_external_handler(&rqctx, handler).await
+}
+
+/**
+ * Release a Floating IP back to its IP pool.
+ */
+#[endpoint {
+    method = DELETE,
+    path = "/organizations/{organization_name}/projects/{project_name}/floating-ips/{floating_ip_name}",
+    tags = ["floating-ips"],
+}]
+async fn project_floating_ips_delete_floating_ip(
+    rqctx: Arc<RequestContext<Arc<ServerContext>>>,
+    path_params: Path<FloatingIpPathParam>,
+) -> Result<HttpResponseThrottled<HttpResponseDeleted>, HttpError> {
+    let apictx = rqctx.context();
+    let nexus = &apictx.nexus;
+    let path = path_params.into_inner();
+    let handler = async {
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        nexus
+            .project_delete_floating_ip(
+                &opctx,
+                &path.organization_name,
+                &path.project_name,
+                &path.floating_ip_name,
+            )
+            .await?;
+        Ok(HttpResponseDeleted())
+    };
+    apictx.instrument_external_handler(&rqctx, handler).await
+}
+
+/**
+ * Attach a Floating IP to the primary network interface of a stopped
+ * instance.
+ */
+#[endpoint {
+    method = POST,
+    path = "/organizations/{organization_name}/projects/{project_name}/floating-ips/{floating_ip_name}/attach",
+    tags = ["floating-ips"],
+}]
+async fn project_floating_ips_attach(
+    rqctx: Arc<RequestContext<Arc<ServerContext>>>,
+    path_params: Path<FloatingIpPathParam>,
+    attach_params: TypedBody<params::FloatingIpAttach>,
+) -> Result<HttpResponseThrottled<HttpResponseOk<FloatingIp>>, HttpError> {
+    let apictx = rqctx.context();
+    let nexus = &apictx.nexus;
+    let path = path_params.into_inner();
+    let handler = async {
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let floating_ip = nexus
+            .floating_ip_attach(
+                &opctx,
+                &path.organization_name,
+                &path.project_name,
+                &path.floating_ip_name,
+                &attach_params.into_inner(),
+            )
+            .await?;
+        Ok(HttpResponseOk(floating_ip.into()))
+    };
+    apictx.instrument_external_handler(&rqctx, handler).await
+}
+
+/**
+ * Detach a Floating IP from the stopped instance it is attached to.
+ */
+#[endpoint {
+    method = POST,
+    path = "/organizations/{organization_name}/projects/{project_name}/floating-ips/{floating_ip_name}/detach",
+    tags = ["floating-ips"],
+}]
+async fn project_floating_ips_detach(
+    rqctx: Arc<RequestContext<Arc<ServerContext>>>,
+    path_params: Path<FloatingIpPathParam>,
+) -> Result<HttpResponseThrottled<HttpResponseOk<FloatingIp>>, HttpError> {
+    let apictx = rqctx.context();
+    let nexus = &apictx.nexus;
+    let path = path_params.into_inner();
+    let handler = async {
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let floating_ip = nexus
+            .floating_ip_detach(
+                &opctx,
+                &path.organization_name,
+                &path.project_name,
+                &path.floating_ip_name,
+            )
+            .await?;
+        Ok(HttpResponseOk(floating_ip.into()))
+    };
+    apictx.instrument_external_handler(&rqctx, handler).await
+}
+
+/**
+ * List VPCs in a project.
+ */
+#[endpoint {
+    method = GET,
+    path = "/organizations/{organization_name}/projects/{project_name}/vpcs",
+    tags = ["vpcs"],
+}]
+async fn project_vpcs_get(
+    rqctx: Arc<RequestContext<Arc<ServerContext>>>,
+    query_params: Query<PaginatedByNameWithLabels>,
+    path_params: Path<ProjectPathParam>,
+) -> Result<HttpResponseThrottled<HttpResponseOk<ResultsPage<Vpc>>>, HttpError>
+{
+    let apictx = rqctx.context();
+    let nexus = &apictx.nexus;
+    let query = query_params.into_inner();
+    let path = path_params.into_inner();
+    let organization_name = &path.organization_name;
+    let project_name = &path.project_name;
+    let handler = async {
+        let vpcs = nexus
+            .project_list_vpcs(
+                &organization_name,
+                &project_name,
+                &data_page_params_for(&rqctx, &query)?
+                    .map_name(|n| Name::ref_cast(n)),
+                ScanByNameWithLabels::from_query(&query)?.label_selector(),
+            )
+            .await?
+            .into_iter()
+            .map(|p| p.into())
+            .collect();
+
+        Ok(HttpResponseOk(ScanByNameWithLabels::results_page(&query, vpcs)?))
+    };
+    apictx.instrument_external_handler(&rqctx, handler).await
+}
+
+/**
+ * Path parameters for VPC requests
+ */
+#[derive(Deserialize, JsonSchema)]
+struct VpcPathParam {
+    organization_name: Name,
+    project_name: Name,
+    vpc_name: Name,
+}
+
+/**
+ * Get a VPC in a project.
+ */
+#[endpoint {
+    method = GET,
+    path = "/organizations/{organization_name}/projects/{project_name}/vpcs/{vpc_name}",
+    tags = ["vpcs"],
+}]
+async fn project_vpcs_get_vpc(
+    rqctx: Arc<RequestContext<Arc<ServerContext>>>,
+    path_params: Path<VpcPathParam>,
+) -> Result<HttpResponseThrottled<HttpResponseOkWithETag<Vpc>>, HttpError> {
+    let apictx = rqctx.context();
+    let nexus = &apictx.nexus;
+    let path = path_params.into_inner();
+    let organization_name = &path.organization_name;
+    let project_name = &path.project_name;
+    let vpc_name = &path.vpc_name;
+    let handler = async {
+        let vpc = nexus
+            .project_lookup_vpc(&organization_name, &project_name, &vpc_name)
+            .await?;
+        Ok(HttpResponseOkWithETag(vpc.into()))
+    };
+    apictx.instrument_external_handler(&rqctx, handler).await
+}
+
+/**
+ * Create a VPC in a project.
+ */
+#[endpoint {
+    method = POST,
+    path = "/organizations/{organization_name}/projects/{project_name}/vpcs",
+    tags = ["vpcs"],
+}]
+async fn project_vpcs_post(
     rqctx: Arc<RequestContext<Arc<ServerContext>>>,
     path_params: Path<ProjectPathParam>,
     new_vpc: TypedBody<params::VpcCreate>,
-) -> Result<HttpResponseCreated<Vpc>, HttpError> {
+) -> Result<HttpResponseThrottled<HttpResponseCreated<Vpc>>, HttpError> {
+    let apictx = rqctx.context();
+    let nexus = &apictx.nexus;
+    let path = path_params.into_inner();
+    let handler = async {
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let vpc: Vpc = nexus
+            .idempotent_create(
+                opctx,
+                new_vpc.into_inner(),
+                move |nexus, _, new_vpc_params| async move {
+                    let vpc = nexus
+                        .project_create_vpc(
+                            &path.organization_name,
+                            &path.project_name,
+                            &new_vpc_params,
+                        )
+                        .await?;
+                    Ok(vpc.into())
+                },
+            )
+            .await?;
+        Ok(HttpResponseCreated(vpc))
+    };
+    apictx.instrument_external_handler(&rqctx, handler).await
+}
+
+/**
+ * Update a VPC.
+ */
+#[endpoint {
+    method = PUT,
+    path = "/organizations/{organization_name}/projects/{project_name}/vpcs/{vpc_name}",
+    tags = ["vpcs"],
+}]
+async fn project_vpcs_put_vpc(
+    rqctx: Arc<RequestContext<Arc<ServerContext>>>,
+    path_params: Path<VpcPathParam>,
+    updated_vpc: TypedBody<params::VpcUpdate>,
+) -> Result<HttpResponseThrottled<HttpResponseUpdatedNoContent>, HttpError> {
+    let apictx = rqctx.context();
+    let nexus = &apictx.nexus;
+    let path = path_params.into_inner();
+    let handler = async {
+        let preconditions = Preconditions::from_request(&rqctx).await?;
+        nexus
+            .project_update_vpc(
+                &path.organization_name,
+                &path.project_name,
+                &path.vpc_name,
+                &updated_vpc.into_inner(),
+                &preconditions,
+            )
+            .await?;
+        Ok(HttpResponseUpdatedNoContent())
+    };
+    apictx.instrument_external_handler(&rqctx, handler).await
+}
+
+/**
+ * Delete a vpc from a project.
+ */
+#[endpoint {
+    method = DELETE,
+    path = "/organizations/{organization_name}/projects/{project_name}/vpcs/{vpc_name}",
+    tags = ["vpcs"],
+}]
+async fn project_vpcs_delete_vpc(
+    rqctx: Arc<RequestContext<Arc<ServerContext>>>,
+    path_params: Path<VpcPathParam>,
+) -> Result<HttpResponseThrottled<HttpResponseDeleted>, HttpError> {
     let apictx = rqctx.context();
     let nexus = &apictx.nexus;
     let path = path_params.into_inner();
     let organization_name = &path.organization_name;
     let project_name = &path.project_name;
-    let new_vpc_params = &new_vpc.into_inner();
+    let vpc_name = &path.vpc_name;
     let handler = async {
-        let vpc = nexus
-            .project_create_vpc(
+        let preconditions = Preconditions::from_request(&rqctx).await?;
+        nexus
+            .project_delete_vpc(
                 &organization_name,
                 &project_name,
-                &new_vpc_params,
+                &vpc_name,
+                &preconditions,
+            )
+            .await?;
+        Ok(HttpResponseDeleted())
+    };
+    apictx.instrument_external_handler(&rqctx, handler).await
+}
+
+/**
+ * List subnets in a VPC.
+ */
+#[endpoint {
+    method = GET,
+    path = "/organizations/{organization_name}/projects/{project_name}/vpcs/{vpc_name}/subnets",
+    tags = ["subnets"],
+}]
+async fn vpc_subnets_get(
+    rqctx: Arc<RequestContext<Arc<ServerContext>>>,
+    query_params: Query<PaginatedByName>,
+    path_params: Path<VpcPathParam>,
+) -> Result<
+    HttpResponseThrottled<HttpResponseOk<ResultsPage<VpcSubnet>>>,
+    HttpError,
+> {
+    let apictx = rqctx.context();
+    let nexus = &apictx.nexus;
+    let query = query_params.into_inner();
+    let path = path_params.into_inner();
+    let handler = async {
+        let vpcs = nexus
+            .vpc_list_subnets(
+                &path.organization_name,
+                &path.project_name,
+                &path.vpc_name,
+                &data_page_params_for(&rqctx, &query)?
+                    .map_name(|n| Name::ref_cast(n)),
+            )
+            .await?
+            .into_iter()
+            .map(|vpc| vpc.into())
+            .collect();
+        Ok(HttpResponseOk(ScanByName::results_page(&query, vpcs)?))
+    };
+    apictx.instrument_external_handler(&rqctx, handler).await
+}
+
+/**
+ * Path parameters for VPC Subnet requests
+ */
+#[derive(Deserialize, JsonSchema)]
+struct VpcSubnetPathParam {
+    organization_name: Name,
+    project_name: Name,
+    vpc_name: Name,
+    subnet_name: Name,
+}
+
+/**
+ * Get subnet in a VPC.
+ */
+#[endpoint {
+    method = GET,
+    path = "/organizations/{organization_name}/projects/{project_name}/vpcs/{vpc_name}/subnets/{subnet_name}",
+    tags = ["subnets"],
+}]
+async fn vpc_subnets_get_subnet(
+    rqctx: Arc<RequestContext<Arc<ServerContext>>>,
+    path_params: Path<VpcSubnetPathParam>,
+) -> Result<HttpResponseThrottled<HttpResponseOkWithETag<VpcSubnet>>, HttpError>
+{
+    let apictx = rqctx.context();
+    let nexus = &apictx.nexus;
+    let path = path_params.into_inner();
+    let handler = async {
+        let subnet = nexus
+            .vpc_lookup_subnet(
+                &path.organization_name,
+                &path.project_name,
+                &path.vpc_name,
+                &path.subnet_name,
+            )
+            .await?;
+        Ok(HttpResponseOkWithETag(subnet.into()))
+    };
+    apictx.instrument_external_handler(&rqctx, handler).await
+}
+
+/**
+ * Create a subnet in a VPC.
+ */
+#[endpoint {
+    method = POST,
+    path = "/organizations/{organization_name}/projects/{project_name}/vpcs/{vpc_name}/subnets",
+    tags = ["subnets"],
+}]
+async fn vpc_subnets_post(
+    rqctx: Arc<RequestContext<Arc<ServerContext>>>,
+    path_params: Path<VpcPathParam>,
+    create_params: TypedBody<params::VpcSubnetCreate>,
+) -> Result<HttpResponseThrottled<HttpResponseCreated<VpcSubnet>>, HttpError> {
+    let apictx = rqctx.context();
+    let nexus = &apictx.nexus;
+    let path = path_params.into_inner();
+    let handler = async {
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let subnet: VpcSubnet = nexus
+            .idempotent_create(
+                opctx,
+                create_params.into_inner(),
+                move |nexus, _, create_params| async move {
+                    let subnet = nexus
+                        .vpc_create_subnet(
+                            &path.organization_name,
+                            &path.project_name,
+                            &path.vpc_name,
+                            &create_params,
+                        )
+                        .await?;
+                    Ok(subnet.into())
+                },
+            )
+            .await?;
+        Ok(HttpResponseCreated(subnet))
+    };
+    apictx.instrument_external_handler(&rqctx, handler).await
+}
+
+/**
+ * Delete a subnet from a VPC.
+ */
+#[endpoint {
+    method = DELETE,
+    path = "/organizations/{organization_name}/projects/{project_name}/vpcs/{vpc_name}/subnets/{subnet_name}",
+    tags = ["subnets"],
+}]
+async fn vpc_subnets_delete_subnet(
+    rqctx: Arc<RequestContext<Arc<ServerContext>>>,
+    path_params: Path<VpcSubnetPathParam>,
+) -> Result<HttpResponseThrottled<HttpResponseDeleted>, HttpError> {
+    let apictx = rqctx.context();
+    let nexus = &apictx.nexus;
+    let path = path_params.into_inner();
+    let handler = async {
+        let preconditions = Preconditions::from_request(&rqctx).await?;
+        nexus
+            .vpc_delete_subnet(
+                &path.organization_name,
+                &path.project_name,
+                &path.vpc_name,
+                &path.subnet_name,
+                &preconditions,
+            )
+            .await?;
+        Ok(HttpResponseDeleted())
+    };
+    apictx.instrument_external_handler(&rqctx, handler).await
+}
+
+/**
+ * Update a VPC Subnet.
+ */
+#[endpoint {
+    method = PUT,
+    path = "/organizations/{organization_name}/projects/{project_name}/vpcs/{vpc_name}/subnets/{subnet_name}",
+    tags = ["subnets"],
+}]
+async fn vpc_subnets_put_subnet(
+    rqctx: Arc<RequestContext<Arc<ServerContext>>>,
+    path_params: Path<VpcSubnetPathParam>,
+    subnet_params: TypedBody<params::VpcSubnetUpdate>,
+) -> Result<HttpResponseThrottled<HttpResponseUpdatedNoContent>, HttpError> {
+    let apictx = rqctx.context();
+    let nexus = &apictx.nexus;
+    let path = path_params.into_inner();
+    let handler = async {
+        let preconditions = Preconditions::from_request(&rqctx).await?;
+        nexus
+            .vpc_update_subnet(
+                &path.organization_name,
+                &path.project_name,
+                &path.vpc_name,
+                &path.subnet_name,
+                &subnet_params.into_inner(),
+                &preconditions,
+            )
+            .await?;
+        Ok(HttpResponseUpdatedNoContent())
+    };
+    apictx.instrument_external_handler(&rqctx, handler).await
+}
+
+/**
+ * List IP addresses on a VPC subnet.
+ */
+// TODO-correctness: This API has not actually been specified in an RFD yet, and
+// may not actually be what we want. It is being implemented here to give our
+// testing introspection into network interfaces.
+#[endpoint {
+    method = GET,
+    path = "/organizations/{organization_name}/projects/{project_name}/vpcs/{vpc_name}/subnets/{subnet_name}/ips",
+    tags = ["subnets"],
+}]
+async fn subnets_ips_get(
+    rqctx: Arc<RequestContext<Arc<ServerContext>>>,
+    query_params: Query<PaginatedByName>,
+    path_params: Path<VpcSubnetPathParam>,
+) -> Result<
+    HttpResponseThrottled<HttpResponseOk<ResultsPage<NetworkInterface>>>,
+    HttpError,
+> {
+    let apictx = rqctx.context();
+    let nexus = &apictx.nexus;
+    let query = query_params.into_inner();
+    let path = path_params.into_inner();
+    let handler = async {
+        let interfaces = nexus
+            .subnet_list_network_interfaces(
+                &path.organization_name,
+                &path.project_name,
+                &path.vpc_name,
+                &path.subnet_name,
+                &data_page_params_for(&rqctx, &query)?
+                    .map_name(|n| Name::ref_cast(n)),
+            )
+            .await?;
+        Ok(HttpResponseOk(ScanByName::results_page(&query, interfaces)?))
+    };
+    apictx.instrument_external_handler(&rqctx, handler).await
+}
+
+/**
+ * Attach a custom router to a VPC Subnet.
+ */
+#[endpoint {
+    method = POST,
+    path = "/organizations/{organization_name}/projects/{project_name}/vpcs/{vpc_name}/subnets/{subnet_name}/router/attach",
+    tags = ["subnets"],
+}]
+async fn vpc_subnets_attach_router(
+    rqctx: Arc<RequestContext<Arc<ServerContext>>>,
+    path_params: Path<VpcSubnetPathParam>,
+    attach_params: TypedBody<params::VpcSubnetRouterAttach>,
+) -> Result<HttpResponseThrottled<HttpResponseOk<VpcSubnet>>, HttpError> {
+    let apictx = rqctx.context();
+    let nexus = &apictx.nexus;
+    let path = path_params.into_inner();
+    let handler = async {
+        let subnet = nexus
+            .vpc_subnet_attach_router(
+                &path.organization_name,
+                &path.project_name,
+                &path.vpc_name,
+                &path.subnet_name,
+                &attach_params.into_inner(),
+            )
+            .await?;
+        Ok(HttpResponseOk(subnet.into()))
+    };
+    apictx.instrument_external_handler(&rqctx, handler).await
+}
+
+/**
+ * Detach the custom router from a VPC Subnet.
+ */
+#[endpoint {
+    method = POST,
+    path = "/organizations/{organization_name}/projects/{project_name}/vpcs/{vpc_name}/subnets/{subnet_name}/router/detach",
+    tags = ["subnets"],
+}]
+async fn vpc_subnets_detach_router(
+    rqctx: Arc<RequestContext<Arc<ServerContext>>>,
+    path_params: Path<VpcSubnetPathParam>,
+) -> Result<HttpResponseThrottled<HttpResponseOk<VpcSubnet>>, HttpError> {
+    let apictx = rqctx.context();
+    let nexus = &apictx.nexus;
+    let path = path_params.into_inner();
+    let handler = async {
+        let subnet = nexus
+            .vpc_subnet_detach_router(
+                &path.organization_name,
+                &path.project_name,
+                &path.vpc_name,
+                &path.subnet_name,
+            )
+            .await?;
+        Ok(HttpResponseOk(subnet.into()))
+    };
+    apictx.instrument_external_handler(&rqctx, handler).await
+}
+
+/**
+ * Get the effective route table of a VPC Subnet.
+ */
+#[endpoint {
+    method = GET,
+    path = "/organizations/{organization_name}/projects/{project_name}/vpcs/{vpc_name}/subnets/{subnet_name}/routes/effective",
+    tags = ["subnets"],
+}]
+async fn vpc_subnets_get_effective_routes(
+    rqctx: Arc<RequestContext<Arc<ServerContext>>>,
+    path_params: Path<VpcSubnetPathParam>,
+) -> Result<HttpResponseThrottled<HttpResponseOk<EffectiveRouteTable>>, HttpError>
+{
+    let apictx = rqctx.context();
+    let nexus = &apictx.nexus;
+    let path = path_params.into_inner();
+    let handler = async {
+        let table = nexus
+            .subnet_effective_routes(
+                &path.organization_name,
+                &path.project_name,
+                &path.vpc_name,
+                &path.subnet_name,
             )
             .await?;
-        Ok(HttpResponseCreated(vpc.into()))
+        Ok(HttpResponseOk(table))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_external_handler(&rqctx, handler).await
 }
 
+/*
+ * VPC Firewalls
+ */
+
 /**
- * Update a VPC.
+ * List firewall rules for a VPC.
+ *
+ * The response's `ETag` header may be used to make a later update of the
+ * rules conditional.
  */
 #[endpoint {
-    method = PUT,
-    path = "/organizations/{organization_name}/projects/{project_name}/vpcs/{vpc_name}",
-    tags = ["vpcs"],
+    method = GET,
+    path = "/organizations/{organization_name}/projects/{project_name}/vpcs/{vpc_name}/firewall/rules",
+    tags = ["firewall"],
 }]
-async fn project_vpcs_put_vpc(
+async fn vpc_firewall_rules_get(
     rqctx: Arc<RequestContext<Arc<ServerContext>>>,
     path_params: Path<VpcPathParam>,
-    updated_vpc: TypedBody<params::VpcUpdate>,
-) -> Result<HttpResponseUpdatedNoContent, HttpError> {
+) -> Result<
+    HttpResponseThrottled<HttpResponseOkTagged<VpcFirewallRules>>,
+    HttpError,
+> {
     let apictx = rqctx.context();
     let nexus = &apictx.nexus;
     let path = path_params.into_inner();
     let handler = async {
-        nexus
-            .project_update_vpc(
+        let (rules, time_modified) = nexus
+            .vpc_list_firewall_rules(
                 &path.organization_name,
                 &path.project_name,
                 &path.vpc_name,
-                &updated_vpc.into_inner(),
             )
             .await?;
-        Ok(HttpResponseUpdatedNoContent())
+        Ok(HttpResponseOkTagged(
+            VpcFirewallRules {
+                rules: rules.into_iter().map(|rule| rule.into()).collect(),
+            },
+            ETag::from_time_modified(&time_modified),
+        ))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_external_handler(&rqctx, handler).await
 }
 
 /**
- * Delete a vpc from a project.
+ * Replace the firewall rules for a VPC
  */
 #[endpoint {
-    method = DELETE,
-    path = "/organizations/{organization_name}/projects/{project_name}/vpcs/{vpc_name}",
-    tags = ["vpcs"],
+    method = PUT,
+    path = "/organizations/{organization_name}/projects/{project_name}/vpcs/{vpc_name}/firewall/rules",
+    tags = ["firewall"],
 }]
-async fn project_vpcs_delete_vpc(
+async fn vpc_firewall_rules_put(
     rqctx: Arc<RequestContext<Arc<ServerContext>>>,
     path_params: Path<VpcPathParam>,
-) -> Result<HttpResponseDeleted, HttpError> {
+    router_params: TypedBody<VpcFirewallRuleUpdateParams>,
+) -> Result<
+    HttpResponseThrottled<HttpResponseOkTagged<VpcFirewallRules>>,
+    HttpError,
+> {
+    // TODO: limit size of the ruleset because the GET endpoint is not paginated
     let apictx = rqctx.context();
     let nexus = &apictx.nexus;
     let path = path_params.into_inner();
-    let organization_name = &path.organization_name;
-    let project_name = &path.project_name;
-    let vpc_name = &path.vpc_name;
     let handler = async {
-        nexus
-            .project_delete_vpc(&organization_name, &project_name, &vpc_name)
+        let preconditions = Preconditions::from_request(&rqctx).await?;
+        let (rules, time_modified) = nexus
+            .vpc_update_firewall_rules(
+                &path.organization_name,
+                &path.project_name,
+                &path.vpc_name,
+                &router_params.into_inner(),
+                &preconditions,
+            )
             .await?;
-        Ok(HttpResponseDeleted())
+        Ok(HttpResponseOkTagged(
+            VpcFirewallRules {
+                rules: rules.into_iter().map(|rule| rule.into()).collect(),
+            },
+            ETag::from_time_modified(&time_modified),
+        ))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_external_handler(&rqctx, handler).await
 }
 
+/*
+ * VPC Routers
+ */
+
 /**
- * List subnets in a VPC.
+ * List VPC Custom and System Routers
  */
 #[endpoint {
     method = GET,
-    path = "/organizations/{organization_name}/projects/{project_name}/vpcs/{vpc_name}/subnets",
-    tags = ["subnets"],
+    path = "/organizations/{organization_name}/projects/{project_name}/vpcs/{vpc_name}/routers",
+    tags = ["routers"],
 }]
-async fn vpc_subnets_get(
+async fn vpc_routers_get(
     rqctx: Arc<RequestContext<Arc<ServerContext>>>,
     query_params: Query<PaginatedByName>,
     path_params: Path<VpcPathParam>,
-) -> Result<HttpResponseOk<ResultsPage<VpcSubnet>>, HttpError> {
+) -> Result<
+    HttpResponseThrottled<HttpResponseOk<ResultsPage<VpcRouter>>>,
+    HttpError,
+> {
     let apictx = rqctx.context();
     let nexus = &apictx.nexus;
     let query = query_params.into_inner();
     let path = path_params.into_inner();
     let handler = async {
-        let vpcs = nexus
-            .vpc_list_subnets(
+        let routers = nexus
+            .vpc_list_routers(
                 &path.organization_name,
                 &path.project_name,
                 &path.vpc_name,
@@ -1299,843 +3335,1139 @@ async fn vpc_subnets_get(
             )
             .await?
             .into_iter()
-            .map(|vpc| vpc.into())
+            .map(|s| s.into())
             .collect();
-        Ok(HttpResponseOk(ScanByName::results_page(&query, vpcs)?))
+        Ok(HttpResponseOk(ScanByName::results_page(&query, routers)?))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_external_handler(&rqctx, handler).await
 }
 
 /**
- * Path parameters for VPC Subnet requests
+ * Path parameters for VPC Router requests
  */
 #[derive(Deserialize, JsonSchema)]
-struct VpcSubnetPathParam {
+struct VpcRouterPathParam {
     organization_name: Name,
     project_name: Name,
     vpc_name: Name,
-    subnet_name: Name,
+    router_name: Name,
 }
 
 /**
- * Get subnet in a VPC.
+ * Get a VPC Router
  */
 #[endpoint {
     method = GET,
-    path = "/organizations/{organization_name}/projects/{project_name}/vpcs/{vpc_name}/subnets/{subnet_name}",
-    tags = ["subnets"],
+    path = "/organizations/{organization_name}/projects/{project_name}/vpcs/{vpc_name}/routers/{router_name}",
+    tags = ["routers"],
 }]
-async fn vpc_subnets_get_subnet(
+async fn vpc_routers_get_router(
     rqctx: Arc<RequestContext<Arc<ServerContext>>>,
-    path_params: Path<VpcSubnetPathParam>,
-) -> Result<HttpResponseOk<VpcSubnet>, HttpError> {
+    path_params: Path<VpcRouterPathParam>,
+) -> Result<HttpResponseThrottled<HttpResponseOkWithETag<VpcRouter>>, HttpError>
+{
     let apictx = rqctx.context();
     let nexus = &apictx.nexus;
     let path = path_params.into_inner();
     let handler = async {
-        let subnet = nexus
-            .vpc_lookup_subnet(
+        let vpc_router = nexus
+            .vpc_lookup_router(
                 &path.organization_name,
                 &path.project_name,
                 &path.vpc_name,
-                &path.subnet_name,
+                &path.router_name,
             )
             .await?;
-        Ok(HttpResponseOk(subnet.into()))
+        Ok(HttpResponseOkWithETag(vpc_router.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_external_handler(&rqctx, handler).await
 }
 
 /**
- * Create a subnet in a VPC.
+ * Create a VPC Router
  */
 #[endpoint {
     method = POST,
-    path = "/organizations/{organization_name}/projects/{project_name}/vpcs/{vpc_name}/subnets",
-    tags = ["subnets"],
+    path = "/organizations/{organization_name}/projects/{project_name}/vpcs/{vpc_name}/routers",
+    tags = ["routers"],
 }]
-async fn vpc_subnets_post(
+async fn vpc_routers_post(
     rqctx: Arc<RequestContext<Arc<ServerContext>>>,
     path_params: Path<VpcPathParam>,
-    create_params: TypedBody<params::VpcSubnetCreate>,
-) -> Result<HttpResponseCreated<VpcSubnet>, HttpError> {
+    create_params: TypedBody<params::VpcRouterCreate>,
+) -> Result<HttpResponseThrottled<HttpResponseCreated<VpcRouter>>, HttpError> {
     let apictx = rqctx.context();
     let nexus = &apictx.nexus;
     let path = path_params.into_inner();
     let handler = async {
-        let subnet = nexus
-            .vpc_create_subnet(
-                &path.organization_name,
-                &path.project_name,
-                &path.vpc_name,
-                &create_params.into_inner(),
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let router: VpcRouter = nexus
+            .idempotent_create(
+                opctx,
+                create_params.into_inner(),
+                move |nexus, _, create_params| async move {
+                    let router = nexus
+                        .vpc_create_router(
+                            &path.organization_name,
+                            &path.project_name,
+                            &path.vpc_name,
+                            &VpcRouterKind::Custom,
+                            &create_params,
+                        )
+                        .await?;
+                    Ok(router.into())
+                },
             )
             .await?;
-        Ok(HttpResponseCreated(subnet.into()))
+        Ok(HttpResponseCreated(router))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_external_handler(&rqctx, handler).await
 }
 
 /**
- * Delete a subnet from a VPC.
+ * Delete a router from its VPC
  */
 #[endpoint {
     method = DELETE,
-    path = "/organizations/{organization_name}/projects/{project_name}/vpcs/{vpc_name}/subnets/{subnet_name}",
-    tags = ["subnets"],
+    path = "/organizations/{organization_name}/projects/{project_name}/vpcs/{vpc_name}/routers/{router_name}",
+    tags = ["routers"],
 }]
-async fn vpc_subnets_delete_subnet(
+async fn vpc_routers_delete_router(
     rqctx: Arc<RequestContext<Arc<ServerContext>>>,
-    path_params: Path<VpcSubnetPathParam>,
-) -> Result<HttpResponseDeleted, HttpError> {
+    path_params: Path<VpcRouterPathParam>,
+) -> Result<HttpResponseThrottled<HttpResponseDeleted>, HttpError> {
     let apictx = rqctx.context();
     let nexus = &apictx.nexus;
     let path = path_params.into_inner();
     let handler = async {
+        let preconditions = Preconditions::from_request(&rqctx).await?;
         nexus
-            .vpc_delete_subnet(
+            .vpc_delete_router(
                 &path.organization_name,
                 &path.project_name,
                 &path.vpc_name,
-                &path.subnet_name,
+                &path.router_name,
+                &preconditions,
             )
             .await?;
         Ok(HttpResponseDeleted())
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_external_handler(&rqctx, handler).await
 }
 
 /**
- * Update a VPC Subnet.
+ * Update a VPC Router
  */
 #[endpoint {
     method = PUT,
-    path = "/organizations/{organization_name}/projects/{project_name}/vpcs/{vpc_name}/subnets/{subnet_name}",
-    tags = ["subnets"],
+    path = "/organizations/{organization_name}/projects/{project_name}/vpcs/{vpc_name}/routers/{router_name}",
+    tags = ["routers"],
 }]
-async fn vpc_subnets_put_subnet(
+async fn vpc_routers_put_router(
     rqctx: Arc<RequestContext<Arc<ServerContext>>>,
-    path_params: Path<VpcSubnetPathParam>,
-    subnet_params: TypedBody<params::VpcSubnetUpdate>,
-) -> Result<HttpResponseUpdatedNoContent, HttpError> {
+    path_params: Path<VpcRouterPathParam>,
+    router_params: TypedBody<params::VpcRouterUpdate>,
+) -> Result<HttpResponseThrottled<HttpResponseUpdatedNoContent>, HttpError> {
     let apictx = rqctx.context();
     let nexus = &apictx.nexus;
     let path = path_params.into_inner();
     let handler = async {
+        let preconditions = Preconditions::from_request(&rqctx).await?;
         nexus
-            .vpc_update_subnet(
+            .vpc_update_router(
                 &path.organization_name,
                 &path.project_name,
                 &path.vpc_name,
-                &path.subnet_name,
-                &subnet_params.into_inner(),
+                &path.router_name,
+                &router_params.into_inner(),
+                &preconditions,
             )
             .await?;
         Ok(HttpResponseUpdatedNoContent())
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_external_handler(&rqctx, handler).await
 }
 
+/*
+ * Vpc Router Routes
+ */
+
 /**
- * List IP addresses on a VPC subnet.
+ * List a Router's routes
  */
-// TODO-correctness: This API has not actually been specified in an RFD yet, and
-// may not actually be what we want. It is being implemented here to give our
-// testing introspection into network interfaces.
 #[endpoint {
     method = GET,
-    path = "/organizations/{organization_name}/projects/{project_name}/vpcs/{vpc_name}/subnets/{subnet_name}/ips",
-    tags = ["subnets"],
+    path = "/organizations/{organization_name}/projects/{project_name}/vpcs/{vpc_name}/routers/{router_name}/routes",
+    tags = ["routes"],
 }]
-async fn subnets_ips_get(
+async fn routers_routes_get(
     rqctx: Arc<RequestContext<Arc<ServerContext>>>,
     query_params: Query<PaginatedByName>,
-    path_params: Path<VpcSubnetPathParam>,
-) -> Result<HttpResponseOk<ResultsPage<NetworkInterface>>, HttpError> {
+    path_params: Path<VpcRouterPathParam>,
+) -> Result<
+    HttpResponseThrottled<HttpResponseOk<ResultsPage<RouterRoute>>>,
+    HttpError,
+> {
     let apictx = rqctx.context();
     let nexus = &apictx.nexus;
     let query = query_params.into_inner();
     let path = path_params.into_inner();
     let handler = async {
-        let interfaces = nexus
-            .subnet_list_network_interfaces(
+        let routes = nexus
+            .router_list_routes(
                 &path.organization_name,
                 &path.project_name,
                 &path.vpc_name,
-                &path.subnet_name,
+                &path.router_name,
                 &data_page_params_for(&rqctx, &query)?
                     .map_name(|n| Name::ref_cast(n)),
             )
             .await?
             .into_iter()
-            .map(|interfaces| interfaces.into())
+            .map(|route| route.into())
             .collect();
-        Ok(HttpResponseOk(ScanByName::results_page(&query, interfaces)?))
+        Ok(HttpResponseOk(ScanByName::results_page(&query, routes)?))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_external_handler(&rqctx, handler).await
 }
 
-/*
- * VPC Firewalls
+/**
+ * Path parameters for Router Route requests
  */
+#[derive(Deserialize, JsonSchema)]
+struct RouterRoutePathParam {
+    organization_name: Name,
+    project_name: Name,
+    vpc_name: Name,
+    router_name: Name,
+    route_name: Name,
+}
 
 /**
- * List firewall rules for a VPC.
+ * Get a VPC Router route
  */
 #[endpoint {
     method = GET,
-    path = "/organizations/{organization_name}/projects/{project_name}/vpcs/{vpc_name}/firewall/rules",
-    tags = ["firewall"],
+    path = "/organizations/{organization_name}/projects/{project_name}/vpcs/{vpc_name}/routers/{router_name}/routes/{route_name}",
+    tags = ["routes"],
 }]
-async fn vpc_firewall_rules_get(
+async fn routers_routes_get_route(
     rqctx: Arc<RequestContext<Arc<ServerContext>>>,
-    path_params: Path<VpcPathParam>,
-) -> Result<HttpResponseOk<VpcFirewallRules>, HttpError> {
-    // TODO: Check If-Match and fail if the ETag doesn't match anymore.
-    // Without this check, if firewall rules change while someone is listing
-    // the rules, they will see a mix of the old and new rules.
+    path_params: Path<RouterRoutePathParam>,
+) -> Result<HttpResponseThrottled<HttpResponseOkWithETag<RouterRoute>>, HttpError>
+{
     let apictx = rqctx.context();
     let nexus = &apictx.nexus;
     let path = path_params.into_inner();
     let handler = async {
-        let rules = nexus
-            .vpc_list_firewall_rules(
+        let route = nexus
+            .router_lookup_route(
                 &path.organization_name,
                 &path.project_name,
                 &path.vpc_name,
+                &path.router_name,
+                &path.route_name,
             )
             .await?;
-        Ok(HttpResponseOk(VpcFirewallRules {
-            rules: rules.into_iter().map(|rule| rule.into()).collect(),
-        }))
+        Ok(HttpResponseOkWithETag(route.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_external_handler(&rqctx, handler).await
 }
 
 /**
- * Replace the firewall rules for a VPC
+ * Create a VPC Router
+ */
+#[endpoint {
+    method = POST,
+    path = "/organizations/{organization_name}/projects/{project_name}/vpcs/{vpc_name}/routers/{router_name}/routes",
+    tags = ["routes"],
+}]
+async fn routers_routes_post(
+    rqctx: Arc<RequestContext<Arc<ServerContext>>>,
+    path_params: Path<VpcRouterPathParam>,
+    create_params: TypedBody<RouterRouteCreateParams>,
+) -> Result<HttpResponseThrottled<HttpResponseCreated<RouterRoute>>, HttpError>
+{
+    let apictx = rqctx.context();
+    let nexus = &apictx.nexus;
+    let path = path_params.into_inner();
+    let handler = async {
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let route: RouterRoute = nexus
+            .idempotent_create(
+                opctx,
+                create_params.into_inner(),
+                move |nexus, _, create_params| async move {
+                    let route = nexus
+                        .router_create_route(
+                            &path.organization_name,
+                            &path.project_name,
+                            &path.vpc_name,
+                            &path.router_name,
+                            &RouterRouteKind::Custom,
+                            &create_params,
+                        )
+                        .await?;
+                    Ok(route.into())
+                },
+            )
+            .await?;
+        Ok(HttpResponseCreated(route))
+    };
+    apictx.instrument_external_handler(&rqctx, handler).await
+}
+
+/**
+ * Delete a route from its router
+ */
+#[endpoint {
+    method = DELETE,
+    path = "/organizations/{organization_name}/projects/{project_name}/vpcs/{vpc_name}/routers/{router_name}/routes/{route_name}",
+    tags = ["routes"],
+}]
+async fn routers_routes_delete_route(
+    rqctx: Arc<RequestContext<Arc<ServerContext>>>,
+    path_params: Path<RouterRoutePathParam>,
+) -> Result<HttpResponseThrottled<HttpResponseDeleted>, HttpError> {
+    let apictx = rqctx.context();
+    let nexus = &apictx.nexus;
+    let path = path_params.into_inner();
+    let handler = async {
+        let preconditions = Preconditions::from_request(&rqctx).await?;
+        nexus
+            .router_delete_route(
+                &path.organization_name,
+                &path.project_name,
+                &path.vpc_name,
+                &path.router_name,
+                &path.route_name,
+                &preconditions,
+            )
+            .await?;
+        Ok(HttpResponseDeleted())
+    };
+    apictx.instrument_external_handler(&rqctx, handler).await
+}
+
+/**
+ * Update a Router route
  */
 #[endpoint {
     method = PUT,
-    path = "/organizations/{organization_name}/projects/{project_name}/vpcs/{vpc_name}/firewall/rules",
-    tags = ["firewall"],
+    path = "/organizations/{organization_name}/projects/{project_name}/vpcs/{vpc_name}/routers/{router_name}/routes/{route_name}",
+    tags = ["routes"],
 }]
-async fn vpc_firewall_rules_put(
+async fn routers_routes_put_route(
     rqctx: Arc<RequestContext<Arc<ServerContext>>>,
-    path_params: Path<VpcPathParam>,
-    router_params: TypedBody<VpcFirewallRuleUpdateParams>,
-) -> Result<HttpResponseOk<VpcFirewallRules>, HttpError> {
-    // TODO: Check If-Match and fail if the ETag doesn't match anymore.
-    // TODO: limit size of the ruleset because the GET endpoint is not paginated
+    path_params: Path<RouterRoutePathParam>,
+    router_params: TypedBody<RouterRouteUpdateParams>,
+) -> Result<HttpResponseThrottled<HttpResponseUpdatedNoContent>, HttpError> {
     let apictx = rqctx.context();
     let nexus = &apictx.nexus;
     let path = path_params.into_inner();
     let handler = async {
-        let rules = nexus
-            .vpc_update_firewall_rules(
+        let preconditions = Preconditions::from_request(&rqctx).await?;
+        nexus
+            .router_update_route(
                 &path.organization_name,
                 &path.project_name,
                 &path.vpc_name,
+                &path.router_name,
+                &path.route_name,
                 &router_params.into_inner(),
+                &preconditions,
             )
             .await?;
-        Ok(HttpResponseOk(VpcFirewallRules {
-            rules: rules.into_iter().map(|rule| rule.into()).collect(),
-        }))
+        Ok(HttpResponseUpdatedNoContent())
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_external_handler(&rqctx, handler).await
 }
 
 /*
- * VPC Routers
+ * Racks
  */
 
 /**
- * List VPC Custom and System Routers
+ * List racks in the system.
  */
 #[endpoint {
     method = GET,
-    path = "/organizations/{organization_name}/projects/{project_name}/vpcs/{vpc_name}/routers",
-    tags = ["routers"],
+    path = "/hardware/racks",
+    tags = ["racks"],
 }]
-async fn vpc_routers_get(
+async fn hardware_racks_get(
     rqctx: Arc<RequestContext<Arc<ServerContext>>>,
-    query_params: Query<PaginatedByName>,
-    path_params: Path<VpcPathParam>,
-) -> Result<HttpResponseOk<ResultsPage<VpcRouter>>, HttpError> {
+    query_params: Query<PaginatedById>,
+) -> Result<HttpResponseThrottled<HttpResponseOk<ResultsPage<Rack>>>, HttpError>
+{
     let apictx = rqctx.context();
     let nexus = &apictx.nexus;
     let query = query_params.into_inner();
-    let path = path_params.into_inner();
     let handler = async {
-        let routers = nexus
-            .vpc_list_routers(
-                &path.organization_name,
-                &path.project_name,
-                &path.vpc_name,
-                &data_page_params_for(&rqctx, &query)?
-                    .map_name(|n| Name::ref_cast(n)),
-            )
-            .await?
-            .into_iter()
-            .map(|s| s.into())
-            .collect();
-        Ok(HttpResponseOk(ScanByName::results_page(&query, routers)?))
+        let rack_stream =
+            nexus.racks_list(&data_page_params_for(&rqctx, &query)?).await?;
+        let view_list = to_list::<db::model::Rack, Rack>(rack_stream).await;
+        Ok(HttpResponseOk(ScanById::results_page(&query, view_list)?))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_external_handler(&rqctx, handler).await
 }
 
 /**
- * Path parameters for VPC Router requests
+ * Path parameters for Rack requests
  */
 #[derive(Deserialize, JsonSchema)]
-struct VpcRouterPathParam {
-    organization_name: Name,
-    project_name: Name,
-    vpc_name: Name,
-    router_name: Name,
+struct RackPathParam {
+    /** The rack's unique ID. */
+    rack_id: Uuid,
 }
 
 /**
- * Get a VPC Router
+ * Fetch information about a particular rack.
  */
 #[endpoint {
     method = GET,
-    path = "/organizations/{organization_name}/projects/{project_name}/vpcs/{vpc_name}/routers/{router_name}",
-    tags = ["routers"],
+    path = "/hardware/racks/{rack_id}",
+    tags = ["racks"],
 }]
-async fn vpc_routers_get_router(
+async fn hardware_racks_get_rack(
     rqctx: Arc<RequestContext<Arc<ServerContext>>>,
-    path_params: Path<VpcRouterPathParam>,
-) -> Result<HttpResponseOk<VpcRouter>, HttpError> {
+    path_params: Path<RackPathParam>,
+) -> Result<HttpResponseThrottled<HttpResponseOk<Rack>>, HttpError> {
     let apictx = rqctx.context();
     let nexus = &apictx.nexus;
     let path = path_params.into_inner();
     let handler = async {
-        let vpc_router = nexus
-            .vpc_lookup_router(
-                &path.organization_name,
-                &path.project_name,
-                &path.vpc_name,
-                &path.router_name,
-            )
-            .await?;
-        Ok(HttpResponseOk(vpc_router.into()))
+        let rack_info = nexus.rack_lookup(&path.rack_id).await?;
+        Ok(HttpResponseOk(rack_info.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_external_handler(&rqctx, handler).await
 }
 
+/*
+ * Sleds
+ */
+
 /**
- * Create a VPC Router
+ * List sleds in the system.
  */
 #[endpoint {
-    method = POST,
-    path = "/organizations/{organization_name}/projects/{project_name}/vpcs/{vpc_name}/routers",
-    tags = ["routers"],
+    method = GET,
+    path = "/hardware/sleds",
+    tags = ["sleds"],
 }]
-async fn vpc_routers_post(
+async fn hardware_sleds_get(
     rqctx: Arc<RequestContext<Arc<ServerContext>>>,
-    path_params: Path<VpcPathParam>,
-    create_params: TypedBody<params::VpcRouterCreate>,
-) -> Result<HttpResponseCreated<VpcRouter>, HttpError> {
+    query_params: Query<PaginatedById>,
+) -> Result<HttpResponseThrottled<HttpResponseOk<ResultsPage<Sled>>>, HttpError>
+{
     let apictx = rqctx.context();
     let nexus = &apictx.nexus;
-    let path = path_params.into_inner();
+    let query = query_params.into_inner();
     let handler = async {
-        let router = nexus
-            .vpc_create_router(
-                &path.organization_name,
-                &path.project_name,
-                &path.vpc_name,
-                &VpcRouterKind::Custom,
-                &create_params.into_inner(),
-            )
-            .await?;
-        Ok(HttpResponseCreated(router.into()))
+        let sleds = nexus
+            .sleds_list(&data_page_params_for(&rqctx, &query)?)
+            .await?
+            .into_iter()
+            .map(|s| s.into())
+            .collect();
+        Ok(HttpResponseOk(ScanById::results_page(&query, sleds)?))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_external_handler(&rqctx, handler).await
 }
 
 /**
- * Delete a router from its VPC
+ * Path parameters for Sled requests
+ */
+#[derive(Deserialize, JsonSchema)]
+struct SledPathParam {
+    /** The sled's unique ID. */
+    sled_id: Uuid,
+}
+
+/**
+ * Fetch information about a sled in the system.
  */
 #[endpoint {
-    method = DELETE,
-    path = "/organizations/{organization_name}/projects/{project_name}/vpcs/{vpc_name}/routers/{router_name}",
-    tags = ["routers"],
+    method = GET,
+    path = "/hardware/sleds/{sled_id}",
+    tags = ["sleds"],
 }]
-async fn vpc_routers_delete_router(
+async fn hardware_sleds_get_sled(
     rqctx: Arc<RequestContext<Arc<ServerContext>>>,
-    path_params: Path<VpcRouterPathParam>,
-) -> Result<HttpResponseDeleted, HttpError> {
+    path_params: Path<SledPathParam>,
+) -> Result<HttpResponseThrottled<HttpResponseOk<Sled>>, HttpError> {
     let apictx = rqctx.context();
     let nexus = &apictx.nexus;
     let path = path_params.into_inner();
     let handler = async {
-        nexus
-            .vpc_delete_router(
-                &path.organization_name,
-                &path.project_name,
-                &path.vpc_name,
-                &path.router_name,
-            )
-            .await?;
-        Ok(HttpResponseDeleted())
+        let sled_info = nexus.sled_lookup(&path.sled_id).await?;
+        Ok(HttpResponseOk(sled_info.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_external_handler(&rqctx, handler).await
 }
 
+/*
+ * Operations
+ */
+
 /**
- * Update a VPC Router
+ * List operations in a project.
  */
 #[endpoint {
-    method = PUT,
-    path = "/organizations/{organization_name}/projects/{project_name}/vpcs/{vpc_name}/routers/{router_name}",
-    tags = ["routers"],
+    method = GET,
+    path = "/organizations/{organization_name}/projects/{project_name}/operations",
+    tags = ["operations"],
 }]
-async fn vpc_routers_put_router(
+async fn project_operations_get(
     rqctx: Arc<RequestContext<Arc<ServerContext>>>,
-    path_params: Path<VpcRouterPathParam>,
-    router_params: TypedBody<params::VpcRouterUpdate>,
-) -> Result<HttpResponseUpdatedNoContent, HttpError> {
+    query_params: Query<PaginatedById>,
+    path_params: Path<ProjectPathParam>,
+) -> Result<
+    HttpResponseThrottled<HttpResponseOk<ResultsPage<Operation>>>,
+    HttpError,
+> {
     let apictx = rqctx.context();
     let nexus = &apictx.nexus;
+    let query = query_params.into_inner();
     let path = path_params.into_inner();
     let handler = async {
-        nexus
-            .vpc_update_router(
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let operations = nexus
+            .operations_list(
+                &opctx,
                 &path.organization_name,
                 &path.project_name,
-                &path.vpc_name,
-                &path.router_name,
-                &router_params.into_inner(),
+                &data_page_params_for(&rqctx, &query)?,
             )
-            .await?;
-        Ok(HttpResponseUpdatedNoContent())
+            .await?
+            .into_iter()
+            .map(|o| o.into())
+            .collect();
+        Ok(HttpResponseOk(ScanById::results_page_by_id(
+            &query,
+            operations,
+            |operation: &Operation| operation.id,
+        )?))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_external_handler(&rqctx, handler).await
 }
 
-/*
- * Vpc Router Routes
+/**
+ * Path parameters for Operation requests
  */
+#[derive(Deserialize, JsonSchema)]
+struct OperationPathParam {
+    organization_name: Name,
+    project_name: Name,
+    operation_id: Uuid,
+}
 
 /**
- * List a Router's routes
+ * Fetch an operation in a project.
  */
 #[endpoint {
     method = GET,
-    path = "/organizations/{organization_name}/projects/{project_name}/vpcs/{vpc_name}/routers/{router_name}/routes",
-    tags = ["routes"],
+    path = "/organizations/{organization_name}/projects/{project_name}/operations/{operation_id}",
+    tags = ["operations"],
 }]
-async fn routers_routes_get(
+async fn project_operations_get_operation(
     rqctx: Arc<RequestContext<Arc<ServerContext>>>,
-    query_params: Query<PaginatedByName>,
-    path_params: Path<VpcRouterPathParam>,
-) -> Result<HttpResponseOk<ResultsPage<RouterRoute>>, HttpError> {
+    path_params: Path<OperationPathParam>,
+) -> Result<HttpResponseThrottled<HttpResponseOk<Operation>>, HttpError> {
     let apictx = rqctx.context();
     let nexus = &apictx.nexus;
-    let query = query_params.into_inner();
     let path = path_params.into_inner();
     let handler = async {
-        let routes = nexus
-            .router_list_routes(
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let operation = nexus
+            .operation_fetch(
+                &opctx,
                 &path.organization_name,
                 &path.project_name,
-                &path.vpc_name,
-                &path.router_name,
-                &data_page_params_for(&rqctx, &query)?
-                    .map_name(|n| Name::ref_cast(n)),
+                path.operation_id,
             )
-            .await?
-            .into_iter()
-            .map(|route| route.into())
-            .collect();
-        Ok(HttpResponseOk(ScanByName::results_page(&query, routes)?))
+            .await?;
+        Ok(HttpResponseOk(operation.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_external_handler(&rqctx, handler).await
 }
 
+/*
+ * Events
+ */
+
+/** Number of events returned at once, unless the client asks for fewer */
+const EVENTS_DEFAULT_LIMIT: u32 = 100;
+
+/** Most events returned at once */
+const EVENTS_MAX_LIMIT: u32 = 1000;
+
+/** Longest that a request waits for an event to be appended */
+const EVENTS_MAX_WAIT_SECS: u32 = 60;
+
 /**
- * Path parameters for Router Route requests
+ * Query parameters for reading a Project's event log
  */
 #[derive(Deserialize, JsonSchema)]
-struct RouterRoutePathParam {
-    organization_name: Name,
-    project_name: Name,
-    vpc_name: Name,
-    router_name: Name,
-    route_name: Name,
+struct ProjectEventsQuery {
+    /** return the events after this position (default: 0, the beginning) */
+    after: Option<u64>,
+    /** maximum number of events to return (default: 100, at most 1000) */
+    limit: Option<NonZeroU32>,
+    /**
+     * if there are no events after `after` yet, how many seconds to wait for
+     * one to be appended (default: 0, at most 60)
+     */
+    wait_secs: Option<u32>,
 }
 
 /**
- * Get a VPC Router route
+ * Read a project's event log.
+ *
+ * The log records the creation, deletion, and state changes of the project's
+ * instances, disks, and snapshots.  Events are returned in order, starting
+ * with the one after position `after`.  If there are none yet, the request
+ * waits up to `wait_secs` for one, so a client can follow the log by asking
+ * for the events after `next_cursor` in a loop.
  */
 #[endpoint {
     method = GET,
-    path = "/organizations/{organization_name}/projects/{project_name}/vpcs/{vpc_name}/routers/{router_name}/routes/{route_name}",
-    tags = ["routes"],
+    path = "/organizations/{organization_name}/projects/{project_name}/events",
+    tags = ["events"],
 }]
-async fn routers_routes_get_route(
+async fn project_events_get(
     rqctx: Arc<RequestContext<Arc<ServerContext>>>,
-    path_params: Path<RouterRoutePathParam>,
-) -> Result<HttpResponseOk<RouterRoute>, HttpError> {
+    query_params: Query<ProjectEventsQuery>,
+    path_params: Path<ProjectPathParam>,
+) -> Result<HttpResponseThrottled<HttpResponseOk<ProjectEventsPage>>, HttpError>
+{
     let apictx = rqctx.context();
     let nexus = &apictx.nexus;
+    let query = query_params.into_inner();
     let path = path_params.into_inner();
     let handler = async {
-        let route = nexus
-            .router_lookup_route(
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let after = query.after.unwrap_or(0);
+        let limit = query
+            .limit
+            .map_or(EVENTS_DEFAULT_LIMIT, NonZeroU32::get)
+            .min(EVENTS_MAX_LIMIT);
+        let wait_secs = query.wait_secs.unwrap_or(0).min(EVENTS_MAX_WAIT_SECS);
+        let items = nexus
+            .project_list_events(
+                &opctx,
                 &path.organization_name,
                 &path.project_name,
-                &path.vpc_name,
-                &path.router_name,
-                &path.route_name,
+                after,
+                NonZeroU32::new(limit).unwrap(),
+                Duration::from_secs(u64::from(wait_secs)),
             )
-            .await?;
-        Ok(HttpResponseOk(route.into()))
+            .await?
+            .into_iter()
+            .map(|e| e.into())
+            .collect::<Vec<ProjectEvent>>();
+        let next_cursor = items.last().map_or(after, |e| e.seq);
+        Ok(HttpResponseOk(ProjectEventsPage { items, next_cursor }))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_external_handler(&rqctx, handler).await
 }
 
 /**
- * Create a VPC Router
+ * List the webhooks to which a project's events are delivered.
  */
 #[endpoint {
-    method = POST,
-    path = "/organizations/{organization_name}/projects/{project_name}/vpcs/{vpc_name}/routers/{router_name}/routes",
-    tags = ["routes"],
+    method = GET,
+    path = "/organizations/{organization_name}/projects/{project_name}/webhooks",
+    tags = ["events"],
 }]
-async fn routers_routes_post(
+async fn project_webhooks_get(
     rqctx: Arc<RequestContext<Arc<ServerContext>>>,
-    path_params: Path<VpcRouterPathParam>,
-    create_params: TypedBody<RouterRouteCreateParams>,
-) -> Result<HttpResponseCreated<RouterRoute>, HttpError> {
+    query_params: Query<PaginatedById>,
+    path_params: Path<ProjectPathParam>,
+) -> Result<
+    HttpResponseThrottled<HttpResponseOk<ResultsPage<ProjectWebhook>>>,
+    HttpError,
+> {
     let apictx = rqctx.context();
     let nexus = &apictx.nexus;
+    let query = query_params.into_inner();
     let path = path_params.into_inner();
     let handler = async {
-        let route = nexus
-            .router_create_route(
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let webhooks = nexus
+            .project_list_webhooks(
+                &opctx,
                 &path.organization_name,
                 &path.project_name,
-                &path.vpc_name,
-                &path.router_name,
-                &RouterRouteKind::Custom,
-                &create_params.into_inner(),
+                &data_page_params_for(&rqctx, &query)?,
             )
-            .await?;
-        Ok(HttpResponseCreated(route.into()))
+            .await?
+            .into_iter()
+            .map(|w| w.into())
+            .collect();
+        Ok(HttpResponseOk(ScanById::results_page_by_id(
+            &query,
+            webhooks,
+            |webhook: &ProjectWebhook| webhook.id,
+        )?))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_external_handler(&rqctx, handler).await
 }
 
 /**
- * Delete a route from its router
+ * Register a webhook to which a project's events are delivered.
+ *
+ * Each event appended to the project's log from then on is POSTed to the
+ * webhook's URL, in order, and signed with the webhook's secret.  Failed
+ * deliveries are retried with backoff.
  */
 #[endpoint {
-    method = DELETE,
-    path = "/organizations/{organization_name}/projects/{project_name}/vpcs/{vpc_name}/routers/{router_name}/routes/{route_name}",
-    tags = ["routes"],
+    method = POST,
+    path = "/organizations/{organization_name}/projects/{project_name}/webhooks",
+    tags = ["events"],
 }]
-async fn routers_routes_delete_route(
+async fn project_webhooks_post(
     rqctx: Arc<RequestContext<Arc<ServerContext>>>,
-    path_params: Path<RouterRoutePathParam>,
-) -> Result<HttpResponseDeleted, HttpError> {
+    path_params: Path<ProjectPathParam>,
+    new_webhook: TypedBody<params::ProjectWebhookCreate>,
+) -> Result<HttpResponseThrottled<HttpResponseCreated<ProjectWebhook>>, HttpError>
+{
     let apictx = rqctx.context();
     let nexus = &apictx.nexus;
     let path = path_params.into_inner();
     let handler = async {
-        nexus
-            .router_delete_route(
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let webhook = nexus
+            .project_create_webhook(
+                &opctx,
                 &path.organization_name,
-                &path.project_name,
-                &path.vpc_name,
-                &path.router_name,
-                &path.route_name,
+                &path.project_name,
+                &new_webhook.into_inner(),
             )
             .await?;
-        Ok(HttpResponseDeleted())
+        Ok(HttpResponseCreated(webhook.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_external_handler(&rqctx, handler).await
 }
 
 /**
- * Update a Router route
+ * Path parameters for webhook requests
+ */
+#[derive(Deserialize, JsonSchema)]
+struct WebhookPathParam {
+    organization_name: Name,
+    project_name: Name,
+    webhook_id: Uuid,
+}
+
+/**
+ * Stop delivering a project's events to a webhook.
  */
 #[endpoint {
-    method = PUT,
-    path = "/organizations/{organization_name}/projects/{project_name}/vpcs/{vpc_name}/routers/{router_name}/routes/{route_name}",
-    tags = ["routes"],
+    method = DELETE,
+    path = "/organizations/{organization_name}/projects/{project_name}/webhooks/{webhook_id}",
+    tags = ["events"],
 }]
-async fn routers_routes_put_route(
+async fn project_webhooks_delete_webhook(
     rqctx: Arc<RequestContext<Arc<ServerContext>>>,
-    path_params: Path<RouterRoutePathParam>,
-    router_params: TypedBody<RouterRouteUpdateParams>,
-) -> Result<HttpResponseUpdatedNoContent, HttpError> {
+    path_params: Path<WebhookPathParam>,
+) -> Result<HttpResponseThrottled<HttpResponseDeleted>, HttpError> {
     let apictx = rqctx.context();
     let nexus = &apictx.nexus;
     let path = path_params.into_inner();
     let handler = async {
+        let opctx = OpContext::for_external_api(&rqctx).await?;
         nexus
-            .router_update_route(
+            .project_delete_webhook(
+                &opctx,
                 &path.organization_name,
                 &path.project_name,
-                &path.vpc_name,
-                &path.router_name,
-                &path.route_name,
-                &router_params.into_inner(),
+                path.webhook_id,
             )
             .await?;
-        Ok(HttpResponseUpdatedNoContent())
+        Ok(HttpResponseDeleted())
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_external_handler(&rqctx, handler).await
 }
 
 /*
- * Racks
+ * Users
  */
 
 /**
- * List racks in the system.
+ * List users.
  */
 #[endpoint {
     method = GET,
-    path = "/hardware/racks",
-    tags = ["racks"],
+    path = "/users",
+    tags = ["users"],
 }]
-async fn hardware_racks_get(
+async fn users_get(
     rqctx: Arc<RequestContext<Arc<ServerContext>>>,
-    query_params: Query<PaginatedById>,
-) -> Result<HttpResponseOk<ResultsPage<Rack>>, HttpError> {
+    query_params: Query<PaginatedByName>,
+) -> Result<HttpResponseThrottled<HttpResponseOk<ResultsPage<User>>>, HttpError>
+{
     let apictx = rqctx.context();
     let nexus = &apictx.nexus;
     let query = query_params.into_inner();
+    let pagparams =
+        data_page_params_for(&rqctx, &query)?.map_name(|n| Name::ref_cast(n));
     let handler = async {
-        let rack_stream =
-            nexus.racks_list(&data_page_params_for(&rqctx, &query)?).await?;
-        let view_list = to_list::<db::model::Rack, Rack>(rack_stream).await;
-        Ok(HttpResponseOk(ScanById::results_page(&query, view_list)?))
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let users = nexus
+            .users_list(&opctx, &pagparams)
+            .await?
+            .into_iter()
+            .map(|i| i.into())
+            .collect();
+        Ok(HttpResponseOk(ScanByName::results_page(&query, users)?))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_external_handler(&rqctx, handler).await
 }
 
 /**
- * Path parameters for Rack requests
+ * Create a new user.
+ */
+#[endpoint {
+    method = POST,
+    path = "/users",
+    tags = ["users"],
+}]
+async fn users_post(
+    rqctx: Arc<RequestContext<Arc<ServerContext>>>,
+    new_user: TypedBody<params::UserCreate>,
+) -> Result<HttpResponseThrottled<HttpResponseCreated<User>>, HttpError> {
+    let apictx = rqctx.context();
+    let nexus = &apictx.nexus;
+    let handler = async {
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let user = nexus.user_create(&opctx, &new_user.into_inner()).await?;
+        Ok(HttpResponseCreated(user.into()))
+    };
+    apictx.instrument_external_handler(&rqctx, handler).await
+}
+
+/**
+ * Path parameters for user requests
  */
 #[derive(Deserialize, JsonSchema)]
-struct RackPathParam {
-    /** The rack's unique ID. */
-    rack_id: Uuid,
+struct UserPathParam {
+    /// The user's unique name.
+    user_name: Name,
 }
 
 /**
- * Fetch information about a particular rack.
+ * Fetch a specific user.
  */
 #[endpoint {
     method = GET,
-    path = "/hardware/racks/{rack_id}",
-    tags = ["racks"],
+    path = "/users/{user_name}",
+    tags = ["users"],
 }]
-async fn hardware_racks_get_rack(
+async fn users_get_user(
     rqctx: Arc<RequestContext<Arc<ServerContext>>>,
-    path_params: Path<RackPathParam>,
-) -> Result<HttpResponseOk<Rack>, HttpError> {
+    path_params: Path<UserPathParam>,
+) -> Result<HttpResponseThrottled<HttpResponseOk<User>>, HttpError> {
     let apictx = rqctx.context();
     let nexus = &apictx.nexus;
     let path = path_params.into_inner();
+    let user_name = &path.user_name;
     let handler = async {
-        let rack_info = nexus.rack_lookup(&path.rack_id).await?;
-        Ok(HttpResponseOk(rack_info.into()))
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let user = nexus.user_fetch(&opctx, &user_name).await?;
+        Ok(HttpResponseOk(user.into()))
+    };
+    apictx.instrument_external_handler(&rqctx, handler).await
+}
+
+/**
+ * Delete a specific user, ending their sessions and removing their roles.
+ */
+#[endpoint {
+    method = DELETE,
+    path = "/users/{user_name}",
+    tags = ["users"],
+}]
+async fn users_delete_user(
+    rqctx: Arc<RequestContext<Arc<ServerContext>>>,
+    path_params: Path<UserPathParam>,
+) -> Result<HttpResponseThrottled<HttpResponseDeleted>, HttpError> {
+    let apictx = rqctx.context();
+    let nexus = &apictx.nexus;
+    let path = path_params.into_inner();
+    let user_name = &path.user_name;
+    let handler = async {
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        nexus.user_delete(&opctx, &user_name).await?;
+        Ok(HttpResponseDeleted())
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_external_handler(&rqctx, handler).await
 }
 
 /*
- * Sleds
+ * Built-in (system) users
  */
 
 /**
- * List sleds in the system.
+ * List the built-in system users
  */
 #[endpoint {
     method = GET,
-    path = "/hardware/sleds",
-    tags = ["sleds"],
+    path = "/system/users",
+    tags = ["users"],
 }]
-async fn hardware_sleds_get(
+async fn system_users_get(
     rqctx: Arc<RequestContext<Arc<ServerContext>>>,
-    query_params: Query<PaginatedById>,
-) -> Result<HttpResponseOk<ResultsPage<Sled>>, HttpError> {
+    query_params: Query<PaginatedByName>,
+) -> Result<HttpResponseThrottled<HttpResponseOk<ResultsPage<User>>>, HttpError>
+{
     let apictx = rqctx.context();
     let nexus = &apictx.nexus;
     let query = query_params.into_inner();
+    let pagparams =
+        data_page_params_for(&rqctx, &query)?.map_name(|n| Name::ref_cast(n));
     let handler = async {
-        let sleds = nexus
-            .sleds_list(&data_page_params_for(&rqctx, &query)?)
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let users = nexus
+            .users_builtin_list(&opctx, &pagparams)
             .await?
             .into_iter()
-            .map(|s| s.into())
+            .map(|i| i.into())
             .collect();
-        Ok(HttpResponseOk(ScanById::results_page(&query, sleds)?))
+        Ok(HttpResponseOk(ScanByName::results_page(&query, users)?))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_external_handler(&rqctx, handler).await
 }
 
 /**
- * Path parameters for Sled requests
+ * Path parameters for global (system) user requests
  */
 #[derive(Deserialize, JsonSchema)]
-struct SledPathParam {
-    /** The sled's unique ID. */
-    sled_id: Uuid,
+struct SystemUserPathParam {
+    /// The built-in user's unique name.
+    user_name: Name,
 }
 
 /**
- * Fetch information about a sled in the system.
+ * Fetch a specific built-in system user
  */
 #[endpoint {
     method = GET,
-    path = "/hardware/sleds/{sled_id}",
-    tags = ["sleds"],
+    path = "/system/users/{user_name}",
+    tags = ["users"],
 }]
-async fn hardware_sleds_get_sled(
+async fn system_users_get_user(
     rqctx: Arc<RequestContext<Arc<ServerContext>>>,
-    path_params: Path<SledPathParam>,
-) -> Result<HttpResponseOk<Sled>, HttpError> {
+    path_params: Path<SystemUserPathParam>,
+) -> Result<HttpResponseThrottled<HttpResponseOk<User>>, HttpError> {
     let apictx = rqctx.context();
     let nexus = &apictx.nexus;
     let path = path_params.into_inner();
+    let user_name = &path.user_name;
     let handler = async {
-        let sled_info = nexus.sled_lookup(&path.sled_id).await?;
-        Ok(HttpResponseOk(sled_info.into()))
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let user = nexus.user_builtin_fetch(&opctx, &user_name).await?;
+        Ok(HttpResponseOk(user.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_external_handler(&rqctx, handler).await
 }
 
 /*
- * Sagas
+ * API tokens
  */
 
 /**
- * List all sagas (for debugging)
+ * List the current user's API tokens.
  */
 #[endpoint {
     method = GET,
-    path = "/sagas",
-    tags = ["sagas"],
+    path = "/session/me/tokens",
+    tags = ["tokens"],
 }]
-async fn sagas_get(
+async fn session_me_tokens_get(
     rqctx: Arc<RequestContext<Arc<ServerContext>>>,
     query_params: Query<PaginatedById>,
-) -> Result<HttpResponseOk<ResultsPage<Saga>>, HttpError> {
+) -> Result<
+    HttpResponseThrottled<HttpResponseOk<ResultsPage<ApiToken>>>,
+    HttpError,
+> {
     let apictx = rqctx.context();
     let nexus = &apictx.nexus;
     let query = query_params.into_inner();
-    let pagparams = data_page_params_for(&rqctx, &query)?;
     let handler = async {
-        let saga_stream = nexus.sagas_list(&pagparams).await?;
-        let view_list = to_list(saga_stream).await;
-        Ok(HttpResponseOk(ScanById::results_page(&query, view_list)?))
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let tokens = nexus
+            .api_tokens_list(&opctx, &data_page_params_for(&rqctx, &query)?)
+            .await?
+            .into_iter()
+            .map(|t| t.into())
+            .collect();
+        Ok(HttpResponseOk(ScanById::results_page_by_id(
+            &query,
+            tokens,
+            |token: &ApiToken| token.id,
+        )?))
+    };
+    apictx.instrument_external_handler(&rqctx, handler).await
+}
+
+/**
+ * Create an API token for the current user.
+ *
+ * The token itself is only returned here, so save it somewhere safe.
+ */
+#[endpoint {
+    method = POST,
+    path = "/session/me/tokens",
+    tags = ["tokens"],
+}]
+async fn session_me_tokens_post(
+    rqctx: Arc<RequestContext<Arc<ServerContext>>>,
+    new_token: TypedBody<params::ApiTokenCreate>,
+) -> Result<
+    HttpResponseThrottled<HttpResponseCreated<ApiTokenWithSecret>>,
+    HttpError,
+> {
+    let apictx = rqctx.context();
+    let nexus = &apictx.nexus;
+    let handler = async {
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let (token, secret) =
+            nexus.api_token_create(&opctx, &new_token.into_inner()).await?;
+        Ok(HttpResponseCreated(ApiTokenWithSecret {
+            token: token.into(),
+            secret,
+        }))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_external_handler(&rqctx, handler).await
 }
 
 /**
- * Path parameters for Saga requests
+ * Path parameters for API token requests
  */
 #[derive(Deserialize, JsonSchema)]
-struct SagaPathParam {
-    saga_id: Uuid,
+struct ApiTokenPathParam {
+    /// The token's unique ID.
+    token_id: Uuid,
 }
 
 /**
- * Fetch information about a single saga (for debugging)
+ * Revoke one of the current user's API tokens.
  */
 #[endpoint {
-    method = GET,
-    path = "/sagas/{saga_id}",
-    tags = ["sagas"],
+    method = DELETE,
+    path = "/session/me/tokens/{token_id}",
+    tags = ["tokens"],
 }]
-async fn sagas_get_saga(
+async fn session_me_tokens_delete_token(
     rqctx: Arc<RequestContext<Arc<ServerContext>>>,
-    path_params: Path<SagaPathParam>,
-) -> Result<HttpResponseOk<Saga>, HttpError> {
+    path_params: Path<ApiTokenPathParam>,
+) -> Result<HttpResponseThrottled<HttpResponseDeleted>, HttpError> {
     let apictx = rqctx.context();
     let nexus = &apictx.nexus;
     let path = path_params.into_inner();
     let handler = async {
-        let saga = nexus.saga_get(path.saga_id).await?;
-        Ok(HttpResponseOk(saga))
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        nexus.api_token_delete(&opctx, path.token_id).await?;
+        Ok(HttpResponseDeleted())
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_external_handler(&rqctx, handler).await
 }
 
 /*
- * Built-in (system) users
+ * SSH keys
  */
 
 /**
- * List the built-in system users
+ * List the current user's SSH public keys.
  */
 #[endpoint {
     method = GET,
-    path = "/users",
-    tags = ["users"],
+    path = "/session/me/sshkeys",
+    tags = ["sshkeys"],
 }]
-async fn users_get(
+async fn session_me_sshkeys_get(
     rqctx: Arc<RequestContext<Arc<ServerContext>>>,
-    query_params: Query<PaginatedByName>,
-) -> Result<HttpResponseOk<ResultsPage<User>>, HttpError> {
+    query_params: Query<PaginatedById>,
+) -> Result<HttpResponseThrottled<HttpResponseOk<ResultsPage<SshKey>>>, HttpError>
+{
     let apictx = rqctx.context();
     let nexus = &apictx.nexus;
     let query = query_params.into_inner();
-    let pagparams =
-        data_page_params_for(&rqctx, &query)?.map_name(|n| Name::ref_cast(n));
     let handler = async {
         let opctx = OpContext::for_external_api(&rqctx).await?;
-        let users = nexus
-            .users_builtin_list(&opctx, &pagparams)
+        let keys = nexus
+            .ssh_keys_list(&opctx, &data_page_params_for(&rqctx, &query)?)
             .await?
             .into_iter()
-            .map(|i| i.into())
+            .map(|k| k.into())
             .collect();
-        Ok(HttpResponseOk(ScanByName::results_page(&query, users)?))
+        Ok(HttpResponseOk(ScanById::results_page_by_id(
+            &query,
+            keys,
+            |key: &SshKey| key.id,
+        )?))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_external_handler(&rqctx, handler).await
 }
 
 /**
- * Path parameters for global (system) user requests
+ * Upload an SSH public key for the current user.
+ *
+ * The key is authorized for logging in to every Instance that the user creates
+ * from then on.
+ */
+#[endpoint {
+    method = POST,
+    path = "/session/me/sshkeys",
+    tags = ["sshkeys"],
+}]
+async fn session_me_sshkeys_post(
+    rqctx: Arc<RequestContext<Arc<ServerContext>>>,
+    new_key: TypedBody<params::SshKeyCreate>,
+) -> Result<HttpResponseThrottled<HttpResponseCreated<SshKey>>, HttpError> {
+    let apictx = rqctx.context();
+    let nexus = &apictx.nexus;
+    let handler = async {
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let key = nexus.ssh_key_create(&opctx, &new_key.into_inner()).await?;
+        Ok(HttpResponseCreated(key.into()))
+    };
+    apictx.instrument_external_handler(&rqctx, handler).await
+}
+
+/**
+ * Path parameters for SSH key requests
  */
 #[derive(Deserialize, JsonSchema)]
-struct UserPathParam {
-    /// The built-in user's unique name.
-    user_name: Name,
+struct SshKeyPathParam {
+    /// The key's unique ID.
+    ssh_key_id: Uuid,
 }
 
 /**
- * Fetch a specific built-in system user
+ * Remove one of the current user's SSH public keys.
+ *
+ * Instances that were created while the key was present keep it.
  */
 #[endpoint {
-    method = GET,
-    path = "/users/{user_name}",
-    tags = ["users"],
+    method = DELETE,
+    path = "/session/me/sshkeys/{ssh_key_id}",
+    tags = ["sshkeys"],
 }]
-async fn users_get_user(
+async fn session_me_sshkeys_delete_key(
     rqctx: Arc<RequestContext<Arc<ServerContext>>>,
-    path_params: Path<UserPathParam>,
-) -> Result<HttpResponseOk<User>, HttpError> {
+    path_params: Path<SshKeyPathParam>,
+) -> Result<HttpResponseThrottled<HttpResponseDeleted>, HttpError> {
     let apictx = rqctx.context();
     let nexus = &apictx.nexus;
     let path = path_params.into_inner();
-    let user_name = &path.user_name;
     let handler = async {
         let opctx = OpContext::for_external_api(&rqctx).await?;
-        let user = nexus.user_builtin_fetch(&opctx, &user_name).await?;
-        Ok(HttpResponseOk(user.into()))
+        nexus.ssh_key_delete(&opctx, path.ssh_key_id).await?;
+        Ok(HttpResponseDeleted())
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_external_handler(&rqctx, handler).await
 }
 
 /**
@@ -2149,8 +4481,12 @@ async fn users_get_user(
 async fn timeseries_schema_get(
     rqctx: Arc<RequestContext<Arc<ServerContext>>>,
     query_params: Query<oximeter_db::TimeseriesSchemaPaginationParams>,
-) -> Result<HttpResponseOk<ResultsPage<oximeter_db::TimeseriesSchema>>, HttpError>
-{
+) -> Result<
+    HttpResponseThrottled<
+        HttpResponseOk<ResultsPage<oximeter_db::TimeseriesSchema>>,
+    >,
+    HttpError,
+> {
     let apictx = rqctx.context();
     let nexus = &apictx.nexus;
     let query = query_params.into_inner();
@@ -2158,7 +4494,7 @@ async fn timeseries_schema_get(
     let handler = async {
         Ok(HttpResponseOk(nexus.timeseries_schema_list(&query, limit).await?))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_external_handler(&rqctx, handler).await
 }
 
 /*
@@ -2185,7 +4521,8 @@ struct RolePage {
 async fn roles_get(
     rqctx: Arc<RequestContext<Arc<ServerContext>>>,
     query_params: Query<PaginationParams<EmptyScanParams, RolePage>>,
-) -> Result<HttpResponseOk<ResultsPage<Role>>, HttpError> {
+) -> Result<HttpResponseThrottled<HttpResponseOk<ResultsPage<Role>>>, HttpError>
+{
     let apictx = rqctx.context();
     let nexus = &apictx.nexus;
     let query = query_params.into_inner();
@@ -2220,7 +4557,7 @@ async fn roles_get(
             |role: &Role, _| RolePage { last_seen: role.name.to_string() },
         )?))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_external_handler(&rqctx, handler).await
 }
 
 /**
@@ -2243,7 +4580,7 @@ struct RolePathParam {
 async fn roles_get_role(
     rqctx: Arc<RequestContext<Arc<ServerContext>>>,
     path_params: Path<RolePathParam>,
-) -> Result<HttpResponseOk<Role>, HttpError> {
+) -> Result<HttpResponseThrottled<HttpResponseOk<Role>>, HttpError> {
     let apictx = rqctx.context();
     let nexus = &apictx.nexus;
     let path = path_params.into_inner();
@@ -2253,5 +4590,81 @@ async fn roles_get_role(
         let role = nexus.role_builtin_fetch(&opctx, &role_name).await?;
         Ok(HttpResponseOk(role.into()))
     };
-    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+    apictx.instrument_external_handler(&rqctx, handler).await
+}
+
+/*
+ * Audit log
+ */
+
+/**
+ * Query parameters for listing the audit log
+ */
+#[derive(Clone, Deserialize, JsonSchema, Serialize)]
+struct AuditLogScanParams {
+    /** only list entries recorded at or after this time */
+    start_time: Option<DateTime<Utc>>,
+    /** only list entries recorded before this time */
+    end_time: Option<DateTime<Utc>>,
+}
+
+/**
+ * Page selector for the audit log, which is listed in chronological order
+ */
+#[derive(Deserialize, JsonSchema, Serialize)]
+struct AuditLogPage {
+    #[serde(flatten)]
+    scan: AuditLogScanParams,
+    last_time: DateTime<Utc>,
+    last_id: Uuid,
+}
+
+/**
+ * List the audit log of mutating API calls
+ */
+#[endpoint {
+    method = GET,
+    path = "/audit-log",
+    tags = ["audit-log"],
+}]
+async fn audit_log_get(
+    rqctx: Arc<RequestContext<Arc<ServerContext>>>,
+    query_params: Query<PaginationParams<AuditLogScanParams, AuditLogPage>>,
+) -> Result<
+    HttpResponseThrottled<HttpResponseOk<ResultsPage<AuditLogEntry>>>,
+    HttpError,
+> {
+    let apictx = rqctx.context();
+    let nexus = &apictx.nexus;
+    let query = query_params.into_inner();
+    let handler = async {
+        let opctx = OpContext::for_external_api(&rqctx).await?;
+        let (scan, marker) = match &query.page {
+            WhichPage::First(scan) => (scan, None),
+            WhichPage::Next(AuditLogPage { scan, last_time, last_id }) => {
+                (scan, Some((*last_time, *last_id)))
+            }
+        };
+        let pagparams = DataPageParams {
+            limit: rqctx.page_limit(&query)?,
+            direction: PaginationOrder::Ascending,
+            marker: marker.as_ref(),
+        };
+        let entries = nexus
+            .audit_log_list(&opctx, scan.start_time, scan.end_time, &pagparams)
+            .await?
+            .into_iter()
+            .map(|e| e.into())
+            .collect();
+        Ok(HttpResponseOk(dropshot::ResultsPage::new(
+            entries,
+            scan,
+            |entry: &AuditLogEntry, scan: &AuditLogScanParams| AuditLogPage {
+                scan: scan.clone(),
+                last_time: entry.time_created,
+                last_id: entry.id,
+            },
+        )?))
+    };
+    apictx.instrument_external_handler(&rqctx, handler).await
 }
@@ -0,0 +1,246 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+ * Asynchronous responses for long-running requests
+ *
+ * Some requests (like creating an Instance) are carried out by a saga, which
+ * can take a while.  By default, these requests don't complete until the saga
+ * does.  A client that would rather not wait can send `Prefer: respond-async`
+ * (RFC 7240).  Then the request completes with 202 ("Accepted") as soon as the
+ * saga has started.  The response body describes the [`Operation`] carrying
+ * out the request, and the `Location` header says where to poll for its
+ * status.
+ *
+ * Dropshot describes only one response per endpoint, so the 202 response is
+ * added to the OpenAPI document afterwards, by
+ * [`document_accepted_responses`].
+ */
+
+use super::views::Operation;
+use crate::ServerContext;
+use dropshot::ApiEndpointResponse;
+use dropshot::HttpError;
+use dropshot::HttpResponse;
+use dropshot::HttpResponseAccepted;
+use dropshot::RequestContext;
+use http::header;
+use http::HeaderMap;
+use http::HeaderValue;
+use http::Response;
+use hyper::Body;
+use omicron_common::api::external::Name;
+use serde::Deserialize;
+use serde::Serialize;
+use std::sync::Arc;
+
+/**
+ * Returns whether the client that made the request `rqctx` asked not to wait
+ * for long-running requests to finish
+ */
+pub async fn respond_async(
+    rqctx: &Arc<RequestContext<Arc<ServerContext>>>,
+) -> bool {
+    let request = rqctx.request.lock().await;
+    prefers_respond_async(request.headers())
+}
+
+fn prefers_respond_async(headers: &HeaderMap) -> bool {
+    /*
+     * Each preference is a token, possibly followed by parameters.  Values we
+     * can't parse are ignored, as RFC 7240 asks.
+     */
+    headers
+        .get_all("prefer")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|preference| preference.split(';').next())
+        .any(|token| token.trim().eq_ignore_ascii_case("respond-async"))
+}
+
+/**
+ * The result of a long-running request: either its result, or (if the client
+ * asked not to wait) the operation carrying it out
+ */
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MaybeAccepted<T> {
+    Done(T),
+    Accepted(Operation),
+}
+
+impl<T> MaybeAccepted<T> {
+    pub fn map<U, F>(self, f: F) -> MaybeAccepted<U>
+    where
+        F: FnOnce(T) -> U,
+    {
+        match self {
+            MaybeAccepted::Done(result) => MaybeAccepted::Done(f(result)),
+            MaybeAccepted::Accepted(operation) => {
+                MaybeAccepted::Accepted(operation)
+            }
+        }
+    }
+
+    /**
+     * Returns the HTTP response for this result, using `done` to make the
+     * response for a request that has finished
+     *
+     * The operation is in the given Project.
+     */
+    pub fn into_response<R, F>(
+        self,
+        organization_name: &Name,
+        project_name: &Name,
+        done: F,
+    ) -> HttpResponseMaybeAccepted<R>
+    where
+        F: FnOnce(T) -> R,
+    {
+        match self {
+            MaybeAccepted::Done(result) => {
+                HttpResponseMaybeAccepted::Done(done(result))
+            }
+            MaybeAccepted::Accepted(operation) => {
+                let location = format!(
+                    "/organizations/{}/projects/{}/operations/{}",
+                    organization_name.as_str(),
+                    project_name.as_str(),
+                    operation.id
+                );
+                HttpResponseMaybeAccepted::Accepted { operation, location }
+            }
+        }
+    }
+}
+
+/**
+ * Appended to the description of `R` in the API description of an endpoint
+ * whose response is an [`HttpResponseMaybeAccepted<R>`], which is how
+ * [`document_accepted_responses`] finds those endpoints
+ */
+const MAYBE_ACCEPTED_SUFFIX: &str =
+    " (or, with `Prefer: respond-async`, 202 and the operation carrying out the \
+     request)";
+
+/**
+ * Like `R`, unless the client asked not to wait for the request to finish, in
+ * which case this is a 202 ("Accepted") response describing the operation
+ * carrying out the request
+ */
+pub enum HttpResponseMaybeAccepted<R> {
+    Done(R),
+    Accepted { operation: Operation, location: String },
+}
+
+impl<R> HttpResponse for HttpResponseMaybeAccepted<R>
+where
+    R: HttpResponse,
+{
+    fn to_result(self) -> Result<Response<Body>, HttpError> {
+        match self {
+            HttpResponseMaybeAccepted::Done(response) => response.to_result(),
+            HttpResponseMaybeAccepted::Accepted { operation, location } => {
+                let mut response =
+                    HttpResponseAccepted(operation).to_result()?;
+                let location =
+                    HeaderValue::from_str(&location).map_err(|e| {
+                        HttpError::for_internal_error(format!(
+                            "bad operation location: {}",
+                            e
+                        ))
+                    })?;
+                response.headers_mut().insert(header::LOCATION, location);
+                Ok(response)
+            }
+        }
+    }
+
+    fn metadata() -> ApiEndpointResponse {
+        let metadata = R::metadata();
+        let description = format!(
+            "{}{}",
+            metadata.description.as_deref().unwrap_or("successful operation"),
+            MAYBE_ACCEPTED_SUFFIX
+        );
+        ApiEndpointResponse { description: Some(description), ..metadata }
+    }
+}
+
+/**
+ * Adds the 202 ("Accepted") response, with its `Location` header, to each
+ * operation in the OpenAPI document `spec` whose response is an
+ * [`HttpResponseMaybeAccepted`]
+ */
+pub fn document_accepted_responses(spec: &mut serde_json::Value) {
+    let accepted = serde_json::json!({
+        "description": "the operation carrying out the request has started",
+        "headers": {
+            "Location": {
+                "description": "where to poll for the operation's status",
+                "style": "simple",
+                "required": true,
+                "schema": { "type": "string" }
+            }
+        },
+        "content": {
+            "application/json": {
+                "schema": { "$ref": "#/components/schemas/Operation" }
+            }
+        }
+    });
+    let operations = spec
+        .get_mut("paths")
+        .and_then(serde_json::Value::as_object_mut)
+        .into_iter()
+        .flat_map(|paths| paths.values_mut())
+        .filter_map(serde_json::Value::as_object_mut)
+        .flat_map(|path| path.values_mut());
+    for operation in operations {
+        let responses = match operation
+            .get_mut("responses")
+            .and_then(serde_json::Value::as_object_mut)
+        {
+            Some(responses) => responses,
+            None => continue,
+        };
+        let maybe_accepted = responses.values().any(|response| {
+            response["description"]
+                .as_str()
+                .map_or(false, |d| d.ends_with(MAYBE_ACCEPTED_SUFFIX))
+        });
+        if maybe_accepted {
+            responses.insert(String::from("202"), accepted.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::prefers_respond_async;
+    use http::HeaderMap;
+    use http::HeaderValue;
+
+    fn prefers(values: &[&str]) -> bool {
+        let mut headers = HeaderMap::new();
+        for value in values {
+            headers.append("prefer", HeaderValue::from_str(value).unwrap());
+        }
+        prefers_respond_async(&headers)
+    }
+
+    #[test]
+    fn test_prefer_respond_async() {
+        assert!(!prefers(&[]));
+        assert!(prefers(&["respond-async"]));
+        assert!(prefers(&["Respond-Async"]));
+        assert!(prefers(&["return=minimal, respond-async"]));
+        assert!(prefers(&["respond-async; foo=bar, wait=10"]));
+        assert!(prefers(&["wait=10", "respond-async"]));
+        assert!(!prefers(&["wait=10"]));
+        assert!(!prefers(&["return=respond-async"]));
+        assert!(!prefers(&["respond-asynchronously"]));
+    }
+}
@@ -6,12 +6,15 @@
  * Params define the request bodies of API endpoints for creating or updating resources.
  */
 
+use chrono::{DateTime, Utc};
 use omicron_common::api::external::{
-    ByteCount, IdentityMetadataCreateParams, IdentityMetadataUpdateParams,
-    InstanceCpuCount, Ipv4Net, Ipv6Net, Name,
+    AffinityPolicy, ByteCount, IdentityMetadataCreateParams,
+    IdentityMetadataUpdateParams, InstanceCpuCount, Ipv4Net, Ipv6Net, Labels,
+    Name,
 };
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
 use uuid::Uuid;
 
 /*
@@ -58,6 +61,31 @@ pub struct ProjectUpdate {
     pub identity: IdentityMetadataUpdateParams,
 }
 
+/*
+ * QUOTAS
+ */
+
+/**
+ * New limits for the [`Quota`](crate::external_api::views::Quota) on an
+ * Organization or Project
+ *
+ * These replace all of the existing limits.  A limit that is omitted is
+ * unlimited.
+ */
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
+pub struct QuotaUpdate {
+    /// maximum number of Instances
+    pub instances: Option<u32>,
+    /// maximum total vCPUs of all Instances
+    pub vcpus: Option<u32>,
+    /// maximum total memory of all Instances
+    pub memory: Option<ByteCount>,
+    /// maximum total size of all Disks
+    pub disk_bytes: Option<ByteCount>,
+    /// maximum number of VPCs
+    pub vpcs: Option<u32>,
+}
+
 /*
  * NETWORK INTERFACES
  */
@@ -70,8 +98,22 @@ pub struct ProjectUpdate {
 pub struct NetworkInterfaceCreate {
     #[serde(flatten)]
     pub identity: IdentityMetadataCreateParams,
+    /** The VPC in which to create the interface */
+    pub vpc_name: Name,
+    /** The VPC Subnet in which to create the interface */
+    pub subnet_name: Name,
+    /**
+     * The IP address for the interface.  One will be auto-assigned if not
+     * provided.
+     */
+    pub ip: Option<IpAddr>,
 }
 
+/**
+ * Most network interfaces that an Instance may have
+ */
+pub const MAX_NICS_PER_INSTANCE: usize = 8;
+
 /*
  * INSTANCES
  */
@@ -86,6 +128,80 @@ pub struct InstanceCreate {
     pub ncpus: InstanceCpuCount,
     pub memory: ByteCount,
     pub hostname: String, /* TODO-cleanup different type? */
+
+    /// The name of an affinity group in the same project for this Instance to
+    /// join
+    pub affinity_group: Option<Name>,
+
+    /// Data for the Instance's initialization system (such as cloud-init),
+    /// base64-encoded.  At most 32 KiB once decoded.
+    #[serde(default, with = "user_data")]
+    #[schemars(with = "String")]
+    pub user_data: Vec<u8>,
+
+    /// SSH public keys to authorize for logging in to the Instance
+    #[serde(default)]
+    pub ssh_public_keys: Vec<String>,
+
+    /// The network interfaces to create for the Instance.  If there are none,
+    /// the Instance gets one in the "default" Subnet of the Project's
+    /// "default" VPC.
+    #[serde(default)]
+    pub network_interfaces: Vec<NetworkInterfaceCreate>,
+}
+
+/**
+ * Most bytes of user data that an Instance may be created with
+ */
+pub const MAX_USER_DATA_BYTES: usize = 32 * 1024;
+
+/**
+ * Serializes an Instance's user data as a base64 string, and limits its size
+ * when deserializing it.
+ */
+mod user_data {
+    use super::MAX_USER_DATA_BYTES;
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S>(data: &[u8], serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        base64::encode(data).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Vec<u8>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let encoded = String::deserialize(deserializer)?;
+        let data = base64::decode(&encoded).map_err(|e| {
+            D::Error::custom(format!("user data is not valid base64: {}", e))
+        })?;
+        if data.len() > MAX_USER_DATA_BYTES {
+            return Err(D::Error::custom(format!(
+                "user data is {} bytes, but may be at most {}",
+                data.len(),
+                MAX_USER_DATA_BYTES
+            )));
+        }
+        Ok(data)
+    }
+}
+
+/**
+ * Updateable properties of an
+ * [`Instance`](omicron_common::api::external::Instance)
+ *
+ * The vCPUs and memory can only be changed while the Instance is stopped.
+ */
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct InstanceUpdate {
+    pub ncpus: Option<InstanceCpuCount>,
+    pub memory: Option<ByteCount>,
+    /** if present, these replace all of the Instance's labels */
+    pub labels: Option<Labels>,
 }
 
 /**
@@ -96,6 +212,41 @@ pub struct InstanceMigrate {
     pub dst_sled_uuid: Uuid,
 }
 
+/**
+ * Query parameters for fetching the recent output of an
+ * [`Instance`](omicron_common::api::external::Instance)'s serial console
+ */
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct InstanceSerialConsoleRequest {
+    /** most bytes of output to return (defaults to all that are retained) */
+    pub max_bytes: Option<u64>,
+}
+
+/*
+ * AFFINITY GROUPS
+ */
+
+/**
+ * Create-time parameters for an
+ * [`AffinityGroup`](crate::external_api::views::AffinityGroup)
+ */
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct AffinityGroupCreate {
+    #[serde(flatten)]
+    pub identity: IdentityMetadataCreateParams,
+    pub policy: AffinityPolicy,
+}
+
+/**
+ * Updateable properties of an
+ * [`AffinityGroup`](crate::external_api::views::AffinityGroup)
+ */
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct AffinityGroupUpdate {
+    #[serde(flatten)]
+    pub identity: IdentityMetadataUpdateParams,
+}
+
 /*
  * VPCS
  */
@@ -164,6 +315,73 @@ pub struct VpcSubnetUpdate {
     pub ipv6_block: Option<Ipv6Net>,
 }
 
+/**
+ * Parameters for attaching a custom router to a
+ * [`VpcSubnet`](crate::external_api::views::VpcSubnet)
+ */
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct VpcSubnetRouterAttach {
+    /** Name of the custom router, which must be in the subnet's VPC */
+    pub router_name: Name,
+}
+
+/*
+ * IP POOLS
+ */
+
+/**
+ * Create-time parameters for an [`IpPool`](crate::external_api::views::IpPool)
+ */
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct IpPoolCreate {
+    #[serde(flatten)]
+    pub identity: IdentityMetadataCreateParams,
+}
+
+/**
+ * An inclusive range of external addresses to add to an
+ * [`IpPool`](crate::external_api::views::IpPool)
+ */
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct IpPoolRangeCreate {
+    /** The first address in the range */
+    pub first: IpAddr,
+    /**
+     * The last address in the range.  It must be in the same address family
+     * as `first`, and not less than it.
+     */
+    pub last: IpAddr,
+}
+
+/*
+ * FLOATING IPS
+ */
+
+/**
+ * Create-time parameters for a
+ * [`FloatingIp`](crate::external_api::views::FloatingIp)
+ */
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct FloatingIpCreate {
+    #[serde(flatten)]
+    pub identity: IdentityMetadataCreateParams,
+    /** The IP pool from which to allocate the address */
+    pub pool_name: Name,
+}
+
+/**
+ * Parameters for attaching a
+ * [`FloatingIp`](crate::external_api::views::FloatingIp) to an Instance
+ */
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct FloatingIpAttach {
+    /**
+     * The Instance, in the same Project, whose primary network interface
+     * receives the address
+     */
+    pub instance_name: Name,
+}
+
 /*
  * VPC ROUTERS
  */
@@ -213,10 +431,38 @@ impl DiskCreate {
     }
 
     pub fn extent_count(&self) -> i64 {
-        let extent_size = EXTENT_SIZE as i64;
-        let size = self.size.to_bytes() as i64;
-        size / extent_size
-            + ((size % extent_size) + extent_size - 1) / extent_size
+        extent_count(self.size)
+    }
+}
+
+/// Returns the number of extents needed to hold a Disk of size `size`
+fn extent_count(size: ByteCount) -> i64 {
+    let extent_size = EXTENT_SIZE as i64;
+    let size = size.to_bytes() as i64;
+    size / extent_size + ((size % extent_size) + extent_size - 1) / extent_size
+}
+
+/**
+ * Updateable properties of a [`Disk`](omicron_common::api::external::Disk)
+ */
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct DiskUpdate {
+    /** if present, these replace all of the Disk's labels */
+    pub labels: Option<Labels>,
+}
+
+/**
+ * Parameters for resizing a [`Disk`](omicron_common::api::external::Disk)
+ */
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct DiskResize {
+    /** new size of the Disk */
+    pub size: ByteCount,
+}
+
+impl DiskResize {
+    pub fn extent_count(&self) -> i64 {
+        extent_count(self.size)
     }
 }
 
@@ -229,6 +475,111 @@ pub struct DiskIdentifier {
     pub disk: Name,
 }
 
+/*
+ * SNAPSHOTS
+ */
+
+/**
+ * Create-time parameters for a [`Snapshot`](crate::external_api::views::Snapshot)
+ */
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct SnapshotCreate {
+    #[serde(flatten)]
+    pub identity: IdentityMetadataCreateParams,
+}
+
+/*
+ * USERS
+ */
+
+/**
+ * Create-time parameters for a [`User`](crate::db::model::User)
+ */
+#[derive(Clone, Deserialize, Serialize, JsonSchema)]
+pub struct UserCreate {
+    #[serde(flatten)]
+    pub identity: IdentityMetadataCreateParams,
+    /// the password that the user will log in with
+    pub password: String,
+}
+
+impl std::fmt::Debug for UserCreate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("UserCreate")
+            .field("identity", &self.identity)
+            .field("password", &"<redacted>")
+            .finish()
+    }
+}
+
+/*
+ * API TOKENS
+ */
+
+/**
+ * What requests authenticated with an API token are allowed to do
+ */
+#[derive(
+    Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize, JsonSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiTokenScope {
+    /** anything that the token's owner is allowed to do */
+    ReadWrite,
+    /** only operations that don't modify anything */
+    ReadOnly,
+}
+
+/**
+ * Create-time parameters for an [`ApiToken`](crate::external_api::views::ApiToken)
+ */
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct ApiTokenCreate {
+    /** what the token is for */
+    pub description: String,
+    /** when the token stops working (if not specified, it never expires) */
+    pub time_expires: Option<DateTime<Utc>>,
+    pub scope: ApiTokenScope,
+}
+
+/*
+ * SSH KEYS
+ */
+
+/**
+ * Create-time parameters for an [`SshKey`](crate::external_api::views::SshKey)
+ */
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct SshKeyCreate {
+    /** what the key is for */
+    pub description: String,
+    /**
+     * the public key, in the one-line format of OpenSSH's `.pub` files (e.g.,
+     * "ssh-ed25519 AAAA... user@host")
+     */
+    pub public_key: String,
+}
+
+/*
+ * PROJECT WEBHOOKS
+ */
+
+/**
+ * Create-time parameters for a
+ * [`ProjectWebhook`](crate::external_api::views::ProjectWebhook)
+ */
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct ProjectWebhookCreate {
+    /** the http or https URL to which the Project's events are POSTed */
+    pub url: String,
+    /**
+     * the key with which each delivery is signed (16 to 255 bytes): the
+     * "x-oxide-signature" header of each request is "sha256=" followed by the
+     * hex-encoded HMAC-SHA256 of the request body
+     */
+    pub secret: String,
+}
+
 /*
  * BUILT-IN USERS
  *
@@ -256,12 +607,58 @@ mod test {
             identity: IdentityMetadataCreateParams {
                 name: Name::try_from("myobject".to_string()).unwrap(),
                 description: "desc".to_string(),
+                labels: Default::default(),
             },
             snapshot_id: None,
             size,
         }
     }
 
+    #[test]
+    fn test_instance_create_user_data() {
+        let parse = |extra: serde_json::Value| {
+            let mut params = serde_json::json!({
+                "name": "myobject",
+                "description": "desc",
+                "ncpus": 1,
+                "memory": 1024,
+                "hostname": "myobject",
+                "affinity_group": null,
+            });
+            params
+                .as_object_mut()
+                .unwrap()
+                .extend(extra.as_object().unwrap().clone().into_iter());
+            serde_json::from_value::<InstanceCreate>(params)
+        };
+
+        let params = parse(serde_json::json!({})).unwrap();
+        assert!(params.user_data.is_empty());
+        assert!(params.ssh_public_keys.is_empty());
+
+        let params = parse(serde_json::json!({
+            "user_data": "I2Nsb3VkLWNvbmZpZwo=",
+            "ssh_public_keys": ["ssh-ed25519 AAAA me@example.com"],
+        }))
+        .unwrap();
+        assert_eq!(params.user_data, b"#cloud-config\n");
+        assert_eq!(params.ssh_public_keys, ["ssh-ed25519 AAAA me@example.com"]);
+        assert_eq!(
+            serde_json::to_value(&params).unwrap()["user_data"],
+            "I2Nsb3VkLWNvbmZpZwo="
+        );
+
+        let largest = base64::encode(vec![0u8; MAX_USER_DATA_BYTES]);
+        assert!(parse(serde_json::json!({ "user_data": largest })).is_ok());
+        let too_large = base64::encode(vec![0u8; MAX_USER_DATA_BYTES + 1]);
+        let error =
+            parse(serde_json::json!({ "user_data": too_large })).unwrap_err();
+        assert!(error.to_string().contains("may be at most 32768"));
+        let error = parse(serde_json::json!({ "user_data": "not base64!" }))
+            .unwrap_err();
+        assert!(error.to_string().contains("not valid base64"));
+    }
+
     #[test]
     fn test_extent_count() {
         let params = new_disk_create_params(ByteCount::try_from(0u64).unwrap());
@@ -0,0 +1,163 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+ * Versions of the external API
+ *
+ * Clients say which version of the API they were written against with the
+ * `api-version` header, whose value is one of [`API_VERSIONS`].  Requests
+ * without the header get the current (newest) version, and requests for a
+ * version that isn't supported are rejected.
+ *
+ * openapi/nexus.json describes the current version and lists all of the
+ * supported versions.  Each version's document is also frozen under
+ * openapi/nexus-versions/ when the version is introduced, and a test fails if
+ * the current document stops being compatible with the current version's
+ * frozen document.  That's the cue to add a new version.
+ *
+ * For now, that's all there is to versioning: there's only one version, so
+ * handlers only ever deal with the current version of each type.
+ * TODO Once a second version is added, requests and responses in older
+ * versions will have to be translated to and from the current version of
+ * each type that changed, which means making the requested version available
+ * to handlers and converting the bodies on their way through.
+ */
+
+use dropshot::HttpError;
+use http::HeaderMap;
+
+/** Header that clients use to choose a version of the API */
+pub const API_VERSION_HEADER: &str = "api-version";
+
+/**
+ * Versions of the external API that Nexus supports, oldest first
+ *
+ * The last of these is the current version.  To make a change that would break
+ * clients of the current version:
+ *
+ * - add a new version to the end of this list,
+ * - translate requests and responses in the older versions for the handlers
+ *   whose types change (see the TODO above), and
+ * - freeze the new version's OpenAPI document by copying openapi/nexus.json to
+ *   openapi/nexus-versions/ once the change is made.
+ */
+pub const API_VERSIONS: &[ApiVersion] = &[ApiVersion("2021-12-01")];
+
+/**
+ * A version of the external API, named for the date it was introduced
+ */
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct ApiVersion(&'static str);
+
+impl ApiVersion {
+    /** Returns the newest version of the API, which handlers speak */
+    pub fn current() -> ApiVersion {
+        *API_VERSIONS.last().unwrap()
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        self.0
+    }
+
+    /**
+     * Returns the version requested by `request` (the current version, if it
+     * didn't ask for one)
+     */
+    pub fn from_request<B>(
+        request: &http::Request<B>,
+    ) -> Result<ApiVersion, HttpError> {
+        parse_header(request.headers(), API_VERSIONS).map_err(|message| {
+            HttpError::for_bad_request(
+                Some(String::from("UnsupportedApiVersion")),
+                message,
+            )
+        })
+    }
+}
+
+impl std::fmt::Display for ApiVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+fn parse_header(
+    headers: &HeaderMap,
+    versions: &[ApiVersion],
+) -> Result<ApiVersion, String> {
+    let mut values = headers.get_all(API_VERSION_HEADER).iter();
+    let value = match (values.next(), values.next()) {
+        (None, _) => return Ok(*versions.last().unwrap()),
+        (Some(value), None) => value,
+        (Some(_), Some(_)) => {
+            return Err(format!(
+                "header \"{}\" may only be specified once",
+                API_VERSION_HEADER
+            ))
+        }
+    };
+    let requested = value.to_str().unwrap_or("").trim();
+    versions.iter().find(|v| v.0 == requested).copied().ok_or_else(|| {
+        format!(
+            "unsupported API version \"{}\" (supported versions: {})",
+            value.to_str().unwrap_or("(not ASCII)"),
+            versions.iter().map(|v| v.0).collect::<Vec<_>>().join(", ")
+        )
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_header, ApiVersion, API_VERSIONS, API_VERSION_HEADER};
+    use http::HeaderMap;
+    use http::HeaderValue;
+
+    const V1: ApiVersion = ApiVersion("2020-01-01");
+    const V2: ApiVersion = ApiVersion("2020-06-01");
+    const V3: ApiVersion = ApiVersion("2021-01-01");
+    const VERSIONS: &[ApiVersion] = &[V1, V2, V3];
+
+    #[test]
+    fn test_versions_are_ordered() {
+        assert!(!API_VERSIONS.is_empty());
+        for pair in API_VERSIONS.windows(2) {
+            assert!(pair[0] < pair[1], "{} >= {}", pair[0], pair[1]);
+        }
+        for version in API_VERSIONS {
+            assert!(
+                chrono::NaiveDate::parse_from_str(version.as_str(), "%Y-%m-%d")
+                    .is_ok(),
+                "version \"{}\" is not a date",
+                version
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_header() {
+        let parse = |values: &[&str]| {
+            let mut headers = HeaderMap::new();
+            for value in values {
+                headers.append(
+                    API_VERSION_HEADER,
+                    HeaderValue::from_str(value).unwrap(),
+                );
+            }
+            parse_header(&headers, VERSIONS)
+        };
+
+        assert_eq!(parse(&[]), Ok(V3));
+        assert_eq!(parse(&["2020-01-01"]), Ok(V1));
+        assert_eq!(parse(&[" 2020-06-01 "]), Ok(V2));
+        assert_eq!(
+            parse(&["2020-02-01"]).unwrap_err(),
+            "unsupported API version \"2020-02-01\" (supported versions: \
+             2020-01-01, 2020-06-01, 2021-01-01)"
+        );
+        assert_eq!(
+            parse(&["2020-01-01", "2020-01-01"]).unwrap_err(),
+            "header \"api-version\" may only be specified once"
+        );
+    }
+}
@@ -9,12 +9,19 @@
 use crate::authn;
 use crate::db::identity::{Asset, Resource};
 use crate::db::model;
+use crate::external_api::params;
 use api_identity::ObjectIdentity;
+use chrono::{DateTime, Utc};
+use dropshot::HttpError;
 use omicron_common::api::external::{
-    IdentityMetadata, Ipv4Net, Ipv6Net, Name, ObjectIdentity, RoleName,
+    AffinityPolicy, ByteCount, Error, IdentityMetadata, Ipv4Net, Ipv6Net,
+    Labels, Name, ObjectIdentity, RoleName,
 };
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::convert::TryFrom;
+use std::net::IpAddr;
 use std::net::SocketAddr;
 use uuid::Uuid;
 
@@ -29,11 +36,12 @@ use uuid::Uuid;
 pub struct Organization {
     #[serde(flatten)]
     pub identity: IdentityMetadata,
+    pub labels: Labels,
 }
 
 impl Into<Organization> for model::Organization {
     fn into(self) -> Organization {
-        Organization { identity: self.identity() }
+        Organization { identity: self.identity(), labels: self.labels.0 }
     }
 }
 
@@ -52,6 +60,7 @@ pub struct Project {
      */
     #[serde(flatten)]
     pub identity: IdentityMetadata,
+    pub labels: Labels,
     pub organization_id: Uuid,
 }
 
@@ -59,11 +68,159 @@ impl Into<Project> for model::Project {
     fn into(self) -> Project {
         Project {
             identity: self.identity(),
+            labels: self.labels.0,
             organization_id: self.organization_id,
         }
     }
 }
 
+/*
+ * QUOTAS
+ */
+
+/**
+ * Client view of the resource quota on an Organization or Project
+ */
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct Quota {
+    /** limits on the resources that may be provisioned */
+    pub limits: QuotaLimits,
+    /** resources currently provisioned */
+    pub usage: ResourceUsage,
+}
+
+impl Into<Quota> for (model::Quota, model::ResourceUsage) {
+    fn into(self) -> Quota {
+        Quota { limits: self.0.into(), usage: self.1.into() }
+    }
+}
+
+/**
+ * Limits on the resources provisioned within an Organization or Project.  A
+ * limit that is omitted is unlimited.
+ */
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct QuotaLimits {
+    /** maximum number of Instances */
+    pub instances: Option<u32>,
+    /** maximum total vCPUs of all Instances */
+    pub vcpus: Option<u32>,
+    /** maximum total memory of all Instances */
+    pub memory: Option<ByteCount>,
+    /** maximum total size of all Disks */
+    pub disk_bytes: Option<ByteCount>,
+    /** maximum number of VPCs */
+    pub vpcs: Option<u32>,
+}
+
+impl Into<QuotaLimits> for model::Quota {
+    fn into(self) -> QuotaLimits {
+        let count = |limit: Option<i64>| {
+            limit.map(|v| u32::try_from(v).unwrap_or(u32::MAX))
+        };
+        QuotaLimits {
+            instances: count(self.instances),
+            vcpus: count(self.vcpus),
+            memory: self.memory.map(|b| b.0),
+            disk_bytes: self.disk_bytes.map(|b| b.0),
+            vpcs: count(self.vpcs),
+        }
+    }
+}
+
+/**
+ * Resources provisioned within an Organization or Project
+ */
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct ResourceUsage {
+    /** number of Instances */
+    pub instances: u64,
+    /** total vCPUs of all Instances */
+    pub vcpus: u64,
+    /** total memory of all Instances */
+    pub memory: ByteCount,
+    /** total size of all Disks */
+    pub disk_bytes: ByteCount,
+    /** number of VPCs */
+    pub vpcs: u64,
+}
+
+impl Into<ResourceUsage> for model::ResourceUsage {
+    fn into(self) -> ResourceUsage {
+        let count = |v: i64| u64::try_from(v).unwrap_or(0);
+        let bytes = |v: i64| ByteCount::try_from(v).unwrap();
+        ResourceUsage {
+            instances: count(self.instances),
+            vcpus: count(self.vcpus),
+            memory: bytes(self.memory),
+            disk_bytes: bytes(self.disk_bytes),
+            vpcs: count(self.vpcs),
+        }
+    }
+}
+
+/*
+ * AFFINITY GROUPS
+ */
+
+/**
+ * Client view of an [`AffinityGroup`]
+ */
+#[derive(ObjectIdentity, Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct AffinityGroup {
+    #[serde(flatten)]
+    pub identity: IdentityMetadata,
+
+    /** id for the project containing this affinity group */
+    pub project_id: Uuid,
+
+    /** how the members of this group are placed relative to one another */
+    pub policy: AffinityPolicy,
+}
+
+impl Into<AffinityGroup> for model::AffinityGroup {
+    fn into(self) -> AffinityGroup {
+        AffinityGroup {
+            identity: self.identity(),
+            project_id: self.project_id,
+            policy: self.policy.0,
+        }
+    }
+}
+
+/*
+ * SNAPSHOTS
+ */
+
+/**
+ * Client view of a [`Snapshot`]
+ */
+#[derive(ObjectIdentity, Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct Snapshot {
+    #[serde(flatten)]
+    pub identity: IdentityMetadata,
+
+    /** id for the project containing this snapshot */
+    pub project_id: Uuid,
+
+    /** id for the disk from which this snapshot was taken */
+    pub disk_id: Uuid,
+
+    /** size of the disk when this snapshot was taken */
+    pub size: ByteCount,
+}
+
+impl Into<Snapshot> for model::Snapshot {
+    fn into(self) -> Snapshot {
+        Snapshot {
+            identity: self.identity(),
+            project_id: self.project_id,
+            disk_id: self.disk_id,
+            size: self.size.into(),
+        }
+    }
+}
+
 /*
  * VPCs
  */
@@ -75,6 +232,7 @@ impl Into<Project> for model::Project {
 pub struct Vpc {
     #[serde(flatten)]
     pub identity: IdentityMetadata,
+    pub labels: Labels,
 
     /** id for the project containing this VPC */
     pub project_id: Uuid,
@@ -94,6 +252,7 @@ impl Into<Vpc> for model::Vpc {
     fn into(self) -> Vpc {
         Vpc {
             identity: self.identity(),
+            labels: self.labels.0,
             project_id: self.project_id,
             system_router_id: self.system_router_id,
             ipv6_prefix: *self.ipv6_prefix,
@@ -118,6 +277,9 @@ pub struct VpcSubnet {
 
     /** The IPv6 subnet CIDR block. */
     pub ipv6_block: Ipv6Net,
+
+    /** The custom router attached to the subnet, if any. */
+    pub custom_router_id: Option<Uuid>,
 }
 
 impl Into<VpcSubnet> for model::VpcSubnet {
@@ -127,6 +289,94 @@ impl Into<VpcSubnet> for model::VpcSubnet {
             vpc_id: self.vpc_id,
             ipv4_block: self.ipv4_block.0,
             ipv6_block: self.ipv6_block.0,
+            custom_router_id: self.custom_router_id,
+        }
+    }
+}
+
+/*
+ * IP POOLS
+ */
+
+/**
+ * Client view of an [`IpPool`]
+ */
+#[derive(ObjectIdentity, Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct IpPool {
+    #[serde(flatten)]
+    pub identity: IdentityMetadata,
+}
+
+impl Into<IpPool> for model::IpPool {
+    fn into(self) -> IpPool {
+        IpPool { identity: self.identity() }
+    }
+}
+
+/**
+ * Client view of an inclusive range of addresses in an [`IpPool`]
+ */
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct IpPoolRange {
+    pub id: Uuid,
+    pub time_created: DateTime<Utc>,
+    /** id for the pool containing this range */
+    pub ip_pool_id: Uuid,
+    /** the first address in the range */
+    pub first: IpAddr,
+    /** the last address in the range */
+    pub last: IpAddr,
+}
+
+impl Into<IpPoolRange> for model::IpPoolRange {
+    fn into(self) -> IpPoolRange {
+        IpPoolRange {
+            id: self.id,
+            time_created: self.time_created,
+            ip_pool_id: self.ip_pool_id,
+            first: self.first_address.ip(),
+            last: self.last_address.ip(),
+        }
+    }
+}
+
+/*
+ * FLOATING IPS
+ */
+
+/**
+ * Client view of a [`FloatingIp`]
+ */
+#[derive(ObjectIdentity, Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct FloatingIp {
+    #[serde(flatten)]
+    pub identity: IdentityMetadata,
+
+    /** id for the project containing this floating IP */
+    pub project_id: Uuid,
+
+    /** id for the pool from which the address was allocated */
+    pub ip_pool_id: Uuid,
+
+    /** the external address */
+    pub ip: IpAddr,
+
+    /** the Instance to which the address is attached, if any */
+    pub instance_id: Option<Uuid>,
+
+    /** the network interface to which the address is attached, if any */
+    pub network_interface_id: Option<Uuid>,
+}
+
+impl Into<FloatingIp> for model::FloatingIp {
+    fn into(self) -> FloatingIp {
+        FloatingIp {
+            identity: self.identity(),
+            project_id: self.project_id,
+            ip_pool_id: self.ip_pool_id,
+            ip: self.ip.ip(),
+            instance_id: self.instance_id,
+            network_interface_id: self.network_interface_id,
         }
     }
 }
@@ -171,7 +421,36 @@ impl Into<Sled> for model::Sled {
 }
 
 /*
- * BUILT-IN USERS
+ * SERIAL CONSOLES
+ */
+
+/**
+ * Recent output from an Instance's serial console
+ */
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct InstanceSerialConsoleData {
+    /** the most recent bytes written to the console, oldest first */
+    pub data: Vec<u8>,
+    /**
+     * total number of bytes the Instance has written to the console, of which
+     * `data` are the last
+     */
+    pub last_byte_offset: u64,
+}
+
+impl Into<InstanceSerialConsoleData>
+    for sled_agent_client::types::InstanceSerialConsoleData
+{
+    fn into(self) -> InstanceSerialConsoleData {
+        InstanceSerialConsoleData {
+            data: self.data,
+            last_byte_offset: self.last_byte_offset,
+        }
+    }
+}
+
+/*
+ * USERS
  */
 
 /**
@@ -187,6 +466,12 @@ pub struct User {
     pub identity: IdentityMetadata,
 }
 
+impl Into<User> for model::User {
+    fn into(self) -> User {
+        User { identity: self.identity() }
+    }
+}
+
 impl Into<User> for model::UserBuiltin {
     fn into(self) -> User {
         User { identity: self.identity() }
@@ -209,6 +494,82 @@ impl Into<SessionUser> for authn::Actor {
     }
 }
 
+/*
+ * API TOKENS
+ */
+
+/**
+ * Client view of an [`ApiToken`]
+ *
+ * The token itself is only available when it's created.  See
+ * [`ApiTokenWithSecret`].
+ */
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize, JsonSchema)]
+pub struct ApiToken {
+    pub id: Uuid,
+    pub description: String,
+    pub time_created: DateTime<Utc>,
+    /** when the token stops working, if ever */
+    pub time_expires: Option<DateTime<Utc>>,
+    pub scope: params::ApiTokenScope,
+}
+
+impl Into<ApiToken> for model::ApiToken {
+    fn into(self) -> ApiToken {
+        ApiToken {
+            id: self.id,
+            description: self.description,
+            time_created: self.time_created,
+            time_expires: self.time_expires,
+            scope: self.scope.0,
+        }
+    }
+}
+
+/**
+ * Client view of a newly-created [`ApiToken`], including the token itself
+ */
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct ApiTokenWithSecret {
+    #[serde(flatten)]
+    pub token: ApiToken,
+    /**
+     * the token to present as an RFC 6750 bearer token (this is the only time
+     * it's available)
+     */
+    pub secret: String,
+}
+
+/*
+ * SSH KEYS
+ */
+
+/**
+ * Client view of an [`SshKey`]
+ */
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize, JsonSchema)]
+pub struct SshKey {
+    pub id: Uuid,
+    pub description: String,
+    pub time_created: DateTime<Utc>,
+    /** the public key, in the one-line format of OpenSSH's `.pub` files */
+    pub public_key: String,
+    /** the key's SHA-256 fingerprint, as printed by `ssh-keygen -l` */
+    pub fingerprint: String,
+}
+
+impl Into<SshKey> for model::SshKey {
+    fn into(self) -> SshKey {
+        SshKey {
+            id: self.id,
+            description: self.description,
+            time_created: self.time_created,
+            public_key: self.public_key,
+            fingerprint: self.fingerprint,
+        }
+    }
+}
+
 /*
  * ROLES
  */
@@ -230,3 +591,275 @@ impl Into<Role> for model::RoleBuiltin {
         }
     }
 }
+
+/**
+ * Client view of a resource's policy, which says who has which roles on it
+ */
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize, JsonSchema)]
+pub struct Policy {
+    /** roles granted to particular users on the resource */
+    pub role_assignments: Vec<RoleAssignment>,
+}
+
+impl Into<Policy> for Vec<model::RoleAssignment> {
+    fn into(self) -> Policy {
+        Policy {
+            role_assignments: self.into_iter().map(|r| r.into()).collect(),
+        }
+    }
+}
+
+/**
+ * Client view of a [`RoleAssignment`]
+ */
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize, JsonSchema)]
+pub struct RoleAssignment {
+    /** the user to whom the role is granted */
+    pub user_id: Uuid,
+    /** the role, which must be one that applies to this kind of resource */
+    pub role_name: RoleName,
+}
+
+impl Into<RoleAssignment> for model::RoleAssignment {
+    fn into(self) -> RoleAssignment {
+        RoleAssignment {
+            user_id: self.user_id,
+            role_name: RoleName::new(&self.resource_type, &self.role_name),
+        }
+    }
+}
+
+/*
+ * AUDIT LOG
+ */
+
+/**
+ * Client view of an [`AuditLogEntry`]
+ */
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema, PartialEq)]
+pub struct AuditLogEntry {
+    pub id: Uuid,
+    /** when the request finished */
+    pub time_created: DateTime<Utc>,
+    /** the authenticated actor that made the request, if any */
+    pub actor_id: Option<Uuid>,
+    pub request_id: String,
+    pub http_method: String,
+    pub http_path: String,
+    /** the resource that the request acted on, if known */
+    pub resource_id: Option<Uuid>,
+    /** the HTTP status code of the response */
+    pub http_status: u16,
+}
+
+impl Into<AuditLogEntry> for model::AuditLogEntry {
+    fn into(self) -> AuditLogEntry {
+        AuditLogEntry {
+            id: self.id,
+            time_created: self.time_created,
+            actor_id: self.actor_id,
+            request_id: self.request_id,
+            http_method: self.http_method,
+            http_path: self.http_path,
+            resource_id: self.resource_id,
+            /* Only valid status codes are ever written to the database. */
+            http_status: u16::try_from(self.http_status).unwrap(),
+        }
+    }
+}
+
+/*
+ * OPERATIONS
+ */
+
+/**
+ * Client view of an [`Operation`]: a long-running request, like creating an
+ * Instance, that's carried out in the background
+ */
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema, PartialEq)]
+pub struct Operation {
+    pub id: Uuid,
+    /** what the operation does, like "instance-create" */
+    pub operation_type: String,
+    pub project_id: Uuid,
+    pub time_created: DateTime<Utc>,
+    pub status: OperationStatus,
+    /** the number of the operation's steps that have been completed */
+    pub steps_completed: u32,
+    /** why the operation failed, if it did */
+    pub error: Option<String>,
+    /** the resource that the operation acts on */
+    pub target: OperationTarget,
+}
+
+/**
+ * Status of an [`Operation`]
+ *
+ * An operation that fails is "unwinding" while the steps it completed are
+ * undone, and then "failed".
+ */
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, JsonSchema, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum OperationStatus {
+    Running,
+    Unwinding,
+    Succeeded,
+    Failed,
+}
+
+/**
+ * The resource that an [`Operation`] acts on
+ */
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema, PartialEq)]
+pub struct OperationTarget {
+    /** the type of resource, like "instance" */
+    pub resource_type: String,
+    /**
+     * the resource's id -- for an operation that creates the resource, this
+     * is not known until the resource has been created
+     */
+    pub id: Option<Uuid>,
+}
+
+impl Into<Operation> for model::OperationState {
+    fn into(self) -> Operation {
+        let failure = self.events.iter().find(|e| e.event_type == "failed");
+        let status = match self.saga_state.0 {
+            steno::SagaCachedState::Running => OperationStatus::Running,
+            steno::SagaCachedState::Unwinding => OperationStatus::Unwinding,
+            steno::SagaCachedState::Done if failure.is_some() => {
+                OperationStatus::Failed
+            }
+            steno::SagaCachedState::Done => OperationStatus::Succeeded,
+        };
+
+        /* Steps that have been undone no longer count as completed. */
+        let node_ids = |event_type: &str| {
+            self.events
+                .iter()
+                .filter(|e| e.event_type == event_type)
+                .map(|e| u32::from(e.node_id.0))
+                .collect::<BTreeSet<u32>>()
+        };
+        let steps_completed = node_ids("succeeded")
+            .difference(&node_ids("undo_finished"))
+            .count();
+
+        /*
+         * The error is reported the same way it would have been had the client
+         * waited for the request to finish.
+         */
+        let error = failure.map(|event| {
+            event
+                .data
+                .clone()
+                .and_then(|data| {
+                    serde_json::from_value::<steno::ActionError>(data).ok()
+                })
+                .and_then(|error| error.convert::<Error>().ok())
+                .map(|error| HttpError::from(error).external_message)
+                .unwrap_or_else(|| String::from("Internal Server Error"))
+        });
+
+        Operation {
+            id: self.operation.id,
+            operation_type: self.template_name,
+            project_id: self.operation.project_id,
+            time_created: self.operation.time_created,
+            status,
+            steps_completed: u32::try_from(steps_completed).unwrap(),
+            error,
+            target: OperationTarget {
+                resource_type: self.operation.target_type,
+                id: self.operation.target_id,
+            },
+        }
+    }
+}
+
+/*
+ * PROJECT EVENTS
+ */
+
+/**
+ * Client view of an event in a Project's event log
+ */
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema, PartialEq)]
+pub struct ProjectEvent {
+    pub id: Uuid,
+    /** the event's position in the Project's log, counting from 1 */
+    pub seq: u64,
+    pub time_created: DateTime<Utc>,
+    pub project_id: Uuid,
+    /** the type of resource that the event is about, like "instance" */
+    pub resource_type: String,
+    pub resource_id: Uuid,
+    /** what happened: "created", "state_changed", or "deleted" */
+    pub event_type: String,
+    /** the resource's new state, for "state_changed" events */
+    pub state: Option<String>,
+}
+
+impl Into<ProjectEvent> for model::ProjectEvent {
+    fn into(self) -> ProjectEvent {
+        ProjectEvent {
+            id: self.id,
+            /* Positions in the log start at 1. */
+            seq: u64::try_from(self.seq).unwrap(),
+            time_created: self.time_created,
+            project_id: self.project_id,
+            resource_type: self.resource_type,
+            resource_id: self.resource_id,
+            event_type: self.event_type,
+            state: self.state,
+        }
+    }
+}
+
+/**
+ * A batch of events from a Project's event log
+ */
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema, PartialEq)]
+pub struct ProjectEventsPage {
+    pub items: Vec<ProjectEvent>,
+    /**
+     * the position of the last event returned (or the position asked for, if
+     * there were none), to pass as `after` to get the events that follow
+     */
+    pub next_cursor: u64,
+}
+
+/**
+ * Client view of a URL to which a Project's events are delivered
+ *
+ * The secret with which deliveries are signed is never shown.
+ */
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema, PartialEq)]
+pub struct ProjectWebhook {
+    pub id: Uuid,
+    pub time_created: DateTime<Utc>,
+    pub project_id: Uuid,
+    pub url: String,
+    /** position of the last event delivered to the webhook */
+    pub delivered_seq: u64,
+    /**
+     * position of the last event that couldn't be delivered to the webhook
+     * (and was skipped), if any
+     */
+    pub last_failed_seq: Option<u64>,
+}
+
+impl Into<ProjectWebhook> for model::ProjectWebhook {
+    fn into(self) -> ProjectWebhook {
+        ProjectWebhook {
+            id: self.id,
+            time_created: self.time_created,
+            project_id: self.project_id,
+            url: self.url,
+            delivered_seq: u64::try_from(self.delivered_seq).unwrap(),
+            last_failed_seq: self
+                .last_failed_seq
+                .map(|seq| u64::try_from(seq).unwrap()),
+        }
+    }
+}
@@ -0,0 +1,425 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Resolving VPC firewall rules into the rules of each network interface
+//!
+//! A VPC firewall rule names the things it applies to (its targets) and the
+//! things it matches traffic to or from (its host filters) by VPC, subnet, or
+//! Instance name.  Sled agents know nothing about those names, so before Nexus
+//! sends them the rules, it works out which network interfaces each rule
+//! targets, and which blocks of addresses its host filters match:
+//!
+//! * A VPC target applies to every interface in the VPC (if it names the VPC
+//!   whose rule it is), and a VPC host filter matches the address blocks of
+//!   every subnet in the named VPC.
+//! * A subnet target applies to the interfaces in the named subnet of the
+//!   rule's VPC, and a subnet host filter matches that subnet's blocks.
+//! * An Instance target applies to the named Instance's interfaces in the
+//!   rule's VPC, and an Instance host filter matches their addresses.
+//! * An IP host filter matches just that address.
+//! * An internet gateway host filter matches nothing yet, since there are no
+//!   internet gateways.
+//!
+//! A name that doesn't resolve to anything is not an error: the rule just
+//! applies to (or matches) nothing on that account.  Disabled rules are left
+//! out entirely.
+
+use crate::db;
+use crate::db::identity::Resource;
+use omicron_common::api::external;
+use omicron_common::api::internal::sled_agent::{
+    FirewallRule, FirewallRuleHost, NetworkInterfaceFirewallRules,
+};
+use std::collections::{BTreeMap, BTreeSet};
+use std::net::IpAddr;
+use uuid::Uuid;
+
+/// A VPC, as far as resolving its firewall rules is concerned
+#[derive(Clone, Debug)]
+pub struct VpcNetwork {
+    /// name of the VPC whose rules are being resolved
+    pub name: external::Name,
+    /// the VPC's subnets
+    pub subnets: Vec<Subnet>,
+    /// the VPC's network interfaces
+    pub interfaces: Vec<Interface>,
+    /// subnets of the other VPCs in the same Project, indexed by VPC name
+    ///
+    /// Only the VPCs named by the rules' host filters need to be here.
+    pub other_vpcs: BTreeMap<external::Name, Vec<Subnet>>,
+}
+
+/// A VPC subnet whose address blocks a host filter may match
+#[derive(Clone, Debug)]
+pub struct Subnet {
+    pub id: Uuid,
+    pub name: external::Name,
+    pub ipv4_block: external::Ipv4Net,
+    pub ipv6_block: external::Ipv6Net,
+}
+
+impl From<&db::model::VpcSubnet> for Subnet {
+    fn from(subnet: &db::model::VpcSubnet) -> Self {
+        Subnet {
+            id: subnet.id(),
+            name: subnet.name().0.clone(),
+            ipv4_block: subnet.ipv4_block.0,
+            ipv6_block: subnet.ipv6_block.0,
+        }
+    }
+}
+
+/// A network interface to which rules may apply
+#[derive(Clone, Debug)]
+pub struct Interface {
+    pub id: Uuid,
+    /// name of the Instance to which the interface belongs
+    pub instance_name: external::Name,
+    /// the subnet in which the interface has its address
+    pub subnet_id: Uuid,
+    pub ip: IpAddr,
+}
+
+/// Returns the firewall rules that apply to each of the VPC's network
+/// interfaces, given the VPC's firewall rules
+///
+/// Every interface in `network` gets an entry (in the same order), even if no
+/// rules apply to it, so that sending the result to a sled agent replaces
+/// whatever rules it had for the interface before.
+pub fn resolve_rules(
+    network: &VpcNetwork,
+    rules: &[external::VpcFirewallRule],
+) -> Vec<NetworkInterfaceFirewallRules> {
+    let resolved = rules
+        .iter()
+        .filter(|rule| rule.status == external::VpcFirewallRuleStatus::Enabled)
+        .map(|rule| (rule, resolve_rule(network, rule)))
+        .collect::<Vec<_>>();
+    network
+        .interfaces
+        .iter()
+        .map(|interface| NetworkInterfaceFirewallRules {
+            interface_id: interface.id,
+            rules: resolved
+                .iter()
+                .filter(|(rule, _)| {
+                    rule.targets
+                        .iter()
+                        .any(|target| is_target(network, target, interface))
+                })
+                .map(|(_, resolved)| resolved.clone())
+                .collect(),
+        })
+        .collect()
+}
+
+/// Returns whether `target` names something to which `interface` belongs
+fn is_target(
+    network: &VpcNetwork,
+    target: &external::VpcFirewallRuleTarget,
+    interface: &Interface,
+) -> bool {
+    match target {
+        external::VpcFirewallRuleTarget::Vpc(name) => *name == network.name,
+        external::VpcFirewallRuleTarget::Subnet(name) => network
+            .subnets
+            .iter()
+            .any(|s| s.id == interface.subnet_id && s.name == *name),
+        external::VpcFirewallRuleTarget::Instance(name) => {
+            interface.instance_name == *name
+        }
+    }
+}
+
+fn resolve_rule(
+    network: &VpcNetwork,
+    rule: &external::VpcFirewallRule,
+) -> FirewallRule {
+    FirewallRule {
+        rule_id: rule.identity.id,
+        direction: rule.direction,
+        hosts: rule.filters.hosts.as_ref().map(|filters| {
+            filters
+                .iter()
+                .flat_map(|filter| resolve_host_filter(network, filter))
+                .collect::<BTreeSet<_>>()
+                .into_iter()
+                .collect()
+        }),
+        protocols: rule.filters.protocols.clone(),
+        ports: rule.filters.ports.clone(),
+        action: rule.action,
+        priority: rule.priority,
+    }
+}
+
+/// Returns the blocks of addresses that a host filter matches
+fn resolve_host_filter(
+    network: &VpcNetwork,
+    filter: &external::VpcFirewallRuleHostFilter,
+) -> Vec<FirewallRuleHost> {
+    match filter {
+        external::VpcFirewallRuleHostFilter::Vpc(name) => {
+            let subnets = if *name == network.name {
+                &network.subnets[..]
+            } else {
+                network.other_vpcs.get(name).map_or(&[][..], |s| &s[..])
+            };
+            subnets.iter().flat_map(subnet_hosts).collect()
+        }
+        external::VpcFirewallRuleHostFilter::Subnet(name) => network
+            .subnets
+            .iter()
+            .filter(|s| s.name == *name)
+            .flat_map(subnet_hosts)
+            .collect(),
+        external::VpcFirewallRuleHostFilter::Instance(name) => network
+            .interfaces
+            .iter()
+            .filter(|i| i.instance_name == *name)
+            .map(|i| address_host(i.ip))
+            .collect(),
+        external::VpcFirewallRuleHostFilter::Ip(ip) => vec![address_host(*ip)],
+        external::VpcFirewallRuleHostFilter::InternetGateway(_) => vec![],
+    }
+}
+
+fn subnet_hosts(subnet: &Subnet) -> Vec<FirewallRuleHost> {
+    vec![
+        FirewallRuleHost {
+            address: IpAddr::V4(subnet.ipv4_block.network()),
+            prefix_len: subnet.ipv4_block.prefix(),
+        },
+        FirewallRuleHost {
+            address: IpAddr::V6(subnet.ipv6_block.network()),
+            prefix_len: subnet.ipv6_block.prefix(),
+        },
+    ]
+}
+
+/// Returns the block containing only `address`
+fn address_host(address: IpAddr) -> FirewallRuleHost {
+    let prefix_len = match address {
+        IpAddr::V4(_) => 32,
+        IpAddr::V6(_) => 128,
+    };
+    FirewallRuleHost { address, prefix_len }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{resolve_rules, Interface, Subnet, VpcNetwork};
+    use chrono::Utc;
+    use omicron_common::api::external;
+    use omicron_common::api::internal::sled_agent::FirewallRuleHost;
+    use std::collections::BTreeMap;
+    use uuid::Uuid;
+
+    fn name(name: &str) -> external::Name {
+        name.parse().unwrap()
+    }
+
+    fn subnet(subnet_name: &str, ipv4_block: &str, ipv6_block: &str) -> Subnet {
+        Subnet {
+            id: Uuid::new_v4(),
+            name: name(subnet_name),
+            ipv4_block: external::Ipv4Net(ipv4_block.parse().unwrap()),
+            ipv6_block: external::Ipv6Net(ipv6_block.parse().unwrap()),
+        }
+    }
+
+    fn interface(instance_name: &str, subnet: &Subnet, ip: &str) -> Interface {
+        Interface {
+            id: Uuid::new_v4(),
+            instance_name: name(instance_name),
+            subnet_id: subnet.id,
+            ip: ip.parse().unwrap(),
+        }
+    }
+
+    fn host(address: &str, prefix_len: u8) -> FirewallRuleHost {
+        FirewallRuleHost { address: address.parse().unwrap(), prefix_len }
+    }
+
+    /// Makes an enabled rule that allows inbound traffic to `targets` from
+    /// `hosts` (if given)
+    fn rule(
+        targets: &[&str],
+        hosts: Option<&[&str]>,
+    ) -> external::VpcFirewallRule {
+        external::VpcFirewallRule {
+            identity: external::IdentityMetadata {
+                id: Uuid::new_v4(),
+                name: name("rule"),
+                description: String::new(),
+                time_created: Utc::now(),
+                time_modified: Utc::now(),
+            },
+            status: external::VpcFirewallRuleStatus::Enabled,
+            direction: external::VpcFirewallRuleDirection::Inbound,
+            targets: targets.iter().map(|t| t.parse().unwrap()).collect(),
+            filters: external::VpcFirewallRuleFilter {
+                hosts: hosts.map(|hosts| {
+                    hosts.iter().map(|h| h.parse().unwrap()).collect()
+                }),
+                protocols: Some(vec![external::VpcFirewallRuleProtocol::Tcp]),
+                ports: Some(vec!["22".parse().unwrap()]),
+            },
+            action: external::VpcFirewallRuleAction::Allow,
+            priority: external::VpcFirewallRulePriority(65534),
+            vpc_id: Uuid::new_v4(),
+        }
+    }
+
+    /// Returns a VPC "default" with two subnets ("web" and "db") and three
+    /// Instances: "www1" and "www2" in "web", and "pg" with an interface in
+    /// each subnet
+    fn network() -> VpcNetwork {
+        let web = subnet("web", "172.30.0.0/24", "fd00:1122:3344:100::/64");
+        let db = subnet("db", "172.30.1.0/24", "fd00:1122:3344:101::/64");
+        let interfaces = vec![
+            interface("www1", &web, "172.30.0.5"),
+            interface("www2", &web, "172.30.0.6"),
+            interface("pg", &web, "172.30.0.7"),
+            interface("pg", &db, "fd00:1122:3344:101::5"),
+        ];
+        let mut other_vpcs = BTreeMap::new();
+        other_vpcs.insert(
+            name("backend"),
+            vec![subnet("default", "10.0.0.0/16", "fd00:5566:7788:100::/64")],
+        );
+        VpcNetwork {
+            name: name("default"),
+            subnets: vec![web, db],
+            interfaces,
+            other_vpcs,
+        }
+    }
+
+    #[test]
+    fn test_resolve_targets() {
+        let network = network();
+        // Each case lists a rule's targets, and which of the network's
+        // interfaces (by index) the rule should apply to.
+        let cases: &[(&[&str], &[usize])] = &[
+            (&["vpc:default"], &[0, 1, 2, 3]),
+            (&["vpc:backend"], &[]),
+            (&["subnet:web"], &[0, 1, 2]),
+            (&["subnet:db"], &[3]),
+            (&["subnet:nonexistent"], &[]),
+            (&["instance:pg"], &[2, 3]),
+            (&["instance:www1", "subnet:db"], &[0, 3]),
+            (&["instance:nonexistent"], &[]),
+        ];
+        for (targets, expected) in cases {
+            let rule = rule(targets, None);
+            let resolved = resolve_rules(&network, &[rule.clone()]);
+            assert_eq!(resolved.len(), network.interfaces.len());
+            for (i, interface) in resolved.iter().enumerate() {
+                assert_eq!(interface.interface_id, network.interfaces[i].id);
+                let rule_ids = interface
+                    .rules
+                    .iter()
+                    .map(|r| r.rule_id)
+                    .collect::<Vec<_>>();
+                if expected.contains(&i) {
+                    assert_eq!(
+                        rule_ids,
+                        vec![rule.identity.id],
+                        "targets {:?} should apply to interface {}",
+                        targets,
+                        i
+                    );
+                } else {
+                    assert!(
+                        rule_ids.is_empty(),
+                        "targets {:?} should not apply to interface {}",
+                        targets,
+                        i
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_resolve_host_filters() {
+        let network = network();
+        // Each case lists a rule's host filters, and the blocks of addresses
+        // they should resolve to, in order.
+        let cases: &[(&[&str], &[(&str, u8)])] = &[
+            (
+                &["vpc:default"],
+                &[
+                    ("172.30.0.0", 24),
+                    ("172.30.1.0", 24),
+                    ("fd00:1122:3344:100::", 64),
+                    ("fd00:1122:3344:101::", 64),
+                ],
+            ),
+            (
+                &["vpc:backend"],
+                &[("10.0.0.0", 16), ("fd00:5566:7788:100::", 64)],
+            ),
+            (&["vpc:nonexistent"], &[]),
+            (
+                &["subnet:db"],
+                &[("172.30.1.0", 24), ("fd00:1122:3344:101::", 64)],
+            ),
+            (
+                &["instance:pg"],
+                &[("172.30.0.7", 32), ("fd00:1122:3344:101::5", 128)],
+            ),
+            (&["ip:192.0.2.1"], &[("192.0.2.1", 32)]),
+            (&["ip:2001:db8::1"], &[("2001:db8::1", 128)]),
+            // Overlapping filters don't produce the same block twice.
+            (
+                &["instance:www2", "ip:172.30.0.6", "ip:192.0.2.1"],
+                &[("172.30.0.6", 32), ("192.0.2.1", 32)],
+            ),
+            (&["inetgw:default"], &[]),
+        ];
+        for (filters, expected) in cases {
+            let resolved = resolve_rules(
+                &network,
+                &[rule(&["vpc:default"], Some(filters))],
+            );
+            let expected = expected
+                .iter()
+                .map(|(address, prefix_len)| host(address, *prefix_len))
+                .collect::<Vec<_>>();
+            for interface in &resolved {
+                assert_eq!(
+                    interface.rules[0].hosts.as_ref(),
+                    Some(&expected),
+                    "host filters {:?}",
+                    filters
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_resolve_rule_fields() {
+        let network = network();
+        let mut disabled = rule(&["vpc:default"], None);
+        disabled.status = external::VpcFirewallRuleStatus::Disabled;
+        let enabled = rule(&["vpc:default"], None);
+        let resolved = resolve_rules(&network, &[disabled, enabled.clone()]);
+
+        // Disabled rules aren't sent at all, and a rule without host filters
+        // matches any host.
+        for interface in &resolved {
+            assert_eq!(interface.rules.len(), 1);
+            let rule = &interface.rules[0];
+            assert_eq!(rule.rule_id, enabled.identity.id);
+            assert_eq!(rule.direction, enabled.direction);
+            assert_eq!(rule.hosts, None);
+            assert_eq!(rule.protocols, enabled.filters.protocols);
+            assert_eq!(rule.ports, enabled.filters.ports);
+            assert_eq!(rule.action, enabled.action);
+            assert_eq!(rule.priority, enabled.priority);
+        }
+    }
+}
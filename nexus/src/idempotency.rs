@@ -0,0 +1,329 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+ * Idempotency keys for external API create requests
+ *
+ * A client that retries a create request (say, after a timeout) risks creating
+ * the resource twice, or getting a confusing "already exists" error for a
+ * resource that its first attempt created.  To avoid this, clients can send an
+ * `Idempotency-Key` header with a unique value of their choosing.  The first
+ * request with a given key (from a given actor) claims the key in the
+ * `idempotency_key` table, along with a hash of the request.  Once the request
+ * succeeds, its response is stored with the key, and later requests with the
+ * same key get that response back instead of creating anything.  If the
+ * request fails, the key is released so that the request can be retried.
+ * The request runs in a task of its own (see [`create_and_record`]), so that
+ * its outcome is recorded even if the client hangs up before it's done.
+ *
+ * A request that's retried while the original is still in progress waits for
+ * the original to finish.  For saga-backed creates, the key records the id of
+ * the saga carrying out the original request, so that the retry finds that
+ * saga rather than starting a second one.
+ *
+ * Keys expire after [`IDEMPOTENCY_KEY_TTL`], after which they may be reused.
+ *
+ * TODO-robustness If Nexus crashes while a request is in progress, its key
+ * stays claimed, with no response, until it expires.
+ */
+
+use crate::db::DataStore;
+use dropshot::HttpError;
+use http::HeaderMap;
+use omicron_common::api::external::Error;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use slog::Logger;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+/** Header that clients use to provide an idempotency key */
+pub const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+
+/** How long a key (and the response stored with it) is remembered */
+pub const IDEMPOTENCY_KEY_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/**
+ * How long a retried request waits for the original request with the same key
+ * to finish before giving up
+ */
+pub const IDEMPOTENCY_WAIT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/** How often a retried request checks whether the original has finished */
+pub const IDEMPOTENCY_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/** Maximum length (in bytes) of an idempotency key */
+const MAX_KEY_LENGTH: usize = 255;
+
+/**
+ * The idempotency key provided with a request, along with the parts of the
+ * request that a retry must match
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct IdempotencyKey {
+    pub key: String,
+    pub http_method: String,
+    pub http_path: String,
+}
+
+impl IdempotencyKey {
+    /** Returns the idempotency key provided with `request`, if any */
+    pub fn from_request<B>(
+        request: &http::Request<B>,
+    ) -> Result<Option<IdempotencyKey>, HttpError> {
+        let key = parse_header(request.headers()).map_err(|message| {
+            HttpError::for_bad_request(
+                Some(String::from("InvalidIdempotencyKey")),
+                message,
+            )
+        })?;
+        Ok(key.map(|key| IdempotencyKey {
+            key,
+            http_method: request.method().to_string(),
+            http_path: request.uri().path().to_string(),
+        }))
+    }
+}
+
+fn parse_header(headers: &HeaderMap) -> Result<Option<String>, String> {
+    let mut values = headers.get_all(IDEMPOTENCY_KEY_HEADER).iter();
+    let value = match (values.next(), values.next()) {
+        (None, _) => return Ok(None),
+        (Some(value), None) => value,
+        (Some(_), Some(_)) => {
+            return Err(format!(
+                "header \"{}\" may only be specified once",
+                IDEMPOTENCY_KEY_HEADER
+            ))
+        }
+    };
+    let key = value
+        .to_str()
+        .map_err(|_| {
+            format!("header \"{}\" must be ASCII", IDEMPOTENCY_KEY_HEADER)
+        })?
+        .trim();
+    if key.is_empty() || key.len() > MAX_KEY_LENGTH {
+        return Err(format!(
+            "header \"{}\" must be between 1 and {} bytes long",
+            IDEMPOTENCY_KEY_HEADER, MAX_KEY_LENGTH
+        ));
+    }
+    Ok(Some(key.to_string()))
+}
+
+/**
+ * Returns the hash of a request's (parsed) body that's stored with its
+ * idempotency key, so that a retry with a different body can be rejected
+ */
+pub fn request_hash<B: Serialize>(body: &B) -> Result<String, Error> {
+    let body = serde_json::to_vec(body).map_err(|e| {
+        Error::internal_error(&format!("serializing request body: {}", e))
+    })?;
+    Ok(hex::encode(Sha256::digest(&body)))
+}
+
+/**
+ * Runs `create`, a request that has claimed the idempotency key `client_key`,
+ * to completion in a task of its own, returning its result
+ *
+ * The request's response is stored with the key if it succeeds, and the key is
+ * released if it fails.  Since `create` runs in its own task, that happens
+ * even if the caller stops waiting for it.
+ */
+pub async fn create_and_record<T, F>(
+    datastore: Arc<DataStore>,
+    log: Logger,
+    actor_id: Uuid,
+    client_key: String,
+    create: F,
+) -> Result<T, Error>
+where
+    T: Serialize + Send + 'static,
+    F: Future<Output = Result<T, Error>> + Send + 'static,
+{
+    let task = tokio::spawn(async move {
+        match create.await {
+            Ok(view) => {
+                let response_body =
+                    serde_json::to_value(&view).map_err(|e| {
+                        Error::internal_error(&format!(
+                            "serializing response: {}",
+                            e
+                        ))
+                    })?;
+                datastore
+                    .idempotency_key_complete(
+                        actor_id,
+                        client_key,
+                        response_body,
+                    )
+                    .await?;
+                Ok(view)
+            }
+            Err(error) => {
+                if let Err(release_error) = datastore
+                    .idempotency_key_release(actor_id, client_key.clone())
+                    .await
+                {
+                    warn!(log, "failed to release idempotency key";
+                        "key" => &client_key,
+                        "error" => ?release_error,
+                    );
+                }
+                Err(error)
+            }
+        }
+    });
+    task.await.map_err(|e| {
+        Error::internal_error(&format!("idempotent request failed: {}", e))
+    })?
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        create_and_record, parse_header, request_hash, IdempotencyKey,
+        IDEMPOTENCY_KEY_HEADER,
+    };
+    use crate::db;
+    use crate::db::datastore::datastore_test;
+    use chrono::{Duration, Utc};
+    use http::HeaderMap;
+    use http::HeaderValue;
+    use nexus_test_utils::db::test_setup_database;
+    use omicron_common::api::external::Error;
+    use omicron_test_utils::dev;
+    use std::sync::Arc;
+    use tokio::sync::oneshot;
+    use uuid::Uuid;
+
+    fn parse(values: &[&str]) -> Result<Option<String>, String> {
+        let mut headers = HeaderMap::new();
+        for value in values {
+            headers.append(
+                IDEMPOTENCY_KEY_HEADER,
+                HeaderValue::from_str(value).unwrap(),
+            );
+        }
+        parse_header(&headers)
+    }
+
+    #[test]
+    fn test_parse_header() {
+        assert_eq!(parse(&[]), Ok(None));
+        assert_eq!(parse(&["abc-123"]), Ok(Some(String::from("abc-123"))));
+        assert_eq!(parse(&[" abc "]), Ok(Some(String::from("abc"))));
+        assert_eq!(
+            parse(&[""]).unwrap_err(),
+            "header \"idempotency-key\" must be between 1 and 255 bytes long"
+        );
+        assert!(parse(&[&"a".repeat(255)]).is_ok());
+        assert!(parse(&[&"a".repeat(256)]).is_err());
+        assert_eq!(
+            parse(&["a", "b"]).unwrap_err(),
+            "header \"idempotency-key\" may only be specified once"
+        );
+    }
+
+    #[test]
+    fn test_request_hash() {
+        let hash = request_hash(&vec!["a", "b"]).unwrap();
+        assert_eq!(hash.len(), 64);
+        assert_eq!(hash, request_hash(&vec!["a", "b"]).unwrap());
+        assert_ne!(hash, request_hash(&vec!["a", "c"]).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_create_and_record_dropped() {
+        let logctx = dev::test_setup_log("test_create_and_record_dropped");
+        let mut db = test_setup_database(&logctx.log).await;
+        let (_, datastore) = datastore_test(&logctx, &db).await;
+        let actor_id = Uuid::new_v4();
+        let claim = |key: &str| {
+            db::model::IdempotencyKey::new(
+                actor_id,
+                &IdempotencyKey {
+                    key: String::from(key),
+                    http_method: String::from("POST"),
+                    http_path: String::from("/organizations"),
+                },
+                request_hash(&"body").unwrap(),
+            )
+        };
+        let expired_before = Utc::now() - Duration::days(1);
+
+        // Drop a request part way through.  The request still finishes, and
+        // its response is stored for retries to find.
+        assert!(datastore
+            .idempotency_key_claim(claim("key-1"), expired_before)
+            .await
+            .unwrap());
+        let (finish, finished) = oneshot::channel();
+        let request = create_and_record(
+            Arc::clone(&datastore),
+            logctx.log.clone(),
+            actor_id,
+            String::from("key-1"),
+            async move {
+                finished.await.unwrap();
+                Ok(String::from("created"))
+            },
+        );
+        tokio::time::timeout(std::time::Duration::from_millis(100), request)
+            .await
+            .expect_err("request finished early");
+        let fetch = |key: &'static str| {
+            let datastore = Arc::clone(&datastore);
+            async move {
+                datastore
+                    .idempotency_key_fetch(actor_id, String::from(key))
+                    .await
+                    .unwrap()
+            }
+        };
+        assert_eq!(fetch("key-1").await.unwrap().response_body, None);
+        finish.send(()).unwrap();
+        let deadline = Utc::now() + Duration::seconds(30);
+        while fetch("key-1").await.unwrap().response_body.is_none() {
+            assert!(Utc::now() < deadline, "response was never recorded");
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        }
+        assert_eq!(
+            fetch("key-1").await.unwrap().response_body,
+            Some(serde_json::json!("created"))
+        );
+
+        // The key of a request that fails is released, even if the request
+        // was dropped.
+        assert!(datastore
+            .idempotency_key_claim(claim("key-2"), expired_before)
+            .await
+            .unwrap());
+        let (finish, finished) = oneshot::channel();
+        let request = create_and_record::<String, _>(
+            Arc::clone(&datastore),
+            logctx.log.clone(),
+            actor_id,
+            String::from("key-2"),
+            async move {
+                finished.await.unwrap();
+                Err(Error::unavail("try again later"))
+            },
+        );
+        tokio::time::timeout(std::time::Duration::from_millis(100), request)
+            .await
+            .expect_err("request finished early");
+        finish.send(()).unwrap();
+        while fetch("key-2").await.is_some() {
+            assert!(Utc::now() < deadline, "key was never released");
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        }
+
+        db.cleanup().await.unwrap();
+        logctx.cleanup_successful();
+    }
+}
@@ -83,7 +83,7 @@ async fn cpapi_sled_agents_post(
     let si = sled_info.into_inner();
     let sled_id = &path.sled_id;
     let handler = async {
-        nexus.upsert_sled(*sled_id, si.sa_address).await?;
+        nexus.upsert_sled(*sled_id, si).await?;
         Ok(HttpResponseUpdatedNoContent())
     };
     apictx.internal_latencies.instrument_dropshot_handler(&rqctx, handler).await
@@ -18,6 +18,10 @@ use uuid::Uuid;
 pub struct SledAgentStartupInfo {
     /// the address of the sled agent's API endpoint
     pub sa_address: SocketAddr,
+    /// number of hardware threads that can be used by Instances
+    pub usable_hardware_threads: u32,
+    /// amount of physical memory that can be used by Instances
+    pub usable_physical_ram: ByteCount,
 }
 
 /// Sent by a sled agent on startup to Nexus to request further instruction
@@ -16,27 +16,40 @@
 /* Clippy's style lints are useful, but not worth running automatically. */
 #![allow(clippy::style)]
 
+mod audit;
 pub mod authn; // Public only for testing
 pub mod authz;
 mod config;
 mod context;
+mod crucible;
 pub mod db; // Public only for some documentation examples
 mod defaults;
+mod dns;
+mod events;
 pub mod external_api; // public for testing
+mod firewall;
+mod idempotency;
 pub mod internal_api; // public for testing
 mod nexus;
+mod placement;
 mod populate;
+mod routing;
 mod saga_interface;
 mod sagas;
+mod ssh_key;
+mod throttle;
 
 pub use config::Config;
 pub use context::ServerContext;
 pub use crucible_agent_client;
 use external_api::http_entrypoints::external_api;
+use external_api::versions::{ApiVersion, API_VERSIONS};
 use internal_api::http_entrypoints::internal_api;
 pub use nexus::Nexus;
 pub use nexus::TestInterfaces;
+pub use placement::PlacementPolicy;
 use slog::Logger;
+use std::io::Write;
 use std::sync::Arc;
 use uuid::Uuid;
 
@@ -49,16 +62,25 @@ extern crate diesel;
 
 /**
  * Run the OpenAPI generator for the external API, which emits the OpenAPI spec
- * to stdout.
+ * for the current version (listing all of the supported versions) to stdout.
  */
 pub fn run_openapi_external() -> Result<(), String> {
-    external_api()
-        .openapi("Oxide Region API", "0.0.1")
+    let mut spec = external_api()
+        .openapi("Oxide Region API", ApiVersion::current().as_str())
         .description("API for interacting with the Oxide control plane")
         .contact_url("https://oxide.computer")
         .contact_email("api@oxide.computer")
-        .write(&mut std::io::stdout())
-        .map_err(|e| e.to_string())
+        .json()
+        .map_err(|e| e.to_string())?;
+    spec["info"]["x-api-versions"] = API_VERSIONS
+        .iter()
+        .map(|version| serde_json::Value::from(version.as_str()))
+        .collect();
+    external_api::operation::document_accepted_responses(&mut spec);
+    let mut stdout = std::io::stdout();
+    serde_json::to_writer_pretty(&mut stdout, &spec)
+        .map_err(|e| e.to_string())?;
+    writeln!(stdout).map_err(|e| e.to_string())
 }
 
 pub fn run_openapi_internal() -> Result<(), String> {
@@ -119,6 +141,17 @@ impl Server {
         let http_server_external = http_server_starter_external.start();
         let http_server_internal = http_server_starter_internal.start();
 
+        /*
+         * Other services find Nexus through internal DNS.
+         * TODO-robustness If this fails, nothing retries it, so this Nexus
+         * won't be in the zone until it restarts.
+         */
+        if let Err(error) =
+            apictx.nexus.upsert_nexus(http_server_internal.local_addr()).await
+        {
+            warn!(log, "failed to register nexus"; "error" => ?error);
+        }
+
         Ok(Server { apictx, http_server_external, http_server_internal })
     }
 
@@ -7,28 +7,51 @@
  */
 
 use crate::authn;
+use crate::authn::external::password::{password_hash, password_verify};
+use crate::authn::external::token::{api_token_generate, api_token_hash};
 use crate::authz;
 use crate::config;
 use crate::context::OpContext;
 use crate::db;
+use crate::db::fixed_data::role_builtin::BUILTIN_ROLES;
 use crate::db::identity::{Asset, Resource};
 use crate::db::model::DatasetKind;
 use crate::db::model::Name;
 use crate::db::subnet_allocation::SubnetError;
 use crate::defaults;
+use crate::dns;
+use crate::events::{self, EventKind};
+use crate::external_api::etag::Preconditions;
+use crate::external_api::operation::MaybeAccepted;
 use crate::external_api::params;
-use crate::internal_api::params::{OximeterInfo, ZpoolPutRequest};
+use crate::external_api::views;
+use crate::firewall;
+use crate::idempotency;
+use crate::internal_api::params as internal_params;
+use crate::internal_api::params::{
+    OximeterInfo, SledAgentStartupInfo, ZpoolPutRequest,
+};
+use crate::placement::PlacementEngine;
+use crate::placement::PlacementRequest;
 use crate::populate::populate_start;
 use crate::populate::PopulateStatus;
+use crate::routing;
 use crate::saga_interface::SagaContext;
 use crate::sagas;
+use crate::ssh_key::SshPublicKey;
 use anyhow::anyhow;
 use anyhow::Context;
 use async_trait::async_trait;
+use chrono::DateTime;
+use chrono::Utc;
 use futures::future::ready;
+use futures::future::BoxFuture;
+use futures::FutureExt;
 use futures::StreamExt;
 use hex;
+use internal_dns_client::Client as InternalDnsClient;
 use omicron_common::api::external;
+use omicron_common::api::external::AffinityPolicy;
 use omicron_common::api::external::CreateResult;
 use omicron_common::api::external::DataPageParams;
 use omicron_common::api::external::DeleteResult;
@@ -36,11 +59,14 @@ use omicron_common::api::external::DiskState;
 use omicron_common::api::external::Error;
 use omicron_common::api::external::IdentityMetadataCreateParams;
 use omicron_common::api::external::InstanceState;
+use omicron_common::api::external::LabelSelector;
 use omicron_common::api::external::ListResult;
 use omicron_common::api::external::ListResultVec;
 use omicron_common::api::external::LookupResult;
+use omicron_common::api::external::LookupType;
 use omicron_common::api::external::PaginationOrder;
 use omicron_common::api::external::ResourceType;
+use omicron_common::api::external::RoleName;
 use omicron_common::api::external::RouteDestination;
 use omicron_common::api::external::RouteTarget;
 use omicron_common::api::external::RouterRouteCreateParams;
@@ -49,11 +75,14 @@ use omicron_common::api::external::RouterRouteUpdateParams;
 use omicron_common::api::external::UpdateResult;
 use omicron_common::api::external::VpcFirewallRuleUpdateParams;
 use omicron_common::api::external::VpcRouterKind;
+use omicron_common::api::internal::dns::{DnsRecord, DnsZoneConfig, DNS_ZONE};
 use omicron_common::api::internal::nexus;
 use omicron_common::api::internal::nexus::DiskRuntimeState;
+use omicron_common::api::internal::sled_agent::FirewallRulesEnsureBody;
 use omicron_common::api::internal::sled_agent::InstanceRuntimeStateMigrateParams;
 use omicron_common::api::internal::sled_agent::InstanceRuntimeStateRequested;
 use omicron_common::api::internal::sled_agent::InstanceStateRequested;
+use omicron_common::api::internal::sled_agent::NetworkInterfaceFirewallRules;
 use omicron_common::backoff;
 use omicron_common::bail_unless;
 use oximeter_client::Client as OximeterClient;
@@ -63,15 +92,22 @@ use oximeter_producer::register;
 use rand::{rngs::StdRng, RngCore, SeedableRng};
 use sled_agent_client::Client as SledAgentClient;
 use slog::Logger;
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::TryFrom;
 use std::convert::TryInto;
+use std::future::Future;
+use std::net::IpAddr;
 use std::net::SocketAddr;
 use std::num::NonZeroU32;
 use std::sync::Arc;
 use std::time::Duration;
+use std::time::Instant;
 use steno::SagaId;
 use steno::SagaResultOk;
 use steno::SagaTemplate;
 use steno::SagaType;
+use tokio::net::TcpStream;
+use tokio_tungstenite::WebSocketStream;
 use uuid::Uuid;
 
 // TODO: When referring to API types, we should try to include
@@ -92,6 +128,11 @@ pub trait TestInterfaces {
         id: &Uuid,
     ) -> Result<Arc<SledAgentClient>, Error>;
 
+    /**
+     * Returns the id of the sled on which an Instance was placed.
+     */
+    async fn instance_sled_id_by_id(&self, id: &Uuid) -> Result<Uuid, Error>;
+
     /**
      * Returns the SledAgentClient for a Disk from its id.
      */
@@ -106,6 +147,31 @@ pub trait TestInterfaces {
     ) -> CreateResult<db::model::ConsoleSession>;
 }
 
+/**
+ * Describes the resource that a saga run on behalf of the external API acts on
+ *
+ * Every such saga is recorded as an operation, which clients can use to follow
+ * its progress.
+ */
+struct OperationTarget {
+    project_id: Uuid,
+    resource_type: ResourceType,
+    id: OperationTargetId,
+}
+
+#[derive(Clone, Copy)]
+enum OperationTargetId {
+    /** the saga acts on an existing resource with this id */
+    Existing(Uuid),
+    /** the saga deletes the existing resource with this id */
+    Deleted(Uuid),
+    /**
+     * the saga creates the resource, and its id is the output of the saga
+     * node with this name
+     */
+    Created(&'static str),
+}
+
 /**
  * Manages an Oxide fleet -- the heart of the control plane
  */
@@ -139,6 +205,18 @@ pub struct Nexus {
 
     /** Client to the timeseries database. */
     timeseries_client: oximeter_db::Client,
+
+    /** Client to the internal DNS server */
+    dns_client: InternalDnsClient,
+
+    /** chooses the sled on which each new Instance runs */
+    placement: Arc<dyn PlacementEngine>,
+
+    /** wakes those waiting for events to be appended to a Project's log */
+    events_published: tokio::sync::watch::Sender<()>,
+
+    /** where webhooks may be delivered */
+    webhooks_config: config::WebhooksConfig,
 }
 
 /*
@@ -178,6 +256,10 @@ impl Nexus {
         ));
         let timeseries_client =
             oximeter_db::Client::new(config.timeseries_db.address, &log);
+        let dns_client = InternalDnsClient::new(
+            &format!("http://{}", config.internal_dns.address),
+            log.new(o!("component" => "InternalDnsClient")),
+        );
 
         /*
          * TODO-cleanup We may want a first-class subsystem for managing startup
@@ -205,6 +287,10 @@ impl Nexus {
             recovery_task: std::sync::Mutex::new(None),
             populate_status,
             timeseries_client,
+            dns_client,
+            placement: Arc::new(config.placement.policy),
+            events_published: tokio::sync::watch::channel(()).0,
+            webhooks_config: config.webhooks.clone(),
         };
 
         /* TODO-cleanup all the extra Arcs here seems wrong */
@@ -230,6 +316,11 @@ impl Nexus {
         );
 
         *nexus.recovery_task.lock().unwrap() = Some(recovery_task);
+        events::webhooks_deliver_start(
+            &nexus,
+            config.webhooks.clone(),
+            log.new(o!("component" => "WebhookDelivery")),
+        );
         nexus
     }
 
@@ -257,10 +348,18 @@ impl Nexus {
     pub async fn upsert_sled(
         &self,
         id: Uuid,
-        address: SocketAddr,
+        info: SledAgentStartupInfo,
     ) -> Result<(), Error> {
-        info!(self.log, "registered sled agent"; "sled_uuid" => id.to_string());
-        let sled = db::model::Sled::new(id, address);
+        info!(self.log, "registered sled agent";
+            "sled_uuid" => id.to_string(),
+            "usable_hardware_threads" => info.usable_hardware_threads,
+            "usable_physical_ram" => info.usable_physical_ram.to_bytes());
+        let sled = db::model::Sled::new(
+            id,
+            info.sa_address,
+            info.usable_hardware_threads,
+            info.usable_physical_ram,
+        );
         self.db_datastore.sled_upsert(sled).await?;
         Ok(())
     }
@@ -289,6 +388,9 @@ impl Nexus {
         info!(self.log, "upserting dataset"; "zpool_id" => zpool_id.to_string(), "dataset_id" => id.to_string(), "address" => address.to_string());
         let dataset = db::model::Dataset::new(id, zpool_id, address, kind);
         self.db_datastore.dataset_upsert(dataset).await?;
+        if kind.0 != internal_params::DatasetKind::Crucible {
+            self.dns_zone_push().await;
+        }
         Ok(())
     }
 
@@ -310,6 +412,7 @@ impl Nexus {
             "collector_id" => ?oximeter_info.collector_id,
             "address" => oximeter_info.address,
         );
+        self.dns_zone_push().await;
 
         // Regardless, notify the collector of any assigned metric producers. This should be empty
         // if this Oximeter collector is registering for the first time, but may not be if the
@@ -359,6 +462,119 @@ impl Nexus {
         Ok(())
     }
 
+    /**
+     * Records that this Nexus is serving its internal API at `address`, so
+     * that other services can find it through internal DNS
+     */
+    pub async fn upsert_nexus(&self, address: SocketAddr) -> Result<(), Error> {
+        let info = db::model::NexusInfo::new(self.id, address);
+        self.db_datastore.nexus_upsert(&info).await?;
+        info!(self.log, "registered nexus"; "address" => address.to_string());
+        self.dns_zone_push().await;
+        Ok(())
+    }
+
+    /**
+     * Sends the internal DNS server a new generation of the zone, built from
+     * the current Instances, network interfaces, and control plane services
+     *
+     * This must be done whenever any of the names in the zone or their
+     * addresses change: when an Instance or network interface is created or
+     * deleted, when a Project is renamed or a VPC's DNS name changes, and when
+     * a service registers.
+     * See [`dns`] for what the zone contains.
+     *
+     * TODO-robustness Failures are logged but otherwise ignored, so a DNS
+     * server that misses an update serves stale records until the next one.
+     * Something should periodically make sure the server has the latest
+     * generation.
+     */
+    pub async fn dns_zone_push(&self) {
+        let result = async {
+            /*
+             * Allocate the generation before reading the records, so that of
+             * two concurrent pushes, the one with the newer generation also
+             * has the newer records.
+             */
+            let generation =
+                self.db_datastore.dns_zone_generation_next(DNS_ZONE).await?;
+            let records = self.dns_zone_records().await?;
+            let zone = DnsZoneConfig { generation: generation.0, records };
+            self.dns_client
+                .zone_put(&zone.into())
+                .await
+                .map_err(Error::from)?;
+            Ok::<(), Error>(())
+        }
+        .await;
+        if let Err(error) = result {
+            warn!(self.log, "failed to update internal DNS zone";
+                "error" => ?error);
+        }
+    }
+
+    /**
+     * Builds the records of the internal DNS zone from the database
+     */
+    async fn dns_zone_records(
+        &self,
+    ) -> Result<BTreeMap<String, Vec<DnsRecord>>, Error> {
+        let instances = self
+            .db_datastore
+            .instance_list_all_dns_hosts()
+            .await?
+            .into_iter()
+            .map(|(hostname, vpc_dns_name, project_name, ip)| {
+                dns::InstanceHost {
+                    hostname,
+                    vpc_dns_name: vpc_dns_name.0,
+                    project_name: project_name.0,
+                    ip: ip.ip(),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let mut services = Vec::new();
+        for info in self.db_datastore.nexus_list_all().await? {
+            services.push(dns::ServiceHost {
+                kind: dns::ServiceKind::Nexus,
+                id: info.id,
+                address: info.address(),
+            });
+        }
+        for info in self.db_datastore.oximeter_list_all().await? {
+            services.push(dns::ServiceHost {
+                kind: dns::ServiceKind::Oximeter,
+                id: info.id,
+                address: info.address(),
+            });
+        }
+        for (dataset_kind, kind) in [
+            (
+                internal_params::DatasetKind::Cockroach,
+                dns::ServiceKind::Cockroach,
+            ),
+            (
+                internal_params::DatasetKind::Clickhouse,
+                dns::ServiceKind::Clickhouse,
+            ),
+        ] {
+            for dataset in self
+                .db_datastore
+                .dataset_list_all_by_kind(dataset_kind.into())
+                .await?
+            {
+                services.push(dns::ServiceHost {
+                    kind,
+                    id: dataset.id(),
+                    address: dataset.address(),
+                });
+            }
+        }
+
+        Ok(dns::zone_records(&instances, &services))
+    }
+
     /// Register as a metric producer with the oximeter metric collection server.
     pub async fn register_as_producer(&self, address: SocketAddr) {
         let producer_endpoint = nexus::ProducerEndpoint {
@@ -426,6 +642,8 @@ impl Nexus {
      */
     async fn execute_saga<P, S>(
         self: &Arc<Self>,
+        opctx: &OpContext,
+        target: OperationTarget,
         saga_template: Arc<SagaTemplate<S>>,
         template_name: &str,
         saga_params: Arc<P>,
@@ -440,8 +658,104 @@ impl Nexus {
          * SagaParamsType must already impl Serialize.
          */
         P: serde::Serialize,
+    {
+        let (_, completion) = self
+            .saga_start(
+                opctx,
+                target,
+                saga_template,
+                template_name,
+                saga_params,
+            )
+            .await?;
+        completion.await
+    }
+
+    /**
+     * Like [`Nexus::execute_saga()`], but if `respond_async` is set, this
+     * returns the operation carrying out the saga as soon as the saga has
+     * started rather than waiting for it to finish
+     */
+    async fn execute_saga_or_accept<P, S>(
+        self: &Arc<Self>,
+        opctx: &OpContext,
+        target: OperationTarget,
+        saga_template: Arc<SagaTemplate<S>>,
+        template_name: &str,
+        saga_params: Arc<P>,
+        respond_async: bool,
+    ) -> Result<MaybeAccepted<SagaResultOk>, Error>
+    where
+        S: SagaType<
+            ExecContextType = Arc<SagaContext>,
+            SagaParamsType = Arc<P>,
+        >,
+        P: serde::Serialize,
+    {
+        let project_id = target.project_id;
+        let (saga_id, completion) = self
+            .saga_start(
+                opctx,
+                target,
+                saga_template,
+                template_name,
+                saga_params,
+            )
+            .await?;
+        if !respond_async {
+            return Ok(MaybeAccepted::Done(completion.await?));
+        }
+
+        /*
+         * The saga runs to completion whether or not anybody waits for it, but
+         * the bookkeeping at the end of `completion` only happens if it's
+         * polled.
+         */
+        tokio::spawn(completion);
+        let operation = self
+            .db_datastore
+            .operation_fetch_no_auth(Some(project_id), saga_id)
+            .await?;
+        Ok(MaybeAccepted::Accepted(operation.into()))
+    }
+
+    /**
+     * Creates and starts a saga on behalf of an external API request,
+     * returning its id and a future that completes when the saga does
+     *
+     * The saga is recorded as an operation acting on `target`.
+     */
+    async fn saga_start<P, S>(
+        self: &Arc<Self>,
+        opctx: &OpContext,
+        target: OperationTarget,
+        saga_template: Arc<SagaTemplate<S>>,
+        template_name: &str,
+        saga_params: Arc<P>,
+    ) -> Result<(Uuid, BoxFuture<'static, Result<SagaResultOk, Error>>), Error>
+    where
+        S: SagaType<
+            ExecContextType = Arc<SagaContext>,
+            SagaParamsType = Arc<P>,
+        >,
+        P: serde::Serialize,
     {
         let saga_id = SagaId(Uuid::new_v4());
+
+        /*
+         * If this saga is carrying out a request made with an idempotency key,
+         * record the saga with the key before creating it.  That way, a retry
+         * of the request that arrives while the saga is still running can
+         * tell what it's waiting on.
+         */
+        if let (Some(key), Some(authn::Actor(actor_id))) =
+            (opctx.idempotency_key(), opctx.authn.actor())
+        {
+            self.db_datastore
+                .idempotency_key_set_saga(*actor_id, key, saga_id.0)
+                .await?;
+        }
+
         let saga_logger =
             self.log.new(o!("template_name" => template_name.to_owned()));
         let saga_context = Arc::new(Arc::new(SagaContext::new(
@@ -469,19 +783,196 @@ impl Nexus {
                 Error::internal_error(&format!("{:#}", error))
             })?;
 
+        let target_id = match target.id {
+            OperationTargetId::Existing(id)
+            | OperationTargetId::Deleted(id) => Some(id),
+            OperationTargetId::Created(_) => None,
+        };
+        self.db_datastore
+            .operation_create(db::model::Operation::new(
+                saga_id.0,
+                target.project_id,
+                target.resource_type,
+                target_id,
+            ))
+            .await?;
+
         self.sec_client
             .saga_start(saga_id)
             .await
             .context("starting saga")
             .map_err(|error| Error::internal_error(&format!("{:#}", error)))?;
 
-        let result = future.await;
-        result.kind.map_err(|saga_error| {
-            saga_error.error_source.convert::<Error>().unwrap_or_else(|e| {
-                /* TODO-error more context would be useful */
-                Error::InternalError { internal_message: e.to_string() }
-            })
-        })
+        let nexus = Arc::clone(self);
+        let log = self.log.new(o!("saga_id" => saga_id.to_string()));
+        let completion = async move {
+            let result = future.await.kind.map_err(|saga_error| {
+                saga_error.error_source.convert::<Error>().unwrap_or_else(|e| {
+                    /* TODO-error more context would be useful */
+                    Error::InternalError { internal_message: e.to_string() }
+                })
+            });
+
+            /*
+             * TODO-robustness If Nexus crashes before getting here, the
+             * operation never learns the id of the resource it created, and no
+             * event is published for the resource's creation or deletion, even
+             * if the saga is recovered and finishes.
+             */
+            match (&result, target.id) {
+                (Ok(outputs), OperationTargetId::Created(output_name)) => {
+                    let target_id = outputs.lookup_output::<Uuid>(output_name);
+                    let recorded = match &target_id {
+                        Ok(target_id) => nexus
+                            .db_datastore
+                            .operation_set_target(saga_id.0, *target_id)
+                            .await
+                            .map_err(|e| e.to_string()),
+                        Err(e) => Err(e.to_string()),
+                    };
+                    if let Err(error) = recorded {
+                        warn!(log, "failed to record operation's target";
+                            "error" => error);
+                    }
+                    if let Ok(target_id) = target_id {
+                        nexus
+                            .project_event_publish(
+                                target.project_id,
+                                target.resource_type,
+                                target_id,
+                                EventKind::Created,
+                            )
+                            .await;
+                    }
+                }
+                (Ok(_), OperationTargetId::Deleted(target_id)) => {
+                    nexus
+                        .project_event_publish(
+                            target.project_id,
+                            target.resource_type,
+                            target_id,
+                            EventKind::Deleted,
+                        )
+                        .await;
+                }
+                _ => (),
+            }
+            result
+        };
+        Ok((saga_id.0, completion.boxed()))
+    }
+
+    /**
+     * Runs `create`, the body of a request to create some resource, honoring
+     * the idempotency key that the client supplied with the request (if any)
+     *
+     * `body` is the request's body, which is hashed so that a retry with a
+     * different body can be rejected.  `create` is called with this Nexus,
+     * `opctx`, and `body` to produce the view that's sent back to the client.
+     * If an earlier request with the same key already succeeded, `create` isn't
+     * called and the view returned by the earlier request is returned instead.
+     * Otherwise, the request runs to completion even if this future is
+     * dropped.  See [`crate::idempotency`].
+     */
+    pub async fn idempotent_create<B, T, C, F>(
+        self: &Arc<Self>,
+        opctx: OpContext,
+        body: B,
+        create: C,
+    ) -> Result<T, Error>
+    where
+        B: serde::Serialize,
+        T: serde::Serialize + serde::de::DeserializeOwned + Send + 'static,
+        C: FnOnce(Arc<Nexus>, OpContext, B) -> F,
+        F: Future<Output = Result<T, Error>> + Send + 'static,
+    {
+        let (key, actor_id) =
+            match (opctx.idempotency_key(), opctx.authn.actor()) {
+                (Some(key), Some(authn::Actor(actor_id))) => {
+                    (key.clone(), *actor_id)
+                }
+                _ => return create(Arc::clone(self), opctx, body).await,
+            };
+
+        let claim = db::model::IdempotencyKey::new(
+            actor_id,
+            &key,
+            idempotency::request_hash(&body)?,
+        );
+        let deadline = Instant::now() + idempotency::IDEMPOTENCY_WAIT_TIMEOUT;
+        loop {
+            let expired_before = Utc::now()
+                - chrono::Duration::from_std(idempotency::IDEMPOTENCY_KEY_TTL)
+                    .unwrap();
+            if self
+                .db_datastore
+                .idempotency_key_claim(claim.clone(), expired_before)
+                .await?
+            {
+                break;
+            }
+
+            /*
+             * Someone else has claimed this key.  (If they've released it in
+             * the meantime, try again to claim it.)
+             */
+            let existing = match self
+                .db_datastore
+                .idempotency_key_fetch(actor_id, key.key.clone())
+                .await?
+            {
+                Some(existing) => existing,
+                None => continue,
+            };
+            if !existing.same_request(&claim) {
+                return Err(Error::InvalidRequest {
+                    message: format!(
+                        "idempotency key \"{}\" was already used for a \
+                        different request",
+                        key.key
+                    ),
+                });
+            }
+            if let Some(response_body) = existing.response_body {
+                return serde_json::from_value(response_body).map_err(|e| {
+                    Error::internal_error(&format!(
+                        "deserializing stored response: {}",
+                        e
+                    ))
+                });
+            }
+
+            /*
+             * The original request is still in progress.  Wait for it to
+             * finish.
+             */
+            if Instant::now() >= deadline {
+                let message = match existing.saga_id {
+                    Some(saga_id) => format!(
+                        "request with idempotency key \"{}\" is still in \
+                        progress (saga {})",
+                        key.key, saga_id
+                    ),
+                    None => format!(
+                        "request with idempotency key \"{}\" is still in \
+                        progress",
+                        key.key
+                    ),
+                };
+                return Err(Error::unavail(&message));
+            }
+            tokio::time::sleep(idempotency::IDEMPOTENCY_POLL_INTERVAL).await;
+        }
+
+        let log = opctx.log.clone();
+        idempotency::create_and_record(
+            Arc::clone(&self.db_datastore),
+            log,
+            actor_id,
+            key.key,
+            create(Arc::clone(self), opctx, body),
+        )
+        .await
     }
 
     /*
@@ -494,7 +985,10 @@ impl Nexus {
         new_organization: &params::OrganizationCreate,
     ) -> CreateResult<db::model::Organization> {
         let db_org = db::model::Organization::new(new_organization.clone());
-        self.db_datastore.organization_create(opctx, db_org).await
+        let db_org =
+            self.db_datastore.organization_create(opctx, db_org).await?;
+        opctx.audit_resource(db_org.id());
+        Ok(db_org)
     }
 
     pub async fn organization_fetch(
@@ -509,24 +1003,34 @@ impl Nexus {
         &self,
         opctx: &OpContext,
         pagparams: &DataPageParams<'_, Name>,
+        label_selector: &LabelSelector,
     ) -> ListResultVec<db::model::Organization> {
-        self.db_datastore.organizations_list_by_name(opctx, pagparams).await
+        self.db_datastore
+            .organizations_list_by_name(opctx, pagparams, label_selector)
+            .await
     }
 
     pub async fn organizations_list_by_id(
         &self,
         opctx: &OpContext,
         pagparams: &DataPageParams<'_, Uuid>,
+        label_selector: &LabelSelector,
     ) -> ListResultVec<db::model::Organization> {
-        self.db_datastore.organizations_list_by_id(opctx, pagparams).await
+        self.db_datastore
+            .organizations_list_by_id(opctx, pagparams, label_selector)
+            .await
     }
 
     pub async fn organization_delete(
         &self,
         opctx: &OpContext,
         name: &Name,
+        preconditions: &Preconditions,
     ) -> DeleteResult {
-        self.db_datastore.organization_delete(opctx, name).await
+        let authz_org =
+            self.db_datastore.organization_lookup_by_path(name).await?;
+        opctx.audit_resource(authz_org.id());
+        self.db_datastore.organization_delete(opctx, name, preconditions).await
     }
 
     pub async fn organization_update(
@@ -534,9 +1038,18 @@ impl Nexus {
         opctx: &OpContext,
         name: &Name,
         new_params: &params::OrganizationUpdate,
+        preconditions: &Preconditions,
     ) -> UpdateResult<db::model::Organization> {
+        let authz_org =
+            self.db_datastore.organization_lookup_by_path(name).await?;
+        opctx.audit_resource(authz_org.id());
         self.db_datastore
-            .organization_update(opctx, name, new_params.clone().into())
+            .organization_update(
+                opctx,
+                name,
+                new_params.clone().into(),
+                preconditions,
+            )
             .await
     }
 
@@ -559,6 +1072,7 @@ impl Nexus {
         let db_project = db::model::Project::new(org.id(), new_project.clone());
         let db_project =
             self.db_datastore.project_create(opctx, &org, db_project).await?;
+        opctx.audit_resource(db_project.id());
 
         // TODO: We probably want to have "project creation" and "default VPC
         // creation" co-located within a saga for atomicity.
@@ -574,6 +1088,7 @@ impl Nexus {
                     identity: IdentityMetadataCreateParams {
                         name: "default".parse().unwrap(),
                         description: "Default VPC".to_string(),
+                        labels: Default::default(),
                     },
                     ipv6_prefix: Some(defaults::random_vpc_ipv6_prefix()?),
                     // TODO-robustness this will need to be None if we decide to
@@ -609,13 +1124,14 @@ impl Nexus {
         opctx: &OpContext,
         organization_name: &Name,
         pagparams: &DataPageParams<'_, Name>,
+        label_selector: &LabelSelector,
     ) -> ListResultVec<db::model::Project> {
         let authz_org = self
             .db_datastore
             .organization_lookup_by_path(organization_name)
             .await?;
         self.db_datastore
-            .projects_list_by_name(opctx, &authz_org, pagparams)
+            .projects_list_by_name(opctx, &authz_org, pagparams, label_selector)
             .await
     }
 
@@ -624,13 +1140,14 @@ impl Nexus {
         opctx: &OpContext,
         organization_name: &Name,
         pagparams: &DataPageParams<'_, Uuid>,
+        label_selector: &LabelSelector,
     ) -> ListResultVec<db::model::Project> {
         let authz_org = self
             .db_datastore
             .organization_lookup_by_path(organization_name)
             .await?;
         self.db_datastore
-            .projects_list_by_id(opctx, &authz_org, pagparams)
+            .projects_list_by_id(opctx, &authz_org, pagparams, label_selector)
             .await
     }
 
@@ -639,12 +1156,16 @@ impl Nexus {
         opctx: &OpContext,
         organization_name: &Name,
         project_name: &Name,
+        preconditions: &Preconditions,
     ) -> DeleteResult {
         let authz_project = self
             .db_datastore
             .project_lookup_by_path(organization_name, project_name)
             .await?;
-        self.db_datastore.project_delete(opctx, &authz_project).await
+        opctx.audit_resource(authz_project.id());
+        self.db_datastore
+            .project_delete(opctx, &authz_project, preconditions)
+            .await
     }
 
     pub async fn project_update(
@@ -653,945 +1174,2448 @@ impl Nexus {
         organization_name: &Name,
         project_name: &Name,
         new_params: &params::ProjectUpdate,
+        preconditions: &Preconditions,
     ) -> UpdateResult<db::model::Project> {
         let authz_project = self
             .db_datastore
             .project_lookup_by_path(organization_name, project_name)
             .await?;
-        self.db_datastore
-            .project_update(opctx, &authz_project, new_params.clone().into())
-            .await
+        opctx.audit_resource(authz_project.id());
+        let project = self
+            .db_datastore
+            .project_update(
+                opctx,
+                &authz_project,
+                new_params.clone().into(),
+                preconditions,
+            )
+            .await?;
+        if new_params.identity.name.is_some() {
+            self.dns_zone_push().await;
+        }
+        Ok(project)
     }
 
     /*
-     * Disks
+     * Quotas
      */
 
-    pub async fn project_list_disks(
+    pub async fn organization_quota_fetch(
         &self,
         opctx: &OpContext,
         organization_name: &Name,
-        project_name: &Name,
-        pagparams: &DataPageParams<'_, Name>,
-    ) -> ListResultVec<db::model::Disk> {
-        let authz_project = self
+    ) -> LookupResult<(db::model::Quota, db::model::ResourceUsage)> {
+        let authz_org = self
             .db_datastore
-            .project_lookup_by_path(organization_name, project_name)
+            .organization_lookup_by_path(organization_name)
             .await?;
-        self.db_datastore
-            .project_list_disks(opctx, &authz_project, pagparams)
-            .await
+        self.db_datastore.organization_quota_fetch(opctx, &authz_org).await
     }
 
-    pub async fn project_create_disk(
-        self: &Arc<Self>,
+    pub async fn organization_quota_update(
+        &self,
         opctx: &OpContext,
         organization_name: &Name,
-        project_name: &Name,
-        params: &params::DiskCreate,
-    ) -> CreateResult<db::model::Disk> {
-        let authz_project = self
+        params: &params::QuotaUpdate,
+    ) -> UpdateResult<(db::model::Quota, db::model::ResourceUsage)> {
+        let authz_org = self
             .db_datastore
-            .project_lookup_by_path(organization_name, project_name)
+            .organization_lookup_by_path(organization_name)
             .await?;
-
-        // TODO-security This may need to be revisited once we implement authz
-        // checks for saga actions.  Even then, though, it still will be correct
-        // (if possibly redundant) to check this here.
-        opctx.authorize(authz::Action::CreateChild, &authz_project).await?;
-
-        /*
-         * Until we implement snapshots, do not allow disks to be created with a
-         * snapshot id.
-         */
-        if params.snapshot_id.is_some() {
-            return Err(Error::InvalidValue {
-                label: String::from("snapshot_id"),
-                message: String::from("snapshots are not yet supported"),
-            });
-        }
-
-        let saga_params = Arc::new(sagas::ParamsDiskCreate {
-            serialized_authn: authn::saga::Serialized::for_opctx(opctx),
-            project_id: authz_project.id(),
-            create_params: params.clone(),
-        });
-        let saga_outputs = self
-            .execute_saga(
-                Arc::clone(&sagas::SAGA_DISK_CREATE_TEMPLATE),
-                sagas::SAGA_DISK_CREATE_NAME,
-                saga_params,
-            )
+        opctx.audit_resource(authz_org.id());
+        self.db_datastore
+            .organization_quota_update(opctx, &authz_org, params.clone())
             .await?;
-        let disk_created = saga_outputs
-            .lookup_output::<db::model::Disk>("created_disk")
-            .map_err(|e| Error::InternalError {
-                internal_message: e.to_string(),
-            })?;
-        Ok(disk_created)
+        self.db_datastore.organization_quota_fetch(opctx, &authz_org).await
     }
 
-    pub async fn disk_fetch(
+    pub async fn project_quota_fetch(
         &self,
         opctx: &OpContext,
         organization_name: &Name,
         project_name: &Name,
-        disk_name: &Name,
-    ) -> LookupResult<db::model::Disk> {
+    ) -> LookupResult<(db::model::Quota, db::model::ResourceUsage)> {
         let authz_project = self
             .db_datastore
             .project_lookup_by_path(organization_name, project_name)
             .await?;
-        Ok(self
-            .db_datastore
-            .disk_fetch(opctx, &authz_project, disk_name)
-            .await?
-            .1)
+        self.db_datastore.project_quota_fetch(opctx, &authz_project).await
     }
 
-    pub async fn project_delete_disk(
-        self: &Arc<Self>,
+    pub async fn project_quota_update(
+        &self,
         opctx: &OpContext,
         organization_name: &Name,
         project_name: &Name,
-        disk_name: &Name,
-    ) -> DeleteResult {
-        let authz_disk = self
+        params: &params::QuotaUpdate,
+    ) -> UpdateResult<(db::model::Quota, db::model::ResourceUsage)> {
+        let authz_org = self
             .db_datastore
-            .disk_lookup_by_path(organization_name, project_name, disk_name)
+            .organization_lookup_by_path(organization_name)
+            .await?;
+        let (authz_project, _) = self
+            .db_datastore
+            .project_fetch(opctx, &authz_org, project_name)
+            .await?;
+        opctx.audit_resource(authz_project.id());
+        self.db_datastore
+            .project_quota_update(
+                opctx,
+                &authz_org,
+                &authz_project,
+                params.clone(),
+            )
             .await?;
-
-        // TODO: We need to sort out the authorization checks.
-        //
-        // Normally, this would be coupled alongside access to the
-        // datastore, but now that disk deletion exists within a Saga,
-        // this would require OpContext to be serialized (which is
-        // not trivial).
-        opctx.authorize(authz::Action::Delete, &authz_disk).await?;
-
-        let saga_params =
-            Arc::new(sagas::ParamsDiskDelete { disk_id: authz_disk.id() });
-        self.execute_saga(
-            Arc::clone(&sagas::SAGA_DISK_DELETE_TEMPLATE),
-            sagas::SAGA_DISK_DELETE_NAME,
-            saga_params,
-        )
-        .await?;
-
-        Ok(())
-    }
-
-    /*
-     * Instances
-     */
+        self.db_datastore.project_quota_fetch(opctx, &authz_project).await
+    }
 
     /*
-     * TODO-design This interface should not exist.  See
-     * SagaContext::alloc_server().
+     * Policies
      */
-    pub async fn sled_allocate(&self) -> Result<Uuid, Error> {
-        // TODO: replace this with a real allocation policy.
-        //
-        // This implementation always assigns the first sled (by ID order).
-        let pagparams = DataPageParams {
-            marker: None,
-            direction: dropshot::PaginationOrder::Ascending,
-            limit: std::num::NonZeroU32::new(1).unwrap(),
-        };
-        let sleds = self.db_datastore.sled_list(&pagparams).await?;
 
-        sleds
-            .first()
-            .ok_or_else(|| Error::ServiceUnavailable {
-                internal_message: String::from(
-                    "no sleds available for new Instance",
-                ),
-            })
-            .map(|s| s.id())
+    pub async fn organization_fetch_policy(
+        &self,
+        opctx: &OpContext,
+        organization_name: &Name,
+    ) -> LookupResult<Vec<db::model::RoleAssignment>> {
+        let authz_org = self
+            .db_datastore
+            .organization_lookup_by_path(organization_name)
+            .await?;
+        self.db_datastore.role_assignments_fetch(opctx, &authz_org).await
     }
 
-    pub async fn project_list_instances(
+    pub async fn organization_update_policy(
         &self,
         opctx: &OpContext,
         organization_name: &Name,
-        project_name: &Name,
-        pagparams: &DataPageParams<'_, Name>,
-    ) -> ListResultVec<db::model::Instance> {
-        let authz_project = self
+        policy: &views::Policy,
+    ) -> UpdateResult<Vec<db::model::RoleAssignment>> {
+        let authz_org = self
             .db_datastore
-            .project_lookup_by_path(organization_name, project_name)
+            .organization_lookup_by_path(organization_name)
             .await?;
+        opctx.audit_resource(authz_org.id());
+        let role_assignments = policy_role_assignments(
+            ResourceType::Organization,
+            authz_org.id(),
+            policy,
+        )?;
         self.db_datastore
-            .project_list_instances(opctx, &authz_project, pagparams)
+            .role_assignments_replace(opctx, &authz_org, role_assignments)
             .await
     }
 
-    pub async fn project_create_instance(
-        self: &Arc<Self>,
+    pub async fn project_fetch_policy(
+        &self,
         opctx: &OpContext,
         organization_name: &Name,
         project_name: &Name,
-        params: &params::InstanceCreate,
-    ) -> CreateResult<db::model::Instance> {
+    ) -> LookupResult<Vec<db::model::RoleAssignment>> {
         let authz_project = self
             .db_datastore
             .project_lookup_by_path(organization_name, project_name)
             .await?;
+        self.db_datastore.role_assignments_fetch(opctx, &authz_project).await
+    }
 
-        opctx.authorize(authz::Action::CreateChild, &authz_project).await?;
-
-        let saga_params = Arc::new(sagas::ParamsInstanceCreate {
-            project_id: authz_project.id(),
-            create_params: params.clone(),
-        });
-
-        let saga_outputs = self
-            .execute_saga(
-                Arc::clone(&sagas::SAGA_INSTANCE_CREATE_TEMPLATE),
-                sagas::SAGA_INSTANCE_CREATE_NAME,
-                saga_params,
-            )
+    pub async fn project_update_policy(
+        &self,
+        opctx: &OpContext,
+        organization_name: &Name,
+        project_name: &Name,
+        policy: &views::Policy,
+    ) -> UpdateResult<Vec<db::model::RoleAssignment>> {
+        let authz_project = self
+            .db_datastore
+            .project_lookup_by_path(organization_name, project_name)
             .await?;
-        /* TODO-error more context would be useful  */
-        let instance_id =
-            saga_outputs.lookup_output::<Uuid>("instance_id").map_err(|e| {
-                Error::InternalError { internal_message: e.to_string() }
-            })?;
-        /*
-         * TODO-correctness TODO-robustness TODO-design It's not quite correct
-         * to take this instance id and look it up again.  It's possible that
-         * it's been modified or even deleted since the saga executed.  In that
-         * case, we might return a different state of the Instance than the one
-         * that the user created or even fail with a 404!  Both of those are
-         * wrong behavior -- we should be returning the very instance that the
-         * user created.
-         *
-         * How can we fix this?  Right now we have internal representations like
-         * Instance and analaogous end-user-facing representations like
-         * Instance.  The former is not even serializable.  The saga
-         * _could_ emit the View version, but that's not great for two (related)
-         * reasons: (1) other sagas might want to provision instances and get
-         * back the internal representation to do other things with the
-         * newly-created instance, and (2) even within a saga, it would be
-         * useful to pass a single Instance representation along the saga,
-         * but they probably would want the internal representation, not the
-         * view.
-         *
-         * The saga could emit an Instance directly.  Today, Instance
-         * etc. aren't supposed to even be serializable -- we wanted to be able
-         * to have other datastore state there if needed.  We could have a third
-         * InstanceInternalView...but that's starting to feel pedantic.  We
-         * could just make Instance serializable, store that, and call it a
-         * day.  Does it matter that we might have many copies of the same
-         * objects in memory?
-         *
-         * If we make these serializable, it would be nice if we could leverage
-         * the type system to ensure that we never accidentally send them out a
-         * dropshot endpoint.  (On the other hand, maybe we _do_ want to do
-         * that, for internal interfaces!  Can we do this on a
-         * per-dropshot-server-basis?)
-         *
-         * TODO Even worse, post-authz, we do two lookups here instead of one.
-         * Maybe sagas should be able to emit `authz::Instance`-type objects.
-         */
-        let authz_instance =
-            self.db_datastore.instance_lookup_by_id(instance_id).await?;
-        self.db_datastore.instance_refetch(opctx, &authz_instance).await
+        opctx.audit_resource(authz_project.id());
+        let role_assignments = policy_role_assignments(
+            ResourceType::Project,
+            authz_project.id(),
+            policy,
+        )?;
+        self.db_datastore
+            .role_assignments_replace(opctx, &authz_project, role_assignments)
+            .await
     }
 
     /*
-     * TODO-correctness It's not totally clear what the semantics and behavior
-     * should be here.  It might be nice to say that you can only do this
-     * operation if the Instance is already stopped, in which case we can
-     * execute this immediately by just removing it from the database, with the
-     * same race we have with disk delete (i.e., if someone else is requesting
-     * an instance boot, we may wind up in an inconsistent state).  On the other
-     * hand, we could always allow this operation, issue the request to the SA
-     * to destroy the instance (not just stop it), and proceed with deletion
-     * when that finishes.  But in that case, although the HTTP DELETE request
-     * completed, the object will still appear for a little while, which kind of
-     * sucks.
+     * Disks
      */
-    pub async fn project_destroy_instance(
+
+    pub async fn project_list_disks(
         &self,
         opctx: &OpContext,
         organization_name: &Name,
         project_name: &Name,
-        instance_name: &Name,
-    ) -> DeleteResult {
-        /*
-         * TODO-robustness We need to figure out what to do with Destroyed
-         * instances?  Presumably we need to clean them up at some point, but
-         * not right away so that callers can see that they've been destroyed.
-         */
-        let authz_instance = self
+        pagparams: &DataPageParams<'_, Name>,
+        label_selector: &LabelSelector,
+    ) -> ListResultVec<db::model::Disk> {
+        let authz_project = self
             .db_datastore
-            .instance_lookup_by_path(
-                organization_name,
-                project_name,
-                instance_name,
-            )
+            .project_lookup_by_path(organization_name, project_name)
             .await?;
-        self.db_datastore.project_delete_instance(opctx, &authz_instance).await
+        self.db_datastore
+            .project_list_disks(
+                opctx,
+                &authz_project,
+                pagparams,
+                label_selector,
+            )
+            .await
     }
 
-    pub async fn project_migrate_instance(
+    pub async fn project_create_disk(
         self: &Arc<Self>,
         opctx: &OpContext,
         organization_name: &Name,
         project_name: &Name,
-        instance_name: &Name,
-        params: params::InstanceMigrate,
-    ) -> UpdateResult<db::model::Instance> {
-        let authz_instance = self
+        params: &params::DiskCreate,
+        respond_async: bool,
+    ) -> CreateResult<MaybeAccepted<db::model::Disk>> {
+        let authz_project = self
             .db_datastore
-            .instance_lookup_by_path(
-                organization_name,
-                project_name,
-                instance_name,
-            )
+            .project_lookup_by_path(organization_name, project_name)
             .await?;
-        opctx.authorize(authz::Action::Modify, &authz_instance).await?;
 
-        // Kick off the migration saga
-        let saga_params = Arc::new(sagas::ParamsInstanceMigrate {
+        // TODO-security This may need to be revisited once we implement authz
+        // checks for saga actions.  Even then, though, it still will be correct
+        // (if possibly redundant) to check this here.
+        opctx.authorize(authz::Action::CreateChild, &authz_project).await?;
+
+        /*
+         * A Disk can only be created from a Snapshot in the same Project, and
+         * it must be big enough to hold the Snapshot's contents.
+         */
+        let origin_disk_id = match params.snapshot_id {
+            None => None,
+            Some(snapshot_id) => {
+                let (_, db_snapshot) = self
+                    .db_datastore
+                    .snapshot_fetch_by_id(opctx, &authz_project, snapshot_id)
+                    .await?;
+                if params.size.to_bytes() < db_snapshot.size.to_bytes() {
+                    return Err(Error::InvalidValue {
+                        label: String::from("size"),
+                        message: format!(
+                            "disk must be at least as large as the snapshot \
+                             it is created from ({} bytes)",
+                            db_snapshot.size.to_bytes()
+                        ),
+                    });
+                }
+                Some(db_snapshot.disk_id)
+            }
+        };
+
+        let saga_params = Arc::new(sagas::ParamsDiskCreate {
             serialized_authn: authn::saga::Serialized::for_opctx(opctx),
-            instance_id: authz_instance.id(),
-            migrate_params: params,
+            project_id: authz_project.id(),
+            create_params: params.clone(),
+            origin_disk_id,
         });
-        self.execute_saga(
-            Arc::clone(&sagas::SAGA_INSTANCE_MIGRATE_TEMPLATE),
-            sagas::SAGA_INSTANCE_MIGRATE_NAME,
-            saga_params,
-        )
-        .await?;
-
-        // TODO correctness TODO robustness TODO design
-        // Should we lookup the instance again here?
-        // See comment in project_create_instance.
-        self.db_datastore.instance_refetch(opctx, &authz_instance).await
+        let target = OperationTarget {
+            project_id: authz_project.id(),
+            resource_type: ResourceType::Disk,
+            id: OperationTargetId::Created("disk_id"),
+        };
+        let saga_outputs = match self
+            .execute_saga_or_accept(
+                opctx,
+                target,
+                Arc::clone(&sagas::SAGA_DISK_CREATE_TEMPLATE),
+                sagas::SAGA_DISK_CREATE_NAME,
+                saga_params,
+                respond_async,
+            )
+            .await?
+        {
+            MaybeAccepted::Done(saga_outputs) => saga_outputs,
+            MaybeAccepted::Accepted(operation) => {
+                return Ok(MaybeAccepted::Accepted(operation))
+            }
+        };
+        let disk_created = saga_outputs
+            .lookup_output::<db::model::Disk>("created_disk")
+            .map_err(|e| Error::InternalError {
+                internal_message: e.to_string(),
+            })?;
+        opctx.audit_resource(disk_created.id());
+        Ok(MaybeAccepted::Done(disk_created))
     }
 
-    pub async fn instance_fetch(
+    pub async fn disk_fetch(
         &self,
         opctx: &OpContext,
         organization_name: &Name,
         project_name: &Name,
-        instance_name: &Name,
-    ) -> LookupResult<db::model::Instance> {
+        disk_name: &Name,
+    ) -> LookupResult<db::model::Disk> {
         let authz_project = self
             .db_datastore
             .project_lookup_by_path(organization_name, project_name)
             .await?;
         Ok(self
             .db_datastore
-            .instance_fetch(opctx, &authz_project, instance_name)
+            .disk_fetch(opctx, &authz_project, disk_name)
             .await?
             .1)
     }
 
-    fn check_runtime_change_allowed(
+    pub async fn project_update_disk(
         &self,
-        runtime: &nexus::InstanceRuntimeState,
-        requested: &InstanceRuntimeStateRequested,
-    ) -> Result<(), Error> {
-        /*
-         * Users are allowed to request a start or stop even if the instance is
-         * already in the desired state (or moving to it), and we will issue a
-         * request to the SA to make the state change in these cases in case the
-         * runtime state we saw here was stale.  However, users are not allowed
-         * to change the state of an instance that's migrating, failed or
-         * destroyed.  But if we're already migrating, requesting a migration is
-         * allowed to allow for idempotency.
-         */
-        let allowed = match runtime.run_state {
-            InstanceState::Creating => true,
-            InstanceState::Starting => true,
-            InstanceState::Running => true,
-            InstanceState::Stopping => true,
-            InstanceState::Stopped => true,
-            InstanceState::Rebooting => true,
+        opctx: &OpContext,
+        organization_name: &Name,
+        project_name: &Name,
+        disk_name: &Name,
+        params: &params::DiskUpdate,
+        preconditions: &Preconditions,
+    ) -> UpdateResult<db::model::Disk> {
+        let authz_disk = self
+            .db_datastore
+            .disk_lookup_by_path(organization_name, project_name, disk_name)
+            .await?;
+        opctx.audit_resource(authz_disk.id());
+        self.db_datastore
+            .disk_update(
+                opctx,
+                &authz_disk,
+                params.clone().into(),
+                preconditions,
+            )
+            .await
+    }
 
-            InstanceState::Migrating => {
-                requested.run_state == InstanceStateRequested::Migrating
-            }
-            InstanceState::Repairing => false,
-            InstanceState::Failed => false,
-            InstanceState::Destroyed => false,
-        };
+    pub async fn project_delete_disk(
+        self: &Arc<Self>,
+        opctx: &OpContext,
+        organization_name: &Name,
+        project_name: &Name,
+        disk_name: &Name,
+        respond_async: bool,
+    ) -> Result<MaybeAccepted<()>, Error> {
+        let authz_disk = self
+            .db_datastore
+            .disk_lookup_by_path(organization_name, project_name, disk_name)
+            .await?;
+
+        // TODO: We need to sort out the authorization checks.
+        //
+        // Normally, this would be coupled alongside access to the
+        // datastore, but now that disk deletion exists within a Saga,
+        // this would require OpContext to be serialized (which is
+        // not trivial).
+        opctx.authorize(authz::Action::Delete, &authz_disk).await?;
+        opctx.audit_resource(authz_disk.id());
+
+        /*
+         * The data for a Disk's Snapshots lives alongside the Disk's regions,
+         * so the Disk cannot be deleted while it has Snapshots.
+         * TODO-correctness A Snapshot could be created between this check and
+         * the deletion below.
+         */
+        if self.db_datastore.disk_has_snapshots(authz_disk.id()).await? {
+            return Err(Error::InvalidRequest {
+                message: String::from("disk to be deleted has snapshots"),
+            });
+        }
+
+        let saga_params =
+            Arc::new(sagas::ParamsDiskDelete { disk_id: authz_disk.id() });
+        let target = OperationTarget {
+            project_id: authz_disk.project().id(),
+            resource_type: ResourceType::Disk,
+            id: OperationTargetId::Deleted(authz_disk.id()),
+        };
+        let result = self
+            .execute_saga_or_accept(
+                opctx,
+                target,
+                Arc::clone(&sagas::SAGA_DISK_DELETE_TEMPLATE),
+                sagas::SAGA_DISK_DELETE_NAME,
+                saga_params,
+                respond_async,
+            )
+            .await?;
+
+        Ok(result.map(|_| ()))
+    }
+
+    pub async fn project_resize_disk(
+        self: &Arc<Self>,
+        opctx: &OpContext,
+        organization_name: &Name,
+        project_name: &Name,
+        disk_name: &Name,
+        params: &params::DiskResize,
+    ) -> UpdateResult<db::model::Disk> {
+        let authz_project = self
+            .db_datastore
+            .project_lookup_by_path(organization_name, project_name)
+            .await?;
+        let (authz_disk, db_disk) = self
+            .db_datastore
+            .disk_fetch(opctx, &authz_project, disk_name)
+            .await?;
+        opctx.authorize(authz::Action::Modify, &authz_disk).await?;
+        opctx.audit_resource(authz_disk.id());
+
+        /*
+         * Crucible regions can only grow.
+         */
+        let current_size = db_disk.size.to_bytes();
+        if params.size.to_bytes() < current_size {
+            return Err(Error::InvalidValue {
+                label: String::from("size"),
+                message: format!(
+                    "disk cannot be shrunk (current size is {} bytes)",
+                    current_size
+                ),
+            });
+        }
+        if params.size.to_bytes() == current_size {
+            return Ok(db_disk);
+        }
+
+        match db_disk.state().into() {
+            DiskState::Detached | DiskState::Attached(_) => (),
+            _ => {
+                return Err(Error::InvalidRequest {
+                    message: format!(
+                        "cannot resize disk in state \"{}\"",
+                        db_disk.runtime_state.disk_state
+                    ),
+                });
+            }
+        }
+
+        /*
+         * A Disk's Snapshots live alongside its regions, so the regions can
+         * only grow in place while there are Snapshots.
+         */
+        let allow_move =
+            !self.db_datastore.disk_has_snapshots(authz_disk.id()).await?;
+
+        let saga_params = Arc::new(sagas::ParamsDiskResize {
+            serialized_authn: authn::saga::Serialized::for_opctx(opctx),
+            disk: db_disk,
+            resize_params: params.clone(),
+            allow_move,
+        });
+        let target = OperationTarget {
+            project_id: authz_project.id(),
+            resource_type: ResourceType::Disk,
+            id: OperationTargetId::Existing(authz_disk.id()),
+        };
+        let saga_outputs = self
+            .execute_saga(
+                opctx,
+                target,
+                Arc::clone(&sagas::SAGA_DISK_RESIZE_TEMPLATE),
+                sagas::SAGA_DISK_RESIZE_NAME,
+                saga_params,
+            )
+            .await?;
+        let disk_resized = saga_outputs
+            .lookup_output::<db::model::Disk>("resized_disk")
+            .map_err(|e| Error::InternalError {
+                internal_message: e.to_string(),
+            })?;
+        Ok(disk_resized)
+    }
+
+    /*
+     * Snapshots
+     */
+
+    pub async fn disk_list_snapshots(
+        &self,
+        opctx: &OpContext,
+        organization_name: &Name,
+        project_name: &Name,
+        disk_name: &Name,
+        pagparams: &DataPageParams<'_, Name>,
+    ) -> ListResultVec<db::model::Snapshot> {
+        let authz_disk = self
+            .db_datastore
+            .disk_lookup_by_path(organization_name, project_name, disk_name)
+            .await?;
+        self.db_datastore
+            .disk_list_snapshots(opctx, &authz_disk, pagparams)
+            .await
+    }
+
+    pub async fn disk_create_snapshot(
+        self: &Arc<Self>,
+        opctx: &OpContext,
+        organization_name: &Name,
+        project_name: &Name,
+        disk_name: &Name,
+        params: &params::SnapshotCreate,
+    ) -> CreateResult<db::model::Snapshot> {
+        params.identity.check_no_labels(ResourceType::Snapshot)?;
+        let authz_project = self
+            .db_datastore
+            .project_lookup_by_path(organization_name, project_name)
+            .await?;
+        let (authz_disk, db_disk) = self
+            .db_datastore
+            .disk_fetch(opctx, &authz_project, disk_name)
+            .await?;
+        opctx.authorize(authz::Action::CreateChild, &authz_disk).await?;
+
+        /*
+         * TODO-correctness An attached Disk may be written to while its
+         * regions are being snapshotted, in which case the regions' snapshots
+         * may not agree with one another.  The Upstairs should be asked to
+         * flush and hold writes for the duration.
+         */
+        match db_disk.state().into() {
+            DiskState::Detached | DiskState::Attached(_) => (),
+            _ => {
+                return Err(Error::InvalidRequest {
+                    message: format!(
+                        "cannot snapshot disk in state \"{}\"",
+                        db_disk.runtime_state.disk_state
+                    ),
+                });
+            }
+        }
+
+        let saga_params = Arc::new(sagas::ParamsSnapshotCreate {
+            disk: db_disk,
+            create_params: params.clone(),
+        });
+        let target = OperationTarget {
+            project_id: authz_project.id(),
+            resource_type: ResourceType::Snapshot,
+            id: OperationTargetId::Created("snapshot_id"),
+        };
+        let saga_outputs = self
+            .execute_saga(
+                opctx,
+                target,
+                Arc::clone(&sagas::SAGA_SNAPSHOT_CREATE_TEMPLATE),
+                sagas::SAGA_SNAPSHOT_CREATE_NAME,
+                saga_params,
+            )
+            .await?;
+        let snapshot_created = saga_outputs
+            .lookup_output::<db::model::Snapshot>("created_snapshot")
+            .map_err(|e| Error::InternalError {
+                internal_message: e.to_string(),
+            })?;
+        opctx.audit_resource(snapshot_created.id());
+        Ok(snapshot_created)
+    }
+
+    pub async fn snapshot_fetch(
+        &self,
+        opctx: &OpContext,
+        organization_name: &Name,
+        project_name: &Name,
+        disk_name: &Name,
+        snapshot_name: &Name,
+    ) -> LookupResult<db::model::Snapshot> {
+        let authz_disk = self
+            .db_datastore
+            .disk_lookup_by_path(organization_name, project_name, disk_name)
+            .await?;
+        Ok(self
+            .db_datastore
+            .snapshot_fetch(opctx, &authz_disk, snapshot_name)
+            .await?
+            .1)
+    }
+
+    pub async fn disk_delete_snapshot(
+        self: &Arc<Self>,
+        opctx: &OpContext,
+        organization_name: &Name,
+        project_name: &Name,
+        disk_name: &Name,
+        snapshot_name: &Name,
+    ) -> DeleteResult {
+        let authz_disk = self
+            .db_datastore
+            .disk_lookup_by_path(organization_name, project_name, disk_name)
+            .await?;
+        let (authz_snapshot, _) = self
+            .db_datastore
+            .snapshot_fetch(opctx, &authz_disk, snapshot_name)
+            .await?;
+
+        // As with disk deletion, the authz check happens here rather than
+        // within the saga.
+        opctx.authorize(authz::Action::Delete, &authz_snapshot).await?;
+        opctx.audit_resource(authz_snapshot.id());
+
+        let saga_params = Arc::new(sagas::ParamsSnapshotDelete {
+            snapshot_id: authz_snapshot.id(),
+            disk_id: authz_disk.id(),
+        });
+        let target = OperationTarget {
+            project_id: authz_snapshot.project().id(),
+            resource_type: ResourceType::Snapshot,
+            id: OperationTargetId::Deleted(authz_snapshot.id()),
+        };
+        self.execute_saga(
+            opctx,
+            target,
+            Arc::clone(&sagas::SAGA_SNAPSHOT_DELETE_TEMPLATE),
+            sagas::SAGA_SNAPSHOT_DELETE_NAME,
+            saga_params,
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    /*
+     * Instances
+     */
+
+    /**
+     * Builds the placement request for an Instance with `ncpus` vCPUs and
+     * `memory` bytes of memory, constrained by the Instance's affinity group
+     * (if any).  `instance_id` is given when re-placing an existing Instance,
+     * which does not constrain its own placement.
+     */
+    pub async fn placement_request(
+        &self,
+        ncpus: u64,
+        memory: u64,
+        affinity_group_id: Option<Uuid>,
+        instance_id: Option<Uuid>,
+    ) -> Result<PlacementRequest, Error> {
+        let mut request =
+            PlacementRequest { ncpus, memory, ..Default::default() };
+        if let Some(group_id) = affinity_group_id {
+            let group =
+                self.db_datastore.affinity_group_fetch(&group_id).await?;
+            let member_sleds = self
+                .db_datastore
+                .affinity_group_member_sleds(&group_id, instance_id)
+                .await?
+                .into_iter()
+                .collect();
+            match group.policy.0 {
+                AffinityPolicy::Affinity => {
+                    request.required_sleds = member_sleds
+                }
+                AffinityPolicy::AntiAffinity => {
+                    request.excluded_sleds = member_sleds
+                }
+            }
+        }
+        Ok(request)
+    }
+
+    /*
+     * TODO-design This interface should not exist.  See
+     * SagaContext::alloc_server().
+     */
+    pub async fn sled_allocate(
+        &self,
+        request: &PlacementRequest,
+    ) -> Result<Uuid, Error> {
+        let sleds = self.db_datastore.sled_list_with_reservations().await?;
+        let sled_id = self.placement.choose_sled(request, &sleds)?;
+        debug!(self.log, "placed instance";
+            "sled_id" => sled_id.to_string(),
+            "ncpus" => request.ncpus,
+            "memory" => request.memory);
+        Ok(sled_id)
+    }
+
+    pub async fn project_list_instances(
+        &self,
+        opctx: &OpContext,
+        organization_name: &Name,
+        project_name: &Name,
+        pagparams: &DataPageParams<'_, Name>,
+        label_selector: &LabelSelector,
+    ) -> ListResultVec<db::model::Instance> {
+        let authz_project = self
+            .db_datastore
+            .project_lookup_by_path(organization_name, project_name)
+            .await?;
+        self.db_datastore
+            .project_list_instances(
+                opctx,
+                &authz_project,
+                pagparams,
+                label_selector,
+            )
+            .await
+    }
+
+    pub async fn project_create_instance(
+        self: &Arc<Self>,
+        opctx: &OpContext,
+        organization_name: &Name,
+        project_name: &Name,
+        params: &params::InstanceCreate,
+        respond_async: bool,
+    ) -> CreateResult<MaybeAccepted<db::model::Instance>> {
+        let authz_project = self
+            .db_datastore
+            .project_lookup_by_path(organization_name, project_name)
+            .await?;
+
+        opctx.authorize(authz::Action::CreateChild, &authz_project).await?;
+
+        if params.network_interfaces.len() > params::MAX_NICS_PER_INSTANCE {
+            return Err(Error::InvalidValue {
+                label: String::from("network_interfaces"),
+                message: format!(
+                    "an instance may have at most {} network interfaces",
+                    params::MAX_NICS_PER_INSTANCE
+                ),
+            });
+        }
+        for interface in &params.network_interfaces {
+            interface
+                .identity
+                .check_no_labels(ResourceType::NetworkInterface)?;
+        }
+
+        let affinity_group_id = match &params.affinity_group {
+            Some(group_name) => Some(
+                self.db_datastore
+                    .affinity_group_fetch_by_name(
+                        &authz_project.id(),
+                        &Name(group_name.clone()),
+                    )
+                    .await?
+                    .id(),
+            ),
+            None => None,
+        };
+
+        /*
+         * The creating user's SSH keys are authorized along with any given
+         * explicitly.  The latter are checked just like the keys users upload,
+         * so that a malformed one is rejected here rather than ending up in
+         * the Instance's configuration.
+         */
+        let mut create_params = params.clone();
+        create_params.ssh_public_keys = params
+            .ssh_public_keys
+            .iter()
+            .map(|key| match SshPublicKey::parse(key) {
+                Ok(key) => Ok(key.to_string()),
+                Err(Error::InvalidValue { message, .. }) => {
+                    Err(Error::InvalidValue {
+                        label: String::from("ssh_public_keys"),
+                        message,
+                    })
+                }
+                Err(e) => Err(e),
+            })
+            .collect::<Result<_, _>>()?;
+        if let Some(actor) = opctx.authn.actor() {
+            for key in
+                self.db_datastore.ssh_keys_list_all(opctx, actor.0).await?
+            {
+                if !create_params.ssh_public_keys.contains(&key.public_key) {
+                    create_params.ssh_public_keys.push(key.public_key);
+                }
+            }
+        }
+
+        let saga_params = Arc::new(sagas::ParamsInstanceCreate {
+            project_id: authz_project.id(),
+            create_params,
+            affinity_group_id,
+        });
+
+        let target = OperationTarget {
+            project_id: authz_project.id(),
+            resource_type: ResourceType::Instance,
+            id: OperationTargetId::Created("instance_id"),
+        };
+        let saga_outputs = match self
+            .execute_saga_or_accept(
+                opctx,
+                target,
+                Arc::clone(&sagas::SAGA_INSTANCE_CREATE_TEMPLATE),
+                sagas::SAGA_INSTANCE_CREATE_NAME,
+                saga_params,
+                respond_async,
+            )
+            .await?
+        {
+            MaybeAccepted::Done(saga_outputs) => saga_outputs,
+            MaybeAccepted::Accepted(operation) => {
+                return Ok(MaybeAccepted::Accepted(operation))
+            }
+        };
+        /* TODO-error more context would be useful  */
+        let instance_id =
+            saga_outputs.lookup_output::<Uuid>("instance_id").map_err(|e| {
+                Error::InternalError { internal_message: e.to_string() }
+            })?;
+        opctx.audit_resource(instance_id);
+        /*
+         * TODO-correctness TODO-robustness TODO-design It's not quite correct
+         * to take this instance id and look it up again.  It's possible that
+         * it's been modified or even deleted since the saga executed.  In that
+         * case, we might return a different state of the Instance than the one
+         * that the user created or even fail with a 404!  Both of those are
+         * wrong behavior -- we should be returning the very instance that the
+         * user created.
+         *
+         * How can we fix this?  Right now we have internal representations like
+         * Instance and analaogous end-user-facing representations like
+         * Instance.  The former is not even serializable.  The saga
+         * _could_ emit the View version, but that's not great for two (related)
+         * reasons: (1) other sagas might want to provision instances and get
+         * back the internal representation to do other things with the
+         * newly-created instance, and (2) even within a saga, it would be
+         * useful to pass a single Instance representation along the saga,
+         * but they probably would want the internal representation, not the
+         * view.
+         *
+         * The saga could emit an Instance directly.  Today, Instance
+         * etc. aren't supposed to even be serializable -- we wanted to be able
+         * to have other datastore state there if needed.  We could have a third
+         * InstanceInternalView...but that's starting to feel pedantic.  We
+         * could just make Instance serializable, store that, and call it a
+         * day.  Does it matter that we might have many copies of the same
+         * objects in memory?
+         *
+         * If we make these serializable, it would be nice if we could leverage
+         * the type system to ensure that we never accidentally send them out a
+         * dropshot endpoint.  (On the other hand, maybe we _do_ want to do
+         * that, for internal interfaces!  Can we do this on a
+         * per-dropshot-server-basis?)
+         *
+         * TODO Even worse, post-authz, we do two lookups here instead of one.
+         * Maybe sagas should be able to emit `authz::Instance`-type objects.
+         */
+        let authz_instance =
+            self.db_datastore.instance_lookup_by_id(instance_id).await?;
+        self.db_datastore
+            .instance_refetch(opctx, &authz_instance)
+            .await
+            .map(MaybeAccepted::Done)
+    }
+
+    /*
+     * TODO-correctness It's not totally clear what the semantics and behavior
+     * should be here.  It might be nice to say that you can only do this
+     * operation if the Instance is already stopped, in which case we can
+     * execute this immediately by just removing it from the database, with the
+     * same race we have with disk delete (i.e., if someone else is requesting
+     * an instance boot, we may wind up in an inconsistent state).  On the other
+     * hand, we could always allow this operation, issue the request to the SA
+     * to destroy the instance (not just stop it), and proceed with deletion
+     * when that finishes.  But in that case, although the HTTP DELETE request
+     * completed, the object will still appear for a little while, which kind of
+     * sucks.
+     */
+    pub async fn project_destroy_instance(
+        &self,
+        opctx: &OpContext,
+        organization_name: &Name,
+        project_name: &Name,
+        instance_name: &Name,
+    ) -> DeleteResult {
+        /*
+         * TODO-robustness We need to figure out what to do with Destroyed
+         * instances?  Presumably we need to clean them up at some point, but
+         * not right away so that callers can see that they've been destroyed.
+         */
+        let authz_instance = self
+            .db_datastore
+            .instance_lookup_by_path(
+                organization_name,
+                project_name,
+                instance_name,
+            )
+            .await?;
+        opctx.audit_resource(authz_instance.id());
+        let db_instance =
+            self.db_datastore.instance_refetch(opctx, &authz_instance).await?;
+        let interfaces = self
+            .db_datastore
+            .instance_list_all_network_interfaces(&authz_instance.id())
+            .await?;
+        self.db_datastore
+            .project_delete_instance(opctx, &authz_instance)
+            .await?;
+        /*
+         * TODO-robustness If these fail, the Floating IPs or the interfaces
+         * (and their addresses) are left behind with no Instance.
+         */
+        self.db_datastore
+            .instance_release_floating_ips(&authz_instance.id())
+            .await?;
+        self.db_datastore
+            .instance_delete_all_network_interfaces(&authz_instance.id())
+            .await?;
+
+        /*
+         * The Instance's sled no longer needs its interfaces' firewall rules,
+         * and rules elsewhere in their VPCs may have matched their addresses.
+         */
+        self.sled_firewall_rules_clear(
+            db_instance.runtime().sled_uuid,
+            interfaces.iter().map(|interface| interface.id()).collect(),
+        )
+        .await;
+        self.vpcs_firewall_rules_push(
+            interfaces.iter().map(|interface| interface.vpc_id).collect(),
+        )
+        .await;
+        self.dns_zone_push().await;
+        self.project_event_publish(
+            authz_instance.project().id(),
+            ResourceType::Instance,
+            authz_instance.id(),
+            EventKind::Deleted,
+        )
+        .await;
+        Ok(())
+    }
+
+    /**
+     * Changes the vCPUs or memory of a stopped Instance.  The Instance may
+     * have to move to another sled to get the new resources.
+     */
+    pub async fn project_update_instance(
+        &self,
+        opctx: &OpContext,
+        organization_name: &Name,
+        project_name: &Name,
+        instance_name: &Name,
+        params: &params::InstanceUpdate,
+    ) -> UpdateResult<db::model::Instance> {
+        let authz_project = self
+            .db_datastore
+            .project_lookup_by_path(organization_name, project_name)
+            .await?;
+        let (authz_instance, db_instance) = self
+            .db_datastore
+            .instance_fetch(opctx, &authz_project, instance_name)
+            .await?;
+        opctx.authorize(authz::Action::Modify, &authz_instance).await?;
+        opctx.audit_resource(authz_instance.id());
+
+        /*
+         * Only a stopped Instance can be resized, but its labels can be
+         * changed at any time.
+         */
+        if params.ncpus.is_some() || params.memory.is_some() {
+            let runtime = db_instance.runtime();
+            let instance_state = runtime.state.state();
+            let state_error = match instance_state {
+                InstanceState::Stopped => None,
+                InstanceState::Creating | InstanceState::Starting => {
+                    Some("instance is starting")
+                }
+                InstanceState::Running | InstanceState::Rebooting => {
+                    Some("instance is running; stop it first")
+                }
+                InstanceState::Stopping => Some("instance is stopping"),
+                InstanceState::Migrating => Some("instance is migrating"),
+                InstanceState::Repairing => Some("instance is being repaired"),
+                InstanceState::Failed => Some("instance has failed"),
+                InstanceState::Destroyed => Some("instance has been destroyed"),
+            };
+            if let Some(message) = state_error {
+                return Err(Error::InvalidRequest {
+                    message: format!(
+                        "cannot resize instance \"{}\": {}",
+                        instance_name.as_str(),
+                        message
+                    ),
+                });
+            }
+
+            let ncpus = params.ncpus.unwrap_or(runtime.ncpus.0);
+            let memory = params.memory.unwrap_or(runtime.memory.0);
+            let request = self
+                .placement_request(
+                    u64::from(ncpus.0),
+                    memory.to_bytes(),
+                    db_instance.affinity_group_id,
+                    Some(authz_instance.id()),
+                )
+                .await?;
+
+            /*
+             * The Instance's current reservation must not count against its new
+             * size.  Stay on the current sled if it has room, since that's where
+             * the Instance's state already lives.
+             */
+            let mut sleds =
+                self.db_datastore.sled_list_with_reservations().await?;
+            for sled in sleds.iter_mut() {
+                if sled.sled_id == runtime.sled_uuid {
+                    sled.reserved_cpus = sled
+                        .reserved_cpus
+                        .saturating_sub(u64::from(runtime.ncpus.0 .0));
+                    sled.reserved_ram = sled
+                        .reserved_ram
+                        .saturating_sub(runtime.memory.to_bytes());
+                }
+            }
+            let current_sled: Vec<_> = sleds
+                .iter()
+                .filter(|s| s.sled_id == runtime.sled_uuid)
+                .cloned()
+                .collect();
+            let sled_id =
+                match self.placement.choose_sled(&request, &current_sled) {
+                    Ok(sled_id) => sled_id,
+                    Err(_) => self.placement.choose_sled(&request, &sleds)?,
+                };
+
+            let new_runtime = db::model::InstanceRuntimeState {
+                time_updated: Utc::now(),
+                gen: runtime.gen.next().into(),
+                sled_uuid: sled_id,
+                ncpus: ncpus.into(),
+                memory: memory.into(),
+                ..runtime.clone()
+            };
+            let updated = self
+                .db_datastore
+                .instance_resize(opctx, &authz_instance, &new_runtime)
+                .await?;
+            if !updated {
+                return Err(Error::unavail(
+                    "instance changed while it was being resized",
+                ));
+            }
+            debug!(self.log, "resized instance";
+                "instance_id" => authz_instance.id().to_string(),
+                "sled_id" => sled_id.to_string(),
+                "ncpus" => ncpus.0,
+                "memory" => memory.to_bytes());
+            if sled_id != runtime.sled_uuid {
+                self.instance_firewall_rules_moved(
+                    &authz_instance.id(),
+                    runtime.sled_uuid,
+                )
+                .await;
+            }
+        }
+
+        if params.labels.is_some() {
+            self.db_datastore
+                .instance_update(opctx, &authz_instance, params.clone().into())
+                .await?;
+        }
+
+        self.db_datastore.instance_refetch(opctx, &authz_instance).await
+    }
+
+    pub async fn project_migrate_instance(
+        self: &Arc<Self>,
+        opctx: &OpContext,
+        organization_name: &Name,
+        project_name: &Name,
+        instance_name: &Name,
+        params: params::InstanceMigrate,
+        respond_async: bool,
+    ) -> UpdateResult<MaybeAccepted<db::model::Instance>> {
+        let authz_instance = self
+            .db_datastore
+            .instance_lookup_by_path(
+                organization_name,
+                project_name,
+                instance_name,
+            )
+            .await?;
+        opctx.authorize(authz::Action::Modify, &authz_instance).await?;
+
+        // Kick off the migration saga
+        let saga_params = Arc::new(sagas::ParamsInstanceMigrate {
+            serialized_authn: authn::saga::Serialized::for_opctx(opctx),
+            instance_id: authz_instance.id(),
+            migrate_params: params,
+        });
+        let target = OperationTarget {
+            project_id: authz_instance.project().id(),
+            resource_type: ResourceType::Instance,
+            id: OperationTargetId::Existing(authz_instance.id()),
+        };
+        let result = self
+            .execute_saga_or_accept(
+                opctx,
+                target,
+                Arc::clone(&sagas::SAGA_INSTANCE_MIGRATE_TEMPLATE),
+                sagas::SAGA_INSTANCE_MIGRATE_NAME,
+                saga_params,
+                respond_async,
+            )
+            .await?;
+        if let MaybeAccepted::Accepted(operation) = result {
+            return Ok(MaybeAccepted::Accepted(operation));
+        }
+
+        // TODO correctness TODO robustness TODO design
+        // Should we lookup the instance again here?
+        // See comment in project_create_instance.
+        self.db_datastore
+            .instance_refetch(opctx, &authz_instance)
+            .await
+            .map(MaybeAccepted::Done)
+    }
+
+    pub async fn instance_fetch(
+        &self,
+        opctx: &OpContext,
+        organization_name: &Name,
+        project_name: &Name,
+        instance_name: &Name,
+    ) -> LookupResult<db::model::Instance> {
+        let authz_project = self
+            .db_datastore
+            .project_lookup_by_path(organization_name, project_name)
+            .await?;
+        Ok(self
+            .db_datastore
+            .instance_fetch(opctx, &authz_project, instance_name)
+            .await?
+            .1)
+    }
+
+    fn check_runtime_change_allowed(
+        &self,
+        runtime: &nexus::InstanceRuntimeState,
+        requested: &InstanceRuntimeStateRequested,
+    ) -> Result<(), Error> {
+        /*
+         * Users are allowed to request a start or stop even if the instance is
+         * already in the desired state (or moving to it), and we will issue a
+         * request to the SA to make the state change in these cases in case the
+         * runtime state we saw here was stale.  However, users are not allowed
+         * to change the state of an instance that's migrating, failed or
+         * destroyed.  But if we're already migrating, requesting a migration is
+         * allowed to allow for idempotency.
+         */
+        let allowed = match runtime.run_state {
+            InstanceState::Creating => true,
+            InstanceState::Starting => true,
+            InstanceState::Running => true,
+            InstanceState::Stopping => true,
+            InstanceState::Stopped => true,
+            InstanceState::Rebooting => true,
+
+            InstanceState::Migrating => {
+                requested.run_state == InstanceStateRequested::Migrating
+            }
+            InstanceState::Repairing => false,
+            InstanceState::Failed => false,
+            InstanceState::Destroyed => false,
+        };
+
+        if allowed {
+            Ok(())
+        } else {
+            Err(Error::InvalidRequest {
+                message: format!(
+                    "instance state cannot be changed from state \"{}\"",
+                    runtime.run_state
+                ),
+            })
+        }
+    }
+
+    pub async fn sled_client(
+        &self,
+        id: &Uuid,
+    ) -> Result<Arc<SledAgentClient>, Error> {
+        // TODO: We should consider injecting connection pooling here,
+        // but for now, connections to sled agents are constructed
+        // on an "as requested" basis.
+        //
+        // Franky, returning an "Arc" here without a connection pool is a little
+        // silly; it's not actually used if each client connection exists as a
+        // one-shot.
+        let sled = self.sled_lookup(id).await?;
+
+        let log = self.log.new(o!("SledAgent" => id.clone().to_string()));
+        let dur = std::time::Duration::from_secs(60);
+        let client = reqwest::ClientBuilder::new()
+            .connect_timeout(dur)
+            .timeout(dur)
+            .build()
+            .unwrap();
+        Ok(Arc::new(SledAgentClient::new_with_client(
+            &format!("http://{}", sled.address()),
+            client,
+            log,
+        )))
+    }
+
+    /**
+     * Returns the SledAgentClient for the host where this Instance is running.
+     */
+    async fn instance_sled(
+        &self,
+        instance: &db::model::Instance,
+    ) -> Result<Arc<SledAgentClient>, Error> {
+        let sa_id = &instance.runtime().sled_uuid;
+        self.sled_client(&sa_id).await
+    }
+
+    /**
+     * Returns recent output from an Instance's serial console, fetched from
+     * the sled where the Instance is running.
+     *
+     * The history holds everything attached users typed that was echoed back,
+     * so reading it requires the same permission as attaching to the console.
+     */
+    pub async fn instance_serial_console_history(
+        &self,
+        opctx: &OpContext,
+        organization_name: &Name,
+        project_name: &Name,
+        instance_name: &Name,
+        max_bytes: Option<u64>,
+    ) -> LookupResult<sled_agent_client::types::InstanceSerialConsoleData> {
+        let authz_project = self
+            .db_datastore
+            .project_lookup_by_path(organization_name, project_name)
+            .await?;
+        let (authz_instance, db_instance) = self
+            .db_datastore
+            .instance_fetch(opctx, &authz_project, instance_name)
+            .await?;
+        opctx.authorize(authz::Action::Modify, &authz_instance).await?;
+        let sa = self.instance_sled(&db_instance).await?;
+        Ok(sa
+            .instance_serial_console_history(&db_instance.id(), max_bytes)
+            .await
+            .map_err(Error::from)?
+            .into_inner())
+    }
+
+    /**
+     * Opens a WebSocket to the serial console of an Instance, on the sled
+     * where the Instance is running.
+     *
+     * Anything written to the console can be read back by anyone else
+     * attached, so attaching requires permission to modify the Instance.
+     */
+    pub async fn instance_serial_console_connect(
+        &self,
+        opctx: &OpContext,
+        organization_name: &Name,
+        project_name: &Name,
+        instance_name: &Name,
+    ) -> Result<WebSocketStream<TcpStream>, Error> {
+        let authz_project = self
+            .db_datastore
+            .project_lookup_by_path(organization_name, project_name)
+            .await?;
+        let (authz_instance, db_instance) = self
+            .db_datastore
+            .instance_fetch(opctx, &authz_project, instance_name)
+            .await?;
+        opctx.authorize(authz::Action::Modify, &authz_instance).await?;
+
+        let sled = self.sled_lookup(&db_instance.runtime().sled_uuid).await?;
+        let unavail = |e: &dyn std::fmt::Display| {
+            Error::unavail(&format!(
+                "failed to attach to serial console of instance {}: {}",
+                db_instance.id(),
+                e
+            ))
+        };
+        let stream = TcpStream::connect(sled.address())
+            .await
+            .map_err(|e| unavail(&e))?;
+        let url = format!(
+            "ws://{}/instances/{}/serial-console",
+            sled.address(),
+            db_instance.id()
+        );
+        let (websocket, _) = tokio_tungstenite::client_async(url, stream)
+            .await
+            .map_err(|e| unavail(&e))?;
+        Ok(websocket)
+    }
+
+    /**
+     * Reboot the specified instance.
+     */
+    pub async fn instance_reboot(
+        &self,
+        opctx: &OpContext,
+        organization_name: &Name,
+        project_name: &Name,
+        instance_name: &Name,
+    ) -> UpdateResult<db::model::Instance> {
+        /*
+         * To implement reboot, we issue a call to the sled agent to set a
+         * runtime state of "reboot". We cannot simply stop the Instance and
+         * start it again here because if we crash in the meantime, we might
+         * leave it stopped.
+         *
+         * When an instance is rebooted, the "rebooting" flag remains set on
+         * the runtime state as it transitions to "Stopping" and "Stopped".
+         * This flag is cleared when the state goes to "Starting".  This way,
+         * even if the whole rack powered off while this was going on, we would
+         * never lose track of the fact that this Instance was supposed to be
+         * running.
+         */
+        let authz_project = self
+            .db_datastore
+            .project_lookup_by_path(organization_name, project_name)
+            .await?;
+        let (authz_instance, db_instance) = self
+            .db_datastore
+            .instance_fetch(opctx, &authz_project, instance_name)
+            .await?;
+        opctx.audit_resource(authz_instance.id());
+        let requested = InstanceRuntimeStateRequested {
+            run_state: InstanceStateRequested::Reboot,
+            migration_params: None,
+        };
+        self.instance_set_runtime(
+            opctx,
+            &authz_instance,
+            &db_instance,
+            requested,
+        )
+        .await?;
+        self.db_datastore.instance_refetch(opctx, &authz_instance).await
+    }
+
+    /**
+     * Make sure the given Instance is running.
+     */
+    pub async fn instance_start(
+        &self,
+        opctx: &OpContext,
+        organization_name: &Name,
+        project_name: &Name,
+        instance_name: &Name,
+    ) -> UpdateResult<db::model::Instance> {
+        let authz_project = self
+            .db_datastore
+            .project_lookup_by_path(organization_name, project_name)
+            .await?;
+        let (authz_instance, db_instance) = self
+            .db_datastore
+            .instance_fetch(opctx, &authz_project, instance_name)
+            .await?;
+        opctx.audit_resource(authz_instance.id());
+        let requested = InstanceRuntimeStateRequested {
+            run_state: InstanceStateRequested::Running,
+            migration_params: None,
+        };
+        self.instance_set_runtime(
+            opctx,
+            &authz_instance,
+            &db_instance,
+            requested,
+        )
+        .await?;
+        self.db_datastore.instance_refetch(opctx, &authz_instance).await
+    }
+
+    /**
+     * Make sure the given Instance is stopped.
+     */
+    pub async fn instance_stop(
+        &self,
+        opctx: &OpContext,
+        organization_name: &Name,
+        project_name: &Name,
+        instance_name: &Name,
+    ) -> UpdateResult<db::model::Instance> {
+        let authz_project = self
+            .db_datastore
+            .project_lookup_by_path(organization_name, project_name)
+            .await?;
+        let (authz_instance, db_instance) = self
+            .db_datastore
+            .instance_fetch(opctx, &authz_project, instance_name)
+            .await?;
+        opctx.audit_resource(authz_instance.id());
+        let requested = InstanceRuntimeStateRequested {
+            run_state: InstanceStateRequested::Stopped,
+            migration_params: None,
+        };
+        self.instance_set_runtime(
+            opctx,
+            &authz_instance,
+            &db_instance,
+            requested,
+        )
+        .await?;
+        self.db_datastore.instance_refetch(opctx, &authz_instance).await
+    }
+
+    /**
+     * Idempotently place the instance in a 'Migrating' state.
+     */
+    pub async fn instance_start_migrate(
+        &self,
+        opctx: &OpContext,
+        instance_id: Uuid,
+        migration_id: Uuid,
+        dst_propolis_id: Uuid,
+    ) -> UpdateResult<db::model::Instance> {
+        let authz_instance =
+            self.db_datastore.instance_lookup_by_id(instance_id).await?;
+        let db_instance =
+            self.db_datastore.instance_refetch(opctx, &authz_instance).await?;
+        let requested = InstanceRuntimeStateRequested {
+            run_state: InstanceStateRequested::Migrating,
+            migration_params: Some(InstanceRuntimeStateMigrateParams {
+                migration_id,
+                dst_propolis_id,
+            }),
+        };
+        self.instance_set_runtime(
+            opctx,
+            &authz_instance,
+            &db_instance,
+            requested,
+        )
+        .await?;
+        self.db_datastore.instance_refetch(opctx, &authz_instance).await
+    }
+
+    /**
+     * Modifies the runtime state of the Instance as requested.  This generally
+     * means booting or halting the Instance.
+     */
+    async fn instance_set_runtime(
+        &self,
+        opctx: &OpContext,
+        authz_instance: &authz::Instance,
+        db_instance: &db::model::Instance,
+        requested: InstanceRuntimeStateRequested,
+    ) -> Result<(), Error> {
+        opctx.authorize(authz::Action::Modify, authz_instance).await?;
+
+        self.check_runtime_change_allowed(
+            &db_instance.runtime().clone().into(),
+            &requested,
+        )?;
+
+        let sa = self.instance_sled(&db_instance).await?;
+
+        /*
+         * Ask the sled agent to begin the state change.  Then update the
+         * database to reflect the new intermediate state.  If this update is
+         * not the newest one, that's fine.  That might just mean the sled agent
+         * beat us to it.
+         */
+
+        let runtime: nexus::InstanceRuntimeState =
+            db_instance.runtime().clone().into();
+
+        // See also: sic_create_instance_record in sagas.rs for a similar
+        // construction.
+        let nics = self
+            .db_datastore
+            .instance_list_all_network_interfaces(&db_instance.id())
+            .await?;
+        let nics = self
+            .network_interfaces_view(nics)
+            .await?
+            .iter()
+            .map(sled_agent_client::types::NetworkInterface::from)
+            .collect();
+        let instance_hardware = sled_agent_client::types::InstanceHardware {
+            runtime: sled_agent_client::types::InstanceRuntimeState::from(
+                runtime,
+            ),
+            nics,
+            guest_metadata: db_instance.guest_metadata().into(),
+        };
+
+        let new_runtime = sa
+            .instance_put(
+                &db_instance.id(),
+                &sled_agent_client::types::InstanceEnsureBody {
+                    initial: instance_hardware,
+                    target: requested.into(),
+                    migrate: None,
+                },
+            )
+            .await
+            .map_err(Error::from)?;
+
+        let new_runtime: nexus::InstanceRuntimeState =
+            new_runtime.into_inner().into();
+        let new_state = new_runtime.run_state.to_string();
+
+        let updated = self
+            .db_datastore
+            .instance_update_runtime(&db_instance.id(), &new_runtime.into())
+            .await?;
+        if updated {
+            self.project_event_publish(
+                authz_instance.project().id(),
+                ResourceType::Instance,
+                authz_instance.id(),
+                EventKind::StateChanged(new_state),
+            )
+            .await;
+        }
+        Ok(())
+    }
+
+    /**
+     * Lists disks attached to the instance.
+     */
+    pub async fn instance_list_disks(
+        &self,
+        opctx: &OpContext,
+        organization_name: &Name,
+        project_name: &Name,
+        instance_name: &Name,
+        pagparams: &DataPageParams<'_, Name>,
+    ) -> ListResultVec<db::model::Disk> {
+        let authz_instance = self
+            .db_datastore
+            .instance_lookup_by_path(
+                organization_name,
+                project_name,
+                instance_name,
+            )
+            .await?;
+        self.db_datastore
+            .instance_list_disks(opctx, &authz_instance, pagparams)
+            .await
+    }
+
+    /**
+     * Attach a disk to an instance.
+     */
+    pub async fn instance_attach_disk(
+        &self,
+        opctx: &OpContext,
+        organization_name: &Name,
+        project_name: &Name,
+        instance_name: &Name,
+        disk_name: &Name,
+    ) -> UpdateResult<db::model::Disk> {
+        // TODO: This shouldn't be looking up multiple database entries by name,
+        // it should resolve names to IDs first.
+        let authz_project = self
+            .db_datastore
+            .project_lookup_by_path(organization_name, project_name)
+            .await?;
+        let (authz_disk, db_disk) = self
+            .db_datastore
+            .disk_fetch(opctx, &authz_project, disk_name)
+            .await?;
+        let (authz_instance, db_instance) = self
+            .db_datastore
+            .instance_fetch(opctx, &authz_project, instance_name)
+            .await?;
+        opctx.audit_resource(authz_disk.id());
+        let instance_id = &authz_instance.id();
+
+        fn disk_attachment_error(
+            disk: &db::model::Disk,
+        ) -> CreateResult<db::model::Disk> {
+            let disk_status = match disk.runtime().state().into() {
+                DiskState::Destroyed => "disk is destroyed",
+                DiskState::Faulted => "disk is faulted",
+                DiskState::Creating => "disk is detached",
+                DiskState::Detached => "disk is detached",
+
+                /*
+                 * It would be nice to provide a more specific message here, but
+                 * the appropriate identifier to provide the user would be the
+                 * other instance's name.  Getting that would require another
+                 * database hit, which doesn't seem worth it for this.
+                 */
+                DiskState::Attaching(_) => {
+                    "disk is attached to another instance"
+                }
+                DiskState::Attached(_) => {
+                    "disk is attached to another instance"
+                }
+                DiskState::Detaching(_) => {
+                    "disk is attached to another instance"
+                }
+            };
+            let message = format!(
+                "cannot attach disk \"{}\": {}",
+                disk.name().as_str(),
+                disk_status
+            );
+            Err(Error::InvalidRequest { message })
+        }
+
+        match &db_disk.state().into() {
+            /*
+             * If we're already attaching or attached to the requested instance,
+             * there's nothing else to do.
+             * TODO-security should it be an error if you're not authorized to
+             * do this and we did not actually have to do anything?
+             */
+            DiskState::Attached(id) if id == instance_id => return Ok(db_disk),
+
+            /*
+             * If the disk is currently attaching or attached to another
+             * instance, fail this request.  Users must explicitly detach first
+             * if that's what they want.  If it's detaching, they have to wait
+             * for it to become detached.
+             * TODO-debug: the error message here could be better.  We'd have to
+             * look up the other instance by id (and gracefully handle it not
+             * existing).
+             */
+            DiskState::Attached(id) => {
+                assert_ne!(id, instance_id);
+                return disk_attachment_error(&db_disk);
+            }
+            DiskState::Detaching(_) => {
+                return disk_attachment_error(&db_disk);
+            }
+            DiskState::Attaching(id) if id != instance_id => {
+                return disk_attachment_error(&db_disk);
+            }
+            DiskState::Destroyed => {
+                return disk_attachment_error(&db_disk);
+            }
+            DiskState::Faulted => {
+                return disk_attachment_error(&db_disk);
+            }
+
+            DiskState::Creating => (),
+            DiskState::Detached => (),
+            DiskState::Attaching(id) => {
+                assert_eq!(id, instance_id);
+            }
+        }
+
+        self.disk_set_runtime(
+            opctx,
+            &authz_disk,
+            &db_disk,
+            self.instance_sled(&db_instance).await?,
+            sled_agent_client::types::DiskStateRequested::Attached(
+                *instance_id,
+            ),
+        )
+        .await?;
+        self.db_datastore.disk_refetch(opctx, &authz_disk).await
+    }
+
+    /**
+     * Detach a disk from an instance.
+     */
+    pub async fn instance_detach_disk(
+        &self,
+        opctx: &OpContext,
+        organization_name: &Name,
+        project_name: &Name,
+        instance_name: &Name,
+        disk_name: &Name,
+    ) -> UpdateResult<db::model::Disk> {
+        // TODO: This shouldn't be looking up multiple database entries by name,
+        // it should resolve names to IDs first.
+        let authz_project = self
+            .db_datastore
+            .project_lookup_by_path(organization_name, project_name)
+            .await?;
+        let (authz_disk, db_disk) = self
+            .db_datastore
+            .disk_fetch(opctx, &authz_project, disk_name)
+            .await?;
+        let (authz_instance, db_instance) = self
+            .db_datastore
+            .instance_fetch(opctx, &authz_project, instance_name)
+            .await?;
+        opctx.audit_resource(authz_disk.id());
+        let instance_id = &authz_instance.id();
+
+        match &db_disk.state().into() {
+            /*
+             * This operation is a noop if the disk is not attached or already
+             * detaching from the same instance.
+             * TODO-security should it be an error if you're not authorized to
+             * do this and we did not actually have to do anything?
+             */
+            DiskState::Creating => return Ok(db_disk),
+            DiskState::Detached => return Ok(db_disk),
+            DiskState::Destroyed => return Ok(db_disk),
+            DiskState::Faulted => return Ok(db_disk),
+            DiskState::Detaching(id) if id == instance_id => {
+                return Ok(db_disk)
+            }
+
+            /*
+             * This operation is not allowed if the disk is attached to some
+             * other instance.
+             */
+            DiskState::Attaching(id) if id != instance_id => {
+                return Err(Error::InvalidRequest {
+                    message: String::from("disk is attached elsewhere"),
+                });
+            }
+            DiskState::Attached(id) if id != instance_id => {
+                return Err(Error::InvalidRequest {
+                    message: String::from("disk is attached elsewhere"),
+                });
+            }
+            DiskState::Detaching(_) => {
+                return Err(Error::InvalidRequest {
+                    message: String::from("disk is attached elsewhere"),
+                });
+            }
+
+            /* These are the cases where we have to do something. */
+            DiskState::Attaching(_) => (),
+            DiskState::Attached(_) => (),
+        }
+
+        self.disk_set_runtime(
+            opctx,
+            &authz_disk,
+            &db_disk,
+            self.instance_sled(&db_instance).await?,
+            sled_agent_client::types::DiskStateRequested::Detached,
+        )
+        .await?;
+        self.db_datastore.disk_refetch(opctx, &authz_disk).await
+    }
+
+    /**
+     * Modifies the runtime state of the Disk as requested.  This generally
+     * means attaching or detaching the disk.
+     */
+    async fn disk_set_runtime(
+        &self,
+        opctx: &OpContext,
+        authz_disk: &authz::Disk,
+        db_disk: &db::model::Disk,
+        sa: Arc<SledAgentClient>,
+        requested: sled_agent_client::types::DiskStateRequested,
+    ) -> Result<(), Error> {
+        let runtime: DiskRuntimeState = db_disk.runtime().into();
+
+        opctx.authorize(authz::Action::Modify, authz_disk).await?;
+
+        /*
+         * Ask the Sled Agent to begin the state change.  Then update the
+         * database to reflect the new intermediate state.
+         */
+        let new_runtime = sa
+            .disk_put(
+                &authz_disk.id(),
+                &sled_agent_client::types::DiskEnsureBody {
+                    initial_runtime:
+                        sled_agent_client::types::DiskRuntimeState::from(
+                            runtime,
+                        ),
+                    target: requested,
+                },
+            )
+            .await
+            .map_err(Error::from)?;
+
+        let new_runtime: DiskRuntimeState = new_runtime.into_inner().into();
+        let new_state = new_runtime.disk_state.to_string();
+
+        let updated = self
+            .db_datastore
+            .disk_update_runtime(opctx, authz_disk, &new_runtime.into())
+            .await?;
+        if updated {
+            self.project_event_publish(
+                authz_disk.project().id(),
+                ResourceType::Disk,
+                authz_disk.id(),
+                EventKind::StateChanged(new_state),
+            )
+            .await;
+        }
+        Ok(())
+    }
+
+    /*
+     * Network interfaces
+     */
+
+    /**
+     * Returns an error unless the Instance is stopped, which it must be for
+     * its network interfaces to change
+     */
+    fn instance_check_interfaces_changeable(
+        db_instance: &db::model::Instance,
+    ) -> Result<(), Error> {
+        let state_error = match db_instance.runtime().state.state() {
+            InstanceState::Stopped => return Ok(()),
+            InstanceState::Creating | InstanceState::Starting => {
+                "instance is starting"
+            }
+            InstanceState::Running | InstanceState::Rebooting => {
+                "instance is running; stop it first"
+            }
+            InstanceState::Stopping => "instance is stopping",
+            InstanceState::Migrating => "instance is migrating",
+            InstanceState::Repairing => "instance is being repaired",
+            InstanceState::Failed => "instance has failed",
+            InstanceState::Destroyed => "instance has been destroyed",
+        };
+        Err(Error::InvalidRequest {
+            message: format!(
+                "cannot change network interfaces of instance \"{}\": {}",
+                db_instance.name().as_str(),
+                state_error
+            ),
+        })
+    }
+
+    pub async fn instance_list_network_interfaces(
+        &self,
+        opctx: &OpContext,
+        organization_name: &Name,
+        project_name: &Name,
+        instance_name: &Name,
+        pagparams: &DataPageParams<'_, Name>,
+    ) -> ListResultVec<external::NetworkInterface> {
+        let authz_instance = self
+            .db_datastore
+            .instance_lookup_by_path(
+                organization_name,
+                project_name,
+                instance_name,
+            )
+            .await?;
+        let interfaces = self
+            .db_datastore
+            .instance_list_network_interfaces(opctx, &authz_instance, pagparams)
+            .await?;
+        self.network_interfaces_view(interfaces).await
+    }
+
+    pub async fn instance_fetch_network_interface(
+        &self,
+        opctx: &OpContext,
+        organization_name: &Name,
+        project_name: &Name,
+        instance_name: &Name,
+        interface_name: &Name,
+    ) -> LookupResult<external::NetworkInterface> {
+        let authz_instance = self
+            .db_datastore
+            .instance_lookup_by_path(
+                organization_name,
+                project_name,
+                instance_name,
+            )
+            .await?;
+        let interface = self
+            .db_datastore
+            .instance_fetch_network_interface(
+                opctx,
+                &authz_instance,
+                interface_name,
+            )
+            .await?;
+        let mut views = self.network_interfaces_view(vec![interface]).await?;
+        Ok(views.pop().unwrap())
+    }
+
+    /**
+     * Converts network interfaces into their external views, filling in the
+     * address of the Floating IP attached to each one, if any
+     */
+    async fn network_interfaces_view(
+        &self,
+        interfaces: Vec<db::model::NetworkInterface>,
+    ) -> ListResultVec<external::NetworkInterface> {
+        let external_ips = self
+            .db_datastore
+            .network_interfaces_list_floating_ips(
+                interfaces.iter().map(|interface| interface.id()).collect(),
+            )
+            .await?
+            .into_iter()
+            .filter_map(|floating_ip| {
+                floating_ip
+                    .network_interface_id
+                    .map(|interface_id| (interface_id, floating_ip.ip.ip()))
+            })
+            .collect::<BTreeMap<_, _>>();
+        Ok(interfaces
+            .into_iter()
+            .map(|interface| {
+                let external_ip = external_ips.get(&interface.id()).copied();
+                external::NetworkInterface { external_ip, ..interface.into() }
+            })
+            .collect())
+    }
+
+    /**
+     * Creates a new network interface for a stopped Instance
+     */
+    pub async fn instance_create_network_interface(
+        &self,
+        opctx: &OpContext,
+        organization_name: &Name,
+        project_name: &Name,
+        instance_name: &Name,
+        params: &params::NetworkInterfaceCreate,
+    ) -> CreateResult<db::model::NetworkInterface> {
+        params.identity.check_no_labels(ResourceType::NetworkInterface)?;
+        let authz_project = self
+            .db_datastore
+            .project_lookup_by_path(organization_name, project_name)
+            .await?;
+        let (authz_instance, db_instance) = self
+            .db_datastore
+            .instance_fetch(opctx, &authz_project, instance_name)
+            .await?;
+        opctx.authorize(authz::Action::Modify, &authz_instance).await?;
+        Self::instance_check_interfaces_changeable(&db_instance)?;
+
+        /*
+         * TODO-correctness Two concurrent requests can each see room for one
+         * more interface, leaving the Instance with one too many.
+         */
+        let existing = self
+            .db_datastore
+            .instance_list_all_network_interfaces(&authz_instance.id())
+            .await?;
+        if existing.len() >= params::MAX_NICS_PER_INSTANCE {
+            return Err(Error::InvalidRequest {
+                message: format!(
+                    "an instance may have at most {} network interfaces",
+                    params::MAX_NICS_PER_INSTANCE
+                ),
+            });
+        }
+
+        let vpc = self
+            .db_datastore
+            .vpc_fetch_by_name(
+                &authz_project.id(),
+                &db::model::Name(params.vpc_name.clone()),
+            )
+            .await?;
+        let subnet = self
+            .db_datastore
+            .vpc_subnet_fetch_by_name(
+                &vpc.id(),
+                &db::model::Name(params.subnet_name.clone()),
+            )
+            .await?;
+
+        let mac = db::model::MacAddr::new()?;
+        let interface_id = Uuid::new_v4();
+        let interface = db::model::IncompleteNetworkInterface::new(
+            interface_id,
+            authz_instance.id(),
+            vpc.id(),
+            subnet,
+            mac,
+            params.ip,
+            params.clone(),
+        );
+        let interface = self
+            .db_datastore
+            .instance_create_network_interface(interface)
+            .await?;
+        opctx.audit_resource(interface.id());
+        self.vpc_firewall_rules_push(&vpc).await;
+        self.dns_zone_push().await;
+        Ok(interface)
+    }
+
+    /**
+     * Deletes a network interface from a stopped Instance
+     */
+    pub async fn instance_delete_network_interface(
+        &self,
+        opctx: &OpContext,
+        organization_name: &Name,
+        project_name: &Name,
+        instance_name: &Name,
+        interface_name: &Name,
+    ) -> DeleteResult {
+        let authz_project = self
+            .db_datastore
+            .project_lookup_by_path(organization_name, project_name)
+            .await?;
+        let (authz_instance, db_instance) = self
+            .db_datastore
+            .instance_fetch(opctx, &authz_project, instance_name)
+            .await?;
+        opctx.authorize(authz::Action::Modify, &authz_instance).await?;
+        let interface = self
+            .db_datastore
+            .instance_fetch_network_interface(
+                opctx,
+                &authz_instance,
+                interface_name,
+            )
+            .await?;
+        Self::instance_check_interfaces_changeable(&db_instance)?;
+        opctx.audit_resource(interface.id());
+        self.db_datastore
+            .network_interface_detach_floating_ips(&interface.id())
+            .await?;
+        self.db_datastore
+            .instance_delete_network_interface(&interface.id())
+            .await?;
+
+        self.sled_firewall_rules_clear(
+            db_instance.runtime().sled_uuid,
+            vec![interface.id()],
+        )
+        .await;
+        self.vpcs_firewall_rules_push(BTreeSet::from([interface.vpc_id])).await;
+        self.dns_zone_push().await;
+        Ok(())
+    }
+
+    /*
+     * IP pools
+     */
+
+    pub async fn ip_pools_list(
+        &self,
+        opctx: &OpContext,
+        pagparams: &DataPageParams<'_, Name>,
+    ) -> ListResultVec<db::model::IpPool> {
+        self.db_datastore.ip_pools_list_by_name(opctx, pagparams).await
+    }
+
+    pub async fn ip_pool_create(
+        &self,
+        opctx: &OpContext,
+        params: &params::IpPoolCreate,
+    ) -> CreateResult<db::model::IpPool> {
+        params.identity.check_no_labels(ResourceType::IpPool)?;
+        let pool = db::model::IpPool::new(Uuid::new_v4(), params.clone());
+        let pool = self.db_datastore.ip_pool_create(opctx, pool).await?;
+        opctx.audit_resource(pool.id());
+        Ok(pool)
+    }
+
+    pub async fn ip_pool_fetch(
+        &self,
+        opctx: &OpContext,
+        pool_name: &Name,
+    ) -> LookupResult<db::model::IpPool> {
+        self.db_datastore.ip_pool_fetch(opctx, pool_name).await
+    }
+
+    pub async fn ip_pool_delete(
+        &self,
+        opctx: &OpContext,
+        pool_name: &Name,
+    ) -> DeleteResult {
+        let pool = self.db_datastore.ip_pool_fetch(opctx, pool_name).await?;
+        opctx.audit_resource(pool.id());
+        self.db_datastore.ip_pool_delete(opctx, pool.id()).await
+    }
 
-        if allowed {
-            Ok(())
-        } else {
-            Err(Error::InvalidRequest {
-                message: format!(
-                    "instance state cannot be changed from state \"{}\"",
-                    runtime.run_state
-                ),
-            })
-        }
+    pub async fn ip_pool_list_ranges(
+        &self,
+        opctx: &OpContext,
+        pool_name: &Name,
+        pagparams: &DataPageParams<'_, Uuid>,
+    ) -> ListResultVec<db::model::IpPoolRange> {
+        let pool = self.db_datastore.ip_pool_fetch(opctx, pool_name).await?;
+        self.db_datastore.ip_pool_list_ranges(opctx, &pool, pagparams).await
     }
 
-    pub async fn sled_client(
+    pub async fn ip_pool_add_range(
         &self,
-        id: &Uuid,
-    ) -> Result<Arc<SledAgentClient>, Error> {
-        // TODO: We should consider injecting connection pooling here,
-        // but for now, connections to sled agents are constructed
-        // on an "as requested" basis.
-        //
-        // Franky, returning an "Arc" here without a connection pool is a little
-        // silly; it's not actually used if each client connection exists as a
-        // one-shot.
-        let sled = self.sled_lookup(id).await?;
+        opctx: &OpContext,
+        pool_name: &Name,
+        params: &params::IpPoolRangeCreate,
+    ) -> CreateResult<db::model::IpPoolRange> {
+        let pool = self.db_datastore.ip_pool_fetch(opctx, pool_name).await?;
+        let (first, last) = (params.first, params.last);
+        let span = match (first, last) {
+            (IpAddr::V4(first), IpAddr::V4(last)) => {
+                u128::from(u32::from(last))
+                    .checked_sub(u128::from(u32::from(first)))
+            }
+            (IpAddr::V6(first), IpAddr::V6(last)) => {
+                u128::from(last).checked_sub(u128::from(first))
+            }
+            _ => {
+                return Err(Error::InvalidRequest {
+                    message: "the first and last addresses of an IP pool \
+                        range must be in the same address family"
+                        .to_string(),
+                });
+            }
+        };
+        let span = span.ok_or_else(|| Error::InvalidRequest {
+            message: "the last address of an IP pool range must not be less \
+                than the first"
+                .to_string(),
+        })?;
+        /*
+         * Addresses are allocated by counting from the start of each range in
+         * the database, which can't count past a 64-bit integer.
+         */
+        if span > i64::MAX as u128 {
+            return Err(Error::InvalidRequest {
+                message: "IP pool range is too large".to_string(),
+            });
+        }
+        let range = db::model::IpPoolRange::new(pool.id(), first, last);
+        let range = self.db_datastore.ip_pool_add_range(opctx, range).await?;
+        opctx.audit_resource(range.id);
+        Ok(range)
+    }
 
-        let log = self.log.new(o!("SledAgent" => id.clone().to_string()));
-        let dur = std::time::Duration::from_secs(60);
-        let client = reqwest::ClientBuilder::new()
-            .connect_timeout(dur)
-            .timeout(dur)
-            .build()
-            .unwrap();
-        Ok(Arc::new(SledAgentClient::new_with_client(
-            &format!("http://{}", sled.address()),
-            client,
-            log,
-        )))
+    pub async fn ip_pool_delete_range(
+        &self,
+        opctx: &OpContext,
+        pool_name: &Name,
+        range_id: Uuid,
+    ) -> DeleteResult {
+        let pool = self.db_datastore.ip_pool_fetch(opctx, pool_name).await?;
+        opctx.audit_resource(range_id);
+        self.db_datastore.ip_pool_delete_range(opctx, &pool, range_id).await
     }
 
-    /**
-     * Returns the SledAgentClient for the host where this Instance is running.
+    /*
+     * Floating IPs
      */
-    async fn instance_sled(
+
+    pub async fn project_list_floating_ips(
         &self,
-        instance: &db::model::Instance,
-    ) -> Result<Arc<SledAgentClient>, Error> {
-        let sa_id = &instance.runtime().sled_uuid;
-        self.sled_client(&sa_id).await
+        opctx: &OpContext,
+        organization_name: &Name,
+        project_name: &Name,
+        pagparams: &DataPageParams<'_, Name>,
+    ) -> ListResultVec<db::model::FloatingIp> {
+        let authz_project = self
+            .db_datastore
+            .project_lookup_by_path(organization_name, project_name)
+            .await?;
+        self.db_datastore
+            .project_list_floating_ips(opctx, &authz_project, pagparams)
+            .await
     }
 
     /**
-     * Reboot the specified instance.
+     * Allocates a Floating IP in a Project from an IP pool
      */
-    pub async fn instance_reboot(
+    pub async fn project_create_floating_ip(
         &self,
         opctx: &OpContext,
         organization_name: &Name,
         project_name: &Name,
-        instance_name: &Name,
-    ) -> UpdateResult<db::model::Instance> {
-        /*
-         * To implement reboot, we issue a call to the sled agent to set a
-         * runtime state of "reboot". We cannot simply stop the Instance and
-         * start it again here because if we crash in the meantime, we might
-         * leave it stopped.
-         *
-         * When an instance is rebooted, the "rebooting" flag remains set on
-         * the runtime state as it transitions to "Stopping" and "Stopped".
-         * This flag is cleared when the state goes to "Starting".  This way,
-         * even if the whole rack powered off while this was going on, we would
-         * never lose track of the fact that this Instance was supposed to be
-         * running.
-         */
+        params: &params::FloatingIpCreate,
+    ) -> CreateResult<db::model::FloatingIp> {
+        params.identity.check_no_labels(ResourceType::FloatingIp)?;
         let authz_project = self
             .db_datastore
             .project_lookup_by_path(organization_name, project_name)
             .await?;
-        let (authz_instance, db_instance) = self
+        opctx.authorize(authz::Action::CreateChild, &authz_project).await?;
+        let pool = self
             .db_datastore
-            .instance_fetch(opctx, &authz_project, instance_name)
+            .ip_pool_lookup_noauthz(&db::model::Name(params.pool_name.clone()))
             .await?;
-        let requested = InstanceRuntimeStateRequested {
-            run_state: InstanceStateRequested::Reboot,
-            migration_params: None,
-        };
-        self.instance_set_runtime(
-            opctx,
-            &authz_instance,
-            &db_instance,
-            requested,
-        )
-        .await?;
-        self.db_datastore.instance_refetch(opctx, &authz_instance).await
+        let floating_ip = db::model::IncompleteFloatingIp::new(
+            Uuid::new_v4(),
+            authz_project.id(),
+            pool.id(),
+            params.clone(),
+        );
+        let floating_ip =
+            self.db_datastore.project_create_floating_ip(floating_ip).await?;
+        opctx.audit_resource(floating_ip.id());
+        Ok(floating_ip)
     }
 
-    /**
-     * Make sure the given Instance is running.
-     */
-    pub async fn instance_start(
+    pub async fn project_fetch_floating_ip(
         &self,
         opctx: &OpContext,
         organization_name: &Name,
         project_name: &Name,
-        instance_name: &Name,
-    ) -> UpdateResult<db::model::Instance> {
+        floating_ip_name: &Name,
+    ) -> LookupResult<db::model::FloatingIp> {
         let authz_project = self
             .db_datastore
             .project_lookup_by_path(organization_name, project_name)
             .await?;
-        let (authz_instance, db_instance) = self
+        Ok(self
             .db_datastore
-            .instance_fetch(opctx, &authz_project, instance_name)
-            .await?;
-        let requested = InstanceRuntimeStateRequested {
-            run_state: InstanceStateRequested::Running,
-            migration_params: None,
-        };
-        self.instance_set_runtime(
-            opctx,
-            &authz_instance,
-            &db_instance,
-            requested,
-        )
-        .await?;
-        self.db_datastore.instance_refetch(opctx, &authz_instance).await
+            .floating_ip_fetch(opctx, &authz_project, floating_ip_name)
+            .await?
+            .1)
     }
 
     /**
-     * Make sure the given Instance is stopped.
+     * Deletes a Floating IP, returning its address to its pool
+     *
+     * If it's attached to an Instance, the Instance loses the address.
      */
-    pub async fn instance_stop(
+    pub async fn project_delete_floating_ip(
         &self,
         opctx: &OpContext,
         organization_name: &Name,
         project_name: &Name,
-        instance_name: &Name,
-    ) -> UpdateResult<db::model::Instance> {
+        floating_ip_name: &Name,
+    ) -> DeleteResult {
         let authz_project = self
             .db_datastore
             .project_lookup_by_path(organization_name, project_name)
             .await?;
-        let (authz_instance, db_instance) = self
+        let (authz_floating_ip, _) = self
             .db_datastore
-            .instance_fetch(opctx, &authz_project, instance_name)
+            .floating_ip_fetch(opctx, &authz_project, floating_ip_name)
             .await?;
-        let requested = InstanceRuntimeStateRequested {
-            run_state: InstanceStateRequested::Stopped,
-            migration_params: None,
-        };
-        self.instance_set_runtime(
-            opctx,
-            &authz_instance,
-            &db_instance,
-            requested,
-        )
-        .await?;
-        self.db_datastore.instance_refetch(opctx, &authz_instance).await
+        opctx.audit_resource(authz_floating_ip.id());
+        self.db_datastore
+            .project_delete_floating_ip(opctx, &authz_floating_ip)
+            .await
     }
 
     /**
-     * Idempotently place the instance in a 'Migrating' state.
+     * Attaches a Floating IP to the primary network interface of a stopped
+     * Instance in the same Project
      */
-    pub async fn instance_start_migrate(
+    pub async fn floating_ip_attach(
         &self,
         opctx: &OpContext,
-        instance_id: Uuid,
-        migration_id: Uuid,
-        dst_propolis_id: Uuid,
-    ) -> UpdateResult<db::model::Instance> {
-        let authz_instance =
-            self.db_datastore.instance_lookup_by_id(instance_id).await?;
-        let db_instance =
-            self.db_datastore.instance_refetch(opctx, &authz_instance).await?;
-        let requested = InstanceRuntimeStateRequested {
-            run_state: InstanceStateRequested::Migrating,
-            migration_params: Some(InstanceRuntimeStateMigrateParams {
-                migration_id,
-                dst_propolis_id,
-            }),
-        };
-        self.instance_set_runtime(
-            opctx,
-            &authz_instance,
-            &db_instance,
-            requested,
-        )
-        .await?;
-        self.db_datastore.instance_refetch(opctx, &authz_instance).await
+        organization_name: &Name,
+        project_name: &Name,
+        floating_ip_name: &Name,
+        params: &params::FloatingIpAttach,
+    ) -> UpdateResult<db::model::FloatingIp> {
+        let authz_project = self
+            .db_datastore
+            .project_lookup_by_path(organization_name, project_name)
+            .await?;
+        let (authz_floating_ip, _) = self
+            .db_datastore
+            .floating_ip_fetch(opctx, &authz_project, floating_ip_name)
+            .await?;
+        let (authz_instance, db_instance) = self
+            .db_datastore
+            .instance_fetch(
+                opctx,
+                &authz_project,
+                &db::model::Name(params.instance_name.clone()),
+            )
+            .await?;
+        opctx.authorize(authz::Action::Modify, &authz_instance).await?;
+        Self::instance_check_interfaces_changeable(&db_instance)?;
+        let interface = self
+            .db_datastore
+            .instance_fetch_primary_network_interface(&authz_instance.id())
+            .await?;
+        opctx.audit_resource(authz_floating_ip.id());
+        self.db_datastore
+            .floating_ip_attach(opctx, &authz_floating_ip, &interface)
+            .await
     }
 
     /**
-     * Modifies the runtime state of the Instance as requested.  This generally
-     * means booting or halting the Instance.
+     * Detaches a Floating IP from the stopped Instance it's attached to
      */
-    async fn instance_set_runtime(
+    pub async fn floating_ip_detach(
         &self,
         opctx: &OpContext,
-        authz_instance: &authz::Instance,
-        db_instance: &db::model::Instance,
-        requested: InstanceRuntimeStateRequested,
-    ) -> Result<(), Error> {
-        opctx.authorize(authz::Action::Modify, authz_instance).await?;
-
-        self.check_runtime_change_allowed(
-            &db_instance.runtime().clone().into(),
-            &requested,
-        )?;
-
-        let sa = self.instance_sled(&db_instance).await?;
-
-        /*
-         * Ask the sled agent to begin the state change.  Then update the
-         * database to reflect the new intermediate state.  If this update is
-         * not the newest one, that's fine.  That might just mean the sled agent
-         * beat us to it.
-         */
-
-        let runtime: nexus::InstanceRuntimeState =
-            db_instance.runtime().clone().into();
-
-        // TODO: Populate this with an appropriate NIC.
-        // See also: sic_create_instance_record in sagas.rs for a similar
-        // construction.
-        let instance_hardware = sled_agent_client::types::InstanceHardware {
-            runtime: sled_agent_client::types::InstanceRuntimeState::from(
-                runtime,
-            ),
-            nics: vec![],
-        };
-
-        let new_runtime = sa
-            .instance_put(
-                &db_instance.id(),
-                &sled_agent_client::types::InstanceEnsureBody {
-                    initial: instance_hardware,
-                    target: requested.into(),
-                    migrate: None,
-                },
-            )
-            .await
-            .map_err(Error::from)?;
-
-        let new_runtime: nexus::InstanceRuntimeState =
-            new_runtime.into_inner().into();
-
-        self.db_datastore
-            .instance_update_runtime(&db_instance.id(), &new_runtime.into())
-            .await
-            .map(|_| ())
+        organization_name: &Name,
+        project_name: &Name,
+        floating_ip_name: &Name,
+    ) -> UpdateResult<db::model::FloatingIp> {
+        let authz_project = self
+            .db_datastore
+            .project_lookup_by_path(organization_name, project_name)
+            .await?;
+        let (authz_floating_ip, db_floating_ip) = self
+            .db_datastore
+            .floating_ip_fetch(opctx, &authz_project, floating_ip_name)
+            .await?;
+        let instance_id = db_floating_ip.instance_id.ok_or_else(|| {
+            Error::InvalidRequest {
+                message: "floating IP is not attached".to_string(),
+            }
+        })?;
+        let db_instance = self
+            .db_datastore
+            .instance_list_all_by_ids(vec![instance_id])
+            .await?
+            .pop()
+            .ok_or_else(|| {
+                Error::internal_error(&format!(
+                    "floating IP attached to missing instance {}",
+                    instance_id
+                ))
+            })?;
+        Self::instance_check_interfaces_changeable(&db_instance)?;
+        opctx.audit_resource(authz_floating_ip.id());
+        self.db_datastore.floating_ip_detach(opctx, &authz_floating_ip).await
     }
 
-    /**
-     * Lists disks attached to the instance.
+    /*
+     * Affinity groups
      */
-    pub async fn instance_list_disks(
+
+    pub async fn project_list_affinity_groups(
         &self,
         opctx: &OpContext,
         organization_name: &Name,
         project_name: &Name,
-        instance_name: &Name,
         pagparams: &DataPageParams<'_, Name>,
-    ) -> ListResultVec<db::model::Disk> {
-        let authz_instance = self
+    ) -> ListResultVec<db::model::AffinityGroup> {
+        let authz_project = self
             .db_datastore
-            .instance_lookup_by_path(
-                organization_name,
-                project_name,
-                instance_name,
-            )
+            .project_lookup_by_path(organization_name, project_name)
             .await?;
+        opctx.authorize(authz::Action::ListChildren, &authz_project).await?;
         self.db_datastore
-            .instance_list_disks(opctx, &authz_instance, pagparams)
+            .project_list_affinity_groups(&authz_project.id(), pagparams)
             .await
     }
 
-    /**
-     * Attach a disk to an instance.
-     */
-    pub async fn instance_attach_disk(
+    pub async fn project_create_affinity_group(
         &self,
         opctx: &OpContext,
         organization_name: &Name,
         project_name: &Name,
-        instance_name: &Name,
-        disk_name: &Name,
-    ) -> UpdateResult<db::model::Disk> {
-        // TODO: This shouldn't be looking up multiple database entries by name,
-        // it should resolve names to IDs first.
+        params: &params::AffinityGroupCreate,
+    ) -> CreateResult<db::model::AffinityGroup> {
+        params.identity.check_no_labels(ResourceType::AffinityGroup)?;
         let authz_project = self
             .db_datastore
             .project_lookup_by_path(organization_name, project_name)
             .await?;
-        let (authz_disk, db_disk) = self
-            .db_datastore
-            .disk_fetch(opctx, &authz_project, disk_name)
-            .await?;
-        let (authz_instance, db_instance) = self
-            .db_datastore
-            .instance_fetch(opctx, &authz_project, instance_name)
-            .await?;
-        let instance_id = &authz_instance.id();
-
-        fn disk_attachment_error(
-            disk: &db::model::Disk,
-        ) -> CreateResult<db::model::Disk> {
-            let disk_status = match disk.runtime().state().into() {
-                DiskState::Destroyed => "disk is destroyed",
-                DiskState::Faulted => "disk is faulted",
-                DiskState::Creating => "disk is detached",
-                DiskState::Detached => "disk is detached",
-
-                /*
-                 * It would be nice to provide a more specific message here, but
-                 * the appropriate identifier to provide the user would be the
-                 * other instance's name.  Getting that would require another
-                 * database hit, which doesn't seem worth it for this.
-                 */
-                DiskState::Attaching(_) => {
-                    "disk is attached to another instance"
-                }
-                DiskState::Attached(_) => {
-                    "disk is attached to another instance"
-                }
-                DiskState::Detaching(_) => {
-                    "disk is attached to another instance"
-                }
-            };
-            let message = format!(
-                "cannot attach disk \"{}\": {}",
-                disk.name().as_str(),
-                disk_status
-            );
-            Err(Error::InvalidRequest { message })
-        }
-
-        match &db_disk.state().into() {
-            /*
-             * If we're already attaching or attached to the requested instance,
-             * there's nothing else to do.
-             * TODO-security should it be an error if you're not authorized to
-             * do this and we did not actually have to do anything?
-             */
-            DiskState::Attached(id) if id == instance_id => return Ok(db_disk),
-
-            /*
-             * If the disk is currently attaching or attached to another
-             * instance, fail this request.  Users must explicitly detach first
-             * if that's what they want.  If it's detaching, they have to wait
-             * for it to become detached.
-             * TODO-debug: the error message here could be better.  We'd have to
-             * look up the other instance by id (and gracefully handle it not
-             * existing).
-             */
-            DiskState::Attached(id) => {
-                assert_ne!(id, instance_id);
-                return disk_attachment_error(&db_disk);
-            }
-            DiskState::Detaching(_) => {
-                return disk_attachment_error(&db_disk);
-            }
-            DiskState::Attaching(id) if id != instance_id => {
-                return disk_attachment_error(&db_disk);
-            }
-            DiskState::Destroyed => {
-                return disk_attachment_error(&db_disk);
-            }
-            DiskState::Faulted => {
-                return disk_attachment_error(&db_disk);
-            }
-
-            DiskState::Creating => (),
-            DiskState::Detached => (),
-            DiskState::Attaching(id) => {
-                assert_eq!(id, instance_id);
-            }
-        }
-
-        self.disk_set_runtime(
-            opctx,
-            &authz_disk,
-            &db_disk,
-            self.instance_sled(&db_instance).await?,
-            sled_agent_client::types::DiskStateRequested::Attached(
-                *instance_id,
-            ),
-        )
-        .await?;
-        self.db_datastore.disk_refetch(opctx, &authz_disk).await
+        opctx.authorize(authz::Action::CreateChild, &authz_project).await?;
+        let group = db::model::AffinityGroup::new(
+            Uuid::new_v4(),
+            authz_project.id(),
+            params.clone(),
+        );
+        self.db_datastore.project_create_affinity_group(group).await
     }
 
     /**
-     * Detach a disk from an instance.
+     * Looks up an affinity group that the caller is allowed to see, returning
+     * it along with an [`authz::AffinityGroup`] for further authz checks
      */
-    pub async fn instance_detach_disk(
+    async fn affinity_group_fetch(
         &self,
         opctx: &OpContext,
         organization_name: &Name,
         project_name: &Name,
-        instance_name: &Name,
-        disk_name: &Name,
-    ) -> UpdateResult<db::model::Disk> {
-        // TODO: This shouldn't be looking up multiple database entries by name,
-        // it should resolve names to IDs first.
+        group_name: &Name,
+    ) -> LookupResult<(authz::AffinityGroup, db::model::AffinityGroup)> {
         let authz_project = self
             .db_datastore
             .project_lookup_by_path(organization_name, project_name)
             .await?;
-        let (authz_disk, db_disk) = self
-            .db_datastore
-            .disk_fetch(opctx, &authz_project, disk_name)
-            .await?;
-        let (authz_instance, db_instance) = self
+        let db_group = self
             .db_datastore
-            .instance_fetch(opctx, &authz_project, instance_name)
+            .affinity_group_fetch_by_name(&authz_project.id(), group_name)
             .await?;
-        let instance_id = &authz_instance.id();
-
-        match &db_disk.state().into() {
-            /*
-             * This operation is a noop if the disk is not attached or already
-             * detaching from the same instance.
-             * TODO-security should it be an error if you're not authorized to
-             * do this and we did not actually have to do anything?
-             */
-            DiskState::Creating => return Ok(db_disk),
-            DiskState::Detached => return Ok(db_disk),
-            DiskState::Destroyed => return Ok(db_disk),
-            DiskState::Faulted => return Ok(db_disk),
-            DiskState::Detaching(id) if id == instance_id => {
-                return Ok(db_disk)
-            }
-
-            /*
-             * This operation is not allowed if the disk is attached to some
-             * other instance.
-             */
-            DiskState::Attaching(id) if id != instance_id => {
-                return Err(Error::InvalidRequest {
-                    message: String::from("disk is attached elsewhere"),
-                });
-            }
-            DiskState::Attached(id) if id != instance_id => {
-                return Err(Error::InvalidRequest {
-                    message: String::from("disk is attached elsewhere"),
-                });
-            }
-            DiskState::Detaching(_) => {
-                return Err(Error::InvalidRequest {
-                    message: String::from("disk is attached elsewhere"),
-                });
-            }
-
-            /* These are the cases where we have to do something. */
-            DiskState::Attaching(_) => (),
-            DiskState::Attached(_) => (),
-        }
-
-        self.disk_set_runtime(
-            opctx,
-            &authz_disk,
-            &db_disk,
-            self.instance_sled(&db_instance).await?,
-            sled_agent_client::types::DiskStateRequested::Detached,
-        )
-        .await?;
-        self.db_datastore.disk_refetch(opctx, &authz_disk).await
+        let authz_group = authz_project.child_generic(
+            ResourceType::AffinityGroup,
+            db_group.id(),
+            LookupType::from(&group_name.0),
+        );
+        opctx.authorize(authz::Action::Read, &authz_group).await?;
+        Ok((authz_group, db_group))
     }
 
-    /**
-     * Modifies the runtime state of the Disk as requested.  This generally
-     * means attaching or detaching the disk.
-     */
-    async fn disk_set_runtime(
+    pub async fn project_lookup_affinity_group(
         &self,
         opctx: &OpContext,
-        authz_disk: &authz::Disk,
-        db_disk: &db::model::Disk,
-        sa: Arc<SledAgentClient>,
-        requested: sled_agent_client::types::DiskStateRequested,
-    ) -> Result<(), Error> {
-        let runtime: DiskRuntimeState = db_disk.runtime().into();
-
-        opctx.authorize(authz::Action::Modify, authz_disk).await?;
-
-        /*
-         * Ask the Sled Agent to begin the state change.  Then update the
-         * database to reflect the new intermediate state.
-         */
-        let new_runtime = sa
-            .disk_put(
-                &authz_disk.id(),
-                &sled_agent_client::types::DiskEnsureBody {
-                    initial_runtime:
-                        sled_agent_client::types::DiskRuntimeState::from(
-                            runtime,
-                        ),
-                    target: requested,
-                },
+        organization_name: &Name,
+        project_name: &Name,
+        group_name: &Name,
+    ) -> LookupResult<db::model::AffinityGroup> {
+        Ok(self
+            .affinity_group_fetch(
+                opctx,
+                organization_name,
+                project_name,
+                group_name,
             )
-            .await
-            .map_err(Error::from)?;
-
-        let new_runtime: DiskRuntimeState = new_runtime.into_inner().into();
+            .await?
+            .1)
+    }
 
+    pub async fn project_update_affinity_group(
+        &self,
+        opctx: &OpContext,
+        organization_name: &Name,
+        project_name: &Name,
+        group_name: &Name,
+        params: &params::AffinityGroupUpdate,
+        preconditions: &Preconditions,
+    ) -> UpdateResult<db::model::AffinityGroup> {
+        params.identity.check_no_labels(ResourceType::AffinityGroup)?;
+        let (authz_group, _) = self
+            .affinity_group_fetch(
+                opctx,
+                organization_name,
+                project_name,
+                group_name,
+            )
+            .await?;
+        opctx.authorize(authz::Action::Modify, &authz_group).await?;
         self.db_datastore
-            .disk_update_runtime(opctx, authz_disk, &new_runtime.into())
+            .project_update_affinity_group(
+                &authz_group.id(),
+                params.clone().into(),
+                preconditions,
+            )
             .await
-            .map(|_| ())
     }
 
-    /**
-     * Creates a new network interface for this instance
-     */
-    pub async fn instance_create_network_interface(
+    pub async fn project_delete_affinity_group(
         &self,
+        opctx: &OpContext,
         organization_name: &Name,
         project_name: &Name,
-        instance_name: &Name,
-        vpc_name: &Name,
-        subnet_name: &Name,
-        params: &params::NetworkInterfaceCreate,
-    ) -> CreateResult<db::model::NetworkInterface> {
-        let authz_instance = self
-            .db_datastore
-            .instance_lookup_by_path(
+        group_name: &Name,
+    ) -> DeleteResult {
+        let (authz_group, _) = self
+            .affinity_group_fetch(
+                opctx,
                 organization_name,
                 project_name,
-                instance_name,
+                group_name,
             )
             .await?;
-        let vpc = self
-            .db_datastore
-            .vpc_fetch_by_name(&authz_instance.project().id(), vpc_name)
-            .await?;
-        let subnet = self
-            .db_datastore
-            .vpc_subnet_fetch_by_name(&vpc.id(), subnet_name)
-            .await?;
-
-        let mac = db::model::MacAddr::new()?;
-
-        let interface_id = Uuid::new_v4();
-        // Request an allocation
-        let ip = None;
-        let interface = db::model::IncompleteNetworkInterface::new(
-            interface_id,
-            authz_instance.id(),
-            // TODO-correctness: vpc_id here is used for name uniqueness. Should
-            // interface names be unique to the subnet's VPC or to the
-            // VPC associated with the instance's default interface?
-            vpc.id(),
-            subnet,
-            mac,
-            ip,
-            params.clone(),
-        );
-        self.db_datastore.instance_create_network_interface(interface).await
+        opctx.authorize(authz::Action::Delete, &authz_group).await?;
+        self.db_datastore.project_delete_affinity_group(&authz_group.id()).await
     }
 
+    /*
+     * VPCs
+     */
+
     pub async fn project_list_vpcs(
         &self,
         organization_name: &Name,
         project_name: &Name,
         pagparams: &DataPageParams<'_, Name>,
+        label_selector: &LabelSelector,
     ) -> ListResultVec<db::model::Vpc> {
         let project_id = self
             .db_datastore
             .project_lookup_by_path(organization_name, project_name)
             .await?
             .id();
-        let vpcs =
-            self.db_datastore.project_list_vpcs(&project_id, pagparams).await?;
+        let vpcs = self
+            .db_datastore
+            .project_list_vpcs(&project_id, pagparams, label_selector)
+            .await?;
         Ok(vpcs)
     }
 
@@ -1626,6 +3650,7 @@ impl Nexus {
                     description: "Routes are automatically added to this \
                         router as vpc subnets are created"
                         .into(),
+                    labels: Default::default(),
                 },
             },
         );
@@ -1638,6 +3663,7 @@ impl Nexus {
                 identity: IdentityMetadataCreateParams {
                     name: "default".parse().unwrap(),
                     description: "The default route of a vpc".to_string(),
+                    labels: Default::default(),
                 },
                 target: RouteTarget::InternetGateway(
                     "outbound".parse().unwrap(),
@@ -1683,6 +3709,7 @@ impl Nexus {
                     "The default subnet for {}",
                     params.identity.name
                 ),
+                labels: Default::default(),
             },
             *defaults::DEFAULT_VPC_SUBNET_IPV4_BLOCK,
             ipv6_block,
@@ -1722,7 +3749,9 @@ impl Nexus {
             *vpc_id,
             defaults::DEFAULT_FIREWALL_RULES.clone(),
         );
-        self.db_datastore.vpc_update_firewall_rules(&vpc_id, rules).await?;
+        self.db_datastore
+            .vpc_update_firewall_rules(&vpc_id, rules, &Preconditions::none())
+            .await?;
         Ok(())
     }
 
@@ -1746,6 +3775,7 @@ impl Nexus {
         project_name: &Name,
         vpc_name: &Name,
         params: &params::VpcUpdate,
+        preconditions: &Preconditions,
     ) -> UpdateResult<()> {
         let project_id = self
             .db_datastore
@@ -1754,10 +3784,16 @@ impl Nexus {
             .id();
         let vpc =
             self.db_datastore.vpc_fetch_by_name(&project_id, vpc_name).await?;
-        Ok(self
-            .db_datastore
-            .project_update_vpc(&vpc.id(), params.clone().into())
-            .await?)
+        self.db_datastore
+            .project_update_vpc(&vpc.id(), params.clone().into(), preconditions)
+            .await?;
+        if params.identity.name.is_some() {
+            self.project_firewall_rules_push(&project_id).await;
+        }
+        if params.dns_name.is_some() {
+            self.dns_zone_push().await;
+        }
+        Ok(())
     }
 
     pub async fn project_delete_vpc(
@@ -1765,41 +3801,62 @@ impl Nexus {
         organization_name: &Name,
         project_name: &Name,
         vpc_name: &Name,
+        preconditions: &Preconditions,
     ) -> DeleteResult {
         let vpc = self
             .project_lookup_vpc(organization_name, project_name, vpc_name)
             .await?;
         // TODO: This should eventually use a saga to call the
         // networking subsystem to have it clean up the networking resources
-        self.db_datastore.vpc_delete_router(&vpc.system_router_id).await?;
-        self.db_datastore.project_delete_vpc(&vpc.id()).await?;
+        //
+        // The VPC is deleted before its system router so that nothing is
+        // removed if the VPC does not satisfy the caller's preconditions.
+        self.db_datastore.project_delete_vpc(&vpc.id(), preconditions).await?;
+        self.db_datastore
+            .vpc_delete_router(&vpc.system_router_id, &Preconditions::none())
+            .await?;
 
         // Delete all firewall rules after deleting the VPC, to ensure no
         // firewall rules get added between rules deletion and VPC deletion.
         self.db_datastore.vpc_delete_all_firewall_rules(&vpc.id()).await
     }
 
+    /**
+     * Returns the firewall rules of a VPC, along with the VPC's
+     * `time_modified`, from which the rules' entity tag is derived
+     */
     pub async fn vpc_list_firewall_rules(
         &self,
         organization_name: &Name,
         project_name: &Name,
         vpc_name: &Name,
-    ) -> ListResultVec<db::model::VpcFirewallRule> {
+    ) -> LookupResult<(Vec<db::model::VpcFirewallRule>, DateTime<Utc>)> {
+        /*
+         * The VPC is read before its rules.  If the rules change in between,
+         * the caller gets the older entity tag with the newer rules, so a
+         * conditional update based on them fails rather than clobbering
+         * anything.
+         */
         let vpc = self
             .project_lookup_vpc(organization_name, project_name, vpc_name)
             .await?;
         let rules =
             self.db_datastore.vpc_list_firewall_rules(&vpc.id()).await?;
-        Ok(rules)
+        Ok((rules, vpc.time_modified()))
     }
 
+    /**
+     * Replaces the firewall rules of a VPC, returning the new rules along
+     * with the VPC's new `time_modified`
+     */
     pub async fn vpc_update_firewall_rules(
         &self,
         organization_name: &Name,
         project_name: &Name,
         vpc_name: &Name,
         params: &VpcFirewallRuleUpdateParams,
-    ) -> UpdateResult<Vec<db::model::VpcFirewallRule>> {
+        preconditions: &Preconditions,
+    ) -> UpdateResult<(Vec<db::model::VpcFirewallRule>, DateTime<Utc>)> {
         let vpc = self
             .project_lookup_vpc(organization_name, project_name, vpc_name)
             .await?;
@@ -1807,7 +3864,281 @@ impl Nexus {
             vpc.id(),
             params.clone(),
         );
-        self.db_datastore.vpc_update_firewall_rules(&vpc.id(), rules).await
+        let result = self
+            .db_datastore
+            .vpc_update_firewall_rules(&vpc.id(), rules, preconditions)
+            .await?;
+        self.vpc_firewall_rules_push(&vpc).await;
+        Ok(result)
+    }
+
+    /**
+     * Sends the firewall rules of every network interface in the VPC to the
+     * sleds hosting them
+     *
+     * This must be done whenever something that the rules resolve to changes:
+     * the rules themselves, the interfaces in the VPC, or the sleds on which
+     * their Instances run.  See [`firewall`] for how the rules are resolved.
+     *
+     * TODO-robustness Failures are logged but otherwise ignored, so a sled
+     * that misses an update keeps stale rules until the next one.  Something
+     * should periodically make sure that each sled has the rules it should.
+     */
+    pub async fn vpc_firewall_rules_push(&self, vpc: &db::model::Vpc) {
+        let sled_rules = match self.vpc_firewall_rules_resolve(vpc).await {
+            Ok(sled_rules) => sled_rules,
+            Err(error) => {
+                warn!(self.log, "failed to resolve firewall rules";
+                    "vpc_id" => vpc.id().to_string(), "error" => ?error);
+                return;
+            }
+        };
+        for (sled_id, interfaces) in sled_rules {
+            self.sled_firewall_rules_send(sled_id, interfaces).await;
+        }
+    }
+
+    /**
+     * Resolves the VPC's firewall rules for each of its network interfaces,
+     * grouping them by the sled hosting the interface
+     */
+    async fn vpc_firewall_rules_resolve(
+        &self,
+        vpc: &db::model::Vpc,
+    ) -> Result<BTreeMap<Uuid, Vec<NetworkInterfaceFirewallRules>>, Error> {
+        let rules: Vec<external::VpcFirewallRule> = self
+            .db_datastore
+            .vpc_list_firewall_rules(&vpc.id())
+            .await?
+            .into_iter()
+            .map(|rule| rule.into())
+            .collect();
+        let subnets = self
+            .db_datastore
+            .vpc_list_all_subnets(&vpc.id())
+            .await?
+            .iter()
+            .map(firewall::Subnet::from)
+            .collect();
+        let db_interfaces = self
+            .db_datastore
+            .vpc_list_all_network_interfaces(&vpc.id())
+            .await?;
+        let instance_ids = db_interfaces
+            .iter()
+            .map(|interface| interface.instance_id)
+            .collect::<BTreeSet<_>>();
+        let instances = self
+            .db_datastore
+            .instance_list_all_by_ids(instance_ids.into_iter().collect())
+            .await?
+            .into_iter()
+            .map(|instance| (instance.id(), instance))
+            .collect::<BTreeMap<_, _>>();
+
+        /*
+         * An interface whose Instance is gone (because it's being deleted) is
+         * about to go too, so there's no sled to send its rules to.
+         */
+        let mut interface_sleds = BTreeMap::new();
+        let mut interfaces = Vec::new();
+        for interface in &db_interfaces {
+            if let Some(instance) = instances.get(&interface.instance_id) {
+                interface_sleds
+                    .insert(interface.id(), instance.runtime().sled_uuid);
+                interfaces.push(firewall::Interface {
+                    id: interface.id(),
+                    instance_name: instance.name().0.clone(),
+                    subnet_id: interface.subnet_id,
+                    ip: interface.ip.ip(),
+                });
+            }
+        }
+
+        /*
+         * Host filters may name other VPCs in the same Project.  One that
+         * doesn't exist just matches nothing.
+         */
+        let other_vpc_names = rules
+            .iter()
+            .flat_map(|rule| rule.filters.hosts.iter().flatten())
+            .filter_map(|filter| match filter {
+                external::VpcFirewallRuleHostFilter::Vpc(name)
+                    if *name != vpc.name().0 =>
+                {
+                    Some(name.clone())
+                }
+                _ => None,
+            })
+            .collect::<BTreeSet<_>>();
+        let mut other_vpcs = BTreeMap::new();
+        for name in other_vpc_names {
+            let other_vpc = match self
+                .db_datastore
+                .vpc_fetch_by_name(&vpc.project_id, &Name(name.clone()))
+                .await
+            {
+                Ok(other_vpc) => other_vpc,
+                Err(Error::ObjectNotFound { .. }) => continue,
+                Err(error) => return Err(error),
+            };
+            let subnets = self
+                .db_datastore
+                .vpc_list_all_subnets(&other_vpc.id())
+                .await?
+                .iter()
+                .map(firewall::Subnet::from)
+                .collect();
+            other_vpcs.insert(name, subnets);
+        }
+
+        let network = firewall::VpcNetwork {
+            name: vpc.name().0.clone(),
+            subnets,
+            interfaces,
+            other_vpcs,
+        };
+        let mut sled_rules = BTreeMap::new();
+        for interface_rules in firewall::resolve_rules(&network, &rules) {
+            let sled_id = interface_sleds[&interface_rules.interface_id];
+            sled_rules
+                .entry(sled_id)
+                .or_insert_with(Vec::new)
+                .push(interface_rules);
+        }
+        Ok(sled_rules)
+    }
+
+    /**
+     * Like [`Nexus::vpc_firewall_rules_push()`], for every VPC in the Project
+     *
+     * This is needed when a VPC is renamed or its Subnets change, since the
+     * rules of any VPC in the Project may refer to it.
+     */
+    async fn project_firewall_rules_push(&self, project_id: &Uuid) {
+        match self.db_datastore.project_list_all_vpcs(project_id).await {
+            Ok(vpcs) => {
+                for vpc in vpcs {
+                    self.vpc_firewall_rules_push(&vpc).await;
+                }
+            }
+            Err(error) => {
+                warn!(self.log, "failed to list VPCs";
+                    "project_id" => project_id.to_string(), "error" => ?error);
+            }
+        }
+    }
+
+    /**
+     * Sends the firewall rules of every VPC in which the Instance has a
+     * network interface to the sleds hosting those VPCs' interfaces
+     */
+    pub async fn instance_firewall_rules_push(&self, instance_id: &Uuid) {
+        let vpc_ids = match self
+            .db_datastore
+            .instance_list_all_network_interfaces(instance_id)
+            .await
+        {
+            Ok(interfaces) => interfaces
+                .iter()
+                .map(|interface| interface.vpc_id)
+                .collect::<BTreeSet<_>>(),
+            Err(error) => {
+                warn!(self.log, "failed to list network interfaces";
+                    "instance_id" => instance_id.to_string(),
+                    "error" => ?error);
+                return;
+            }
+        };
+        self.vpcs_firewall_rules_push(vpc_ids).await;
+    }
+
+    /**
+     * Like [`Nexus::vpc_firewall_rules_push()`], for each of the given VPCs
+     */
+    async fn vpcs_firewall_rules_push(&self, vpc_ids: BTreeSet<Uuid>) {
+        for vpc_id in vpc_ids {
+            match self.db_datastore.vpc_fetch_by_id(&vpc_id).await {
+                Ok(vpc) => self.vpc_firewall_rules_push(&vpc).await,
+                Err(error) => {
+                    warn!(self.log, "failed to fetch VPC";
+                        "vpc_id" => vpc_id.to_string(), "error" => ?error);
+                }
+            }
+        }
+    }
+
+    /**
+     * Updates firewall rules after an Instance has moved to a different sled:
+     * its network interfaces' rules are sent to its new sled, and its old sled
+     * is told to forget them
+     */
+    pub async fn instance_firewall_rules_moved(
+        &self,
+        instance_id: &Uuid,
+        old_sled_id: Uuid,
+    ) {
+        self.instance_firewall_rules_push(instance_id).await;
+        match self
+            .db_datastore
+            .instance_list_all_network_interfaces(instance_id)
+            .await
+        {
+            Ok(interfaces) => {
+                let interface_ids =
+                    interfaces.iter().map(|interface| interface.id()).collect();
+                self.sled_firewall_rules_clear(old_sled_id, interface_ids)
+                    .await;
+            }
+            Err(error) => {
+                warn!(self.log, "failed to list network interfaces";
+                    "instance_id" => instance_id.to_string(),
+                    "error" => ?error);
+            }
+        }
+    }
+
+    /**
+     * Tells a sled to forget the firewall rules of network interfaces that it
+     * no longer hosts
+     */
+    async fn sled_firewall_rules_clear(
+        &self,
+        sled_id: Uuid,
+        interface_ids: Vec<Uuid>,
+    ) {
+        if interface_ids.is_empty() {
+            return;
+        }
+        let interfaces = interface_ids
+            .into_iter()
+            .map(|interface_id| NetworkInterfaceFirewallRules {
+                interface_id,
+                rules: vec![],
+            })
+            .collect();
+        self.sled_firewall_rules_send(sled_id, interfaces).await;
+    }
+
+    async fn sled_firewall_rules_send(
+        &self,
+        sled_id: Uuid,
+        interfaces: Vec<NetworkInterfaceFirewallRules>,
+    ) {
+        let body = FirewallRulesEnsureBody { interfaces };
+        let result = async {
+            self.sled_client(&sled_id)
+                .await?
+                .firewall_rules_put(&body.into())
+                .await
+                .map_err(Error::from)?;
+            Ok::<(), Error>(())
+        }
+        .await;
+        if let Err(error) = result {
+            warn!(self.log, "failed to send firewall rules to sled";
+                "sled_id" => sled_id.to_string(), "error" => ?error);
+        }
     }
 
     pub async fn vpc_list_subnets(
@@ -1850,6 +4181,7 @@ impl Nexus {
         vpc_name: &Name,
         params: &params::VpcSubnetCreate,
     ) -> CreateResult<db::model::VpcSubnet> {
+        params.identity.check_no_labels(ResourceType::VpcSubnet)?;
         let vpc = self
             .project_lookup_vpc(organization_name, project_name, vpc_name)
             .await?;
@@ -1888,7 +4220,7 @@ impl Nexus {
         // See <https://github.com/oxidecomputer/omicron/issues/685> for
         // details.
         let subnet_id = Uuid::new_v4();
-        match params.ipv6_block {
+        let subnet = match params.ipv6_block {
             None => {
                 const NUM_RETRIES: usize = 2;
                 let mut retry = 0;
@@ -1986,7 +4318,9 @@ impl Nexus {
                     },
                 )
             }
-        }
+        }?;
+        self.project_firewall_rules_push(&vpc.project_id).await;
+        Ok(subnet)
     }
 
     // TODO: When a subnet is deleted it should remove its entry from the VPC's system router.
@@ -1996,7 +4330,58 @@ impl Nexus {
         project_name: &Name,
         vpc_name: &Name,
         subnet_name: &Name,
+        preconditions: &Preconditions,
     ) -> DeleteResult {
+        let vpc = self
+            .project_lookup_vpc(organization_name, project_name, vpc_name)
+            .await?;
+        let subnet = self
+            .db_datastore
+            .vpc_subnet_fetch_by_name(&vpc.id(), subnet_name)
+            .await?;
+        self.db_datastore
+            .vpc_delete_subnet(&subnet.id(), preconditions)
+            .await?;
+        self.project_firewall_rules_push(&vpc.project_id).await;
+        Ok(())
+    }
+
+    pub async fn vpc_update_subnet(
+        &self,
+        organization_name: &Name,
+        project_name: &Name,
+        vpc_name: &Name,
+        subnet_name: &Name,
+        params: &params::VpcSubnetUpdate,
+        preconditions: &Preconditions,
+    ) -> UpdateResult<()> {
+        params.identity.check_no_labels(ResourceType::VpcSubnet)?;
+        let vpc = self
+            .project_lookup_vpc(organization_name, project_name, vpc_name)
+            .await?;
+        let subnet = self
+            .db_datastore
+            .vpc_subnet_fetch_by_name(&vpc.id(), subnet_name)
+            .await?;
+        self.db_datastore
+            .vpc_update_subnet(
+                &subnet.id(),
+                params.clone().into(),
+                preconditions,
+            )
+            .await?;
+        self.project_firewall_rules_push(&vpc.project_id).await;
+        Ok(())
+    }
+
+    pub async fn subnet_list_network_interfaces(
+        &self,
+        organization_name: &Name,
+        project_name: &Name,
+        vpc_name: &Name,
+        subnet_name: &Name,
+        pagparams: &DataPageParams<'_, Name>,
+    ) -> ListResultVec<external::NetworkInterface> {
         let subnet = self
             .vpc_lookup_subnet(
                 organization_name,
@@ -2005,17 +4390,59 @@ impl Nexus {
                 subnet_name,
             )
             .await?;
-        self.db_datastore.vpc_delete_subnet(&subnet.id()).await
+        let interfaces = self
+            .db_datastore
+            .subnet_list_network_interfaces(&subnet.id(), pagparams)
+            .await?;
+        self.network_interfaces_view(interfaces).await
     }
 
-    pub async fn vpc_update_subnet(
+    /**
+     * Attaches a custom router to a subnet, replacing any custom router
+     * already attached to it
+     */
+    pub async fn vpc_subnet_attach_router(
         &self,
         organization_name: &Name,
         project_name: &Name,
         vpc_name: &Name,
         subnet_name: &Name,
-        params: &params::VpcSubnetUpdate,
-    ) -> UpdateResult<()> {
+        params: &params::VpcSubnetRouterAttach,
+    ) -> UpdateResult<db::model::VpcSubnet> {
+        let vpc = self
+            .project_lookup_vpc(organization_name, project_name, vpc_name)
+            .await?;
+        let subnet = self
+            .db_datastore
+            .vpc_subnet_fetch_by_name(&vpc.id(), subnet_name)
+            .await?;
+        let router = self
+            .db_datastore
+            .vpc_router_fetch_by_name(
+                &vpc.id(),
+                &Name(params.router_name.clone()),
+            )
+            .await?;
+        if router.kind.0 != VpcRouterKind::Custom {
+            return Err(Error::invalid_request(
+                "only custom routers can be attached to a subnet",
+            ));
+        }
+        self.db_datastore
+            .vpc_subnet_set_custom_router(&subnet.id(), Some(router.id()))
+            .await
+    }
+
+    /**
+     * Detaches a subnet's custom router, if it has one
+     */
+    pub async fn vpc_subnet_detach_router(
+        &self,
+        organization_name: &Name,
+        project_name: &Name,
+        vpc_name: &Name,
+        subnet_name: &Name,
+    ) -> UpdateResult<db::model::VpcSubnet> {
         let subnet = self
             .vpc_lookup_subnet(
                 organization_name,
@@ -2024,31 +4451,135 @@ impl Nexus {
                 subnet_name,
             )
             .await?;
-        Ok(self
+        self.db_datastore.vpc_subnet_set_custom_router(&subnet.id(), None).await
+    }
+
+    /**
+     * Resolves the VPC's system router and the subnet's custom router, if
+     * any, into the routes that apply to traffic leaving the subnet
+     */
+    pub async fn subnet_effective_routes(
+        &self,
+        organization_name: &Name,
+        project_name: &Name,
+        vpc_name: &Name,
+        subnet_name: &Name,
+    ) -> LookupResult<external::EffectiveRouteTable> {
+        let vpc = self
+            .project_lookup_vpc(organization_name, project_name, vpc_name)
+            .await?;
+        let subnet = self
             .db_datastore
-            .vpc_update_subnet(&subnet.id(), params.clone().into())
-            .await?)
+            .vpc_subnet_fetch_by_name(&vpc.id(), subnet_name)
+            .await?;
+
+        let system_router = self.router_resolve(&vpc.system_router_id).await?;
+        let custom_router = match subnet.custom_router_id {
+            Some(router_id) => Some(self.router_resolve(&router_id).await?),
+            None => None,
+        };
+
+        let subnets = self
+            .db_datastore
+            .vpc_list_all_subnets(&vpc.id())
+            .await?
+            .iter()
+            .map(firewall::Subnet::from)
+            .collect();
+        let instance_ids = self
+            .db_datastore
+            .vpc_list_all_network_interfaces(&vpc.id())
+            .await?
+            .into_iter()
+            .map(|interface| interface.instance_id)
+            .collect::<BTreeSet<_>>();
+        let instances = self
+            .db_datastore
+            .instance_list_all_by_ids(instance_ids.into_iter().collect())
+            .await?
+            .iter()
+            .map(|instance| instance.name().0.clone())
+            .collect();
+
+        /*
+         * Routes may send traffic to or for other VPCs in the same Project.
+         * A destination VPC that doesn't exist just routes nothing, and the
+         * resolver rejects a target VPC that doesn't.
+         */
+        let other_vpc_names = system_router
+            .routes
+            .iter()
+            .chain(custom_router.iter().flat_map(|router| &router.routes))
+            .flat_map(|route| {
+                let target = match &route.target {
+                    RouteTarget::Vpc(name) => Some(name),
+                    _ => None,
+                };
+                let destination = match &route.destination {
+                    RouteDestination::Vpc(name) => Some(name),
+                    _ => None,
+                };
+                target.into_iter().chain(destination)
+            })
+            .filter(|name| **name != vpc.name().0)
+            .cloned()
+            .collect::<BTreeSet<_>>();
+        let mut other_vpcs = BTreeMap::new();
+        for name in other_vpc_names {
+            let other_vpc = match self
+                .db_datastore
+                .vpc_fetch_by_name(&vpc.project_id, &Name(name.clone()))
+                .await
+            {
+                Ok(other_vpc) => other_vpc,
+                Err(Error::ObjectNotFound { .. }) => continue,
+                Err(error) => return Err(error),
+            };
+            let subnets = self
+                .db_datastore
+                .vpc_list_all_subnets(&other_vpc.id())
+                .await?
+                .iter()
+                .map(firewall::Subnet::from)
+                .collect();
+            other_vpcs.insert(name, subnets);
+        }
+
+        let network = routing::VpcNetwork {
+            name: vpc.name().0.clone(),
+            subnets,
+            instances,
+            other_vpcs,
+        };
+        let routes = routing::resolve_routes(
+            &network,
+            &system_router,
+            custom_router.as_ref(),
+        )?;
+        Ok(external::EffectiveRouteTable { routes })
     }
 
-    pub async fn subnet_list_network_interfaces(
+    /**
+     * Fetches a router and all of its routes, for resolving route tables
+     */
+    async fn router_resolve(
         &self,
-        organization_name: &Name,
-        project_name: &Name,
-        vpc_name: &Name,
-        subnet_name: &Name,
-        pagparams: &DataPageParams<'_, Name>,
-    ) -> ListResultVec<db::model::NetworkInterface> {
-        let subnet = self
-            .vpc_lookup_subnet(
-                organization_name,
-                project_name,
-                vpc_name,
-                subnet_name,
-            )
-            .await?;
-        self.db_datastore
-            .subnet_list_network_interfaces(&subnet.id(), pagparams)
-            .await
+        router_id: &Uuid,
+    ) -> LookupResult<routing::Router> {
+        let router =
+            self.db_datastore.vpc_router_fetch_by_id(router_id).await?;
+        let routes = self
+            .db_datastore
+            .router_list_all_routes(router_id)
+            .await?
+            .into_iter()
+            .map(|route| route.into())
+            .collect();
+        Ok(routing::Router {
+            id: router.id(),
+            name: router.name().0.clone(),
+            routes,
+        })
     }
 
     pub async fn vpc_list_routers(
@@ -2090,6 +4621,7 @@ impl Nexus {
         kind: &VpcRouterKind,
         params: &params::VpcRouterCreate,
     ) -> CreateResult<db::model::VpcRouter> {
+        params.identity.check_no_labels(ResourceType::VpcRouter)?;
         let vpc = self
             .project_lookup_vpc(organization_name, project_name, vpc_name)
             .await?;
@@ -2101,14 +4633,13 @@ impl Nexus {
     }
 
     // TODO: When a router is deleted all its routes should be deleted
-    // TODO: When a router is deleted it should be unassociated w/ any subnets it may be associated with
-    //       or trigger an error
     pub async fn vpc_delete_router(
         &self,
         organization_name: &Name,
         project_name: &Name,
         vpc_name: &Name,
         router_name: &Name,
+        preconditions: &Preconditions,
     ) -> DeleteResult {
         let router = self
             .vpc_lookup_router(
@@ -2123,7 +4654,7 @@ impl Nexus {
                 internal_message: "Cannot delete system router".to_string(),
             });
         }
-        self.db_datastore.vpc_delete_router(&router.id()).await
+        self.db_datastore.vpc_delete_router(&router.id(), preconditions).await
     }
 
     pub async fn vpc_update_router(
@@ -2133,7 +4664,9 @@ impl Nexus {
         vpc_name: &Name,
         router_name: &Name,
         params: &params::VpcRouterUpdate,
+        preconditions: &Preconditions,
     ) -> UpdateResult<()> {
+        params.identity.check_no_labels(ResourceType::VpcRouter)?;
         let router = self
             .vpc_lookup_router(
                 organization_name,
@@ -2144,7 +4677,11 @@ impl Nexus {
             .await?;
         Ok(self
             .db_datastore
-            .vpc_update_router(&router.id(), params.clone().into())
+            .vpc_update_router(
+                &router.id(),
+                params.clone().into(),
+                preconditions,
+            )
             .await?)
     }
 
@@ -2206,6 +4743,7 @@ impl Nexus {
         kind: &RouterRouteKind,
         params: &RouterRouteCreateParams,
     ) -> CreateResult<db::model::RouterRoute> {
+        params.identity.check_no_labels(ResourceType::RouterRoute)?;
         let router = self
             .vpc_lookup_router(
                 organization_name,
@@ -2228,6 +4766,7 @@ impl Nexus {
         vpc_name: &Name,
         router_name: &Name,
         route_name: &Name,
+        preconditions: &Preconditions,
     ) -> DeleteResult {
         let route = self
             .router_lookup_route(
@@ -2245,7 +4784,7 @@ impl Nexus {
                     .to_string(),
             });
         }
-        self.db_datastore.router_delete_route(&route.id()).await
+        self.db_datastore.router_delete_route(&route.id(), preconditions).await
     }
 
     pub async fn router_update_route(
@@ -2256,7 +4795,9 @@ impl Nexus {
         router_name: &Name,
         route_name: &Name,
         params: &RouterRouteUpdateParams,
+        preconditions: &Preconditions,
     ) -> UpdateResult<()> {
+        params.identity.check_no_labels(ResourceType::RouterRoute)?;
         let route = self
             .router_lookup_route(
                 organization_name,
@@ -2281,7 +4822,11 @@ impl Nexus {
         }
         Ok(self
             .db_datastore
-            .router_update_route(&route.id(), params.clone().into())
+            .router_update_route(
+                &route.id(),
+                params.clone().into(),
+                preconditions,
+            )
             .await?)
     }
 
@@ -2336,40 +4881,277 @@ impl Nexus {
     }
 
     /*
-     * Sagas
+     * Operations
      */
 
-    pub async fn sagas_list(
+    pub async fn operations_list(
         &self,
+        opctx: &OpContext,
+        organization_name: &Name,
+        project_name: &Name,
         pagparams: &DataPageParams<'_, Uuid>,
-    ) -> ListResult<external::Saga> {
-        /*
-         * The endpoint we're serving only supports `ScanById`, which only
-         * supports an ascending scan.
-         */
-        bail_unless!(
-            pagparams.direction == dropshot::PaginationOrder::Ascending
-        );
-        let marker = pagparams.marker.map(|s| SagaId::from(*s));
-        let saga_list = self
-            .sec_client
-            .saga_list(marker, pagparams.limit)
+    ) -> ListResultVec<db::model::OperationState> {
+        let authz_project = self
+            .db_datastore
+            .project_lookup_by_path(organization_name, project_name)
+            .await?;
+        self.db_datastore
+            .operations_list(opctx, &authz_project, pagparams)
             .await
-            .into_iter()
-            .map(external::Saga::from)
-            .map(Ok);
-        Ok(futures::stream::iter(saga_list).boxed())
     }
 
-    pub async fn saga_get(&self, id: Uuid) -> LookupResult<external::Saga> {
-        self.sec_client
-            .saga_get(steno::SagaId::from(id))
+    pub async fn operation_fetch(
+        &self,
+        opctx: &OpContext,
+        organization_name: &Name,
+        project_name: &Name,
+        operation_id: Uuid,
+    ) -> LookupResult<db::model::OperationState> {
+        let authz_project = self
+            .db_datastore
+            .project_lookup_by_path(organization_name, project_name)
+            .await?;
+        self.db_datastore
+            .operation_fetch(opctx, &authz_project, operation_id)
+            .await
+    }
+
+    /*
+     * Users
+     */
+
+    pub async fn users_list(
+        &self,
+        opctx: &OpContext,
+        pagparams: &DataPageParams<'_, Name>,
+    ) -> ListResultVec<db::model::User> {
+        self.db_datastore.users_list_by_name(opctx, pagparams).await
+    }
+
+    pub async fn user_fetch(
+        &self,
+        opctx: &OpContext,
+        name: &Name,
+    ) -> LookupResult<db::model::User> {
+        self.db_datastore.user_fetch(opctx, name).await
+    }
+
+    pub async fn user_create(
+        &self,
+        opctx: &OpContext,
+        params: &params::UserCreate,
+    ) -> CreateResult<db::model::User> {
+        params.identity.check_no_labels(ResourceType::User)?;
+        // Hashing the password is deliberately expensive, so check that the
+        // caller is allowed to do this before doing it.  (The datastore checks
+        // again.)
+        opctx.authorize(authz::Action::Modify, &authz::FLEET).await?;
+        if params.password.is_empty()
+            || params.password.len() > defaults::MAX_PASSWORD_LENGTH
+        {
+            return Err(Error::InvalidValue {
+                label: String::from("password"),
+                message: format!(
+                    "must be between 1 and {} bytes long",
+                    defaults::MAX_PASSWORD_LENGTH
+                ),
+            });
+        }
+        let password_hash = password_hash(params.password.clone())
             .await
-            .map(external::Saga::from)
-            .map(Ok)
-            .map_err(|_: ()| {
-                Error::not_found_by_id(ResourceType::SagaDbg, &id)
-            })?
+            .map_err(|error| Error::internal_error(&format!("{:#}", error)))?;
+        let user = db::model::User::new(
+            Uuid::new_v4(),
+            params.identity.clone(),
+            password_hash,
+        );
+        let user = self.db_datastore.user_create(opctx, user).await?;
+        opctx.audit_resource(user.id());
+        Ok(user)
+    }
+
+    pub async fn user_delete(
+        &self,
+        opctx: &OpContext,
+        name: &Name,
+    ) -> DeleteResult {
+        let user = self.db_datastore.user_fetch(opctx, name).await?;
+        opctx.audit_resource(user.id());
+        self.db_datastore.user_delete(opctx, user.id()).await
+    }
+
+    /// Fetch the user named `username` in order to authenticate them
+    ///
+    /// Like sessions, this is used before the request is authenticated, so
+    /// there's no `OpContext` and no authorization check.
+    pub async fn user_fetch_for_authn(
+        &self,
+        username: &str,
+    ) -> LookupResult<db::model::User> {
+        // A user name that's not even a valid name can't match any user.
+        let name = username.parse::<external::Name>().map_err(|_| {
+            LookupType::ByName(username.to_owned())
+                .into_not_found(ResourceType::User)
+        })?;
+        self.db_datastore.user_fetch_for_authn(&Name::from(name)).await
+    }
+
+    /// Checks `password` against the one stored for the user named `username`,
+    /// returning the user's id if it matches
+    pub async fn user_password_check(
+        &self,
+        username: &str,
+        password: &str,
+    ) -> Result<Option<Uuid>, Error> {
+        let user = match self.user_fetch_for_authn(username).await {
+            Ok(user) => user,
+            Err(Error::ObjectNotFound { .. }) => return Ok(None),
+            Err(error) => return Err(error),
+        };
+        let matched =
+            password_verify(password.to_owned(), user.password_hash.clone())
+                .await
+                .map_err(|error| {
+                    Error::internal_error(&format!("{:#}", error))
+                })?;
+        Ok(if matched { Some(user.id()) } else { None })
+    }
+
+    /*
+     * API tokens
+     *
+     * Users manage their own API tokens, so these operate on the tokens
+     * belonging to the authenticated actor rather than checking roles.
+     */
+
+    /// Returns the id of the user whose own tokens and SSH keys the request
+    /// may manage
+    ///
+    /// These can't be created or removed using a read-only token, since that
+    /// would let a read-only token mint itself a read-write one (or grant
+    /// access to the user's instances).
+    fn current_user_id(opctx: &OpContext, modify: bool) -> Result<Uuid, Error> {
+        let actor =
+            opctx.authn.actor().ok_or_else(|| Error::Unauthenticated {
+                internal_message: String::from(
+                    "API tokens and SSH keys belong to an authenticated user",
+                ),
+            })?;
+        if modify && opctx.authn.read_only() {
+            return Err(Error::Forbidden);
+        }
+        Ok(actor.0)
+    }
+
+    /// Creates an API token for the authenticated user, returning what's
+    /// stored about it along with the token itself
+    pub async fn api_token_create(
+        &self,
+        opctx: &OpContext,
+        params: &params::ApiTokenCreate,
+    ) -> Result<(db::model::ApiToken, String), Error> {
+        let user_id = Self::current_user_id(opctx, true)?;
+        if let Some(time_expires) = params.time_expires {
+            if time_expires <= Utc::now() {
+                return Err(Error::InvalidValue {
+                    label: String::from("time_expires"),
+                    message: String::from("must be in the future"),
+                });
+            }
+        }
+        let secret = api_token_generate();
+        let token = db::model::ApiToken::new(
+            user_id,
+            api_token_hash(&secret),
+            params.clone(),
+        );
+        let token = self.db_datastore.api_token_create(opctx, token).await?;
+        opctx.audit_resource(token.id);
+        Ok((token, secret))
+    }
+
+    pub async fn api_tokens_list(
+        &self,
+        opctx: &OpContext,
+        pagparams: &DataPageParams<'_, Uuid>,
+    ) -> ListResultVec<db::model::ApiToken> {
+        let user_id = Self::current_user_id(opctx, false)?;
+        self.db_datastore.api_tokens_list(opctx, user_id, pagparams).await
+    }
+
+    /// Revokes one of the authenticated user's API tokens
+    ///
+    /// Since tokens are looked up on every request, this takes effect
+    /// immediately.
+    pub async fn api_token_delete(
+        &self,
+        opctx: &OpContext,
+        token_id: Uuid,
+    ) -> DeleteResult {
+        let user_id = Self::current_user_id(opctx, true)?;
+        opctx.audit_resource(token_id);
+        self.db_datastore.api_token_delete(opctx, user_id, token_id).await
+    }
+
+    /// Fetch the API token whose hash is `token_hash` in order to authenticate
+    /// a request
+    ///
+    /// Like sessions, this is used before the request is authenticated, so
+    /// there's no `OpContext` and no authorization check.
+    pub async fn api_token_fetch_for_authn(
+        &self,
+        token_hash: String,
+    ) -> LookupResult<db::model::ApiToken> {
+        self.db_datastore.api_token_fetch_for_authn(token_hash).await
+    }
+
+    /*
+     * SSH keys
+     *
+     * Like API tokens, users manage their own SSH keys.  Each key is
+     * authorized for logging in to every instance the user creates from then
+     * on.
+     */
+
+    /// Validates and stores an SSH public key for the authenticated user
+    pub async fn ssh_key_create(
+        &self,
+        opctx: &OpContext,
+        params: &params::SshKeyCreate,
+    ) -> CreateResult<db::model::SshKey> {
+        let user_id = Self::current_user_id(opctx, true)?;
+        let public_key = SshPublicKey::parse(&params.public_key)?;
+        let key = db::model::SshKey::new(
+            user_id,
+            params.description.clone(),
+            public_key.to_string(),
+            public_key.fingerprint(),
+        );
+        let key = self.db_datastore.ssh_key_create(opctx, key).await?;
+        opctx.audit_resource(key.id);
+        Ok(key)
+    }
+
+    pub async fn ssh_keys_list(
+        &self,
+        opctx: &OpContext,
+        pagparams: &DataPageParams<'_, Uuid>,
+    ) -> ListResultVec<db::model::SshKey> {
+        let user_id = Self::current_user_id(opctx, false)?;
+        self.db_datastore.ssh_keys_list(opctx, user_id, pagparams).await
+    }
+
+    /// Removes one of the authenticated user's SSH keys
+    ///
+    /// Instances that were created with the key keep it.
+    pub async fn ssh_key_delete(
+        &self,
+        opctx: &OpContext,
+        ssh_key_id: Uuid,
+    ) -> DeleteResult {
+        let user_id = Self::current_user_id(opctx, true)?;
+        opctx.audit_resource(ssh_key_id);
+        self.db_datastore.ssh_key_delete(opctx, user_id, ssh_key_id).await
     }
 
     /*
@@ -2412,6 +5194,176 @@ impl Nexus {
         self.db_datastore.role_builtin_fetch(opctx, name).await
     }
 
+    /*
+     * Audit log
+     */
+
+    pub async fn audit_log_list(
+        &self,
+        opctx: &OpContext,
+        start_time: Option<DateTime<Utc>>,
+        end_time: Option<DateTime<Utc>>,
+        pagparams: &DataPageParams<'_, (DateTime<Utc>, Uuid)>,
+    ) -> ListResultVec<db::model::AuditLogEntry> {
+        self.db_datastore
+            .audit_log_list(opctx, start_time, end_time, pagparams)
+            .await
+    }
+
+    /*
+     * Project events and webhooks
+     */
+
+    /**
+     * Appends an event to a Project's log (see [`crate::events`])
+     *
+     * The change that the event describes has already been made by the time
+     * this is called, so failure is logged rather than returned.
+     */
+    async fn project_event_publish(
+        &self,
+        project_id: Uuid,
+        resource_type: ResourceType,
+        resource_id: Uuid,
+        kind: EventKind,
+    ) {
+        let event = db::model::ProjectEvent::new(
+            project_id,
+            resource_type,
+            resource_id,
+            kind,
+        );
+        match self.db_datastore.project_event_append(event).await {
+            Ok(_) => {
+                /* It's fine if nobody is waiting. */
+                let _ = self.events_published.send(());
+            }
+            Err(error) => {
+                warn!(self.log, "failed to publish project event";
+                    "project_id" => %project_id,
+                    "resource_id" => %resource_id,
+                    "error" => ?error);
+            }
+        }
+    }
+
+    /**
+     * Returns a receiver that's notified when this Nexus appends an event to
+     * any Project's log
+     */
+    pub fn project_events_subscribe(&self) -> tokio::sync::watch::Receiver<()> {
+        self.events_published.subscribe()
+    }
+
+    /**
+     * Lists up to `limit` of a Project's events that come after position
+     * `after`, waiting up to `wait` for one to be appended if there aren't any
+     */
+    pub async fn project_list_events(
+        &self,
+        opctx: &OpContext,
+        organization_name: &Name,
+        project_name: &Name,
+        after: u64,
+        limit: NonZeroU32,
+        wait: Duration,
+    ) -> ListResultVec<db::model::ProjectEvent> {
+        let authz_project = self
+            .db_datastore
+            .project_lookup_by_path(organization_name, project_name)
+            .await?;
+        let after = i64::try_from(after).map_err(|_| Error::InvalidValue {
+            label: String::from("after"),
+            message: String::from("cursor is out of range"),
+        })?;
+        let deadline = Instant::now() + wait;
+        let mut published = self.project_events_subscribe();
+        loop {
+            let events = self
+                .db_datastore
+                .project_events_list(opctx, &authz_project, after, limit.get())
+                .await?;
+            let now = Instant::now();
+            if !events.is_empty() || now >= deadline {
+                return Ok(events);
+            }
+            events::wait_for_events(
+                &mut published,
+                (deadline - now).min(events::EVENTS_POLL_INTERVAL),
+            )
+            .await;
+        }
+    }
+
+    pub async fn project_create_webhook(
+        &self,
+        opctx: &OpContext,
+        organization_name: &Name,
+        project_name: &Name,
+        params: &params::ProjectWebhookCreate,
+    ) -> CreateResult<db::model::ProjectWebhook> {
+        events::destination_resolve(&params.url, &self.webhooks_config)
+            .await
+            .map_err(|error| Error::InvalidValue {
+            label: String::from("url"),
+            message: error.to_string(),
+        })?;
+        if params.secret.len() < 16 || params.secret.len() > 255 {
+            return Err(Error::InvalidValue {
+                label: String::from("secret"),
+                message: String::from(
+                    "secret must be between 16 and 255 bytes long",
+                ),
+            });
+        }
+
+        let authz_project = self
+            .db_datastore
+            .project_lookup_by_path(organization_name, project_name)
+            .await?;
+        let webhook =
+            db::model::ProjectWebhook::new(authz_project.id(), params.clone());
+        let webhook = self
+            .db_datastore
+            .project_webhook_create(opctx, &authz_project, webhook)
+            .await?;
+        opctx.audit_resource(webhook.id);
+        Ok(webhook)
+    }
+
+    pub async fn project_list_webhooks(
+        &self,
+        opctx: &OpContext,
+        organization_name: &Name,
+        project_name: &Name,
+        pagparams: &DataPageParams<'_, Uuid>,
+    ) -> ListResultVec<db::model::ProjectWebhook> {
+        let authz_project = self
+            .db_datastore
+            .project_lookup_by_path(organization_name, project_name)
+            .await?;
+        self.db_datastore
+            .project_webhooks_list(opctx, &authz_project, pagparams)
+            .await
+    }
+
+    pub async fn project_delete_webhook(
+        &self,
+        opctx: &OpContext,
+        organization_name: &Name,
+        project_name: &Name,
+        webhook_id: Uuid,
+    ) -> DeleteResult {
+        let authz_project = self
+            .db_datastore
+            .project_lookup_by_path(organization_name, project_name)
+            .await?;
+        opctx.audit_resource(webhook_id);
+        self.db_datastore
+            .project_webhook_delete(opctx, &authz_project, webhook_id)
+            .await
+    }
+
     /*
      * Internal control plane interfaces.
      */
@@ -2438,6 +5390,25 @@ impl Nexus {
                     "instance_id" => %id,
                     "propolis_id" => %new_runtime_state.propolis_uuid,
                     "new_state" => %new_runtime_state.run_state);
+                match self.db_datastore.instance_lookup_by_id(*id).await {
+                    Ok(authz_instance) => {
+                        self.project_event_publish(
+                            authz_instance.project().id(),
+                            ResourceType::Instance,
+                            *id,
+                            EventKind::StateChanged(
+                                new_runtime_state.run_state.to_string(),
+                            ),
+                        )
+                        .await
+                    }
+                    /* The instance may have been deleted in the meantime. */
+                    Err(error) => {
+                        warn!(log, "failed to publish instance update";
+                            "instance_id" => %id,
+                            "error" => ?error);
+                    }
+                }
                 Ok(())
             }
 
@@ -2501,6 +5472,13 @@ impl Nexus {
                 info!(log, "disk updated by sled agent";
                     "disk_id" => %id,
                     "new_state" => ?new_state);
+                self.project_event_publish(
+                    authz_disk.project().id(),
+                    ResourceType::Disk,
+                    id,
+                    EventKind::StateChanged(new_state.disk_state.to_string()),
+                )
+                .await;
                 Ok(())
             }
 
@@ -2651,6 +5629,49 @@ fn generate_session_token() -> String {
     hex::encode(random_bytes)
 }
 
+/**
+ * Returns the role assignments described by `policy` for the resource of type
+ * `resource_type` with id `resource_id`
+ *
+ * Only built-in roles that apply to this type of resource can be granted.
+ * Duplicate assignments are collapsed.
+ */
+fn policy_role_assignments(
+    resource_type: ResourceType,
+    resource_id: Uuid,
+    policy: &views::Policy,
+) -> Result<Vec<db::model::RoleAssignment>, Error> {
+    let mut assignments = BTreeSet::new();
+    for assignment in &policy.role_assignments {
+        let role = BUILTIN_ROLES
+            .iter()
+            .filter(|role| role.resource_type == resource_type)
+            .find(|role| {
+                RoleName::new(&role.resource_type.to_string(), role.role_name)
+                    == assignment.role_name
+            })
+            .ok_or_else(|| Error::InvalidValue {
+                label: String::from("role_name"),
+                message: format!(
+                    "role \"{}\" cannot be granted on resources of type \"{}\"",
+                    assignment.role_name, resource_type
+                ),
+            })?;
+        assignments.insert((assignment.user_id, role.role_name));
+    }
+    Ok(assignments
+        .into_iter()
+        .map(|(user_id, role_name)| {
+            db::model::RoleAssignment::new(
+                user_id,
+                resource_type,
+                resource_id,
+                role_name,
+            )
+        })
+        .collect())
+}
+
 #[async_trait]
 impl TestInterfaces for Nexus {
     async fn instance_sled_by_id(
@@ -2668,6 +5689,18 @@ impl TestInterfaces for Nexus {
         self.instance_sled(&db_instance).await
     }
 
+    async fn instance_sled_id_by_id(&self, id: &Uuid) -> Result<Uuid, Error> {
+        let opctx = OpContext::for_tests(
+            self.log.new(o!()),
+            Arc::clone(&self.db_datastore),
+        );
+        let authz_instance =
+            self.db_datastore.instance_lookup_by_id(*id).await?;
+        let db_instance =
+            self.db_datastore.instance_refetch(&opctx, &authz_instance).await?;
+        Ok(db_instance.runtime().sled_uuid)
+    }
+
     async fn disk_sled_by_id(
         &self,
         id: &Uuid,
@@ -0,0 +1,416 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Choosing the sled on which a new Instance will run
+
+use omicron_common::api::external::Error;
+use serde::Deserialize;
+use serde::Serialize;
+use std::cmp::Ordering;
+use std::collections::BTreeSet;
+use uuid::Uuid;
+
+/// Configuration for Instance placement
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct PlacementConfig {
+    /// how to choose among the sleds that can fit a new Instance
+    pub policy: PlacementPolicy,
+}
+
+/// Describes how Nexus chooses among the sleds that have enough free capacity
+/// for a new Instance
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PlacementPolicy {
+    /// Use the first sled (in order of id) that has enough free capacity.
+    FirstFit,
+    /// Use the sled that will have the most free capacity left over, spreading
+    /// Instances evenly across the rack.
+    Spread,
+    /// Use the sled that will have the least free capacity left over, packing
+    /// Instances onto as few sleds as possible.
+    Pack,
+}
+
+/// The capacity of a sled and the portion of it already reserved by Instances
+#[derive(Clone, Debug, PartialEq)]
+pub struct SledResources {
+    pub sled_id: Uuid,
+    /// number of hardware threads usable by Instances
+    pub hardware_threads: u64,
+    /// bytes of physical memory usable by Instances
+    pub physical_ram: u64,
+    /// number of vCPUs reserved by existing Instances
+    pub reserved_cpus: u64,
+    /// bytes of memory reserved by existing Instances
+    pub reserved_ram: u64,
+}
+
+impl SledResources {
+    fn free_cpus(&self) -> u64 {
+        self.hardware_threads.saturating_sub(self.reserved_cpus)
+    }
+
+    fn free_ram(&self) -> u64 {
+        self.physical_ram.saturating_sub(self.reserved_ram)
+    }
+
+    fn fits(&self, request: &PlacementRequest) -> bool {
+        self.free_cpus() >= request.ncpus && self.free_ram() >= request.memory
+    }
+
+    /// Returns the fraction of this sled's scarcest resource that would be
+    /// left over after placing `request` on it
+    fn headroom_after(&self, request: &PlacementRequest) -> f64 {
+        fn fraction(free: u64, total: u64) -> f64 {
+            if total == 0 {
+                0.0
+            } else {
+                free as f64 / total as f64
+            }
+        }
+        let cpus = fraction(
+            self.free_cpus().saturating_sub(request.ncpus),
+            self.hardware_threads,
+        );
+        let ram = fraction(
+            self.free_ram().saturating_sub(request.memory),
+            self.physical_ram,
+        );
+        cpus.min(ram)
+    }
+}
+
+/// Describes the resources needed by an Instance that is being placed
+#[derive(Clone, Debug, Default)]
+pub struct PlacementRequest {
+    /// number of vCPUs
+    pub ncpus: u64,
+    /// bytes of memory
+    pub memory: u64,
+    /// Anti-affinity hints: sleds the Instance should preferably not land on
+    ///
+    /// These are only hints.  If no other sled has enough capacity, one of
+    /// these sleds will be used anyway.
+    pub avoid_sleds: BTreeSet<Uuid>,
+    /// If non-empty, the Instance must be placed on one of these sleds (e.g.,
+    /// to run alongside the other members of its affinity group)
+    pub required_sleds: BTreeSet<Uuid>,
+    /// The Instance must not be placed on any of these sleds (e.g., because
+    /// they already run members of its anti-affinity group)
+    pub excluded_sleds: BTreeSet<Uuid>,
+}
+
+impl PlacementRequest {
+    /// Returns whether the hard constraints on this request allow placing it
+    /// on sled `sled_id`
+    fn permits(&self, sled_id: &Uuid) -> bool {
+        (self.required_sleds.is_empty()
+            || self.required_sleds.contains(sled_id))
+            && !self.excluded_sleds.contains(sled_id)
+    }
+
+    fn is_constrained(&self) -> bool {
+        !self.required_sleds.is_empty() || !self.excluded_sleds.is_empty()
+    }
+}
+
+/// Chooses the sled on which a new Instance will run
+pub trait PlacementEngine: Send + Sync {
+    /// Returns the id of the sled from `sleds` on which to place `request`
+    fn choose_sled(
+        &self,
+        request: &PlacementRequest,
+        sleds: &[SledResources],
+    ) -> Result<Uuid, Error>;
+}
+
+impl PlacementEngine for PlacementPolicy {
+    fn choose_sled(
+        &self,
+        request: &PlacementRequest,
+        sleds: &[SledResources],
+    ) -> Result<Uuid, Error> {
+        if sleds.is_empty() {
+            return Err(Error::unavail("no sleds available for new Instance"));
+        }
+
+        // The hard constraints rule out some sleds no matter how much capacity
+        // they have.  If that leaves nothing, waiting for capacity to free up
+        // won't help.
+        let eligible: Vec<&SledResources> =
+            sleds.iter().filter(|s| request.permits(&s.sled_id)).collect();
+        if eligible.is_empty() {
+            return Err(Error::invalid_request(
+                if request.required_sleds.is_empty() {
+                    "every sled already runs a member of the Instance's \
+                    anti-affinity group"
+                } else {
+                    "no available sled runs the members of the Instance's \
+                    affinity group"
+                },
+            ));
+        }
+
+        let candidates: Vec<&SledResources> =
+            eligible.into_iter().filter(|s| s.fits(request)).collect();
+        if candidates.is_empty() {
+            return Err(Error::unavail(&format!(
+                "no sled {}has enough free capacity for an Instance with {} \
+                vCPUs and {} bytes of memory",
+                if request.is_constrained() {
+                    "allowed by the Instance's affinity group "
+                } else {
+                    ""
+                },
+                request.ncpus,
+                request.memory
+            )));
+        }
+
+        // Honor the anti-affinity hints if we can, but fall back to the
+        // hinted sleds rather than failing outright.
+        let preferred: Vec<&SledResources> = candidates
+            .iter()
+            .copied()
+            .filter(|s| !request.avoid_sleds.contains(&s.sled_id))
+            .collect();
+        let candidates =
+            if preferred.is_empty() { candidates } else { preferred };
+
+        // Ties are always broken by sled id so that placement is
+        // deterministic.
+        let by_headroom = |a: &&SledResources, b: &&SledResources| {
+            a.headroom_after(request)
+                .partial_cmp(&b.headroom_after(request))
+                .unwrap_or(Ordering::Equal)
+        };
+        let chosen = match self {
+            PlacementPolicy::FirstFit => {
+                candidates.into_iter().min_by_key(|s| s.sled_id)
+            }
+            PlacementPolicy::Spread => candidates.into_iter().min_by(|a, b| {
+                by_headroom(b, a).then_with(|| a.sled_id.cmp(&b.sled_id))
+            }),
+            PlacementPolicy::Pack => candidates.into_iter().min_by(|a, b| {
+                by_headroom(a, b).then_with(|| a.sled_id.cmp(&b.sled_id))
+            }),
+        };
+
+        // `candidates` is non-empty, so there is always a choice.
+        Ok(chosen.unwrap().sled_id)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::PlacementEngine;
+    use super::PlacementPolicy;
+    use super::PlacementRequest;
+    use super::SledResources;
+    use omicron_common::api::external::Error;
+    use uuid::Uuid;
+
+    const GIB: u64 = 1 << 30;
+
+    fn sled(n: u128, reserved_cpus: u64, reserved_ram: u64) -> SledResources {
+        SledResources {
+            sled_id: Uuid::from_u128(n),
+            hardware_threads: 16,
+            physical_ram: 64 * GIB,
+            reserved_cpus,
+            reserved_ram,
+        }
+    }
+
+    fn request(ncpus: u64, memory: u64) -> PlacementRequest {
+        PlacementRequest { ncpus, memory, ..Default::default() }
+    }
+
+    /// Returns a copy of `base` that asks for `ncpus` vCPUs instead
+    fn request_with(ncpus: u64, base: &PlacementRequest) -> PlacementRequest {
+        PlacementRequest { ncpus, ..base.clone() }
+    }
+
+    #[test]
+    fn test_no_sleds() {
+        for policy in [
+            PlacementPolicy::FirstFit,
+            PlacementPolicy::Spread,
+            PlacementPolicy::Pack,
+        ] {
+            let error = policy.choose_sled(&request(1, GIB), &[]).unwrap_err();
+            assert!(matches!(error, Error::ServiceUnavailable { .. }));
+        }
+    }
+
+    #[test]
+    fn test_no_capacity() {
+        let sleds = vec![sled(1, 16, 0), sled(2, 0, 64 * GIB), sled(3, 8, 0)];
+        for policy in [
+            PlacementPolicy::FirstFit,
+            PlacementPolicy::Spread,
+            PlacementPolicy::Pack,
+        ] {
+            let error =
+                policy.choose_sled(&request(9, GIB), &sleds).unwrap_err();
+            assert!(matches!(error, Error::ServiceUnavailable { .. }));
+        }
+    }
+
+    #[test]
+    fn test_first_fit() {
+        let sleds = vec![sled(3, 0, 0), sled(1, 16, 0), sled(2, 4, 0)];
+        let policy = PlacementPolicy::FirstFit;
+        // Sled 1 is full, so sled 2 is the first that fits.
+        assert_eq!(
+            policy.choose_sled(&request(4, GIB), &sleds).unwrap(),
+            Uuid::from_u128(2)
+        );
+        // Sled 2 doesn't have 16 free threads, so we move on to sled 3.
+        assert_eq!(
+            policy.choose_sled(&request(16, GIB), &sleds).unwrap(),
+            Uuid::from_u128(3)
+        );
+    }
+
+    #[test]
+    fn test_spread() {
+        let sleds =
+            vec![sled(1, 4, GIB), sled(2, 0, 32 * GIB), sled(3, 2, 2 * GIB)];
+        let policy = PlacementPolicy::Spread;
+        // Sled 2 has the most free threads, but half of its memory is already
+        // reserved, so the sled with the most headroom overall is sled 3.
+        assert_eq!(
+            policy.choose_sled(&request(2, GIB), &sleds).unwrap(),
+            Uuid::from_u128(3)
+        );
+
+        // Equally loaded sleds are chosen in order of id.
+        let sleds = vec![sled(2, 0, 0), sled(1, 0, 0)];
+        assert_eq!(
+            policy.choose_sled(&request(2, GIB), &sleds).unwrap(),
+            Uuid::from_u128(1)
+        );
+    }
+
+    #[test]
+    fn test_pack() {
+        let sleds = vec![sled(1, 0, 0), sled(2, 12, GIB), sled(3, 14, 0)];
+        let policy = PlacementPolicy::Pack;
+        // Sled 3 is the most heavily loaded sled that can still fit 2 vCPUs.
+        assert_eq!(
+            policy.choose_sled(&request(2, GIB), &sleds).unwrap(),
+            Uuid::from_u128(3)
+        );
+        // Sled 3 can't fit 4 vCPUs, but sled 2 can.
+        assert_eq!(
+            policy.choose_sled(&request(4, GIB), &sleds).unwrap(),
+            Uuid::from_u128(2)
+        );
+    }
+
+    #[test]
+    fn test_anti_affinity_hints() {
+        let sleds = vec![sled(1, 0, 0), sled(2, 0, 0), sled(3, 15, 0)];
+        let mut req = request(2, GIB);
+        req.avoid_sleds.insert(Uuid::from_u128(1));
+        for policy in [
+            PlacementPolicy::FirstFit,
+            PlacementPolicy::Spread,
+            PlacementPolicy::Pack,
+        ] {
+            assert_eq!(
+                policy.choose_sled(&req, &sleds).unwrap(),
+                Uuid::from_u128(2)
+            );
+        }
+
+        // The hints are ignored when no other sled can fit the Instance.
+        req.avoid_sleds.insert(Uuid::from_u128(2));
+        assert_eq!(
+            PlacementPolicy::Spread.choose_sled(&req, &sleds).unwrap(),
+            Uuid::from_u128(1)
+        );
+    }
+
+    #[test]
+    fn test_affinity() {
+        let sleds = vec![sled(1, 0, 0), sled(2, 12, 0), sled(3, 0, 0)];
+        let mut req = request(2, GIB);
+        req.required_sleds.insert(Uuid::from_u128(2));
+        for policy in [
+            PlacementPolicy::FirstFit,
+            PlacementPolicy::Spread,
+            PlacementPolicy::Pack,
+        ] {
+            assert_eq!(
+                policy.choose_sled(&req, &sleds).unwrap(),
+                Uuid::from_u128(2)
+            );
+        }
+
+        // Affinity is never traded away for capacity.
+        let error = PlacementPolicy::Spread
+            .choose_sled(&request_with(8, &req), &sleds)
+            .unwrap_err();
+        assert!(matches!(error, Error::ServiceUnavailable { .. }));
+
+        // If the group's sled has gone away, there's nowhere to put it.
+        req.required_sleds.clear();
+        req.required_sleds.insert(Uuid::from_u128(4));
+        let error =
+            PlacementPolicy::Spread.choose_sled(&req, &sleds).unwrap_err();
+        assert!(matches!(error, Error::InvalidRequest { .. }));
+    }
+
+    #[test]
+    fn test_anti_affinity() {
+        let sleds = vec![sled(1, 0, 0), sled(2, 0, 0), sled(3, 12, 0)];
+        let mut req = request(2, GIB);
+        req.excluded_sleds.insert(Uuid::from_u128(1));
+        req.excluded_sleds.insert(Uuid::from_u128(2));
+        for policy in [
+            PlacementPolicy::FirstFit,
+            PlacementPolicy::Spread,
+            PlacementPolicy::Pack,
+        ] {
+            assert_eq!(
+                policy.choose_sled(&req, &sleds).unwrap(),
+                Uuid::from_u128(3)
+            );
+        }
+
+        // Unlike the hints, excluded sleds are never used, even if they're
+        // the only ones with room.
+        let error = PlacementPolicy::Spread
+            .choose_sled(&request_with(8, &req), &sleds)
+            .unwrap_err();
+        assert!(matches!(error, Error::ServiceUnavailable { .. }));
+
+        // Once every sled runs a member of the group, no more can be added.
+        req.excluded_sleds.insert(Uuid::from_u128(3));
+        let error =
+            PlacementPolicy::Spread.choose_sled(&req, &sleds).unwrap_err();
+        assert!(matches!(error, Error::InvalidRequest { .. }));
+    }
+
+    #[test]
+    fn test_policy_names() {
+        #[derive(serde::Deserialize)]
+        struct Wrapper {
+            policy: PlacementPolicy,
+        }
+        for (name, policy) in [
+            ("first_fit", PlacementPolicy::FirstFit),
+            ("spread", PlacementPolicy::Spread),
+            ("pack", PlacementPolicy::Pack),
+        ] {
+            let parsed: Wrapper =
+                toml::from_str(&format!("policy = \"{}\"", name)).unwrap();
+            assert_eq!(parsed.policy, policy);
+        }
+        assert!(toml::from_str::<Wrapper>("policy = \"random\"").is_err());
+    }
+}
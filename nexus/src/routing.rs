@@ -0,0 +1,624 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Resolving VPC routers into the effective route table of each subnet
+//!
+//! Traffic leaving a VPC Subnet is routed by three sets of routes, which are
+//! merged into a single longest-prefix-match table:
+//!
+//! 1. A route to each of the VPC's own subnets, which sends traffic for the
+//!    subnet's address blocks to that subnet.
+//! 2. The routes of the custom router attached to the subnet, if there is one.
+//! 3. The routes of the VPC's system router.
+//!
+//! When routes from more than one of these route the same block of addresses,
+//! the one earlier in the list wins, so a custom router can override the
+//! system router but neither can take over a subnet's own block.  Two routes
+//! of the same router that send the same block to different targets conflict,
+//! and the table can't be resolved.
+//!
+//! Each route's destination resolves to blocks of addresses:
+//!
+//! * An IP destination is the block containing only that address.
+//! * A subnet destination is the named subnet's IPv4 and IPv6 blocks.
+//! * A VPC destination is the blocks of every subnet in the named VPC, which
+//!   may be another VPC in the same Project.
+//! * A route of kind [`RouterRouteKind::Default`] covers every address
+//!   (`0.0.0.0/0` and `::/0`), whatever its destination says.
+//!
+//! A destination that names nothing just routes nothing.  A target must
+//! exist, though: it must be a subnet of the VPC, a VPC in the Project, an
+//! Instance with a network interface in the VPC, or an internet gateway, of
+//! which there is only [`OUTBOUND_GATEWAY`] so far.  A route with any other
+//! target is rejected, and so is the table containing it.
+
+use crate::firewall::Subnet;
+use omicron_common::api::external;
+use omicron_common::api::external::{
+    EffectiveRoute, RouteDestination, RouteTarget, RouterRouteKind,
+};
+use std::cmp::Reverse;
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, BTreeSet};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use uuid::Uuid;
+
+/// Name of the internet gateway that every VPC has
+pub const OUTBOUND_GATEWAY: &str = "outbound";
+
+/// A VPC, as far as resolving its routers is concerned
+#[derive(Clone, Debug)]
+pub struct VpcNetwork {
+    /// name of the VPC whose routers are being resolved
+    pub name: external::Name,
+    /// the VPC's subnets
+    pub subnets: Vec<Subnet>,
+    /// names of the Instances that have a network interface in the VPC
+    pub instances: BTreeSet<external::Name>,
+    /// subnets of the other VPCs in the same Project, indexed by VPC name
+    ///
+    /// Only the VPCs named by the routes need to be here.
+    pub other_vpcs: BTreeMap<external::Name, Vec<Subnet>>,
+}
+
+/// A VPC router and its routes
+#[derive(Clone, Debug)]
+pub struct Router {
+    pub id: Uuid,
+    pub name: external::Name,
+    pub routes: Vec<external::RouterRoute>,
+}
+
+/// A block of addresses, as its first address and prefix length
+type Block = (IpAddr, u8);
+
+/// Returns the effective route table of a subnet in the VPC, given the VPC's
+/// system router and the custom router attached to the subnet, if any
+///
+/// The IPv4 routes come first, then the IPv6 ones, each ordered from the
+/// longest prefix to the shortest.
+pub fn resolve_routes(
+    network: &VpcNetwork,
+    system_router: &Router,
+    custom_router: Option<&Router>,
+) -> Result<Vec<EffectiveRoute>, external::Error> {
+    let mut table = BTreeMap::new();
+    for subnet in &network.subnets {
+        for (destination, prefix_len) in subnet_blocks(subnet) {
+            table.insert(
+                (destination, prefix_len),
+                EffectiveRoute {
+                    destination,
+                    prefix_len,
+                    target: RouteTarget::Subnet(subnet.name.clone()),
+                    kind: RouterRouteKind::VpcSubnet,
+                    router_id: None,
+                    route_id: None,
+                },
+            );
+        }
+    }
+    let routers = custom_router.into_iter().chain(Some(system_router));
+    for router in routers {
+        for (block, route) in resolve_router(network, router)? {
+            table.entry(block).or_insert(route);
+        }
+    }
+
+    let mut routes = table.into_values().collect::<Vec<_>>();
+    routes.sort_by_key(|route| {
+        (route.destination.is_ipv6(), Reverse(route.prefix_len))
+    });
+    Ok(routes)
+}
+
+/// Resolves a router's routes into the blocks of addresses they route,
+/// checking that their targets exist and that they don't conflict
+fn resolve_router(
+    network: &VpcNetwork,
+    router: &Router,
+) -> Result<BTreeMap<Block, EffectiveRoute>, external::Error> {
+    let mut resolved = BTreeMap::new();
+    for route in &router.routes {
+        check_target(network, router, route)?;
+        for block in destination_blocks(network, route) {
+            match resolved.entry(block) {
+                Entry::Vacant(entry) => {
+                    entry
+                        .insert((route, effective_route(router, route, block)));
+                }
+                Entry::Occupied(entry) => {
+                    let (other, _) = entry.get();
+                    if other.target != route.target {
+                        return Err(external::Error::InvalidRequest {
+                            message: format!(
+                                "routes \"{}\" and \"{}\" of router \"{}\" \
+                                 send traffic for {}/{} to different targets",
+                                other.identity.name,
+                                route.identity.name,
+                                router.name,
+                                block.0,
+                                block.1
+                            ),
+                        });
+                    }
+                }
+            }
+        }
+    }
+    Ok(resolved
+        .into_iter()
+        .map(|(block, (_, effective_route))| (block, effective_route))
+        .collect())
+}
+
+/// Checks that the thing to which a route sends traffic exists
+fn check_target(
+    network: &VpcNetwork,
+    router: &Router,
+    route: &external::RouterRoute,
+) -> Result<(), external::Error> {
+    let exists = match &route.target {
+        RouteTarget::Ip(_) => true,
+        RouteTarget::Vpc(name) => {
+            *name == network.name || network.other_vpcs.contains_key(name)
+        }
+        RouteTarget::Subnet(name) => {
+            network.subnets.iter().any(|s| s.name == *name)
+        }
+        RouteTarget::Instance(name) => network.instances.contains(name),
+        RouteTarget::InternetGateway(name) => name.as_str() == OUTBOUND_GATEWAY,
+    };
+    if exists {
+        Ok(())
+    } else {
+        Err(external::Error::InvalidRequest {
+            message: format!(
+                "route \"{}\" of router \"{}\" has nonexistent target \"{}\"",
+                route.identity.name, router.name, route.target
+            ),
+        })
+    }
+}
+
+/// Returns the blocks of addresses whose traffic a route sends to its target
+fn destination_blocks(
+    network: &VpcNetwork,
+    route: &external::RouterRoute,
+) -> Vec<Block> {
+    if route.kind == RouterRouteKind::Default {
+        return vec![
+            (IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0),
+            (IpAddr::V6(Ipv6Addr::UNSPECIFIED), 0),
+        ];
+    }
+    match &route.destination {
+        RouteDestination::Ip(ip) => vec![address_block(*ip)],
+        RouteDestination::Vpc(name) => {
+            let subnets = if *name == network.name {
+                &network.subnets[..]
+            } else {
+                network.other_vpcs.get(name).map_or(&[][..], |s| &s[..])
+            };
+            subnets.iter().flat_map(subnet_blocks).collect()
+        }
+        RouteDestination::Subnet(name) => network
+            .subnets
+            .iter()
+            .filter(|s| s.name == *name)
+            .flat_map(subnet_blocks)
+            .collect(),
+    }
+}
+
+fn effective_route(
+    router: &Router,
+    route: &external::RouterRoute,
+    (destination, prefix_len): Block,
+) -> EffectiveRoute {
+    EffectiveRoute {
+        destination,
+        prefix_len,
+        target: route.target.clone(),
+        kind: route.kind,
+        router_id: Some(router.id),
+        route_id: Some(route.identity.id),
+    }
+}
+
+fn subnet_blocks(subnet: &Subnet) -> Vec<Block> {
+    vec![
+        (IpAddr::V4(subnet.ipv4_block.network()), subnet.ipv4_block.prefix()),
+        (IpAddr::V6(subnet.ipv6_block.network()), subnet.ipv6_block.prefix()),
+    ]
+}
+
+/// Returns the block containing only `address`
+fn address_block(address: IpAddr) -> Block {
+    match address {
+        IpAddr::V4(_) => (address, 32),
+        IpAddr::V6(_) => (address, 128),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{resolve_routes, Router, Subnet, VpcNetwork};
+    use chrono::Utc;
+    use omicron_common::api::external;
+    use omicron_common::api::external::{EffectiveRoute, RouterRouteKind};
+    use std::collections::{BTreeMap, BTreeSet};
+    use std::net::IpAddr;
+    use uuid::Uuid;
+
+    fn name(name: &str) -> external::Name {
+        name.parse().unwrap()
+    }
+
+    fn subnet(subnet_name: &str, ipv4_block: &str, ipv6_block: &str) -> Subnet {
+        Subnet {
+            id: Uuid::new_v4(),
+            name: name(subnet_name),
+            ipv4_block: external::Ipv4Net(ipv4_block.parse().unwrap()),
+            ipv6_block: external::Ipv6Net(ipv6_block.parse().unwrap()),
+        }
+    }
+
+    fn route(
+        route_name: &str,
+        kind: RouterRouteKind,
+        target: &str,
+        destination: &str,
+    ) -> external::RouterRoute {
+        external::RouterRoute {
+            identity: external::IdentityMetadata {
+                id: Uuid::new_v4(),
+                name: name(route_name),
+                description: String::new(),
+                time_created: Utc::now(),
+                time_modified: Utc::now(),
+            },
+            router_id: Uuid::new_v4(),
+            kind,
+            target: target.parse().unwrap(),
+            destination: destination.parse().unwrap(),
+        }
+    }
+
+    /// Makes a router from (name, target, destination) triples describing
+    /// custom routes
+    fn router(
+        router_name: &str,
+        mut routes: Vec<external::RouterRoute>,
+        custom: &[(&str, &str, &str)],
+    ) -> Router {
+        routes.extend(custom.iter().map(
+            |(route_name, target, destination)| {
+                route(route_name, RouterRouteKind::Custom, target, destination)
+            },
+        ));
+        Router { id: Uuid::new_v4(), name: name(router_name), routes }
+    }
+
+    /// Makes a VPC's system router, with its default route and `custom`
+    fn system_router(custom: &[(&str, &str, &str)]) -> Router {
+        let default = route(
+            "default",
+            RouterRouteKind::Default,
+            "inetgw:outbound",
+            "vpc:default",
+        );
+        router("system", vec![default], custom)
+    }
+
+    /// Returns a VPC "default" with two subnets ("web" and "db") and an
+    /// Instance "nat", and another VPC "backend" in the same Project
+    fn network() -> VpcNetwork {
+        let mut other_vpcs = BTreeMap::new();
+        other_vpcs.insert(
+            name("backend"),
+            vec![subnet("default", "10.0.0.0/16", "fd00:5566:7788:100::/64")],
+        );
+        VpcNetwork {
+            name: name("default"),
+            subnets: vec![
+                subnet("web", "172.30.0.0/24", "fd00:1122:3344:100::/64"),
+                subnet("db", "172.30.1.0/24", "fd00:1122:3344:101::/64"),
+            ],
+            instances: BTreeSet::from([name("nat")]),
+            other_vpcs,
+        }
+    }
+
+    /// Returns the route that the table applies to traffic for `address`: the
+    /// first one whose block contains it
+    fn lookup<'a>(
+        table: &'a [EffectiveRoute],
+        address: &str,
+    ) -> Option<&'a EffectiveRoute> {
+        let address: IpAddr = address.parse().unwrap();
+        table.iter().find(|route| {
+            ipnetwork::IpNetwork::new(route.destination, route.prefix_len)
+                .unwrap()
+                .contains(address)
+        })
+    }
+
+    fn blocks(routes: &[&EffectiveRoute]) -> Vec<(String, u8)> {
+        routes
+            .iter()
+            .map(|route| (route.destination.to_string(), route.prefix_len))
+            .collect()
+    }
+
+    #[test]
+    fn test_resolve_system_router() {
+        let network = network();
+        let system = system_router(&[]);
+        let table = resolve_routes(&network, &system, None).unwrap();
+        let routes = table
+            .iter()
+            .map(|route| {
+                (
+                    route.destination.to_string(),
+                    route.prefix_len,
+                    route.target.to_string(),
+                )
+            })
+            .collect::<Vec<_>>();
+        let expected = [
+            ("172.30.0.0", 24, "subnet:web"),
+            ("172.30.1.0", 24, "subnet:db"),
+            ("0.0.0.0", 0, "inetgw:outbound"),
+            ("fd00:1122:3344:100::", 64, "subnet:web"),
+            ("fd00:1122:3344:101::", 64, "subnet:db"),
+            ("::", 0, "inetgw:outbound"),
+        ]
+        .iter()
+        .map(|(address, prefix_len, target)| {
+            (address.to_string(), *prefix_len, target.to_string())
+        })
+        .collect::<Vec<_>>();
+        assert_eq!(routes, expected);
+
+        let default_route = &system.routes[0];
+        let default = lookup(&table, "192.0.2.1").unwrap();
+        assert_eq!(default.kind, RouterRouteKind::Default);
+        assert_eq!(default.router_id, Some(system.id));
+        assert_eq!(default.route_id, Some(default_route.identity.id));
+        let local = lookup(&table, "172.30.0.5").unwrap();
+        assert_eq!(local.kind, RouterRouteKind::VpcSubnet);
+        assert_eq!(local.router_id, None);
+        assert_eq!(local.route_id, None);
+    }
+
+    #[test]
+    fn test_resolve_destinations() {
+        let network = network();
+        let system = system_router(&[]);
+        // Each case lists a custom route's destination, and the blocks it
+        // should route, in order.
+        let cases: &[(&str, &[(&str, u8)])] = &[
+            ("ip:192.0.2.1", &[("192.0.2.1", 32)]),
+            ("ip:2001:db8::1", &[("2001:db8::1", 128)]),
+            ("vpc:backend", &[("10.0.0.0", 16), ("fd00:5566:7788:100::", 64)]),
+            ("vpc:nonexistent", &[]),
+            ("subnet:nonexistent", &[]),
+            // The VPC's own subnets always route to themselves.
+            ("subnet:web", &[]),
+            ("vpc:default", &[]),
+        ];
+        for (destination, expected) in cases {
+            let custom =
+                router("custom", vec![], &[("r", "instance:nat", destination)]);
+            let table =
+                resolve_routes(&network, &system, Some(&custom)).unwrap();
+            let routed = table
+                .iter()
+                .filter(|route| route.router_id == Some(custom.id))
+                .collect::<Vec<_>>();
+            let expected = expected
+                .iter()
+                .map(|(address, prefix_len)| (address.to_string(), *prefix_len))
+                .collect::<Vec<_>>();
+            assert_eq!(
+                blocks(&routed),
+                expected,
+                "destination {}",
+                destination
+            );
+        }
+    }
+
+    #[test]
+    fn test_route_conflicts() {
+        let network = network();
+        // Each case lists the custom routes of the subnet's custom router and
+        // of the system router, and then either where traffic for some
+        // addresses should go, or the error resolving the table.
+        struct Case {
+            custom: &'static [(&'static str, &'static str, &'static str)],
+            system: &'static [(&'static str, &'static str, &'static str)],
+            expected:
+                Result<&'static [(&'static str, &'static str)], &'static str>,
+        }
+        let cases = [
+            // A custom router overrides the system router for the same block.
+            Case {
+                custom: &[("to-backend", "instance:nat", "vpc:backend")],
+                system: &[("to-backend", "vpc:backend", "vpc:backend")],
+                expected: Ok(&[
+                    ("10.0.0.5", "instance:nat"),
+                    ("fd00:5566:7788:100::5", "instance:nat"),
+                ]),
+            },
+            // A longer prefix wins over a shorter one, whichever router it
+            // comes from.
+            Case {
+                custom: &[("to-backend", "instance:nat", "vpc:backend")],
+                system: &[("backend-host", "vpc:backend", "ip:10.0.0.5")],
+                expected: Ok(&[
+                    ("10.0.0.5", "vpc:backend"),
+                    ("10.0.0.6", "instance:nat"),
+                    ("192.0.2.1", "inetgw:outbound"),
+                ]),
+            },
+            Case {
+                custom: &[("dmz", "instance:nat", "ip:192.0.2.1")],
+                system: &[],
+                expected: Ok(&[
+                    ("192.0.2.1", "instance:nat"),
+                    ("192.0.2.2", "inetgw:outbound"),
+                    ("172.30.1.9", "subnet:db"),
+                    ("fd00:1122:3344:100::5", "subnet:web"),
+                    ("2001:db8::1", "inetgw:outbound"),
+                ]),
+            },
+            // Nothing overrides the routes to the VPC's own subnets.
+            Case {
+                custom: &[("hijack", "instance:nat", "subnet:db")],
+                system: &[("hijack", "inetgw:outbound", "vpc:default")],
+                expected: Ok(&[
+                    ("172.30.1.9", "subnet:db"),
+                    ("172.30.0.9", "subnet:web"),
+                ]),
+            },
+            // Routes of the same router may overlap if they agree...
+            Case {
+                custom: &[
+                    ("a", "instance:nat", "ip:192.0.2.1"),
+                    ("b", "instance:nat", "ip:192.0.2.1"),
+                ],
+                system: &[],
+                expected: Ok(&[("192.0.2.1", "instance:nat")]),
+            },
+            // ... but not if they don't.
+            Case {
+                custom: &[
+                    ("a", "instance:nat", "ip:192.0.2.1"),
+                    ("b", "inetgw:outbound", "ip:192.0.2.1"),
+                ],
+                system: &[],
+                expected: Err(
+                    "routes \"a\" and \"b\" of router \"custom\" send traffic \
+                     for 192.0.2.1/32 to different targets",
+                ),
+            },
+            Case {
+                custom: &[],
+                system: &[
+                    ("peer", "vpc:backend", "vpc:backend"),
+                    ("out", "inetgw:outbound", "vpc:backend"),
+                ],
+                expected: Err(
+                    "routes \"peer\" and \"out\" of router \"system\" send \
+                     traffic for 10.0.0.0/16 to different targets",
+                ),
+            },
+            // Different destinations can resolve to the same block.
+            Case {
+                custom: &[
+                    ("a", "instance:nat", "vpc:backend"),
+                    ("b", "ip:172.30.0.1", "vpc:backend"),
+                ],
+                system: &[],
+                expected: Err(
+                    "routes \"a\" and \"b\" of router \"custom\" send traffic \
+                     for 10.0.0.0/16 to different targets",
+                ),
+            },
+        ];
+        for case in &cases {
+            let system = system_router(case.system);
+            let custom = router("custom", vec![], case.custom);
+            let result = resolve_routes(&network, &system, Some(&custom));
+            match (result, case.expected) {
+                (Ok(table), Ok(expected)) => {
+                    for (address, target) in expected {
+                        let route = lookup(&table, address).unwrap();
+                        assert_eq!(
+                            route.target.to_string(),
+                            *target,
+                            "custom routes {:?}, system routes {:?}, \
+                             address {}",
+                            case.custom,
+                            case.system,
+                            address
+                        );
+                    }
+                }
+                (
+                    Err(external::Error::InvalidRequest { message }),
+                    Err(expected),
+                ) => {
+                    assert_eq!(message, expected);
+                }
+                (result, expected) => panic!(
+                    "custom routes {:?}, system routes {:?}: expected {:?}, \
+                     got {:?}",
+                    case.custom, case.system, expected, result
+                ),
+            }
+        }
+    }
+
+    #[test]
+    fn test_reject_nonexistent_targets() {
+        let network = network();
+        let system = system_router(&[]);
+        // Each case lists a custom route's target, and whether it exists.
+        let cases = [
+            ("ip:192.0.2.1", true),
+            ("ip:2001:db8::1", true),
+            ("vpc:default", true),
+            ("vpc:backend", true),
+            ("vpc:nonexistent", false),
+            ("subnet:db", true),
+            ("subnet:nonexistent", false),
+            ("instance:nat", true),
+            ("instance:nonexistent", false),
+            ("inetgw:outbound", true),
+            ("inetgw:nonexistent", false),
+        ];
+        for (target, exists) in &cases {
+            // The target is checked even if the route routes nothing.
+            for destination in &["ip:192.0.2.1", "subnet:nonexistent"] {
+                let custom =
+                    router("custom", vec![], &[("r", target, destination)]);
+                let result = resolve_routes(&network, &system, Some(&custom));
+                match result {
+                    Ok(_) if *exists => (),
+                    Err(external::Error::InvalidRequest { message })
+                        if !*exists =>
+                    {
+                        assert_eq!(
+                            message,
+                            format!(
+                                "route \"r\" of router \"custom\" has \
+                                 nonexistent target \"{}\"",
+                                target
+                            )
+                        );
+                    }
+                    result => panic!(
+                        "target {}, destination {}: unexpected {:?}",
+                        target, destination, result
+                    ),
+                }
+            }
+        }
+
+        // The system router's targets are checked too.
+        let system =
+            system_router(&[("r", "subnet:nonexistent", "vpc:backend")]);
+        let error = resolve_routes(&network, &system, None).unwrap_err();
+        assert_eq!(
+            error,
+            external::Error::invalid_request(
+                "route \"r\" of router \"system\" has nonexistent target \
+                 \"subnet:nonexistent\""
+            )
+        );
+    }
+}
@@ -16,6 +16,12 @@ use std::fmt;
 use std::sync::Arc;
 use uuid::Uuid;
 
+/*
+ * Number of times to try placing an Instance when concurrent placements in its
+ * affinity group keep ruling out the sled chosen for it.
+ */
+const AFFINITY_CLAIM_ATTEMPTS: usize = 10;
+
 /*
  * TODO-design Should this be the same thing as ServerContext?  It's
  * very analogous, but maybe there's utility in having separate views for the
@@ -47,21 +53,58 @@ impl SagaContext {
     }
 
     /*
-     * TODO-design This interface should not exist.  Instead, sleds should be
-     * represented in the database.  Reservations will wind up writing to the
-     * database.  Allocating a server will thus be a saga action, complete with
-     * an undo action.  The only thing needed at this layer is a way to read and
-     * write to the database, which we already have.
-     *
-     * Note: the parameters appear here (unused) to make sure callers make sure
-     * to have them available.  They're not used now, but they will be in a real
-     * implementation.
+     * TODO-design This interface should not exist.  Instead, allocating a
+     * server should write a reservation to the database as a saga action,
+     * complete with an undo action.  For now, CPU and memory reservations are
+     * implied by the Instance record created later in the saga (see
+     * `DataStore::sled_list_with_reservations()`).  Only membership in the
+     * Instance's affinity group is reserved here, by claiming the chosen sled
+     * in the group before the Instance record exists.  The claim is released
+     * by the saga's undo action, or once the Instance record is created.
      */
     pub async fn alloc_server(
         &self,
-        _params: &params::InstanceCreate,
+        instance_id: &Uuid,
+        params: &params::InstanceCreate,
+        affinity_group_id: Option<Uuid>,
     ) -> Result<Uuid, Error> {
-        self.nexus.sled_allocate().await
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            let request = self
+                .nexus
+                .placement_request(
+                    u64::from(params.ncpus.0),
+                    params.memory.to_bytes(),
+                    affinity_group_id,
+                    Some(*instance_id),
+                )
+                .await?;
+            let sled_id = self.nexus.sled_allocate(&request).await?;
+            let group_id = match affinity_group_id {
+                None => return Ok(sled_id),
+                Some(group_id) => group_id,
+            };
+
+            /*
+             * Another Instance may have joined the group since the request
+             * was built.  If its sled rules this one out, try again with the
+             * group's current members.
+             */
+            let claim = db::model::AffinityGroupClaim {
+                instance_id: *instance_id,
+                affinity_group_id: group_id,
+                sled_id,
+            };
+            if self.datastore().affinity_group_claim(claim).await? {
+                return Ok(sled_id);
+            }
+            if attempts >= AFFINITY_CLAIM_ATTEMPTS {
+                return Err(Error::unavail(
+                    "could not claim a sled in the affinity group",
+                ));
+            }
+        }
     }
 
     pub fn authz(&self) -> &Arc<authz::Authz> {
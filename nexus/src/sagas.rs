@@ -14,6 +14,8 @@
  */
 
 use crate::context::OpContext;
+use crate::crucible::RegionClient;
+use crate::db::datastore::RegionResize;
 use crate::db::identity::{Asset, Resource};
 use crate::external_api::params;
 use crate::saga_interface::SagaContext;
@@ -32,14 +34,18 @@ use omicron_common::api::external::IdentityMetadataCreateParams;
 use omicron_common::api::external::InstanceState;
 use omicron_common::api::external::Name;
 use omicron_common::api::external::NetworkInterface;
+use omicron_common::api::internal::crucible::{
+    CreateRegionSnapshot, ImportRegionSnapshot, ResizeRegion,
+};
 use omicron_common::api::internal::nexus::InstanceRuntimeState;
+use omicron_common::api::internal::sled_agent::GuestMetadata;
 use omicron_common::api::internal::sled_agent::InstanceHardware;
 use omicron_common::backoff::{self, BackoffError};
 use serde::Deserialize;
 use serde::Serialize;
 use slog::Logger;
 use std::collections::BTreeMap;
-use std::convert::{TryFrom, TryInto};
+use std::convert::TryInto;
 use std::sync::Arc;
 use steno::new_action_noop_undo;
 use steno::ActionContext;
@@ -58,6 +64,9 @@ pub const SAGA_INSTANCE_CREATE_NAME: &'static str = "instance-create";
 pub const SAGA_INSTANCE_MIGRATE_NAME: &'static str = "instance-migrate";
 pub const SAGA_DISK_CREATE_NAME: &'static str = "disk-create";
 pub const SAGA_DISK_DELETE_NAME: &'static str = "disk-delete";
+pub const SAGA_DISK_RESIZE_NAME: &'static str = "disk-resize";
+pub const SAGA_SNAPSHOT_CREATE_NAME: &'static str = "snapshot-create";
+pub const SAGA_SNAPSHOT_DELETE_NAME: &'static str = "snapshot-delete";
 lazy_static! {
     pub static ref SAGA_INSTANCE_CREATE_TEMPLATE: Arc<SagaTemplate<SagaInstanceCreate>> =
         Arc::new(saga_instance_create());
@@ -67,6 +76,12 @@ lazy_static! {
         Arc::new(saga_disk_create());
     pub static ref SAGA_DISK_DELETE_TEMPLATE: Arc<SagaTemplate<SagaDiskDelete>> =
         Arc::new(saga_disk_delete());
+    pub static ref SAGA_DISK_RESIZE_TEMPLATE: Arc<SagaTemplate<SagaDiskResize>> =
+        Arc::new(saga_disk_resize());
+    pub static ref SAGA_SNAPSHOT_CREATE_TEMPLATE: Arc<SagaTemplate<SagaSnapshotCreate>> =
+        Arc::new(saga_snapshot_create());
+    pub static ref SAGA_SNAPSHOT_DELETE_TEMPLATE: Arc<SagaTemplate<SagaSnapshotDelete>> =
+        Arc::new(saga_snapshot_delete());
 }
 
 lazy_static! {
@@ -97,6 +112,21 @@ fn all_templates(
             Arc::clone(&SAGA_DISK_DELETE_TEMPLATE)
                 as Arc<dyn SagaTemplateGeneric<Arc<SagaContext>>>,
         ),
+        (
+            SAGA_DISK_RESIZE_NAME,
+            Arc::clone(&SAGA_DISK_RESIZE_TEMPLATE)
+                as Arc<dyn SagaTemplateGeneric<Arc<SagaContext>>>,
+        ),
+        (
+            SAGA_SNAPSHOT_CREATE_NAME,
+            Arc::clone(&SAGA_SNAPSHOT_CREATE_TEMPLATE)
+                as Arc<dyn SagaTemplateGeneric<Arc<SagaContext>>>,
+        ),
+        (
+            SAGA_SNAPSHOT_DELETE_NAME,
+            Arc::clone(&SAGA_SNAPSHOT_DELETE_TEMPLATE)
+                as Arc<dyn SagaTemplateGeneric<Arc<SagaContext>>>,
+        ),
     ]
     .into_iter()
     .collect()
@@ -116,6 +146,8 @@ async fn saga_generate_uuid<UserType: SagaType>(
 pub struct ParamsInstanceCreate {
     pub project_id: Uuid,
     pub create_params: params::InstanceCreate,
+    /// id of the affinity group named in `create_params`, if any
+    pub affinity_group_id: Option<Uuid>,
 }
 
 #[derive(Debug)]
@@ -143,18 +175,25 @@ pub fn saga_instance_create() -> SagaTemplate<SagaInstanceCreate> {
     template_builder.append(
         "server_id",
         "AllocServer",
-        // TODO-robustness This still needs an undo action, and we should really
-        // keep track of resources and reservations, etc.  See the comment on
+        // TODO-robustness The undo action only releases the sled claimed in
+        // the Instance's affinity group.  CPU and memory are still reserved
+        // by the Instance record.  See the comment on
         // SagaContext::alloc_server()
-        new_action_noop_undo(sic_alloc_server),
+        ActionFunc::new_action(sic_alloc_server, sic_alloc_server_undo),
     );
 
     template_builder.append(
-        "network_interface",
-        "CreateNetworkInterface",
+        "network_interface_ids",
+        "GenerateNetworkInterfaceIds",
+        new_action_noop_undo(sic_generate_network_interface_ids),
+    );
+
+    template_builder.append(
+        "network_interfaces",
+        "CreateNetworkInterfaces",
         ActionFunc::new_action(
-            sic_create_network_interface,
-            sic_create_network_interface_undo,
+            sic_create_network_interfaces,
+            sic_create_network_interfaces_undo,
         ),
     );
 
@@ -164,6 +203,18 @@ pub fn saga_instance_create() -> SagaTemplate<SagaInstanceCreate> {
         new_action_noop_undo(sic_create_instance_record),
     );
 
+    template_builder.append(
+        "no_result",
+        "PushFirewallRules",
+        new_action_noop_undo(sic_push_firewall_rules),
+    );
+
+    template_builder.append(
+        "no_result",
+        "PushDnsZone",
+        new_action_noop_undo(sic_push_dns_zone),
+    );
+
     template_builder.append(
         "instance_ensure",
         "InstanceEnsure",
@@ -178,78 +229,172 @@ async fn sic_alloc_server(
 ) -> Result<Uuid, ActionError> {
     let osagactx = sagactx.user_data();
     let params = sagactx.saga_params();
+    let instance_id = sagactx.lookup::<Uuid>("instance_id")?;
     osagactx
-        .alloc_server(&params.create_params)
+        .alloc_server(
+            &instance_id,
+            &params.create_params,
+            params.affinity_group_id,
+        )
         .await
         .map_err(ActionError::action_failed)
 }
 
-async fn sic_create_network_interface(
+async fn sic_alloc_server_undo(
+    sagactx: ActionContext<SagaInstanceCreate>,
+) -> Result<(), anyhow::Error> {
+    let osagactx = sagactx.user_data();
+    let instance_id = sagactx.lookup::<Uuid>("instance_id")?;
+    osagactx.datastore().affinity_group_claim_release(&instance_id).await?;
+    Ok(())
+}
+
+/**
+ * Returns the network interfaces to create for the Instance, which are the
+ * ones requested or, if there are none, one in the default VPC Subnet.
+ */
+fn sic_network_interfaces_requested(
+    instance_id: Uuid,
+    create_params: &params::InstanceCreate,
+) -> Vec<params::NetworkInterfaceCreate> {
+    if !create_params.network_interfaces.is_empty() {
+        return create_params.network_interfaces.clone();
+    }
+
+    let default_name: Name = "default".parse().unwrap();
+    vec![params::NetworkInterfaceCreate {
+        identity: IdentityMetadataCreateParams {
+            // By naming the interface after the instance id, we should
+            // avoid name conflicts on creation.
+            name: format!("default-{}", instance_id).parse().unwrap(),
+            description: format!(
+                "default interface for {}",
+                create_params.identity.name
+            ),
+            labels: Default::default(),
+        },
+        vpc_name: default_name.clone(),
+        subnet_name: default_name,
+        ip: None,
+    }]
+}
+
+async fn sic_generate_network_interface_ids(
     sagactx: ActionContext<SagaInstanceCreate>,
-) -> Result<NetworkInterface, ActionError> {
+) -> Result<Vec<Uuid>, ActionError> {
+    let params = sagactx.saga_params();
+    let instance_id = sagactx.lookup::<Uuid>("instance_id")?;
+    let count =
+        sic_network_interfaces_requested(instance_id, &params.create_params)
+            .len();
+    Ok((0..count).map(|_| Uuid::new_v4()).collect())
+}
+
+async fn sic_create_network_interfaces(
+    sagactx: ActionContext<SagaInstanceCreate>,
+) -> Result<Vec<NetworkInterface>, ActionError> {
     let osagactx = sagactx.user_data();
     let params = sagactx.saga_params();
     let instance_id = sagactx.lookup::<Uuid>("instance_id")?;
+    let interface_ids = sagactx.lookup::<Vec<Uuid>>("network_interface_ids")?;
+    let requested =
+        sic_network_interfaces_requested(instance_id, &params.create_params);
+
+    let mut interfaces = Vec::with_capacity(requested.len());
+    for (interface_id, interface_params) in
+        interface_ids.iter().zip(requested.into_iter())
+    {
+        let result = sic_create_network_interface(
+            osagactx,
+            params.project_id,
+            instance_id,
+            *interface_id,
+            interface_params,
+        )
+        .await;
+        match result {
+            Ok(interface) => interfaces.push(interface),
+            Err(error) => {
+                /*
+                 * The undo action isn't run for an action that fails, so
+                 * remove any interfaces that this one created before failing.
+                 */
+                sic_delete_network_interfaces(osagactx, &interface_ids)
+                    .await
+                    .map_err(ActionError::action_failed)?;
+                return Err(ActionError::action_failed(error));
+            }
+        }
+    }
+    Ok(interfaces)
+}
 
-    let default_name =
-        db::model::Name(Name::try_from("default".to_string()).unwrap());
+async fn sic_create_network_interface(
+    osagactx: &Arc<SagaContext>,
+    project_id: Uuid,
+    instance_id: Uuid,
+    interface_id: Uuid,
+    interface_params: params::NetworkInterfaceCreate,
+) -> Result<NetworkInterface, Error> {
     let vpc = osagactx
         .datastore()
-        .vpc_fetch_by_name(&params.project_id, &default_name)
-        .await
-        .map_err(ActionError::action_failed)?;
+        .vpc_fetch_by_name(
+            &project_id,
+            &db::model::Name(interface_params.vpc_name.clone()),
+        )
+        .await?;
     let subnet = osagactx
         .datastore()
-        .vpc_subnet_fetch_by_name(&vpc.id(), &default_name)
-        .await
-        .map_err(ActionError::action_failed)?;
+        .vpc_subnet_fetch_by_name(
+            &vpc.id(),
+            &db::model::Name(interface_params.subnet_name.clone()),
+        )
+        .await?;
 
-    let mac = db::model::MacAddr::new().map_err(ActionError::action_failed)?;
-    let interface_id = Uuid::new_v4();
-    // Request an allocation
-    let ip = None;
+    let mac = db::model::MacAddr::new()?;
     let interface = db::model::IncompleteNetworkInterface::new(
         interface_id,
         instance_id,
-        // TODO-correctness: vpc_id here is used for name uniqueness. Should
-        // interface names be unique to the subnet's VPC or to the
-        // VPC associated with the instance's default interface?
         vpc.id(),
         subnet,
         mac,
-        ip,
-        params::NetworkInterfaceCreate {
-            identity: IdentityMetadataCreateParams {
-                // By naming the interface after the instance id, we should
-                // avoid name conflicts on creation.
-                name: format!("default-{}", instance_id).parse().unwrap(),
-                description: format!(
-                    "default interface for {}",
-                    params.create_params.identity.name
-                ),
-            },
-        },
+        interface_params.ip,
+        interface_params,
     );
 
     let interface = osagactx
         .datastore()
         .instance_create_network_interface(interface)
-        .await
-        .map_err(ActionError::action_failed)?;
+        .await?;
     Ok(interface.into())
 }
 
-async fn sic_create_network_interface_undo(
+/**
+ * Deletes whichever of the given network interfaces exist
+ */
+async fn sic_delete_network_interfaces(
+    osagactx: &Arc<SagaContext>,
+    interface_ids: &[Uuid],
+) -> Result<(), Error> {
+    for interface_id in interface_ids {
+        match osagactx
+            .datastore()
+            .instance_delete_network_interface(interface_id)
+            .await
+        {
+            Ok(()) | Err(Error::ObjectNotFound { .. }) => (),
+            Err(error) => return Err(error),
+        }
+    }
+    Ok(())
+}
+
+async fn sic_create_network_interfaces_undo(
     sagactx: ActionContext<SagaInstanceCreate>,
 ) -> Result<(), anyhow::Error> {
     let osagactx = sagactx.user_data();
-    let network_interface =
-        sagactx.lookup::<NetworkInterface>("network_interface")?;
-
-    osagactx
-        .datastore()
-        .instance_delete_network_interface(&network_interface.identity.id)
-        .await?;
+    let interface_ids = sagactx.lookup::<Vec<Uuid>>("network_interface_ids")?;
+    sic_delete_network_interfaces(osagactx, &interface_ids).await?;
     Ok(())
 }
 
@@ -261,8 +406,8 @@ async fn sic_create_instance_record(
     let sled_uuid = sagactx.lookup::<Uuid>("server_id");
     let instance_id = sagactx.lookup::<Uuid>("instance_id");
     let propolis_uuid = sagactx.lookup::<Uuid>("propolis_id");
-    let network_interface =
-        sagactx.lookup::<NetworkInterface>("network_interface")?;
+    let network_interfaces =
+        sagactx.lookup::<Vec<NetworkInterface>>("network_interfaces")?;
 
     let runtime = InstanceRuntimeState {
         run_state: InstanceState::Creating,
@@ -282,6 +427,7 @@ async fn sic_create_instance_record(
         instance_id?,
         params.project_id,
         &params.create_params,
+        params.affinity_group_id,
         runtime.into(),
     );
 
@@ -291,13 +437,46 @@ async fn sic_create_instance_record(
         .await
         .map_err(ActionError::action_failed)?;
 
+    // Now that the Instance record places it in its affinity group, the sled
+    // claimed for it is no longer needed.
+    osagactx
+        .datastore()
+        .affinity_group_claim_release(&instance.id())
+        .await
+        .map_err(ActionError::action_failed)?;
+
     // See also: instance_set_runtime in nexus.rs for a similar construction.
     Ok(InstanceHardware {
         runtime: instance.runtime().clone().into(),
-        nics: vec![network_interface],
+        nics: network_interfaces,
+        guest_metadata: instance.guest_metadata(),
     })
 }
 
+/*
+ * The new Instance's network interfaces need their firewall rules before it
+ * starts, and other rules in their VPCs may match their addresses.
+ */
+async fn sic_push_firewall_rules(
+    sagactx: ActionContext<SagaInstanceCreate>,
+) -> Result<(), ActionError> {
+    let osagactx = sagactx.user_data();
+    let instance_id = sagactx.lookup::<Uuid>("instance_id")?;
+    osagactx.nexus().instance_firewall_rules_push(&instance_id).await;
+    Ok(())
+}
+
+/*
+ * The new Instance's network interfaces need names in the internal DNS zone.
+ */
+async fn sic_push_dns_zone(
+    sagactx: ActionContext<SagaInstanceCreate>,
+) -> Result<(), ActionError> {
+    let osagactx = sagactx.user_data();
+    osagactx.nexus().dns_zone_push().await;
+    Ok(())
+}
+
 async fn sic_instance_ensure(
     sagactx: ActionContext<SagaInstanceCreate>,
 ) -> Result<(), ActionError> {
@@ -409,7 +588,7 @@ pub fn saga_instance_migrate() -> SagaTemplate<SagaInstanceMigrate> {
 
 async fn sim_migrate_prep(
     sagactx: ActionContext<SagaInstanceMigrate>,
-) -> Result<(Uuid, InstanceRuntimeState), ActionError> {
+) -> Result<(Uuid, InstanceRuntimeState, GuestMetadata), ActionError> {
     let osagactx = sagactx.user_data();
     let params = sagactx.saga_params();
     let opctx = OpContext::for_saga_action(&sagactx, &params.serialized_authn);
@@ -433,7 +612,9 @@ async fn sim_migrate_prep(
         .map_err(ActionError::action_failed)?;
     let instance_id = instance.id();
 
-    Ok((instance_id, instance.runtime_state.into()))
+    let guest_metadata = instance.guest_metadata();
+
+    Ok((instance_id, instance.runtime_state.into(), guest_metadata))
 }
 
 async fn sim_instance_migrate(
@@ -445,8 +626,10 @@ async fn sim_instance_migrate(
     let migration_id = sagactx.lookup::<Uuid>("migrate_id")?;
     let dst_sled_uuid = params.migrate_params.dst_sled_uuid;
     let dst_propolis_uuid = sagactx.lookup::<Uuid>("dst_propolis_id")?;
-    let (instance_id, old_runtime) =
-        sagactx.lookup::<(Uuid, InstanceRuntimeState)>("migrate_instance")?;
+    let (instance_id, old_runtime, guest_metadata) =
+        sagactx.lookup::<(Uuid, InstanceRuntimeState, GuestMetadata)>(
+            "migrate_instance",
+        )?;
 
     let runtime = InstanceRuntimeState {
         sled_uuid: dst_sled_uuid,
@@ -458,6 +641,7 @@ async fn sim_instance_migrate(
         runtime: runtime.into(),
         // TODO: populate NICs
         nics: vec![],
+        guest_metadata: guest_metadata.into(),
     };
     let target = sled_agent_client::types::InstanceRuntimeStateRequested {
         run_state: sled_agent_client::types::InstanceStateRequested::Migrating,
@@ -505,6 +689,13 @@ async fn sim_instance_migrate(
         .await
         .map_err(ActionError::action_failed)?;
 
+    if dst_sled_uuid != old_runtime.sled_uuid {
+        osagactx
+            .nexus()
+            .instance_firewall_rules_moved(&instance_id, old_runtime.sled_uuid)
+            .await;
+    }
+
     Ok(())
 }
 
@@ -520,6 +711,9 @@ pub struct ParamsDiskCreate {
     pub serialized_authn: authn::saga::Serialized,
     pub project_id: Uuid,
     pub create_params: params::DiskCreate,
+    /// id of the Disk from which the snapshot named in `create_params` was
+    /// taken, if any
+    pub origin_disk_id: Option<Uuid>,
 }
 
 #[derive(Debug)]
@@ -559,6 +753,14 @@ fn saga_disk_create() -> SagaTemplate<SagaDiskCreate> {
         ActionFunc::new_action(sdc_regions_ensure, sdc_regions_ensure_undo),
     );
 
+    template_builder.append(
+        "no_result",
+        "RegionsImport",
+        // The regions themselves are removed by the undo action for
+        // "RegionsEnsure".
+        new_action_noop_undo(sdc_regions_import),
+    );
+
     template_builder.append(
         "disk_runtime",
         "FinalizeDiskRecord",
@@ -757,6 +959,76 @@ async fn sdc_regions_ensure_undo(
     Ok(())
 }
 
+async fn sdc_regions_import(
+    sagactx: ActionContext<SagaDiskCreate>,
+) -> Result<(), ActionError> {
+    let osagactx = sagactx.user_data();
+    let params = sagactx.saga_params();
+    let (snapshot_id, origin_disk_id) =
+        match (params.create_params.snapshot_id, params.origin_disk_id) {
+            (Some(snapshot_id), Some(origin_disk_id)) => {
+                (snapshot_id, origin_disk_id)
+            }
+            _ => return Ok(()),
+        };
+
+    let datasets_and_regions = sagactx
+        .lookup::<Vec<(db::model::Dataset, db::model::Region)>>(
+            "datasets_and_regions",
+        )?;
+    let sources = osagactx
+        .datastore()
+        .get_allocated_regions(origin_disk_id)
+        .await
+        .map_err(ActionError::action_failed)?;
+    import_regions(datasets_and_regions, sources, snapshot_id)
+        .await
+        .map_err(ActionError::action_failed)?;
+    Ok(())
+}
+
+/// Populates each of a new Disk's regions from snapshot `snapshot_id` of the
+/// regions in `sources`
+async fn import_regions(
+    datasets_and_regions: Vec<(db::model::Dataset, db::model::Region)>,
+    sources: Vec<(db::model::Dataset, db::model::Region)>,
+    snapshot_id: Uuid,
+) -> Result<(), Error> {
+    if sources.is_empty() {
+        return Err(Error::internal_error(
+            "snapshot's disk has no regions to import from",
+        ));
+    }
+
+    // Every region holds a complete copy of its Disk, so any source region
+    // will do.  Spread the copies across all of them.
+    let request_count = datasets_and_regions.len();
+    futures::stream::iter(datasets_and_regions.into_iter().enumerate())
+        .map(|(i, (dataset, region))| {
+            let (source_dataset, source_region) = &sources[i % sources.len()];
+            let request = ImportRegionSnapshot {
+                source_address: source_dataset.address(),
+                source_region_id: source_region.id(),
+                snapshot_id,
+            };
+            async move {
+                RegionClient::new(dataset.address())
+                    .region_import(region.id(), &request)
+                    .await
+            }
+        })
+        .buffer_unordered(std::cmp::min(
+            request_count,
+            MAX_CONCURRENT_REGION_REQUESTS,
+        ))
+        .collect::<Vec<Result<_, _>>>()
+        .await
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| Error::internal_error(&e.to_string()))?;
+    Ok(())
+}
+
 async fn sdc_finalize_disk_record(
     sagactx: ActionContext<SagaDiskCreate>,
 ) -> Result<(), ActionError> {
@@ -883,3 +1155,598 @@ async fn sdd_delete_region_records(
         .map_err(ActionError::action_failed)?;
     Ok(())
 }
+
+/*
+ * "Resize Disk" saga template
+ */
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ParamsDiskResize {
+    pub serialized_authn: authn::saga::Serialized,
+    /// the Disk to resize, as it was before the resize
+    pub disk: db::model::Disk,
+    pub resize_params: params::DiskResize,
+    /// whether regions that can't grow in place may be moved to another
+    /// dataset (they can't be if the Disk has Snapshots, whose data lives
+    /// alongside the regions)
+    pub allow_move: bool,
+}
+
+#[derive(Debug)]
+pub struct SagaDiskResize;
+impl SagaType for SagaDiskResize {
+    type SagaParamsType = Arc<ParamsDiskResize>;
+    type ExecContextType = Arc<SagaContext>;
+}
+
+fn saga_disk_resize() -> SagaTemplate<SagaDiskResize> {
+    let mut template_builder = SagaTemplateBuilder::new();
+
+    // Concurrent resizes of the same Disk would each allocate regions and then
+    // record their own size, so only one resize may run at a time.
+    template_builder.append(
+        "resize_id",
+        "GenerateResizeId",
+        new_action_noop_undo(saga_generate_uuid),
+    );
+
+    template_builder.append(
+        "no_result",
+        "ClaimDisk",
+        ActionFunc::new_action(sdr_claim_disk, sdr_claim_disk_undo),
+    );
+
+    // Regions that can't be grown in place are moved by way of a snapshot.
+    template_builder.append(
+        "move_snapshot_id",
+        "GenerateMoveSnapshotId",
+        new_action_noop_undo(saga_generate_uuid),
+    );
+
+    template_builder.append(
+        "region_resizes",
+        "AllocRegions",
+        ActionFunc::new_action(sdr_alloc_regions, sdr_alloc_regions_undo),
+    );
+
+    template_builder.append(
+        "no_result",
+        "RegionsGrow",
+        ActionFunc::new_action(sdr_regions_grow, sdr_regions_grow_undo),
+    );
+
+    template_builder.append(
+        "resized_disk",
+        "UpdateDiskRecord",
+        ActionFunc::new_action(
+            sdr_update_disk_record,
+            sdr_update_disk_record_undo,
+        ),
+    );
+
+    template_builder.append(
+        "no_result",
+        "NotifySledAgent",
+        new_action_noop_undo(sdr_notify_sled_agent),
+    );
+
+    template_builder.append(
+        "no_result",
+        "DeleteReplacedRegions",
+        // TODO(https://github.com/oxidecomputer/omicron/issues/612):
+        // As with "DeleteRegions" in the disk deletion saga, we need a better
+        // way to deal with this failing than propagating the error to the
+        // user.
+        new_action_noop_undo(sdr_delete_replaced_regions),
+    );
+
+    template_builder.append(
+        "no_result",
+        "ReleaseDisk",
+        new_action_noop_undo(sdr_release_disk),
+    );
+
+    template_builder.build()
+}
+
+async fn sdr_claim_disk(
+    sagactx: ActionContext<SagaDiskResize>,
+) -> Result<(), ActionError> {
+    let osagactx = sagactx.user_data();
+    let params = sagactx.saga_params();
+    let datastore = osagactx.datastore();
+    let opctx = OpContext::for_saga_action(&sagactx, &params.serialized_authn);
+    let resize_id = sagactx.lookup::<Uuid>("resize_id")?;
+
+    let authz_disk = datastore
+        .disk_lookup_by_id(params.disk.id())
+        .await
+        .map_err(ActionError::action_failed)?;
+    datastore
+        .disk_resize_claim(
+            &opctx,
+            &authz_disk,
+            resize_id,
+            params.disk.size.into(),
+        )
+        .await
+        .map_err(ActionError::action_failed)?;
+    Ok(())
+}
+
+async fn sdr_claim_disk_undo(
+    sagactx: ActionContext<SagaDiskResize>,
+) -> Result<(), anyhow::Error> {
+    let osagactx = sagactx.user_data();
+    let params = sagactx.saga_params();
+    let datastore = osagactx.datastore();
+    let opctx = OpContext::for_saga_action(&sagactx, &params.serialized_authn);
+    let resize_id = sagactx.lookup::<Uuid>("resize_id")?;
+
+    let authz_disk = datastore.disk_lookup_by_id(params.disk.id()).await?;
+    datastore.disk_resize_release(&opctx, &authz_disk, resize_id).await?;
+    Ok(())
+}
+
+async fn sdr_release_disk(
+    sagactx: ActionContext<SagaDiskResize>,
+) -> Result<(), ActionError> {
+    let osagactx = sagactx.user_data();
+    let params = sagactx.saga_params();
+    let datastore = osagactx.datastore();
+    let opctx = OpContext::for_saga_action(&sagactx, &params.serialized_authn);
+    let resize_id = sagactx.lookup::<Uuid>("resize_id")?;
+
+    let authz_disk = datastore
+        .disk_lookup_by_id(params.disk.id())
+        .await
+        .map_err(ActionError::action_failed)?;
+    datastore
+        .disk_resize_release(&opctx, &authz_disk, resize_id)
+        .await
+        .map_err(ActionError::action_failed)?;
+    Ok(())
+}
+
+async fn sdr_alloc_regions(
+    sagactx: ActionContext<SagaDiskResize>,
+) -> Result<Vec<RegionResize>, ActionError> {
+    let osagactx = sagactx.user_data();
+    let params = sagactx.saga_params();
+    // TODO-correctness If this action is replayed after a region was replaced,
+    // the replacement is already large enough and the original is no longer
+    // recorded, so the original region would never be copied or destroyed.
+    let resizes = osagactx
+        .datastore()
+        .region_resize_allocate(
+            params.disk.id(),
+            params.resize_params.extent_count(),
+            params.allow_move,
+        )
+        .await
+        .map_err(ActionError::action_failed)?;
+    Ok(resizes)
+}
+
+async fn sdr_alloc_regions_undo(
+    sagactx: ActionContext<SagaDiskResize>,
+) -> Result<(), anyhow::Error> {
+    let osagactx = sagactx.user_data();
+
+    let resizes = sagactx.lookup::<Vec<RegionResize>>("region_resizes")?;
+    osagactx.datastore().region_resize_undo(resizes).await?;
+    Ok(())
+}
+
+/// Makes the Crucible Agents' regions match one resize from "AllocRegions"
+///
+/// A region grown in place is simply resized.  A replacement region is created
+/// and then populated from a snapshot of the region it replaces.
+async fn grow_region(
+    log: &Logger,
+    resize: &RegionResize,
+    move_snapshot_id: Uuid,
+) -> Result<(), Error> {
+    let (source_dataset, source_region) = match &resize.replaced {
+        None => {
+            if resize.previous_extent_count == resize.region.extent_count() {
+                return Ok(());
+            }
+            let request = ResizeRegion {
+                extent_count: resize.region.extent_count().try_into().unwrap(),
+            };
+            return RegionClient::new(resize.dataset.address())
+                .region_resize(resize.region.id(), &request)
+                .await
+                .map_err(|e| Error::internal_error(&e.to_string()));
+        }
+        Some(replaced) => replaced,
+    };
+
+    // TODO-correctness If the Disk is attached, writes that land on the
+    // original region after the snapshot is taken are not carried over to the
+    // replacement.  Moving a region of an attached Disk needs help from the
+    // Upstairs.
+    ensure_region_in_dataset(log, &resize.dataset, &resize.region).await?;
+    RegionClient::new(source_dataset.address())
+        .region_snapshot_create(
+            source_region.id(),
+            &CreateRegionSnapshot { id: move_snapshot_id },
+        )
+        .await
+        .map_err(|e| Error::internal_error(&e.to_string()))?;
+    import_regions(
+        vec![(resize.dataset.clone(), resize.region.clone())],
+        vec![(source_dataset.clone(), source_region.clone())],
+        move_snapshot_id,
+    )
+    .await
+}
+
+async fn sdr_regions_grow(
+    sagactx: ActionContext<SagaDiskResize>,
+) -> Result<(), ActionError> {
+    let log = sagactx.user_data().log();
+    let move_snapshot_id = sagactx.lookup::<Uuid>("move_snapshot_id")?;
+    let resizes = sagactx.lookup::<Vec<RegionResize>>("region_resizes")?;
+    let request_count = resizes.len();
+    futures::stream::iter(resizes)
+        .map(|resize| async move {
+            grow_region(log, &resize, move_snapshot_id).await
+        })
+        .buffer_unordered(std::cmp::min(
+            request_count,
+            MAX_CONCURRENT_REGION_REQUESTS,
+        ))
+        .collect::<Vec<Result<_, _>>>()
+        .await
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(ActionError::action_failed)?;
+    Ok(())
+}
+
+async fn sdr_regions_grow_undo(
+    sagactx: ActionContext<SagaDiskResize>,
+) -> Result<(), anyhow::Error> {
+    let move_snapshot_id = sagactx.lookup::<Uuid>("move_snapshot_id")?;
+    let resizes = sagactx.lookup::<Vec<RegionResize>>("region_resizes")?;
+
+    // Shrink the regions that were grown in place back to their original size.
+    for resize in &resizes {
+        if resize.replaced.is_none()
+            && resize.previous_extent_count != resize.region.extent_count()
+        {
+            let request = ResizeRegion {
+                extent_count: resize.previous_extent_count.try_into().unwrap(),
+            };
+            RegionClient::new(resize.dataset.address())
+                .region_resize(resize.region.id(), &request)
+                .await?;
+        }
+    }
+
+    // Destroy any replacement regions, along with the snapshots taken to
+    // populate them.
+    let (replacements, replaced): (Vec<_>, Vec<_>) = resizes
+        .into_iter()
+        .filter_map(|resize| {
+            let replaced = resize.replaced?;
+            Some(((resize.dataset, resize.region), replaced))
+        })
+        .unzip();
+    delete_regions(replacements).await?;
+    delete_region_snapshots(replaced, move_snapshot_id).await?;
+    Ok(())
+}
+
+async fn sdr_update_disk_record(
+    sagactx: ActionContext<SagaDiskResize>,
+) -> Result<db::model::Disk, ActionError> {
+    let osagactx = sagactx.user_data();
+    let params = sagactx.saga_params();
+    let datastore = osagactx.datastore();
+    let opctx = OpContext::for_saga_action(&sagactx, &params.serialized_authn);
+
+    let authz_disk = datastore
+        .disk_lookup_by_id(params.disk.id())
+        .await
+        .map_err(ActionError::action_failed)?;
+    let disk = datastore
+        .disk_update_size(&opctx, &authz_disk, params.resize_params.size)
+        .await
+        .map_err(ActionError::action_failed)?;
+    Ok(disk)
+}
+
+async fn sdr_update_disk_record_undo(
+    sagactx: ActionContext<SagaDiskResize>,
+) -> Result<(), anyhow::Error> {
+    let osagactx = sagactx.user_data();
+    let params = sagactx.saga_params();
+    let datastore = osagactx.datastore();
+    let opctx = OpContext::for_saga_action(&sagactx, &params.serialized_authn);
+
+    let authz_disk = datastore.disk_lookup_by_id(params.disk.id()).await?;
+    datastore
+        .disk_update_size(&opctx, &authz_disk, params.disk.size.into())
+        .await?;
+    Ok(())
+}
+
+async fn sdr_notify_sled_agent(
+    sagactx: ActionContext<SagaDiskResize>,
+) -> Result<(), ActionError> {
+    let osagactx = sagactx.user_data();
+    let params = sagactx.saga_params();
+    let datastore = osagactx.datastore();
+    let opctx = OpContext::for_saga_action(&sagactx, &params.serialized_authn);
+
+    let disk = sagactx.lookup::<db::model::Disk>("resized_disk")?;
+    let instance_id = match disk.runtime().attach_instance_id {
+        Some(instance_id) => instance_id,
+        None => return Ok(()),
+    };
+
+    let authz_instance = datastore
+        .instance_lookup_by_id(instance_id)
+        .await
+        .map_err(ActionError::action_failed)?;
+    let instance = datastore
+        .instance_refetch(&opctx, &authz_instance)
+        .await
+        .map_err(ActionError::action_failed)?;
+    let sled_client = osagactx
+        .sled_client(&instance.runtime().sled_uuid)
+        .await
+        .map_err(ActionError::action_failed)?;
+    sled_client
+        .disk_resize_post(
+            &disk.id(),
+            &sled_agent_client::types::DiskResizeBody {
+                instance_id,
+                size: params.resize_params.size.into(),
+            },
+        )
+        .await
+        .map_err(Error::from)
+        .map_err(ActionError::action_failed)?;
+    Ok(())
+}
+
+async fn sdr_delete_replaced_regions(
+    sagactx: ActionContext<SagaDiskResize>,
+) -> Result<(), ActionError> {
+    let move_snapshot_id = sagactx.lookup::<Uuid>("move_snapshot_id")?;
+    let resizes = sagactx.lookup::<Vec<RegionResize>>("region_resizes")?;
+
+    let replaced: Vec<_> =
+        resizes.into_iter().filter_map(|resize| resize.replaced).collect();
+    delete_region_snapshots(replaced.clone(), move_snapshot_id)
+        .await
+        .map_err(ActionError::action_failed)?;
+    delete_regions(replaced).await.map_err(ActionError::action_failed)?;
+    Ok(())
+}
+
+/*
+ * "Create Snapshot" saga template
+ */
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ParamsSnapshotCreate {
+    /// the Disk to take a snapshot of
+    pub disk: db::model::Disk,
+    pub create_params: params::SnapshotCreate,
+}
+
+#[derive(Debug)]
+pub struct SagaSnapshotCreate;
+impl SagaType for SagaSnapshotCreate {
+    type SagaParamsType = Arc<ParamsSnapshotCreate>;
+    type ExecContextType = Arc<SagaContext>;
+}
+
+fn saga_snapshot_create() -> SagaTemplate<SagaSnapshotCreate> {
+    let mut template_builder = SagaTemplateBuilder::new();
+
+    template_builder.append(
+        "snapshot_id",
+        "GenerateSnapshotId",
+        new_action_noop_undo(saga_generate_uuid),
+    );
+
+    template_builder.append(
+        "no_result",
+        "CreateRegionSnapshots",
+        ActionFunc::new_action(
+            ssc_create_region_snapshots,
+            ssc_create_region_snapshots_undo,
+        ),
+    );
+
+    // The record is created last so that the Snapshot only becomes visible
+    // once its data exists.
+    template_builder.append(
+        "created_snapshot",
+        "CreateSnapshotRecord",
+        new_action_noop_undo(ssc_create_snapshot_record),
+    );
+
+    template_builder.build()
+}
+
+async fn ssc_create_region_snapshots(
+    sagactx: ActionContext<SagaSnapshotCreate>,
+) -> Result<(), ActionError> {
+    let osagactx = sagactx.user_data();
+    let params = sagactx.saga_params();
+    let snapshot_id = sagactx.lookup::<Uuid>("snapshot_id")?;
+
+    let datasets_and_regions = osagactx
+        .datastore()
+        .get_allocated_regions(params.disk.id())
+        .await
+        .map_err(ActionError::action_failed)?;
+    let request = CreateRegionSnapshot { id: snapshot_id };
+    let request = &request;
+    let request_count = datasets_and_regions.len();
+    futures::stream::iter(datasets_and_regions)
+        .map(|(dataset, region)| async move {
+            RegionClient::new(dataset.address())
+                .region_snapshot_create(region.id(), request)
+                .await
+        })
+        .buffer_unordered(std::cmp::min(
+            request_count,
+            MAX_CONCURRENT_REGION_REQUESTS,
+        ))
+        .collect::<Vec<Result<_, _>>>()
+        .await
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| {
+            ActionError::action_failed(Error::internal_error(&e.to_string()))
+        })?;
+    Ok(())
+}
+
+async fn ssc_create_region_snapshots_undo(
+    sagactx: ActionContext<SagaSnapshotCreate>,
+) -> Result<(), anyhow::Error> {
+    let osagactx = sagactx.user_data();
+    let params = sagactx.saga_params();
+    let snapshot_id = sagactx.lookup::<Uuid>("snapshot_id")?;
+
+    let datasets_and_regions =
+        osagactx.datastore().get_allocated_regions(params.disk.id()).await?;
+    delete_region_snapshots(datasets_and_regions, snapshot_id).await?;
+    Ok(())
+}
+
+async fn ssc_create_snapshot_record(
+    sagactx: ActionContext<SagaSnapshotCreate>,
+) -> Result<db::model::Snapshot, ActionError> {
+    let osagactx = sagactx.user_data();
+    let params = sagactx.saga_params();
+
+    let snapshot_id = sagactx.lookup::<Uuid>("snapshot_id")?;
+    let snapshot = db::model::Snapshot::new(
+        snapshot_id,
+        &params.disk,
+        params.create_params.clone(),
+    );
+    let snapshot_created = osagactx
+        .datastore()
+        .disk_create_snapshot(snapshot)
+        .await
+        .map_err(ActionError::action_failed)?;
+    Ok(snapshot_created)
+}
+
+/// Removes snapshot `snapshot_id` from each of the given regions
+///
+/// Regions that don't have the snapshot are skipped so that this can be used
+/// to clean up after a partially-completed "CreateRegionSnapshots" step and can
+/// be safely replayed.
+async fn delete_region_snapshots(
+    datasets_and_regions: Vec<(db::model::Dataset, db::model::Region)>,
+    snapshot_id: Uuid,
+) -> Result<(), Error> {
+    let request_count = datasets_and_regions.len();
+    futures::stream::iter(datasets_and_regions)
+        .map(|(dataset, region)| async move {
+            let result = RegionClient::new(dataset.address())
+                .region_snapshot_delete(region.id(), snapshot_id)
+                .await;
+            match result {
+                Err(e)
+                    if e.status() == Some(reqwest::StatusCode::NOT_FOUND) =>
+                {
+                    Ok(())
+                }
+                result => result,
+            }
+        })
+        .buffer_unordered(std::cmp::min(
+            request_count,
+            MAX_CONCURRENT_REGION_REQUESTS,
+        ))
+        .collect::<Vec<Result<_, _>>>()
+        .await
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| Error::internal_error(&e.to_string()))?;
+    Ok(())
+}
+
+/*
+ * "Delete Snapshot" saga template
+ */
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ParamsSnapshotDelete {
+    pub snapshot_id: Uuid,
+    /// id of the Disk from which the Snapshot was taken
+    pub disk_id: Uuid,
+}
+
+#[derive(Debug)]
+pub struct SagaSnapshotDelete;
+impl SagaType for SagaSnapshotDelete {
+    type SagaParamsType = Arc<ParamsSnapshotDelete>;
+    type ExecContextType = Arc<SagaContext>;
+}
+
+fn saga_snapshot_delete() -> SagaTemplate<SagaSnapshotDelete> {
+    let mut template_builder = SagaTemplateBuilder::new();
+
+    template_builder.append(
+        "no_result",
+        "DeleteSnapshotRecord",
+        new_action_noop_undo(ssd_delete_snapshot_record),
+    );
+
+    template_builder.append(
+        "no_result",
+        "DeleteRegionSnapshots",
+        // TODO(https://github.com/oxidecomputer/omicron/issues/612):
+        // As with "DeleteRegions" in the disk deletion saga, we need a better
+        // way to deal with this failing than propagating the error to the
+        // user.
+        new_action_noop_undo(ssd_delete_region_snapshots),
+    );
+
+    template_builder.build()
+}
+
+async fn ssd_delete_snapshot_record(
+    sagactx: ActionContext<SagaSnapshotDelete>,
+) -> Result<(), ActionError> {
+    let osagactx = sagactx.user_data();
+    let params = sagactx.saga_params();
+
+    osagactx
+        .datastore()
+        .project_delete_snapshot_no_auth(&params.snapshot_id)
+        .await
+        .map_err(ActionError::action_failed)?;
+    Ok(())
+}
+
+async fn ssd_delete_region_snapshots(
+    sagactx: ActionContext<SagaSnapshotDelete>,
+) -> Result<(), ActionError> {
+    let osagactx = sagactx.user_data();
+    let params = sagactx.saga_params();
+
+    let datasets_and_regions = osagactx
+        .datastore()
+        .get_allocated_regions(params.disk_id)
+        .await
+        .map_err(ActionError::action_failed)?;
+    delete_region_snapshots(datasets_and_regions, params.snapshot_id)
+        .await
+        .map_err(ActionError::action_failed)?;
+    Ok(())
+}
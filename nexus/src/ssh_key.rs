@@ -0,0 +1,265 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Validation of SSH public keys that users upload
+//!
+//! Keys are accepted in the one-line format of OpenSSH's `authorized_keys` and
+//! `.pub` files: the algorithm, the base64-encoded key, and an optional
+//! comment.  Options (like `command="..."`) are not accepted, since the keys
+//! are handed to guests for them to install as they see fit.
+
+use omicron_common::api::external::Error;
+use sha2::{Digest, Sha256};
+
+/// Longest key that may be uploaded (this fits a 16384-bit RSA key)
+const MAX_KEY_LENGTH: usize = 4096;
+
+/// Smallest RSA modulus, in bits, that we accept
+const MIN_RSA_BITS: usize = 2048;
+
+/// Algorithms that we accept keys for
+const SUPPORTED_ALGORITHMS: &[&str] = &[
+    "ssh-ed25519",
+    "ssh-rsa",
+    "ecdsa-sha2-nistp256",
+    "ecdsa-sha2-nistp384",
+    "ecdsa-sha2-nistp521",
+    "sk-ssh-ed25519@openssh.com",
+    "sk-ecdsa-sha2-nistp256@openssh.com",
+];
+
+/// An SSH public key that has been checked to be well-formed
+#[derive(Clone, Debug, PartialEq)]
+pub struct SshPublicKey {
+    algorithm: String,
+    blob: Vec<u8>,
+    comment: Option<String>,
+}
+
+impl SshPublicKey {
+    /// Parses a key in OpenSSH's one-line format
+    pub fn parse(key: &str) -> Result<Self, Error> {
+        let invalid = |message: &str| Error::InvalidValue {
+            label: String::from("public_key"),
+            message: String::from(message),
+        };
+
+        let key = key.trim();
+        if key.len() > MAX_KEY_LENGTH {
+            return Err(invalid("key is too long"));
+        }
+        if key.contains(&['\n', '\r'][..]) {
+            return Err(invalid("expected a single key on one line"));
+        }
+
+        let mut fields = key.splitn(3, char::is_whitespace);
+        let algorithm = fields.next().unwrap_or("");
+        if !SUPPORTED_ALGORITHMS.contains(&algorithm) {
+            return Err(invalid(&format!(
+                "unsupported key type \"{}\" (expected one of: {})",
+                algorithm,
+                SUPPORTED_ALGORITHMS.join(", ")
+            )));
+        }
+        let blob = fields
+            .next()
+            .and_then(|encoded| base64::decode(encoded).ok())
+            .ok_or_else(|| invalid("key data is not valid base64"))?;
+        let comment = fields
+            .next()
+            .map(str::trim)
+            .filter(|c| !c.is_empty())
+            .map(String::from);
+
+        let strings = wire_strings(&blob)
+            .ok_or_else(|| invalid("key data is malformed"))?;
+        if strings.first() != Some(&algorithm.as_bytes()) {
+            return Err(invalid("key data does not match the key type"));
+        }
+        let well_formed = match (algorithm, &strings[1..]) {
+            ("ssh-ed25519", [key]) => key.len() == 32,
+            ("sk-ssh-ed25519@openssh.com", [key, _application]) => {
+                key.len() == 32
+            }
+            ("ssh-rsa", [_exponent, modulus]) => {
+                if mpint_bits(modulus) < MIN_RSA_BITS {
+                    return Err(invalid(&format!(
+                        "RSA keys must be at least {} bits",
+                        MIN_RSA_BITS
+                    )));
+                }
+                true
+            }
+            (
+                "sk-ecdsa-sha2-nistp256@openssh.com",
+                [curve, _, _application],
+            ) => *curve == b"nistp256",
+            (_, [curve, _]) => {
+                algorithm.strip_prefix("ecdsa-sha2-").map(str::as_bytes)
+                    == Some(*curve)
+            }
+            _ => false,
+        };
+        if !well_formed {
+            return Err(invalid("key data is malformed"));
+        }
+
+        Ok(SshPublicKey { algorithm: algorithm.to_string(), blob, comment })
+    }
+
+    /// Returns the key's SHA-256 fingerprint, formatted as OpenSSH does (e.g.,
+    /// by `ssh-keygen -l`)
+    pub fn fingerprint(&self) -> String {
+        let digest = Sha256::digest(&self.blob);
+        format!(
+            "SHA256:{}",
+            base64::encode_config(digest, base64::STANDARD_NO_PAD)
+        )
+    }
+}
+
+/// Formats the key back into OpenSSH's one-line format
+impl std::fmt::Display for SshPublicKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.algorithm, base64::encode(&self.blob))?;
+        if let Some(comment) = &self.comment {
+            write!(f, " {}", comment)?;
+        }
+        Ok(())
+    }
+}
+
+/// Splits a key blob into the length-prefixed strings (RFC 4251, section 5)
+/// that it's made of, or returns `None` if it's not made entirely of them
+fn wire_strings(mut blob: &[u8]) -> Option<Vec<&[u8]>> {
+    let mut strings = Vec::new();
+    while !blob.is_empty() {
+        if blob.len() < 4 {
+            return None;
+        }
+        let (length, rest) = blob.split_at(4);
+        let length =
+            u32::from_be_bytes([length[0], length[1], length[2], length[3]])
+                as usize;
+        if rest.len() < length {
+            return None;
+        }
+        let (string, rest) = rest.split_at(length);
+        strings.push(string);
+        blob = rest;
+    }
+    Some(strings)
+}
+
+/// Returns the number of significant bits in a non-negative multi-precision
+/// integer (RFC 4251, section 5)
+fn mpint_bits(mpint: &[u8]) -> usize {
+    match mpint.iter().position(|b| *b != 0) {
+        Some(first) => {
+            (mpint.len() - first) * 8 - mpint[first].leading_zeros() as usize
+        }
+        None => 0,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::SshPublicKey;
+    use omicron_common::api::external::Error;
+
+    const ED25519: &str = "ssh-ed25519 \
+        AAAAC3NzaC1lZDI1NTE5AAAAIKXkSIJ435lKBvyNnNI302aN08q4FQabqURJ0pOjUBoE \
+        user@example.com";
+    const ECDSA: &str = "ecdsa-sha2-nistp256 \
+        AAAAE2VjZHNhLXNoYTItbmlzdHAyNTYAAAAIbmlzdHAyNTYAAABBBF6fi2LGZ0s75q2bOc\
+        LhTXlrR81xQW2nNu2TTV/NgMDpjxBWPohoGcfOrjFE9XEF0JfRFOOEsaVe5kXEVj+dA4k=";
+    const RSA_2048: &str = "ssh-rsa \
+        AAAAB3NzaC1yc2EAAAADAQABAAABAQCwK1PKRjP3YmmTUI/XuGZoHzq7+cpcSPsO9Hpzdr\
+        64TQfsn+PcRqIyOxopN+AH6cadLMecs8u8jJiCYQCi/ggozzaWHCGT1rC9wWCQwVQYPnbO\
+        Jg6emN21vRE463U0dCfA/zTaQlcSPYmHl7cLCHwUEQ0QAhiUyuIDWBbbNVYOPexTnkO+8m\
+        gf+XC2peCqyI2dB7U4/lexi9ft3QAxEc4/a7+LFXkKvtKYP8ymZHqw1XSvYu6V4pGWmrUy\
+        X9krmVIR3S39bjnAtrHdVMlNragnd9KrMDn4/1OThpd9LsyJPytS7Iv6rKZRU22Y9AH6uj\
+        NnGRylkkb5AgPXZCZfBuBZ rsa key";
+    const RSA_1024: &str = "ssh-rsa \
+        AAAAB3NzaC1yc2EAAAADAQABAAAAgQC9Bmxr8soLgGKT2xlPB1ojf0B6etkG72qphQzyRO\
+        Zlh1J0LFbpj6s8UTmlJ6d2q+LIZ1+w/W76lyXozjUYA0dzDd2uK/RxyE1YpKSzIpQfNhH+\
+        uCOUR+AFto6hO5VmhvCaPUjgX8RhAq0NEbd4nn3vdVDLKac/Wr1lVI0/EteZqQ== old";
+
+    fn parse_error(key: &str) -> String {
+        match SshPublicKey::parse(key) {
+            Err(Error::InvalidValue { label, message }) => {
+                assert_eq!(label, "public_key");
+                message
+            }
+            other => panic!("expected an invalid value, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_valid_keys() {
+        // The fingerprints are those printed by `ssh-keygen -l`.
+        let key = SshPublicKey::parse(ED25519).unwrap();
+        assert_eq!(key.to_string(), ED25519);
+        assert_eq!(
+            key.fingerprint(),
+            "SHA256:XAsYWdzvkSXSh399U/qaSvZ8xnv/sJmS6/x8O7MzzN4"
+        );
+
+        let key = SshPublicKey::parse(ECDSA).unwrap();
+        assert_eq!(key.to_string(), ECDSA);
+        assert_eq!(
+            key.fingerprint(),
+            "SHA256:nazpZ0Vu/0c/VPV7bT5FVHh6jI/C3hSOVJEgDxA9fC0"
+        );
+
+        let key = SshPublicKey::parse(RSA_2048).unwrap();
+        assert_eq!(key.to_string(), RSA_2048);
+        assert_eq!(
+            key.fingerprint(),
+            "SHA256:Bh7HZwvvBKwbO0Vi8STwiQ0TVFAs4dV6No8QWeWqPDo"
+        );
+
+        // Surrounding whitespace is dropped.
+        let padded = format!("  {}\t\n", ED25519);
+        assert_eq!(SshPublicKey::parse(&padded).unwrap().to_string(), ED25519);
+    }
+
+    #[test]
+    fn test_parse_invalid_keys() {
+        assert!(parse_error("").starts_with("unsupported key type \"\""));
+        assert!(parse_error("ssh-dss AAAAB3NzaC1kc3MAAACBAJma")
+            .starts_with("unsupported key type \"ssh-dss\""));
+        assert!(parse_error(&format!("command=\"ls\" {}", ED25519))
+            .starts_with("unsupported key type"));
+        assert_eq!(parse_error("ssh-ed25519"), "key data is not valid base64");
+        assert_eq!(
+            parse_error("ssh-ed25519 not!base64"),
+            "key data is not valid base64"
+        );
+        assert_eq!(
+            parse_error(&format!("{}\n{}", ED25519, ECDSA)),
+            "expected a single key on one line"
+        );
+        assert_eq!(
+            parse_error(&"ssh-ed25519 AAAA".repeat(1000)),
+            "key is too long"
+        );
+
+        // The key type must match the one inside the key data.
+        let mismatched = ED25519.replacen("ssh-ed25519", "ssh-rsa", 1);
+        assert_eq!(
+            parse_error(&mismatched),
+            "key data does not match the key type"
+        );
+
+        // Truncated key data is rejected, even if it's valid base64.
+        let truncated = ED25519.replacen("UBoE", "", 1);
+        assert_eq!(parse_error(&truncated), "key data is malformed");
+
+        assert_eq!(
+            parse_error(RSA_1024),
+            "RSA keys must be at least 2048 bits"
+        );
+    }
+}
@@ -0,0 +1,771 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Rate limiting and load shedding for the external API (see RFD 6)
+//!
+//! Each external API request is subject to a token-bucket rate limit for the
+//! address it came from and, once it's been authenticated, another for the
+//! actor making it.  Requests over either limit fail with 429 ("Too Many
+//! Requests").  Separately, Nexus sheds load by failing requests with 503
+//! ("Service Unavailable") while too many requests are already in progress, or
+//! while getting a database connection takes too long, rather than letting them
+//! pile up behind the ones that are already waiting.  Each rejected request is
+//! counted, by reason, in the `requests_throttled` metric.
+//!
+//! Dropshot doesn't tell handlers which address a request came from, so the
+//! per-address limit can only go by the `X-Forwarded-For` header, which it
+//! trusts only when Nexus is configured with the networks of the proxies in
+//! front of it (in which case Nexus must not be reachable except through them).
+//! The request's source is then the last address in that header that isn't one
+//! of those proxies.  Requests whose source isn't known this way -- including
+//! every request, when no proxies are configured -- aren't subject to a
+//! per-address limit at all, only to the per-actor one: putting them all in
+//! one bucket would let any one client get everybody else throttled.
+//! TODO-security Key the limit on the connection's peer address once Dropshot
+//! exposes it, so that unauthenticated requests (e.g., logins) are limited
+//! even without proxies.
+//!
+//! Dropshot errors can't carry headers, so external API handlers return a
+//! [`HttpResponseThrottled`], which adds a `Retry-After` header to a 429.  The
+//! error's message also says how many seconds to wait.
+
+use crate::db;
+use dropshot::ApiEndpointResponse;
+use dropshot::HttpError;
+use dropshot::HttpResponse;
+use http::header;
+use http::HeaderValue;
+use http::Response;
+use http::StatusCode;
+use hyper::Body;
+use ipnetwork::IpNetwork;
+use oximeter::types::Cumulative;
+use oximeter::{Metric, Producer, Sample};
+use oximeter_instruments::http::HttpService;
+use serde::Deserialize;
+use serde::Serialize;
+use slog::Logger;
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::marker::PhantomData;
+use std::net::IpAddr;
+use std::num::NonZeroU32;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// Configuration for throttling external API requests
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct ThrottleConfig {
+    /// rate limit for requests made by each authenticated actor
+    pub per_actor: RateLimitConfig,
+    /// rate limit for requests from each source IP address
+    pub per_source_ip: RateLimitConfig,
+    /// networks of the proxies whose `X-Forwarded-For` entries are trusted to
+    /// report where a request came from (if empty, that header is ignored)
+    pub trusted_proxies: Vec<IpNetwork>,
+    /// most external API requests that may be in progress at once
+    pub max_concurrent_requests: NonZeroU32,
+    /// requests are shed while getting a database connection takes longer
+    /// than this (in milliseconds)
+    pub db_wait_threshold_ms: u64,
+}
+
+/// Configuration for a token-bucket rate limit
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub struct RateLimitConfig {
+    /// sustained rate at which requests are allowed
+    pub requests_per_second: NonZeroU32,
+    /// most requests allowed at once, after a quiet period
+    pub burst: NonZeroU32,
+}
+
+impl RateLimitConfig {
+    fn rate(&self) -> f64 {
+        f64::from(self.requests_per_second.get())
+    }
+
+    fn capacity(&self) -> f64 {
+        f64::from(self.burst.get())
+    }
+}
+
+tokio::task_local! {
+    /// How long (in whole seconds) the client should wait before retrying the
+    /// external API request that this task is handling, if it was throttled
+    static RETRY_AFTER: Cell<Option<u64>>;
+}
+
+/// Runs `future`, which handles an external API request, and returns its
+/// output along with how long the client should wait before retrying, if the
+/// throttle rejected the request along the way
+pub async fn retry_after_scope<F: Future>(
+    future: F,
+) -> (F::Output, Option<u64>) {
+    RETRY_AFTER
+        .scope(Cell::new(None), async move {
+            let output = future.await;
+            (output, RETRY_AFTER.with(Cell::get))
+        })
+        .await
+}
+
+/// Response for an external API request that may have been throttled: either
+/// the response built from the handler's own `R` or a 429 error with a
+/// `Retry-After` header
+///
+/// The API description only documents `R`.
+pub enum HttpResponseThrottled<R> {
+    Handled(Response<Body>, PhantomData<fn() -> R>),
+    TooManyRequests { error: HttpError, request_id: String, retry_after: u64 },
+}
+
+impl<R: HttpResponse> HttpResponse for HttpResponseThrottled<R> {
+    fn to_result(self) -> Result<Response<Body>, HttpError> {
+        match self {
+            HttpResponseThrottled::Handled(response, _) => Ok(response),
+            HttpResponseThrottled::TooManyRequests {
+                error,
+                request_id,
+                retry_after,
+            } => {
+                let mut response = error.into_response(&request_id);
+                response.headers_mut().insert(
+                    header::RETRY_AFTER,
+                    HeaderValue::from(retry_after),
+                );
+                Ok(response)
+            }
+        }
+    }
+
+    fn metadata() -> ApiEndpointResponse {
+        R::metadata()
+    }
+}
+
+/// How often to measure how long it takes to get a database connection
+const DB_WAIT_PROBE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Most token buckets that a [`RateLimiter`] keeps before it discards the full
+/// ones
+const MAX_BUCKETS: usize = 10_000;
+
+/// A bucket of tokens that refills at a steady rate, from which each request
+/// takes one
+#[derive(Debug)]
+struct TokenBucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn full(limit: &RateLimitConfig, now: Instant) -> Self {
+        TokenBucket { tokens: limit.capacity(), last_refill: now }
+    }
+
+    /// Adds the tokens accrued since the bucket was last refilled
+    fn refill(&mut self, limit: &RateLimitConfig, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last_refill);
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * limit.rate())
+            .min(limit.capacity());
+        self.last_refill = self.last_refill.max(now);
+    }
+
+    /// Takes a token for a request, or returns how long it will be until one
+    /// is available
+    fn take(
+        &mut self,
+        limit: &RateLimitConfig,
+        now: Instant,
+    ) -> Result<(), Duration> {
+        self.refill(limit, now);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - self.tokens) / limit.rate()))
+        }
+    }
+
+    fn is_full(&mut self, limit: &RateLimitConfig, now: Instant) -> bool {
+        self.refill(limit, now);
+        self.tokens >= limit.capacity()
+    }
+}
+
+/// Applies the same rate limit separately to each of a set of keys (e.g.,
+/// actors)
+#[derive(Debug)]
+struct RateLimiter<K> {
+    limit: RateLimitConfig,
+    buckets: Mutex<BTreeMap<K, TokenBucket>>,
+}
+
+impl<K: Ord> RateLimiter<K> {
+    fn new(limit: RateLimitConfig) -> Self {
+        RateLimiter { limit, buckets: Mutex::new(BTreeMap::new()) }
+    }
+
+    /// Admits a request for `key`, or returns how long it will be until one
+    /// would be admitted
+    fn check(&self, key: K, now: Instant) -> Result<(), Duration> {
+        let limit = &self.limit;
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() >= MAX_BUCKETS && !buckets.contains_key(&key) {
+            // A full bucket behaves exactly like one that doesn't exist yet,
+            // so discarding them loses nothing.
+            buckets.retain(|_, bucket| !bucket.is_full(limit, now));
+        }
+        buckets
+            .entry(key)
+            .or_insert_with(|| TokenBucket::full(limit, now))
+            .take(limit, now)
+    }
+}
+
+/// Why a request was throttled
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+enum ThrottleReason {
+    /// the actor exceeded its rate limit
+    ActorRate,
+    /// the source address exceeded its rate limit
+    SourceIpRate,
+    /// too many requests were already in progress
+    Concurrency,
+    /// getting a database connection was taking too long
+    DbWait,
+}
+
+impl ThrottleReason {
+    const ALL: [ThrottleReason; 4] = [
+        ThrottleReason::ActorRate,
+        ThrottleReason::SourceIpRate,
+        ThrottleReason::Concurrency,
+        ThrottleReason::DbWait,
+    ];
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            ThrottleReason::ActorRate => "actor_rate",
+            ThrottleReason::SourceIpRate => "source_ip_rate",
+            ThrottleReason::Concurrency => "concurrency",
+            ThrottleReason::DbWait => "db_wait",
+        }
+    }
+}
+
+/// The number of external API requests that were throttled for a particular
+/// reason
+#[derive(Clone, Debug, Metric)]
+pub struct RequestsThrottled {
+    pub reason: String,
+    #[datum]
+    pub count: Cumulative<i64>,
+}
+
+/// Counts throttled requests, for export through oximeter
+#[derive(Clone, Debug)]
+pub struct ThrottleStats {
+    service: HttpService,
+    counts: Arc<Mutex<BTreeMap<ThrottleReason, RequestsThrottled>>>,
+}
+
+impl ThrottleStats {
+    fn new(service: HttpService) -> Self {
+        let counts = ThrottleReason::ALL
+            .iter()
+            .map(|reason| {
+                let count = RequestsThrottled {
+                    reason: reason.as_str().to_string(),
+                    count: Cumulative::new(0),
+                };
+                (*reason, count)
+            })
+            .collect();
+        ThrottleStats { service, counts: Arc::new(Mutex::new(counts)) }
+    }
+
+    fn record(&self, reason: ThrottleReason) {
+        let mut counts = self.counts.lock().unwrap();
+        counts.get_mut(&reason).unwrap().count.increment();
+    }
+}
+
+impl Producer for ThrottleStats {
+    fn produce(
+        &mut self,
+    ) -> Result<Box<(dyn Iterator<Item = Sample> + 'static)>, oximeter::Error>
+    {
+        // As with `LatencyTracker`, the samples are copied out so that the
+        // iterator doesn't borrow from behind the lock.
+        #[allow(clippy::needless_collect)]
+        let counts: Vec<_> =
+            self.counts.lock().unwrap().values().cloned().collect();
+        let service = self.service.clone();
+        Ok(Box::new(
+            counts.into_iter().map(move |count| Sample::new(&service, &count)),
+        ))
+    }
+}
+
+/// Recent measurements of how long it takes to get a database connection
+#[derive(Debug, Default)]
+struct DbWait {
+    /// how long the last completed measurement took
+    last: Duration,
+    /// when the measurement in progress (if any) started
+    probe_started: Option<Instant>,
+}
+
+/// Decides which external API requests to handle
+#[derive(Debug)]
+pub struct Throttle {
+    per_actor: RateLimiter<Uuid>,
+    /// applies only to requests whose source address is known
+    per_source_ip: RateLimiter<IpAddr>,
+    trusted_proxies: Vec<IpNetwork>,
+    max_concurrent_requests: usize,
+    concurrent_requests: Arc<AtomicUsize>,
+    db_wait_threshold: Duration,
+    db_wait: Mutex<DbWait>,
+    stats: ThrottleStats,
+}
+
+/// Represents a request that the [`Throttle`] admitted, for as long as it's
+/// being handled
+#[derive(Debug)]
+pub struct Admission {
+    concurrent_requests: Arc<AtomicUsize>,
+}
+
+impl Drop for Admission {
+    fn drop(&mut self) {
+        self.concurrent_requests.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl Throttle {
+    /// Creates a throttle whose counters are reported as belonging to
+    /// `service`
+    pub fn new(config: &ThrottleConfig, service: HttpService) -> Self {
+        Throttle {
+            per_actor: RateLimiter::new(config.per_actor),
+            per_source_ip: RateLimiter::new(config.per_source_ip),
+            trusted_proxies: config.trusted_proxies.clone(),
+            max_concurrent_requests: config.max_concurrent_requests.get()
+                as usize,
+            concurrent_requests: Arc::new(AtomicUsize::new(0)),
+            db_wait_threshold: Duration::from_millis(
+                config.db_wait_threshold_ms,
+            ),
+            db_wait: Mutex::new(DbWait::default()),
+            stats: ThrottleStats::new(service),
+        }
+    }
+
+    /// Returns the producer of this throttle's metrics
+    pub fn stats(&self) -> ThrottleStats {
+        self.stats.clone()
+    }
+
+    /// Decides whether to start handling `request` at all, applying the load
+    /// shedding checks and the rate limit for its source address (if that's
+    /// known)
+    ///
+    /// The request counts as being in progress until the returned `Admission`
+    /// is dropped.
+    pub fn admit<B>(
+        &self,
+        request: &http::Request<B>,
+    ) -> Result<Admission, HttpError> {
+        let now = Instant::now();
+        let db_wait = self.db_wait(now);
+        if db_wait > self.db_wait_threshold {
+            return Err(self.shed(
+                ThrottleReason::DbWait,
+                format!("waiting {:?} for a database connection", db_wait),
+            ));
+        }
+
+        let in_progress =
+            self.concurrent_requests.fetch_add(1, Ordering::SeqCst);
+        let admission = Admission {
+            concurrent_requests: Arc::clone(&self.concurrent_requests),
+        };
+        if in_progress >= self.max_concurrent_requests {
+            return Err(self.shed(
+                ThrottleReason::Concurrency,
+                format!("{} requests already in progress", in_progress),
+            ));
+        }
+
+        if let Some(address) =
+            source_ip(request.headers(), &self.trusted_proxies)
+        {
+            self.per_source_ip.check(address, now).map_err(|retry_after| {
+                self.throttled(
+                    ThrottleReason::SourceIpRate,
+                    "this address",
+                    retry_after,
+                )
+            })?;
+        }
+
+        Ok(admission)
+    }
+
+    /// Applies the rate limit for requests made by the actor `actor_id`
+    pub fn check_actor(&self, actor_id: Uuid) -> Result<(), HttpError> {
+        self.per_actor.check(actor_id, Instant::now()).map_err(|retry_after| {
+            self.throttled(ThrottleReason::ActorRate, "this user", retry_after)
+        })
+    }
+
+    /// Starts a task that periodically measures how long it takes to get a
+    /// connection from `pool`, which the load shedding checks use
+    ///
+    /// The task exits once the throttle has been dropped.
+    pub fn monitor_db_wait(self: &Arc<Self>, pool: &db::Pool, log: Logger) {
+        let throttle = Arc::downgrade(self);
+        let pool = pool.pool().clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(DB_WAIT_PROBE_INTERVAL);
+            loop {
+                interval.tick().await;
+                let throttle = match throttle.upgrade() {
+                    Some(throttle) => throttle,
+                    None => break,
+                };
+                let started = Instant::now();
+                throttle.db_wait.lock().unwrap().probe_started = Some(started);
+                if let Err(error) = pool.get().await {
+                    warn!(log, "failed to get a database connection";
+                        "error" => ?error);
+                }
+                let mut db_wait = throttle.db_wait.lock().unwrap();
+                db_wait.last = started.elapsed();
+                db_wait.probe_started = None;
+            }
+        });
+    }
+
+    /// Returns how long it's taking to get a database connection: the longer
+    /// of the last measurement and the one in progress
+    fn db_wait(&self, now: Instant) -> Duration {
+        let db_wait = self.db_wait.lock().unwrap();
+        let in_progress = db_wait
+            .probe_started
+            .map(|started| now.saturating_duration_since(started))
+            .unwrap_or_default();
+        db_wait.last.max(in_progress)
+    }
+
+    fn shed(&self, reason: ThrottleReason, why: String) -> HttpError {
+        self.stats.record(reason);
+        HttpError::for_unavail(
+            Some(String::from("ServiceNotAvailable")),
+            format!("shedding load: {}", why),
+        )
+    }
+
+    fn throttled(
+        &self,
+        reason: ThrottleReason,
+        source: &str,
+        retry_after: Duration,
+    ) -> HttpError {
+        self.stats.record(reason);
+        // As with `Retry-After`, this is a whole number of seconds.
+        let seconds = retry_after.as_secs_f64().ceil().max(1.0) as u64;
+        // The throttle may also be used outside of an external API handler
+        // (e.g., in tests), where there's no `Retry-After` header to set.
+        let _ = RETRY_AFTER.try_with(|cell| cell.set(Some(seconds)));
+        HttpError::for_client_error(
+            Some(String::from("TooManyRequests")),
+            StatusCode::TOO_MANY_REQUESTS,
+            format!(
+                "too many requests from {}; retry after {} seconds",
+                source, seconds
+            ),
+        )
+    }
+}
+
+/// Returns the address that a request with `headers` came from, according to
+/// the proxies in `trusted_proxies`, if it's known
+///
+/// Each proxy appends the address it got the request from to
+/// `X-Forwarded-For`, so the source is the last address there that isn't one
+/// of our proxies.  Anything before that was written by the client.
+fn source_ip(
+    headers: &http::HeaderMap,
+    trusted_proxies: &[IpNetwork],
+) -> Option<IpAddr> {
+    if trusted_proxies.is_empty() {
+        return None;
+    }
+    let mut source = None;
+    for forwarded_for in headers.get_all("x-forwarded-for").iter().rev() {
+        for entry in forwarded_for.to_str().ok()?.rsplit(',') {
+            let address = entry.trim().parse::<IpAddr>().ok()?;
+            source = Some(address);
+            if !trusted_proxies.iter().any(|net| net.contains(address)) {
+                return source;
+            }
+        }
+    }
+    // Every address is one of our proxies; the first one is the source.
+    source
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        retry_after_scope, source_ip, RateLimitConfig, RateLimiter, Throttle,
+        ThrottleConfig, TokenBucket, MAX_BUCKETS,
+    };
+    use http::StatusCode;
+    use ipnetwork::IpNetwork;
+    use oximeter::types::Datum;
+    use oximeter::Producer;
+    use oximeter_instruments::http::HttpService;
+    use std::collections::BTreeMap;
+    use std::net::IpAddr;
+    use std::num::NonZeroU32;
+    use std::time::{Duration, Instant};
+    use uuid::Uuid;
+
+    fn limit(requests_per_second: u32, burst: u32) -> RateLimitConfig {
+        RateLimitConfig {
+            requests_per_second: NonZeroU32::new(requests_per_second).unwrap(),
+            burst: NonZeroU32::new(burst).unwrap(),
+        }
+    }
+
+    fn throttle(max_concurrent_requests: u32) -> Throttle {
+        Throttle::new(
+            &ThrottleConfig {
+                per_actor: limit(1, 1),
+                per_source_ip: limit(1, 2),
+                trusted_proxies: vec!["198.51.100.0/24".parse().unwrap()],
+                max_concurrent_requests: NonZeroU32::new(
+                    max_concurrent_requests,
+                )
+                .unwrap(),
+                db_wait_threshold_ms: 1000,
+            },
+            HttpService { name: String::from("test"), id: Uuid::new_v4() },
+        )
+    }
+
+    fn request(forwarded_for: Option<&str>) -> http::Request<()> {
+        let mut builder = http::Request::builder().uri("/organizations");
+        if let Some(forwarded_for) = forwarded_for {
+            builder = builder.header("x-forwarded-for", forwarded_for);
+        }
+        builder.body(()).unwrap()
+    }
+
+    /// Returns the throttle's counts of throttled requests, by reason
+    fn counts(throttle: &Throttle) -> BTreeMap<String, i64> {
+        throttle
+            .stats()
+            .produce()
+            .unwrap()
+            .map(|sample| {
+                assert_eq!(
+                    sample.timeseries_name,
+                    "http_service:requests_throttled"
+                );
+                let reason = sample
+                    .fields()
+                    .into_iter()
+                    .find(|field| field.name == "reason")
+                    .unwrap()
+                    .value
+                    .to_string();
+                match sample.measurement.datum() {
+                    Datum::CumulativeI64(count) => (reason, count.value()),
+                    other => panic!("unexpected datum: {:?}", other),
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_token_bucket() {
+        let limit = limit(2, 3);
+        let start = Instant::now();
+        let at = |millis| start + Duration::from_millis(millis);
+        let mut bucket = TokenBucket::full(&limit, start);
+
+        // A full bucket admits a burst, then one request per refill interval.
+        for _ in 0..3 {
+            assert_eq!(bucket.take(&limit, start), Ok(()));
+        }
+        assert_eq!(bucket.take(&limit, start), Err(Duration::from_millis(500)));
+        assert_eq!(
+            bucket.take(&limit, at(250)),
+            Err(Duration::from_millis(250))
+        );
+        assert_eq!(bucket.take(&limit, at(500)), Ok(()));
+        assert_eq!(
+            bucket.take(&limit, at(500)),
+            Err(Duration::from_millis(500))
+        );
+
+        // The bucket never holds more than the burst.
+        assert!(!bucket.is_full(&limit, at(1500)));
+        assert!(bucket.is_full(&limit, at(2000)));
+        assert!(bucket.is_full(&limit, at(60_000)));
+        for _ in 0..3 {
+            assert_eq!(bucket.take(&limit, at(60_000)), Ok(()));
+        }
+        assert!(bucket.take(&limit, at(60_000)).is_err());
+    }
+
+    #[test]
+    fn test_rate_limiter() {
+        let limiter = RateLimiter::new(limit(1, 1));
+        let start = Instant::now();
+
+        // Each key has its own bucket.
+        assert_eq!(limiter.check(1, start), Ok(()));
+        assert!(limiter.check(1, start).is_err());
+        assert_eq!(limiter.check(2, start), Ok(()));
+
+        // Full buckets are discarded once there are too many of them, but the
+        // others are kept.
+        let later = start + Duration::from_secs(1);
+        for key in 3..MAX_BUCKETS + 1 {
+            assert_eq!(limiter.check(key, later), Ok(()));
+        }
+        assert_eq!(limiter.buckets.lock().unwrap().len(), MAX_BUCKETS);
+        assert_eq!(limiter.check(0, later), Ok(()));
+        let buckets = limiter.buckets.lock().unwrap();
+        assert_eq!(buckets.len(), MAX_BUCKETS - 1);
+        assert!(!buckets.contains_key(&1));
+        assert!(!buckets.contains_key(&2));
+    }
+
+    #[test]
+    fn test_source_ip() {
+        let proxies: Vec<IpNetwork> = vec![
+            "198.51.100.0/24".parse().unwrap(),
+            "2001:db8:1::/48".parse().unwrap(),
+        ];
+        let address =
+            |value: Option<&str>| source_ip(request(value).headers(), &proxies);
+        let ip = |s: &str| Some(s.parse::<IpAddr>().unwrap());
+        assert_eq!(address(None), None);
+        assert_eq!(address(Some("192.0.2.1")), ip("192.0.2.1"));
+        assert_eq!(address(Some("192.0.2.1, 2001:db8::1")), ip("2001:db8::1"));
+        assert_eq!(address(Some("192.0.2.1, not-an-address")), None);
+
+        // Addresses added by our own proxies are skipped, but anything before
+        // the first address that isn't one of them is up to the client.
+        assert_eq!(
+            address(Some("192.0.2.1, 192.0.2.2, 198.51.100.1")),
+            ip("192.0.2.2")
+        );
+        assert_eq!(
+            address(Some("not-an-address, 192.0.2.2, 2001:db8:1::1")),
+            ip("192.0.2.2")
+        );
+        assert_eq!(
+            address(Some("198.51.100.2, 198.51.100.1")),
+            ip("198.51.100.2")
+        );
+
+        // Without any trusted proxies, the header is ignored.
+        let headers = request(Some("192.0.2.1")).headers().clone();
+        assert_eq!(source_ip(&headers, &[]), None);
+    }
+
+    #[test]
+    fn test_throttle() {
+        let throttle = throttle(2);
+
+        // Requests are limited by source address...
+        let first = throttle.admit(&request(Some("192.0.2.1"))).unwrap();
+        drop(throttle.admit(&request(Some("192.0.2.1"))).unwrap());
+        let error = throttle.admit(&request(Some("192.0.2.1"))).unwrap_err();
+        assert_eq!(error.status_code, StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(error.error_code.as_deref(), Some("TooManyRequests"));
+        assert_eq!(
+            error.external_message,
+            "too many requests from this address; retry after 1 seconds"
+        );
+        drop(throttle.admit(&request(Some("192.0.2.2"))).unwrap());
+
+        // ... if it's known.  Requests from unknown sources don't count
+        // against each other (or against any known address)...
+        for _ in 0..5 {
+            drop(throttle.admit(&request(None)).unwrap());
+            drop(throttle.admit(&request(Some("192.0.2.1, bogus"))).unwrap());
+        }
+
+        // ... and by actor.
+        let actor = Uuid::new_v4();
+        throttle.check_actor(actor).unwrap();
+        let error = throttle.check_actor(actor).unwrap_err();
+        assert_eq!(error.status_code, StatusCode::TOO_MANY_REQUESTS);
+        throttle.check_actor(Uuid::new_v4()).unwrap();
+
+        // Requests are shed while too many are in progress...
+        let second = throttle.admit(&request(Some("192.0.2.3"))).unwrap();
+        let error = throttle.admit(&request(Some("192.0.2.4"))).unwrap_err();
+        assert_eq!(error.status_code, StatusCode::SERVICE_UNAVAILABLE);
+        drop(first);
+        drop(second);
+        drop(throttle.admit(&request(Some("192.0.2.5"))).unwrap());
+
+        // ... or while the database is slow to hand out connections.
+        throttle.db_wait.lock().unwrap().last = Duration::from_secs(2);
+        let error = throttle.admit(&request(Some("192.0.2.6"))).unwrap_err();
+        assert_eq!(error.status_code, StatusCode::SERVICE_UNAVAILABLE);
+        throttle.db_wait.lock().unwrap().last = Duration::from_millis(10);
+        throttle.db_wait.lock().unwrap().probe_started =
+            Some(Instant::now() - Duration::from_secs(2));
+        assert!(throttle.admit(&request(Some("192.0.2.6"))).is_err());
+        throttle.db_wait.lock().unwrap().probe_started = None;
+        drop(throttle.admit(&request(Some("192.0.2.6"))).unwrap());
+
+        let expected = vec![
+            ("actor_rate", 1),
+            ("concurrency", 1),
+            ("db_wait", 2),
+            ("source_ip_rate", 1),
+        ];
+        assert_eq!(
+            counts(&throttle),
+            expected
+                .into_iter()
+                .map(|(reason, count)| (reason.to_string(), count))
+                .collect::<BTreeMap<_, _>>()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_retry_after() {
+        let throttle = throttle(2);
+        let actor = Uuid::new_v4();
+
+        let (result, retry_after) =
+            retry_after_scope(async { throttle.check_actor(actor) }).await;
+        assert!(result.is_ok());
+        assert_eq!(retry_after, None);
+
+        let (result, retry_after) =
+            retry_after_scope(async { throttle.check_actor(actor) }).await;
+        assert_eq!(
+            result.unwrap_err().status_code,
+            StatusCode::TOO_MANY_REQUESTS
+        );
+        assert_eq!(retry_after, Some(1));
+    }
+}
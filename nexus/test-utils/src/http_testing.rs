@@ -86,6 +86,7 @@ impl<'a> RequestBuilder<'a> {
                 http::header::CONTENT_LENGTH,
                 http::header::CONTENT_TYPE,
                 http::header::DATE,
+                http::header::ETAG,
                 http::header::LOCATION,
                 http::header::SET_COOKIE,
                 http::header::HeaderName::from_static("x-request-id"),
@@ -400,6 +401,7 @@ impl TestResponse {
 
 /// Specifies what user (if any) the caller wants to use for authenticating to
 /// the server
+#[derive(Clone, Copy)]
 pub enum AuthnMode {
     UnprivilegedUser,
     PrivilegedUser,
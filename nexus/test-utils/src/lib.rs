@@ -31,12 +31,18 @@ const RACK_UUID: &str = "c19a698f-c6f9-4a17-ae30-20d711b8f7dc";
 pub const OXIMETER_UUID: &str = "39e6175b-4df2-4730-b11d-cbc1e60a2e78";
 pub const PRODUCER_UUID: &str = "a6458b7d-87c3-4483-be96-854d814c20de";
 
+/// Number of hardware threads reported by each simulated sled agent
+pub const SLED_AGENT_HARDWARE_THREADS: u32 = 32;
+/// Amount of physical memory (in bytes) reported by each simulated sled agent
+pub const SLED_AGENT_PHYSICAL_RAM: u64 = 64 << 30;
+
 pub struct ControlPlaneTestContext {
     pub external_client: ClientTestContext,
     pub internal_client: ClientTestContext,
     pub server: omicron_nexus::Server,
     pub database: dev::db::CockroachInstance,
     pub clickhouse: dev::clickhouse::ClickHouseInstance,
+    pub internal_dns: internal_dns::Server,
     pub logctx: LogContext,
     pub sled_agent: sim::Server,
     pub oximeter: Oximeter,
@@ -49,6 +55,7 @@ impl ControlPlaneTestContext {
         self.server.http_server_internal.close().await.unwrap();
         self.database.cleanup().await.unwrap();
         self.clickhouse.cleanup().await.unwrap();
+        self.internal_dns.close().await.unwrap();
         self.sled_agent.http_server.close().await.unwrap();
         self.oximeter.close().await.unwrap();
         self.producer.close().await.unwrap();
@@ -100,9 +107,16 @@ pub async fn test_setup_with_config(
     /* Start ClickHouse database server. */
     let clickhouse = dev::clickhouse::ClickHouseInstance::new(0).await.unwrap();
 
-    /* Store actual address/port information for the databases after they start. */
+    /* Start the internal DNS server, which Nexus keeps up to date. */
+    let internal_dns = start_internal_dns(log).await.unwrap();
+
+    /*
+     * Store actual address/port information for the databases and the DNS
+     * server after they start.
+     */
     config.database.url = database.pg_config().clone();
     config.timeseries_db.address.set_port(clickhouse.port());
+    config.internal_dns.address = internal_dns.http_server.local_addr();
 
     let server = omicron_nexus::Server::start(&config, &rack_id, &logctx.log)
         .await
@@ -161,6 +175,7 @@ pub async fn test_setup_with_config(
         internal_client: testctx_internal,
         database,
         clickhouse,
+        internal_dns,
         sled_agent,
         oximeter,
         producer,
@@ -187,11 +202,32 @@ pub async fn start_sled_agent(
             zpools: vec![],
             ip: IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
         },
+        hardware: sim::ConfigHardware {
+            hardware_threads: SLED_AGENT_HARDWARE_THREADS,
+            physical_ram: SLED_AGENT_PHYSICAL_RAM,
+        },
     };
 
     sim::Server::start(&config, &log).await
 }
 
+pub async fn start_internal_dns(
+    log: &Logger,
+) -> Result<internal_dns::Server, String> {
+    let config = internal_dns::Config {
+        log: ConfigLogging::StderrTerminal { level: ConfigLoggingLevel::Error },
+        dropshot: ConfigDropshot {
+            bind_address: SocketAddr::new("127.0.0.1".parse().unwrap(), 0),
+            ..Default::default()
+        },
+        dns: internal_dns::DnsConfig {
+            bind_address: SocketAddr::new("127.0.0.1".parse().unwrap(), 0),
+        },
+    };
+    let log = log.new(o!("component" => "internal_dns::Server"));
+    internal_dns::Server::start(&config, &log).await.map_err(|e| e.to_string())
+}
+
 pub async fn start_oximeter(
     nexus_address: SocketAddr,
     db_port: u16,
@@ -19,7 +19,7 @@ use omicron_common::api::external::InstanceCpuCount;
 use omicron_common::api::external::VpcRouter;
 use omicron_nexus::crucible_agent_client::types::State as RegionState;
 use omicron_nexus::external_api::params;
-use omicron_nexus::external_api::views::{Organization, Project, Vpc};
+use omicron_nexus::external_api::views::{Organization, Project, User, Vpc};
 use omicron_sled_agent::sim::SledAgent;
 use std::sync::Arc;
 use uuid::Uuid;
@@ -69,12 +69,33 @@ pub async fn create_organization(
             identity: IdentityMetadataCreateParams {
                 name: organization_name.parse().unwrap(),
                 description: "an org".to_string(),
+                labels: Default::default(),
             },
         },
     )
     .await
 }
 
+pub async fn create_user(
+    client: &ClientTestContext,
+    user_name: &str,
+    password: &str,
+) -> User {
+    object_create(
+        client,
+        "/users",
+        &params::UserCreate {
+            identity: IdentityMetadataCreateParams {
+                name: user_name.parse().unwrap(),
+                description: "a user".to_string(),
+                labels: Default::default(),
+            },
+            password: password.to_string(),
+        },
+    )
+    .await
+}
+
 pub async fn create_project(
     client: &ClientTestContext,
     organization_name: &str,
@@ -88,6 +109,7 @@ pub async fn create_project(
             identity: IdentityMetadataCreateParams {
                 name: project_name.parse().unwrap(),
                 description: "a pier".to_string(),
+                labels: Default::default(),
             },
         },
     )
@@ -111,6 +133,7 @@ pub async fn create_disk(
             identity: IdentityMetadataCreateParams {
                 name: disk_name.parse().unwrap(),
                 description: String::from("sells rainsticks"),
+                labels: Default::default(),
             },
             snapshot_id: None,
             size: ByteCount::from_gibibytes_u32(1),
@@ -136,10 +159,15 @@ pub async fn create_instance(
             identity: IdentityMetadataCreateParams {
                 name: instance_name.parse().unwrap(),
                 description: format!("instance {:?}", instance_name),
+                labels: Default::default(),
             },
             ncpus: InstanceCpuCount(4),
             memory: ByteCount::from_mebibytes_u32(256),
             hostname: String::from("the_host"),
+            affinity_group: None,
+            user_data: vec![],
+            ssh_public_keys: vec![],
+            network_interfaces: vec![],
         },
     )
     .await
@@ -162,6 +190,7 @@ pub async fn create_vpc(
             identity: IdentityMetadataCreateParams {
                 name: vpc_name.parse().unwrap(),
                 description: "vpc description".to_string(),
+                labels: Default::default(),
             },
             ipv6_prefix: None,
             dns_name: "abc".parse().unwrap(),
@@ -191,6 +220,7 @@ pub async fn create_vpc_with_error(
                 identity: IdentityMetadataCreateParams {
                     name: vpc_name.parse().unwrap(),
                     description: String::from("vpc description"),
+                    labels: Default::default(),
                 },
                 ipv6_prefix: None,
                 dns_name: "abc".parse().unwrap(),
@@ -218,6 +248,7 @@ pub async fn create_router(
             identity: IdentityMetadataCreateParams {
                 name: router_name.parse().unwrap(),
                 description: String::from("router description"),
+                labels: Default::default(),
             },
         },
     )
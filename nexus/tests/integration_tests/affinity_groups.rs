@@ -0,0 +1,265 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use http::method::Method;
+use http::StatusCode;
+use omicron_common::api::external::AffinityPolicy;
+use omicron_common::api::external::ByteCount;
+use omicron_common::api::external::IdentityMetadataCreateParams;
+use omicron_common::api::external::IdentityMetadataUpdateParams;
+use omicron_common::api::external::Instance;
+use omicron_common::api::external::InstanceCpuCount;
+use omicron_nexus::external_api::{params, views::AffinityGroup};
+
+use dropshot::test_util::object_get;
+use dropshot::test_util::objects_list_page;
+use dropshot::test_util::objects_post;
+use dropshot::test_util::ClientTestContext;
+use dropshot::HttpErrorResponseBody;
+
+use nexus_test_utils::http_testing::AuthnMode;
+use nexus_test_utils::http_testing::NexusRequest;
+use nexus_test_utils::http_testing::RequestBuilder;
+use nexus_test_utils::identity_eq;
+use nexus_test_utils::resource_helpers::{
+    create_instance, create_organization, create_project, object_create,
+};
+use nexus_test_utils::ControlPlaneTestContext;
+use nexus_test_utils_macros::nexus_test;
+
+#[nexus_test]
+async fn test_affinity_groups(cptestctx: &ControlPlaneTestContext) {
+    let client = &cptestctx.external_client;
+
+    /* Create a project that we'll use for testing. */
+    let org_name = "test-org";
+    create_organization(&client, &org_name).await;
+    let project_name = "springfield-squidport";
+    let groups_url = format!(
+        "/organizations/{}/projects/{}/affinity-groups",
+        org_name, project_name
+    );
+    let _ = create_project(&client, &org_name, &project_name).await;
+
+    /* There are no affinity groups to start with. */
+    assert!(groups_list(&client, &groups_url).await.is_empty());
+
+    /* Make sure we get a 404 if we fetch or delete one. */
+    let group_url = format!("{}/web-tier", groups_url);
+    let error = client
+        .make_request_error(Method::GET, &group_url, StatusCode::NOT_FOUND)
+        .await;
+    assert_eq!(
+        error.message,
+        "not found: affinity-group with name \"web-tier\""
+    );
+    let error = client
+        .make_request_error(Method::DELETE, &group_url, StatusCode::NOT_FOUND)
+        .await;
+    assert_eq!(
+        error.message,
+        "not found: affinity-group with name \"web-tier\""
+    );
+
+    /* Create a group. */
+    let group = group_create(
+        &client,
+        &groups_url,
+        "web-tier",
+        AffinityPolicy::AntiAffinity,
+    )
+    .await;
+    assert_eq!(group.identity.name, "web-tier");
+    assert_eq!(group.policy, AffinityPolicy::AntiAffinity);
+
+    /* Attempt to create a second group with a conflicting name. */
+    let error = client
+        .make_request_error_body(
+            Method::POST,
+            &groups_url,
+            group_create_params("web-tier", AffinityPolicy::Affinity),
+            StatusCode::BAD_REQUEST,
+        )
+        .await;
+    assert_eq!(error.message, "already exists: affinity-group \"web-tier\"");
+
+    /* List groups again and expect to find the one we just created. */
+    let groups = groups_list(&client, &groups_url).await;
+    assert_eq!(groups.len(), 1);
+    groups_eq(&groups[0], &group);
+
+    /* Fetch the group and expect it to match. */
+    groups_eq(&object_get::<AffinityGroup>(client, &group_url).await, &group);
+
+    /* Rename the group. */
+    let updated: AffinityGroup = NexusRequest::object_put(
+        &client,
+        &group_url,
+        Some(&params::AffinityGroupUpdate {
+            identity: IdentityMetadataUpdateParams {
+                name: Some("front-end".parse().unwrap()),
+                description: None,
+                labels: None,
+            },
+        }),
+    )
+    .execute()
+    .await
+    .unwrap()
+    .parsed_body()
+    .unwrap();
+    assert_eq!(updated.identity.id, group.identity.id);
+    assert_eq!(updated.identity.name, "front-end");
+    assert_eq!(updated.policy, AffinityPolicy::AntiAffinity);
+    let error = client
+        .make_request_error(Method::GET, &group_url, StatusCode::NOT_FOUND)
+        .await;
+    assert_eq!(
+        error.message,
+        "not found: affinity-group with name \"web-tier\""
+    );
+    let group_url = format!("{}/front-end", groups_url);
+
+    /*
+     * Create an Instance in the group.  The group can't be deleted while it
+     * has members.
+     */
+    let instances_url = format!(
+        "/organizations/{}/projects/{}/instances",
+        org_name, project_name
+    );
+    let instance: Instance = object_create(
+        &client,
+        &instances_url,
+        &params::InstanceCreate {
+            affinity_group: Some("front-end".parse().unwrap()),
+            user_data: vec![],
+            ssh_public_keys: vec![],
+            network_interfaces: vec![],
+            ..instance_create_params("web0")
+        },
+    )
+    .await;
+    assert_eq!(instance.affinity_group_id, Some(group.identity.id));
+    let error = client
+        .make_request_error(Method::DELETE, &group_url, StatusCode::BAD_REQUEST)
+        .await;
+    assert_eq!(
+        error.message,
+        "affinity group to be deleted contains an instance"
+    );
+
+    /* Instances can only join groups that exist in the same project. */
+    let other_project = "pokemon";
+    let _ = create_project(&client, &org_name, &other_project).await;
+    let error: HttpErrorResponseBody = NexusRequest::new(
+        RequestBuilder::new(
+            &client,
+            Method::POST,
+            &format!(
+                "/organizations/{}/projects/{}/instances",
+                org_name, other_project
+            ),
+        )
+        .body(Some(&params::InstanceCreate {
+            affinity_group: Some("front-end".parse().unwrap()),
+            user_data: vec![],
+            ssh_public_keys: vec![],
+            network_interfaces: vec![],
+            ..instance_create_params("web1")
+        }))
+        .expect_status(Some(StatusCode::NOT_FOUND)),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap()
+    .parsed_body()
+    .unwrap();
+    assert_eq!(
+        error.message,
+        "not found: affinity-group with name \"front-end\""
+    );
+
+    /* Instances outside any group don't get in the way of deletion. */
+    let plain = create_instance(&client, org_name, project_name, "plain").await;
+    assert_eq!(plain.affinity_group_id, None);
+
+    /* Delete an empty group. */
+    let _ =
+        group_create(&client, &groups_url, "scratch", AffinityPolicy::Affinity)
+            .await;
+    let scratch_url = format!("{}/scratch", groups_url);
+    client
+        .make_request_no_body(
+            Method::DELETE,
+            &scratch_url,
+            StatusCode::NO_CONTENT,
+        )
+        .await
+        .unwrap();
+    let error = client
+        .make_request_error(Method::GET, &scratch_url, StatusCode::NOT_FOUND)
+        .await;
+    assert_eq!(
+        error.message,
+        "not found: affinity-group with name \"scratch\""
+    );
+    let groups = groups_list(&client, &groups_url).await;
+    assert_eq!(groups.len(), 1);
+    assert_eq!(groups[0].identity.name, "front-end");
+}
+
+fn group_create_params(
+    name: &str,
+    policy: AffinityPolicy,
+) -> params::AffinityGroupCreate {
+    params::AffinityGroupCreate {
+        identity: IdentityMetadataCreateParams {
+            name: name.parse().unwrap(),
+            description: String::from("group description"),
+            labels: Default::default(),
+        },
+        policy,
+    }
+}
+
+fn instance_create_params(name: &str) -> params::InstanceCreate {
+    params::InstanceCreate {
+        identity: IdentityMetadataCreateParams {
+            name: name.parse().unwrap(),
+            description: format!("instance {:?}", name),
+            labels: Default::default(),
+        },
+        ncpus: InstanceCpuCount(1),
+        memory: ByteCount::from_gibibytes_u32(1),
+        hostname: String::from(name),
+        affinity_group: None,
+        user_data: vec![],
+        ssh_public_keys: vec![],
+        network_interfaces: vec![],
+    }
+}
+
+async fn group_create(
+    client: &ClientTestContext,
+    groups_url: &str,
+    name: &str,
+    policy: AffinityPolicy,
+) -> AffinityGroup {
+    objects_post(client, groups_url, group_create_params(name, policy)).await
+}
+
+async fn groups_list(
+    client: &ClientTestContext,
+    groups_url: &str,
+) -> Vec<AffinityGroup> {
+    objects_list_page::<AffinityGroup>(client, groups_url).await.items
+}
+
+fn groups_eq(group1: &AffinityGroup, group2: &AffinityGroup) {
+    identity_eq(&group1.identity, &group2.identity);
+    assert_eq!(group1.project_id, group2.project_id);
+    assert_eq!(group1.policy, group2.policy);
+}
@@ -0,0 +1,498 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Tests API versions: that the header selecting one is honored, and that the
+//! current OpenAPI document doesn't break clients of the current version
+
+use http::method::Method;
+use http::StatusCode;
+use nexus_test_utils::http_testing::AuthnMode;
+use nexus_test_utils::http_testing::NexusRequest;
+use nexus_test_utils::http_testing::RequestBuilder;
+use nexus_test_utils::ControlPlaneTestContext;
+use nexus_test_utils_macros::nexus_test;
+use omicron_nexus::external_api::versions::{
+    ApiVersion, API_VERSIONS, API_VERSION_HEADER,
+};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, BTreeSet};
+
+const OPENAPI_DOCUMENT: &str = "../openapi/nexus.json";
+const FROZEN_DOCUMENTS: &str = "../openapi/nexus-versions";
+
+#[nexus_test]
+async fn test_api_version_header(cptestctx: &ControlPlaneTestContext) {
+    let client = &cptestctx.external_client;
+
+    // Asking for the current version and not asking for any are the same.
+    for version in &[Some(ApiVersion::current().as_str()), None] {
+        let mut builder =
+            RequestBuilder::new(client, Method::GET, "/organizations")
+                .expect_status(Some(StatusCode::OK));
+        if let Some(version) = version {
+            builder = builder.header(API_VERSION_HEADER, *version);
+        }
+        NexusRequest::new(builder)
+            .authn_as(AuthnMode::PrivilegedUser)
+            .execute()
+            .await
+            .unwrap();
+    }
+
+    let error: dropshot::HttpErrorResponseBody = NexusRequest::new(
+        RequestBuilder::new(client, Method::GET, "/organizations")
+            .header(API_VERSION_HEADER, "1999-01-01")
+            .expect_status(Some(StatusCode::BAD_REQUEST)),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap()
+    .parsed_body()
+    .unwrap();
+    assert_eq!(error.error_code.as_deref(), Some("UnsupportedApiVersion"));
+    assert_eq!(
+        error.message,
+        format!(
+            "unsupported API version \"1999-01-01\" (supported versions: {})",
+            API_VERSIONS
+                .iter()
+                .map(|v| v.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+    );
+}
+
+#[test]
+fn test_api_versions() {
+    let current = read_document(OPENAPI_DOCUMENT);
+    let versions: Vec<&str> = API_VERSIONS.iter().map(|v| v.as_str()).collect();
+    assert_eq!(current["info"]["version"], ApiVersion::current().as_str());
+    assert_eq!(current["info"]["x-api-versions"], json!(versions));
+
+    // Every supported version has a frozen document.  (The older ones aren't
+    // checked against anything; they're kept to describe those versions.)
+    for version in &versions {
+        read_document(&format!("{}/{}.json", FROZEN_DOCUMENTS, version));
+    }
+
+    let frozen = read_document(&format!(
+        "{}/{}.json",
+        FROZEN_DOCUMENTS,
+        ApiVersion::current()
+    ));
+    let changes = breaking_changes(&frozen, &current);
+    assert!(
+        changes.is_empty(),
+        "{} is incompatible with API version {}:\n    {}\n\
+         Either make the change compatibly, or add a new API version (see \
+         nexus/src/external_api/versions.rs).",
+        OPENAPI_DOCUMENT,
+        ApiVersion::current(),
+        changes.join("\n    ")
+    );
+}
+
+/// Makes sure the compatibility check catches (and only catches) changes that
+/// would break clients, by making such changes to the real document
+#[test]
+fn test_breaking_changes() {
+    let old = read_document(OPENAPI_DOCUMENT);
+    let check = |change: fn(&mut Value), expected: &[&str]| {
+        let mut new = old.clone();
+        change(&mut new);
+        assert_eq!(breaking_changes(&old, &new), expected);
+    };
+    check(|_| (), &[]);
+
+    // Removing a property from a response breaks clients that use it...
+    check(
+        |doc| {
+            let instance = doc.pointer_mut("/components/schemas/Instance");
+            let instance = instance.unwrap();
+            instance["properties"].as_object_mut().unwrap().remove("labels");
+            let required = instance["required"].as_array_mut().unwrap();
+            required.retain(|p| p != "labels");
+        },
+        &["schema \"Instance\": removed property \"labels\""],
+    );
+    // ... as does requiring a new property in a request...
+    check(
+        |doc| {
+            let create = doc.pointer_mut("/components/schemas/InstanceCreate");
+            let create = create.unwrap();
+            create["properties"]["color"] = json!({ "type": "string" });
+            create["required"].as_array_mut().unwrap().push(json!("color"));
+        },
+        &["schema \"InstanceCreate\": property \"color\" is now required"],
+    );
+    // ... or changing a property's type.
+    check(
+        |doc| {
+            let instance = doc.pointer_mut("/components/schemas/Instance");
+            instance.unwrap()["properties"]["hostname"] =
+                json!({ "type": "integer" });
+        },
+        &["schema \"Instance\": property \"hostname\" changed type"],
+    );
+    check(
+        |doc| {
+            let paths = doc["paths"].as_object_mut().unwrap();
+            paths.remove("/organizations");
+        },
+        &[
+            "removed operation GET /organizations",
+            "removed operation POST /organizations",
+        ],
+    );
+
+    // But adding an optional property to a request is fine, as are changes to
+    // hidden operations.
+    check(
+        |doc| {
+            let create = doc.pointer_mut("/components/schemas/InstanceCreate");
+            create.unwrap()["properties"]["color"] =
+                json!({ "type": "string" });
+        },
+        &[],
+    );
+    check(
+        |doc| {
+            doc["paths"].as_object_mut().unwrap().remove("/login");
+        },
+        &[],
+    );
+}
+
+fn read_document(path: &str) -> Value {
+    let contents = std::fs::read_to_string(path)
+        .unwrap_or_else(|e| panic!("failed to read {}: {}", path, e));
+    serde_json::from_str(&contents)
+        .unwrap_or_else(|e| panic!("failed to parse {}: {}", path, e))
+}
+
+/*
+ * Checking compatibility
+ *
+ * This is deliberately conservative: it flags anything that might break an
+ * existing client, from the client's point of view.  Clients send requests, so
+ * a request schema may accept more than it did (new optional properties, new
+ * enum values) but not less.  They receive responses, so a response schema may
+ * promise more than it did (new properties) but not less.
+ */
+
+const METHODS: &[&str] = &["get", "put", "post", "delete", "patch"];
+
+/// Returns descriptions of the changes from `old` to `new` that would break
+/// clients written against `old`
+fn breaking_changes(old: &Value, new: &Value) -> Vec<String> {
+    let mut changes = Vec::new();
+    let new_operations = operations(new);
+
+    for ((path, method), old_op) in operations(old) {
+        let what = format!("{} {}", method.to_uppercase(), path);
+        let new_op = match new_operations.get(&(path, method)) {
+            Some(op) => op,
+            None => {
+                changes.push(format!("removed operation {}", what));
+                continue;
+            }
+        };
+        if old_op["operationId"] != new_op["operationId"] {
+            changes.push(format!("{}: renamed operation", what));
+        }
+
+        let old_params = parameters(old_op);
+        let new_params = parameters(new_op);
+        for (name, old_param) in &old_params {
+            match new_params.get(name) {
+                None => changes.push(format!(
+                    "{}: removed parameter \"{}\"",
+                    what, name.1
+                )),
+                Some(new_param) => {
+                    if is_true(new_param, "required")
+                        && !is_true(old_param, "required")
+                    {
+                        changes.push(format!(
+                            "{}: parameter \"{}\" is now required",
+                            what, name.1
+                        ));
+                    }
+                    if shape(&old_param["schema"])
+                        != shape(&new_param["schema"])
+                    {
+                        changes.push(format!(
+                            "{}: parameter \"{}\" changed type",
+                            what, name.1
+                        ));
+                    }
+                }
+            }
+        }
+        for (name, new_param) in &new_params {
+            if !old_params.contains_key(name) && is_true(new_param, "required")
+            {
+                changes.push(format!(
+                    "{}: added required parameter \"{}\"",
+                    what, name.1
+                ));
+            }
+        }
+
+        if shape(&body_schema(&old_op["requestBody"]))
+            != shape(&body_schema(&new_op["requestBody"]))
+        {
+            changes.push(format!("{}: changed request body", what));
+        }
+
+        let empty = serde_json::Map::new();
+        let old_responses = old_op["responses"].as_object().unwrap_or(&empty);
+        for (code, old_response) in old_responses {
+            match new_op["responses"].get(code) {
+                None => {
+                    changes.push(format!("{}: removed response {}", what, code))
+                }
+                Some(new_response) => {
+                    if shape(&body_schema(old_response))
+                        != shape(&body_schema(new_response))
+                    {
+                        changes.push(format!(
+                            "{}: changed response {}",
+                            what, code
+                        ));
+                    }
+                }
+            }
+        }
+    }
+
+    let (requests, responses) = reachable_schemas(old);
+    let empty = serde_json::Map::new();
+    let old_schemas =
+        old["components"]["schemas"].as_object().unwrap_or(&empty);
+    for (name, old_schema) in old_schemas {
+        let is_request = requests.contains(name.as_str());
+        let is_response = responses.contains(name.as_str());
+        if !is_request && !is_response {
+            continue;
+        }
+        let what = format!("schema \"{}\"", name);
+        let new_schema = match new["components"]["schemas"].get(name) {
+            Some(schema) => schema,
+            None => {
+                changes.push(format!("removed {}", what));
+                continue;
+            }
+        };
+
+        let old_required = string_set(&old_schema["required"]);
+        let new_required = string_set(&new_schema["required"]);
+        let old_properties =
+            old_schema["properties"].as_object().unwrap_or(&empty);
+        for (property, old_property) in old_properties {
+            match new_schema["properties"].get(property) {
+                None => {
+                    if is_response {
+                        changes.push(format!(
+                            "{}: removed property \"{}\"",
+                            what, property
+                        ));
+                    }
+                }
+                Some(new_property) => {
+                    if shape(old_property) != shape(new_property) {
+                        changes.push(format!(
+                            "{}: property \"{}\" changed type",
+                            what, property
+                        ));
+                    }
+                    if is_response
+                        && old_required.contains(property)
+                        && !new_required.contains(property)
+                    {
+                        changes.push(format!(
+                            "{}: property \"{}\" is no longer required",
+                            what, property
+                        ));
+                    }
+                }
+            }
+        }
+        if is_request {
+            for property in new_required.difference(&old_required) {
+                changes.push(format!(
+                    "{}: property \"{}\" is now required",
+                    what, property
+                ));
+            }
+        }
+
+        let old_values = enum_values(old_schema);
+        let new_values = enum_values(new_schema);
+        if is_request {
+            for value in old_values.difference(&new_values) {
+                changes.push(format!("{}: removed value {}", what, value));
+            }
+        }
+        if is_response {
+            for value in new_values.difference(&old_values) {
+                changes.push(format!("{}: added value {}", what, value));
+            }
+        }
+    }
+
+    changes
+}
+
+/// Returns the operations in `doc` that aren't hidden, by path and method
+fn operations(doc: &Value) -> BTreeMap<(&str, &str), &Value> {
+    let mut operations = BTreeMap::new();
+    let empty = serde_json::Map::new();
+    for (path, item) in doc["paths"].as_object().unwrap_or(&empty) {
+        for method in METHODS {
+            if let Some(op) = item.get(*method) {
+                if op["tags"] != json!(["hidden"]) {
+                    operations.insert((path.as_str(), *method), op);
+                }
+            }
+        }
+    }
+    operations
+}
+
+/// Returns an operation's parameters by location and name
+fn parameters(op: &Value) -> BTreeMap<(String, String), &Value> {
+    op["parameters"]
+        .as_array()
+        .map(|params| {
+            params
+                .iter()
+                .map(|p| {
+                    let location = p["in"].as_str().unwrap_or("").to_string();
+                    let name = p["name"].as_str().unwrap_or("").to_string();
+                    ((location, name), p)
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Returns the JSON schema of a request or response body
+fn body_schema(body: &Value) -> Value {
+    body["content"]["application/json"]["schema"].clone()
+}
+
+/// Returns the parts of a schema that determine what values it describes,
+/// leaving out descriptions, defaults, and the like
+fn shape(schema: &Value) -> Value {
+    match schema {
+        Value::Object(schema) => schema
+            .iter()
+            .filter_map(|(key, value)| match key.as_str() {
+                "type" | "format" | "$ref" => {
+                    Some((key.clone(), value.clone()))
+                }
+                "items" => Some((key.clone(), shape(value))),
+                "allOf" => Some((
+                    key.clone(),
+                    value
+                        .as_array()
+                        .map(|schemas| schemas.iter().map(shape).collect())
+                        .unwrap_or(Value::Null),
+                )),
+                _ => None,
+            })
+            .collect::<serde_json::Map<_, _>>()
+            .into(),
+        _ => Value::Null,
+    }
+}
+
+fn is_true(value: &Value, key: &str) -> bool {
+    value[key].as_bool().unwrap_or(false)
+}
+
+fn string_set(value: &Value) -> BTreeSet<String> {
+    value
+        .as_array()
+        .map(|values| {
+            values.iter().filter_map(Value::as_str).map(String::from).collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Returns the values of an enum schema, including those of each variant of
+/// a `oneOf` (which is how enums with documented variants are described)
+fn enum_values(schema: &Value) -> BTreeSet<String> {
+    let mut values: BTreeSet<String> = schema["enum"]
+        .as_array()
+        .map(|values| values.iter().map(Value::to_string).collect())
+        .unwrap_or_default();
+    if let Some(variants) = schema["oneOf"].as_array() {
+        for variant in variants {
+            values.extend(enum_values(variant));
+        }
+    }
+    values
+}
+
+/// Returns the names of the schemas reachable from request parameters and
+/// bodies, and those reachable from responses
+fn reachable_schemas(doc: &Value) -> (BTreeSet<&str>, BTreeSet<&str>) {
+    let mut requests = BTreeSet::new();
+    let mut responses = BTreeSet::new();
+    for op in operations(doc).values() {
+        collect_refs(&op["parameters"], &mut requests);
+        collect_refs(&op["requestBody"], &mut requests);
+        collect_refs(&op["responses"], &mut responses);
+    }
+    (close_refs(doc, requests), close_refs(doc, responses))
+}
+
+/// Adds the names of the schemas that `value` refers to directly to `names`
+fn collect_refs<'a>(value: &'a Value, names: &mut BTreeSet<&'a str>) {
+    match value {
+        Value::Object(object) => {
+            for (key, value) in object {
+                match (key.as_str(), value) {
+                    ("$ref", Value::String(reference)) => {
+                        if let Some(name) =
+                            reference.strip_prefix("#/components/schemas/")
+                        {
+                            names.insert(name);
+                        }
+                    }
+                    _ => collect_refs(value, names),
+                }
+            }
+        }
+        Value::Array(values) => {
+            for value in values {
+                collect_refs(value, names);
+            }
+        }
+        _ => (),
+    }
+}
+
+/// Returns `names` plus the names of all of the schemas they refer to,
+/// directly or not
+fn close_refs<'a>(
+    doc: &'a Value,
+    mut names: BTreeSet<&'a str>,
+) -> BTreeSet<&'a str> {
+    let mut pending: Vec<&str> = names.iter().copied().collect();
+    while let Some(name) = pending.pop() {
+        let mut referenced = BTreeSet::new();
+        collect_refs(&doc["components"]["schemas"][name], &mut referenced);
+        for name in referenced {
+            if names.insert(name) {
+                pending.push(name);
+            }
+        }
+    }
+    names
+}
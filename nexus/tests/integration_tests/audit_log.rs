@@ -0,0 +1,136 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Tests the audit log of mutating API calls
+
+use chrono::SecondsFormat;
+use dropshot::test_util::ClientTestContext;
+use http::method::Method;
+use http::StatusCode;
+use nexus_test_utils::http_testing::AuthnMode;
+use nexus_test_utils::http_testing::NexusRequest;
+use nexus_test_utils::http_testing::RequestBuilder;
+use nexus_test_utils::resource_helpers::create_organization;
+use nexus_test_utils::resource_helpers::create_project;
+use nexus_test_utils::ControlPlaneTestContext;
+use nexus_test_utils_macros::nexus_test;
+use omicron_common::api::external::IdentityMetadataCreateParams;
+use omicron_nexus::authn::USER_TEST_PRIVILEGED;
+use omicron_nexus::external_api::params;
+use omicron_nexus::external_api::views::AuditLogEntry;
+
+const ORG_NAME: &str = "test-org";
+const PROJECT_NAME: &str = "springfield-squidport";
+
+#[nexus_test]
+async fn test_audit_log(cptestctx: &ControlPlaneTestContext) {
+    let client = &cptestctx.external_client;
+
+    // Successful requests are recorded along with the actor that made them
+    // and the resource they acted on.  GET requests are not recorded.
+    let org = create_organization(&client, ORG_NAME).await;
+    let project = create_project(&client, ORG_NAME, PROJECT_NAME).await;
+    NexusRequest::object_get(client, &format!("/organizations/{}", ORG_NAME))
+        .authn_as(AuthnMode::PrivilegedUser)
+        .execute()
+        .await
+        .unwrap();
+
+    // So are requests that fail, whether or not they were authenticated.
+    RequestBuilder::new(client, Method::POST, "/organizations")
+        .body(Some(&params::OrganizationCreate {
+            identity: org_identity("sneaky-org"),
+        }))
+        .expect_status(Some(StatusCode::UNAUTHORIZED))
+        .execute()
+        .await
+        .unwrap();
+    NexusRequest::new(
+        RequestBuilder::new(client, Method::POST, "/organizations")
+            .body(Some(&params::OrganizationCreate {
+                identity: org_identity(ORG_NAME),
+            }))
+            .expect_status(Some(StatusCode::BAD_REQUEST)),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap();
+
+    let entries = audit_log_list(client, "").await;
+    let summary = entries
+        .iter()
+        .map(|e| (e.http_method.as_str(), e.http_path.as_str(), e.http_status))
+        .collect::<Vec<_>>();
+    let projects_url = format!("/organizations/{}/projects", ORG_NAME);
+    assert_eq!(
+        summary,
+        vec![
+            ("POST", "/organizations", 201),
+            ("POST", projects_url.as_str(), 201),
+            ("POST", "/organizations", 401),
+            ("POST", "/organizations", 400),
+        ]
+    );
+    assert_eq!(entries[0].actor_id, Some(USER_TEST_PRIVILEGED.id));
+    assert_eq!(entries[0].resource_id, Some(org.identity.id));
+    assert_eq!(entries[1].resource_id, Some(project.identity.id));
+    assert_eq!(entries[2].actor_id, None);
+    assert_eq!(entries[2].resource_id, None);
+    assert_eq!(entries[3].actor_id, Some(USER_TEST_PRIVILEGED.id));
+    assert_eq!(entries[3].resource_id, None);
+    assert!(entries.windows(2).all(|w| w[0].time_created <= w[1].time_created));
+
+    // The listing can be restricted to a range of time.
+    let time_param = |e: &AuditLogEntry| {
+        e.time_created.to_rfc3339_opts(SecondsFormat::Micros, true)
+    };
+    let later = audit_log_list(
+        client,
+        &format!("start_time={}", time_param(&entries[1])),
+    )
+    .await;
+    assert_eq!(later, entries[1..]);
+    let between = audit_log_list(
+        client,
+        &format!(
+            "start_time={}&end_time={}",
+            time_param(&entries[1]),
+            time_param(&entries[3])
+        ),
+    )
+    .await;
+    assert_eq!(between, entries[1..3]);
+
+    // Only fleet administrators can see the audit log.
+    NexusRequest::expect_failure(
+        client,
+        StatusCode::FORBIDDEN,
+        Method::GET,
+        "/audit-log",
+    )
+    .authn_as(AuthnMode::UnprivilegedUser)
+    .execute()
+    .await
+    .unwrap();
+}
+
+fn org_identity(name: &str) -> IdentityMetadataCreateParams {
+    IdentityMetadataCreateParams {
+        name: name.parse().unwrap(),
+        description: String::from("an org"),
+        labels: Default::default(),
+    }
+}
+
+/// Lists the whole audit log, a couple of entries at a time
+async fn audit_log_list(
+    client: &ClientTestContext,
+    params: &str,
+) -> Vec<AuditLogEntry> {
+    NexusRequest::iter_collection_authn(client, "/audit-log", params, Some(2))
+        .await
+        .expect("failed to list audit log")
+        .all_items
+}
@@ -199,6 +199,7 @@ async fn test_projects_basic(cptestctx: &ControlPlaneTestContext) {
                         description: String::from(
                             "<auto-generated by test suite>",
                         ),
+                        labels: Default::default(),
                     },
                 },
             )
@@ -301,6 +302,7 @@ async fn test_projects_basic(cptestctx: &ControlPlaneTestContext) {
             identity: IdentityMetadataUpdateParams {
                 name: None,
                 description: None,
+                labels: None,
             },
         }))
         .expect_status(Some(StatusCode::NOT_FOUND)),
@@ -347,6 +349,7 @@ async fn test_projects_basic(cptestctx: &ControlPlaneTestContext) {
         identity: IdentityMetadataUpdateParams {
             name: None,
             description: Some("Li'l lightnin'".to_string()),
+            labels: None,
         },
     };
     let project = NexusRequest::object_put(
@@ -381,6 +384,7 @@ async fn test_projects_basic(cptestctx: &ControlPlaneTestContext) {
         identity: IdentityMetadataUpdateParams {
             name: Some("lil-lightnin".parse().unwrap()),
             description: Some("little lightning".to_string()),
+            labels: None,
         },
     };
     let project = NexusRequest::object_put(
@@ -416,6 +420,7 @@ async fn test_projects_basic(cptestctx: &ControlPlaneTestContext) {
         identity: IdentityMetadataCreateParams {
             name: "simproject1".parse().unwrap(),
             description: "a duplicate of simproject1".to_string(),
+            labels: Default::default(),
         },
     };
     let error = NexusRequest::new(
@@ -468,6 +473,7 @@ async fn test_projects_basic(cptestctx: &ControlPlaneTestContext) {
         identity: IdentityMetadataCreateParams {
             name: "honor-roller".parse().unwrap(),
             description: "a soapbox racer".to_string(),
+            labels: Default::default(),
         },
     };
     let project: Project =
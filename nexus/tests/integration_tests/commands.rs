@@ -13,6 +13,7 @@
  */
 
 use expectorate::assert_contents;
+use omicron_nexus::external_api::versions::ApiVersion;
 use omicron_test_utils::dev::test_cmds::assert_exit_code;
 use omicron_test_utils::dev::test_cmds::error_for_enoent;
 use omicron_test_utils::dev::test_cmds::path_to_executable;
@@ -125,7 +126,7 @@ fn test_nexus_openapi() {
         .expect("stdout was not valid OpenAPI");
     assert_eq!(spec.openapi, "3.0.3");
     assert_eq!(spec.info.title, "Oxide Region API");
-    assert_eq!(spec.info.version, "0.0.1");
+    assert_eq!(spec.info.version, ApiVersion::current().as_str());
 
     /*
      * Spot check a couple of items.
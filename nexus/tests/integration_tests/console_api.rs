@@ -10,16 +10,22 @@ use std::env::current_dir;
 use nexus_test_utils::http_testing::{
     AuthnMode, NexusRequest, RequestBuilder, TestResponse,
 };
+use nexus_test_utils::resource_helpers::{create_organization, create_user};
 use nexus_test_utils::{
     load_test_config, test_setup_with_config, ControlPlaneTestContext,
 };
 use nexus_test_utils_macros::nexus_test;
 use omicron_common::api::external::IdentityMetadataCreateParams;
+use omicron_common::api::external::RoleName;
 use omicron_nexus::authn::{USER_TEST_PRIVILEGED, USER_TEST_UNPRIVILEGED};
 use omicron_nexus::external_api::console_api::LoginParams;
-use omicron_nexus::external_api::params::OrganizationCreate;
+use omicron_nexus::external_api::params::ProjectCreate;
 use omicron_nexus::external_api::views;
 
+const USER_NAME: &str = "console-user";
+const USER_PASSWORD: &str = "console-password";
+const PROJECTS_URL: &str = "/organizations/my-org/projects";
+
 #[nexus_test]
 async fn test_sessions(cptestctx: &ControlPlaneTestContext) {
     let testctx = &cptestctx.external_client;
@@ -35,19 +41,53 @@ async fn test_sessions(cptestctx: &ControlPlaneTestContext) {
         .await
         .expect("failed to clear cookie and 204 on logout");
 
+    // a user can only log in with their own password
+    let user = create_user(&testctx, USER_NAME, USER_PASSWORD).await;
+    RequestBuilder::new(&testctx, Method::POST, "/login")
+        .body(Some(&LoginParams {
+            username: USER_NAME.to_string(),
+            password: "not-the-password".to_string(),
+        }))
+        .expect_status(Some(StatusCode::UNAUTHORIZED))
+        .expect_response_header(
+            header::SET_COOKIE,
+            "session=; Path=/; Secure; HttpOnly; SameSite=Lax; Max-Age=0",
+        )
+        .execute()
+        .await
+        .expect("failed to 401 on bad password");
+
+    // let the user create projects in an org
+    create_organization(&testctx, "my-org").await;
+    NexusRequest::object_put(
+        &testctx,
+        "/organizations/my-org/policy",
+        Some(&views::Policy {
+            role_assignments: vec![views::RoleAssignment {
+                user_id: user.identity.id,
+                role_name: RoleName::new("organization", "collaborator"),
+            }],
+        }),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .expect("failed to grant role");
+
     // log in and pull the token out of the header so we can use it for authed requests
     let session_token = log_in_and_extract_token(&testctx).await;
 
-    let org_params = OrganizationCreate {
+    let project_params = ProjectCreate {
         identity: IdentityMetadataCreateParams {
-            name: "my-org".parse().unwrap(),
-            description: "an org".to_string(),
+            name: "my-project".parse().unwrap(),
+            description: "a project".to_string(),
+            labels: Default::default(),
         },
     };
 
     // hitting auth-gated API endpoint without session cookie 401s
-    RequestBuilder::new(&testctx, Method::POST, "/organizations")
-        .body(Some(&org_params))
+    RequestBuilder::new(&testctx, Method::POST, PROJECTS_URL)
+        .body(Some(&project_params))
         .expect_status(Some(StatusCode::UNAUTHORIZED))
         .execute()
         .await
@@ -61,14 +101,14 @@ async fn test_sessions(cptestctx: &ControlPlaneTestContext) {
         .expect("failed to 302 on unauthed console page request");
 
     // now make same requests with cookie
-    RequestBuilder::new(&testctx, Method::POST, "/organizations")
+    RequestBuilder::new(&testctx, Method::POST, PROJECTS_URL)
         .header(header::COOKIE, &session_token)
-        .body(Some(&org_params))
+        .body(Some(&project_params))
         // TODO: explicit expect_status not needed. decide whether to keep it anyway
         .expect_status(Some(StatusCode::CREATED))
         .execute()
         .await
-        .expect("failed to create org with session cookie");
+        .expect("failed to create project with session cookie");
 
     RequestBuilder::new(&testctx, Method::GET, "/orgs/whatever")
         .header(header::COOKIE, &session_token)
@@ -92,9 +132,9 @@ async fn test_sessions(cptestctx: &ControlPlaneTestContext) {
 
     // now the same requests with the same session cookie should 401/302 because
     // logout also deletes the session server-side
-    RequestBuilder::new(&testctx, Method::POST, "/organizations")
+    RequestBuilder::new(&testctx, Method::POST, PROJECTS_URL)
         .header(header::COOKIE, &session_token)
-        .body(Some(&org_params))
+        .body(Some(&project_params))
         .expect_status(Some(StatusCode::UNAUTHORIZED))
         .execute()
         .await
@@ -120,6 +160,7 @@ async fn test_console_pages(cptestctx: &ControlPlaneTestContext) {
         .await
         .expect("failed to redirect to IdP on auth failure");
 
+    create_user(&testctx, USER_NAME, USER_PASSWORD).await;
     let session_token = log_in_and_extract_token(&testctx).await;
 
     // hit console page with session, should get back HTML response
@@ -281,7 +322,10 @@ fn get_header_value(resp: TestResponse, header_name: HeaderName) -> String {
 
 async fn log_in_and_extract_token(testctx: &ClientTestContext) -> String {
     let login = RequestBuilder::new(&testctx, Method::POST, "/login")
-        .body(Some(&LoginParams { username: "privileged".to_string() }))
+        .body(Some(&LoginParams {
+            username: USER_NAME.to_string(),
+            password: USER_PASSWORD.to_string(),
+        }))
         .expect_status(Some(StatusCode::OK))
         .execute()
         .await
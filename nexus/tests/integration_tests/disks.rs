@@ -245,6 +245,7 @@ async fn test_disk_create_disk_that_already_exists_fails(
         identity: IdentityMetadataCreateParams {
             name: DISK_NAME.parse().unwrap(),
             description: String::from("sells rainsticks"),
+            labels: Default::default(),
         },
         snapshot_id: None,
         size: ByteCount::from_gibibytes_u32(1),
@@ -624,6 +625,7 @@ async fn test_disk_region_creation_failure(
         identity: IdentityMetadataCreateParams {
             name: DISK_NAME.parse().unwrap(),
             description: String::from("sells rainsticks"),
+            labels: Default::default(),
         },
         snapshot_id: None,
         size: disk_size,
@@ -668,6 +670,188 @@ async fn test_disk_region_creation_failure(
     let _ = create_disk(client, ORG_NAME, PROJECT_NAME, DISK_NAME).await;
 }
 
+#[nexus_test]
+async fn test_disk_resize(cptestctx: &ControlPlaneTestContext) {
+    let client = &cptestctx.external_client;
+    let test = DiskTest::new(&cptestctx).await;
+    create_org_and_project(client).await;
+    let nexus = &cptestctx.server.apictx.nexus;
+    let disk_url = format!("{}/{}", get_disks_url(), DISK_NAME);
+    let resize_url = format!("{}/resize", disk_url);
+    let disk = create_disk(&client, ORG_NAME, PROJECT_NAME, DISK_NAME).await;
+
+    // Disks can't be shrunk.
+    let error: HttpErrorResponseBody = NexusRequest::new(
+        RequestBuilder::new(client, Method::POST, &resize_url)
+            .body(Some(&params::DiskResize {
+                size: ByteCount::from_mebibytes_u32(512),
+            }))
+            .expect_status(Some(StatusCode::BAD_REQUEST)),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap()
+    .parsed_body()
+    .unwrap();
+    assert_eq!(
+        error.message,
+        "unsupported value for \"size\": disk cannot be shrunk (current size \
+         is 1073741824 bytes)"
+    );
+    assert_eq!(disk_get(&client, &disk_url).await.size, disk.size);
+
+    // Grow the detached disk.  Each of its regions grows along with it.
+    let resized = disk_resize(client, &resize_url, 2).await;
+    assert_eq!(resized.size.to_whole_gibibytes(), 2);
+    disks_eq(&disk_get(&client, &disk_url).await, &resized);
+    for id in &test.dataset_ids {
+        let crucible =
+            test.sled_agent.get_crucible_dataset(test.zpool_id, *id).await;
+        let regions = crucible.list().await;
+        assert_eq!(regions.len(), 1);
+        assert_eq!(regions[0].extent_count, 2048);
+    }
+    assert!(cptestctx
+        .sled_agent
+        .sled_agent
+        .disk_resized(disk.identity.id)
+        .await
+        .is_none());
+
+    // Grow the disk while it's attached.  The sled agent running the instance
+    // is told about the new size.
+    let instance =
+        create_instance(&client, ORG_NAME, PROJECT_NAME, INSTANCE_NAME).await;
+    disk_post(
+        client,
+        &get_disk_attach_url(instance.identity.name.as_str()),
+        disk.identity.name.clone(),
+    )
+    .await;
+    disk_simulate(nexus, &disk.identity.id).await;
+    let resized = disk_resize(client, &resize_url, 3).await;
+    assert_eq!(resized.size.to_whole_gibibytes(), 3);
+    assert_eq!(resized.state, DiskState::Attached(instance.identity.id));
+    assert_eq!(
+        cptestctx.sled_agent.sled_agent.disk_resized(disk.identity.id).await,
+        Some((instance.identity.id, resized.size))
+    );
+}
+
+#[nexus_test]
+async fn test_disk_resize_moves_regions(cptestctx: &ControlPlaneTestContext) {
+    let client = &cptestctx.external_client;
+    let test = DiskTest::new(&cptestctx).await;
+    create_org_and_project(client).await;
+    let disk_url = format!("{}/{}", get_disks_url(), DISK_NAME);
+    let resize_url = format!("{}/resize", disk_url);
+
+    // Fill most of the Zpool with a disk.
+    let disk_size = ByteCount::from_gibibytes_u32(3);
+    let disk: Disk = NexusRequest::objects_post(
+        client,
+        &get_disks_url(),
+        &params::DiskCreate {
+            identity: IdentityMetadataCreateParams {
+                name: DISK_NAME.parse().unwrap(),
+                description: String::from("sells rainsticks"),
+                labels: Default::default(),
+            },
+            snapshot_id: None,
+            size: disk_size,
+        },
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap()
+    .parsed_body()
+    .unwrap();
+    let dataset_count = test.dataset_ids.len() as u64;
+    assert!(
+        4 * ByteCount::from_gibibytes_u32(1).to_bytes() * dataset_count
+            > test.zpool_size.to_bytes(),
+        "(test constraint) Zpool needs to be too small to grow the disk",
+    );
+
+    // With nowhere to put the bigger regions, the disk can't grow.
+    NexusRequest::new(
+        RequestBuilder::new(client, Method::POST, &resize_url)
+            .body(Some(&params::DiskResize {
+                size: ByteCount::from_gibibytes_u32(4),
+            }))
+            .expect_status(Some(StatusCode::SERVICE_UNAVAILABLE)),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap();
+    assert_eq!(disk_get(&client, &disk_url).await.size, disk_size);
+
+    // Add another Zpool.  Now regions that don't fit where they are can move.
+    let zpool_id = Uuid::new_v4();
+    test.sled_agent
+        .create_zpool(zpool_id, ByteCount::from_gibibytes_u32(20).to_bytes())
+        .await;
+    let mut datasets = Vec::new();
+    for id in &test.dataset_ids {
+        datasets.push((test.zpool_id, *id));
+    }
+    for _ in 0..test.dataset_ids.len() {
+        let id = Uuid::new_v4();
+        test.sled_agent.create_crucible_dataset(zpool_id, id).await;
+        let crucible = test.sled_agent.get_crucible_dataset(zpool_id, id).await;
+        crucible.set_create_callback(Box::new(|_| RegionState::Created)).await;
+        datasets.push((zpool_id, id));
+    }
+    let resized = disk_resize(client, &resize_url, 4).await;
+    assert_eq!(resized.size.to_whole_gibibytes(), 4);
+
+    // The disk is still backed by one full-size region on each of three
+    // datasets.  Regions that moved were copied from the ones they replaced,
+    // which have been destroyed.
+    let mut live_regions = 0;
+    let mut moved_regions = 0;
+    for (zpool_id, dataset_id) in datasets {
+        let crucible =
+            test.sled_agent.get_crucible_dataset(zpool_id, dataset_id).await;
+        for region in crucible.list().await {
+            assert_eq!(region.volume_id, disk.identity.id.to_string());
+            if region.state == RegionState::Destroyed {
+                continue;
+            }
+            live_regions += 1;
+            assert_eq!(region.extent_count, 4096);
+            if crucible.get_import(region.id.clone()).await.is_some() {
+                moved_regions += 1;
+            }
+        }
+    }
+    assert_eq!(live_regions, test.dataset_ids.len());
+    assert!(moved_regions > 0);
+}
+
+async fn disk_resize(
+    client: &ClientTestContext,
+    resize_url: &str,
+    gibibytes: u32,
+) -> Disk {
+    NexusRequest::new(
+        RequestBuilder::new(client, Method::POST, resize_url)
+            .body(Some(&params::DiskResize {
+                size: ByteCount::from_gibibytes_u32(gibibytes),
+            }))
+            .expect_status(Some(StatusCode::OK)),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap()
+    .parsed_body()
+    .unwrap()
+}
+
 async fn disk_get(client: &ClientTestContext, disk_url: &str) -> Disk {
     NexusRequest::object_get(client, disk_url)
         .authn_as(AuthnMode::PrivilegedUser)
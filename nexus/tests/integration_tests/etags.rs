@@ -0,0 +1,405 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Tests ETags and conditional PUT and DELETE requests
+
+use dropshot::test_util::ClientTestContext;
+use dropshot::HttpErrorResponseBody;
+use http::header;
+use http::method::Method;
+use http::StatusCode;
+use nexus_test_utils::http_testing::AuthnMode;
+use nexus_test_utils::http_testing::NexusRequest;
+use nexus_test_utils::http_testing::RequestBuilder;
+use nexus_test_utils::http_testing::TestResponse;
+use nexus_test_utils::resource_helpers::create_disk;
+use nexus_test_utils::resource_helpers::create_organization;
+use nexus_test_utils::resource_helpers::create_project;
+use nexus_test_utils::resource_helpers::DiskTest;
+use nexus_test_utils::ControlPlaneTestContext;
+use nexus_test_utils_macros::nexus_test;
+use omicron_common::api::external::AffinityPolicy;
+use omicron_common::api::external::IdentityMetadataCreateParams;
+use omicron_common::api::external::IdentityMetadataUpdateParams;
+use omicron_common::api::external::{
+    VpcFirewallRuleAction, VpcFirewallRuleDirection, VpcFirewallRuleFilter,
+    VpcFirewallRulePriority, VpcFirewallRuleStatus, VpcFirewallRuleTarget,
+    VpcFirewallRuleUpdate, VpcFirewallRuleUpdateParams,
+};
+use omicron_nexus::external_api::params;
+
+const ORG_NAME: &str = "test-org";
+const PROJECT_NAME: &str = "springfield-squidport";
+
+#[nexus_test]
+async fn test_organization_etags(cptestctx: &ControlPlaneTestContext) {
+    let client = &cptestctx.external_client;
+    create_organization(&client, ORG_NAME).await;
+    let org_url = format!("/organizations/{}", ORG_NAME);
+    let etag = etag_get(client, &org_url).await;
+    let update = params::OrganizationUpdate {
+        identity: IdentityMetadataUpdateParams {
+            name: None,
+            description: Some(String::from("a new description")),
+            labels: None,
+        },
+    };
+
+    // Updates fail if the Organization does not match the preconditions.
+    let error = expect_failure(
+        client,
+        Method::PUT,
+        &org_url,
+        Some(&update),
+        header::IF_MATCH,
+        "\"0\"",
+        StatusCode::PRECONDITION_FAILED,
+    )
+    .await;
+    assert_eq!(
+        error.message,
+        "resource does not match the request's preconditions"
+    );
+    expect_failure(
+        client,
+        Method::PUT,
+        &org_url,
+        Some(&update),
+        header::IF_NONE_MATCH,
+        "*",
+        StatusCode::PRECONDITION_FAILED,
+    )
+    .await;
+    let error = expect_failure(
+        client,
+        Method::PUT,
+        &org_url,
+        Some(&update),
+        header::IF_MATCH,
+        "not-quoted",
+        StatusCode::BAD_REQUEST,
+    )
+    .await;
+    assert_eq!(
+        error.message,
+        "header \"if-match\": invalid entity tag: not-quoted"
+    );
+
+    // With the current ETag, the update succeeds, and the response carries the
+    // new ETag.
+    let response = conditional_request(
+        client,
+        Method::PUT,
+        &org_url,
+        Some(&update),
+        &etag,
+        StatusCode::OK,
+    )
+    .await;
+    let new_etag = response_etag(&response);
+    assert_ne!(new_etag, etag);
+    assert_eq!(etag_get(client, &org_url).await, new_etag);
+
+    // The old ETag no longer works, for updates or for deletes.
+    expect_failure(
+        client,
+        Method::PUT,
+        &org_url,
+        Some(&update),
+        header::IF_MATCH,
+        &etag,
+        StatusCode::PRECONDITION_FAILED,
+    )
+    .await;
+    expect_failure::<()>(
+        client,
+        Method::DELETE,
+        &org_url,
+        None,
+        header::IF_MATCH,
+        &etag,
+        StatusCode::PRECONDITION_FAILED,
+    )
+    .await;
+
+    conditional_request::<()>(
+        client,
+        Method::DELETE,
+        &org_url,
+        None,
+        &new_etag,
+        StatusCode::NO_CONTENT,
+    )
+    .await;
+}
+
+#[nexus_test]
+async fn test_vpc_etags(cptestctx: &ControlPlaneTestContext) {
+    let client = &cptestctx.external_client;
+    create_organization(&client, ORG_NAME).await;
+    create_project(&client, ORG_NAME, PROJECT_NAME).await;
+    let vpc_url = format!(
+        "/organizations/{}/projects/{}/vpcs/default",
+        ORG_NAME, PROJECT_NAME
+    );
+    let etag = etag_get(client, &vpc_url).await;
+    let update = params::VpcUpdate {
+        identity: IdentityMetadataUpdateParams {
+            name: None,
+            description: Some(String::from("a new description")),
+            labels: None,
+        },
+        dns_name: None,
+    };
+
+    // Updates that don't use preconditions still clobber the VPC.
+    NexusRequest::new(
+        RequestBuilder::new(client, Method::PUT, &vpc_url)
+            .body(Some(&update))
+            .expect_status(Some(StatusCode::NO_CONTENT)),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap();
+    let new_etag = etag_get(client, &vpc_url).await;
+    assert_ne!(new_etag, etag);
+
+    // That invalidates the ETag another client may have fetched earlier.
+    expect_failure(
+        client,
+        Method::PUT,
+        &vpc_url,
+        Some(&update),
+        header::IF_MATCH,
+        &etag,
+        StatusCode::PRECONDITION_FAILED,
+    )
+    .await;
+    expect_failure::<()>(
+        client,
+        Method::DELETE,
+        &vpc_url,
+        None,
+        header::IF_MATCH,
+        &etag,
+        StatusCode::PRECONDITION_FAILED,
+    )
+    .await;
+
+    // The VPC (and its system router) are left alone by the failed delete.
+    etag_get(client, &vpc_url).await;
+    etag_get(client, &format!("{}/routers/system", vpc_url)).await;
+
+    conditional_request::<()>(
+        client,
+        Method::DELETE,
+        &vpc_url,
+        None,
+        "*",
+        StatusCode::NO_CONTENT,
+    )
+    .await;
+}
+
+#[nexus_test]
+async fn test_vpc_firewall_rules_etags(cptestctx: &ControlPlaneTestContext) {
+    let client = &cptestctx.external_client;
+    create_organization(&client, ORG_NAME).await;
+    create_project(&client, ORG_NAME, PROJECT_NAME).await;
+    let vpc_url = format!(
+        "/organizations/{}/projects/{}/vpcs/default",
+        ORG_NAME, PROJECT_NAME
+    );
+    let rules_url = format!("{}/firewall/rules", vpc_url);
+    let etag = etag_get(client, &rules_url).await;
+    let update = VpcFirewallRuleUpdateParams {
+        rules: vec![VpcFirewallRuleUpdate {
+            name: "deny-all-incoming".parse().unwrap(),
+            action: VpcFirewallRuleAction::Deny,
+            description: String::from("deny all incoming traffic"),
+            status: VpcFirewallRuleStatus::Enabled,
+            targets: vec![VpcFirewallRuleTarget::Vpc(
+                "default".parse().unwrap(),
+            )],
+            filters: VpcFirewallRuleFilter {
+                hosts: None,
+                ports: None,
+                protocols: None,
+            },
+            direction: VpcFirewallRuleDirection::Inbound,
+            priority: VpcFirewallRulePriority(100),
+        }],
+    };
+
+    // Replacing the rules fails if they've changed since the client saw them.
+    expect_failure(
+        client,
+        Method::PUT,
+        &rules_url,
+        Some(&update),
+        header::IF_MATCH,
+        "\"0\"",
+        StatusCode::PRECONDITION_FAILED,
+    )
+    .await;
+
+    // With the current ETag, the rules are replaced, and the response carries
+    // the new ETag, which is also the VPC's.
+    let response = conditional_request(
+        client,
+        Method::PUT,
+        &rules_url,
+        Some(&update),
+        &etag,
+        StatusCode::OK,
+    )
+    .await;
+    let new_etag = response_etag(&response);
+    assert_ne!(new_etag, etag);
+    assert_eq!(etag_get(client, &rules_url).await, new_etag);
+    assert_eq!(etag_get(client, &vpc_url).await, new_etag);
+
+    // The old ETag no longer works.
+    expect_failure(
+        client,
+        Method::PUT,
+        &rules_url,
+        Some(&update),
+        header::IF_MATCH,
+        &etag,
+        StatusCode::PRECONDITION_FAILED,
+    )
+    .await;
+}
+
+#[nexus_test]
+async fn test_disk_and_affinity_group_etags(
+    cptestctx: &ControlPlaneTestContext,
+) {
+    let client = &cptestctx.external_client;
+    DiskTest::new(&cptestctx).await;
+    create_organization(&client, ORG_NAME).await;
+    create_project(&client, ORG_NAME, PROJECT_NAME).await;
+    let project_url =
+        format!("/organizations/{}/projects/{}", ORG_NAME, PROJECT_NAME);
+    create_disk(&client, ORG_NAME, PROJECT_NAME, "just-rainsticks").await;
+    NexusRequest::objects_post(
+        client,
+        &format!("{}/affinity-groups", project_url),
+        &params::AffinityGroupCreate {
+            identity: IdentityMetadataCreateParams {
+                name: "web".parse().unwrap(),
+                description: String::from("web servers"),
+                labels: Default::default(),
+            },
+            policy: AffinityPolicy::AntiAffinity,
+        },
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap();
+
+    let updates = vec![
+        (
+            format!("{}/disks/just-rainsticks", project_url),
+            serde_json::json!({ "labels": { "owner": "rainsticks" } }),
+        ),
+        (
+            format!("{}/affinity-groups/web", project_url),
+            serde_json::json!({ "description": "front-end web servers" }),
+        ),
+    ];
+    for (url, update) in &updates {
+        // Updates fail if the resource does not match the preconditions, and
+        // succeed with its current ETag, returning the new one.
+        let etag = etag_get(client, url).await;
+        expect_failure(
+            client,
+            Method::PUT,
+            url,
+            Some(update),
+            header::IF_MATCH,
+            "\"0\"",
+            StatusCode::PRECONDITION_FAILED,
+        )
+        .await;
+        let response = conditional_request(
+            client,
+            Method::PUT,
+            url,
+            Some(update),
+            &etag,
+            StatusCode::OK,
+        )
+        .await;
+        let new_etag = response_etag(&response);
+        assert_ne!(new_etag, etag);
+        assert_eq!(etag_get(client, url).await, new_etag);
+    }
+}
+
+/// Fetches the object at `url`, returning its ETag
+async fn etag_get(client: &ClientTestContext, url: &str) -> String {
+    let response = NexusRequest::object_get(client, url)
+        .authn_as(AuthnMode::PrivilegedUser)
+        .execute()
+        .await
+        .unwrap();
+    response_etag(&response)
+}
+
+fn response_etag(response: &TestResponse) -> String {
+    response
+        .headers
+        .get(header::ETAG)
+        .expect("response had no ETag")
+        .to_str()
+        .unwrap()
+        .to_string()
+}
+
+async fn conditional_request<B: serde::Serialize>(
+    client: &ClientTestContext,
+    method: Method,
+    url: &str,
+    body: Option<&B>,
+    if_match: &str,
+    status: StatusCode,
+) -> TestResponse {
+    NexusRequest::new(
+        RequestBuilder::new(client, method, url)
+            .header(header::IF_MATCH, if_match)
+            .body(body)
+            .expect_status(Some(status)),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap()
+}
+
+async fn expect_failure<B: serde::Serialize>(
+    client: &ClientTestContext,
+    method: Method,
+    url: &str,
+    body: Option<&B>,
+    header_name: header::HeaderName,
+    header_value: &str,
+    status: StatusCode,
+) -> HttpErrorResponseBody {
+    NexusRequest::new(
+        RequestBuilder::new(client, method, url)
+            .header(header_name, header_value)
+            .body(body)
+            .expect_status(Some(status)),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap()
+    .parsed_body()
+    .unwrap()
+}
@@ -0,0 +1,469 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Tests Projects' event logs and their delivery to webhooks
+
+use dropshot::test_util::ClientTestContext;
+use dropshot::HttpErrorResponseBody;
+use dropshot::ResultsPage;
+use hmac::{Hmac, Mac};
+use http::method::Method;
+use http::HeaderMap;
+use http::StatusCode;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Request, Response, Server};
+use nexus_test_utils::http_testing::AuthnMode;
+use nexus_test_utils::http_testing::NexusRequest;
+use nexus_test_utils::http_testing::RequestBuilder;
+use nexus_test_utils::resource_helpers::create_disk;
+use nexus_test_utils::resource_helpers::create_instance;
+use nexus_test_utils::resource_helpers::create_organization;
+use nexus_test_utils::resource_helpers::create_project;
+use nexus_test_utils::resource_helpers::DiskTest;
+use nexus_test_utils::ControlPlaneTestContext;
+use nexus_test_utils::{load_test_config, test_setup_with_config};
+use nexus_test_utils_macros::nexus_test;
+use omicron_nexus::external_api::params;
+use omicron_nexus::external_api::views::{
+    ProjectEvent, ProjectEventsPage, ProjectWebhook,
+};
+use omicron_nexus::TestInterfaces as _;
+use sha2::Sha256;
+use sled_agent_client::TestInterfaces as _;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+const ORG_NAME: &str = "test-org";
+const PROJECT_NAME: &str = "springfield-squidport";
+const WEBHOOK_SECRET: &str = "correct horse battery staple";
+
+fn get_project_url() -> String {
+    format!("/organizations/{}/projects/{}", ORG_NAME, PROJECT_NAME)
+}
+
+async fn events_get(
+    client: &ClientTestContext,
+    after: u64,
+    wait_secs: u32,
+) -> ProjectEventsPage {
+    let url = format!(
+        "{}/events?after={}&wait_secs={}",
+        get_project_url(),
+        after,
+        wait_secs
+    );
+    NexusRequest::object_get(client, &url)
+        .authn_as(AuthnMode::PrivilegedUser)
+        .execute()
+        .await
+        .unwrap()
+        .parsed_body()
+        .unwrap()
+}
+
+/// Summarizes an event as (resource type, event type, state)
+fn describe(event: &ProjectEvent) -> (&str, &str, Option<&str>) {
+    (
+        event.resource_type.as_str(),
+        event.event_type.as_str(),
+        event.state.as_deref(),
+    )
+}
+
+#[nexus_test]
+async fn test_project_events(cptestctx: &ControlPlaneTestContext) {
+    let client = &cptestctx.external_client;
+    let nexus = &cptestctx.server.apictx.nexus;
+    create_organization(client, ORG_NAME).await;
+    let project = create_project(client, ORG_NAME, PROJECT_NAME).await;
+
+    // The log starts out empty, and asking for events without waiting returns
+    // right away.
+    let page = events_get(client, 0, 0).await;
+    assert!(page.items.is_empty());
+    assert_eq!(page.next_cursor, 0);
+
+    // Creating an Instance is logged by the time the request finishes.
+    let instance =
+        create_instance(client, ORG_NAME, PROJECT_NAME, "just-rainsticks")
+            .await;
+    let instance_id = instance.identity.id;
+    let page = events_get(client, 0, 0).await;
+    assert_eq!(page.items.len(), 1);
+    let event = &page.items[0];
+    assert_eq!(event.seq, 1);
+    assert_eq!(event.project_id, project.identity.id);
+    assert_eq!(event.resource_id, instance_id);
+    assert_eq!(describe(event), ("instance", "created", None));
+    assert_eq!(page.next_cursor, 1);
+
+    // A client waiting for the next event gets it as soon as the sled agent
+    // reports that the Instance has started.
+    let sa = nexus.instance_sled_by_id(&instance_id).await.unwrap();
+    let (page, ()) = futures::join!(events_get(client, 1, 30), async {
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        sa.instance_finish_transition(instance_id).await;
+    });
+    assert_eq!(page.items.len(), 1);
+    assert_eq!(page.items[0].seq, 2);
+    assert_eq!(
+        describe(&page.items[0]),
+        ("instance", "state_changed", Some("running"))
+    );
+    assert_eq!(page.next_cursor, 2);
+
+    // State changes that Nexus asks for are logged too, as is the Instance's
+    // deletion.
+    let instance_url =
+        format!("{}/instances/{}", get_project_url(), "just-rainsticks");
+    NexusRequest::new(
+        RequestBuilder::new(
+            client,
+            Method::POST,
+            &format!("{}/stop", instance_url),
+        )
+        .body(None as Option<&serde_json::Value>)
+        .expect_status(Some(StatusCode::ACCEPTED)),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap();
+    sa.instance_finish_transition(instance_id).await;
+    NexusRequest::object_delete(client, &instance_url)
+        .authn_as(AuthnMode::PrivilegedUser)
+        .execute()
+        .await
+        .unwrap();
+    let page = events_get(client, 2, 0).await;
+    let described = page.items.iter().map(describe).collect::<Vec<_>>();
+    assert_eq!(
+        described,
+        vec![
+            ("instance", "state_changed", Some("stopping")),
+            ("instance", "state_changed", Some("stopped")),
+            ("instance", "deleted", None),
+        ]
+    );
+    let seqs = page.items.iter().map(|e| e.seq).collect::<Vec<_>>();
+    assert_eq!(seqs, vec![3, 4, 5]);
+    assert_eq!(page.next_cursor, 5);
+
+    // A client that has seen everything gets nothing back once it's done
+    // waiting.
+    let page = events_get(client, 5, 1).await;
+    assert!(page.items.is_empty());
+    assert_eq!(page.next_cursor, 5);
+
+    // Only those who can see the Project can read its events.
+    NexusRequest::expect_failure(
+        client,
+        StatusCode::NOT_FOUND,
+        Method::GET,
+        &format!("{}/events", get_project_url()),
+    )
+    .authn_as(AuthnMode::UnprivilegedUser)
+    .execute()
+    .await
+    .unwrap();
+}
+
+/// A request received by the webhook receiver
+struct Delivery {
+    /// the status with which the receiver responded
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Vec<u8>,
+}
+
+/// Starts an HTTP server that stands in for a webhook, sending each request it
+/// receives to the returned channel
+///
+/// `respond` picks the status of each response, given how many requests came
+/// before it and the event that was delivered.
+fn start_webhook_receiver(
+    respond: fn(usize, &ProjectEvent) -> StatusCode,
+) -> (SocketAddr, mpsc::UnboundedReceiver<Delivery>) {
+    let (tx, rx) = mpsc::unbounded_channel();
+    let requests = Arc::new(AtomicUsize::new(0));
+    let make_service = make_service_fn(move |_| {
+        let tx = tx.clone();
+        let requests = Arc::clone(&requests);
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request: Request<Body>| {
+                let tx = tx.clone();
+                let requests = Arc::clone(&requests);
+                async move {
+                    let (parts, body) = request.into_parts();
+                    let body = hyper::body::to_bytes(body).await.unwrap();
+                    let event = serde_json::from_slice(&body).unwrap();
+                    let status = respond(
+                        requests.fetch_add(1, Ordering::SeqCst),
+                        &event,
+                    );
+                    let _ = tx.send(Delivery {
+                        status,
+                        headers: parts.headers,
+                        body: body.to_vec(),
+                    });
+                    Ok::<_, Infallible>(
+                        Response::builder()
+                            .status(status)
+                            .body(Body::empty())
+                            .unwrap(),
+                    )
+                }
+            }))
+        }
+    });
+    let server =
+        Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(make_service);
+    let address = server.local_addr();
+    tokio::spawn(server);
+    (address, rx)
+}
+
+async fn next_delivery(
+    deliveries: &mut mpsc::UnboundedReceiver<Delivery>,
+) -> Delivery {
+    tokio::time::timeout(Duration::from_secs(30), deliveries.recv())
+        .await
+        .expect("timed out waiting for a webhook delivery")
+        .unwrap()
+}
+
+async fn webhook_create_error(
+    client: &ClientTestContext,
+    url: &str,
+    secret: &str,
+) -> HttpErrorResponseBody {
+    NexusRequest::new(
+        RequestBuilder::new(
+            client,
+            Method::POST,
+            &format!("{}/webhooks", get_project_url()),
+        )
+        .body(Some(&params::ProjectWebhookCreate {
+            url: String::from(url),
+            secret: String::from(secret),
+        }))
+        .expect_status(Some(StatusCode::BAD_REQUEST)),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap()
+    .parsed_body()
+    .unwrap()
+}
+
+async fn webhooks_list(client: &ClientTestContext) -> Vec<ProjectWebhook> {
+    NexusRequest::object_get(client, &format!("{}/webhooks", get_project_url()))
+        .authn_as(AuthnMode::PrivilegedUser)
+        .execute()
+        .await
+        .unwrap()
+        .parsed_body::<ResultsPage<ProjectWebhook>>()
+        .unwrap()
+        .items
+}
+
+#[nexus_test]
+async fn test_project_webhooks(cptestctx: &ControlPlaneTestContext) {
+    let client = &cptestctx.external_client;
+    DiskTest::new(&cptestctx).await;
+    create_organization(client, ORG_NAME).await;
+    create_project(client, ORG_NAME, PROJECT_NAME).await;
+    // The receiver fails the first request so that retries are exercised.
+    let (address, mut deliveries) = start_webhook_receiver(|n, _| {
+        if n == 0 {
+            StatusCode::SERVICE_UNAVAILABLE
+        } else {
+            StatusCode::OK
+        }
+    });
+    let webhook_url = format!("http://{}/hooks/oxide", address);
+    let webhooks_url = format!("{}/webhooks", get_project_url());
+
+    // Webhooks need an http or https URL and a secret that's long enough.
+    let error =
+        webhook_create_error(client, "ftp://example.com", WEBHOOK_SECRET).await;
+    assert_eq!(
+        error.message,
+        "unsupported value for \"url\": expected an http or https URL"
+    );
+
+    // Nor may they be delivered to internal addresses, other than the
+    // loopback addresses the test configuration allows.
+    for (url, ip) in &[
+        ("http://169.254.169.254/latest/meta-data", "169.254.169.254"),
+        ("http://[::1]:8080/", "::1"),
+        ("https://[fd00:1122:3344:101::3]/", "fd00:1122:3344:101::3"),
+        ("http://10.0.0.1/", "10.0.0.1"),
+        ("http://[::ffff:a00:1]/", "::ffff:10.0.0.1"),
+    ] {
+        let error = webhook_create_error(client, url, WEBHOOK_SECRET).await;
+        assert_eq!(
+            error.message,
+            format!(
+                "unsupported value for \"url\": {} is an internal address",
+                ip
+            )
+        );
+    }
+
+    let error = webhook_create_error(client, &webhook_url, "hunter2").await;
+    assert_eq!(
+        error.message,
+        "unsupported value for \"secret\": secret must be between 16 and 255 \
+         bytes long"
+    );
+
+    // Only events that come after a webhook is created are delivered to it.
+    create_disk(client, ORG_NAME, PROJECT_NAME, "old-disk").await;
+    let webhook: ProjectWebhook = NexusRequest::objects_post(
+        client,
+        &webhooks_url,
+        &params::ProjectWebhookCreate {
+            url: webhook_url.clone(),
+            secret: String::from(WEBHOOK_SECRET),
+        },
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap()
+    .parsed_body()
+    .unwrap();
+    assert_eq!(webhook.url, webhook_url);
+    assert_eq!(webhook.delivered_seq, 1);
+    assert_eq!(webhook.last_failed_seq, None);
+    assert_eq!(webhooks_list(client).await, vec![webhook.clone()]);
+
+    // The next event is delivered, signed, and retried until it's accepted.
+    let disk = create_disk(client, ORG_NAME, PROJECT_NAME, "new-disk").await;
+    let failed = next_delivery(&mut deliveries).await;
+    assert_eq!(failed.status, StatusCode::SERVICE_UNAVAILABLE);
+    let delivery = next_delivery(&mut deliveries).await;
+    assert_eq!(delivery.status, StatusCode::OK);
+    assert_eq!(delivery.body, failed.body);
+    assert_eq!(
+        delivery.headers.get(http::header::CONTENT_TYPE).unwrap(),
+        "application/json"
+    );
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(WEBHOOK_SECRET.as_bytes()).unwrap();
+    mac.update(&delivery.body);
+    let expected_signature =
+        format!("sha256={}", hex::encode(mac.finalize().into_bytes()));
+    assert_eq!(
+        delivery.headers.get("x-oxide-signature").unwrap(),
+        expected_signature.as_str()
+    );
+    let event: ProjectEvent = serde_json::from_slice(&delivery.body).unwrap();
+    assert_eq!(event.seq, 2);
+    assert_eq!(event.resource_id, disk.identity.id);
+    assert_eq!(describe(&event), ("disk", "created", None));
+
+    // The webhook records how far delivery has gotten.
+    let webhooks = webhooks_list(client).await;
+    assert_eq!(webhooks.len(), 1);
+    assert_eq!(webhooks[0].delivered_seq, 2);
+    assert_eq!(webhooks[0].last_failed_seq, None);
+
+    // Only those who can modify the Project can register webhooks.
+    NexusRequest::new(
+        RequestBuilder::new(client, Method::POST, &webhooks_url)
+            .body(Some(&params::ProjectWebhookCreate {
+                url: webhook_url.clone(),
+                secret: String::from(WEBHOOK_SECRET),
+            }))
+            .expect_status(Some(StatusCode::NOT_FOUND)),
+    )
+    .authn_as(AuthnMode::UnprivilegedUser)
+    .execute()
+    .await
+    .unwrap();
+
+    // Once a webhook is deleted, it's gone.
+    let webhook_url = format!("{}/{}", webhooks_url, webhook.id);
+    NexusRequest::object_delete(client, &webhook_url)
+        .authn_as(AuthnMode::PrivilegedUser)
+        .execute()
+        .await
+        .unwrap();
+    assert!(webhooks_list(client).await.is_empty());
+    NexusRequest::expect_failure(
+        client,
+        StatusCode::NOT_FOUND,
+        Method::DELETE,
+        &webhook_url,
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn test_project_webhook_failures() {
+    let mut config = load_test_config();
+    config.webhooks.delivery_retry_secs = 1;
+    let cptestctx =
+        test_setup_with_config("test_project_webhook_failures", &mut config)
+            .await;
+    let client = &cptestctx.external_client;
+    DiskTest::new(&cptestctx).await;
+    create_organization(client, ORG_NAME).await;
+    create_project(client, ORG_NAME, PROJECT_NAME).await;
+
+    // The receiver never accepts the first event, and accepts the rest.
+    let (address, mut deliveries) = start_webhook_receiver(|_, event| {
+        if event.seq == 1 {
+            StatusCode::SERVICE_UNAVAILABLE
+        } else {
+            StatusCode::OK
+        }
+    });
+    let webhook_url = format!("http://{}/hooks/oxide", address);
+    NexusRequest::objects_post(
+        client,
+        &format!("{}/webhooks", get_project_url()),
+        &params::ProjectWebhookCreate {
+            url: webhook_url,
+            secret: String::from(WEBHOOK_SECRET),
+        },
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap();
+
+    // Delivery of the first event is retried for a while and then given up
+    // on, and the event after it is still delivered.
+    create_disk(client, ORG_NAME, PROJECT_NAME, "doomed-disk").await;
+    create_disk(client, ORG_NAME, PROJECT_NAME, "lucky-disk").await;
+    let mut attempts = 0;
+    let delivery = loop {
+        let delivery = next_delivery(&mut deliveries).await;
+        if delivery.status == StatusCode::OK {
+            break delivery;
+        }
+        attempts += 1;
+    };
+    assert!(attempts > 1, "first event was not retried");
+    let event: ProjectEvent = serde_json::from_slice(&delivery.body).unwrap();
+    assert_eq!(event.seq, 2);
+
+    // The webhook records which event it missed.
+    let webhooks = webhooks_list(client).await;
+    assert_eq!(webhooks.len(), 1);
+    assert_eq!(webhooks[0].last_failed_seq, Some(1));
+
+    cptestctx.teardown().await;
+}
@@ -0,0 +1,442 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Tests IP pools and the Floating IPs allocated from them
+
+use dropshot::test_util::ClientTestContext;
+use dropshot::HttpErrorResponseBody;
+use http::method::Method;
+use http::StatusCode;
+use nexus_test_utils::http_testing::AuthnMode;
+use nexus_test_utils::http_testing::NexusRequest;
+use nexus_test_utils::http_testing::RequestBuilder;
+use nexus_test_utils::http_testing::TestResponse;
+use nexus_test_utils::resource_helpers::objects_list_page_authz;
+use nexus_test_utils::resource_helpers::{
+    create_instance, create_organization, create_project, object_create,
+};
+use nexus_test_utils::ControlPlaneTestContext;
+use nexus_test_utils_macros::nexus_test;
+use omicron_common::api::external::{
+    IdentityMetadataCreateParams, NetworkInterface,
+};
+use omicron_nexus::external_api::params;
+use omicron_nexus::external_api::views::{FloatingIp, IpPool, IpPoolRange};
+use omicron_nexus::TestInterfaces as _;
+use sled_agent_client::TestInterfaces as _;
+use std::net::IpAddr;
+
+const ORG_NAME: &str = "test-org";
+const PROJECT_NAME: &str = "springfield-squidport";
+const POOL_URL: &str = "/ip-pools/public";
+
+fn get_floating_ips_url() -> String {
+    format!(
+        "/organizations/{}/projects/{}/floating-ips",
+        ORG_NAME, PROJECT_NAME
+    )
+}
+
+async fn post_expect(
+    client: &ClientTestContext,
+    url: &str,
+    body: Option<&serde_json::Value>,
+    expected_status: StatusCode,
+) -> TestResponse {
+    NexusRequest::new(
+        RequestBuilder::new(client, Method::POST, url)
+            .body(body)
+            .expect_status(Some(expected_status)),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap()
+}
+
+async fn range_add(
+    client: &ClientTestContext,
+    first: &str,
+    last: &str,
+    expected_status: StatusCode,
+) -> TestResponse {
+    let range = params::IpPoolRangeCreate {
+        first: first.parse().unwrap(),
+        last: last.parse().unwrap(),
+    };
+    post_expect(
+        client,
+        &format!("{}/ranges", POOL_URL),
+        Some(&serde_json::to_value(&range).unwrap()),
+        expected_status,
+    )
+    .await
+}
+
+async fn floating_ip_create(
+    client: &ClientTestContext,
+    name: &str,
+    expected_status: StatusCode,
+) -> TestResponse {
+    let params = params::FloatingIpCreate {
+        identity: IdentityMetadataCreateParams {
+            name: name.parse().unwrap(),
+            description: format!("floating IP {:?}", name),
+            labels: Default::default(),
+        },
+        pool_name: "public".parse().unwrap(),
+    };
+    post_expect(
+        client,
+        &get_floating_ips_url(),
+        Some(&serde_json::to_value(&params).unwrap()),
+        expected_status,
+    )
+    .await
+}
+
+async fn floating_ip_attach(
+    client: &ClientTestContext,
+    name: &str,
+    instance_name: &str,
+    expected_status: StatusCode,
+) -> TestResponse {
+    let params = params::FloatingIpAttach {
+        instance_name: instance_name.parse().unwrap(),
+    };
+    post_expect(
+        client,
+        &format!("{}/{}/attach", get_floating_ips_url(), name),
+        Some(&serde_json::to_value(&params).unwrap()),
+        expected_status,
+    )
+    .await
+}
+
+async fn floating_ip_detach(
+    client: &ClientTestContext,
+    name: &str,
+    expected_status: StatusCode,
+) -> TestResponse {
+    post_expect(
+        client,
+        &format!("{}/{}/detach", get_floating_ips_url(), name),
+        None,
+        expected_status,
+    )
+    .await
+}
+
+async fn object_delete(client: &ClientTestContext, url: &str) {
+    NexusRequest::object_delete(client, url)
+        .authn_as(AuthnMode::PrivilegedUser)
+        .execute()
+        .await
+        .unwrap();
+}
+
+#[nexus_test]
+async fn test_ip_pools(cptestctx: &ControlPlaneTestContext) {
+    let client = &cptestctx.external_client;
+
+    let pool: IpPool = object_create(
+        client,
+        "/ip-pools",
+        &params::IpPoolCreate {
+            identity: IdentityMetadataCreateParams {
+                name: "public".parse().unwrap(),
+                description: String::from("addresses for the outside world"),
+                labels: Default::default(),
+            },
+        },
+    )
+    .await;
+    assert_eq!(pool.identity.name, "public");
+    let pools =
+        objects_list_page_authz::<IpPool>(client, "/ip-pools").await.items;
+    assert_eq!(pools.len(), 1);
+    assert_eq!(pools[0].identity.id, pool.identity.id);
+
+    // Ranges are inclusive, and must be well-formed.
+    let range: IpPoolRange =
+        range_add(client, "10.0.0.1", "10.0.0.2", StatusCode::CREATED)
+            .await
+            .parsed_body()
+            .unwrap();
+    assert_eq!(range.ip_pool_id, pool.identity.id);
+    assert_eq!(range.first, "10.0.0.1".parse::<IpAddr>().unwrap());
+    assert_eq!(range.last, "10.0.0.2".parse::<IpAddr>().unwrap());
+    let error: HttpErrorResponseBody =
+        range_add(client, "10.0.1.2", "10.0.1.1", StatusCode::BAD_REQUEST)
+            .await
+            .parsed_body()
+            .unwrap();
+    assert_eq!(
+        error.message,
+        "the last address of an IP pool range must not be less than the first"
+    );
+    let error: HttpErrorResponseBody =
+        range_add(client, "10.0.1.1", "fd00::1", StatusCode::BAD_REQUEST)
+            .await
+            .parsed_body()
+            .unwrap();
+    assert_eq!(
+        error.message,
+        "the first and last addresses of an IP pool range must be in the same \
+         address family"
+    );
+
+    // No address may be in more than one range.
+    let error: HttpErrorResponseBody =
+        range_add(client, "10.0.0.2", "10.0.0.5", StatusCode::BAD_REQUEST)
+            .await
+            .parsed_body()
+            .unwrap();
+    assert_eq!(error.message, "IP pool range overlaps an existing range");
+    let ranges = objects_list_page_authz::<IpPoolRange>(
+        client,
+        &format!("{}/ranges", POOL_URL),
+    )
+    .await
+    .items;
+    assert_eq!(ranges.len(), 1);
+    assert_eq!(ranges[0].id, range.id);
+
+    // Removing a range frees its addresses for another range.
+    object_delete(client, &format!("{}/ranges/{}", POOL_URL, range.id)).await;
+    range_add(client, "10.0.0.2", "10.0.0.5", StatusCode::CREATED).await;
+
+    object_delete(client, POOL_URL).await;
+    NexusRequest::expect_failure(
+        client,
+        StatusCode::NOT_FOUND,
+        Method::GET,
+        POOL_URL,
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap();
+}
+
+#[nexus_test]
+async fn test_floating_ips(cptestctx: &ControlPlaneTestContext) {
+    let client = &cptestctx.external_client;
+    let nexus = &cptestctx.server.apictx.nexus;
+    create_organization(client, ORG_NAME).await;
+    create_project(client, ORG_NAME, PROJECT_NAME).await;
+    let _: IpPool = object_create(
+        client,
+        "/ip-pools",
+        &params::IpPoolCreate {
+            identity: IdentityMetadataCreateParams {
+                name: "public".parse().unwrap(),
+                description: String::from("addresses for the outside world"),
+                labels: Default::default(),
+            },
+        },
+    )
+    .await;
+    range_add(client, "10.0.0.1", "10.0.0.2", StatusCode::CREATED).await;
+
+    // Addresses are allocated from the pool's ranges until they run out.
+    let fip0: FloatingIp =
+        floating_ip_create(client, "fip0", StatusCode::CREATED)
+            .await
+            .parsed_body()
+            .unwrap();
+    let fip1: FloatingIp =
+        floating_ip_create(client, "fip1", StatusCode::CREATED)
+            .await
+            .parsed_body()
+            .unwrap();
+    let mut ips = vec![fip0.ip, fip1.ip];
+    ips.sort();
+    assert_eq!(
+        ips,
+        vec![
+            "10.0.0.1".parse::<IpAddr>().unwrap(),
+            "10.0.0.2".parse::<IpAddr>().unwrap()
+        ]
+    );
+    assert_eq!(fip0.instance_id, None);
+    let error: HttpErrorResponseBody =
+        floating_ip_create(client, "fip2", StatusCode::BAD_REQUEST)
+            .await
+            .parsed_body()
+            .unwrap();
+    assert_eq!(error.message, "no available addresses in IP pool");
+
+    // Releasing a Floating IP returns its address to the pool.
+    object_delete(client, &format!("{}/fip1", get_floating_ips_url())).await;
+    let fip2: FloatingIp =
+        floating_ip_create(client, "fip2", StatusCode::CREATED)
+            .await
+            .parsed_body()
+            .unwrap();
+    assert_eq!(fip2.ip, fip1.ip);
+
+    // The pool can't be deleted while addresses are allocated from it.
+    let error: HttpErrorResponseBody = NexusRequest::expect_failure(
+        client,
+        StatusCode::BAD_REQUEST,
+        Method::DELETE,
+        POOL_URL,
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap()
+    .parsed_body()
+    .unwrap();
+    assert_eq!(
+        error.message,
+        "IP pool to be deleted has floating IPs allocated from it"
+    );
+
+    // Floating IPs can't be attached to a running Instance...
+    let instance =
+        create_instance(client, ORG_NAME, PROJECT_NAME, "webserver").await;
+    let instance_url = format!(
+        "/organizations/{}/projects/{}/instances/webserver",
+        ORG_NAME, PROJECT_NAME
+    );
+    let sa = nexus.instance_sled_by_id(&instance.identity.id).await.unwrap();
+    sa.instance_finish_transition(instance.identity.id).await;
+    let error: HttpErrorResponseBody = floating_ip_attach(
+        client,
+        "fip0",
+        "webserver",
+        StatusCode::BAD_REQUEST,
+    )
+    .await
+    .parsed_body()
+    .unwrap();
+    assert_eq!(
+        error.message,
+        "cannot change network interfaces of instance \"webserver\": instance \
+         is running; stop it first"
+    );
+
+    // ... but can be attached to a stopped one, and show up on its primary
+    // network interface.
+    post_expect(
+        client,
+        &format!("{}/stop", instance_url),
+        None,
+        StatusCode::ACCEPTED,
+    )
+    .await;
+    sa.instance_finish_transition(instance.identity.id).await;
+    let attached: FloatingIp =
+        floating_ip_attach(client, "fip0", "webserver", StatusCode::OK)
+            .await
+            .parsed_body()
+            .unwrap();
+    assert_eq!(attached.instance_id, Some(instance.identity.id));
+    let nics_url = format!("{}/network-interfaces", instance_url);
+    let nics = objects_list_page_authz::<NetworkInterface>(client, &nics_url)
+        .await
+        .items;
+    assert_eq!(nics.len(), 1);
+    assert_eq!(attached.network_interface_id, Some(nics[0].identity.id));
+    assert_eq!(nics[0].external_ip, Some(fip0.ip));
+
+    // An interface has at most one Floating IP, and a Floating IP is attached
+    // to at most one interface.
+    let error: HttpErrorResponseBody = floating_ip_attach(
+        client,
+        "fip2",
+        "webserver",
+        StatusCode::BAD_REQUEST,
+    )
+    .await
+    .parsed_body()
+    .unwrap();
+    assert_eq!(error.message, "instance already has a floating IP attached");
+    let error: HttpErrorResponseBody = floating_ip_attach(
+        client,
+        "fip0",
+        "webserver",
+        StatusCode::BAD_REQUEST,
+    )
+    .await
+    .parsed_body()
+    .unwrap();
+    assert_eq!(error.message, "floating IP is already attached");
+
+    // Detaching leaves the Floating IP allocated to the project.
+    let detached: FloatingIp =
+        floating_ip_detach(client, "fip0", StatusCode::OK)
+            .await
+            .parsed_body()
+            .unwrap();
+    assert_eq!(detached.instance_id, None);
+    assert_eq!(detached.network_interface_id, None);
+    assert_eq!(detached.ip, fip0.ip);
+    let nics = objects_list_page_authz::<NetworkInterface>(client, &nics_url)
+        .await
+        .items;
+    assert_eq!(nics[0].external_ip, None);
+    let error: HttpErrorResponseBody =
+        floating_ip_detach(client, "fip0", StatusCode::BAD_REQUEST)
+            .await
+            .parsed_body()
+            .unwrap();
+    assert_eq!(error.message, "floating IP is not attached");
+
+    // Deleting the Instance releases the Floating IPs attached to it.
+    floating_ip_attach(client, "fip0", "webserver", StatusCode::OK).await;
+    object_delete(client, &instance_url).await;
+    let error: HttpErrorResponseBody = NexusRequest::expect_failure(
+        client,
+        StatusCode::NOT_FOUND,
+        Method::GET,
+        &format!("{}/fip0", get_floating_ips_url()),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap()
+    .parsed_body()
+    .unwrap();
+    assert_eq!(error.message, "not found: floating-ip with name \"fip0\"");
+    let fip3: FloatingIp =
+        floating_ip_create(client, "fip3", StatusCode::CREATED)
+            .await
+            .parsed_body()
+            .unwrap();
+    assert_eq!(fip3.ip, fip0.ip);
+    let fips =
+        objects_list_page_authz::<FloatingIp>(client, &get_floating_ips_url())
+            .await
+            .items;
+    let names =
+        fips.iter().map(|f| f.identity.name.as_str()).collect::<Vec<_>>();
+    assert_eq!(names, vec!["fip2", "fip3"]);
+
+    // The project can't be deleted while it holds Floating IPs.
+    let project_url =
+        format!("/organizations/{}/projects/{}", ORG_NAME, PROJECT_NAME);
+    let error: HttpErrorResponseBody = NexusRequest::expect_failure(
+        client,
+        StatusCode::BAD_REQUEST,
+        Method::DELETE,
+        &project_url,
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap()
+    .parsed_body()
+    .unwrap();
+    assert_eq!(error.message, "project to be deleted contains a floating IP");
+
+    // Once every address is released, the project and the pool can be
+    // deleted.
+    object_delete(client, &format!("{}/fip2", get_floating_ips_url())).await;
+    object_delete(client, &format!("{}/fip3", get_floating_ips_url())).await;
+    object_delete(client, &project_url).await;
+    object_delete(client, POOL_URL).await;
+}
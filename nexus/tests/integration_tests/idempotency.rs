@@ -0,0 +1,244 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Tests create requests made with an `Idempotency-Key` header
+
+use dropshot::test_util::ClientTestContext;
+use dropshot::HttpErrorResponseBody;
+use http::method::Method;
+use http::StatusCode;
+use nexus_test_utils::http_testing::AuthnMode;
+use nexus_test_utils::http_testing::NexusRequest;
+use nexus_test_utils::http_testing::RequestBuilder;
+use nexus_test_utils::http_testing::TestResponse;
+use nexus_test_utils::resource_helpers::create_organization;
+use nexus_test_utils::resource_helpers::create_project;
+use nexus_test_utils::resource_helpers::objects_list_page_authz;
+use nexus_test_utils::ControlPlaneTestContext;
+use nexus_test_utils_macros::nexus_test;
+use omicron_common::api::external::ByteCount;
+use omicron_common::api::external::IdentityMetadataCreateParams;
+use omicron_common::api::external::Instance;
+use omicron_common::api::external::InstanceCpuCount;
+use omicron_nexus::external_api::params;
+use omicron_nexus::external_api::views::{Organization, Project};
+
+const ORG_NAME: &str = "test-org";
+const PROJECT_NAME: &str = "springfield-squidport";
+
+#[nexus_test]
+async fn test_idempotent_organization_create(
+    cptestctx: &ControlPlaneTestContext,
+) {
+    let client = &cptestctx.external_client;
+    let create_params = params::OrganizationCreate {
+        identity: IdentityMetadataCreateParams {
+            name: ORG_NAME.parse().unwrap(),
+            description: String::from("an org"),
+            labels: Default::default(),
+        },
+    };
+
+    // Retrying the request with the same key returns the original result
+    // rather than an "already exists" error.
+    let org: Organization = create_with_key(
+        client,
+        "/organizations",
+        &create_params,
+        "key-1",
+        StatusCode::CREATED,
+    )
+    .await
+    .parsed_body()
+    .unwrap();
+    let retried: Organization = create_with_key(
+        client,
+        "/organizations",
+        &create_params,
+        "key-1",
+        StatusCode::CREATED,
+    )
+    .await
+    .parsed_body()
+    .unwrap();
+    assert_eq!(org.identity.id, retried.identity.id);
+    assert_eq!(org.identity.name, retried.identity.name);
+    let orgs =
+        objects_list_page_authz::<Organization>(client, "/organizations").await;
+    assert_eq!(orgs.items.len(), 1);
+
+    // Reusing the key for a different request is an error.
+    let other_params = params::OrganizationCreate {
+        identity: IdentityMetadataCreateParams {
+            name: "other-org".parse().unwrap(),
+            description: String::from("another org"),
+            labels: Default::default(),
+        },
+    };
+    let error: HttpErrorResponseBody = create_with_key(
+        client,
+        "/organizations",
+        &other_params,
+        "key-1",
+        StatusCode::BAD_REQUEST,
+    )
+    .await
+    .parsed_body()
+    .unwrap();
+    assert_eq!(
+        error.message,
+        "idempotency key \"key-1\" was already used for a different request"
+    );
+
+    // Without a key, retrying gets the usual conflict.
+    NexusRequest::new(
+        RequestBuilder::new(client, Method::POST, "/organizations")
+            .body(Some(&create_params))
+            .expect_status(Some(StatusCode::BAD_REQUEST)),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap();
+
+    // Keys must be valid.
+    let error: HttpErrorResponseBody = create_with_key(
+        client,
+        "/organizations",
+        &other_params,
+        &"k".repeat(256),
+        StatusCode::BAD_REQUEST,
+    )
+    .await
+    .parsed_body()
+    .unwrap();
+    assert_eq!(
+        error.message,
+        "header \"idempotency-key\" must be between 1 and 255 bytes long"
+    );
+}
+
+#[nexus_test]
+async fn test_idempotent_create_failure(cptestctx: &ControlPlaneTestContext) {
+    let client = &cptestctx.external_client;
+    let projects_url = format!("/organizations/{}/projects", ORG_NAME);
+    let create_params = params::ProjectCreate {
+        identity: IdentityMetadataCreateParams {
+            name: PROJECT_NAME.parse().unwrap(),
+            description: String::from("a pier"),
+            labels: Default::default(),
+        },
+    };
+
+    // A request that fails doesn't hold onto its key, so once the problem is
+    // fixed, the same request can be retried with the same key.
+    create_with_key(
+        client,
+        &projects_url,
+        &create_params,
+        "key-1",
+        StatusCode::NOT_FOUND,
+    )
+    .await;
+    create_organization(client, ORG_NAME).await;
+    let project: Project = create_with_key(
+        client,
+        &projects_url,
+        &create_params,
+        "key-1",
+        StatusCode::CREATED,
+    )
+    .await
+    .parsed_body()
+    .unwrap();
+    assert_eq!(project.identity.name, PROJECT_NAME);
+}
+
+#[nexus_test]
+async fn test_idempotent_instance_create(cptestctx: &ControlPlaneTestContext) {
+    let client = &cptestctx.external_client;
+    create_organization(client, ORG_NAME).await;
+    create_project(client, ORG_NAME, PROJECT_NAME).await;
+    let instances_url = format!(
+        "/organizations/{}/projects/{}/instances",
+        ORG_NAME, PROJECT_NAME
+    );
+    let create_params = params::InstanceCreate {
+        identity: IdentityMetadataCreateParams {
+            name: "just-rainsticks".parse().unwrap(),
+            description: String::from("sells rainsticks"),
+            labels: Default::default(),
+        },
+        ncpus: InstanceCpuCount(4),
+        memory: ByteCount::from_mebibytes_u32(256),
+        hostname: String::from("rainsticks"),
+        affinity_group: None,
+        user_data: vec![],
+        ssh_public_keys: vec![],
+        network_interfaces: vec![],
+    };
+
+    // Instances are created by a saga.  A retry gets the instance that the
+    // original saga created, and no second saga is run.
+    let instance: Instance = create_with_key(
+        client,
+        &instances_url,
+        &create_params,
+        "key-1",
+        StatusCode::CREATED,
+    )
+    .await
+    .parsed_body()
+    .unwrap();
+    let retried: Instance = create_with_key(
+        client,
+        &instances_url,
+        &create_params,
+        "key-1",
+        StatusCode::CREATED,
+    )
+    .await
+    .parsed_body()
+    .unwrap();
+    assert_eq!(instance.identity.id, retried.identity.id);
+    let instances =
+        objects_list_page_authz::<Instance>(client, &instances_url).await;
+    assert_eq!(instances.items.len(), 1);
+
+    // The key covers the request's path as well as its body, so it can't be
+    // reused to create the same instance in a different project.
+    let error: HttpErrorResponseBody = create_with_key(
+        client,
+        &format!("/organizations/{}/projects/other/instances", ORG_NAME),
+        &create_params,
+        "key-1",
+        StatusCode::BAD_REQUEST,
+    )
+    .await
+    .parsed_body()
+    .unwrap();
+    assert_eq!(
+        error.message,
+        "idempotency key \"key-1\" was already used for a different request"
+    );
+}
+
+async fn create_with_key<B: serde::Serialize>(
+    client: &ClientTestContext,
+    url: &str,
+    body: &B,
+    key: &str,
+    status: StatusCode,
+) -> TestResponse {
+    NexusRequest::new(
+        RequestBuilder::new(client, Method::POST, url)
+            .header("idempotency-key", key)
+            .body(Some(body))
+            .expect_status(Some(status)),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap()
+}
@@ -0,0 +1,284 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Tests Instances with network interfaces chosen by the user
+
+use dropshot::test_util::ClientTestContext;
+use dropshot::HttpErrorResponseBody;
+use http::method::Method;
+use http::StatusCode;
+use nexus_test_utils::http_testing::AuthnMode;
+use nexus_test_utils::http_testing::NexusRequest;
+use nexus_test_utils::http_testing::RequestBuilder;
+use nexus_test_utils::http_testing::TestResponse;
+use nexus_test_utils::resource_helpers::objects_list_page_authz;
+use nexus_test_utils::resource_helpers::{
+    create_organization, create_project, create_vpc,
+};
+use nexus_test_utils::ControlPlaneTestContext;
+use nexus_test_utils_macros::nexus_test;
+use omicron_common::api::external::{
+    ByteCount, IdentityMetadataCreateParams, Instance, InstanceCpuCount,
+    NetworkInterface,
+};
+use omicron_nexus::external_api::params;
+use omicron_nexus::TestInterfaces as _;
+use sled_agent_client::TestInterfaces as _;
+use std::net::IpAddr;
+
+const ORG_NAME: &str = "test-org";
+const PROJECT_NAME: &str = "springfield-squidport";
+
+fn get_project_url() -> String {
+    format!("/organizations/{}/projects/{}", ORG_NAME, PROJECT_NAME)
+}
+
+fn nic_params(
+    name: &str,
+    vpc_name: &str,
+    subnet_name: &str,
+    ip: Option<&str>,
+) -> params::NetworkInterfaceCreate {
+    params::NetworkInterfaceCreate {
+        identity: IdentityMetadataCreateParams {
+            name: name.parse().unwrap(),
+            description: format!("interface {:?}", name),
+            labels: Default::default(),
+        },
+        vpc_name: vpc_name.parse().unwrap(),
+        subnet_name: subnet_name.parse().unwrap(),
+        ip: ip.map(|ip| ip.parse().unwrap()),
+    }
+}
+
+async fn instance_create(
+    client: &ClientTestContext,
+    name: &str,
+    network_interfaces: Vec<params::NetworkInterfaceCreate>,
+    expected_status: StatusCode,
+) -> TestResponse {
+    NexusRequest::new(
+        RequestBuilder::new(
+            client,
+            Method::POST,
+            &format!("{}/instances", get_project_url()),
+        )
+        .body(Some(&params::InstanceCreate {
+            identity: IdentityMetadataCreateParams {
+                name: name.parse().unwrap(),
+                description: format!("instance {:?}", name),
+                labels: Default::default(),
+            },
+            ncpus: InstanceCpuCount(1),
+            memory: ByteCount::from_mebibytes_u32(256),
+            hostname: String::from(name),
+            affinity_group: None,
+            user_data: vec![],
+            ssh_public_keys: vec![],
+            network_interfaces,
+        }))
+        .expect_status(Some(expected_status)),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap()
+}
+
+async fn nics_list(
+    client: &ClientTestContext,
+    url: &str,
+) -> Vec<NetworkInterface> {
+    objects_list_page_authz::<NetworkInterface>(client, url).await.items
+}
+
+async fn nic_add(
+    client: &ClientTestContext,
+    nics_url: &str,
+    nic: &params::NetworkInterfaceCreate,
+    expected_status: StatusCode,
+) -> TestResponse {
+    NexusRequest::new(
+        RequestBuilder::new(client, Method::POST, nics_url)
+            .body(Some(nic))
+            .expect_status(Some(expected_status)),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap()
+}
+
+#[nexus_test]
+async fn test_instance_network_interfaces(cptestctx: &ControlPlaneTestContext) {
+    let client = &cptestctx.external_client;
+    let nexus = &cptestctx.server.apictx.nexus;
+    create_organization(client, ORG_NAME).await;
+    create_project(client, ORG_NAME, PROJECT_NAME).await;
+    let backend = create_vpc(client, ORG_NAME, PROJECT_NAME, "backend").await;
+    let default_ips_url =
+        format!("{}/vpcs/default/subnets/default/ips", get_project_url());
+
+    // An Instance can be created with interfaces in several VPCs, at addresses
+    // of the user's choosing or not.
+    let instance: Instance = instance_create(
+        client,
+        "appliance",
+        vec![
+            nic_params("net0", "default", "default", Some("172.30.0.10")),
+            nic_params("net1", "backend", "default", None),
+        ],
+        StatusCode::CREATED,
+    )
+    .await
+    .parsed_body()
+    .unwrap();
+    let instance_url = format!("{}/instances/appliance", get_project_url());
+    let nics_url = format!("{}/network-interfaces", instance_url);
+    let nics = nics_list(client, &nics_url).await;
+    assert_eq!(nics.len(), 2);
+    assert_eq!(nics[0].identity.name, "net0");
+    assert_eq!(nics[0].instance_id, instance.identity.id);
+    assert_eq!(nics[0].ip, "172.30.0.10".parse::<IpAddr>().unwrap());
+    assert_ne!(nics[0].vpc_id, backend.identity.id);
+    assert_eq!(nics[1].identity.name, "net1");
+    assert_eq!(nics[1].vpc_id, backend.identity.id);
+    assert_ne!(nics[0].mac, nics[1].mac);
+
+    let nic: NetworkInterface =
+        NexusRequest::object_get(client, &format!("{}/net1", nics_url))
+            .authn_as(AuthnMode::PrivilegedUser)
+            .execute()
+            .await
+            .unwrap()
+            .parsed_body()
+            .unwrap();
+    assert_eq!(nic.identity.id, nics[1].identity.id);
+    assert_eq!(nic.ip, nics[1].ip);
+
+    // If any interface can't be created, none of them are left behind.
+    let error: HttpErrorResponseBody = instance_create(
+        client,
+        "broken",
+        vec![
+            nic_params("eth0", "default", "default", Some("172.30.0.11")),
+            nic_params("eth1", "nonexistent", "default", None),
+        ],
+        StatusCode::NOT_FOUND,
+    )
+    .await
+    .parsed_body()
+    .unwrap();
+    assert_eq!(error.message, "not found: vpc with name \"nonexistent\"");
+    let default_nics = nics_list(client, &default_ips_url).await;
+    assert_eq!(default_nics.len(), 1);
+    assert_eq!(default_nics[0].identity.id, nics[0].identity.id);
+
+    // Interface names need only be unique within their Instance.
+    instance_create(
+        client,
+        "sidecar",
+        vec![nic_params("net1", "backend", "default", None)],
+        StatusCode::CREATED,
+    )
+    .await;
+
+    // There's a limit to how many interfaces an Instance may have.
+    let too_many = (0..=params::MAX_NICS_PER_INSTANCE)
+        .map(|i| nic_params(&format!("net{}", i), "default", "default", None))
+        .collect();
+    let error: HttpErrorResponseBody =
+        instance_create(client, "greedy", too_many, StatusCode::BAD_REQUEST)
+            .await
+            .parsed_body()
+            .unwrap();
+    assert_eq!(
+        error.message,
+        "unsupported value for \"network_interfaces\": an instance may have at \
+         most 8 network interfaces"
+    );
+
+    // Interfaces can't be added or removed while the Instance is running...
+    let sa = nexus.instance_sled_by_id(&instance.identity.id).await.unwrap();
+    sa.instance_finish_transition(instance.identity.id).await;
+    let net2 = nic_params("net2", "default", "default", None);
+    let error: HttpErrorResponseBody =
+        nic_add(client, &nics_url, &net2, StatusCode::BAD_REQUEST)
+            .await
+            .parsed_body()
+            .unwrap();
+    assert_eq!(
+        error.message,
+        "cannot change network interfaces of instance \"appliance\": instance \
+         is running; stop it first"
+    );
+    NexusRequest::expect_failure(
+        client,
+        StatusCode::BAD_REQUEST,
+        Method::DELETE,
+        &format!("{}/net0", nics_url),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap();
+
+    // ... but they can once it's stopped.
+    NexusRequest::new(
+        RequestBuilder::new(
+            client,
+            Method::POST,
+            &format!("{}/stop", instance_url),
+        )
+        .body(None as Option<&serde_json::Value>)
+        .expect_status(Some(StatusCode::ACCEPTED)),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap();
+    sa.instance_finish_transition(instance.identity.id).await;
+
+    let added: NetworkInterface =
+        nic_add(client, &nics_url, &net2, StatusCode::CREATED)
+            .await
+            .parsed_body()
+            .unwrap();
+    assert_eq!(added.identity.name, "net2");
+    assert_eq!(added.instance_id, instance.identity.id);
+    let error: HttpErrorResponseBody =
+        nic_add(client, &nics_url, &net2, StatusCode::BAD_REQUEST)
+            .await
+            .parsed_body()
+            .unwrap();
+    assert_eq!(error.message, "already exists: network-interface \"net2\"");
+
+    NexusRequest::object_delete(client, &format!("{}/net0", nics_url))
+        .authn_as(AuthnMode::PrivilegedUser)
+        .execute()
+        .await
+        .unwrap();
+    let nics = nics_list(client, &nics_url).await;
+    let names =
+        nics.iter().map(|n| n.identity.name.as_str()).collect::<Vec<_>>();
+    assert_eq!(names, vec!["net1", "net2"]);
+    NexusRequest::expect_failure(
+        client,
+        StatusCode::NOT_FOUND,
+        Method::GET,
+        &format!("{}/net0", nics_url),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap();
+
+    // Deleting the Instance deletes its interfaces, freeing their addresses.
+    NexusRequest::object_delete(client, &instance_url)
+        .authn_as(AuthnMode::PrivilegedUser)
+        .execute()
+        .await
+        .unwrap();
+    assert!(nics_list(client, &default_ips_url).await.is_empty());
+}
@@ -0,0 +1,381 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Tests placement of new Instances across several simulated sled agents
+
+use dropshot::HttpErrorResponseBody;
+use http::method::Method;
+use http::StatusCode;
+use nexus_test_utils::http_testing::AuthnMode;
+use nexus_test_utils::http_testing::NexusRequest;
+use nexus_test_utils::http_testing::RequestBuilder;
+use nexus_test_utils::resource_helpers::{
+    create_organization, create_project, object_create,
+};
+use nexus_test_utils::{
+    load_test_config, start_sled_agent, test_setup_with_config,
+    ControlPlaneTestContext, SLED_AGENT_HARDWARE_THREADS, SLED_AGENT_UUID,
+};
+use nexus_test_utils_macros::nexus_test;
+use omicron_common::api::external::AffinityPolicy;
+use omicron_common::api::external::ByteCount;
+use omicron_common::api::external::IdentityMetadataCreateParams;
+use omicron_common::api::external::Instance;
+use omicron_common::api::external::InstanceCpuCount;
+use omicron_common::api::external::InstanceState;
+use omicron_nexus::external_api::params;
+use omicron_nexus::external_api::views::AffinityGroup;
+use omicron_nexus::PlacementPolicy;
+use omicron_nexus::TestInterfaces as _;
+use omicron_sled_agent::sim;
+use sled_agent_client::TestInterfaces as _;
+use std::collections::BTreeSet;
+use std::convert::TryFrom;
+use uuid::Uuid;
+
+static ORGANIZATION_NAME: &str = "test-org";
+static PROJECT_NAME: &str = "springfield-squidport";
+
+fn instances_url() -> String {
+    format!(
+        "/organizations/{}/projects/{}/instances",
+        ORGANIZATION_NAME, PROJECT_NAME
+    )
+}
+
+fn instance_params(name: &str, ncpus: u16) -> params::InstanceCreate {
+    params::InstanceCreate {
+        identity: IdentityMetadataCreateParams {
+            name: name.parse().unwrap(),
+            description: format!("instance {:?}", name),
+            labels: Default::default(),
+        },
+        ncpus: InstanceCpuCount(ncpus),
+        memory: ByteCount::from_gibibytes_u32(1),
+        hostname: String::from(name),
+        affinity_group: None,
+        user_data: vec![],
+        ssh_public_keys: vec![],
+        network_interfaces: vec![],
+    }
+}
+
+async fn create_sized_instance(
+    cptestctx: &ControlPlaneTestContext,
+    name: &str,
+    ncpus: u16,
+) -> Instance {
+    object_create(
+        &cptestctx.external_client,
+        &instances_url(),
+        &instance_params(name, ncpus),
+    )
+    .await
+}
+
+/// Starts `count` more simulated sled agents, returning them along with the
+/// ids of every sled (including the one started by the test context)
+async fn start_more_sleds(
+    cptestctx: &ControlPlaneTestContext,
+    count: usize,
+) -> (Vec<sim::Server>, BTreeSet<Uuid>) {
+    let mut sled_ids = BTreeSet::new();
+    sled_ids.insert(SLED_AGENT_UUID.parse().unwrap());
+    let mut sled_agents = Vec::with_capacity(count);
+    for _ in 0..count {
+        let sa_id = Uuid::new_v4();
+        let log =
+            cptestctx.logctx.log.new(o!( "sled_id" => sa_id.to_string() ));
+        let addr = cptestctx.server.http_server_internal.local_addr();
+        sled_agents.push(start_sled_agent(log, addr, sa_id).await.unwrap());
+        sled_ids.insert(sa_id);
+    }
+    (sled_agents, sled_ids)
+}
+
+async fn instance_sled_id(
+    cptestctx: &ControlPlaneTestContext,
+    instance: &Instance,
+) -> Uuid {
+    cptestctx
+        .server
+        .apictx
+        .nexus
+        .instance_sled_id_by_id(&instance.identity.id)
+        .await
+        .unwrap()
+}
+
+#[nexus_test]
+async fn test_instance_placement_spread(cptestctx: &ControlPlaneTestContext) {
+    let client = &cptestctx.external_client;
+    create_organization(&client, ORGANIZATION_NAME).await;
+    create_project(&client, ORGANIZATION_NAME, PROJECT_NAME).await;
+    let (sled_agents, sled_ids) = start_more_sleds(cptestctx, 3).await;
+
+    // The test config uses the "spread" policy, so each Instance should land
+    // on a sled that has nothing else on it.
+    let mut used_sleds = BTreeSet::new();
+    for i in 0..sled_ids.len() {
+        let instance =
+            create_sized_instance(cptestctx, &format!("inst{}", i), 4).await;
+        let sled_id = instance_sled_id(cptestctx, &instance).await;
+        assert!(used_sleds.insert(sled_id), "sled used twice: {}", sled_id);
+    }
+    assert_eq!(used_sleds, sled_ids);
+
+    for sa in sled_agents {
+        sa.http_server.close().await.unwrap();
+    }
+}
+
+#[tokio::test]
+async fn test_instance_placement_pack() {
+    let mut config = load_test_config();
+    config.placement.policy = PlacementPolicy::Pack;
+    let cptestctx =
+        test_setup_with_config("test_instance_placement_pack", &mut config)
+            .await;
+    let client = &cptestctx.external_client;
+    create_organization(&client, ORGANIZATION_NAME).await;
+    create_project(&client, ORGANIZATION_NAME, PROJECT_NAME).await;
+    let (sled_agents, _) = start_more_sleds(&cptestctx, 2).await;
+
+    // Small Instances should all be packed onto the same sled.
+    let first = create_sized_instance(&cptestctx, "inst0", 4).await;
+    let packed_sled = instance_sled_id(&cptestctx, &first).await;
+    for i in 1..3 {
+        let instance =
+            create_sized_instance(&cptestctx, &format!("inst{}", i), 4).await;
+        assert_eq!(instance_sled_id(&cptestctx, &instance).await, packed_sled);
+    }
+
+    // An Instance that doesn't fit in what's left of that sled must go
+    // somewhere else.
+    let ncpus = u16::try_from(SLED_AGENT_HARDWARE_THREADS - 4).unwrap();
+    let big = create_sized_instance(&cptestctx, "big", ncpus).await;
+    assert_ne!(instance_sled_id(&cptestctx, &big).await, packed_sled);
+
+    for sa in sled_agents {
+        sa.http_server.close().await.unwrap();
+    }
+    cptestctx.teardown().await;
+}
+
+#[nexus_test]
+async fn test_instance_placement_capacity(cptestctx: &ControlPlaneTestContext) {
+    let client = &cptestctx.external_client;
+    let nexus = &cptestctx.server.apictx.nexus;
+    create_organization(&client, ORGANIZATION_NAME).await;
+    create_project(&client, ORGANIZATION_NAME, PROJECT_NAME).await;
+
+    // An Instance bigger than any sled cannot be placed at all.
+    let ncpus = u16::try_from(SLED_AGENT_HARDWARE_THREADS + 1).unwrap();
+    NexusRequest::new(
+        RequestBuilder::new(client, Method::POST, &instances_url())
+            .body(Some(&instance_params("too-big", ncpus)))
+            .expect_status(Some(StatusCode::SERVICE_UNAVAILABLE)),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap();
+
+    // Fill up the only sled.
+    let ncpus = u16::try_from(SLED_AGENT_HARDWARE_THREADS).unwrap();
+    let full = create_sized_instance(cptestctx, "full", ncpus).await;
+    NexusRequest::new(
+        RequestBuilder::new(client, Method::POST, &instances_url())
+            .body(Some(&instance_params("one-more", 1)))
+            .expect_status(Some(StatusCode::SERVICE_UNAVAILABLE)),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap();
+
+    // Once the Instance filling the sled is destroyed, its reservation goes
+    // away and there's room again.
+    let full_url = format!("{}/full", instances_url());
+    let sa = nexus.instance_sled_by_id(&full.identity.id).await.unwrap();
+    sa.instance_finish_transition(full.identity.id).await;
+    let stop_url = format!("{}/stop", full_url);
+    NexusRequest::new(
+        RequestBuilder::new(client, Method::POST, &stop_url)
+            .body(None as Option<&serde_json::Value>)
+            .expect_status(Some(StatusCode::ACCEPTED)),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap();
+    sa.instance_finish_transition(full.identity.id).await;
+    let stopped: Instance = NexusRequest::object_get(client, &full_url)
+        .authn_as(AuthnMode::PrivilegedUser)
+        .execute()
+        .await
+        .unwrap()
+        .parsed_body()
+        .unwrap();
+    assert_eq!(stopped.runtime.run_state, InstanceState::Stopped);
+    NexusRequest::object_delete(client, &full_url)
+        .authn_as(AuthnMode::PrivilegedUser)
+        .execute()
+        .await
+        .unwrap();
+
+    create_sized_instance(cptestctx, "one-more", 1).await;
+}
+
+fn affinity_groups_url() -> String {
+    format!(
+        "/organizations/{}/projects/{}/affinity-groups",
+        ORGANIZATION_NAME, PROJECT_NAME
+    )
+}
+
+async fn create_affinity_group(
+    cptestctx: &ControlPlaneTestContext,
+    name: &str,
+    policy: AffinityPolicy,
+) {
+    let _: AffinityGroup = object_create(
+        &cptestctx.external_client,
+        &affinity_groups_url(),
+        &params::AffinityGroupCreate {
+            identity: IdentityMetadataCreateParams {
+                name: name.parse().unwrap(),
+                description: String::from("a group"),
+                labels: Default::default(),
+            },
+            policy,
+        },
+    )
+    .await;
+}
+
+fn grouped_instance_params(
+    name: &str,
+    ncpus: u16,
+    group: &str,
+) -> params::InstanceCreate {
+    params::InstanceCreate {
+        affinity_group: Some(group.parse().unwrap()),
+        user_data: vec![],
+        ssh_public_keys: vec![],
+        network_interfaces: vec![],
+        ..instance_params(name, ncpus)
+    }
+}
+
+#[nexus_test]
+async fn test_instance_placement_affinity(cptestctx: &ControlPlaneTestContext) {
+    let client = &cptestctx.external_client;
+    create_organization(&client, ORGANIZATION_NAME).await;
+    create_project(&client, ORGANIZATION_NAME, PROJECT_NAME).await;
+    let (sled_agents, _) = start_more_sleds(cptestctx, 2).await;
+    create_affinity_group(cptestctx, "together", AffinityPolicy::Affinity)
+        .await;
+
+    // Every member of the group lands on the same sled, even though "spread"
+    // would otherwise put each of them on an empty one.
+    let first: Instance = object_create(
+        client,
+        &instances_url(),
+        &grouped_instance_params("inst0", 8, "together"),
+    )
+    .await;
+    let group_sled = instance_sled_id(cptestctx, &first).await;
+    for i in 1..4 {
+        let instance: Instance = object_create(
+            client,
+            &instances_url(),
+            &grouped_instance_params(&format!("inst{}", i), 8, "together"),
+        )
+        .await;
+        assert_eq!(instance_sled_id(cptestctx, &instance).await, group_sled);
+    }
+
+    // That sled is now full.  Another member can't go anywhere else.
+    NexusRequest::new(
+        RequestBuilder::new(client, Method::POST, &instances_url())
+            .body(Some(&grouped_instance_params("inst4", 1, "together")))
+            .expect_status(Some(StatusCode::SERVICE_UNAVAILABLE)),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap();
+
+    // Instances outside the group are unaffected.
+    let other = create_sized_instance(cptestctx, "other", 8).await;
+    assert_ne!(instance_sled_id(cptestctx, &other).await, group_sled);
+
+    for sa in sled_agents {
+        sa.http_server.close().await.unwrap();
+    }
+}
+
+#[tokio::test]
+async fn test_instance_placement_anti_affinity() {
+    // Use the "pack" policy to make sure the group is what keeps the
+    // Instances apart.
+    let mut config = load_test_config();
+    config.placement.policy = PlacementPolicy::Pack;
+    let cptestctx = test_setup_with_config(
+        "test_instance_placement_anti_affinity",
+        &mut config,
+    )
+    .await;
+    let client = &cptestctx.external_client;
+    create_organization(&client, ORGANIZATION_NAME).await;
+    create_project(&client, ORGANIZATION_NAME, PROJECT_NAME).await;
+    let (sled_agents, sled_ids) = start_more_sleds(&cptestctx, 2).await;
+    create_affinity_group(&cptestctx, "apart", AffinityPolicy::AntiAffinity)
+        .await;
+
+    // Each member of the group lands on a different sled, even though "pack"
+    // would otherwise put them all on the same one.  The members are created
+    // concurrently, so each placement has to account for the others while
+    // they are still in progress.
+    let members = (0..sled_ids.len())
+        .map(|i| grouped_instance_params(&format!("inst{}", i), 1, "apart"))
+        .collect::<Vec<_>>();
+    let url = instances_url();
+    let instances: Vec<Instance> = futures::future::join_all(
+        members
+            .iter()
+            .map(|params| object_create::<_, Instance>(client, &url, params)),
+    )
+    .await;
+    let mut used_sleds = BTreeSet::new();
+    for instance in &instances {
+        let sled_id = instance_sled_id(&cptestctx, instance).await;
+        assert!(used_sleds.insert(sled_id), "sled used twice: {}", sled_id);
+    }
+    assert_eq!(used_sleds, sled_ids);
+
+    // Once every sled runs a member, the group can't grow any more.
+    let error: HttpErrorResponseBody = NexusRequest::new(
+        RequestBuilder::new(client, Method::POST, &instances_url())
+            .body(Some(&grouped_instance_params("one-more", 1, "apart")))
+            .expect_status(Some(StatusCode::BAD_REQUEST)),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap()
+    .parsed_body()
+    .unwrap();
+    assert_eq!(
+        error.message,
+        "every sled already runs a member of the Instance's anti-affinity \
+        group"
+    );
+
+    for sa in sled_agents {
+        sa.http_server.close().await.unwrap();
+    }
+    cptestctx.teardown().await;
+}
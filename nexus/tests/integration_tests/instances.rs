@@ -11,6 +11,7 @@ use http::StatusCode;
 use nexus_test_utils::http_testing::AuthnMode;
 use nexus_test_utils::http_testing::NexusRequest;
 use nexus_test_utils::http_testing::RequestBuilder;
+use omicron_common::api::external::ByteCount;
 use omicron_common::api::external::IdentityMetadataCreateParams;
 use omicron_common::api::external::Instance;
 use omicron_common::api::external::InstanceCpuCount;
@@ -19,7 +20,9 @@ use omicron_common::api::external::Name;
 use omicron_common::api::external::NetworkInterface;
 use omicron_nexus::TestInterfaces as _;
 use omicron_nexus::{external_api::params, Nexus};
+use omicron_sled_agent::common::nocloud::NoCloudFile;
 use sled_agent_client::TestInterfaces as _;
+use std::convert::TryFrom;
 use std::sync::Arc;
 use uuid::Uuid;
 
@@ -32,6 +35,7 @@ use nexus_test_utils::resource_helpers::{
     create_instance, create_organization, create_project,
 };
 use nexus_test_utils::ControlPlaneTestContext;
+use nexus_test_utils::SLED_AGENT_HARDWARE_THREADS;
 use nexus_test_utils_macros::nexus_test;
 
 static ORGANIZATION_NAME: &str = "test-org";
@@ -137,10 +141,15 @@ async fn test_instances_create_reboot_halt(
                         "instance {:?}",
                         &instance.identity.name
                     ),
+                    labels: Default::default(),
                 },
                 ncpus: instance.ncpus,
                 memory: instance.memory,
                 hostname: instance.hostname.clone(),
+                affinity_group: None,
+                user_data: vec![],
+                ssh_public_keys: vec![],
+                network_interfaces: vec![],
             }))
             .expect_status(Some(StatusCode::BAD_REQUEST)),
     )
@@ -477,6 +486,101 @@ async fn test_instances_delete_fails_when_running_succeeds_when_stopped(
         .unwrap();
 }
 
+#[nexus_test]
+async fn test_instances_resize_only_when_stopped(
+    cptestctx: &ControlPlaneTestContext,
+) {
+    let client = &cptestctx.external_client;
+    let apictx = &cptestctx.server.apictx;
+    let nexus = &apictx.nexus;
+
+    create_organization(&client, ORGANIZATION_NAME).await;
+    let url_instances = format!(
+        "/organizations/{}/projects/{}/instances",
+        ORGANIZATION_NAME, PROJECT_NAME
+    );
+    let _ = create_project(&client, ORGANIZATION_NAME, PROJECT_NAME).await;
+
+    let instance_url = format!("{}/just-rainsticks", url_instances);
+    let instance = create_instance(
+        client,
+        ORGANIZATION_NAME,
+        PROJECT_NAME,
+        "just-rainsticks",
+    )
+    .await;
+    instance_simulate(nexus, &instance.identity.id).await;
+
+    // A running instance can't be resized.
+    let resize = params::InstanceUpdate {
+        ncpus: Some(InstanceCpuCount(8)),
+        memory: Some(ByteCount::from_gibibytes_u32(1)),
+        labels: None,
+    };
+    let error = instance_put_error(
+        client,
+        &instance_url,
+        &resize,
+        StatusCode::BAD_REQUEST,
+    )
+    .await;
+    assert_eq!(
+        error.message,
+        "cannot resize instance \"just-rainsticks\": instance is running; \
+         stop it first"
+    );
+
+    // Stop the instance.  Now it can be resized, but only to something that
+    // fits on a sled.  The instance's own reservation doesn't count against
+    // it.
+    let instance =
+        instance_post(&client, &instance_url, InstanceOp::Stop).await;
+    instance_simulate(nexus, &instance.identity.id).await;
+    let too_big = params::InstanceUpdate {
+        ncpus: Some(InstanceCpuCount(
+            u16::try_from(SLED_AGENT_HARDWARE_THREADS + 1).unwrap(),
+        )),
+        memory: None,
+        labels: None,
+    };
+    let error = instance_put_error(
+        client,
+        &instance_url,
+        &too_big,
+        StatusCode::SERVICE_UNAVAILABLE,
+    )
+    .await;
+    assert_eq!(
+        error.message,
+        format!(
+            "no sled has enough free capacity for an Instance with {} vCPUs \
+             and 268435456 bytes of memory",
+            SLED_AGENT_HARDWARE_THREADS + 1
+        )
+    );
+
+    let resized: Instance =
+        NexusRequest::object_put(client, &instance_url, Some(&resize))
+            .authn_as(AuthnMode::PrivilegedUser)
+            .execute()
+            .await
+            .unwrap()
+            .parsed_body()
+            .unwrap();
+    assert_eq!(resized.ncpus.0, 8);
+    assert_eq!(resized.memory, ByteCount::from_gibibytes_u32(1));
+    assert_eq!(resized.runtime.run_state, InstanceState::Stopped);
+
+    // The new size sticks once the instance is started again.
+    let instance =
+        instance_post(&client, &instance_url, InstanceOp::Start).await;
+    instance_simulate(nexus, &instance.identity.id).await;
+    let instance = instance_get(&client, &instance_url).await;
+    assert_eq!(instance.runtime.run_state, InstanceState::Running);
+    assert_eq!(instance.ncpus.0, 8);
+    assert_eq!(instance.memory, ByteCount::from_gibibytes_u32(1));
+}
+
 #[nexus_test]
 async fn test_instances_invalid_creation_returns_bad_request(
     cptestctx: &ControlPlaneTestContext,
@@ -530,6 +634,102 @@ async fn test_instances_invalid_creation_returns_bad_request(
         .starts_with("unable to parse body: invalid value: integer `-3`"));
 }
 
+#[nexus_test]
+async fn test_instance_guest_metadata(cptestctx: &ControlPlaneTestContext) {
+    let client = &cptestctx.external_client;
+    create_organization(&client, ORGANIZATION_NAME).await;
+    create_project(&client, ORGANIZATION_NAME, PROJECT_NAME).await;
+    let url_instances = format!(
+        "/organizations/{}/projects/{}/instances",
+        ORGANIZATION_NAME, PROJECT_NAME
+    );
+
+    /*
+     * The user data and SSH keys reach the sled agent, which serves them to the
+     * guest as its NoCloud seed.
+     */
+    let instance: serde_json::Value = NexusRequest::objects_post(
+        client,
+        &url_instances,
+        &serde_json::json!({
+            "name": "cloud-inited",
+            "description": "",
+            "ncpus": 1,
+            "memory": 1073741824,
+            "hostname": "cloud-inited",
+            "user_data": "I2Nsb3VkLWNvbmZpZwpwYWNrYWdlczogW2h0b3BdCg==",
+            "ssh_public_keys": ["ssh-ed25519 AAAAC3Nza user@example.com"],
+        }),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap()
+    .parsed_body()
+    .unwrap();
+    let instance_id: Uuid = instance["id"].as_str().unwrap().parse().unwrap();
+    let seed = cptestctx
+        .sled_agent
+        .sled_agent
+        .instance_nocloud_seed(instance_id)
+        .await
+        .unwrap();
+    assert_eq!(
+        seed.file(NoCloudFile::UserData),
+        b"#cloud-config\npackages: [htop]\n"
+    );
+    let meta_data: serde_json::Value =
+        serde_json::from_slice(seed.file(NoCloudFile::MetaData)).unwrap();
+    assert_eq!(meta_data["local-hostname"], "cloud-inited");
+    assert_eq!(
+        meta_data["public-keys"],
+        serde_json::json!(["ssh-ed25519 AAAAC3Nza user@example.com"])
+    );
+
+    /* Neither is ever shown back through the API. */
+    assert!(instance.get("user_data").is_none());
+    assert!(instance.get("ssh_public_keys").is_none());
+    let instance_url = format!("{}/cloud-inited", url_instances);
+    let instance: serde_json::Value =
+        NexusRequest::object_get(client, &instance_url)
+            .authn_as(AuthnMode::PrivilegedUser)
+            .execute()
+            .await
+            .unwrap()
+            .parsed_body()
+            .unwrap();
+    assert!(instance.get("user_data").is_none());
+    assert!(instance.get("ssh_public_keys").is_none());
+
+    /* User data must be valid base64, and not too large once decoded. */
+    let too_large = base64::encode(vec![0u8; params::MAX_USER_DATA_BYTES + 1]);
+    for user_data in &["not base64!", too_large.as_str()] {
+        let error = client
+            .make_request_with_body(
+                Method::POST,
+                &url_instances,
+                serde_json::json!({
+                    "name": "too-much-data",
+                    "description": "",
+                    "ncpus": 1,
+                    "memory": 1073741824,
+                    "hostname": "too-much-data",
+                    "user_data": user_data,
+                })
+                .to_string()
+                .into(),
+                StatusCode::BAD_REQUEST,
+            )
+            .await
+            .unwrap_err();
+        assert!(
+            error.message.starts_with("unable to parse body: user data"),
+            "{}",
+            error.message
+        );
+    }
+}
+
 async fn instance_get(
     client: &ClientTestContext,
     instance_url: &str,
@@ -543,6 +743,25 @@ async fn instance_get(
         .unwrap()
 }
 
+async fn instance_put_error(
+    client: &ClientTestContext,
+    instance_url: &str,
+    params: &params::InstanceUpdate,
+    status: StatusCode,
+) -> HttpErrorResponseBody {
+    NexusRequest::new(
+        RequestBuilder::new(client, Method::PUT, instance_url)
+            .body(Some(params))
+            .expect_status(Some(status)),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap()
+    .parsed_body()
+    .unwrap()
+}
+
 async fn instances_list(
     client: &ClientTestContext,
     instances_url: &str,
@@ -0,0 +1,157 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Tests that Nexus keeps the internal DNS zone up to date
+
+use dropshot::test_util::ClientTestContext;
+use http::method::Method;
+use http::StatusCode;
+use internal_dns::wire::{Message, Rcode, RecordData, TYPE_A, TYPE_SRV};
+use nexus_test_utils::http_testing::AuthnMode;
+use nexus_test_utils::http_testing::NexusRequest;
+use nexus_test_utils::http_testing::RequestBuilder;
+use nexus_test_utils::resource_helpers::{create_organization, create_project};
+use nexus_test_utils::ControlPlaneTestContext;
+use nexus_test_utils_macros::nexus_test;
+use omicron_common::api::external::{
+    ByteCount, IdentityMetadataCreateParams, IdentityMetadataUpdateParams,
+    Instance, InstanceCpuCount,
+};
+use omicron_nexus::external_api::params;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::net::UdpSocket;
+
+const ORG_NAME: &str = "test-org";
+const PROJECT_NAME: &str = "springfield-squidport";
+
+/**
+ * Sends a query for `name` to the DNS server at `server` and returns the
+ * response.
+ */
+async fn query(server: SocketAddr, name: &str, qtype: u16) -> Message {
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let query = Message::query(1, name, qtype);
+    socket.send_to(&query.to_bytes().unwrap(), server).await.unwrap();
+    let mut buf = [0u8; 512];
+    let (len, _) = tokio::time::timeout(
+        Duration::from_secs(10),
+        socket.recv_from(&mut buf),
+    )
+    .await
+    .expect("timed out waiting for DNS response")
+    .unwrap();
+    Message::parse(&buf[..len]).unwrap()
+}
+
+async fn instance_create(client: &ClientTestContext, name: &str) -> Instance {
+    NexusRequest::objects_post(
+        client,
+        &format!(
+            "/organizations/{}/projects/{}/instances",
+            ORG_NAME, PROJECT_NAME
+        ),
+        &params::InstanceCreate {
+            identity: IdentityMetadataCreateParams {
+                name: name.parse().unwrap(),
+                description: format!("instance {:?}", name),
+                labels: Default::default(),
+            },
+            ncpus: InstanceCpuCount(1),
+            memory: ByteCount::from_mebibytes_u32(256),
+            hostname: String::from(name),
+            affinity_group: None,
+            user_data: vec![],
+            ssh_public_keys: vec![],
+            network_interfaces: vec![params::NetworkInterfaceCreate {
+                identity: IdentityMetadataCreateParams {
+                    name: "net0".parse().unwrap(),
+                    description: String::from("the interface"),
+                    labels: Default::default(),
+                },
+                vpc_name: "default".parse().unwrap(),
+                subnet_name: "default".parse().unwrap(),
+                ip: Some("172.30.0.10".parse().unwrap()),
+            }],
+        },
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap()
+    .parsed_body()
+    .unwrap()
+}
+
+#[nexus_test]
+async fn test_internal_dns_zone(cptestctx: &ControlPlaneTestContext) {
+    let client = &cptestctx.external_client;
+    let dns_address = cptestctx.internal_dns.dns_address;
+
+    /*
+     * Nexus registered itself on startup, so its SRV record points at its
+     * internal API.
+     */
+    let response = query(dns_address, "_nexus._tcp.internal", TYPE_SRV).await;
+    assert_eq!(response.rcode, Rcode::NoError);
+    assert_eq!(response.answers.len(), 1);
+    match &response.answers[0].data {
+        RecordData::Srv { port, target, .. } => {
+            assert_eq!(*port, cptestctx.internal_client.bind_address.port());
+            assert!(target.ends_with(".host.internal"), "target {}", target);
+            let response = query(dns_address, target, TYPE_A).await;
+            assert_eq!(
+                response.answers.iter().map(|a| &a.data).collect::<Vec<_>>(),
+                vec![&RecordData::A("127.0.0.1".parse().unwrap())]
+            );
+        }
+        data => panic!("expected SRV record, found {:?}", data),
+    }
+
+    /* Creating an Instance adds a name for it in its VPC. */
+    create_organization(client, ORG_NAME).await;
+    create_project(client, ORG_NAME, PROJECT_NAME).await;
+    let name = format!("web.default.{}.internal", PROJECT_NAME);
+    let response = query(dns_address, &name, TYPE_A).await;
+    assert_eq!(response.rcode, Rcode::NxDomain);
+    instance_create(client, "web").await;
+    let response = query(dns_address, &name, TYPE_A).await;
+    assert_eq!(response.rcode, Rcode::NoError);
+    assert!(response.authoritative);
+    assert_eq!(
+        response.answers.iter().map(|a| &a.data).collect::<Vec<_>>(),
+        vec![&RecordData::A("172.30.0.10".parse().unwrap())]
+    );
+
+    /* Changing the VPC's DNS name moves the Instance's name. */
+    NexusRequest::new(
+        RequestBuilder::new(
+            client,
+            Method::PUT,
+            &format!(
+                "/organizations/{}/projects/{}/vpcs/default",
+                ORG_NAME, PROJECT_NAME
+            ),
+        )
+        .body(Some(&params::VpcUpdate {
+            identity: IdentityMetadataUpdateParams {
+                name: None,
+                description: None,
+                labels: None,
+            },
+            dns_name: Some("frontend".parse().unwrap()),
+        }))
+        .expect_status(Some(StatusCode::NO_CONTENT)),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap();
+    let response = query(dns_address, &name, TYPE_A).await;
+    assert_eq!(response.rcode, Rcode::NxDomain);
+    let name = format!("web.frontend.{}.internal", PROJECT_NAME);
+    let response = query(dns_address, &name, TYPE_A).await;
+    assert_eq!(response.rcode, Rcode::NoError);
+    assert_eq!(response.answers.len(), 1);
+}
@@ -0,0 +1,412 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Tests labels on resources and filtering lists of resources by their labels
+
+use dropshot::test_util::ClientTestContext;
+use dropshot::HttpErrorResponseBody;
+use http::method::Method;
+use http::StatusCode;
+use nexus_test_utils::http_testing::AuthnMode;
+use nexus_test_utils::http_testing::NexusRequest;
+use nexus_test_utils::http_testing::RequestBuilder;
+use nexus_test_utils::resource_helpers::create_organization;
+use nexus_test_utils::resource_helpers::create_project;
+use nexus_test_utils::resource_helpers::object_create;
+use nexus_test_utils::resource_helpers::objects_list_page_authz;
+use nexus_test_utils::resource_helpers::DiskTest;
+use nexus_test_utils::ControlPlaneTestContext;
+use nexus_test_utils_macros::nexus_test;
+use omicron_common::api::external::ByteCount;
+use omicron_common::api::external::Disk;
+use omicron_common::api::external::IdentityMetadataCreateParams;
+use omicron_common::api::external::IdentityMetadataUpdateParams;
+use omicron_common::api::external::Instance;
+use omicron_common::api::external::InstanceCpuCount;
+use omicron_common::api::external::Ipv4Net;
+use omicron_common::api::external::Labels;
+use omicron_nexus::external_api::params;
+use omicron_nexus::external_api::views::Organization;
+use omicron_nexus::external_api::views::Project;
+use omicron_nexus::external_api::views::Vpc;
+
+const ORG_NAME: &str = "test-org";
+const PROJECT_NAME: &str = "springfield-squidport";
+
+fn labels(pairs: &[(&str, &str)]) -> Labels {
+    let map = pairs
+        .iter()
+        .map(|(k, v)| (k.to_string(), serde_json::Value::from(*v)))
+        .collect::<serde_json::Map<_, _>>();
+    serde_json::from_value(serde_json::Value::Object(map)).unwrap()
+}
+
+fn identity(
+    name: &str,
+    pairs: &[(&str, &str)],
+) -> IdentityMetadataCreateParams {
+    IdentityMetadataCreateParams {
+        name: name.parse().unwrap(),
+        description: String::from("labeled"),
+        labels: labels(pairs),
+    }
+}
+
+/// Returns the names of the items listed at `url`
+async fn names_matching<T>(
+    client: &ClientTestContext,
+    url: &str,
+    name: impl Fn(&T) -> String,
+) -> Vec<String>
+where
+    T: serde::de::DeserializeOwned,
+{
+    objects_list_page_authz::<T>(client, url)
+        .await
+        .items
+        .iter()
+        .map(name)
+        .collect()
+}
+
+#[nexus_test]
+async fn test_organization_and_project_labels(
+    cptestctx: &ControlPlaneTestContext,
+) {
+    let client = &cptestctx.external_client;
+
+    let org: Organization = object_create(
+        client,
+        "/organizations",
+        &params::OrganizationCreate {
+            identity: identity(ORG_NAME, &[("cost-center", "1234")]),
+        },
+    )
+    .await;
+    assert_eq!(org.labels, labels(&[("cost-center", "1234")]));
+    create_organization(client, "other-org").await;
+
+    let org_name = |o: &Organization| o.identity.name.to_string();
+    assert_eq!(
+        names_matching(client, "/organizations", org_name).await,
+        vec!["other-org", ORG_NAME]
+    );
+    assert_eq!(
+        names_matching(
+            client,
+            "/organizations?label_selector=cost-center%3D1234",
+            org_name
+        )
+        .await,
+        vec![ORG_NAME]
+    );
+    assert_eq!(
+        names_matching(
+            client,
+            "/organizations?sort_by=id-ascending&label_selector=%21cost-center",
+            org_name
+        )
+        .await,
+        vec!["other-org"]
+    );
+
+    // Updating the labels replaces all of them.
+    let org_url = format!("/organizations/{}", ORG_NAME);
+    let org: Organization = NexusRequest::object_put(
+        client,
+        &org_url,
+        Some(&params::OrganizationUpdate {
+            identity: IdentityMetadataUpdateParams {
+                name: None,
+                description: None,
+                labels: Some(labels(&[("owner", "rainsticks")])),
+            },
+        }),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap()
+    .parsed_body()
+    .unwrap();
+    assert_eq!(org.labels, labels(&[("owner", "rainsticks")]));
+    assert_eq!(
+        names_matching(
+            client,
+            "/organizations?label_selector=cost-center",
+            org_name
+        )
+        .await,
+        Vec::<String>::new()
+    );
+
+    // Leaving them out of an update leaves them alone.
+    let org: Organization = NexusRequest::object_put(
+        client,
+        &org_url,
+        Some(&params::OrganizationUpdate {
+            identity: IdentityMetadataUpdateParams {
+                name: None,
+                description: Some(String::from("relabeled")),
+                labels: None,
+            },
+        }),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap()
+    .parsed_body()
+    .unwrap();
+    assert_eq!(org.labels, labels(&[("owner", "rainsticks")]));
+
+    let projects_url = format!("{}/projects", org_url);
+    for (name, env) in &[("prod-project", "prod"), ("dev-project", "dev")] {
+        let project: Project = object_create(
+            client,
+            &projects_url,
+            &params::ProjectCreate {
+                identity: identity(name, &[("env", *env)]),
+            },
+        )
+        .await;
+        assert_eq!(project.labels.get("env"), Some(*env));
+    }
+    create_project(client, ORG_NAME, PROJECT_NAME).await;
+
+    let project_name = |p: &Project| p.identity.name.to_string();
+    assert_eq!(
+        names_matching(
+            client,
+            &format!("{}?label_selector=env%21%3Dprod", projects_url),
+            project_name
+        )
+        .await,
+        vec!["dev-project", PROJECT_NAME]
+    );
+    assert_eq!(
+        names_matching(
+            client,
+            &format!("{}?label_selector=env,env%21%3Dprod", projects_url),
+            project_name
+        )
+        .await,
+        vec!["dev-project"]
+    );
+}
+
+#[nexus_test]
+async fn test_instance_disk_and_vpc_labels(
+    cptestctx: &ControlPlaneTestContext,
+) {
+    let client = &cptestctx.external_client;
+    DiskTest::new(&cptestctx).await;
+    create_organization(client, ORG_NAME).await;
+    create_project(client, ORG_NAME, PROJECT_NAME).await;
+    let project_url =
+        format!("/organizations/{}/projects/{}", ORG_NAME, PROJECT_NAME);
+
+    // Instances
+    let instances_url = format!("{}/instances", project_url);
+    for (name, tier) in &[("web1", "web"), ("web2", "web"), ("db1", "db")] {
+        let _: Instance = object_create(
+            client,
+            &instances_url,
+            &params::InstanceCreate {
+                identity: identity(name, &[("tier", *tier)]),
+                ncpus: InstanceCpuCount(1),
+                memory: ByteCount::from_mebibytes_u32(256),
+                hostname: name.to_string(),
+                affinity_group: None,
+                user_data: vec![],
+                ssh_public_keys: vec![],
+                network_interfaces: vec![],
+            },
+        )
+        .await;
+    }
+    let instance_name = |i: &Instance| i.identity.name.to_string();
+    let web_url = format!("{}?label_selector=tier%3Dweb", instances_url);
+    assert_eq!(
+        names_matching(client, &web_url, instance_name).await,
+        vec!["web1", "web2"]
+    );
+
+    // The selector is carried along from one page to the next.
+    let web_instances = NexusRequest::iter_collection_authn::<Instance>(
+        client,
+        &instances_url,
+        "label_selector=tier%3Dweb",
+        Some(1),
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        web_instances.all_items.iter().map(instance_name).collect::<Vec<_>>(),
+        vec!["web1", "web2"]
+    );
+
+    // Labels can be changed while the Instance is running.
+    let instance: Instance = NexusRequest::object_put(
+        client,
+        &format!("{}/web2", instances_url),
+        Some(&params::InstanceUpdate {
+            ncpus: None,
+            memory: None,
+            labels: Some(labels(&[("tier", "db")])),
+        }),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap()
+    .parsed_body()
+    .unwrap();
+    assert_eq!(instance.labels, labels(&[("tier", "db")]));
+    assert_eq!(
+        names_matching(client, &web_url, instance_name).await,
+        vec!["web1"]
+    );
+
+    // Disks
+    let disks_url = format!("{}/disks", project_url);
+    let disk: Disk = object_create(
+        client,
+        &disks_url,
+        &params::DiskCreate {
+            identity: identity("data", &[("backup", "nightly")]),
+            snapshot_id: None,
+            size: ByteCount::from_gibibytes_u32(1),
+        },
+    )
+    .await;
+    assert_eq!(disk.labels, labels(&[("backup", "nightly")]));
+    let disk_name = |d: &Disk| d.identity.name.to_string();
+    assert_eq!(
+        names_matching(
+            client,
+            &format!("{}?label_selector=backup", disks_url),
+            disk_name
+        )
+        .await,
+        vec!["data"]
+    );
+    let disk: Disk = NexusRequest::object_put(
+        client,
+        &format!("{}/data", disks_url),
+        Some(&params::DiskUpdate { labels: Some(labels(&[])) }),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap()
+    .parsed_body()
+    .unwrap();
+    assert!(disk.labels.is_empty());
+    assert_eq!(
+        names_matching(
+            client,
+            &format!("{}?label_selector=backup", disks_url),
+            disk_name
+        )
+        .await,
+        Vec::<String>::new()
+    );
+
+    // VPCs
+    let vpcs_url = format!("{}/vpcs", project_url);
+    let vpc: Vpc = object_create(
+        client,
+        &vpcs_url,
+        &params::VpcCreate {
+            identity: identity("labeled-vpc", &[("env", "prod")]),
+            ipv6_prefix: None,
+            dns_name: "labeled".parse().unwrap(),
+        },
+    )
+    .await;
+    assert_eq!(vpc.labels, labels(&[("env", "prod")]));
+    let vpc_name = |v: &Vpc| v.identity.name.to_string();
+    assert_eq!(
+        names_matching(
+            client,
+            &format!("{}?label_selector=env%3Dprod", vpcs_url),
+            vpc_name
+        )
+        .await,
+        vec!["labeled-vpc"]
+    );
+    assert_eq!(
+        names_matching(
+            client,
+            &format!("{}?label_selector=%21env", vpcs_url),
+            vpc_name
+        )
+        .await,
+        vec!["default"]
+    );
+}
+
+#[nexus_test]
+async fn test_labels_invalid(cptestctx: &ControlPlaneTestContext) {
+    let client = &cptestctx.external_client;
+    create_organization(client, ORG_NAME).await;
+
+    // Label keys may not contain uppercase letters, in either a resource's
+    // labels or a selector.
+    NexusRequest::new(
+        RequestBuilder::new(client, Method::POST, "/organizations")
+            .body(Some(&serde_json::json!({
+                "name": "bad-labels",
+                "description": "",
+                "labels": { "Env": "prod" },
+            })))
+            .expect_status(Some(StatusCode::BAD_REQUEST)),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap();
+
+    NexusRequest::expect_failure(
+        client,
+        StatusCode::BAD_REQUEST,
+        Method::GET,
+        "/organizations?label_selector=Env%3Dprod",
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap();
+
+    // Resources without labels can't be given any.
+    create_project(client, ORG_NAME, PROJECT_NAME).await;
+    let error: HttpErrorResponseBody = NexusRequest::new(
+        RequestBuilder::new(
+            client,
+            Method::POST,
+            &format!(
+                "/organizations/{}/projects/{}/vpcs/default/subnets",
+                ORG_NAME, PROJECT_NAME
+            ),
+        )
+        .body(Some(&params::VpcSubnetCreate {
+            identity: identity("labeled-subnet", &[("env", "prod")]),
+            ipv4_block: Ipv4Net("10.1.0.0/24".parse().unwrap()),
+            ipv6_block: None,
+        }))
+        .expect_status(Some(StatusCode::BAD_REQUEST)),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap()
+    .parsed_body()
+    .unwrap();
+    assert_eq!(
+        error.message,
+        "unsupported value for \"labels\": resources of type vpc-subnet have \
+         no labels"
+    );
+}
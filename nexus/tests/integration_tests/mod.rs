@@ -3,21 +3,41 @@
 //! See the driver in the parent directory for how and why this is structured
 //! the way it is.
 
+mod affinity_groups;
+mod api_versions;
+mod audit_log;
 mod authn_http;
 mod basic;
 mod commands;
 mod console_api;
 mod datasets;
 mod disks;
+mod etags;
+mod events;
+mod floating_ips;
+mod idempotency;
+mod instance_network_interfaces;
+mod instance_placement;
 mod instances;
+mod internal_dns;
+mod labels;
+mod operations;
 mod organizations;
 mod oximeter;
+mod policies;
 mod projects;
+mod quotas;
 mod roles_builtin;
 mod router_routes;
+mod serial_console;
+mod snapshots;
+mod ssh_keys;
 mod subnet_allocation;
+mod throttle;
 mod timeseries;
+mod tokens;
 mod unauthorized;
+mod users;
 mod users_builtin;
 mod vpc_firewall;
 mod vpc_routers;
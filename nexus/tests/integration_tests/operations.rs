@@ -0,0 +1,222 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Tests long-running requests made with `Prefer: respond-async` and the
+//! Operations that carry them out
+
+use dropshot::test_util::ClientTestContext;
+use http::method::Method;
+use http::StatusCode;
+use nexus_test_utils::http_testing::AuthnMode;
+use nexus_test_utils::http_testing::NexusRequest;
+use nexus_test_utils::http_testing::RequestBuilder;
+use nexus_test_utils::http_testing::TestResponse;
+use nexus_test_utils::resource_helpers::create_instance;
+use nexus_test_utils::resource_helpers::create_organization;
+use nexus_test_utils::resource_helpers::create_project;
+use nexus_test_utils::resource_helpers::objects_list_page_authz;
+use nexus_test_utils::ControlPlaneTestContext;
+use nexus_test_utils_macros::nexus_test;
+use omicron_common::api::external::ByteCount;
+use omicron_common::api::external::IdentityMetadataCreateParams;
+use omicron_common::api::external::Instance;
+use omicron_common::api::external::InstanceCpuCount;
+use omicron_nexus::external_api::params;
+use omicron_nexus::external_api::views::AuditLogEntry;
+use omicron_nexus::external_api::views::Operation;
+use omicron_nexus::external_api::views::OperationStatus;
+use omicron_test_utils::dev::poll::{wait_for_condition, CondCheckError};
+use std::time::Duration;
+
+const ORG_NAME: &str = "test-org";
+const PROJECT_NAME: &str = "springfield-squidport";
+
+fn get_project_url() -> String {
+    format!("/organizations/{}/projects/{}", ORG_NAME, PROJECT_NAME)
+}
+
+fn get_operations_url() -> String {
+    format!("{}/operations", get_project_url())
+}
+
+#[nexus_test]
+async fn test_operation_instance_create(cptestctx: &ControlPlaneTestContext) {
+    let client = &cptestctx.external_client;
+    create_organization(client, ORG_NAME).await;
+    let project = create_project(client, ORG_NAME, PROJECT_NAME).await;
+
+    // There are no operations yet.
+    let operations =
+        objects_list_page_authz::<Operation>(client, &get_operations_url())
+            .await;
+    assert_eq!(operations.items.len(), 0);
+
+    // Asking not to wait gets back a 202 with the operation creating the
+    // instance.
+    let instances_url = format!("{}/instances", get_project_url());
+    let create_params = params::InstanceCreate {
+        identity: IdentityMetadataCreateParams {
+            name: "just-rainsticks".parse().unwrap(),
+            description: String::from("sells rainsticks"),
+            labels: Default::default(),
+        },
+        ncpus: InstanceCpuCount(4),
+        memory: ByteCount::from_mebibytes_u32(256),
+        hostname: String::from("rainsticks"),
+        affinity_group: None,
+        user_data: vec![],
+        ssh_public_keys: vec![],
+        network_interfaces: vec![],
+    };
+    let response =
+        post_respond_async(client, &instances_url, &create_params).await;
+    let operation: Operation = response.parsed_body().unwrap();
+    let operation_url = format!("{}/{}", get_operations_url(), operation.id);
+    assert_eq!(
+        response.headers.get(http::header::LOCATION).unwrap(),
+        operation_url.as_str()
+    );
+    assert_eq!(operation.operation_type, "instance-create");
+    assert_eq!(operation.project_id, project.identity.id);
+    assert_eq!(operation.target.resource_type, "instance");
+    assert_eq!(operation.error, None);
+
+    // The audit log records the 202 that the client got.
+    let audit_log = NexusRequest::iter_collection_authn::<AuditLogEntry>(
+        client,
+        "/audit-log",
+        "",
+        None,
+    )
+    .await
+    .unwrap()
+    .all_items;
+    let entry =
+        audit_log.iter().rev().find(|e| e.http_path == instances_url).unwrap();
+    assert_eq!(entry.http_status, 202);
+
+    // Once the operation has finished, it says which instance it created.
+    let operation = operation_wait(client, &operation_url).await;
+    assert_eq!(operation.status, OperationStatus::Succeeded);
+    assert!(operation.steps_completed > 0);
+    assert_eq!(operation.error, None);
+    let instances =
+        objects_list_page_authz::<Instance>(client, &instances_url).await;
+    assert_eq!(instances.items.len(), 1);
+    assert_eq!(operation.target.id, Some(instances.items[0].identity.id));
+
+    // Without `Prefer: respond-async`, the request waits for the saga as
+    // before, but it's still recorded as an operation.
+    let instance =
+        create_instance(client, ORG_NAME, PROJECT_NAME, "other-instance").await;
+    let operations =
+        objects_list_page_authz::<Operation>(client, &get_operations_url())
+            .await;
+    assert_eq!(operations.items.len(), 2);
+    let other = operations.items.iter().find(|o| o.id != operation.id).unwrap();
+    assert_eq!(other.operation_type, "instance-create");
+    assert_eq!(other.status, OperationStatus::Succeeded);
+    assert_eq!(other.target.id, Some(instance.identity.id));
+
+    // Operations are only visible to users who can see the Project.
+    NexusRequest::expect_failure(
+        client,
+        StatusCode::NOT_FOUND,
+        Method::GET,
+        &operation_url,
+    )
+    .authn_as(AuthnMode::UnprivilegedUser)
+    .execute()
+    .await
+    .unwrap();
+    NexusRequest::expect_failure(
+        client,
+        StatusCode::NOT_FOUND,
+        Method::GET,
+        &format!("/organizations/{}/projects/other/operations", ORG_NAME),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap();
+}
+
+#[nexus_test]
+async fn test_operation_failed(cptestctx: &ControlPlaneTestContext) {
+    let client = &cptestctx.external_client;
+    create_organization(client, ORG_NAME).await;
+    create_project(client, ORG_NAME, PROJECT_NAME).await;
+
+    // Without any datasets, there's nowhere to put a disk's regions, so the
+    // saga creating it fails.
+    let disks_url = format!("{}/disks", get_project_url());
+    let create_params = params::DiskCreate {
+        identity: IdentityMetadataCreateParams {
+            name: "just-rainsticks".parse().unwrap(),
+            description: String::from("sells rainsticks"),
+            labels: Default::default(),
+        },
+        snapshot_id: None,
+        size: ByteCount::from_gibibytes_u32(1),
+    };
+    let operation: Operation =
+        post_respond_async(client, &disks_url, &create_params)
+            .await
+            .parsed_body()
+            .unwrap();
+    assert_eq!(operation.operation_type, "disk-create");
+    let operation_url = format!("{}/{}", get_operations_url(), operation.id);
+    let operation = operation_wait(client, &operation_url).await;
+    assert_eq!(operation.status, OperationStatus::Failed);
+    assert!(operation.error.is_some());
+    assert_eq!(operation.target.id, None);
+}
+
+async fn post_respond_async<B: serde::Serialize>(
+    client: &ClientTestContext,
+    url: &str,
+    body: &B,
+) -> TestResponse {
+    NexusRequest::new(
+        RequestBuilder::new(client, Method::POST, url)
+            .header("prefer", "respond-async")
+            .body(Some(body))
+            .expect_status(Some(StatusCode::ACCEPTED)),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap()
+}
+
+/// Polls the operation at `operation_url` until it's finished
+async fn operation_wait(
+    client: &ClientTestContext,
+    operation_url: &str,
+) -> Operation {
+    wait_for_condition(
+        || async {
+            let operation: Operation =
+                NexusRequest::object_get(client, operation_url)
+                    .authn_as(AuthnMode::PrivilegedUser)
+                    .execute()
+                    .await
+                    .unwrap()
+                    .parsed_body()
+                    .unwrap();
+            match operation.status {
+                OperationStatus::Succeeded | OperationStatus::Failed => {
+                    Ok(operation)
+                }
+                OperationStatus::Running | OperationStatus::Unwinding => {
+                    Err(CondCheckError::<std::io::Error>::NotYet)
+                }
+            }
+        },
+        &Duration::from_millis(50),
+        &Duration::from_secs(30),
+    )
+    .await
+    .expect("operation did not finish")
+}
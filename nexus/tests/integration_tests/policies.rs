@@ -0,0 +1,218 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Tests granting roles on Organizations and Projects through their policies
+
+use dropshot::test_util::ClientTestContext;
+use dropshot::HttpErrorResponseBody;
+use http::method::Method;
+use http::StatusCode;
+use nexus_test_utils::http_testing::AuthnMode;
+use nexus_test_utils::http_testing::NexusRequest;
+use nexus_test_utils::http_testing::RequestBuilder;
+use nexus_test_utils::resource_helpers::create_organization;
+use nexus_test_utils::resource_helpers::create_project;
+use nexus_test_utils::ControlPlaneTestContext;
+use nexus_test_utils_macros::nexus_test;
+use omicron_common::api::external::RoleName;
+use omicron_nexus::authn::USER_TEST_UNPRIVILEGED;
+use omicron_nexus::external_api::views::{Policy, RoleAssignment};
+use uuid::Uuid;
+
+const ORG_NAME: &str = "test-org";
+const PROJECT_NAME: &str = "springfield-squidport";
+
+#[nexus_test]
+async fn test_project_policy(cptestctx: &ControlPlaneTestContext) {
+    let client = &cptestctx.external_client;
+    create_organization(&client, ORG_NAME).await;
+    create_project(client, ORG_NAME, PROJECT_NAME).await;
+    let project_url =
+        format!("/organizations/{}/projects/{}", ORG_NAME, PROJECT_NAME);
+    let policy_url = format!("{}/policy", project_url);
+
+    // To start with, nobody has been granted any roles, so the unprivileged
+    // user can't even see the Project.
+    let policy = policy_get(client, &policy_url).await;
+    assert_eq!(policy.role_assignments, vec![]);
+    NexusRequest::expect_failure(
+        client,
+        StatusCode::NOT_FOUND,
+        Method::GET,
+        &project_url,
+    )
+    .authn_as(AuthnMode::UnprivilegedUser)
+    .execute()
+    .await
+    .unwrap();
+
+    // Make them a viewer.  Duplicate assignments are collapsed.
+    let viewer = RoleAssignment {
+        user_id: USER_TEST_UNPRIVILEGED.id,
+        role_name: RoleName::new("project", "viewer"),
+    };
+    let policy = policy_put(
+        client,
+        &policy_url,
+        &Policy { role_assignments: vec![viewer.clone(), viewer.clone()] },
+    )
+    .await;
+    assert_eq!(policy.role_assignments, vec![viewer.clone()]);
+    assert_eq!(policy_get(client, &policy_url).await, policy);
+
+    // Now they can see the Project, but they still can't change it.
+    NexusRequest::object_get(client, &project_url)
+        .authn_as(AuthnMode::UnprivilegedUser)
+        .execute()
+        .await
+        .unwrap();
+    NexusRequest::expect_failure(
+        client,
+        StatusCode::FORBIDDEN,
+        Method::DELETE,
+        &project_url,
+    )
+    .authn_as(AuthnMode::UnprivilegedUser)
+    .execute()
+    .await
+    .unwrap();
+
+    // Nor can they change the policy itself.
+    NexusRequest::new(
+        RequestBuilder::new(client, Method::PUT, &policy_url)
+            .body(Some(&Policy { role_assignments: vec![] }))
+            .expect_status(Some(StatusCode::FORBIDDEN)),
+    )
+    .authn_as(AuthnMode::UnprivilegedUser)
+    .execute()
+    .await
+    .unwrap();
+
+    // Only roles that apply to Projects can be granted on them, and only to
+    // users that exist.
+    let error = policy_put_error(
+        client,
+        &policy_url,
+        &Policy {
+            role_assignments: vec![RoleAssignment {
+                user_id: USER_TEST_UNPRIVILEGED.id,
+                role_name: RoleName::new("organization", "admin"),
+            }],
+        },
+    )
+    .await;
+    assert_eq!(
+        error.message,
+        "unsupported value for \"role_name\": role \"organization.admin\" \
+         cannot be granted on resources of type \"project\""
+    );
+    let bogus_user = Uuid::new_v4();
+    let error = policy_put_error(
+        client,
+        &policy_url,
+        &Policy {
+            role_assignments: vec![RoleAssignment {
+                user_id: bogus_user,
+                role_name: RoleName::new("project", "viewer"),
+            }],
+        },
+    )
+    .await;
+    assert_eq!(
+        error.message,
+        format!(
+            "unsupported value for \"user_id\": no such user: {}",
+            bogus_user
+        )
+    );
+    assert_eq!(policy_get(client, &policy_url).await, policy);
+
+    // Emptying the policy takes the role away again.
+    let policy =
+        policy_put(client, &policy_url, &Policy { role_assignments: vec![] })
+            .await;
+    assert_eq!(policy.role_assignments, vec![]);
+    NexusRequest::expect_failure(
+        client,
+        StatusCode::NOT_FOUND,
+        Method::GET,
+        &project_url,
+    )
+    .authn_as(AuthnMode::UnprivilegedUser)
+    .execute()
+    .await
+    .unwrap();
+}
+
+#[nexus_test]
+async fn test_organization_policy(cptestctx: &ControlPlaneTestContext) {
+    let client = &cptestctx.external_client;
+    create_organization(&client, ORG_NAME).await;
+    create_project(client, ORG_NAME, PROJECT_NAME).await;
+    let policy_url = format!("/organizations/{}/policy", ORG_NAME);
+
+    // An Organization admin can do anything with the Organization's Projects.
+    let admin = RoleAssignment {
+        user_id: USER_TEST_UNPRIVILEGED.id,
+        role_name: RoleName::new("organization", "admin"),
+    };
+    let policy = policy_put(
+        client,
+        &policy_url,
+        &Policy { role_assignments: vec![admin.clone()] },
+    )
+    .await;
+    assert_eq!(policy.role_assignments, vec![admin]);
+    assert_eq!(policy_get(client, &policy_url).await, policy);
+    NexusRequest::object_delete(
+        client,
+        &format!("/organizations/{}/projects/{}", ORG_NAME, PROJECT_NAME),
+    )
+    .authn_as(AuthnMode::UnprivilegedUser)
+    .execute()
+    .await
+    .unwrap();
+}
+
+async fn policy_get(client: &ClientTestContext, url: &str) -> Policy {
+    NexusRequest::object_get(client, url)
+        .authn_as(AuthnMode::PrivilegedUser)
+        .execute()
+        .await
+        .unwrap()
+        .parsed_body()
+        .unwrap()
+}
+
+async fn policy_put(
+    client: &ClientTestContext,
+    url: &str,
+    policy: &Policy,
+) -> Policy {
+    NexusRequest::object_put(client, url, Some(policy))
+        .authn_as(AuthnMode::PrivilegedUser)
+        .execute()
+        .await
+        .unwrap()
+        .parsed_body()
+        .unwrap()
+}
+
+async fn policy_put_error(
+    client: &ClientTestContext,
+    url: &str,
+    policy: &Policy,
+) -> HttpErrorResponseBody {
+    NexusRequest::new(
+        RequestBuilder::new(client, Method::PUT, url)
+            .body(Some(policy))
+            .expect_status(Some(StatusCode::BAD_REQUEST)),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap()
+    .parsed_body()
+    .unwrap()
+}
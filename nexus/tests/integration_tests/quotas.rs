@@ -0,0 +1,233 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Tests resource quotas on Organizations and Projects
+
+use dropshot::test_util::ClientTestContext;
+use dropshot::HttpErrorResponseBody;
+use http::method::Method;
+use http::StatusCode;
+use nexus_test_utils::http_testing::AuthnMode;
+use nexus_test_utils::http_testing::NexusRequest;
+use nexus_test_utils::http_testing::RequestBuilder;
+use nexus_test_utils::resource_helpers::create_disk;
+use nexus_test_utils::resource_helpers::create_instance;
+use nexus_test_utils::resource_helpers::create_organization;
+use nexus_test_utils::resource_helpers::create_project;
+use nexus_test_utils::resource_helpers::create_vpc_with_error;
+use nexus_test_utils::resource_helpers::DiskTest;
+use nexus_test_utils::ControlPlaneTestContext;
+use nexus_test_utils_macros::nexus_test;
+use omicron_common::api::external::ByteCount;
+use omicron_common::api::external::IdentityMetadataCreateParams;
+use omicron_common::api::external::InstanceCpuCount;
+use omicron_nexus::external_api::{params, views::Quota};
+
+const ORG_NAME: &str = "test-org";
+const PROJECT_NAME: &str = "springfield-squidport";
+
+#[nexus_test]
+async fn test_project_quota(cptestctx: &ControlPlaneTestContext) {
+    let client = &cptestctx.external_client;
+    create_organization(&client, ORG_NAME).await;
+    create_project(client, ORG_NAME, PROJECT_NAME).await;
+    let quota_url =
+        format!("/organizations/{}/projects/{}/quota", ORG_NAME, PROJECT_NAME);
+
+    // There are no limits to start with.  The Project's default VPC is the
+    // only resource it has.
+    let quota = quota_get(client, &quota_url).await;
+    assert_eq!(quota.limits.instances, None);
+    assert_eq!(quota.limits.vcpus, None);
+    assert_eq!(quota.limits.memory, None);
+    assert_eq!(quota.limits.disk_bytes, None);
+    assert_eq!(quota.limits.vpcs, None);
+    assert_eq!(quota.usage.instances, 0);
+    assert_eq!(quota.usage.vpcs, 1);
+
+    // Set some limits.
+    let quota = quota_put(
+        client,
+        &quota_url,
+        &params::QuotaUpdate {
+            instances: Some(1),
+            vcpus: Some(6),
+            vpcs: Some(1),
+            ..Default::default()
+        },
+    )
+    .await;
+    assert_eq!(quota.limits.instances, Some(1));
+    assert_eq!(quota.limits.vcpus, Some(6));
+    assert_eq!(quota.limits.memory, None);
+    assert_eq!(quota.limits.vpcs, Some(1));
+
+    // The first Instance fits, but the second does not.
+    create_instance(client, ORG_NAME, PROJECT_NAME, "just-rainsticks").await;
+    let quota = quota_get(client, &quota_url).await;
+    assert_eq!(quota.usage.instances, 1);
+    assert_eq!(quota.usage.vcpus, 4);
+    assert_eq!(quota.usage.memory, ByteCount::from_mebibytes_u32(256));
+    let error = instance_create_error(client, "kayak-rental").await;
+    assert_eq!(
+        error.message,
+        "project quota exceeded: instances is limited to 1 \
+         (1 in use, 1 requested)"
+    );
+
+    // Nor does another VPC.
+    let error = create_vpc_with_error(
+        client,
+        ORG_NAME,
+        PROJECT_NAME,
+        "second-vpc",
+        StatusCode::BAD_REQUEST,
+    )
+    .await;
+    assert_eq!(
+        error.message,
+        "project quota exceeded: VPCs is limited to 1 (1 in use, 1 requested)"
+    );
+
+    // Raising the Instance limit is not enough: the vCPU limit still applies.
+    quota_put(
+        client,
+        &quota_url,
+        &params::QuotaUpdate {
+            instances: Some(2),
+            vcpus: Some(6),
+            vpcs: Some(1),
+            ..Default::default()
+        },
+    )
+    .await;
+    let error = instance_create_error(client, "kayak-rental").await;
+    assert_eq!(
+        error.message,
+        "project quota exceeded: vCPUs is limited to 6 (4 in use, 4 requested)"
+    );
+
+    // Removing the limits allows the Instance to be created.
+    let quota =
+        quota_put(client, &quota_url, &params::QuotaUpdate::default()).await;
+    assert_eq!(quota.limits.instances, None);
+    create_instance(client, ORG_NAME, PROJECT_NAME, "kayak-rental").await;
+}
+
+#[nexus_test]
+async fn test_organization_quota(cptestctx: &ControlPlaneTestContext) {
+    let client = &cptestctx.external_client;
+    DiskTest::new(&cptestctx).await;
+    create_organization(&client, ORG_NAME).await;
+    create_project(client, ORG_NAME, PROJECT_NAME).await;
+    create_project(client, ORG_NAME, "other-project").await;
+    let quota_url = format!("/organizations/{}/quota", ORG_NAME);
+
+    quota_put(
+        client,
+        &quota_url,
+        &params::QuotaUpdate {
+            disk_bytes: Some(ByteCount::from_gibibytes_u32(1)),
+            ..Default::default()
+        },
+    )
+    .await;
+
+    // The limit applies across all of the Organization's Projects.
+    create_disk(client, ORG_NAME, PROJECT_NAME, "just-rainsticks").await;
+    let error = NexusRequest::new(
+        RequestBuilder::new(
+            client,
+            Method::POST,
+            &format!(
+                "/organizations/{}/projects/other-project/disks",
+                ORG_NAME
+            ),
+        )
+        .body(Some(&params::DiskCreate {
+            identity: IdentityMetadataCreateParams {
+                name: "sells-rainsticks".parse().unwrap(),
+                description: String::from("one disk too many"),
+                labels: Default::default(),
+            },
+            snapshot_id: None,
+            size: ByteCount::from_gibibytes_u32(1),
+        }))
+        .expect_status(Some(StatusCode::BAD_REQUEST)),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap()
+    .parsed_body::<HttpErrorResponseBody>()
+    .unwrap();
+    assert_eq!(
+        error.message,
+        "organization quota exceeded: disk space (bytes) is limited to \
+         1073741824 (1073741824 in use, 1073741824 requested)"
+    );
+
+    let quota = quota_get(client, &quota_url).await;
+    assert_eq!(quota.limits.disk_bytes, Some(ByteCount::from_gibibytes_u32(1)));
+    assert_eq!(quota.usage.disk_bytes, ByteCount::from_gibibytes_u32(1));
+    assert_eq!(quota.usage.vpcs, 2);
+}
+
+async fn quota_get(client: &ClientTestContext, url: &str) -> Quota {
+    NexusRequest::object_get(client, url)
+        .authn_as(AuthnMode::PrivilegedUser)
+        .execute()
+        .await
+        .unwrap()
+        .parsed_body()
+        .unwrap()
+}
+
+async fn quota_put(
+    client: &ClientTestContext,
+    url: &str,
+    params: &params::QuotaUpdate,
+) -> Quota {
+    NexusRequest::object_put(client, url, Some(params))
+        .authn_as(AuthnMode::PrivilegedUser)
+        .execute()
+        .await
+        .unwrap()
+        .parsed_body()
+        .unwrap()
+}
+
+async fn instance_create_error(
+    client: &ClientTestContext,
+    instance_name: &str,
+) -> HttpErrorResponseBody {
+    let url = format!(
+        "/organizations/{}/projects/{}/instances",
+        ORG_NAME, PROJECT_NAME
+    );
+    NexusRequest::new(
+        RequestBuilder::new(client, Method::POST, &url)
+            .body(Some(&params::InstanceCreate {
+                identity: IdentityMetadataCreateParams {
+                    name: instance_name.parse().unwrap(),
+                    description: format!("instance {:?}", instance_name),
+                    labels: Default::default(),
+                },
+                ncpus: InstanceCpuCount(4),
+                memory: ByteCount::from_mebibytes_u32(256),
+                hostname: String::from("the_host"),
+                affinity_group: None,
+                user_data: vec![],
+                ssh_public_keys: vec![],
+                network_interfaces: vec![],
+            }))
+            .expect_status(Some(StatusCode::BAD_REQUEST)),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap()
+    .parsed_body()
+    .unwrap()
+}
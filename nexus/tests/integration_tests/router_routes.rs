@@ -5,17 +5,20 @@
 use std::net::{IpAddr, Ipv4Addr};
 
 use dropshot::test_util::{
-    object_delete, object_get, objects_list_page, objects_post,
+    object_delete, object_get, objects_list_page, objects_post, read_json,
+    ClientTestContext,
 };
 use dropshot::Method;
 use http::StatusCode;
 use nexus_test_utils::ControlPlaneTestContext;
 use nexus_test_utils_macros::nexus_test;
 use omicron_common::api::external::{
-    IdentityMetadataCreateParams, IdentityMetadataUpdateParams, Name,
-    RouteDestination, RouteTarget, RouterRoute, RouterRouteCreateParams,
-    RouterRouteKind, RouterRouteUpdateParams,
+    EffectiveRouteTable, IdentityMetadataCreateParams,
+    IdentityMetadataUpdateParams, Name, RouteDestination, RouteTarget,
+    RouterRoute, RouterRouteCreateParams, RouterRouteKind,
+    RouterRouteUpdateParams,
 };
+use omicron_nexus::external_api::{params, views::VpcSubnet};
 
 use nexus_test_utils::resource_helpers::{
     create_organization, create_project, create_router, create_vpc,
@@ -106,6 +109,7 @@ async fn test_router_routes(cptestctx: &ControlPlaneTestContext) {
             identity: IdentityMetadataCreateParams {
                 name: route_name.parse().unwrap(),
                 description: "It's a route, what else can I say?".to_string(),
+                labels: Default::default(),
             },
             target: RouteTarget::Ip(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1))),
             destination: RouteDestination::Subnet("loopback".parse().unwrap()),
@@ -135,6 +139,7 @@ async fn test_router_routes(cptestctx: &ControlPlaneTestContext) {
                 identity: IdentityMetadataUpdateParams {
                     name: Some(route_name.parse().unwrap()),
                     description: None,
+                    labels: None,
                 },
                 target: RouteTarget::Ip(IpAddr::V4(Ipv4Addr::new(
                     192, 168, 1, 1,
@@ -166,3 +171,191 @@ async fn test_router_routes(cptestctx: &ControlPlaneTestContext) {
         )
         .await;
 }
+
+#[nexus_test]
+async fn test_subnet_effective_routes(cptestctx: &ControlPlaneTestContext) {
+    let client = &cptestctx.external_client;
+
+    let organization_name = "test-org";
+    let project_name = "springfield-squidport";
+    let vpc_name = "vpc1";
+    let router_name = "router1";
+
+    let vpc_url = format!(
+        "/organizations/{}/projects/{}/vpcs/{}",
+        organization_name, project_name, vpc_name
+    );
+    let subnet_url = format!("{}/subnets/default", vpc_url);
+    let effective_routes_url = format!("{}/routes/effective", subnet_url);
+    let routes_url = format!("{}/routers/{}/routes", vpc_url, router_name);
+
+    create_organization(&client, organization_name).await;
+    let _ = create_project(&client, organization_name, project_name).await;
+    create_vpc(&client, organization_name, project_name, vpc_name).await;
+
+    // With only the system router, traffic for the default subnet stays
+    // there, and everything else goes out through the internet gateway.
+    let table =
+        object_get::<EffectiveRouteTable>(client, &effective_routes_url).await;
+    assert_eq!(
+        ipv4_routes(&table),
+        vec![
+            ("172.30.0.0".to_string(), 22, "subnet:default".to_string()),
+            ("0.0.0.0".to_string(), 0, "inetgw:outbound".to_string()),
+        ]
+    );
+
+    // Only custom routers can be attached to a subnet.
+    let error = client
+        .make_request_error_body(
+            Method::POST,
+            &format!("{}/router/attach", subnet_url),
+            params::VpcSubnetRouterAttach {
+                router_name: "system".parse().unwrap(),
+            },
+            StatusCode::BAD_REQUEST,
+        )
+        .await;
+    assert_eq!(
+        error.message,
+        "only custom routers can be attached to a subnet"
+    );
+
+    // Attach a custom router with a route of its own.
+    let router = create_router(
+        &client,
+        organization_name,
+        project_name,
+        vpc_name,
+        router_name,
+    )
+    .await;
+    create_route(
+        client,
+        &routes_url,
+        "via-nat",
+        "ip:172.30.0.5",
+        "ip:192.0.2.1",
+    )
+    .await;
+    let subnet = attach_router(client, &subnet_url, router_name).await;
+    assert_eq!(subnet.custom_router_id, Some(router.identity.id));
+    let table =
+        object_get::<EffectiveRouteTable>(client, &effective_routes_url).await;
+    assert_eq!(
+        ipv4_routes(&table),
+        vec![
+            ("192.0.2.1".to_string(), 32, "ip:172.30.0.5".to_string()),
+            ("172.30.0.0".to_string(), 22, "subnet:default".to_string()),
+            ("0.0.0.0".to_string(), 0, "inetgw:outbound".to_string()),
+        ]
+    );
+    assert_eq!(table.routes[0].router_id, Some(router.identity.id));
+
+    // A route whose target doesn't exist can be created, but the table that
+    // includes it can't be resolved.
+    create_route(
+        client,
+        &routes_url,
+        "to-nowhere",
+        "instance:nonexistent",
+        "ip:192.0.2.2",
+    )
+    .await;
+    let error = client
+        .make_request_error(
+            Method::GET,
+            &effective_routes_url,
+            StatusCode::BAD_REQUEST,
+        )
+        .await;
+    assert_eq!(
+        error.message,
+        "route \"to-nowhere\" of router \"router1\" has nonexistent target \
+         \"instance:nonexistent\""
+    );
+
+    // Detaching the router leaves just the system router's routes.
+    let mut response = client
+        .make_request(
+            Method::POST,
+            &format!("{}/router/detach", subnet_url),
+            None as Option<()>,
+            StatusCode::OK,
+        )
+        .await
+        .unwrap();
+    let subnet: VpcSubnet = read_json(&mut response).await;
+    assert_eq!(subnet.custom_router_id, None);
+    let table =
+        object_get::<EffectiveRouteTable>(client, &effective_routes_url).await;
+    assert_eq!(ipv4_routes(&table).len(), 2);
+
+    // Deleting an attached router detaches it.
+    object_delete(client, &format!("{}/to-nowhere", routes_url)).await;
+    attach_router(client, &subnet_url, router_name).await;
+    object_delete(client, &format!("{}/routers/{}", vpc_url, router_name))
+        .await;
+    let subnet = object_get::<VpcSubnet>(client, &subnet_url).await;
+    assert_eq!(subnet.custom_router_id, None);
+    let table =
+        object_get::<EffectiveRouteTable>(client, &effective_routes_url).await;
+    assert_eq!(ipv4_routes(&table).len(), 2);
+}
+
+fn ipv4_routes(table: &EffectiveRouteTable) -> Vec<(String, u8, String)> {
+    table
+        .routes
+        .iter()
+        .filter(|route| route.destination.is_ipv4())
+        .map(|route| {
+            (
+                route.destination.to_string(),
+                route.prefix_len,
+                route.target.to_string(),
+            )
+        })
+        .collect()
+}
+
+async fn create_route(
+    client: &ClientTestContext,
+    routes_url: &str,
+    route_name: &str,
+    target: &str,
+    destination: &str,
+) -> RouterRoute {
+    objects_post(
+        client,
+        routes_url,
+        RouterRouteCreateParams {
+            identity: IdentityMetadataCreateParams {
+                name: route_name.parse().unwrap(),
+                description: String::from("route description"),
+                labels: Default::default(),
+            },
+            target: target.parse().unwrap(),
+            destination: destination.parse().unwrap(),
+        },
+    )
+    .await
+}
+
+async fn attach_router(
+    client: &ClientTestContext,
+    subnet_url: &str,
+    router_name: &str,
+) -> VpcSubnet {
+    let mut response = client
+        .make_request(
+            Method::POST,
+            &format!("{}/router/attach", subnet_url),
+            Some(params::VpcSubnetRouterAttach {
+                router_name: router_name.parse().unwrap(),
+            }),
+            StatusCode::OK,
+        )
+        .await
+        .unwrap();
+    read_json(&mut response).await
+}
@@ -0,0 +1,195 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Tests attaching to instances' serial consoles
+
+use dropshot::test_util::ClientTestContext;
+use futures::{SinkExt, StreamExt};
+use http::method::Method;
+use http::StatusCode;
+use nexus_test_utils::http_testing::AuthnMode;
+use nexus_test_utils::http_testing::NexusRequest;
+use nexus_test_utils::resource_helpers::create_instance;
+use nexus_test_utils::resource_helpers::create_organization;
+use nexus_test_utils::resource_helpers::create_project;
+use nexus_test_utils::ControlPlaneTestContext;
+use nexus_test_utils_macros::nexus_test;
+use omicron_common::api::external::RoleName;
+use omicron_nexus::authn;
+use omicron_nexus::authn::external::spoof;
+use omicron_nexus::external_api::views::InstanceSerialConsoleData;
+use omicron_nexus::external_api::views::{Policy, RoleAssignment};
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
+use uuid::Uuid;
+
+const ORG_NAME: &str = "test-org";
+const PROJECT_NAME: &str = "springfield-squidport";
+const INSTANCE_NAME: &str = "just-rainsticks";
+
+fn console_url() -> String {
+    format!(
+        "/organizations/{}/projects/{}/instances/{}/serial-console",
+        ORG_NAME, PROJECT_NAME, INSTANCE_NAME
+    )
+}
+
+async fn console_history(
+    client: &ClientTestContext,
+    query: &str,
+) -> InstanceSerialConsoleData {
+    NexusRequest::object_get(
+        client,
+        &format!("{}/history{}", console_url(), query),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap()
+    .parsed_body()
+    .unwrap()
+}
+
+/// Opens a WebSocket to the console, authenticated as the user `user_id`
+async fn console_attach(
+    client: &ClientTestContext,
+    user_id: Uuid,
+) -> Result<WebSocketStream<TcpStream>, tungstenite::Error> {
+    let request = http::Request::builder()
+        .uri(format!("ws://{}{}", client.bind_address, console_url()))
+        .header(
+            http::header::AUTHORIZATION,
+            spoof::make_header_value(user_id).0.encode(),
+        )
+        .body(())
+        .unwrap();
+    let stream = TcpStream::connect(client.bind_address).await.unwrap();
+    tokio_tungstenite::client_async(request, stream)
+        .await
+        .map(|(websocket, _)| websocket)
+}
+
+/// Returns the data in the next message on `websocket`
+async fn next_output(websocket: &mut WebSocketStream<TcpStream>) -> Vec<u8> {
+    match websocket.next().await {
+        Some(Ok(Message::Binary(bytes))) => bytes,
+        other => panic!("expected console output, found {:?}", other),
+    }
+}
+
+#[nexus_test]
+async fn test_serial_console(cptestctx: &ControlPlaneTestContext) {
+    let client = &cptestctx.external_client;
+    create_organization(client, ORG_NAME).await;
+    create_project(client, ORG_NAME, PROJECT_NAME).await;
+    create_instance(client, ORG_NAME, PROJECT_NAME, INSTANCE_NAME).await;
+
+    // The simulated sled agent's console has already printed a banner, which
+    // we can read without attaching.
+    let banner = console_history(client, "").await;
+    let banner_text = String::from_utf8(banner.data.clone()).unwrap();
+    assert!(banner_text.ends_with("the_host login: "), "{:?}", banner_text);
+    assert_eq!(banner.last_byte_offset, banner.data.len() as u64);
+
+    let tail = console_history(client, "?max_bytes=7").await;
+    assert_eq!(tail.data, b"login: ");
+    assert_eq!(tail.last_byte_offset, banner.last_byte_offset);
+
+    // Attaching replays what's been written so far, then relays anything
+    // typed into the console (which the simulated console echoes back).
+    let mut websocket =
+        console_attach(client, authn::USER_TEST_PRIVILEGED.id).await.unwrap();
+    assert_eq!(next_output(&mut websocket).await, banner.data);
+    websocket.send(Message::Binary(b"root\r".to_vec())).await.unwrap();
+    assert_eq!(next_output(&mut websocket).await, b"root\r");
+    websocket.send(Message::Text(String::from("hunter2\r"))).await.unwrap();
+    assert_eq!(next_output(&mut websocket).await, b"hunter2\r");
+    websocket.close(None).await.unwrap();
+
+    let history = console_history(client, "").await;
+    assert_eq!(
+        history.data,
+        [&banner.data[..], &b"root\rhunter2\r"[..]].concat()
+    );
+    assert_eq!(history.last_byte_offset, history.data.len() as u64);
+
+    // Users who can't see the Instance can't attach to its console.
+    let error = console_attach(client, authn::USER_TEST_UNPRIVILEGED.id)
+        .await
+        .unwrap_err();
+    match error {
+        tungstenite::Error::Http(response) => {
+            assert_eq!(response.status(), StatusCode::NOT_FOUND)
+        }
+        other => panic!("expected an HTTP error, found {:?}", other),
+    }
+
+    // Users who can see the Instance but not modify it can neither attach to
+    // its console nor read the console's history, which includes everything
+    // typed into it.
+    NexusRequest::object_put(
+        client,
+        &format!(
+            "/organizations/{}/projects/{}/policy",
+            ORG_NAME, PROJECT_NAME
+        ),
+        Some(&Policy {
+            role_assignments: vec![RoleAssignment {
+                user_id: authn::USER_TEST_UNPRIVILEGED.id,
+                role_name: RoleName::new("project", "viewer"),
+            }],
+        }),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap();
+    let error = console_attach(client, authn::USER_TEST_UNPRIVILEGED.id)
+        .await
+        .unwrap_err();
+    match error {
+        tungstenite::Error::Http(response) => {
+            assert_eq!(response.status(), StatusCode::FORBIDDEN)
+        }
+        other => panic!("expected an HTTP error, found {:?}", other),
+    }
+    NexusRequest::expect_failure(
+        client,
+        StatusCode::FORBIDDEN,
+        Method::GET,
+        &format!("{}/history", console_url()),
+    )
+    .authn_as(AuthnMode::UnprivilegedUser)
+    .execute()
+    .await
+    .unwrap();
+
+    // Requests which don't ask to upgrade to a WebSocket are rejected, before
+    // Nexus looks for the Instance or its sled.
+    NexusRequest::expect_failure(
+        client,
+        StatusCode::BAD_REQUEST,
+        Method::GET,
+        &console_url(),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap();
+    NexusRequest::expect_failure(
+        client,
+        StatusCode::BAD_REQUEST,
+        Method::GET,
+        &format!(
+            "/organizations/{}/projects/{}/instances/no-such-instance/serial-console",
+            ORG_NAME, PROJECT_NAME
+        ),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap();
+}
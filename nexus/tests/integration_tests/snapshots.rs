@@ -0,0 +1,350 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Tests disk snapshots and creating disks from snapshots
+
+use crucible_agent_client::types::Region;
+use dropshot::test_util::ClientTestContext;
+use dropshot::HttpErrorResponseBody;
+use http::method::Method;
+use http::StatusCode;
+use nexus_test_utils::http_testing::AuthnMode;
+use nexus_test_utils::http_testing::NexusRequest;
+use nexus_test_utils::http_testing::RequestBuilder;
+use nexus_test_utils::identity_eq;
+use nexus_test_utils::resource_helpers::create_disk;
+use nexus_test_utils::resource_helpers::create_organization;
+use nexus_test_utils::resource_helpers::create_project;
+use nexus_test_utils::resource_helpers::object_create;
+use nexus_test_utils::resource_helpers::DiskTest;
+use nexus_test_utils::ControlPlaneTestContext;
+use nexus_test_utils_macros::nexus_test;
+use omicron_common::api::external::ByteCount;
+use omicron_common::api::external::Disk;
+use omicron_common::api::external::IdentityMetadataCreateParams;
+use omicron_nexus::external_api::{params, views::Snapshot};
+use uuid::Uuid;
+
+const ORG_NAME: &str = "test-org";
+const PROJECT_NAME: &str = "springfield-squidport-disks";
+const DISK_NAME: &str = "just-rainsticks";
+
+fn get_disks_url() -> String {
+    format!("/organizations/{}/projects/{}/disks", ORG_NAME, PROJECT_NAME)
+}
+
+fn get_snapshots_url(disk_name: &str) -> String {
+    format!("{}/{}/snapshots", get_disks_url(), disk_name)
+}
+
+#[nexus_test]
+async fn test_snapshot_create_delete(cptestctx: &ControlPlaneTestContext) {
+    let client = &cptestctx.external_client;
+    let test = DiskTest::new(&cptestctx).await;
+    create_organization(&client, ORG_NAME).await;
+    let project = create_project(client, ORG_NAME, PROJECT_NAME).await;
+    let disk = create_disk(&client, ORG_NAME, PROJECT_NAME, DISK_NAME).await;
+    let snapshots_url = get_snapshots_url(DISK_NAME);
+
+    // There are no snapshots to start with.
+    assert!(snapshots_list(&client, &snapshots_url).await.is_empty());
+    let snapshot_url = format!("{}/monday", snapshots_url);
+    let error = expect_failure(
+        client,
+        StatusCode::NOT_FOUND,
+        Method::GET,
+        &snapshot_url,
+    )
+    .await;
+    assert_eq!(error.message, "not found: snapshot with name \"monday\"");
+
+    // Take a snapshot.
+    let snapshot: Snapshot = object_create(
+        client,
+        &snapshots_url,
+        &snapshot_create_params("monday"),
+    )
+    .await;
+    assert_eq!(snapshot.identity.name, "monday");
+    assert_eq!(snapshot.project_id, project.identity.id);
+    assert_eq!(snapshot.disk_id, disk.identity.id);
+    assert_eq!(snapshot.size, disk.size);
+
+    // Each of the Disk's regions now has a snapshot with the same id.
+    for id in &test.dataset_ids {
+        let crucible =
+            test.sled_agent.get_crucible_dataset(test.zpool_id, *id).await;
+        let region = disk_region(&crucible.list().await, disk.identity.id);
+        let region_snapshots =
+            crucible.list_snapshots(region.id.clone()).await.unwrap();
+        assert_eq!(region_snapshots.len(), 1);
+        assert_eq!(region_snapshots[0].id, snapshot.identity.id);
+    }
+
+    // Snapshot names must be unique for each Disk.
+    let error = expect_failure_with_body(
+        client,
+        StatusCode::BAD_REQUEST,
+        Method::POST,
+        &snapshots_url,
+        &snapshot_create_params("monday"),
+    )
+    .await;
+    assert_eq!(error.message, "already exists: snapshot \"monday\"");
+
+    // List and fetch the snapshot.
+    let snapshots = snapshots_list(&client, &snapshots_url).await;
+    assert_eq!(snapshots.len(), 1);
+    snapshots_eq(&snapshots[0], &snapshot);
+    snapshots_eq(&snapshot_get(&client, &snapshot_url).await, &snapshot);
+
+    // The Disk can't be deleted while it has snapshots.
+    let disk_url = format!("{}/{}", get_disks_url(), DISK_NAME);
+    let error = expect_failure(
+        client,
+        StatusCode::BAD_REQUEST,
+        Method::DELETE,
+        &disk_url,
+    )
+    .await;
+    assert_eq!(error.message, "disk to be deleted has snapshots");
+
+    // Delete the snapshot.  Its data is removed from each region.
+    NexusRequest::object_delete(client, &snapshot_url)
+        .authn_as(AuthnMode::PrivilegedUser)
+        .execute()
+        .await
+        .unwrap();
+    let error = expect_failure(
+        client,
+        StatusCode::NOT_FOUND,
+        Method::GET,
+        &snapshot_url,
+    )
+    .await;
+    assert_eq!(error.message, "not found: snapshot with name \"monday\"");
+    assert!(snapshots_list(&client, &snapshots_url).await.is_empty());
+    for id in &test.dataset_ids {
+        let crucible =
+            test.sled_agent.get_crucible_dataset(test.zpool_id, *id).await;
+        let region = disk_region(&crucible.list().await, disk.identity.id);
+        assert!(crucible
+            .list_snapshots(region.id.clone())
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    // Now the Disk can be deleted.
+    NexusRequest::object_delete(client, &disk_url)
+        .authn_as(AuthnMode::PrivilegedUser)
+        .execute()
+        .await
+        .unwrap();
+}
+
+#[nexus_test]
+async fn test_disk_create_from_snapshot(cptestctx: &ControlPlaneTestContext) {
+    let client = &cptestctx.external_client;
+    let test = DiskTest::new(&cptestctx).await;
+    create_organization(&client, ORG_NAME).await;
+    create_project(client, ORG_NAME, PROJECT_NAME).await;
+    let disk = create_disk(&client, ORG_NAME, PROJECT_NAME, DISK_NAME).await;
+    let snapshots_url = get_snapshots_url(DISK_NAME);
+    let snapshot: Snapshot = object_create(
+        client,
+        &snapshots_url,
+        &snapshot_create_params("golden"),
+    )
+    .await;
+    let disks_url = get_disks_url();
+
+    // The new Disk must be able to hold the snapshot's contents.
+    let error = expect_failure_with_body(
+        client,
+        StatusCode::BAD_REQUEST,
+        Method::POST,
+        &disks_url,
+        &disk_create_params(
+            "too-small",
+            snapshot.identity.id,
+            ByteCount::from_mebibytes_u32(512),
+        ),
+    )
+    .await;
+    assert_eq!(
+        error.message,
+        "unsupported value for \"size\": disk must be at least as large as the \
+         snapshot it is created from (1073741824 bytes)"
+    );
+
+    // Snapshots are only found within the Disk's Project.
+    let error = expect_failure_with_body(
+        client,
+        StatusCode::NOT_FOUND,
+        Method::POST,
+        &format!(
+            "/organizations/{}/projects/{}/disks",
+            ORG_NAME,
+            create_project(client, ORG_NAME, "other-project")
+                .await
+                .identity
+                .name
+        ),
+        &disk_create_params("elsewhere", snapshot.identity.id, disk.size),
+    )
+    .await;
+    assert_eq!(
+        error.message,
+        format!("not found: snapshot with id \"{}\"", snapshot.identity.id)
+    );
+
+    // Create a Disk from the snapshot.  Each of its regions is populated from
+    // the snapshot of one of the original Disk's regions.
+    let clone: Disk = object_create(
+        client,
+        &disks_url,
+        &disk_create_params("clone", snapshot.identity.id, disk.size),
+    )
+    .await;
+    assert_eq!(clone.snapshot_id, Some(snapshot.identity.id));
+    let mut source_region_ids = Vec::new();
+    let mut imported_region_ids = Vec::new();
+    for id in &test.dataset_ids {
+        let crucible =
+            test.sled_agent.get_crucible_dataset(test.zpool_id, *id).await;
+        let regions = crucible.list().await;
+        source_region_ids.push(disk_region(&regions, disk.identity.id).id.0);
+        let region = disk_region(&regions, clone.identity.id);
+        let import = crucible
+            .get_import(region.id.clone())
+            .await
+            .expect("region was not populated from a snapshot");
+        assert_eq!(import.snapshot_id, snapshot.identity.id);
+        imported_region_ids.push(import.source_region_id.to_string());
+    }
+    source_region_ids.sort();
+    imported_region_ids.sort();
+    assert_eq!(source_region_ids, imported_region_ids);
+
+    // The snapshot can't be deleted while a Disk was created from it.
+    let snapshot_url = format!("{}/golden", snapshots_url);
+    let error = expect_failure(
+        client,
+        StatusCode::BAD_REQUEST,
+        Method::DELETE,
+        &snapshot_url,
+    )
+    .await;
+    assert_eq!(error.message, "snapshot to be deleted is in use by a disk");
+
+    // Once that Disk is gone, the snapshot can be deleted.
+    NexusRequest::object_delete(client, &format!("{}/clone", disks_url))
+        .authn_as(AuthnMode::PrivilegedUser)
+        .execute()
+        .await
+        .unwrap();
+    NexusRequest::object_delete(client, &snapshot_url)
+        .authn_as(AuthnMode::PrivilegedUser)
+        .execute()
+        .await
+        .unwrap();
+}
+
+fn snapshot_create_params(name: &str) -> params::SnapshotCreate {
+    params::SnapshotCreate {
+        identity: IdentityMetadataCreateParams {
+            name: name.parse().unwrap(),
+            description: String::from("a point in time"),
+            labels: Default::default(),
+        },
+    }
+}
+
+fn disk_create_params(
+    name: &str,
+    snapshot_id: Uuid,
+    size: ByteCount,
+) -> params::DiskCreate {
+    params::DiskCreate {
+        identity: IdentityMetadataCreateParams {
+            name: name.parse().unwrap(),
+            description: String::from("from a snapshot"),
+            labels: Default::default(),
+        },
+        snapshot_id: Some(snapshot_id),
+        size,
+    }
+}
+
+/// Returns the region backing Disk `disk_id` from a Crucible Agent's regions
+fn disk_region(regions: &[Region], disk_id: Uuid) -> Region {
+    regions
+        .iter()
+        .find(|r| r.volume_id == disk_id.to_string())
+        .expect("no region found for disk")
+        .clone()
+}
+
+async fn expect_failure(
+    client: &ClientTestContext,
+    status: StatusCode,
+    method: Method,
+    url: &str,
+) -> HttpErrorResponseBody {
+    NexusRequest::expect_failure(client, status, method, url)
+        .authn_as(AuthnMode::PrivilegedUser)
+        .execute()
+        .await
+        .unwrap()
+        .parsed_body()
+        .unwrap()
+}
+
+async fn expect_failure_with_body<B: serde::Serialize>(
+    client: &ClientTestContext,
+    status: StatusCode,
+    method: Method,
+    url: &str,
+    body: &B,
+) -> HttpErrorResponseBody {
+    NexusRequest::new(
+        RequestBuilder::new(client, method, url)
+            .body(Some(body))
+            .expect_status(Some(status)),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap()
+    .parsed_body()
+    .unwrap()
+}
+
+async fn snapshot_get(client: &ClientTestContext, url: &str) -> Snapshot {
+    NexusRequest::object_get(client, url)
+        .authn_as(AuthnMode::PrivilegedUser)
+        .execute()
+        .await
+        .unwrap()
+        .parsed_body()
+        .unwrap()
+}
+
+async fn snapshots_list(
+    client: &ClientTestContext,
+    list_url: &str,
+) -> Vec<Snapshot> {
+    NexusRequest::iter_collection_authn(client, list_url, "", None)
+        .await
+        .expect("failed to list snapshots")
+        .all_items
+}
+
+fn snapshots_eq(snapshot1: &Snapshot, snapshot2: &Snapshot) {
+    identity_eq(&snapshot1.identity, &snapshot2.identity);
+    assert_eq!(snapshot1.project_id, snapshot2.project_id);
+    assert_eq!(snapshot1.disk_id, snapshot2.disk_id);
+    assert_eq!(snapshot1.size, snapshot2.size);
+}
@@ -37,10 +37,15 @@ async fn create_instance_expect_failure(
         identity: IdentityMetadataCreateParams {
             name: name.parse().unwrap(),
             description: "".to_string(),
+            labels: Default::default(),
         },
         ncpus: InstanceCpuCount(1),
         memory: ByteCount::from_mebibytes_u32(256),
         hostname: name.to_string(),
+        affinity_group: None,
+        user_data: vec![],
+        ssh_public_keys: vec![],
+        network_interfaces: vec![],
     };
 
     NexusRequest::new(
@@ -82,6 +87,7 @@ async fn test_subnet_allocation(cptestctx: &ControlPlaneTestContext) {
         identity: IdentityMetadataUpdateParams {
             name: Some("default".parse().unwrap()),
             description: None,
+            labels: None,
         },
         ipv4_block: Some(Ipv4Net(subnet)),
         ipv6_block: None,
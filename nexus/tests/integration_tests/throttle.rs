@@ -0,0 +1,136 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Tests rate limiting of external API requests
+
+use dropshot::test_util::ClientTestContext;
+use dropshot::HttpErrorResponseBody;
+use http::method::Method;
+use http::StatusCode;
+use nexus_test_utils::http_testing::AuthnMode;
+use nexus_test_utils::http_testing::NexusRequest;
+use nexus_test_utils::http_testing::RequestBuilder;
+use nexus_test_utils::http_testing::TestResponse;
+use nexus_test_utils::{load_test_config, test_setup_with_config};
+use std::num::NonZeroU32;
+use std::time::Duration;
+
+/// Number of requests that each user and each address may make at once
+const BURST: u32 = 3;
+
+async fn organizations_get(
+    client: &ClientTestContext,
+    authn_mode: AuthnMode,
+    source_address: Option<&str>,
+) -> TestResponse {
+    let mut builder =
+        RequestBuilder::new(client, Method::GET, "/organizations");
+    if let Some(source_address) = source_address {
+        builder = builder.header("x-forwarded-for", source_address);
+    }
+    NexusRequest::new(builder).authn_as(authn_mode).execute().await.unwrap()
+}
+
+/// Makes requests until one is throttled, and returns its error after checking
+/// that the response says when to retry
+///
+/// Since the limits allow a request per second, this can take more than
+/// `BURST` requests if the test is running slowly, but it can't take fewer.
+async fn get_until_throttled(
+    client: &ClientTestContext,
+    authn_mode: AuthnMode,
+    source_address: Option<&str>,
+    min_admitted: u32,
+) -> HttpErrorResponseBody {
+    let mut admitted = 0;
+    loop {
+        let response =
+            organizations_get(client, authn_mode, source_address).await;
+        match response.status {
+            StatusCode::OK => admitted += 1,
+            StatusCode::TOO_MANY_REQUESTS => {
+                assert!(
+                    admitted >= min_admitted,
+                    "throttled after only {} requests",
+                    admitted
+                );
+                assert_eq!(
+                    response.headers.get(http::header::RETRY_AFTER).unwrap(),
+                    "1"
+                );
+                return response.parsed_body().unwrap();
+            }
+            other => panic!("unexpected status: {}", other),
+        }
+        assert!(admitted < 10 * BURST, "requests were never throttled");
+    }
+}
+
+#[tokio::test]
+async fn test_rate_limits() {
+    let mut config = load_test_config();
+    let one_per_second = NonZeroU32::new(1).unwrap();
+    let burst = NonZeroU32::new(BURST).unwrap();
+    config.throttle.per_actor.requests_per_second = one_per_second;
+    config.throttle.per_actor.burst = burst;
+    config.throttle.per_source_ip.requests_per_second = one_per_second;
+    config.throttle.per_source_ip.burst = burst;
+    let cptestctx =
+        test_setup_with_config("test_rate_limits", &mut config).await;
+    let client = &cptestctx.external_client;
+
+    // Requests from a single address are limited...
+    let error = get_until_throttled(
+        client,
+        AuthnMode::PrivilegedUser,
+        Some("192.0.2.1"),
+        BURST,
+    )
+    .await;
+    assert_eq!(error.error_code.as_deref(), Some("TooManyRequests"));
+    assert_eq!(
+        error.message,
+        "too many requests from this address; retry after 1 seconds"
+    );
+
+    // ... independently of those from other addresses by other users.
+    let response = organizations_get(
+        client,
+        AuthnMode::UnprivilegedUser,
+        Some("192.0.2.2"),
+    )
+    .await;
+    assert_eq!(response.status, StatusCode::OK);
+
+    // Requests by a single user are limited no matter where they come from.
+    // (That user has already used up most of their burst above.)
+    let error = get_until_throttled(
+        client,
+        AuthnMode::PrivilegedUser,
+        Some("192.0.2.3"),
+        0,
+    )
+    .await;
+    assert_eq!(error.error_code.as_deref(), Some("TooManyRequests"));
+    assert_eq!(
+        error.message,
+        "too many requests from this user; retry after 1 seconds"
+    );
+
+    // Requests whose source address isn't known aren't limited by address,
+    // so one client without a known address can't use up the limit for the
+    // others.  (Wait for the privileged user's limit to recover first.)
+    tokio::time::sleep(Duration::from_secs(BURST.into())).await;
+    let error =
+        get_until_throttled(client, AuthnMode::UnprivilegedUser, None, 0).await;
+    assert_eq!(
+        error.message,
+        "too many requests from this user; retry after 1 seconds"
+    );
+    let response =
+        organizations_get(client, AuthnMode::PrivilegedUser, None).await;
+    assert_eq!(response.status, StatusCode::OK);
+
+    cptestctx.teardown().await;
+}
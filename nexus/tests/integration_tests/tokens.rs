@@ -0,0 +1,279 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Tests API tokens, which users create for automation
+
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use dropshot::test_util::ClientTestContext;
+use dropshot::HttpErrorResponseBody;
+use dropshot::ResultsPage;
+use http::header;
+use http::method::Method;
+use http::StatusCode;
+use nexus_test_utils::http_testing::AuthnMode;
+use nexus_test_utils::http_testing::NexusRequest;
+use nexus_test_utils::http_testing::RequestBuilder;
+use nexus_test_utils::resource_helpers::{create_organization, create_project};
+use nexus_test_utils::ControlPlaneTestContext;
+use nexus_test_utils_macros::nexus_test;
+use omicron_common::api::external::{
+    IdentityMetadataCreateParams, VpcFirewallRuleUpdateParams, VpcFirewallRules,
+};
+use omicron_nexus::authn::USER_TEST_UNPRIVILEGED;
+use omicron_nexus::external_api::params;
+use omicron_nexus::external_api::views::{
+    ApiToken, ApiTokenWithSecret, SessionUser,
+};
+
+const TOKENS_URL: &str = "/session/me/tokens";
+
+#[nexus_test]
+async fn test_api_tokens(cptestctx: &ControlPlaneTestContext) {
+    let client = &cptestctx.external_client;
+
+    // A user can create a token and then use it to authenticate as
+    // themselves.
+    // (Use whole seconds, since the database doesn't store nanoseconds.)
+    let time_expires = Utc.timestamp(Utc::now().timestamp() + 3600, 0);
+    let token = token_create(
+        client,
+        AuthnMode::UnprivilegedUser,
+        Some(time_expires),
+        params::ApiTokenScope::ReadWrite,
+    )
+    .await;
+    assert_eq!(token.token.description, "a token");
+    assert_eq!(token.token.time_expires, Some(time_expires));
+    assert_eq!(token.token.scope, params::ApiTokenScope::ReadWrite);
+    assert!(token.secret.starts_with("oxide-token-"));
+    let me = bearer_request(client, Method::GET, "/session/me", &token.secret)
+        .expect_status(Some(StatusCode::OK))
+        .execute()
+        .await
+        .unwrap()
+        .parsed_body::<SessionUser>()
+        .unwrap();
+    assert_eq!(me.id, USER_TEST_UNPRIVILEGED.id);
+
+    // The token itself is never shown again, and users only see their own
+    // tokens.
+    assert_eq!(
+        tokens_list(client, AuthnMode::UnprivilegedUser).await,
+        vec![token.token.clone()]
+    );
+    assert_eq!(tokens_list(client, AuthnMode::PrivilegedUser).await, vec![]);
+
+    // A read-only token can see things, but not change anything -- including
+    // creating more tokens -- even if its owner could.
+    let token_ro = token_create(
+        client,
+        AuthnMode::PrivilegedUser,
+        None,
+        params::ApiTokenScope::ReadOnly,
+    )
+    .await;
+    assert_eq!(token_ro.token.time_expires, None);
+    bearer_request(client, Method::GET, "/organizations", &token_ro.secret)
+        .expect_status(Some(StatusCode::OK))
+        .execute()
+        .await
+        .unwrap();
+    bearer_request(client, Method::POST, "/organizations", &token_ro.secret)
+        .body(Some(&params::OrganizationCreate {
+            identity: IdentityMetadataCreateParams {
+                name: "test-org".parse().unwrap(),
+                description: String::from("an organization"),
+                labels: Default::default(),
+            },
+        }))
+        .expect_status(Some(StatusCode::FORBIDDEN))
+        .execute()
+        .await
+        .unwrap();
+    bearer_request(client, Method::POST, TOKENS_URL, &token_ro.secret)
+        .body(Some(&token_create_params(
+            None,
+            params::ApiTokenScope::ReadWrite,
+        )))
+        .expect_status(Some(StatusCode::FORBIDDEN))
+        .execute()
+        .await
+        .unwrap();
+
+    // Tokens can't be created already expired.
+    let error: HttpErrorResponseBody = NexusRequest::new(
+        RequestBuilder::new(client, Method::POST, TOKENS_URL)
+            .body(Some(&token_create_params(
+                Some(Utc::now() - Duration::seconds(1)),
+                params::ApiTokenScope::ReadWrite,
+            )))
+            .expect_status(Some(StatusCode::BAD_REQUEST)),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap()
+    .parsed_body()
+    .unwrap();
+    assert_eq!(
+        error.message,
+        "unsupported value for \"time_expires\": must be in the future"
+    );
+
+    // Users can't revoke each other's tokens.
+    let token_url = format!("{}/{}", TOKENS_URL, token.token.id);
+    NexusRequest::expect_failure(
+        client,
+        StatusCode::NOT_FOUND,
+        Method::DELETE,
+        &token_url,
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap();
+
+    // Revoking a token takes effect immediately.
+    NexusRequest::object_delete(client, &token_url)
+        .authn_as(AuthnMode::UnprivilegedUser)
+        .execute()
+        .await
+        .unwrap();
+    bearer_request(client, Method::GET, "/session/me", &token.secret)
+        .expect_status(Some(StatusCode::UNAUTHORIZED))
+        .execute()
+        .await
+        .unwrap();
+    assert_eq!(tokens_list(client, AuthnMode::UnprivilegedUser).await, vec![]);
+
+    // Tokens that were never issued don't work either.
+    bearer_request(
+        client,
+        Method::GET,
+        "/session/me",
+        "oxide-token-0123456789abcdef0123456789abcdef01234567",
+    )
+    .expect_status(Some(StatusCode::UNAUTHORIZED))
+    .execute()
+    .await
+    .unwrap();
+}
+
+#[nexus_test]
+async fn test_read_only_token_cannot_modify(
+    cptestctx: &ControlPlaneTestContext,
+) {
+    let client = &cptestctx.external_client;
+    create_organization(client, "test-org").await;
+    create_project(client, "test-org", "test-project").await;
+    let vpcs_url = "/organizations/test-org/projects/test-project/vpcs";
+    let token_ro = token_create(
+        client,
+        AuthnMode::PrivilegedUser,
+        None,
+        params::ApiTokenScope::ReadOnly,
+    )
+    .await;
+
+    // Requests that would change something are refused, whether or not the
+    // operation checks the actor's roles against a specific action.
+    bearer_request(client, Method::POST, vpcs_url, &token_ro.secret)
+        .body(Some(&params::VpcCreate {
+            identity: IdentityMetadataCreateParams {
+                name: "vpc1".parse().unwrap(),
+                description: String::from("a vpc"),
+                labels: Default::default(),
+            },
+            ipv6_prefix: None,
+            dns_name: "vpc1".parse().unwrap(),
+        }))
+        .expect_status(Some(StatusCode::FORBIDDEN))
+        .execute()
+        .await
+        .unwrap();
+    let rules_url = format!("{}/default/firewall/rules", vpcs_url);
+    bearer_request(client, Method::PUT, &rules_url, &token_ro.secret)
+        .body(Some(&VpcFirewallRuleUpdateParams { rules: vec![] }))
+        .expect_status(Some(StatusCode::FORBIDDEN))
+        .execute()
+        .await
+        .unwrap();
+
+    // Nothing changed.
+    NexusRequest::expect_failure(
+        client,
+        StatusCode::NOT_FOUND,
+        Method::GET,
+        &format!("{}/vpc1", vpcs_url),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap();
+    let rules: VpcFirewallRules =
+        bearer_request(client, Method::GET, &rules_url, &token_ro.secret)
+            .expect_status(Some(StatusCode::OK))
+            .execute()
+            .await
+            .unwrap()
+            .parsed_body()
+            .unwrap();
+    assert!(!rules.rules.is_empty());
+}
+
+fn token_create_params(
+    time_expires: Option<DateTime<Utc>>,
+    scope: params::ApiTokenScope,
+) -> params::ApiTokenCreate {
+    params::ApiTokenCreate {
+        description: String::from("a token"),
+        time_expires,
+        scope,
+    }
+}
+
+async fn token_create(
+    client: &ClientTestContext,
+    authn_mode: AuthnMode,
+    time_expires: Option<DateTime<Utc>>,
+    scope: params::ApiTokenScope,
+) -> ApiTokenWithSecret {
+    NexusRequest::objects_post(
+        client,
+        TOKENS_URL,
+        &token_create_params(time_expires, scope),
+    )
+    .authn_as(authn_mode)
+    .execute()
+    .await
+    .unwrap()
+    .parsed_body()
+    .unwrap()
+}
+
+async fn tokens_list(
+    client: &ClientTestContext,
+    authn_mode: AuthnMode,
+) -> Vec<ApiToken> {
+    NexusRequest::object_get(client, TOKENS_URL)
+        .authn_as(authn_mode)
+        .execute()
+        .await
+        .unwrap()
+        .parsed_body::<ResultsPage<ApiToken>>()
+        .unwrap()
+        .items
+}
+
+/// Returns a request that authenticates with `token` as an RFC 6750 bearer
+/// token
+fn bearer_request<'a>(
+    client: &'a ClientTestContext,
+    method: Method,
+    url: &str,
+    token: &str,
+) -> RequestBuilder<'a> {
+    RequestBuilder::new(client, method, url)
+        .header(header::AUTHORIZATION, format!("Bearer {}", token))
+}
@@ -18,6 +18,7 @@ use nexus_test_utils::http_testing::TestResponse;
 use nexus_test_utils::resource_helpers::DiskTest;
 use nexus_test_utils::ControlPlaneTestContext;
 use nexus_test_utils_macros::nexus_test;
+use omicron_common::api::external::AffinityPolicy;
 use omicron_common::api::external::ByteCount;
 use omicron_common::api::external::IdentityMetadataCreateParams;
 use omicron_common::api::external::IdentityMetadataUpdateParams;
@@ -26,6 +27,7 @@ use omicron_common::api::external::Name;
 use omicron_nexus::authn;
 use omicron_nexus::authn::external::spoof;
 use omicron_nexus::external_api::params;
+use omicron_nexus::external_api::views;
 
 // This test hits a list Nexus API endpoints using both unauthenticated and
 // unauthorized requests to make sure we get the expected behavior (generally:
@@ -107,16 +109,31 @@ lazy_static! {
             url: &*DEMO_ORG_PROJECTS_URL,
             body: serde_json::to_value(&*DEMO_PROJECT_CREATE).unwrap(),
         },
+        // Create an Affinity Group in the Project
+        SetupReq {
+            url: &*DEMO_PROJECT_URL_AFFINITY_GROUPS,
+            body: serde_json::to_value(&*DEMO_AFFINITY_GROUP_CREATE).unwrap(),
+        },
         // Create a Disk in the Project
         SetupReq {
             url: &*DEMO_PROJECT_URL_DISKS,
             body: serde_json::to_value(&*DEMO_DISK_CREATE).unwrap(),
         },
+        // Take a Snapshot of the Disk
+        SetupReq {
+            url: &*DEMO_DISK_URL_SNAPSHOTS,
+            body: serde_json::to_value(&*DEMO_SNAPSHOT_CREATE).unwrap(),
+        },
         // Create an Instance in the Project
         SetupReq {
             url: &*DEMO_PROJECT_URL_INSTANCES,
             body: serde_json::to_value(&*DEMO_INSTANCE_CREATE).unwrap(),
         },
+        // Create a User
+        SetupReq {
+            url: "/users",
+            body: serde_json::to_value(&*DEMO_USER_CREATE).unwrap(),
+        },
     ];
 
     // Organization used for testing
@@ -125,11 +142,16 @@ lazy_static! {
         format!("/organizations/{}", *DEMO_ORG_NAME);
     static ref DEMO_ORG_PROJECTS_URL: String =
         format!("{}/projects", *DEMO_ORG_URL);
+    static ref DEMO_ORG_QUOTA_URL: String =
+        format!("{}/quota", *DEMO_ORG_URL);
+    static ref DEMO_ORG_POLICY_URL: String =
+        format!("{}/policy", *DEMO_ORG_URL);
     static ref DEMO_ORG_CREATE: params::OrganizationCreate =
         params::OrganizationCreate {
             identity: IdentityMetadataCreateParams {
                 name: DEMO_ORG_NAME.clone(),
                 description: "".parse().unwrap(),
+                labels: Default::default(),
             },
         };
 
@@ -139,13 +161,22 @@ lazy_static! {
         format!("{}/{}", *DEMO_ORG_PROJECTS_URL, *DEMO_PROJECT_NAME);
     static ref DEMO_PROJECT_URL_DISKS: String =
         format!("{}/disks", *DEMO_PROJECT_URL);
+    static ref DEMO_PROJECT_URL_AFFINITY_GROUPS: String =
+        format!("{}/affinity-groups", *DEMO_PROJECT_URL);
     static ref DEMO_PROJECT_URL_INSTANCES: String =
         format!("{}/instances", *DEMO_PROJECT_URL);
+    static ref DEMO_PROJECT_QUOTA_URL: String =
+        format!("{}/quota", *DEMO_PROJECT_URL);
+    static ref DEMO_PROJECT_POLICY_URL: String =
+        format!("{}/policy", *DEMO_PROJECT_URL);
+    static ref DEMO_PROJECT_URL_OPERATIONS: String =
+        format!("{}/operations", *DEMO_PROJECT_URL);
     static ref DEMO_PROJECT_CREATE: params::ProjectCreate =
         params::ProjectCreate {
             identity: IdentityMetadataCreateParams {
                 name: DEMO_PROJECT_NAME.clone(),
                 description: "".parse().unwrap(),
+                labels: Default::default(),
             },
         };
 
@@ -158,10 +189,68 @@ lazy_static! {
             identity: IdentityMetadataCreateParams {
                 name: DEMO_DISK_NAME.clone(),
                 description: "".parse().unwrap(),
+                labels: Default::default(),
             },
             snapshot_id: None,
             size: ByteCount::from_gibibytes_u32(16),
         };
+    static ref DEMO_DISK_RESIZE_URL: String =
+        format!("{}/resize", *DEMO_DISK_URL);
+
+    // Quota used for testing
+    static ref DEMO_QUOTA_UPDATE: params::QuotaUpdate =
+        params::QuotaUpdate {
+            instances: Some(10),
+            ..Default::default()
+        };
+
+    // User used for testing
+    static ref DEMO_USER_NAME: Name = "demo-user".parse().unwrap();
+    static ref DEMO_USER_URL: String = format!("/users/{}", *DEMO_USER_NAME);
+    static ref DEMO_USER_CREATE: params::UserCreate = params::UserCreate {
+        identity: IdentityMetadataCreateParams {
+            name: DEMO_USER_NAME.clone(),
+            description: String::from(""),
+            labels: Default::default(),
+        },
+        password: String::from("demo-password"),
+    };
+
+    // Policy used for testing
+    static ref DEMO_POLICY: views::Policy =
+        views::Policy { role_assignments: vec![] };
+
+    // Affinity Group used for testing
+    static ref DEMO_AFFINITY_GROUP_NAME: Name =
+        "demo-affinity-group".parse().unwrap();
+    static ref DEMO_AFFINITY_GROUP_URL: String = format!(
+        "{}/{}",
+        *DEMO_PROJECT_URL_AFFINITY_GROUPS, *DEMO_AFFINITY_GROUP_NAME
+    );
+    static ref DEMO_AFFINITY_GROUP_CREATE: params::AffinityGroupCreate =
+        params::AffinityGroupCreate {
+            identity: IdentityMetadataCreateParams {
+                name: DEMO_AFFINITY_GROUP_NAME.clone(),
+                description: "".parse().unwrap(),
+                labels: Default::default(),
+            },
+            policy: AffinityPolicy::AntiAffinity,
+        };
+
+    // Snapshot used for testing
+    static ref DEMO_DISK_URL_SNAPSHOTS: String =
+        format!("{}/snapshots", *DEMO_DISK_URL);
+    static ref DEMO_SNAPSHOT_NAME: Name = "demo-snapshot".parse().unwrap();
+    static ref DEMO_SNAPSHOT_URL: String =
+        format!("{}/{}", *DEMO_DISK_URL_SNAPSHOTS, *DEMO_SNAPSHOT_NAME);
+    static ref DEMO_SNAPSHOT_CREATE: params::SnapshotCreate =
+        params::SnapshotCreate {
+            identity: IdentityMetadataCreateParams {
+                name: DEMO_SNAPSHOT_NAME.clone(),
+                description: "".parse().unwrap(),
+                labels: Default::default(),
+            },
+        };
 
     // Instance used for testing
     static ref DEMO_INSTANCE_NAME: Name = "demo-instance".parse().unwrap();
@@ -175,6 +264,8 @@ lazy_static! {
         format!("{}/reboot", *DEMO_INSTANCE_URL);
     static ref DEMO_INSTANCE_MIGRATE_URL: String =
         format!("{}/migrate", *DEMO_INSTANCE_URL);
+    static ref DEMO_INSTANCE_SERIAL_HISTORY_URL: String =
+        format!("{}/serial-console/history", *DEMO_INSTANCE_URL);
     static ref DEMO_INSTANCE_DISKS_URL: String =
         format!("{}/disks", *DEMO_INSTANCE_URL);
     static ref DEMO_INSTANCE_DISKS_ATTACH_URL: String =
@@ -186,10 +277,15 @@ lazy_static! {
             identity: IdentityMetadataCreateParams {
                 name: DEMO_INSTANCE_NAME.clone(),
                 description: "".parse().unwrap(),
+                labels: Default::default(),
             },
             ncpus: InstanceCpuCount(1),
             memory: ByteCount::from_gibibytes_u32(16),
             hostname: String::from("demo-instance"),
+            affinity_group: None,
+            user_data: vec![],
+            ssh_public_keys: vec![],
+            network_interfaces: vec![],
         };
 }
 
@@ -283,7 +379,7 @@ impl AllowedMethod {
 
 lazy_static! {
     static ref URL_USERS_DB_INIT: String =
-        format!("/users/{}", authn::USER_DB_INIT.name);
+        format!("/system/users/{}", authn::USER_DB_INIT.name);
 
     /// List of endpoints to be verified
     static ref VERIFY_ENDPOINTS: Vec<VerifyEndpoint> = vec![
@@ -309,12 +405,33 @@ lazy_static! {
                     serde_json::to_value(&params::OrganizationUpdate {
                         identity: IdentityMetadataUpdateParams {
                             name: None,
-                            description: Some("different".to_string())
+                            description: Some("different".to_string()),
+                            labels: None,
                         }
                     }).unwrap()
                 ),
             ],
         },
+        VerifyEndpoint {
+            url: &*DEMO_ORG_QUOTA_URL,
+            visibility: Visibility::Protected,
+            allowed_methods: vec![
+                AllowedMethod::Get,
+                AllowedMethod::Put(
+                    serde_json::to_value(&*DEMO_QUOTA_UPDATE).unwrap()
+                ),
+            ],
+        },
+        VerifyEndpoint {
+            url: &*DEMO_ORG_POLICY_URL,
+            visibility: Visibility::Protected,
+            allowed_methods: vec![
+                AllowedMethod::Get,
+                AllowedMethod::Put(
+                    serde_json::to_value(&*DEMO_POLICY).unwrap()
+                ),
+            ],
+        },
 
         /* Projects */
 
@@ -346,10 +463,70 @@ lazy_static! {
                     serde_json::to_value(params::ProjectUpdate{
                         identity: IdentityMetadataUpdateParams {
                             name: None,
-                            description: Some("different".to_string())
+                            description: Some("different".to_string()),
+                            labels: None,
+                        },
+                    }).unwrap()
+                ),
+            ],
+        },
+        VerifyEndpoint {
+            url: &*DEMO_PROJECT_QUOTA_URL,
+            visibility: Visibility::Protected,
+            allowed_methods: vec![
+                AllowedMethod::Get,
+                AllowedMethod::Put(
+                    serde_json::to_value(&*DEMO_QUOTA_UPDATE).unwrap()
+                ),
+            ],
+        },
+        VerifyEndpoint {
+            url: &*DEMO_PROJECT_POLICY_URL,
+            visibility: Visibility::Protected,
+            allowed_methods: vec![
+                AllowedMethod::Get,
+                AllowedMethod::Put(
+                    serde_json::to_value(&*DEMO_POLICY).unwrap()
+                ),
+            ],
+        },
+
+        /* Operations */
+
+        VerifyEndpoint {
+            url: &*DEMO_PROJECT_URL_OPERATIONS,
+            visibility: Visibility::Protected,
+            allowed_methods: vec![AllowedMethod::Get],
+        },
+
+        /* Affinity Groups */
+
+        VerifyEndpoint {
+            url: &*DEMO_PROJECT_URL_AFFINITY_GROUPS,
+            visibility: Visibility::Protected,
+            allowed_methods: vec![
+                AllowedMethod::Get,
+                AllowedMethod::Post(
+                    serde_json::to_value(&*DEMO_AFFINITY_GROUP_CREATE).unwrap()
+                ),
+            ],
+        },
+
+        VerifyEndpoint {
+            url: &*DEMO_AFFINITY_GROUP_URL,
+            visibility: Visibility::Protected,
+            allowed_methods: vec![
+                AllowedMethod::Get,
+                AllowedMethod::Put(
+                    serde_json::to_value(params::AffinityGroupUpdate {
+                        identity: IdentityMetadataUpdateParams {
+                            name: None,
+                            description: Some("different".to_string()),
+                            labels: None,
                         },
                     }).unwrap()
                 ),
+                AllowedMethod::Delete,
             ],
         },
 
@@ -369,6 +546,45 @@ lazy_static! {
         VerifyEndpoint {
             url: &*DEMO_DISK_URL,
             visibility: Visibility::Protected,
+            allowed_methods: vec![
+                AllowedMethod::Get,
+                AllowedMethod::Put(
+                    serde_json::to_value(params::DiskUpdate {
+                        labels: None,
+                    }).unwrap()
+                ),
+                AllowedMethod::Delete,
+            ],
+        },
+
+        VerifyEndpoint {
+            url: &*DEMO_DISK_RESIZE_URL,
+            visibility: Visibility::Protected,
+            allowed_methods: vec![
+                AllowedMethod::Post(
+                    serde_json::to_value(params::DiskResize {
+                        size: ByteCount::from_gibibytes_u32(32)
+                    }).unwrap()
+                ),
+            ],
+        },
+
+        /* Snapshots */
+
+        VerifyEndpoint {
+            url: &*DEMO_DISK_URL_SNAPSHOTS,
+            visibility: Visibility::Protected,
+            allowed_methods: vec![
+                AllowedMethod::Get,
+                AllowedMethod::Post(
+                    serde_json::to_value(&*DEMO_SNAPSHOT_CREATE).unwrap()
+                ),
+            ],
+        },
+
+        VerifyEndpoint {
+            url: &*DEMO_SNAPSHOT_URL,
+            visibility: Visibility::Protected,
             allowed_methods: vec![
                 AllowedMethod::Get,
                 AllowedMethod::Delete,
@@ -416,6 +632,13 @@ lazy_static! {
             allowed_methods: vec![
                 AllowedMethod::Get,
                 AllowedMethod::Delete,
+                AllowedMethod::Put(
+                    serde_json::to_value(&params::InstanceUpdate {
+                        ncpus: Some(InstanceCpuCount(2)),
+                        memory: None,
+                        labels: None,
+                    }).unwrap()
+                ),
             ],
         },
 
@@ -440,6 +663,11 @@ lazy_static! {
                 AllowedMethod::Post(serde_json::Value::Null)
             ],
         },
+        VerifyEndpoint {
+            url: &*DEMO_INSTANCE_SERIAL_HISTORY_URL,
+            visibility: Visibility::Protected,
+            allowed_methods: vec![AllowedMethod::Get],
+        },
         VerifyEndpoint {
             url: &*DEMO_INSTANCE_MIGRATE_URL,
             visibility: Visibility::Protected,
@@ -468,6 +696,25 @@ lazy_static! {
         VerifyEndpoint {
             url: "/users",
             visibility: Visibility::Public,
+            allowed_methods: vec![
+                AllowedMethod::Get,
+                AllowedMethod::Post(
+                    serde_json::to_value(&*DEMO_USER_CREATE).unwrap()
+                ),
+            ],
+        },
+        VerifyEndpoint {
+            url: &*DEMO_USER_URL,
+            visibility: Visibility::Protected,
+            allowed_methods: vec![
+                AllowedMethod::Get,
+                AllowedMethod::Delete,
+            ],
+        },
+
+        VerifyEndpoint {
+            url: "/system/users",
+            visibility: Visibility::Public,
             allowed_methods: vec![AllowedMethod::Get],
         },
         VerifyEndpoint {
@@ -475,6 +722,14 @@ lazy_static! {
             visibility: Visibility::Protected,
             allowed_methods: vec![AllowedMethod::Get],
         },
+
+        /* Audit log */
+
+        VerifyEndpoint {
+            url: "/audit-log",
+            visibility: Visibility::Public,
+            allowed_methods: vec![AllowedMethod::Get],
+        },
     ];
 }
 
@@ -0,0 +1,222 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Tests users who log in with a password
+
+use dropshot::test_util::ClientTestContext;
+use dropshot::HttpErrorResponseBody;
+use dropshot::ResultsPage;
+use headers::authorization::Authorization;
+use headers::HeaderMapExt;
+use http::header;
+use http::method::Method;
+use http::StatusCode;
+use nexus_test_utils::http_testing::AuthnMode;
+use nexus_test_utils::http_testing::NexusRequest;
+use nexus_test_utils::http_testing::RequestBuilder;
+use nexus_test_utils::resource_helpers::create_organization;
+use nexus_test_utils::resource_helpers::create_user;
+use nexus_test_utils::ControlPlaneTestContext;
+use nexus_test_utils_macros::nexus_test;
+use omicron_common::api::external::IdentityMetadata;
+use omicron_common::api::external::IdentityMetadataCreateParams;
+use omicron_common::api::external::RoleName;
+use omicron_nexus::external_api::params;
+use omicron_nexus::external_api::views::{
+    Policy, RoleAssignment, SessionUser, User,
+};
+
+const ORG_NAME: &str = "test-org";
+
+#[nexus_test]
+async fn test_users(cptestctx: &ControlPlaneTestContext) {
+    let client = &cptestctx.external_client;
+
+    // Create a couple of users and make sure we can see them.
+    let alice = create_user(client, "alice", "correct horse").await;
+    let bob = create_user(client, "bob", "battery staple").await;
+    assert_eq!(alice.identity.name, "alice");
+    assert_eq!(alice.identity.description, "a user");
+    let users = users_list(client).await;
+    assert_eq!(users, vec![alice.identity.clone(), bob.identity.clone()]);
+    let user = NexusRequest::object_get(client, "/users/alice")
+        .authn_as(AuthnMode::PrivilegedUser)
+        .execute()
+        .await
+        .unwrap()
+        .parsed_body::<User>()
+        .unwrap();
+    assert_eq!(user.identity, alice.identity);
+
+    // Names must be unique and passwords can't be empty.
+    let error = user_create_error(client, "alice", "whatever").await;
+    assert_eq!(error.message, "already exists: user \"alice\"");
+    let error = user_create_error(client, "carol", "").await;
+    assert_eq!(
+        error.message,
+        "unsupported value for \"password\": must be between 1 and 512 bytes \
+         long"
+    );
+
+    // Only fleet administrators can manage users.
+    NexusRequest::expect_failure(
+        client,
+        StatusCode::FORBIDDEN,
+        Method::GET,
+        "/users",
+    )
+    .authn_as(AuthnMode::UnprivilegedUser)
+    .execute()
+    .await
+    .unwrap();
+    NexusRequest::expect_failure(
+        client,
+        StatusCode::NOT_FOUND,
+        Method::GET,
+        "/users/alice",
+    )
+    .authn_as(AuthnMode::UnprivilegedUser)
+    .execute()
+    .await
+    .unwrap();
+
+    // Alice can authenticate with her password, but not with anybody else's.
+    let session_me = "/session/me";
+    let me = basic_request(client, session_me, "alice", "correct horse")
+        .expect_status(Some(StatusCode::OK))
+        .execute()
+        .await
+        .unwrap()
+        .parsed_body::<SessionUser>()
+        .unwrap();
+    assert_eq!(me.id, alice.identity.id);
+    basic_request(client, session_me, "alice", "battery staple")
+        .expect_status(Some(StatusCode::UNAUTHORIZED))
+        .execute()
+        .await
+        .unwrap();
+    basic_request(client, session_me, "mallory", "correct horse")
+        .expect_status(Some(StatusCode::UNAUTHORIZED))
+        .execute()
+        .await
+        .unwrap();
+
+    // She can't see much of anything until she's granted a role.
+    create_organization(client, ORG_NAME).await;
+    let org_url = format!("/organizations/{}", ORG_NAME);
+    basic_request(client, &org_url, "alice", "correct horse")
+        .expect_status(Some(StatusCode::NOT_FOUND))
+        .execute()
+        .await
+        .unwrap();
+    NexusRequest::object_put(
+        client,
+        &format!("{}/policy", org_url),
+        Some(&Policy {
+            role_assignments: vec![RoleAssignment {
+                user_id: alice.identity.id,
+                role_name: RoleName::new("organization", "admin"),
+            }],
+        }),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap();
+    basic_request(client, &org_url, "alice", "correct horse")
+        .expect_status(Some(StatusCode::OK))
+        .execute()
+        .await
+        .unwrap();
+
+    // Deleting her takes away her access and her roles.
+    NexusRequest::object_delete(client, "/users/alice")
+        .authn_as(AuthnMode::PrivilegedUser)
+        .execute()
+        .await
+        .unwrap();
+    basic_request(client, &org_url, "alice", "correct horse")
+        .expect_status(Some(StatusCode::UNAUTHORIZED))
+        .execute()
+        .await
+        .unwrap();
+    let policy =
+        NexusRequest::object_get(client, &format!("{}/policy", org_url))
+            .authn_as(AuthnMode::PrivilegedUser)
+            .execute()
+            .await
+            .unwrap()
+            .parsed_body::<Policy>()
+            .unwrap();
+    assert_eq!(policy.role_assignments, vec![]);
+    assert_eq!(users_list(client).await, vec![bob.identity]);
+    NexusRequest::expect_failure(
+        client,
+        StatusCode::NOT_FOUND,
+        Method::DELETE,
+        "/users/alice",
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap();
+
+    // Her name can be reused.
+    create_user(client, "alice", "new phone who dis").await;
+}
+
+async fn users_list(client: &ClientTestContext) -> Vec<IdentityMetadata> {
+    NexusRequest::object_get(client, "/users")
+        .authn_as(AuthnMode::PrivilegedUser)
+        .execute()
+        .await
+        .unwrap()
+        .parsed_body::<ResultsPage<User>>()
+        .unwrap()
+        .items
+        .into_iter()
+        .map(|u| u.identity)
+        .collect()
+}
+
+async fn user_create_error(
+    client: &ClientTestContext,
+    name: &str,
+    password: &str,
+) -> HttpErrorResponseBody {
+    NexusRequest::new(
+        RequestBuilder::new(client, Method::POST, "/users")
+            .body(Some(&params::UserCreate {
+                identity: IdentityMetadataCreateParams {
+                    name: name.parse().unwrap(),
+                    description: String::from("a user"),
+                    labels: Default::default(),
+                },
+                password: String::from(password),
+            }))
+            .expect_status(Some(StatusCode::BAD_REQUEST)),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap()
+    .parsed_body()
+    .unwrap()
+}
+
+/// Returns a GET request for `url` that authenticates with HTTP Basic
+/// credentials
+fn basic_request<'a>(
+    client: &'a ClientTestContext,
+    url: &str,
+    username: &str,
+    password: &str,
+) -> RequestBuilder<'a> {
+    let mut headers = http::HeaderMap::new();
+    headers.typed_insert(Authorization::basic(username, password));
+    RequestBuilder::new(client, Method::GET, url).header(
+        header::AUTHORIZATION,
+        headers.get(header::AUTHORIZATION).unwrap().clone(),
+    )
+}
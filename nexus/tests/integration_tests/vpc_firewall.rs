@@ -4,21 +4,32 @@
 
 use http::method::Method;
 use http::StatusCode;
+use nexus_test_utils::http_testing::{AuthnMode, NexusRequest, RequestBuilder};
 use omicron_common::api::external::{
-    IdentityMetadata, L4Port, L4PortRange, VpcFirewallRule,
-    VpcFirewallRuleAction, VpcFirewallRuleDirection, VpcFirewallRuleFilter,
-    VpcFirewallRuleHostFilter, VpcFirewallRulePriority,
-    VpcFirewallRuleProtocol, VpcFirewallRuleStatus, VpcFirewallRuleTarget,
-    VpcFirewallRuleUpdate, VpcFirewallRuleUpdateParams, VpcFirewallRules,
+    IdentityMetadata, IdentityMetadataCreateParams,
+    IdentityMetadataUpdateParams, Ipv4Net, L4Port, L4PortRange,
+    NetworkInterface, VpcFirewallRule, VpcFirewallRuleAction,
+    VpcFirewallRuleDirection, VpcFirewallRuleFilter, VpcFirewallRuleHostFilter,
+    VpcFirewallRulePriority, VpcFirewallRuleProtocol, VpcFirewallRuleStatus,
+    VpcFirewallRuleTarget, VpcFirewallRuleUpdate, VpcFirewallRuleUpdateParams,
+    VpcFirewallRules,
 };
-use omicron_nexus::external_api::views::Vpc;
+use omicron_common::api::internal::sled_agent::{
+    FirewallRule, FirewallRuleHost,
+};
+use omicron_nexus::external_api::params;
+use omicron_nexus::external_api::views::{Vpc, VpcSubnet};
+use omicron_nexus::TestInterfaces as _;
+use sled_agent_client::TestInterfaces as _;
 use std::convert::TryFrom;
+use std::net::IpAddr;
 use uuid::Uuid;
 
 use dropshot::test_util::{object_delete, object_get};
 
 use nexus_test_utils::resource_helpers::{
-    create_organization, create_project, create_vpc,
+    create_instance, create_organization, create_project, create_vpc,
+    objects_list_page_authz,
 };
 use nexus_test_utils::ControlPlaneTestContext;
 use nexus_test_utils_macros::nexus_test;
@@ -137,6 +148,227 @@ async fn test_vpc_firewall(cptestctx: &ControlPlaneTestContext) {
         .await;
 }
 
+/// Returns the firewall rules that the simulated sled agent last received for
+/// the Instance's only network interface
+async fn instance_firewall_rules(
+    cptestctx: &ControlPlaneTestContext,
+    instance_url: &str,
+) -> Option<Vec<FirewallRule>> {
+    let nics = objects_list_page_authz::<NetworkInterface>(
+        &cptestctx.external_client,
+        &format!("{}/network-interfaces", instance_url),
+    )
+    .await
+    .items;
+    assert_eq!(nics.len(), 1);
+    cptestctx.sled_agent.sled_agent.firewall_rules(nics[0].identity.id).await
+}
+
+fn host(address: IpAddr, prefix_len: u8) -> FirewallRuleHost {
+    FirewallRuleHost { address, prefix_len }
+}
+
+#[nexus_test]
+async fn test_vpc_firewall_rules_sent_to_sleds(
+    cptestctx: &ControlPlaneTestContext,
+) {
+    let client = &cptestctx.external_client;
+    let nexus = &cptestctx.server.apictx.nexus;
+    let org_name = "test-org";
+    let project_name = "springfield-squidport";
+    create_organization(&client, &org_name).await;
+    create_project(&client, &org_name, &project_name).await;
+    let project_url =
+        format!("/organizations/{}/projects/{}", org_name, project_name);
+    let firewall_url = format!("{}/vpcs/default/firewall/rules", project_url);
+    let web_url = format!("{}/instances/web", project_url);
+    let db_url = format!("{}/instances/db", project_url);
+    create_instance(client, org_name, project_name, "web").await;
+    let db = create_instance(client, org_name, project_name, "db").await;
+
+    // A new Instance's sled gets the default rules for its interface, with
+    // "vpc:default" resolved to the default subnet's address blocks.
+    let subnet: VpcSubnet = NexusRequest::object_get(
+        client,
+        &format!("{}/vpcs/default/subnets/default", project_url),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap()
+    .parsed_body()
+    .unwrap();
+    let rules = instance_firewall_rules(cptestctx, &web_url).await.unwrap();
+    let names = NexusRequest::object_get(client, &firewall_url)
+        .authn_as(AuthnMode::PrivilegedUser)
+        .execute()
+        .await
+        .unwrap()
+        .parsed_body::<VpcFirewallRules>()
+        .unwrap()
+        .rules
+        .into_iter()
+        .map(|rule| (rule.identity.id, rule.identity.name.to_string()))
+        .collect::<std::collections::BTreeMap<_, _>>();
+    let rule_names =
+        rules.iter().map(|r| names[&r.rule_id].as_str()).collect::<Vec<_>>();
+    assert_eq!(
+        rule_names,
+        vec!["allow-icmp", "allow-internal-inbound", "allow-rdp", "allow-ssh"]
+    );
+    assert_eq!(
+        rules[1].hosts,
+        Some(vec![
+            host(
+                IpAddr::V4(subnet.ipv4_block.network()),
+                subnet.ipv4_block.prefix()
+            ),
+            host(
+                IpAddr::V6(subnet.ipv6_block.network()),
+                subnet.ipv6_block.prefix()
+            ),
+        ])
+    );
+    assert_eq!(rules[3].hosts, None);
+    assert_eq!(rules[3].ports, Some(vec!["22".parse().unwrap()]));
+    assert_eq!(
+        instance_firewall_rules(cptestctx, &db_url).await,
+        Some(rules.clone())
+    );
+
+    // Changing the rules sends the new ones to the sled: here, only "web"
+    // is targeted, and only traffic from "db" is allowed.
+    let db_ip = objects_list_page_authz::<NetworkInterface>(
+        client,
+        &format!("{}/network-interfaces", db_url),
+    )
+    .await
+    .items[0]
+        .ip;
+    let update_params = VpcFirewallRuleUpdateParams {
+        rules: vec![VpcFirewallRuleUpdate {
+            name: "allow-postgres".parse().unwrap(),
+            action: VpcFirewallRuleAction::Allow,
+            description: "allow the database to call back".to_string(),
+            status: VpcFirewallRuleStatus::Enabled,
+            targets: vec!["instance:web".parse().unwrap()],
+            filters: VpcFirewallRuleFilter {
+                hosts: Some(vec!["instance:db".parse().unwrap()]),
+                ports: Some(vec!["5432".parse().unwrap()]),
+                protocols: Some(vec![VpcFirewallRuleProtocol::Tcp]),
+            },
+            direction: VpcFirewallRuleDirection::Inbound,
+            priority: VpcFirewallRulePriority(100),
+        }],
+    };
+    let updated: VpcFirewallRules =
+        NexusRequest::object_put(client, &firewall_url, Some(&update_params))
+            .authn_as(AuthnMode::PrivilegedUser)
+            .execute()
+            .await
+            .unwrap()
+            .parsed_body()
+            .unwrap();
+    let rules = instance_firewall_rules(cptestctx, &web_url).await.unwrap();
+    assert_eq!(rules.len(), 1);
+    assert_eq!(rules[0].rule_id, updated.rules[0].identity.id);
+    assert_eq!(rules[0].hosts, Some(vec![host(db_ip, 32)]));
+    assert_eq!(rules[0].protocols, Some(vec![VpcFirewallRuleProtocol::Tcp]));
+    assert_eq!(instance_firewall_rules(cptestctx, &db_url).await, None);
+
+    // Once "db" is gone, the rule for "web" matches no hosts at all.
+    let sa = nexus.instance_sled_by_id(&db.identity.id).await.unwrap();
+    sa.instance_finish_transition(db.identity.id).await;
+    NexusRequest::new(
+        RequestBuilder::new(client, Method::POST, &format!("{}/stop", db_url))
+            .body(None as Option<&serde_json::Value>)
+            .expect_status(Some(StatusCode::ACCEPTED)),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap();
+    sa.instance_finish_transition(db.identity.id).await;
+    NexusRequest::object_delete(client, &db_url)
+        .authn_as(AuthnMode::PrivilegedUser)
+        .execute()
+        .await
+        .unwrap();
+    let rules = instance_firewall_rules(cptestctx, &web_url).await.unwrap();
+    assert_eq!(rules[0].hosts, Some(vec![]));
+
+    // Rules are sent again when a Subnet they name comes or goes.
+    let mut update_params = update_params;
+    update_params.rules[0].filters.hosts =
+        Some(vec!["subnet:extra".parse().unwrap()]);
+    NexusRequest::object_put(client, &firewall_url, Some(&update_params))
+        .authn_as(AuthnMode::PrivilegedUser)
+        .execute()
+        .await
+        .unwrap();
+    let rules = instance_firewall_rules(cptestctx, &web_url).await.unwrap();
+    assert_eq!(rules[0].hosts, Some(vec![]));
+    let subnets_url = format!("{}/vpcs/default/subnets", project_url);
+    let extra: VpcSubnet = NexusRequest::objects_post(
+        client,
+        &subnets_url,
+        &params::VpcSubnetCreate {
+            identity: IdentityMetadataCreateParams {
+                name: "extra".parse().unwrap(),
+                description: String::from("extra subnet"),
+                labels: Default::default(),
+            },
+            ipv4_block: Ipv4Net("10.1.0.0/24".parse().unwrap()),
+            ipv6_block: None,
+        },
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap()
+    .parsed_body()
+    .unwrap();
+    let rules = instance_firewall_rules(cptestctx, &web_url).await.unwrap();
+    assert_eq!(
+        rules[0].hosts,
+        Some(vec![
+            host(
+                IpAddr::V4(extra.ipv4_block.network()),
+                extra.ipv4_block.prefix()
+            ),
+            host(
+                IpAddr::V6(extra.ipv6_block.network()),
+                extra.ipv6_block.prefix()
+            ),
+        ])
+    );
+
+    // Renaming the Subnet means the rule no longer matches it.
+    NexusRequest::new(
+        RequestBuilder::new(
+            client,
+            Method::PUT,
+            &format!("{}/extra", subnets_url),
+        )
+        .body(Some(&params::VpcSubnetUpdate {
+            identity: IdentityMetadataUpdateParams {
+                name: Some("spare".parse().unwrap()),
+                description: None,
+                labels: None,
+            },
+            ipv4_block: None,
+            ipv6_block: None,
+        }))
+        .expect_status(Some(StatusCode::NO_CONTENT)),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap();
+    let rules = instance_firewall_rules(cptestctx, &web_url).await.unwrap();
+    assert_eq!(rules[0].hosts, Some(vec![]));
+}
+
 fn is_default_firewall_rules(rules: &Vec<VpcFirewallRule>) -> bool {
     let default_rules = vec![
         VpcFirewallRule {
@@ -63,6 +63,7 @@ async fn test_vpc_routers(cptestctx: &ControlPlaneTestContext) {
         identity: IdentityMetadataCreateParams {
             name: router_name.parse().unwrap(),
             description: "it's not really a router".to_string(),
+            labels: Default::default(),
         },
     };
     let router: VpcRouter =
@@ -107,6 +108,7 @@ async fn test_vpc_routers(cptestctx: &ControlPlaneTestContext) {
         identity: IdentityMetadataCreateParams {
             name: router2_name.parse().unwrap(),
             description: "it's also not really a router".to_string(),
+            labels: Default::default(),
         },
     };
     let router2: VpcRouter =
@@ -128,6 +130,7 @@ async fn test_vpc_routers(cptestctx: &ControlPlaneTestContext) {
         identity: IdentityMetadataUpdateParams {
             name: Some("new-name".parse().unwrap()),
             description: Some("another description".to_string()),
+            labels: None,
         },
     };
     client
@@ -86,6 +86,7 @@ async fn test_vpc_subnets(cptestctx: &ControlPlaneTestContext) {
         identity: IdentityMetadataCreateParams {
             name: subnet_name.parse().unwrap(),
             description: "it's below the net".to_string(),
+            labels: Default::default(),
         },
         ipv4_block,
         ipv6_block,
@@ -132,6 +133,7 @@ async fn test_vpc_subnets(cptestctx: &ControlPlaneTestContext) {
         identity: IdentityMetadataCreateParams {
             name: "new-name".parse().unwrap(),
             description: "it's below the net".to_string(),
+            labels: Default::default(),
         },
         ipv4_block,
         ipv6_block,
@@ -152,6 +154,7 @@ async fn test_vpc_subnets(cptestctx: &ControlPlaneTestContext) {
         identity: IdentityMetadataCreateParams {
             name: subnet_name.parse().unwrap(),
             description: "it's below the net".to_string(),
+            labels: Default::default(),
         },
         ipv4_block: other_ipv4_block,
         ipv6_block: other_ipv6_block,
@@ -181,6 +184,7 @@ async fn test_vpc_subnets(cptestctx: &ControlPlaneTestContext) {
         identity: IdentityMetadataCreateParams {
             name: subnet2_name.parse().unwrap(),
             description: "it's also below the net".to_string(),
+            labels: Default::default(),
         },
         ipv4_block,
         ipv6_block: None,
@@ -205,6 +209,7 @@ async fn test_vpc_subnets(cptestctx: &ControlPlaneTestContext) {
         identity: IdentityMetadataUpdateParams {
             name: Some("new-name".parse().unwrap()),
             description: Some("another description".to_string()),
+            labels: None,
         },
         ipv4_block: None,
         ipv6_block: None,
@@ -69,6 +69,7 @@ async fn test_vpcs(cptestctx: &ControlPlaneTestContext) {
                 identity: IdentityMetadataCreateParams {
                     name: "just-rainsticks".parse().unwrap(),
                     description: String::from("vpc description"),
+                    labels: Default::default(),
                 },
                 ipv6_prefix: Some(bad_prefix),
                 dns_name: "abc".parse().unwrap(),
@@ -124,6 +125,7 @@ async fn test_vpcs(cptestctx: &ControlPlaneTestContext) {
         identity: IdentityMetadataUpdateParams {
             name: Some("new-name".parse().unwrap()),
             description: Some("another description".to_string()),
+            labels: None,
         },
         dns_name: Some("def".parse().unwrap()),
     };
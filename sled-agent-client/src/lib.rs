@@ -262,10 +262,21 @@ impl From<omicron_common::api::internal::sled_agent::InstanceHardware>
         Self {
             nics: s.nics.iter().map(Into::into).collect(),
             runtime: s.runtime.into(),
+            guest_metadata: s.guest_metadata.into(),
         }
     }
 }
 
+impl From<omicron_common::api::internal::sled_agent::GuestMetadata>
+    for types::GuestMetadata
+{
+    fn from(
+        s: omicron_common::api::internal::sled_agent::GuestMetadata,
+    ) -> Self {
+        Self { user_data: s.user_data, ssh_public_keys: s.ssh_public_keys }
+    }
+}
+
 impl From<omicron_common::api::internal::sled_agent::InstanceMigrateParams>
     for types::InstanceMigrateParams
 {
@@ -290,6 +301,7 @@ impl From<&omicron_common::api::external::NetworkInterface>
             time_created: s.identity.time_created,
             time_modified: s.identity.time_modified,
             ip: s.ip.to_string(),
+            external_ip: s.external_ip.map(|ip| ip.to_string()),
             instance_id: s.instance_id,
             mac: s.mac.into(),
             subnet_id: s.subnet_id,
@@ -309,6 +321,119 @@ impl From<omicron_common::api::external::MacAddr> for types::MacAddr {
         Self(s.0.to_string())
     }
 }
+impl From<omicron_common::api::internal::sled_agent::FirewallRulesEnsureBody>
+    for types::FirewallRulesEnsureBody
+{
+    fn from(
+        s: omicron_common::api::internal::sled_agent::FirewallRulesEnsureBody,
+    ) -> Self {
+        Self { interfaces: s.interfaces.into_iter().map(Into::into).collect() }
+    }
+}
+
+impl From<omicron_common::api::internal::sled_agent::NetworkInterfaceFirewallRules>
+    for types::NetworkInterfaceFirewallRules
+{
+    fn from(
+        s: omicron_common::api::internal::sled_agent::NetworkInterfaceFirewallRules,
+    ) -> Self {
+        Self {
+            interface_id: s.interface_id,
+            rules: s.rules.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl From<omicron_common::api::internal::sled_agent::FirewallRule>
+    for types::FirewallRule
+{
+    fn from(
+        s: omicron_common::api::internal::sled_agent::FirewallRule,
+    ) -> Self {
+        Self {
+            rule_id: s.rule_id,
+            direction: s.direction.into(),
+            hosts: s
+                .hosts
+                .map(|hosts| hosts.into_iter().map(Into::into).collect()),
+            protocols: s.protocols.map(|protocols| {
+                protocols.into_iter().map(Into::into).collect()
+            }),
+            ports: s
+                .ports
+                .map(|ports| ports.into_iter().map(Into::into).collect()),
+            action: s.action.into(),
+            priority: s.priority.0,
+        }
+    }
+}
+
+impl From<omicron_common::api::internal::sled_agent::FirewallRuleHost>
+    for types::FirewallRuleHost
+{
+    fn from(
+        s: omicron_common::api::internal::sled_agent::FirewallRuleHost,
+    ) -> Self {
+        Self { address: s.address.to_string(), prefix_len: s.prefix_len }
+    }
+}
+
+impl From<omicron_common::api::external::VpcFirewallRuleDirection>
+    for types::VpcFirewallRuleDirection
+{
+    fn from(
+        s: omicron_common::api::external::VpcFirewallRuleDirection,
+    ) -> Self {
+        match s {
+            omicron_common::api::external::VpcFirewallRuleDirection::Inbound => {
+                Self::Inbound
+            }
+            omicron_common::api::external::VpcFirewallRuleDirection::Outbound => {
+                Self::Outbound
+            }
+        }
+    }
+}
+
+impl From<omicron_common::api::external::VpcFirewallRuleProtocol>
+    for types::VpcFirewallRuleProtocol
+{
+    fn from(s: omicron_common::api::external::VpcFirewallRuleProtocol) -> Self {
+        match s {
+            omicron_common::api::external::VpcFirewallRuleProtocol::Tcp => {
+                Self::Tcp
+            }
+            omicron_common::api::external::VpcFirewallRuleProtocol::Udp => {
+                Self::Udp
+            }
+            omicron_common::api::external::VpcFirewallRuleProtocol::Icmp => {
+                Self::Icmp
+            }
+        }
+    }
+}
+
+impl From<omicron_common::api::external::VpcFirewallRuleAction>
+    for types::VpcFirewallRuleAction
+{
+    fn from(s: omicron_common::api::external::VpcFirewallRuleAction) -> Self {
+        match s {
+            omicron_common::api::external::VpcFirewallRuleAction::Allow => {
+                Self::Allow
+            }
+            omicron_common::api::external::VpcFirewallRuleAction::Deny => {
+                Self::Deny
+            }
+        }
+    }
+}
+
+impl From<omicron_common::api::external::L4PortRange> for types::L4PortRange {
+    fn from(s: omicron_common::api::external::L4PortRange) -> Self {
+        Self(s.to_string())
+    }
+}
+
 /**
  * Exposes additional [`Client`] interfaces for use by the test suite. These
  * are bonus endpoints, not generated in the real client.
@@ -16,7 +16,7 @@ use dropshot::ConfigLoggingLevel;
 use omicron_common::cmd::fatal;
 use omicron_common::cmd::CmdError;
 use omicron_sled_agent::sim::{
-    run_server, Config, ConfigStorage, ConfigZpool, SimMode,
+    run_server, Config, ConfigHardware, ConfigStorage, ConfigZpool, SimMode,
 };
 use std::net::SocketAddr;
 use structopt::StructOpt;
@@ -80,6 +80,11 @@ async fn do_run() -> Result<(), CmdError> {
             zpools: vec![ConfigZpool { size: 1 << 40 }; 10],
             ip: args.sled_agent_addr.ip(),
         },
+        // Pretend to be a modestly-sized server.
+        hardware: ConfigHardware {
+            hardware_threads: 32,
+            physical_ram: 64 << 30,
+        },
     };
 
     run_server(&config).await.map_err(CmdError::Failure)
@@ -208,6 +208,12 @@ impl Agent {
             &std::fs::read_to_string(tar_source.join("digest.toml"))?,
         )?;
 
+        // Nexus keeps the internal DNS server's zone up to date, so start the
+        // server first.
+        // TODO-correctness: Like Nexus, the internal DNS server may not run on
+        // every sled.
+        self.launch(&digests, &tar_source, &destination, "internal-dns")?;
+
         // TODO-correctness: Nexus may not be enabled on all racks.
         // Some decision-making logic should be used here to make this
         // conditional.
@@ -6,4 +6,6 @@
 
 pub mod disk;
 pub mod instance;
+pub mod nocloud;
+pub mod serial_console;
 pub mod vlan;
@@ -0,0 +1,418 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Describes the data an Instance's guest initializes itself from, in the
+//! layout of cloud-init's "NoCloud" data source.
+//!
+//! A NoCloud seed is a pair of files, "meta-data" and "user-data".  The sled
+//! agent writes them to a small FAT filesystem labelled "cidata", which
+//! Propolis attaches to the guest as a read-only disk.  cloud-init finds the
+//! disk by its label.
+
+use omicron_common::api::internal::sled_agent::GuestMetadata;
+use std::convert::TryFrom;
+use uuid::Uuid;
+
+/// One of the files making up a NoCloud seed
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum NoCloudFile {
+    MetaData,
+    UserData,
+}
+
+impl NoCloudFile {
+    /// Returns the name cloud-init looks for the file under.
+    pub fn name(&self) -> &'static str {
+        match self {
+            NoCloudFile::MetaData => "meta-data",
+            NoCloudFile::UserData => "user-data",
+        }
+    }
+
+    // Returns the 8.3 name stored alongside the file's long name.
+    fn short_name(&self) -> &'static [u8; 11] {
+        match self {
+            NoCloudFile::MetaData => b"META-D~1   ",
+            NoCloudFile::UserData => b"USER-D~1   ",
+        }
+    }
+}
+
+/// Volume label cloud-init identifies the seed's filesystem by
+const CIDATA_LABEL: &[u8; 11] = b"CIDATA     ";
+
+const SECTOR_SIZE: usize = 512;
+const DIR_ENTRY_SIZE: usize = 32;
+/// Root directory entries in the image; only five are used.
+const ROOT_ENTRIES: usize = SECTOR_SIZE / DIR_ENTRY_SIZE;
+/// Largest number of clusters a FAT12 filesystem may have
+const MAX_FAT12_CLUSTERS: usize = 4084;
+/// Media descriptor for fixed disks
+const MEDIA_FIXED: u8 = 0xf8;
+/// Date (1980-01-01) stamped on every directory entry
+const FAT_EPOCH: u16 = (1 << 5) | 1;
+
+/// The NoCloud seed for a single Instance.
+///
+/// Deliberately not `Debug`, since the user data may contain secrets.
+#[derive(Clone)]
+pub struct NoCloudSeed {
+    meta_data: Vec<u8>,
+    user_data: Vec<u8>,
+    volume_id: u32,
+}
+
+impl NoCloudSeed {
+    pub fn new(
+        instance_id: &Uuid,
+        hostname: &str,
+        guest_metadata: &GuestMetadata,
+    ) -> Self {
+        // JSON is a subset of the YAML that cloud-init expects here, and using
+        // it saves quoting the values by hand.
+        let meta_data = serde_json::json!({
+            "instance-id": instance_id.to_string(),
+            "local-hostname": hostname,
+            "public-keys": guest_metadata.ssh_public_keys,
+        });
+        let mut meta_data = serde_json::to_vec_pretty(&meta_data).unwrap();
+        meta_data.push(b'\n');
+        let volume_id = u32::from_le_bytes(
+            <[u8; 4]>::try_from(&instance_id.as_bytes()[..4]).unwrap(),
+        );
+        NoCloudSeed {
+            meta_data,
+            user_data: guest_metadata.user_data.clone(),
+            volume_id,
+        }
+    }
+
+    /// Returns the contents of one of the seed's files.
+    pub fn file(&self, file: NoCloudFile) -> &[u8] {
+        match file {
+            NoCloudFile::MetaData => &self.meta_data,
+            NoCloudFile::UserData => &self.user_data,
+        }
+    }
+
+    /// Returns an image of a FAT12 filesystem labelled "cidata" holding the
+    /// seed's files, suitable for attaching to the guest as a disk.
+    pub fn cidata_image(&self) -> Vec<u8> {
+        let files = [NoCloudFile::MetaData, NoCloudFile::UserData];
+
+        // Use the smallest clusters which keep the filesystem within FAT12's
+        // limits.  One cluster beyond the files' keeps the filesystem from
+        // being empty.
+        let mut sectors_per_cluster = 1;
+        let clusters = loop {
+            let cluster_size = sectors_per_cluster * SECTOR_SIZE;
+            let clusters = files
+                .iter()
+                .map(|f| {
+                    (self.file(*f).len() + cluster_size - 1) / cluster_size
+                })
+                .sum::<usize>()
+                + 1;
+            if clusters <= MAX_FAT12_CLUSTERS {
+                break clusters;
+            }
+            sectors_per_cluster *= 2;
+        };
+        let cluster_size = sectors_per_cluster * SECTOR_SIZE;
+        let fat_sectors =
+            ((clusters + 2) * 3 / 2 + 1 + SECTOR_SIZE - 1) / SECTOR_SIZE;
+        let root_offset = (1 + 2 * fat_sectors) * SECTOR_SIZE;
+        let data_offset = root_offset + ROOT_ENTRIES * DIR_ENTRY_SIZE;
+        let total_sectors =
+            data_offset / SECTOR_SIZE + clusters * sectors_per_cluster;
+        let mut image = vec![0u8; total_sectors * SECTOR_SIZE];
+
+        // Boot sector, with a DOS 4.0 extended BIOS parameter block
+        let boot = &mut image[..SECTOR_SIZE];
+        boot[0..3].copy_from_slice(&[0xeb, 0x3c, 0x90]);
+        boot[3..11].copy_from_slice(b"OXIDE   ");
+        put_u16(boot, 11, SECTOR_SIZE as u16);
+        boot[13] = sectors_per_cluster as u8;
+        put_u16(boot, 14, 1);
+        boot[16] = 2;
+        put_u16(boot, 17, ROOT_ENTRIES as u16);
+        if let Ok(total_sectors) = u16::try_from(total_sectors) {
+            put_u16(boot, 19, total_sectors);
+        } else {
+            put_u32(boot, 32, total_sectors as u32);
+        }
+        boot[21] = MEDIA_FIXED;
+        put_u16(boot, 22, fat_sectors as u16);
+        put_u16(boot, 24, 32);
+        put_u16(boot, 26, 64);
+        boot[36] = 0x80;
+        boot[38] = 0x29;
+        put_u32(boot, 39, self.volume_id);
+        boot[43..54].copy_from_slice(CIDATA_LABEL);
+        boot[54..62].copy_from_slice(b"FAT12   ");
+        boot[510..512].copy_from_slice(&[0x55, 0xaa]);
+
+        // Lay the files out in consecutive clusters, chaining each file's
+        // clusters together in the allocation table.
+        let mut fat = vec![0u16; clusters + 2];
+        fat[0] = 0xf00 | u16::from(MEDIA_FIXED);
+        fat[1] = 0xfff;
+        let mut entries = vec![dir_entry(CIDATA_LABEL, 0x08, 0, 0)];
+        let mut next_cluster = 2;
+        for file in &files {
+            let contents = self.file(*file);
+            let count = (contents.len() + cluster_size - 1) / cluster_size;
+            let first_cluster = if count == 0 { 0 } else { next_cluster };
+            for i in 0..count {
+                let cluster = next_cluster + i;
+                fat[cluster] =
+                    if i + 1 == count { 0xfff } else { cluster as u16 + 1 };
+            }
+            let offset = data_offset + (next_cluster - 2) * cluster_size;
+            image[offset..offset + contents.len()].copy_from_slice(contents);
+            next_cluster += count;
+
+            entries.push(long_name_entry(file.name(), file.short_name()));
+            entries.push(dir_entry(
+                file.short_name(),
+                0x20,
+                first_cluster as u16,
+                contents.len() as u32,
+            ));
+        }
+
+        // FAT12 packs two 12-bit entries into every three bytes.  Both copies
+        // of the table are identical.
+        for copy in 0..2 {
+            let table = &mut image[(1 + copy * fat_sectors) * SECTOR_SIZE
+                ..(1 + (copy + 1) * fat_sectors) * SECTOR_SIZE];
+            for (n, entry) in fat.iter().enumerate() {
+                let offset = n * 3 / 2;
+                if n % 2 == 0 {
+                    table[offset] = *entry as u8;
+                    table[offset + 1] |= (*entry >> 8) as u8 & 0x0f;
+                } else {
+                    table[offset] |= (*entry << 4) as u8;
+                    table[offset + 1] = (*entry >> 4) as u8;
+                }
+            }
+        }
+
+        for (n, entry) in entries.iter().enumerate() {
+            let offset = root_offset + n * DIR_ENTRY_SIZE;
+            image[offset..offset + DIR_ENTRY_SIZE].copy_from_slice(entry);
+        }
+        image
+    }
+}
+
+fn put_u16(buf: &mut [u8], offset: usize, value: u16) {
+    buf[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+fn put_u32(buf: &mut [u8], offset: usize, value: u32) {
+    buf[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+// Returns a short (8.3) directory entry.
+fn dir_entry(
+    name: &[u8; 11],
+    attributes: u8,
+    first_cluster: u16,
+    size: u32,
+) -> [u8; DIR_ENTRY_SIZE] {
+    let mut entry = [0u8; DIR_ENTRY_SIZE];
+    entry[0..11].copy_from_slice(name);
+    entry[11] = attributes;
+    put_u16(&mut entry, 16, FAT_EPOCH);
+    put_u16(&mut entry, 18, FAT_EPOCH);
+    put_u16(&mut entry, 24, FAT_EPOCH);
+    put_u16(&mut entry, 26, first_cluster);
+    put_u32(&mut entry, 28, size);
+    entry
+}
+
+// Returns the VFAT entry giving the entry `short_name`, which must follow it,
+// the long name `name`.  Only names of up to 13 characters are supported,
+// which is all the seed needs.
+fn long_name_entry(name: &str, short_name: &[u8; 11]) -> [u8; DIR_ENTRY_SIZE] {
+    let mut chars: Vec<u16> = name.encode_utf16().collect();
+    assert!(chars.len() <= 13);
+    if chars.len() < 13 {
+        chars.push(0);
+    }
+    chars.resize(13, 0xffff);
+
+    let checksum = short_name.iter().fold(0u8, |sum, c| {
+        ((sum & 1) << 7).wrapping_add(sum >> 1).wrapping_add(*c)
+    });
+
+    let mut entry = [0u8; DIR_ENTRY_SIZE];
+    // The first and, since there's only one, last entry of the name
+    entry[0] = 0x41;
+    entry[11] = 0x0f;
+    entry[13] = checksum;
+    let offsets = (1..11)
+        .step_by(2)
+        .chain((14..26).step_by(2))
+        .chain((28..32).step_by(2));
+    for (c, offset) in chars.iter().zip(offsets) {
+        put_u16(&mut entry, offset, *c);
+    }
+    entry
+}
+
+#[cfg(test)]
+mod test {
+    use super::{NoCloudFile, NoCloudSeed};
+    use omicron_common::api::internal::sled_agent::GuestMetadata;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_nocloud_seed() {
+        let id: Uuid = "e398c5d5-5059-4e55-beac-3a1071083aaa".parse().unwrap();
+        let seed = NoCloudSeed::new(
+            &id,
+            "myvm",
+            &GuestMetadata {
+                user_data: b"#cloud-config\npackages: [htop]\n".to_vec(),
+                ssh_public_keys: vec![
+                    String::from("ssh-ed25519 AAAAC3Nza me@example.com"),
+                    String::from("ssh-rsa \"quoted: yaml\""),
+                ],
+            },
+        );
+
+        let meta_data: serde_json::Value =
+            serde_json::from_slice(seed.file(NoCloudFile::MetaData)).unwrap();
+        assert_eq!(
+            meta_data,
+            serde_json::json!({
+                "instance-id": "e398c5d5-5059-4e55-beac-3a1071083aaa",
+                "local-hostname": "myvm",
+                "public-keys": [
+                    "ssh-ed25519 AAAAC3Nza me@example.com",
+                    "ssh-rsa \"quoted: yaml\"",
+                ],
+            })
+        );
+        assert_eq!(
+            seed.file(NoCloudFile::UserData),
+            b"#cloud-config\npackages: [htop]\n"
+        );
+
+        // An Instance without any metadata still gets a valid seed.
+        let seed = NoCloudSeed::new(&id, "myvm", &GuestMetadata::default());
+        let meta_data: serde_json::Value =
+            serde_json::from_slice(seed.file(NoCloudFile::MetaData)).unwrap();
+        assert_eq!(meta_data["public-keys"], serde_json::json!([]));
+        assert!(seed.file(NoCloudFile::UserData).is_empty());
+    }
+
+    fn get_u16(buf: &[u8], offset: usize) -> usize {
+        u16::from_le_bytes([buf[offset], buf[offset + 1]]) as usize
+    }
+
+    // Reads the files in the root directory of the FAT12 filesystem `image`,
+    // returning its volume label and each file's long name and contents.
+    fn read_fat12(image: &[u8]) -> (String, Vec<(String, Vec<u8>)>) {
+        assert_eq!(&image[510..512], &[0x55, 0xaa]);
+        assert_eq!(&image[54..62], b"FAT12   ");
+        let sector_size = get_u16(image, 11);
+        let cluster_size = image[13] as usize * sector_size;
+        let fat_offset = get_u16(image, 14) * sector_size;
+        let fat_size = get_u16(image, 22) * sector_size;
+        let root_offset = fat_offset + image[16] as usize * fat_size;
+        let data_offset = root_offset + get_u16(image, 17) * 32;
+        let fat = &image[fat_offset..fat_offset + fat_size];
+        assert_eq!(fat, &image[fat_offset + fat_size..root_offset]);
+        let fat_entry = |n: usize| {
+            let pair = get_u16(fat, n * 3 / 2);
+            if n % 2 == 0 {
+                pair & 0xfff
+            } else {
+                pair >> 4
+            }
+        };
+
+        let mut label = None;
+        let mut long_name = None;
+        let mut files = vec![];
+        for entry in image[root_offset..data_offset].chunks(32) {
+            match entry[11] {
+                _ if entry[0] == 0 => break,
+                0x08 => {
+                    label =
+                        Some(String::from_utf8(entry[..11].to_vec()).unwrap())
+                }
+                0x0f => {
+                    assert_eq!(entry[0], 0x41);
+                    let chars = (1..11)
+                        .step_by(2)
+                        .chain((14..26).step_by(2))
+                        .chain((28..32).step_by(2))
+                        .map(|offset| get_u16(entry, offset) as u16)
+                        .take_while(|c| *c != 0)
+                        .collect::<Vec<_>>();
+                    long_name = Some(String::from_utf16(&chars).unwrap());
+                }
+                0x20 => {
+                    let size = u32::from_le_bytes([
+                        entry[28], entry[29], entry[30], entry[31],
+                    ]) as usize;
+                    let mut contents = vec![];
+                    let mut cluster = get_u16(entry, 26);
+                    while contents.len() < size {
+                        let offset = data_offset + (cluster - 2) * cluster_size;
+                        contents.extend_from_slice(
+                            &image[offset..offset + cluster_size],
+                        );
+                        cluster = fat_entry(cluster);
+                    }
+                    assert!(size == 0 || cluster == 0xfff);
+                    contents.truncate(size);
+                    files.push((long_name.take().unwrap(), contents));
+                }
+                attributes => panic!("unexpected entry type {}", attributes),
+            }
+        }
+        (label.unwrap(), files)
+    }
+
+    #[test]
+    fn test_cidata_image() {
+        let id: Uuid = "e398c5d5-5059-4e55-beac-3a1071083aaa".parse().unwrap();
+
+        // Span several clusters with the user data, so that it's chained.
+        let user_data = (0..2000)
+            .map(|n| format!("# line {}\n", n))
+            .collect::<String>()
+            .into_bytes();
+        let seed = NoCloudSeed::new(
+            &id,
+            "myvm",
+            &GuestMetadata {
+                user_data: user_data.clone(),
+                ssh_public_keys: vec![],
+            },
+        );
+        let (label, files) = read_fat12(&seed.cidata_image());
+        assert_eq!(label, "CIDATA     ");
+        assert_eq!(
+            files,
+            vec![
+                (
+                    String::from("meta-data"),
+                    seed.file(NoCloudFile::MetaData).to_vec()
+                ),
+                (String::from("user-data"), user_data),
+            ]
+        );
+
+        // Empty files take up no clusters at all.
+        let seed = NoCloudSeed::new(&id, "myvm", &GuestMetadata::default());
+        let (_, files) = read_fat12(&seed.cidata_image());
+        assert_eq!(files[1], (String::from("user-data"), vec![]));
+    }
+}
@@ -0,0 +1,230 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Buffers an Instance's serial console and shares it among clients.
+
+use futures::{SinkExt, StreamExt};
+use hyper::upgrade::OnUpgrade;
+use omicron_common::api::internal::sled_agent::InstanceSerialConsoleData;
+use omicron_common::websocket;
+use slog::Logger;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::{broadcast, mpsc};
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
+
+/// Bytes of console output retained for clients which aren't attached.
+pub const SCROLLBACK_BYTES: usize = 16 * 1024;
+
+// Chunks of output which may be queued for a slow client before it starts
+// missing some.
+const OUTPUT_CHUNKS_QUEUED: usize = 64;
+
+// Chunks of input which may be queued before clients must wait for the
+// Instance to read them.
+const INPUT_CHUNKS_QUEUED: usize = 16;
+
+struct Scrollback {
+    data: VecDeque<u8>,
+    capacity: usize,
+    total_bytes: u64,
+}
+
+/// The serial console of a single Instance.
+///
+/// Whatever reads the Instance's UART passes it to
+/// [`SerialConsole::record_output`], which keeps the most recent output and
+/// passes it along to any attached clients.  Attached clients' keystrokes are
+/// sent to the receiver returned by [`SerialConsole::new`], to be written to
+/// the UART.
+///
+/// Cloning this object clones the reference - it does not create another
+/// console.
+#[derive(Clone)]
+pub struct SerialConsole {
+    scrollback: Arc<Mutex<Scrollback>>,
+    output: broadcast::Sender<Vec<u8>>,
+    input: mpsc::Sender<Vec<u8>>,
+}
+
+impl SerialConsole {
+    /// Creates a console which retains the last `capacity` bytes of output,
+    /// along with the receiving end of its clients' input.
+    pub fn new(capacity: usize) -> (Self, mpsc::Receiver<Vec<u8>>) {
+        let (output, _) = broadcast::channel(OUTPUT_CHUNKS_QUEUED);
+        let (input, input_rx) = mpsc::channel(INPUT_CHUNKS_QUEUED);
+        let scrollback = Scrollback {
+            data: VecDeque::with_capacity(capacity),
+            capacity,
+            total_bytes: 0,
+        };
+        let console = SerialConsole {
+            scrollback: Arc::new(Mutex::new(scrollback)),
+            output,
+            input,
+        };
+        (console, input_rx)
+    }
+
+    /// Records output written by the Instance.
+    pub fn record_output(&self, bytes: &[u8]) {
+        let mut scrollback = self.scrollback.lock().unwrap();
+        scrollback.total_bytes += bytes.len() as u64;
+        scrollback.data.extend(bytes);
+        let excess = scrollback.data.len().saturating_sub(scrollback.capacity);
+        scrollback.data.drain(..excess);
+
+        // Sending while holding the lock keeps attached clients from seeing
+        // output twice, or not at all, around the time they attach.  Failure
+        // means only that no client is attached.
+        let _ = self.output.send(bytes.to_vec());
+    }
+
+    /// Returns up to `max_bytes` of the most recent output (or all of the
+    /// retained output, if `max_bytes` is `None`).
+    pub fn history(&self, max_bytes: Option<u64>) -> InstanceSerialConsoleData {
+        let scrollback = self.scrollback.lock().unwrap();
+        let len = scrollback.data.len();
+        let skip = match max_bytes {
+            Some(max) if (max as usize) < len => len - max as usize,
+            _ => 0,
+        };
+        InstanceSerialConsoleData {
+            data: scrollback.data.iter().skip(skip).copied().collect(),
+            last_byte_offset: scrollback.total_bytes,
+        }
+    }
+
+    /// Attaches a client to the console, returning the output retained so
+    /// far, a receiver for output from here on, and a sender for input.
+    pub fn attach(
+        &self,
+    ) -> (Vec<u8>, broadcast::Receiver<Vec<u8>>, mpsc::Sender<Vec<u8>>) {
+        let scrollback = self.scrollback.lock().unwrap();
+        let output = self.output.subscribe();
+        let history = scrollback.data.iter().copied().collect();
+        (history, output, self.input.clone())
+    }
+}
+
+/// Serves the console, in the background, over the WebSocket handed back by
+/// [`websocket::upgrade`].
+pub fn spawn_server(
+    console: SerialConsole,
+    on_upgrade: OnUpgrade,
+    log: Logger,
+) {
+    tokio::spawn(async move {
+        match websocket::accept(on_upgrade).await {
+            Ok(ws) => serve(console, ws, &log).await,
+            Err(e) => warn!(log, "serial console upgrade failed: {}", e),
+        }
+    });
+}
+
+// Relays the console to and from a client's WebSocket until either the client
+// or the console goes away.
+async fn serve<S>(
+    console: SerialConsole,
+    websocket: WebSocketStream<S>,
+    log: &Logger,
+) where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let (history, mut output, input) = console.attach();
+    // Don't keep the console alive on this client's behalf.
+    drop(console);
+
+    let (mut ws_sink, mut ws_stream) = websocket.split();
+    if !history.is_empty() {
+        if let Err(e) = ws_sink.send(Message::Binary(history)).await {
+            warn!(log, "serial console client went away: {}", e);
+            return;
+        }
+    }
+
+    loop {
+        tokio::select! {
+            chunk = output.recv() => match chunk {
+                Ok(bytes) => {
+                    if let Err(e) = ws_sink.send(Message::Binary(bytes)).await {
+                        warn!(log, "serial console client went away: {}", e);
+                        return;
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    warn!(log, "serial console client missed {} chunks", n);
+                }
+                Err(broadcast::error::RecvError::Closed) => {
+                    let _ = ws_sink.send(Message::Close(None)).await;
+                    return;
+                }
+            },
+            message = ws_stream.next() => match message {
+                Some(Ok(Message::Binary(bytes))) => {
+                    if input.send(bytes).await.is_err() {
+                        return;
+                    }
+                }
+                Some(Ok(Message::Text(text))) => {
+                    if input.send(text.into_bytes()).await.is_err() {
+                        return;
+                    }
+                }
+                Some(Ok(Message::Close(_))) | None => return,
+                Some(Ok(_)) => {}
+                Some(Err(e)) => {
+                    warn!(log, "serial console client failed: {}", e);
+                    return;
+                }
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::SerialConsole;
+
+    #[test]
+    fn test_scrollback_is_bounded() {
+        let (console, _input) = SerialConsole::new(8);
+        let history = console.history(None);
+        assert!(history.data.is_empty());
+        assert_eq!(history.last_byte_offset, 0);
+
+        console.record_output(b"hello ");
+        console.record_output(b"world");
+        let history = console.history(None);
+        assert_eq!(history.data, b"lo world");
+        assert_eq!(history.last_byte_offset, 11);
+
+        assert_eq!(console.history(Some(5)).data, b"world");
+        assert_eq!(console.history(Some(100)).data, b"lo world");
+        assert_eq!(console.history(Some(0)).data, b"");
+
+        // A single chunk larger than the whole scrollback keeps only its tail.
+        console.record_output(b"0123456789");
+        let history = console.history(None);
+        assert_eq!(history.data, b"23456789");
+        assert_eq!(history.last_byte_offset, 21);
+    }
+
+    #[tokio::test]
+    async fn test_attach() {
+        let (console, mut input_rx) = SerialConsole::new(1024);
+        console.record_output(b"before");
+
+        let (history, mut output, input) = console.attach();
+        assert_eq!(history, b"before");
+
+        console.record_output(b"after");
+        assert_eq!(output.recv().await.unwrap(), b"after");
+
+        input.send(b"keys".to_vec()).await.unwrap();
+        assert_eq!(input_rx.recv().await.unwrap(), b"keys");
+    }
+}
@@ -0,0 +1,30 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Inventory of the sled's compute resources, as reported to Nexus
+
+use std::convert::TryFrom;
+
+/// Returns the number of hardware threads that are online on this sled.
+pub fn usable_hardware_threads() -> Result<u32, String> {
+    // Safety: sysconf() has no preconditions.
+    let n = unsafe { libc::sysconf(libc::_SC_NPROCESSORS_ONLN) };
+    u32::try_from(n)
+        .map_err(|_| format!("failed to count online processors: {}", n))
+}
+
+/// Returns the number of bytes of physical memory on this sled.
+pub fn usable_physical_ram() -> Result<u64, String> {
+    // Safety: sysconf() has no preconditions.
+    let (pages, page_size) = unsafe {
+        (libc::sysconf(libc::_SC_PHYS_PAGES), libc::sysconf(libc::_SC_PAGESIZE))
+    };
+    match (u64::try_from(pages), u64::try_from(page_size)) {
+        (Ok(pages), Ok(page_size)) => Ok(pages * page_size),
+        _ => Err(format!(
+            "failed to determine physical memory size: {} pages of {} bytes",
+            pages, page_size
+        )),
+    }
+}
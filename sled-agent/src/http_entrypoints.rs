@@ -4,18 +4,26 @@
 
 //! HTTP entrypoint functions for the sled agent's exposed API
 
-use super::params::DiskEnsureBody;
+use super::params::{DiskEnsureBody, DiskResizeBody};
+use crate::common::serial_console;
 use dropshot::endpoint;
 use dropshot::ApiDescription;
 use dropshot::HttpError;
 use dropshot::HttpResponseOk;
+use dropshot::HttpResponseUpdatedNoContent;
 use dropshot::Path;
+use dropshot::Query;
 use dropshot::RequestContext;
 use dropshot::TypedBody;
+use hyper::{Body, Response};
 use omicron_common::api::external::Error;
 use omicron_common::api::internal::nexus::DiskRuntimeState;
 use omicron_common::api::internal::nexus::InstanceRuntimeState;
+use omicron_common::api::internal::sled_agent::FirewallRulesEnsureBody;
 use omicron_common::api::internal::sled_agent::InstanceEnsureBody;
+use omicron_common::api::internal::sled_agent::InstanceSerialConsoleData;
+use omicron_common::api::internal::sled_agent::InstanceSerialConsoleHistoryParams;
+use omicron_common::websocket;
 use schemars::JsonSchema;
 use serde::Deserialize;
 use std::sync::Arc;
@@ -29,7 +37,11 @@ type SledApiDescription = ApiDescription<SledAgent>;
 pub fn api() -> SledApiDescription {
     fn register_endpoints(api: &mut SledApiDescription) -> Result<(), String> {
         api.register(instance_put)?;
+        api.register(instance_serial_console)?;
+        api.register(instance_serial_console_history)?;
         api.register(disk_put)?;
+        api.register(disk_resize_post)?;
+        api.register(firewall_rules_put)?;
         Ok(())
     }
 
@@ -70,6 +82,43 @@ async fn instance_put(
     ))
 }
 
+#[endpoint {
+    method = GET,
+    path = "/instances/{instance_id}/serial-console",
+    unpublished = true,
+}]
+async fn instance_serial_console(
+    rqctx: Arc<RequestContext<SledAgent>>,
+    path_params: Path<InstancePathParam>,
+) -> Result<Response<Body>, HttpError> {
+    let sa = rqctx.context();
+    let instance_id = path_params.into_inner().instance_id;
+    let console =
+        sa.instance_serial_console(instance_id).map_err(|e| Error::from(e))?;
+    let (response, on_upgrade) =
+        websocket::upgrade(&mut *rqctx.request.lock().await)?;
+    let log = rqctx.log.new(o!("instance_id" => instance_id.to_string()));
+    serial_console::spawn_server(console, on_upgrade, log);
+    Ok(response)
+}
+
+#[endpoint {
+    method = GET,
+    path = "/instances/{instance_id}/serial-console/history",
+}]
+async fn instance_serial_console_history(
+    rqctx: Arc<RequestContext<SledAgent>>,
+    path_params: Path<InstancePathParam>,
+    query_params: Query<InstanceSerialConsoleHistoryParams>,
+) -> Result<HttpResponseOk<InstanceSerialConsoleData>, HttpError> {
+    let sa = rqctx.context();
+    let instance_id = path_params.into_inner().instance_id;
+    let max_bytes = query_params.into_inner().max_bytes;
+    let console =
+        sa.instance_serial_console(instance_id).map_err(|e| Error::from(e))?;
+    Ok(HttpResponseOk(console.history(max_bytes)))
+}
+
 /// Path parameters for Disk requests (sled agent API)
 #[derive(Deserialize, JsonSchema)]
 struct DiskPathParam {
@@ -98,3 +147,41 @@ async fn disk_put(
         .map_err(|e| Error::from(e))?,
     ))
 }
+
+#[endpoint {
+    method = POST,
+    path = "/disks/{disk_id}/resize",
+}]
+async fn disk_resize_post(
+    rqctx: Arc<RequestContext<SledAgent>>,
+    path_params: Path<DiskPathParam>,
+    body: TypedBody<DiskResizeBody>,
+) -> Result<HttpResponseUpdatedNoContent, HttpError> {
+    let sa = rqctx.context();
+    let disk_id = path_params.into_inner().disk_id;
+    let body_args = body.into_inner();
+    sa.disk_resize(disk_id, body_args.instance_id, body_args.size)
+        .await
+        .map_err(|e| Error::from(e))?;
+    Ok(HttpResponseUpdatedNoContent())
+}
+
+/// Replaces the firewall rules of the network interfaces listed
+///
+/// NOTE: Firewall rules are not yet enforced.  The sled agent logs and keeps
+/// the rules it receives, but doesn't filter its Instances' traffic by them.
+#[endpoint {
+    method = PUT,
+    path = "/firewall-rules",
+}]
+async fn firewall_rules_put(
+    rqctx: Arc<RequestContext<SledAgent>>,
+    body: TypedBody<FirewallRulesEnsureBody>,
+) -> Result<HttpResponseUpdatedNoContent, HttpError> {
+    let sa = rqctx.context();
+    let body_args = body.into_inner();
+    info!(rqctx.log, "received firewall rules";
+        "interfaces" => body_args.interfaces.len());
+    sa.firewall_rules_ensure(body_args).await.map_err(|e| Error::from(e))?;
+    Ok(HttpResponseUpdatedNoContent())
+}
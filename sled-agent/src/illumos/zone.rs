@@ -16,6 +16,13 @@ use crate::illumos::{execute, PFEXEC};
 const PROPOLIS_BASE_ZONE: &str = "oxz_propolis_base";
 const STORAGE_BASE_ZONE: &str = "oxz_storage_base";
 const PROPOLIS_SVC_DIRECTORY: &str = "/opt/oxide/propolis-server";
+/// Configuration Propolis runs with, absent anything instance-specific
+const PROPOLIS_BASE_CONFIG: &str = "/opt/oxide/propolis-server/pkg/config.toml";
+/// Directory within a Propolis zone holding the instance's configuration
+const PROPOLIS_INSTANCE_DIRECTORY: &str = "/var/oxide/propolis-server";
+/// PCI slot through which Propolis presents the NoCloud seed to the guest,
+/// chosen to stay clear of those in the base configuration.
+const PROPOLIS_CIDATA_PCI_PATH: &str = "0.16.0";
 pub const CRUCIBLE_SVC_DIRECTORY: &str = "/opt/oxide/crucible-agent";
 pub const COCKROACH_SVC_DIRECTORY: &str = "/opt/oxide/cockroachdb";
 
@@ -431,11 +438,53 @@ impl Zones {
         Self::get_address(zone, addrobj)
     }
 
-    /// Configures and initializes a Propolis server within the specified Zone.
+    /// Writes the configuration for the Propolis server within the specified
+    /// Zone, which attaches `cidata`, an image of the Instance's NoCloud seed,
+    /// to the guest as a read-only disk.
+    ///
+    /// Returns the path of the configuration within the Zone.
+    pub fn install_propolis_config(
+        zone: &str,
+        cidata: &[u8],
+    ) -> Result<String, Error> {
+        let root = format!("{}/{}/root", ZONE_ZFS_DATASET_MOUNTPOINT, zone);
+        let directory = format!("{}{}", root, PROPOLIS_INSTANCE_DIRECTORY);
+        std::fs::create_dir_all(&directory).map_err(Error::Filesystem)?;
+
+        let cidata_path = format!("{}/cidata.img", PROPOLIS_INSTANCE_DIRECTORY);
+        std::fs::write(format!("{}{}", root, cidata_path), cidata)
+            .map_err(Error::Filesystem)?;
+
+        // The base configuration is visible at the same path in the global
+        // zone, where it is read from, and in the Propolis zone.
+        let mut config = std::fs::read_to_string(PROPOLIS_BASE_CONFIG)
+            .map_err(Error::Filesystem)?;
+        config.push_str(&format!(
+            "\n[block_dev.cidata]\n\
+             type = \"file\"\n\
+             path = \"{}\"\n\
+             readonly = \"true\"\n\
+             \n\
+             [dev.cidata]\n\
+             driver = \"pci-virtio-block\"\n\
+             block_dev = \"cidata\"\n\
+             pci-path = \"{}\"\n",
+            cidata_path, PROPOLIS_CIDATA_PCI_PATH,
+        ));
+        let config_path =
+            format!("{}/config.toml", PROPOLIS_INSTANCE_DIRECTORY);
+        std::fs::write(format!("{}{}", root, config_path), config)
+            .map_err(Error::Filesystem)?;
+        Ok(config_path)
+    }
+
+    /// Configures and initializes a Propolis server within the specified Zone,
+    /// running with the configuration file at `config` within the Zone.
     pub fn run_propolis(
         zone: &str,
         id: &Uuid,
         addr: &SocketAddr,
+        config: &str,
     ) -> Result<(), Error> {
         // Import the service manifest for Propolis.
         let mut command = std::process::Command::new(PFEXEC);
@@ -461,6 +510,19 @@ impl Zones {
         ]);
         execute(cmd)?;
 
+        // Point the Propolis server at its configuration.
+        let mut command = std::process::Command::new(PFEXEC);
+        let cmd = command.args(&[
+            ZLOGIN,
+            zone,
+            SVCCFG,
+            "-s",
+            "system/illumos/propolis-server",
+            "setprop",
+            &format!("config/config_file={}", config),
+        ]);
+        execute(cmd)?;
+
         // Create a new Propolis service instance.
         let mut command = std::process::Command::new(PFEXEC);
         let cmd = command.args(&[
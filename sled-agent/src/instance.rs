@@ -6,6 +6,8 @@
 
 use crate::common::{
     instance::{Action as InstanceAction, InstanceStates, PROPOLIS_PORT},
+    nocloud::NoCloudSeed,
+    serial_console::{SerialConsole, SCROLLBACK_BYTES},
     vlan::VlanID,
 };
 use crate::illumos::svc::wait_for_service;
@@ -14,6 +16,7 @@ use crate::instance_manager::InstanceTicket;
 use crate::vnic::{interface_name, IdAllocator, Vnic};
 use anyhow::anyhow;
 use futures::lock::{Mutex, MutexGuard};
+use futures::{SinkExt, StreamExt};
 use omicron_common::api::external::NetworkInterface;
 use omicron_common::api::internal::nexus::InstanceRuntimeState;
 use omicron_common::api::internal::sled_agent::InstanceHardware;
@@ -24,7 +27,10 @@ use propolis_client::Client as PropolisClient;
 use slog::Logger;
 use std::net::SocketAddr;
 use std::sync::Arc;
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
 use tokio::task::JoinHandle;
+use tokio_tungstenite::tungstenite::Message;
 use uuid::Uuid;
 
 #[cfg(not(test))]
@@ -62,6 +68,9 @@ pub enum Error {
     // TODO: Add more specific errors
     #[error("Failure during migration: {0}")]
     Migration(anyhow::Error),
+
+    #[error("Failure relaying serial console: {0}")]
+    SerialConsole(#[from] tokio_tungstenite::tungstenite::Error),
 }
 
 // Issues read-only, idempotent HTTP requests at propolis until it responds with
@@ -136,10 +145,15 @@ struct RunningState {
     ticket: InstanceTicket,
     // Handle to task monitoring for Propolis state changes.
     monitor_task: Option<JoinHandle<()>>,
+    // Handle to task relaying the serial console to and from Propolis.
+    console_task: Option<JoinHandle<()>>,
 }
 
 impl Drop for RunningState {
     fn drop(&mut self) {
+        if let Some(task) = self.console_task.take() {
+            task.abort()
+        }
         if let Some(task) = self.monitor_task.take() {
             // NOTE: We'd prefer to actually await the task, since it
             // will be completed at this point, but async drop doesn't exist.
@@ -185,6 +199,10 @@ struct InstanceInner {
     state: InstanceStates,
     running_state: Option<RunningState>,
 
+    // Input from serial console clients, taken by the task which relays the
+    // console once Propolis is running.
+    console_input: Option<mpsc::Receiver<Vec<u8>>>,
+
     // Connection to Nexus
     nexus_client: Arc<NexusClient>,
 }
@@ -302,8 +320,32 @@ impl InstanceInner {
             }
         }));
 
+        // Relay the serial console in the background, too.
+        let console_task = match (
+            self.console_input.take(),
+            self.state.current().propolis_addr,
+        ) {
+            (Some(input), Some(addr)) => {
+                let console = instance.console.clone();
+                let propolis_id = *self.propolis_id();
+                let log = self.log.clone();
+                Some(tokio::task::spawn(async move {
+                    let r =
+                        serial_console_task(addr, propolis_id, console, input)
+                            .await;
+                    match r {
+                        Err(e) => {
+                            warn!(log, "Serial console task failed: {}", e)
+                        }
+                        Ok(()) => info!(log, "Serial console task complete"),
+                    }
+                }))
+            }
+            _ => None,
+        };
+
         self.running_state =
-            Some(RunningState { client, ticket, monitor_task });
+            Some(RunningState { client, ticket, monitor_task, console_task });
 
         // Store the VNICs while the instance is running.
         self.allocated_nics = guest_nics
@@ -349,6 +391,8 @@ impl InstanceInner {
 #[derive(Clone)]
 pub struct Instance {
     inner: Arc<Mutex<InstanceInner>>,
+    console: SerialConsole,
+    nocloud_seed: NoCloudSeed,
 }
 
 #[cfg(test)]
@@ -371,6 +415,7 @@ mockall::mock! {
             &self,
             target: InstanceRuntimeStateRequested,
         ) -> Result<InstanceRuntimeState, Error>;
+        pub fn serial_console(&self) -> SerialConsole;
     }
     impl Clone for Instance {
         fn clone(&self) -> Self;
@@ -399,6 +444,12 @@ impl Instance {
         nexus_client: Arc<NexusClient>,
     ) -> Result<Self, Error> {
         info!(log, "Instance::new w/initial HW: {:?}", initial);
+        let (console, console_input) = SerialConsole::new(SCROLLBACK_BYTES);
+        let nocloud_seed = NoCloudSeed::new(
+            &id,
+            &initial.runtime.hostname,
+            &initial.guest_metadata,
+        );
         let instance = InstanceInner {
             log: log.new(o!("instance id" => id.to_string())),
             id,
@@ -421,12 +472,13 @@ impl Instance {
             vlan,
             state: InstanceStates::new(initial.runtime),
             running_state: None,
+            console_input: Some(console_input),
             nexus_client,
         };
 
         let inner = Arc::new(Mutex::new(instance));
 
-        Ok(Instance { inner })
+        Ok(Instance { inner, console, nocloud_seed })
     }
 
     async fn setup_propolis_locked(
@@ -505,9 +557,23 @@ impl Instance {
         )?;
         info!(inner.log, "Created address {} for zone: {}", network, zname);
 
-        // Run Propolis in the Zone.
+        // Give Propolis the guest's NoCloud seed to attach, then run it in
+        // the Zone.
+        let config = Zones::install_propolis_config(
+            &zname,
+            &self.nocloud_seed.cidata_image(),
+        )?;
+        info!(
+            inner.log,
+            "Installed propolis config {} in zone: {}", config, zname
+        );
         let server_addr = SocketAddr::new(network.ip(), PROPOLIS_PORT);
-        Zones::run_propolis(&zname, inner.propolis_id(), &server_addr)?;
+        Zones::run_propolis(
+            &zname,
+            inner.propolis_id(),
+            &server_addr,
+            &config,
+        )?;
         info!(inner.log, "Started propolis in zone: {}", zname);
 
         // This isn't strictly necessary - we wait for the HTTP server below -
@@ -639,6 +705,49 @@ impl Instance {
         }
         Ok(inner.state.current().clone())
     }
+
+    /// Returns the instance's serial console.
+    pub fn serial_console(&self) -> SerialConsole {
+        self.console.clone()
+    }
+}
+
+// Relays the guest's serial console between Propolis and `console` until
+// Propolis hangs up.
+//
+// Intended to be spawned in a tokio task within [`Instance::start`].
+async fn serial_console_task(
+    propolis_addr: SocketAddr,
+    propolis_id: Uuid,
+    console: SerialConsole,
+    mut input: mpsc::Receiver<Vec<u8>>,
+) -> Result<(), Error> {
+    let stream = TcpStream::connect(propolis_addr)
+        .await
+        .map_err(tokio_tungstenite::tungstenite::Error::from)?;
+    let url =
+        format!("ws://{}/instances/{}/serial", propolis_addr, propolis_id);
+    let (mut websocket, _) =
+        tokio_tungstenite::client_async(url, stream).await?;
+
+    loop {
+        tokio::select! {
+            message = websocket.next() => match message {
+                Some(Ok(Message::Binary(bytes))) => {
+                    console.record_output(&bytes)
+                }
+                Some(Ok(Message::Text(text))) => {
+                    console.record_output(text.as_bytes())
+                }
+                Some(Ok(Message::Close(_))) | None => return Ok(()),
+                Some(Ok(_)) => {}
+                Some(Err(e)) => return Err(e.into()),
+            },
+            Some(bytes) = input.recv() => {
+                websocket.send(Message::Binary(bytes)).await?;
+            }
+        }
+    }
 }
 
 #[cfg(test)]
@@ -935,18 +1044,31 @@ mod test {
                 Ok("127.0.0.1/24".parse().unwrap())
             });
 
+        let zone_install_propolis_config_ctx =
+            MockZones::install_propolis_config_context();
+        zone_install_propolis_config_ctx
+            .expect()
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|zone, cidata| {
+                assert_eq!(zone, propolis_zone_name(&test_propolis_uuid()));
+                assert_eq!(&cidata[510..512], &[0x55, 0xaa]);
+                Ok(String::from("/var/oxide/propolis-server/config.toml"))
+            });
+
         let zone_run_propolis_ctx = MockZones::run_propolis_context();
         zone_run_propolis_ctx
             .expect()
             .times(1)
             .in_sequence(&mut seq)
-            .returning(|zone, id, addr| {
+            .returning(|zone, id, addr, config| {
                 assert_eq!(zone, propolis_zone_name(&test_propolis_uuid()));
                 assert_eq!(id, &test_propolis_uuid());
                 assert_eq!(
                     addr,
                     &"127.0.0.1:12400".parse::<SocketAddr>().unwrap()
                 );
+                assert_eq!(config, "/var/oxide/propolis-server/config.toml");
                 Ok(())
             });
 
@@ -1019,6 +1141,7 @@ mod test {
                 time_updated: Utc::now(),
             },
             nics: vec![],
+            guest_metadata: Default::default(),
         }
     }
 
@@ -4,6 +4,7 @@
 
 //! API for controlling multiple instances on a sled.
 
+use crate::common::serial_console::SerialConsole;
 use crate::common::vlan::VlanID;
 use crate::vnic::IdAllocator;
 use omicron_common::api::internal::nexus::InstanceRuntimeState;
@@ -34,6 +35,9 @@ pub enum Error {
 
     #[error(transparent)]
     Zone(#[from] crate::illumos::zone::Error),
+
+    #[error("No such instance: {0}")]
+    NoSuchInstance(Uuid),
 }
 
 struct InstanceManagerInternal {
@@ -171,6 +175,25 @@ impl InstanceManager {
 
         instance.transition(target).await.map_err(|e| e.into())
     }
+
+    // Returns an instance running on this sled.
+    fn instance(&self, instance_id: Uuid) -> Result<Instance, Error> {
+        self.inner
+            .instances
+            .lock()
+            .unwrap()
+            .get(&instance_id)
+            .map(|(_, instance)| instance.clone())
+            .ok_or(Error::NoSuchInstance(instance_id))
+    }
+
+    /// Returns the serial console of an instance running on this sled.
+    pub fn serial_console(
+        &self,
+        instance_id: Uuid,
+    ) -> Result<SerialConsole, Error> {
+        Ok(self.instance(instance_id)?.serial_console())
+    }
 }
 
 /// Represents membership of an instance in the [`InstanceManager`].
@@ -254,6 +277,7 @@ mod test {
                 time_updated: Utc::now(),
             },
             nics: vec![],
+            guest_metadata: Default::default(),
         }
     }
 
@@ -2,6 +2,7 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+use omicron_common::api::external::ByteCount;
 use omicron_common::api::internal::nexus::DiskRuntimeState;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
@@ -39,3 +40,12 @@ pub struct DiskEnsureBody {
     /// requested runtime state of the Disk
     pub target: DiskStateRequested,
 }
+
+/// Sent to a sled agent to tell it that an attached Disk has grown
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct DiskResizeBody {
+    /// Instance to which the Disk is attached
+    pub instance_id: Uuid,
+    /// new size of the Disk
+    pub size: ByteCount,
+}
@@ -5,6 +5,7 @@
 //! Library interface to the sled agent
 
 use super::config::Config;
+use super::hardware;
 use super::http_entrypoints::api as http_api;
 use super::sled_agent::SledAgent;
 use slog::Drain;
@@ -74,7 +75,12 @@ impl Server {
         //
         // TODO-robustness if this returns a 400 error, we probably want to
         // return a permanent error from the `notify_nexus` closure.
+        //
+        // TODO We report everything on the sled as usable by Instances,
+        // though some of it is needed by the control plane itself.
         let sa_address = http_server.local_addr();
+        let usable_hardware_threads = hardware::usable_hardware_threads()?;
+        let usable_physical_ram = hardware::usable_physical_ram()?;
         let notify_nexus = || async {
             info!(
                 log,
@@ -85,6 +91,10 @@ impl Server {
                     &config.id,
                     &nexus_client::types::SledAgentStartupInfo {
                         sa_address: sa_address.to_string(),
+                        usable_hardware_threads,
+                        usable_physical_ram: nexus_client::types::ByteCount(
+                            usable_physical_ram,
+                        ),
                     },
                 )
                 .await
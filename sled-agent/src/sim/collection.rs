@@ -301,7 +301,21 @@ impl<S: Simulatable + 'static> SimCollection<S> {
         let mut objects = self.objects.lock().await;
         let maybe_current_object = objects.remove(id);
         let (mut object, is_new) = {
-            if let Some(current_object) = maybe_current_object {
+            if let Some(mut current_object) = maybe_current_object {
+                /*
+                 * If the caller's view of the object is newer than ours (e.g.,
+                 * because Nexus changed a stopped Instance's configuration),
+                 * start over from the caller's state.
+                 */
+                let provided = S::new(current.clone());
+                if current_object.object.desired().is_none()
+                    && current_object.object.generation()
+                        < provided.generation()
+                {
+                    info!(current_object.log, "replaced with newer state";
+                        "current" => ?current);
+                    current_object.object = provided;
+                }
                 (current_object, false)
             } else {
                 /* Create a new SimObject */
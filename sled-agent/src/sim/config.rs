@@ -49,6 +49,17 @@ pub struct ConfigStorage {
     pub ip: IpAddr,
 }
 
+/// Configuration describing the simulated sled's hardware.
+///
+/// This is the capacity that the sled reports to Nexus for Instance placement.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct ConfigHardware {
+    /// The number of hardware threads usable by Instances.
+    pub hardware_threads: u32,
+    /// The amount of physical memory usable by Instances, in bytes.
+    pub physical_ram: u64,
+}
+
 /**
  * Configuration for a sled agent
  */
@@ -66,4 +77,6 @@ pub struct Config {
     pub log: ConfigLogging,
     /** configuration for the sled agent's storage */
     pub storage: ConfigStorage,
+    /** configuration for the sled agent's simulated hardware */
+    pub hardware: ConfigHardware,
 }
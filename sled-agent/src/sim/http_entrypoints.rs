@@ -6,18 +6,25 @@
  * HTTP entrypoint functions for the sled agent's exposed API
  */
 
-use crate::params::DiskEnsureBody;
+use crate::common::serial_console;
+use crate::params::{DiskEnsureBody, DiskResizeBody};
 use dropshot::endpoint;
 use dropshot::ApiDescription;
 use dropshot::HttpError;
 use dropshot::HttpResponseOk;
 use dropshot::HttpResponseUpdatedNoContent;
 use dropshot::Path;
+use dropshot::Query;
 use dropshot::RequestContext;
 use dropshot::TypedBody;
+use hyper::{Body, Response};
 use omicron_common::api::internal::nexus::DiskRuntimeState;
 use omicron_common::api::internal::nexus::InstanceRuntimeState;
+use omicron_common::api::internal::sled_agent::FirewallRulesEnsureBody;
 use omicron_common::api::internal::sled_agent::InstanceEnsureBody;
+use omicron_common::api::internal::sled_agent::InstanceSerialConsoleData;
+use omicron_common::api::internal::sled_agent::InstanceSerialConsoleHistoryParams;
+use omicron_common::websocket;
 use schemars::JsonSchema;
 use serde::Deserialize;
 use std::sync::Arc;
@@ -34,8 +41,12 @@ pub fn api() -> SledApiDescription {
     fn register_endpoints(api: &mut SledApiDescription) -> Result<(), String> {
         api.register(instance_put)?;
         api.register(instance_poke_post)?;
+        api.register(instance_serial_console)?;
+        api.register(instance_serial_console_history)?;
         api.register(disk_put)?;
+        api.register(disk_resize_post)?;
         api.register(disk_poke_post)?;
+        api.register(firewall_rules_put)?;
         Ok(())
     }
 
@@ -86,6 +97,41 @@ async fn instance_poke_post(
     Ok(HttpResponseUpdatedNoContent())
 }
 
+#[endpoint {
+    method = GET,
+    path = "/instances/{instance_id}/serial-console",
+    unpublished = true,
+}]
+async fn instance_serial_console(
+    rqctx: Arc<RequestContext<Arc<SledAgent>>>,
+    path_params: Path<InstancePathParam>,
+) -> Result<Response<Body>, HttpError> {
+    let sa = rqctx.context();
+    let instance_id = path_params.into_inner().instance_id;
+    let console = sa.instance_serial_console(instance_id).await?;
+    let (response, on_upgrade) =
+        websocket::upgrade(&mut *rqctx.request.lock().await)?;
+    let log = rqctx.log.new(o!("instance_id" => instance_id.to_string()));
+    serial_console::spawn_server(console, on_upgrade, log);
+    Ok(response)
+}
+
+#[endpoint {
+    method = GET,
+    path = "/instances/{instance_id}/serial-console/history",
+}]
+async fn instance_serial_console_history(
+    rqctx: Arc<RequestContext<Arc<SledAgent>>>,
+    path_params: Path<InstancePathParam>,
+    query_params: Query<InstanceSerialConsoleHistoryParams>,
+) -> Result<HttpResponseOk<InstanceSerialConsoleData>, HttpError> {
+    let sa = rqctx.context();
+    let instance_id = path_params.into_inner().instance_id;
+    let max_bytes = query_params.into_inner().max_bytes;
+    let console = sa.instance_serial_console(instance_id).await?;
+    Ok(HttpResponseOk(console.history(max_bytes)))
+}
+
 /**
  * Path parameters for Disk requests (sled agent API)
  */
@@ -116,6 +162,22 @@ async fn disk_put(
     ))
 }
 
+#[endpoint {
+    method = POST,
+    path = "/disks/{disk_id}/resize",
+}]
+async fn disk_resize_post(
+    rqctx: Arc<RequestContext<Arc<SledAgent>>>,
+    path_params: Path<DiskPathParam>,
+    body: TypedBody<DiskResizeBody>,
+) -> Result<HttpResponseUpdatedNoContent, HttpError> {
+    let sa = rqctx.context();
+    let disk_id = path_params.into_inner().disk_id;
+    let body_args = body.into_inner();
+    sa.disk_resize(disk_id, body_args.instance_id, body_args.size).await?;
+    Ok(HttpResponseUpdatedNoContent())
+}
+
 #[endpoint {
     method = POST,
     path = "/disks/{disk_id}/poke",
@@ -129,3 +191,16 @@ async fn disk_poke_post(
     sa.disk_poke(disk_id).await;
     Ok(HttpResponseUpdatedNoContent())
 }
+
+#[endpoint {
+    method = PUT,
+    path = "/firewall-rules",
+}]
+async fn firewall_rules_put(
+    rqctx: Arc<RequestContext<Arc<SledAgent>>>,
+    body: TypedBody<FirewallRulesEnsureBody>,
+) -> Result<HttpResponseUpdatedNoContent, HttpError> {
+    let sa = rqctx.context();
+    sa.firewall_rules_ensure(body.into_inner()).await;
+    Ok(HttpResponseUpdatedNoContent())
+}
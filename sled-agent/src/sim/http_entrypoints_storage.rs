@@ -7,11 +7,15 @@
 use crucible_agent_client::types::{CreateRegion, Region, RegionId};
 use dropshot::{
     endpoint, ApiDescription, HttpError, HttpResponseDeleted, HttpResponseOk,
-    Path as TypedPath, RequestContext, TypedBody,
+    HttpResponseUpdatedNoContent, Path as TypedPath, RequestContext, TypedBody,
+};
+use omicron_common::api::internal::crucible::{
+    CreateRegionSnapshot, ImportRegionSnapshot, RegionSnapshot, ResizeRegion,
 };
 use schemars::JsonSchema;
 use serde::Deserialize;
 use std::sync::Arc;
+use uuid::Uuid;
 
 use super::storage::CrucibleData;
 
@@ -26,6 +30,11 @@ pub fn api() -> CrucibleAgentApiDescription {
         api.register(region_create)?;
         api.register(region_get)?;
         api.register(region_delete)?;
+        api.register(region_snapshot_list)?;
+        api.register(region_snapshot_create)?;
+        api.register(region_snapshot_delete)?;
+        api.register(region_import)?;
+        api.register(region_resize)?;
         Ok(())
     }
 
@@ -44,6 +53,12 @@ struct RegionPath {
     id: RegionId,
 }
 
+#[derive(Deserialize, JsonSchema)]
+struct RegionSnapshotPath {
+    id: RegionId,
+    snapshot_id: Uuid,
+}
+
 #[endpoint {
     method = GET,
     path = "/crucible/0/regions",
@@ -105,3 +120,104 @@ async fn region_delete(
         }
     }
 }
+
+// The real Crucible Agent does not yet support snapshots or resizing regions.
+// These endpoints describe the interface Nexus expects from it; see
+// `omicron_common::api::internal::crucible`.
+
+#[endpoint {
+    method = GET,
+    path = "/crucible/0/regions/{id}/snapshots",
+}]
+async fn region_snapshot_list(
+    rc: Arc<RequestContext<Arc<CrucibleData>>>,
+    path: TypedPath<RegionPath>,
+) -> Result<HttpResponseOk<Vec<RegionSnapshot>>, HttpError> {
+    let id = path.into_inner().id;
+    let crucible = rc.context();
+    match crucible.list_snapshots(id).await {
+        Some(snapshots) => Ok(HttpResponseOk(snapshots)),
+        None => {
+            Err(HttpError::for_not_found(None, "Region not found".to_string()))
+        }
+    }
+}
+
+#[endpoint {
+    method = POST,
+    path = "/crucible/0/regions/{id}/snapshots",
+}]
+async fn region_snapshot_create(
+    rc: Arc<RequestContext<Arc<CrucibleData>>>,
+    path: TypedPath<RegionPath>,
+    body: TypedBody<CreateRegionSnapshot>,
+) -> Result<HttpResponseOk<RegionSnapshot>, HttpError> {
+    let id = path.into_inner().id;
+    let params = body.into_inner();
+    let crucible = rc.context();
+    match crucible.create_snapshot(id, params).await {
+        Some(snapshot) => Ok(HttpResponseOk(snapshot)),
+        None => {
+            Err(HttpError::for_not_found(None, "Region not found".to_string()))
+        }
+    }
+}
+
+#[endpoint {
+    method = DELETE,
+    path = "/crucible/0/regions/{id}/snapshots/{snapshot_id}",
+}]
+async fn region_snapshot_delete(
+    rc: Arc<RequestContext<Arc<CrucibleData>>>,
+    path: TypedPath<RegionSnapshotPath>,
+) -> Result<HttpResponseDeleted, HttpError> {
+    let path = path.into_inner();
+    let crucible = rc.context();
+    match crucible.delete_snapshot(path.id, path.snapshot_id).await {
+        Some(_) => Ok(HttpResponseDeleted()),
+        None => Err(HttpError::for_not_found(
+            None,
+            "Snapshot not found".to_string(),
+        )),
+    }
+}
+
+#[endpoint {
+    method = POST,
+    path = "/crucible/0/regions/{id}/import",
+}]
+async fn region_import(
+    rc: Arc<RequestContext<Arc<CrucibleData>>>,
+    path: TypedPath<RegionPath>,
+    body: TypedBody<ImportRegionSnapshot>,
+) -> Result<HttpResponseUpdatedNoContent, HttpError> {
+    let id = path.into_inner().id;
+    let params = body.into_inner();
+    let crucible = rc.context();
+    match crucible.import(id, params).await {
+        Some(()) => Ok(HttpResponseUpdatedNoContent()),
+        None => {
+            Err(HttpError::for_not_found(None, "Region not found".to_string()))
+        }
+    }
+}
+
+#[endpoint {
+    method = POST,
+    path = "/crucible/0/regions/{id}/resize",
+}]
+async fn region_resize(
+    rc: Arc<RequestContext<Arc<CrucibleData>>>,
+    path: TypedPath<RegionPath>,
+    body: TypedBody<ResizeRegion>,
+) -> Result<HttpResponseUpdatedNoContent, HttpError> {
+    let id = path.into_inner().id;
+    let params = body.into_inner();
+    let crucible = rc.context();
+    match crucible.resize(id, params).await {
+        Some(_) => Ok(HttpResponseUpdatedNoContent()),
+        None => {
+            Err(HttpError::for_not_found(None, "Region not found".to_string()))
+        }
+    }
+}
@@ -82,6 +82,12 @@ impl Server {
                     &config.id,
                     &nexus_client::types::SledAgentStartupInfo {
                         sa_address: sa_address.to_string(),
+                        usable_hardware_threads: config
+                            .hardware
+                            .hardware_threads,
+                        usable_physical_ram: nexus_client::types::ByteCount(
+                            config.hardware.physical_ram,
+                        ),
                     },
                 )
                 .await)
@@ -6,15 +6,22 @@
  * Simulated sled agent implementation
  */
 
+use crate::common::nocloud::NoCloudSeed;
+use crate::common::serial_console::{SerialConsole, SCROLLBACK_BYTES};
 use crate::params::DiskStateRequested;
 use futures::lock::Mutex;
 use nexus_client::Client as NexusClient;
+use omicron_common::api::external::ByteCount;
 use omicron_common::api::external::Error;
+use omicron_common::api::external::ResourceType;
 use omicron_common::api::internal::nexus::DiskRuntimeState;
 use omicron_common::api::internal::nexus::InstanceRuntimeState;
+use omicron_common::api::internal::sled_agent::FirewallRule;
+use omicron_common::api::internal::sled_agent::FirewallRulesEnsureBody;
 use omicron_common::api::internal::sled_agent::InstanceHardware;
 use omicron_common::api::internal::sled_agent::InstanceRuntimeStateRequested;
 use slog::Logger;
+use std::collections::HashMap;
 use std::sync::Arc;
 use uuid::Uuid;
 
@@ -38,6 +45,14 @@ pub struct SledAgent {
     instances: Arc<SimCollection<SimInstance>>,
     /** collection of simulated disks, indexed by disk uuid */
     disks: Arc<SimCollection<SimDisk>>,
+    /** latest size reported for each resized disk, indexed by disk uuid */
+    disk_sizes: Mutex<HashMap<Uuid, (Uuid, ByteCount)>>,
+    /** fake serial console of each instance, indexed by instance uuid */
+    serial_consoles: Mutex<HashMap<Uuid, SerialConsole>>,
+    /** NoCloud seed of each instance, indexed by instance uuid */
+    nocloud_seeds: Mutex<HashMap<Uuid, NoCloudSeed>>,
+    /** firewall rules of each network interface, indexed by interface uuid */
+    firewall_rules: Mutex<HashMap<Uuid, Vec<FirewallRule>>>,
     storage: Mutex<Storage>,
 }
 
@@ -71,6 +86,10 @@ impl SledAgent {
                 disk_log,
                 sim_mode,
             )),
+            disk_sizes: Mutex::new(HashMap::new()),
+            serial_consoles: Mutex::new(HashMap::new()),
+            nocloud_seeds: Mutex::new(HashMap::new()),
+            firewall_rules: Mutex::new(HashMap::new()),
             storage: Mutex::new(Storage::new(
                 id,
                 Arc::clone(&nexus_client),
@@ -91,12 +110,50 @@ impl SledAgent {
         initial_hardware: InstanceHardware,
         target: InstanceRuntimeStateRequested,
     ) -> Result<InstanceRuntimeState, Error> {
+        self.serial_consoles.lock().await.entry(instance_id).or_insert_with(
+            || simulated_serial_console(&initial_hardware.runtime.hostname),
+        );
+        self.nocloud_seeds.lock().await.entry(instance_id).or_insert_with(
+            || {
+                NoCloudSeed::new(
+                    &instance_id,
+                    &initial_hardware.runtime.hostname,
+                    &initial_hardware.guest_metadata,
+                )
+            },
+        );
         Ok(self
             .instances
             .sim_ensure(&instance_id, initial_hardware.runtime, target)
             .await?)
     }
 
+    /**
+     * Returns the NoCloud seed that a real sled agent would attach to the guest
+     * of an Instance which has been ensured on this sled.
+     */
+    pub async fn instance_nocloud_seed(
+        &self,
+        instance_id: Uuid,
+    ) -> Result<NoCloudSeed, Error> {
+        self.nocloud_seeds.lock().await.get(&instance_id).cloned().ok_or_else(
+            || Error::not_found_by_id(ResourceType::Instance, &instance_id),
+        )
+    }
+
+    /**
+     * Returns the fake serial console of an Instance which has been ensured on
+     * this sled.
+     */
+    pub async fn instance_serial_console(
+        &self,
+        instance_id: Uuid,
+    ) -> Result<SerialConsole, Error> {
+        self.serial_consoles.lock().await.get(&instance_id).cloned().ok_or_else(
+            || Error::not_found_by_id(ResourceType::Instance, &instance_id),
+        )
+    }
+
     /**
      * Idempotently ensures that the given API Disk (described by `api_disk`)
      * is attached (or not) as specified.  This simulates disk attach and
@@ -111,6 +168,58 @@ impl SledAgent {
         Ok(self.disks.sim_ensure(&disk_id, initial_state, target).await?)
     }
 
+    /**
+     * Records that the given Disk, attached to Instance `instance_id`, has
+     * grown to `size`.  A real sled agent would pass this along to the
+     * Instance's Propolis server.
+     */
+    pub async fn disk_resize(
+        &self,
+        disk_id: Uuid,
+        instance_id: Uuid,
+        size: ByteCount,
+    ) -> Result<(), Error> {
+        self.disk_sizes.lock().await.insert(disk_id, (instance_id, size));
+        Ok(())
+    }
+
+    /**
+     * Returns the Instance and size from the last resize reported for the
+     * given Disk, if any
+     */
+    pub async fn disk_resized(
+        &self,
+        disk_id: Uuid,
+    ) -> Option<(Uuid, ByteCount)> {
+        self.disk_sizes.lock().await.get(&disk_id).cloned()
+    }
+
+    /**
+     * Replaces the firewall rules of the network interfaces listed in `body`.
+     * An interface left with no rules is forgotten.
+     */
+    pub async fn firewall_rules_ensure(&self, body: FirewallRulesEnsureBody) {
+        let mut firewall_rules = self.firewall_rules.lock().await;
+        for interface in body.interfaces {
+            if interface.rules.is_empty() {
+                firewall_rules.remove(&interface.interface_id);
+            } else {
+                firewall_rules.insert(interface.interface_id, interface.rules);
+            }
+        }
+    }
+
+    /**
+     * Returns the firewall rules last received for the given network
+     * interface, if it has any
+     */
+    pub async fn firewall_rules(
+        &self,
+        interface_id: Uuid,
+    ) -> Option<Vec<FirewallRule>> {
+        self.firewall_rules.lock().await.get(&interface_id).cloned()
+    }
+
     pub async fn instance_poke(&self, id: Uuid) {
         self.instances.sim_poke(id).await;
     }
@@ -142,3 +251,22 @@ impl SledAgent {
         self.storage.lock().await.get_dataset(zpool_id, dataset_id).await
     }
 }
+
+/**
+ * Creates a serial console which has printed a boot banner, and which echoes
+ * whatever is typed into it, as a stand-in for a guest's
+ */
+fn simulated_serial_console(hostname: &str) -> SerialConsole {
+    let (console, mut input) = SerialConsole::new(SCROLLBACK_BYTES);
+    console.record_output(
+        format!("Simulated instance booting...\r\n{} login: ", hostname)
+            .as_bytes(),
+    );
+    let echo = console.clone();
+    tokio::spawn(async move {
+        while let Some(bytes) = input.recv().await {
+            echo.record_output(&bytes);
+        }
+    });
+    console
+}
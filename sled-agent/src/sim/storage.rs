@@ -8,12 +8,16 @@
 //! than the representation of "virtual disks" which would be presented
 //! through Nexus' external API.
 
+use chrono::Utc;
 use crucible_agent_client::types::{CreateRegion, Region, RegionId, State};
 use futures::lock::Mutex;
 use nexus_client::types::{
     ByteCount, DatasetKind, DatasetPutRequest, ZpoolPutRequest,
 };
 use nexus_client::Client as NexusClient;
+use omicron_common::api::internal::crucible::{
+    CreateRegionSnapshot, ImportRegionSnapshot, RegionSnapshot, ResizeRegion,
+};
 use slog::Logger;
 use std::collections::HashMap;
 use std::net::{IpAddr, SocketAddr};
@@ -25,12 +29,21 @@ type CreateCallback = Box<dyn Fn(&CreateRegion) -> State + Send + 'static>;
 
 struct CrucibleDataInner {
     regions: HashMap<Uuid, Region>,
+    /// snapshots taken of each region, keyed by region id
+    snapshots: HashMap<Uuid, Vec<RegionSnapshot>>,
+    /// the snapshot from which each region was populated, keyed by region id
+    imports: HashMap<Uuid, ImportRegionSnapshot>,
     on_create: Option<CreateCallback>,
 }
 
 impl CrucibleDataInner {
     fn new() -> Self {
-        Self { regions: HashMap::new(), on_create: None }
+        Self {
+            regions: HashMap::new(),
+            snapshots: HashMap::new(),
+            imports: HashMap::new(),
+            on_create: None,
+        }
     }
 
     fn set_create_callback(&mut self, callback: CreateCallback) {
@@ -90,6 +103,70 @@ impl CrucibleDataInner {
         region.state = State::Destroyed;
         Some(region.clone())
     }
+
+    /// Returns the id of the region with id `id`, provided that it exists and
+    /// has not been destroyed.
+    fn live_region_id(&self, id: &RegionId) -> Option<Uuid> {
+        let id = Uuid::from_str(&id.0).unwrap();
+        match self.regions.get(&id)?.state {
+            State::Destroyed => None,
+            _ => Some(id),
+        }
+    }
+
+    fn create_snapshot(
+        &mut self,
+        id: RegionId,
+        params: CreateRegionSnapshot,
+    ) -> Option<RegionSnapshot> {
+        let id = self.live_region_id(&id)?;
+        let snapshots = self.snapshots.entry(id).or_insert_with(Vec::new);
+        if let Some(snapshot) = snapshots.iter().find(|s| s.id == params.id) {
+            return Some(snapshot.clone());
+        }
+        let snapshot =
+            RegionSnapshot { id: params.id, time_created: Utc::now() };
+        snapshots.push(snapshot.clone());
+        Some(snapshot)
+    }
+
+    fn list_snapshots(&self, id: RegionId) -> Option<Vec<RegionSnapshot>> {
+        let id = self.live_region_id(&id)?;
+        Some(self.snapshots.get(&id).cloned().unwrap_or_default())
+    }
+
+    fn delete_snapshot(
+        &mut self,
+        id: RegionId,
+        snapshot_id: Uuid,
+    ) -> Option<RegionSnapshot> {
+        let id = Uuid::from_str(&id.0).unwrap();
+        let snapshots = self.snapshots.get_mut(&id)?;
+        let index = snapshots.iter().position(|s| s.id == snapshot_id)?;
+        Some(snapshots.remove(index))
+    }
+
+    fn import(
+        &mut self,
+        id: RegionId,
+        params: ImportRegionSnapshot,
+    ) -> Option<()> {
+        let id = self.live_region_id(&id)?;
+        self.imports.insert(id, params);
+        Some(())
+    }
+
+    fn get_import(&self, id: RegionId) -> Option<ImportRegionSnapshot> {
+        let id = Uuid::from_str(&id.0).unwrap();
+        self.imports.get(&id).cloned()
+    }
+
+    fn resize(&mut self, id: RegionId, params: ResizeRegion) -> Option<Region> {
+        let id = self.live_region_id(&id)?;
+        let region = self.regions.get_mut(&id)?;
+        region.extent_count = params.extent_count;
+        Some(region.clone())
+    }
 }
 
 /// Represents a running Crucible Agent. Contains regions.
@@ -122,6 +199,53 @@ impl CrucibleData {
         self.inner.lock().await.delete(id)
     }
 
+    pub async fn create_snapshot(
+        &self,
+        id: RegionId,
+        params: CreateRegionSnapshot,
+    ) -> Option<RegionSnapshot> {
+        self.inner.lock().await.create_snapshot(id, params)
+    }
+
+    pub async fn list_snapshots(
+        &self,
+        id: RegionId,
+    ) -> Option<Vec<RegionSnapshot>> {
+        self.inner.lock().await.list_snapshots(id)
+    }
+
+    pub async fn delete_snapshot(
+        &self,
+        id: RegionId,
+        snapshot_id: Uuid,
+    ) -> Option<RegionSnapshot> {
+        self.inner.lock().await.delete_snapshot(id, snapshot_id)
+    }
+
+    pub async fn import(
+        &self,
+        id: RegionId,
+        params: ImportRegionSnapshot,
+    ) -> Option<()> {
+        self.inner.lock().await.import(id, params)
+    }
+
+    /// Returns the snapshot from which a region was populated, if any
+    pub async fn get_import(
+        &self,
+        id: RegionId,
+    ) -> Option<ImportRegionSnapshot> {
+        self.inner.lock().await.get_import(id)
+    }
+
+    pub async fn resize(
+        &self,
+        id: RegionId,
+        params: ResizeRegion,
+    ) -> Option<Region> {
+        self.inner.lock().await.resize(id, params)
+    }
+
     pub async fn set_state(&self, id: &RegionId, state: State) {
         self.inner
             .lock()
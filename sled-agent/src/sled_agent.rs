@@ -4,6 +4,7 @@
 
 //! Sled agent implementation
 
+use crate::common::serial_console::SerialConsole;
 use crate::config::Config;
 use crate::illumos::zfs::{
     Mountpoint, ZONE_ZFS_DATASET, ZONE_ZFS_DATASET_MOUNTPOINT,
@@ -12,13 +13,18 @@ use crate::instance_manager::InstanceManager;
 use crate::params::DiskStateRequested;
 use crate::storage_manager::StorageManager;
 use omicron_common::api::{
+    external::ByteCount, external::ResourceType,
     internal::nexus::DiskRuntimeState, internal::nexus::InstanceRuntimeState,
+    internal::sled_agent::FirewallRule,
+    internal::sled_agent::FirewallRulesEnsureBody,
     internal::sled_agent::InstanceHardware,
     internal::sled_agent::InstanceMigrateParams,
     internal::sled_agent::InstanceRuntimeStateRequested,
 };
 use slog::Logger;
+use std::collections::HashMap;
 use std::sync::Arc;
+use tokio::sync::Mutex;
 use uuid::Uuid;
 
 #[cfg(not(test))]
@@ -51,12 +57,28 @@ pub enum Error {
 
     #[error("Error managing storage: {0}")]
     Storage(#[from] crate::storage_manager::Error),
+
+    #[error("Not supported: {0}")]
+    NotSupported(String),
 }
 
 impl From<Error> for omicron_common::api::external::Error {
     fn from(err: Error) -> Self {
-        omicron_common::api::external::Error::InternalError {
-            internal_message: err.to_string(),
+        match err {
+            Error::Instance(
+                crate::instance_manager::Error::NoSuchInstance(id),
+            ) => omicron_common::api::external::Error::not_found_by_id(
+                ResourceType::Instance,
+                &id,
+            ),
+            Error::NotSupported(_) => {
+                omicron_common::api::external::Error::InvalidRequest {
+                    message: err.to_string(),
+                }
+            }
+            _ => omicron_common::api::external::Error::InternalError {
+                internal_message: err.to_string(),
+            },
         }
     }
 }
@@ -65,8 +87,12 @@ impl From<Error> for omicron_common::api::external::Error {
 ///
 /// Contains both a connection to the Nexus, as well as managed instances.
 pub struct SledAgent {
+    log: Logger,
     _storage: StorageManager,
     instances: InstanceManager,
+    /// Firewall rules of each network interface, indexed by interface uuid.
+    /// These aren't enforced yet; see [`SledAgent::firewall_rules_ensure`].
+    firewall_rules: Mutex<HashMap<Uuid, Vec<FirewallRule>>>,
 }
 
 impl SledAgent {
@@ -121,9 +147,15 @@ impl SledAgent {
                 storage.upsert_zpool(pool).await?;
             }
         }
-        let instances = InstanceManager::new(log, vlan, nexus_client.clone())?;
-
-        Ok(SledAgent { _storage: storage, instances })
+        let instances =
+            InstanceManager::new(log.clone(), vlan, nexus_client.clone())?;
+
+        Ok(SledAgent {
+            log,
+            _storage: storage,
+            instances,
+            firewall_rules: Mutex::new(HashMap::new()),
+        })
     }
 
     /// Idempotently ensures that a given Instance is running on the sled.
@@ -140,6 +172,14 @@ impl SledAgent {
             .map_err(|e| Error::Instance(e))
     }
 
+    /// Returns the serial console of an Instance running on the sled.
+    pub fn instance_serial_console(
+        &self,
+        instance_id: Uuid,
+    ) -> Result<SerialConsole, Error> {
+        Ok(self.instances.serial_console(instance_id)?)
+    }
+
     /// Idempotently ensures that the given virtual disk is attached (or not) as
     /// specified.
     ///
@@ -152,4 +192,45 @@ impl SledAgent {
     ) -> Result<DiskRuntimeState, Error> {
         todo!("Disk attachment not yet implemented");
     }
+
+    /// Informs the Instance to which a virtual disk is attached that the disk
+    /// has grown to `size`.
+    ///
+    /// NOTE: Not yet implemented: Propolis can't yet grow a disk that's in
+    /// use, so this always fails, and Nexus undoes the resize.
+    pub async fn disk_resize(
+        &self,
+        disk_id: Uuid,
+        _instance_id: Uuid,
+        _size: ByteCount,
+    ) -> Result<(), Error> {
+        Err(Error::NotSupported(format!(
+            "cannot resize disk {} while it is attached to a running instance",
+            disk_id
+        )))
+    }
+
+    /// Establishes the firewall rules of the given network interfaces.
+    ///
+    /// NOTE: Not yet enforced.  The rules are logged and kept (an interface
+    /// left with no rules is forgotten), so that it's possible to tell what
+    /// this sled has been asked to enforce, but nothing filters Instances'
+    /// traffic by them.
+    pub async fn firewall_rules_ensure(
+        &self,
+        body: FirewallRulesEnsureBody,
+    ) -> Result<(), Error> {
+        let mut firewall_rules = self.firewall_rules.lock().await;
+        for interface in body.interfaces {
+            warn!(self.log, "firewall rules received but not enforced";
+                "interface_id" => %interface.interface_id,
+                "rules" => ?interface.rules);
+            if interface.rules.is_empty() {
+                firewall_rules.remove(&interface.interface_id);
+            } else {
+                firewall_rules.insert(interface.interface_id, interface.rules);
+            }
+        }
+        Ok(())
+    }
 }
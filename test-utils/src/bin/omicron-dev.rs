@@ -8,12 +8,14 @@
 
 use anyhow::bail;
 use anyhow::Context;
+use dropshot::{ConfigDropshot, ConfigLogging, ConfigLoggingLevel};
 use futures::stream::StreamExt;
 use omicron_common::cmd::fatal;
 use omicron_common::cmd::CmdError;
 use omicron_test_utils::dev;
 use signal_hook::consts::signal::SIGINT;
 use signal_hook_tokio::Signals;
+use std::net::SocketAddr;
 use std::path::PathBuf;
 use structopt::StructOpt;
 
@@ -27,6 +29,7 @@ async fn main() -> Result<(), anyhow::Error> {
         OmicronDb::DbPopulate { ref args } => cmd_db_populate(args).await,
         OmicronDb::DbWipe { ref args } => cmd_db_wipe(args).await,
         OmicronDb::ChRun { ref args } => cmd_clickhouse_run(args).await,
+        OmicronDb::DnsRun { ref args } => cmd_dns_run(args).await,
     };
     if let Err(error) = result {
         fatal(CmdError::Failure(format!("{:#}", error)));
@@ -61,6 +64,12 @@ enum OmicronDb {
         #[structopt(flatten)]
         args: ChRunArgs,
     },
+
+    /// Run an internal DNS server for development
+    DnsRun {
+        #[structopt(flatten)]
+        args: DnsRunArgs,
+    },
 }
 
 #[derive(Debug, StructOpt)]
@@ -298,3 +307,52 @@ async fn cmd_clickhouse_run(args: &ChRunArgs) -> Result<(), anyhow::Error> {
     }
     Ok(())
 }
+
+#[derive(Debug, StructOpt)]
+struct DnsRunArgs {
+    /// Address on which the server will answer DNS queries
+    /*
+     * These defaults match the internal DNS server in the example Nexus
+     * config file.
+     */
+    #[structopt(long, default_value = "[::1]:5354")]
+    dns_address: SocketAddr,
+
+    /// Address on which the server will accept zone updates over HTTP
+    #[structopt(long, default_value = "[::1]:5380")]
+    http_address: SocketAddr,
+}
+
+async fn cmd_dns_run(args: &DnsRunArgs) -> Result<(), anyhow::Error> {
+    // Start a stream listening for SIGINT
+    let signals = Signals::new(&[SIGINT]).expect("failed to wait for SIGINT");
+    let mut signal_stream = signals.fuse();
+
+    let config = internal_dns::Config {
+        log: ConfigLogging::StderrTerminal { level: ConfigLoggingLevel::Info },
+        dropshot: ConfigDropshot {
+            bind_address: args.http_address,
+            ..Default::default()
+        },
+        dns: internal_dns::DnsConfig { bind_address: args.dns_address },
+    };
+    let log =
+        config.log.to_logger("internal-dns").context("initializing logger")?;
+    let server = internal_dns::Server::start(&config, &log).await?;
+    println!(
+        "omicron-dev: internal DNS server answering queries at {}",
+        server.dns_address
+    );
+    println!(
+        "omicron-dev: internal DNS server accepting zone updates at http://{}",
+        server.http_server.local_addr()
+    );
+
+    // The server has no records until something (like Nexus) updates its
+    // zone, and runs until we receive SIGINT.
+    let caught_signal = signal_stream.next().await;
+    assert_eq!(caught_signal.unwrap(), SIGINT);
+    eprintln!("omicron-dev: caught signal, shutting down");
+    server.close().await?;
+    Ok(())
+}